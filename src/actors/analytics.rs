@@ -0,0 +1,230 @@
+use actix::prelude::*;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::analytics::{MempoolFeeSample, UtxoDistribution};
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::error::{SnapshotError, StorageError};
+use crate::events::{BitcoinEventType, EventManager};
+use crate::mempoolcapture::{capture_dir, prune_captures, MempoolCapture};
+use super::instrument::InstrumentedAddr;
+use super::{ComputeUtxoDistribution, GetChainTip, GetMempoolInfo, GetMempoolSnapshot, GetUtxoDistribution, RecordMempoolFee};
+use super::mempool::MempoolActor;
+use super::storage::StorageActor;
+
+/// Name the leader shares its latest UTXO distribution under
+const UTXO_DISTRIBUTION: &str = "utxo_distribution";
+
+/// Runs periodic chain analytics jobs and caches their latest results
+pub struct AnalyticsActor {
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    event_manager: EventManager,
+    /// Only the leader scans; the others serve what it shares
+    cluster: Cluster,
+    network_name: &'static str,
+    node_id: String,
+    utxo_distribution_interval: Option<Duration>,
+    utxo_distribution: Option<UtxoDistribution>,
+    scan_running: bool,
+    /// Every node snapshots its own mempool, leader or not
+    mempool_snapshot_interval: Option<Duration>,
+    mempool_snapshot_retention: Duration,
+    mempool_snapshot_dir: PathBuf,
+    capture_running: bool,
+    mempool_fee_interval: Option<Duration>,
+}
+
+impl AnalyticsActor {
+    pub fn new(
+        config: &Config,
+        storage_actor: Addr<StorageActor>,
+        mempool_actor: Addr<MempoolActor>,
+        event_manager: EventManager,
+        cluster: Cluster,
+    ) -> Self {
+        let interval = config.analytics.utxo_distribution_interval_secs;
+        let mempool_interval = config.analytics.mempool_snapshot_interval_secs;
+        let fee_interval = config.analytics.mempool_fee_interval_secs;
+        info!("Analytics actor initialized");
+        Self {
+            storage_actor,
+            mempool_actor,
+            event_manager,
+            cluster,
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+            utxo_distribution_interval: (interval > 0).then(|| Duration::from_secs(interval)),
+            utxo_distribution: None,
+            scan_running: false,
+            mempool_snapshot_interval: (mempool_interval > 0).then(|| Duration::from_secs(mempool_interval)),
+            mempool_snapshot_retention: Duration::from_secs(config.analytics.mempool_snapshot_retention_hours * 3600),
+            mempool_snapshot_dir: capture_dir(&config.datadir),
+            capture_running: false,
+            mempool_fee_interval: (fee_interval > 0).then(|| Duration::from_secs(fee_interval)),
+        }
+    }
+
+    /// Record the mempool's minimum fee for the fee rate history
+    fn sample_mempool_fee(&mut self, ctx: &mut Context<Self>) {
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+        ctx.spawn(
+            async move {
+                let info = mempool_actor.send_traced(GetMempoolInfo).await??;
+                let sample = MempoolFeeSample {
+                    time: chrono::Utc::now().timestamp(),
+                    min_fee: info.mempool_min_fee,
+                    size: info.size,
+                    bytes: info.bytes,
+                };
+                storage_actor.send_traced(RecordMempoolFee { sample }).await?
+            }
+            .into_actor(self)
+            .map(|result: Result<(), StorageError>, _actor, _ctx| {
+                if let Err(e) = result {
+                    warn!("Mempool fee sample failed: {}", e);
+                }
+            }),
+        );
+    }
+
+    fn capture_mempool(&mut self, ctx: &mut Context<Self>) {
+        if self.capture_running {
+            return;
+        }
+        self.capture_running = true;
+
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+        let network = self.network_name;
+        let dir = self.mempool_snapshot_dir.clone();
+        let retention = self.mempool_snapshot_retention;
+        ctx.spawn(
+            async move {
+                let tip = storage_actor.send_traced(GetChainTip).await??;
+                let snapshot = mempool_actor.send_traced(GetMempoolSnapshot).await??;
+                let capture = MempoolCapture::new(network, chrono::Utc::now(), tip.map(|tip| tip.height), snapshot);
+                let entries = capture.entries.len();
+                tokio::task::spawn_blocking(move || {
+                    let summary = capture.save(&dir)?;
+                    let pruned = prune_captures(&dir, retention, capture.captured_at)?;
+                    Ok::<_, SnapshotError>((summary, entries, pruned))
+                })
+                .await
+                .map_err(|e| SnapshotError::Io(std::io::Error::other(e.to_string())))?
+            }
+            .into_actor(self)
+            .map(|result, actor, _ctx| {
+                actor.capture_running = false;
+                match result {
+                    Ok((summary, entries, pruned)) => debug!(
+                        "Saved mempool snapshot {} ({} transactions, {} bytes), pruned {}",
+                        summary.id, entries, summary.bytes, pruned
+                    ),
+                    Err(e) => warn!("Mempool snapshot failed: {}", e),
+                }
+            }),
+        );
+    }
+
+    fn scan_utxo_set(&mut self, ctx: &mut Context<Self>) {
+        // Scans can outlast a short interval; never run two at once
+        if self.scan_running {
+            return;
+        }
+        self.scan_running = true;
+
+        if !self.cluster.is_leader() {
+            let cluster = self.cluster.clone();
+            ctx.spawn(
+                async move { cluster.shared::<UtxoDistribution>(UTXO_DISTRIBUTION).await }
+                    .into_actor(self)
+                    .map(|result, actor, _ctx| {
+                        actor.scan_running = false;
+                        match result {
+                            Ok(Some(distribution)) => actor.utxo_distribution = Some(distribution),
+                            Ok(None) => {}
+                            Err(e) => warn!("Could not read the leader's UTXO distribution: {}", e),
+                        }
+                    }),
+            );
+            return;
+        }
+
+        let storage_actor = self.storage_actor.clone();
+        ctx.spawn(
+            async move { storage_actor.send_traced(ComputeUtxoDistribution).await }
+                .into_actor(self)
+                .map(|result, actor, _ctx| {
+                    actor.scan_running = false;
+                    let distribution = match result {
+                        Ok(Ok(Some(distribution))) => distribution,
+                        Ok(Ok(None)) => return,
+                        Ok(Err(e)) => {
+                            warn!("UTXO distribution scan failed: {}", e);
+                            return;
+                        }
+                        Err(e) => {
+                            warn!("UTXO distribution scan failed: {}", e);
+                            return;
+                        }
+                    };
+
+                    info!("UTXO distribution at height {}: {} coins, {} sat",
+                          distribution.height, distribution.total_count, distribution.total_value);
+                    actor.utxo_distribution = Some(distribution.clone());
+
+                    let cluster = actor.cluster.clone();
+                    let shared = distribution.clone();
+                    actor.event_manager.spawn_delivery(async move {
+                        if let Err(e) = cluster.share(UTXO_DISTRIBUTION, &shared).await {
+                            warn!("Could not share the UTXO distribution: {}", e);
+                        }
+                    });
+
+                    let event_manager = actor.event_manager.clone();
+                    let network = actor.network_name;
+                    let node_id = actor.node_id.clone();
+                    let event = BitcoinEventType::UtxoDistributionUpdated { distribution };
+                    actor.event_manager.spawn_delivery(async move {
+                        if let Err(e) = event_manager.publish(event, network, &node_id).await {
+                            warn!("Failed to publish UTXO distribution: {}", e);
+                        }
+                    });
+                }),
+        );
+    }
+}
+
+impl Actor for AnalyticsActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Analytics actor started");
+        if let Some(interval) = self.utxo_distribution_interval {
+            self.scan_utxo_set(ctx);
+            ctx.run_interval(interval, |actor, ctx| actor.scan_utxo_set(ctx));
+        }
+        if let Some(interval) = self.mempool_snapshot_interval {
+            ctx.run_interval(interval, |actor, ctx| actor.capture_mempool(ctx));
+        }
+        if let Some(interval) = self.mempool_fee_interval {
+            ctx.run_interval(interval, |actor, ctx| actor.sample_mempool_fee(ctx));
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!("Analytics actor stopped");
+    }
+}
+
+impl Handler<GetUtxoDistribution> for AnalyticsActor {
+    type Result = Option<UtxoDistribution>;
+
+    fn handle(&mut self, _msg: GetUtxoDistribution, _ctx: &mut Self::Context) -> Self::Result {
+        self.utxo_distribution.clone()
+    }
+}
@@ -1,28 +1,497 @@
 use actix::prelude::*;
-use tracing::{info, error};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use bitcoin::{Block, BlockHash};
+use bitcoin::hashes::Hash;
+use tracing::{info, warn, error, Instrument};
 
 use crate::config::Config;
 use crate::error::StorageError;
-use super::{StoreBlock, GetChainInfo, ChainInfo};
+use crate::events::{BitcoinEventType, EventManager};
+use super::instrument::InstrumentedAddr;
+use super::{StoreBlock, GetChainInfo, ChainInfo, ChainTip, ConnectBlock, GetChainTip, RemoveBlockTransactions};
+use super::{BlockConnected, UtxoDelta, StoreSideBlock, SideBlockOutcome, DisconnectTip, RecordReorg, ReorgRecord};
+use super::{AcceptReorg, WaitForHeight};
+use super::{GetBlockHeight, InvalidateBlock, ReloadChainTip, Shutdown, BlockSubmission};
+#[cfg(feature = "miner")]
+use super::SubmitBlock;
+use super::mempool::MempoolActor;
+
+/// How far ahead of the clock a block timestamp may be, as in Core
+const MAX_FUTURE_BLOCK_TIME: i64 = 2 * 60 * 60;
+
+/// Checks that need no chain state, failing with the BIP 22 reason Core gives
+pub fn check_block(block: &Block, network: bitcoin::Network) -> Result<(), &'static str> {
+    let target = block.header.target();
+    if target > bitcoin::consensus::Params::new(network).pow_limit || block.header.validate_pow(target).is_err() {
+        return Err("high-hash");
+    }
+    if block.txdata.is_empty() {
+        return Err("bad-blk-length");
+    }
+    if !block.check_merkle_root() {
+        return Err("bad-txnmrklroot");
+    }
+    if !block.txdata[0].is_coinbase() {
+        return Err("bad-cb-missing");
+    }
+    if block.txdata[1..].iter().any(|tx| tx.is_coinbase()) {
+        return Err("bad-cb-multiple");
+    }
+    if block.header.time as i64 > crate::clock::now() + MAX_FUTURE_BLOCK_TIME {
+        return Err("time-too-new");
+    }
+    if !block.check_witness_commitment() {
+        return Err("bad-witness-merkle-match");
+    }
+    if block.weight() > bitcoin::Weight::MAX_BLOCK {
+        return Err("bad-blk-weight");
+    }
+    Ok(())
+}
+
+/// The active chain tip, shared with API clients waiting for it to move
+#[derive(Clone)]
+pub struct ChainTipFeed {
+    sender: Arc<watch::Sender<Option<ChainTip>>>,
+}
+
+impl ChainTipFeed {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    fn set(&self, tip: Option<ChainTip>) {
+        self.sender.send_if_modified(|current| {
+            let changed = *current != tip;
+            *current = tip;
+            changed
+        });
+    }
+
+    pub fn current(&self) -> Option<ChainTip> {
+        *self.sender.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<ChainTip>> {
+        self.sender.subscribe()
+    }
+
+    /// Wait up to `timeout` for a tip other than `known`, then return whatever the tip is
+    pub async fn wait_for_change(&self, known: BlockHash, timeout: Duration) -> Option<ChainTip> {
+        let mut receiver = self.sender.subscribe();
+        let changed = receiver.wait_for(|tip| tip.map(|tip| tip.hash) != Some(known));
+        // Timing out just means the tip is still `known`
+        let _ = tokio::time::timeout(timeout, changed).await;
+        let tip = *receiver.borrow();
+        tip
+    }
+
+    /// Wait until the tip reaches `height`, for up to `timeout` if one is given,
+    /// then return whatever the tip is
+    pub async fn wait_for_height(&self, height: u64, timeout: Option<Duration>) -> Option<ChainTip> {
+        let mut receiver = self.sender.subscribe();
+        let reached = receiver.wait_for(|tip| tip.is_some_and(|tip| tip.height >= height));
+        match timeout {
+            Some(timeout) => {
+                let _ = tokio::time::timeout(timeout, reached).await;
+            }
+            None => {
+                let _ = reached.await;
+            }
+        }
+        let tip = *receiver.borrow();
+        tip
+    }
+}
+
+impl Default for ChainTipFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct ChainActor {
-    _storage_actor: Addr<super::storage::StorageActor>,
+    storage_actor: Addr<super::storage::StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    block_subscribers: Vec<Recipient<BlockConnected>>,
+    event_manager: EventManager,
+    network: bitcoin::Network,
+    network_name: &'static str,
+    node_id: String,
+    tip: Option<ChainTip>,
+    tip_feed: ChainTipFeed,
+    /// Blocks refused after an invalidation, along with any children seen since
+    invalid: HashSet<BlockHash>,
+    max_reorg_depth: Option<u64>,
+    /// Reorg deeper than `max_reorg_depth` waiting for `AcceptReorg`
+    held_reorg: Option<HeldReorg>,
+}
+
+/// A reorg onto a side chain with more work that is not followed automatically
+struct HeldReorg {
+    old_tip: ChainTip,
+    new_tip: ChainTip,
+    fork_height: u64,
+    connect: Vec<(Block, u64)>,
+}
+
+/// What became of a block that does not extend the tip
+enum SideChainStep {
+    Finished(BlockSubmission),
+    Held(HeldReorg),
+    Reorganized(ReorgRecord, Vec<(Block, UtxoDelta)>),
 }
 
 impl ChainActor {
-    pub fn new(config: &Config, storage_actor: Addr<super::storage::StorageActor>) -> Self {
+    pub fn new(
+        config: &Config,
+        storage_actor: Addr<super::storage::StorageActor>,
+        mempool_actor: Addr<MempoolActor>,
+        block_subscribers: Vec<Recipient<BlockConnected>>,
+        event_manager: EventManager,
+    ) -> Self {
         info!("Chain actor initialized");
         Self {
-            _storage_actor: storage_actor,
+            storage_actor,
+            mempool_actor,
+            block_subscribers,
+            event_manager,
+            network: config.network.to_bitcoin_network(),
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+            tip: None,
+            tip_feed: ChainTipFeed::new(),
+            invalid: HashSet::new(),
+            max_reorg_depth: config.validation.max_reorg_depth,
+            held_reorg: None,
         }
     }
+
+    /// Feed that follows this actor's tip, to hand out before the actor starts
+    pub fn tip_feed(&self) -> ChainTipFeed {
+        self.tip_feed.clone()
+    }
+
+    fn set_tip(&mut self, tip: Option<ChainTip>) {
+        self.tip = tip;
+        self.tip_feed.set(tip);
+        // A held reorg is worked out from the tip it would disconnect
+        if self.held_reorg.as_ref().is_some_and(|held| Some(held.old_tip) != tip) {
+            info!("Chain tip moved, dropping the held reorg");
+            self.held_reorg = None;
+        }
+    }
+
+    /// Validate a block, then connect it or archive it on a side chain
+    fn accept_block(&mut self, block: Block) -> ResponseActFuture<Self, Result<BlockSubmission, StorageError>> {
+        let block_hash = block.block_hash();
+        if self.invalid.contains(&block_hash) {
+            return Box::pin(fut::ready(Ok(BlockSubmission::DuplicateInvalid)));
+        }
+        let span = tracing::debug_span!("validate_block", hash = %block_hash, height = tracing::field::Empty);
+        let reason = if self.invalid.contains(&block.header.prev_blockhash) {
+            self.invalid.insert(block_hash);
+            Some("bad-prevblk")
+        } else {
+            span.in_scope(|| check_block(&block, self.network).err())
+        };
+        if let Some(reason) = reason {
+            warn!("Ignoring block {}: {}", block_hash, reason);
+            return Box::pin(fut::ready(Ok(BlockSubmission::Rejected(reason.to_string()))));
+        }
+
+        let height = match self.tip {
+            Some(tip) if block.header.prev_blockhash == tip.hash => Some(tip.height + 1),
+            None if block.header.prev_blockhash == bitcoin::BlockHash::all_zeros() => Some(0),
+            _ => None,
+        };
+
+        if let Some(height) = height {
+            span.record("height", height);
+        }
+        let _entered = span.enter();
+        match height {
+            Some(height) => Box::pin(self.connect_block(block, height).map(|result, _actor, _ctx| {
+                result.map(|()| BlockSubmission::Accepted)
+            })),
+            None => self.process_side_block(block),
+        }
+    }
+
+    /// Connect a block on top of the current tip, evict its transactions from the
+    /// mempool and announce its UTXO delta
+    fn connect_block(&self, block: Block, height: u64) -> ResponseActFuture<Self, Result<(), StorageError>> {
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+
+        Box::pin(
+            async move {
+                let delta = apply_block(&storage_actor, &mempool_actor, block.clone(), height).await?;
+                Ok::<_, StorageError>((delta, block))
+            }
+                // The storage and mempool messages are sent from within this span
+                .instrument(tracing::Span::current())
+                .into_actor(self)
+                .map(|result, actor, _ctx| {
+                    let (delta, block) = result.inspect_err(|e| actor.note_invalid(e))?;
+                    actor.block_connected(block, delta);
+                    Ok(())
+                }),
+        )
+    }
+
+    /// Remember a block storage refused as invalid so blocks built on it are ignored
+    fn note_invalid(&mut self, error: &StorageError) {
+        if let StorageError::InvalidBlock { hash, reason } = error {
+            warn!("Rejected block {}: {}", hash, reason);
+            if let Ok(hash) = hash.parse() {
+                self.invalid.insert(hash);
+            }
+        }
+    }
+
+    /// Advance the tip and announce a newly connected block
+    fn block_connected(&mut self, block: Block, delta: UtxoDelta) {
+        self.set_tip(Some(ChainTip {
+            hash: block.block_hash(),
+            height: delta.height,
+        }));
+
+        for subscriber in &self.block_subscribers {
+            subscriber.do_send(BlockConnected {
+                block: block.clone(),
+                height: delta.height,
+            });
+        }
+
+        self.publish(BitcoinEventType::UtxoSetChanged {
+            height: delta.height,
+            block_hash: delta.block_hash,
+            created: delta.created,
+            spent: delta.spent,
+        });
+    }
+
+    fn publish(&self, event: BitcoinEventType) {
+        let event_manager = self.event_manager.clone();
+        let network = self.network_name;
+        let node_id = self.node_id.clone();
+        self.event_manager.spawn_delivery(async move {
+            if let Err(e) = event_manager.publish(event, network, &node_id).await {
+                warn!("Failed to publish chain event: {}", e);
+            }
+        });
+    }
+
+    /// Archive a block that does not extend the tip, reorganizing onto its chain if it has more work
+    fn process_side_block(&self, block: Block) -> ResponseActFuture<Self, Result<BlockSubmission, StorageError>> {
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+        let old_tip = self.tip;
+        let max_reorg_depth = self.max_reorg_depth;
+        let block_hash = block.block_hash();
+
+        Box::pin(
+            async move {
+                let (fork_height, connect) = match storage_actor.send_traced(StoreSideBlock { block }).await?? {
+                    SideBlockOutcome::AlreadyKnown => return Ok(SideChainStep::Finished(BlockSubmission::Duplicate)),
+                    SideBlockOutcome::Orphan => {
+                        // TODO: Request missing parents from peers
+                        warn!("Block {} has an unknown parent, ignoring", block_hash);
+                        return Ok(SideChainStep::Finished(BlockSubmission::Rejected("prev-blk-not-found".to_string())));
+                    }
+                    SideBlockOutcome::Stale { height } => {
+                        info!("Archived side chain block {} at height {}", block_hash, height);
+                        return Ok(SideChainStep::Finished(BlockSubmission::Inconclusive));
+                    }
+                    SideBlockOutcome::Reorg { fork_height, connect } => (fork_height, connect),
+                };
+                let old_tip = old_tip.ok_or_else(|| StorageError::Corruption {
+                    component: "chain tip".to_string(),
+                })?;
+
+                if max_reorg_depth.is_some_and(|max| old_tip.height - fork_height > max) {
+                    let new_tip = connect.last().map(|(block, height)| ChainTip {
+                        hash: block.block_hash(),
+                        height: *height,
+                    }).unwrap_or(old_tip);
+                    return Ok(SideChainStep::Held(HeldReorg { old_tip, new_tip, fork_height, connect }));
+                }
+
+                let (record, connected) = reorganize(&storage_actor, &mempool_actor, old_tip, fork_height, connect).await?;
+                Ok::<_, StorageError>(SideChainStep::Reorganized(record, connected))
+            }
+                .instrument(tracing::Span::current())
+                .into_actor(self)
+                .map(|result, actor, _ctx| {
+                    match result.inspect_err(|e| actor.note_invalid(e))? {
+                        SideChainStep::Finished(submission) => Ok(submission),
+                        SideChainStep::Held(held) => {
+                            actor.hold_reorg(held);
+                            // Valid, but not on the active chain
+                            Ok(BlockSubmission::Inconclusive)
+                        }
+                        SideChainStep::Reorganized(record, connected) => {
+                            actor.reorganized(record, connected);
+                            Ok(BlockSubmission::Accepted)
+                        }
+                    }
+                }),
+        )
+    }
+
+    /// Keep a too deep reorg for the operator and raise the alert
+    fn hold_reorg(&mut self, held: HeldReorg) {
+        let depth = held.old_tip.height - held.fork_height;
+        let max_depth = self.max_reorg_depth.unwrap_or_default();
+        error!(
+            "Not following reorg from {} to {} (depth: {}, max: {}); confirm it with acceptreorg {}",
+            held.old_tip.hash, held.new_tip.hash, depth, max_depth, held.new_tip.hash,
+        );
+        self.publish(BitcoinEventType::ReorgHeld {
+            old_tip: held.old_tip.hash.to_string(),
+            new_tip: held.new_tip.hash.to_string(),
+            fork_height: held.fork_height,
+            depth,
+            max_depth,
+        });
+        self.held_reorg = Some(held);
+    }
+
+    /// Announce a finished reorg and the blocks it connected
+    fn reorganized(&mut self, record: ReorgRecord, connected: Vec<(Block, UtxoDelta)>) {
+        let depth = record.disconnected.len() as u64;
+        warn!("Chain reorganized from {} to {} (depth: {})", record.old_tip, record.new_tip, depth);
+        self.publish(BitcoinEventType::ChainReorg {
+            old_tip: record.old_tip,
+            new_tip: record.new_tip,
+            depth,
+        });
+        for (block, delta) in connected {
+            self.block_connected(block, delta);
+        }
+    }
+}
+
+/// Disconnect the active chain down to `fork_height`, connect the side chain
+/// blocks and record the reorg. If a side chain block is refused, the blocks
+/// connected so far are disconnected again and the old chain restored.
+async fn reorganize(
+    storage_actor: &Addr<super::storage::StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    old_tip: ChainTip,
+    fork_height: u64,
+    connect: Vec<(Block, u64)>,
+) -> Result<(ReorgRecord, Vec<(Block, UtxoDelta)>), StorageError> {
+    let mut disconnected = Vec::new();
+    for _ in fork_height..old_tip.height {
+        // TODO: Return disconnected transactions to the mempool
+        disconnected.push(storage_actor.send_traced(DisconnectTip).await??);
+    }
+
+    let mut connected = Vec::new();
+    for (block, height) in connect {
+        match apply_block(storage_actor, mempool_actor, block.clone(), height).await {
+            Ok(delta) => connected.push((block, delta)),
+            Err(e) => {
+                warn!("Side chain block {} failed to connect, restoring the chain to {}", block.block_hash(), old_tip.hash);
+                restore_chain(storage_actor, mempool_actor, fork_height, connected.len(), disconnected).await
+                    .inspect_err(|restore| error!("Failed to restore the chain after a failed reorg: {}", restore))?;
+                return Err(e);
+            }
+        }
+    }
+
+    let new_tip = connected.last().map(|(block, delta)| ChainTip {
+        hash: block.block_hash(),
+        height: delta.height,
+    }).unwrap_or(old_tip);
+    let record = storage_actor.send_traced(RecordReorg {
+        old_tip,
+        new_tip,
+        fork_height,
+        disconnected: disconnected.iter().map(Block::block_hash).collect(),
+        connected: connected.iter().map(|(block, _)| block.block_hash()).collect(),
+    }).await??;
+    Ok((record, connected))
+}
+
+/// Undo a partial reorg: disconnect the `side_blocks` side chain blocks
+/// connected above `fork_height` and reconnect the `disconnected` ones, given
+/// tip first
+async fn restore_chain(
+    storage_actor: &Addr<super::storage::StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    fork_height: u64,
+    side_blocks: usize,
+    disconnected: Vec<Block>,
+) -> Result<(), StorageError> {
+    for _ in 0..side_blocks {
+        storage_actor.send_traced(DisconnectTip).await??;
+    }
+    for (height, block) in (fork_height + 1..).zip(disconnected.into_iter().rev()) {
+        apply_block(storage_actor, mempool_actor, block, height).await?;
+    }
+    Ok(())
 }
 
+/// Connect a block in storage, then evict its transactions from the mempool
+async fn apply_block(
+    storage_actor: &Addr<super::storage::StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    block: Block,
+    height: u64,
+) -> Result<UtxoDelta, StorageError> {
+    let delta = storage_actor.send_traced(ConnectBlock { block: block.clone(), height }).await??;
+    match mempool_actor.send_traced(RemoveBlockTransactions { block }).await {
+        Ok(Ok(removed)) if removed > 0 => info!("Removed {} mempool transactions", removed),
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => warn!("Failed to update mempool: {}", e),
+        Err(e) => warn!("Failed to update mempool: {}", e),
+    }
+    Ok(delta)
+}
+
+super::instrument::instrument_actor!(ChainActor);
+
 impl Actor for ChainActor {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("Chain actor started");
+
+        // Hold back other messages until the tip is known so heights line up
+        let storage_actor = self.storage_actor.clone();
+        ctx.wait(
+            async move { storage_actor.send_traced(GetChainTip).await }
+                .into_actor(self)
+                .then(|result, actor, _ctx| {
+                    match result {
+                        Ok(Ok(Some(tip))) => {
+                            info!("Loaded chain tip {} at height {}", tip.hash, tip.height);
+                            actor.set_tip(Some(tip));
+                        }
+                        Ok(Ok(None)) => {
+                            info!("No chain tip found, connecting genesis block");
+                            let genesis = bitcoin::blockdata::constants::genesis_block(actor.network);
+                            return actor.connect_block(genesis, 0);
+                        }
+                        Ok(Err(e)) => error!("Failed to load chain tip: {}", e),
+                        Err(e) => error!("Failed to load chain tip: {}", e),
+                    }
+                    Box::pin(fut::ready(Ok(()))) as ResponseActFuture<Self, Result<(), StorageError>>
+                })
+                .map(|result, _actor, _ctx| {
+                    if let Err(e) = result {
+                        error!("Failed to initialize chain state: {}", e);
+                    }
+                }),
+        );
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -30,13 +499,138 @@ impl Actor for ChainActor {
     }
 }
 
+impl Handler<Shutdown> for ChainActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        // Blocks are connected atomically, so anything queued behind this is dropped
+        ctx.stop();
+    }
+}
+
 impl Handler<StoreBlock> for ChainActor {
-    type Result = Result<(), StorageError>;
+    type Result = AtomicResponse<Self, Result<(), StorageError>>;
 
     fn handle(&mut self, msg: StoreBlock, _ctx: &mut Self::Context) -> Self::Result {
         info!("Processing new block: {}", msg.block.block_hash());
-        // TODO: Validate block and update chain state
-        Ok(())
+        // Refused blocks are logged; peers and miners learn nothing back
+        AtomicResponse::new(Box::pin(self.accept_block(msg.block).map(|result, _actor, _ctx| result.map(|_| ()))))
+    }
+}
+
+#[cfg(feature = "miner")]
+impl Handler<SubmitBlock> for ChainActor {
+    type Result = AtomicResponse<Self, Result<BlockSubmission, StorageError>>;
+
+    fn handle(&mut self, msg: SubmitBlock, _ctx: &mut Self::Context) -> Self::Result {
+        let storage_actor = self.storage_actor.clone();
+        let hash = msg.block.block_hash();
+        info!("Processing submitted block: {}", hash);
+
+        AtomicResponse::new(Box::pin(
+            async move { storage_actor.send_traced(GetBlockHeight { hash }).await }
+                .into_actor(self)
+                .then(move |result, actor, _ctx| match result {
+                    Ok(Ok(None)) => actor.accept_block(msg.block),
+                    Ok(Ok(Some(_))) => Box::pin(fut::ready(Ok(BlockSubmission::Duplicate))),
+                    Ok(Err(e)) => Box::pin(fut::ready(Err(e))),
+                    Err(e) => Box::pin(fut::ready(Err(e.into()))) as ResponseActFuture<Self, _>,
+                })
+                .map(|result, _actor, _ctx| match result {
+                    Err(StorageError::InvalidBlock { reason, .. }) => Ok(BlockSubmission::Rejected(reason)),
+                    result => result,
+                }),
+        ))
+    }
+}
+
+impl Handler<ReloadChainTip> for ChainActor {
+    type Result = AtomicResponse<Self, Result<Option<ChainTip>, StorageError>>;
+
+    fn handle(&mut self, _msg: ReloadChainTip, _ctx: &mut Self::Context) -> Self::Result {
+        let storage_actor = self.storage_actor.clone();
+        AtomicResponse::new(Box::pin(
+            async move { storage_actor.send_traced(GetChainTip).await? }
+                .into_actor(self)
+                .map(|result, actor, _ctx| {
+                    let tip = result?;
+                    info!("Reloaded chain tip at height {:?}", tip.map(|tip| tip.height));
+                    actor.set_tip(tip);
+                    actor.invalid.clear();
+                    Ok(tip)
+                }),
+        ))
+    }
+}
+
+impl Handler<InvalidateBlock> for ChainActor {
+    type Result = AtomicResponse<Self, Result<Option<Vec<BlockHash>>, StorageError>>;
+
+    fn handle(&mut self, msg: InvalidateBlock, _ctx: &mut Self::Context) -> Self::Result {
+        let storage_actor = self.storage_actor.clone();
+        let tip = self.tip;
+        let hash = msg.hash;
+
+        AtomicResponse::new(Box::pin(
+            async move {
+                let (Some(height), Some(tip)) = (storage_actor.send_traced(GetBlockHeight { hash }).await??, tip) else {
+                    return Ok(None);
+                };
+
+                let mut disconnected = Vec::new();
+                for _ in height..=tip.height {
+                    // TODO: Return disconnected transactions to the mempool
+                    disconnected.push(storage_actor.send_traced(DisconnectTip).await??.block_hash());
+                }
+                let new_tip = storage_actor.send_traced(GetChainTip).await??;
+                Ok::<_, StorageError>(Some((disconnected, new_tip)))
+            }
+                .into_actor(self)
+                .map(move |result, actor, _ctx| {
+                    let Some((disconnected, new_tip)) = result? else {
+                        return Ok(None);
+                    };
+                    warn!("Invalidated block {} and disconnected {} blocks", hash, disconnected.len());
+                    actor.invalid.extend(disconnected.iter().copied());
+                    actor.set_tip(new_tip);
+                    Ok(Some(disconnected))
+                }),
+        ))
+    }
+}
+
+impl Handler<WaitForHeight> for ChainActor {
+    type Result = ResponseFuture<Option<ChainTip>>;
+
+    fn handle(&mut self, msg: WaitForHeight, _ctx: &mut Self::Context) -> Self::Result {
+        // Waits on the feed, so the actor keeps connecting blocks meanwhile
+        let tip_feed = self.tip_feed.clone();
+        Box::pin(async move { tip_feed.wait_for_height(msg.height, msg.timeout).await })
+    }
+}
+
+impl Handler<AcceptReorg> for ChainActor {
+    type Result = AtomicResponse<Self, Result<Option<ReorgRecord>, StorageError>>;
+
+    fn handle(&mut self, msg: AcceptReorg, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(held) = self.held_reorg.take_if(|held| held.new_tip.hash == msg.hash) else {
+            return AtomicResponse::new(Box::pin(fut::ready(Ok(None))));
+        };
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+        info!("Operator accepted reorg to {}", msg.hash);
+
+        AtomicResponse::new(Box::pin(
+            async move {
+                reorganize(&storage_actor, &mempool_actor, held.old_tip, held.fork_height, held.connect).await
+            }
+                .into_actor(self)
+                .map(|result, actor, _ctx| {
+                    let (record, connected) = result.inspect_err(|e| actor.note_invalid(e))?;
+                    actor.reorganized(record.clone(), connected);
+                    Ok(Some(record))
+                }),
+        ))
     }
 }
 
@@ -44,12 +638,17 @@ impl Handler<GetChainInfo> for ChainActor {
     type Result = Result<ChainInfo, StorageError>;
 
     fn handle(&mut self, _msg: GetChainInfo, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO: Return actual chain information
+        let (blocks, best_block_hash) = match self.tip {
+            Some(tip) => (tip.height, tip.hash.to_string()),
+            None => (0, "0000000000000000000000000000000000000000000000000000000000000000".to_string()),
+        };
+
+        // TODO: Return actual difficulty, work and timing information
         Ok(ChainInfo {
-            chain: "regtest".to_string(),
-            blocks: 0,
-            headers: 0,
-            best_block_hash: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            chain: self.network.to_core_arg().to_string(),
+            blocks,
+            headers: blocks,
+            best_block_hash,
             difficulty: 1.0,
             median_time: 0,
             verification_progress: 1.0,
@@ -59,4 +658,143 @@ impl Handler<GetChainInfo> for ChainActor {
             pruned: false,
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::mempool::{MempoolDeltaFeed, MEMPOOL_DELTA_BUFFER};
+    use crate::actors::storage::StorageActor;
+    use crate::actors::{GetBlock, GetBlockHash, GetStaleBlocks};
+    use crate::scenario::{Scenario, ScenarioRunner};
+
+    #[actix_rt::test]
+    async fn test_tip_feed_wakes_waiters() {
+        let feed = ChainTipFeed::new();
+        let genesis = ChainTip { hash: BlockHash::all_zeros(), height: 0 };
+        feed.set(Some(genesis));
+
+        // Nothing changes, so the wait runs out and reports the same tip
+        let tip = feed.wait_for_change(genesis.hash, Duration::from_millis(20)).await;
+        assert_eq!(tip, Some(genesis));
+
+        let next = ChainTip { hash: BlockHash::from_byte_array([1; 32]), height: 1 };
+        let waiter = feed.clone();
+        let waiting = tokio::spawn(async move { waiter.wait_for_change(genesis.hash, Duration::from_secs(10)).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        feed.set(Some(next));
+        assert_eq!(waiting.await.unwrap(), Some(next));
+
+        // A client that is behind gets the current tip immediately
+        let tip = feed.wait_for_change(genesis.hash, Duration::from_secs(10)).await;
+        assert_eq!(tip, Some(next));
+        assert_eq!(feed.current(), Some(next));
+
+        // Heights already reached return at once, later ones once the tip gets there
+        assert_eq!(feed.wait_for_height(1, None).await, Some(next));
+        assert_eq!(feed.wait_for_height(2, Some(Duration::from_millis(20))).await, Some(next));
+        let waiter = feed.clone();
+        let waiting = tokio::spawn(async move { waiter.wait_for_height(2, Some(Duration::from_secs(10))).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let higher = ChainTip { hash: BlockHash::from_byte_array([2; 32]), height: 2 };
+        feed.set(Some(higher));
+        assert_eq!(waiting.await.unwrap(), Some(higher));
+    }
+
+    /// Solved regtest block on `parent`, its coinbase made unique by `tag`
+    fn solved_block(parent: &Block, parent_height: u64, tag: &[u8]) -> Block {
+        let template = crate::mining::BlockTemplate::new(parent, parent_height, Vec::new(), bitcoin::Network::Regtest, parent.header.time + 1);
+        let spec = crate::mining::CoinbaseSpec {
+            payouts: vec![(bitcoin::ScriptBuf::from(vec![0x51]), 1.0)],
+            message: tag.to_vec(),
+            extra_nonce_size: 8,
+        };
+        template.solve(&spec, &[0; 8], template.min_time, u64::MAX).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_reorg_restores_the_old_chain() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let event_manager = EventManager::with_publishers(Vec::new());
+
+        let storage_actor = StorageActor::new(&config).start();
+        let mempool_actor = MempoolActor::new(
+            &config,
+            storage_actor.clone(),
+            MempoolDeltaFeed::new(MEMPOOL_DELTA_BUFFER),
+            Vec::new(),
+            event_manager.clone(),
+        ).start();
+        let chain_actor = ChainActor::new(&config, storage_actor.clone(), mempool_actor.clone(), Vec::new(), event_manager).start();
+        let mut runner = ScenarioRunner::new(bitcoin::Network::Regtest, storage_actor.clone(), mempool_actor, chain_actor.clone()).unwrap();
+        runner.run(&Scenario::parse("[[steps]]\naction = \"mine\"\nblocks = 2\n").unwrap()).await.unwrap();
+        let old_tip = storage_actor.send(GetChainTip).await.unwrap().unwrap().unwrap();
+
+        // A fork from height 1 whose second block claims more than the subsidy
+        let fork_hash = storage_actor.send(GetBlockHash { height: 1 }).await.unwrap().unwrap().unwrap();
+        let fork_point = storage_actor.send(GetBlock { hash: fork_hash }).await.unwrap().unwrap().unwrap();
+        let side1 = solved_block(&fork_point, 1, b"side 1");
+        let mut side2 = solved_block(&side1, 2, b"side 2");
+        side2.txdata[0].output[0].value += bitcoin::Amount::from_sat(1);
+        side2.header.merkle_root = side2.compute_merkle_root().unwrap();
+        while side2.header.validate_pow(side2.header.target()).is_err() {
+            side2.header.nonce += 1;
+        }
+
+        chain_actor.send(StoreBlock { block: side1.clone() }).await.unwrap().unwrap();
+        let error = chain_actor.send(StoreBlock { block: side2 }).await.unwrap().unwrap_err();
+        assert!(matches!(error, StorageError::InvalidBlock { ref reason, .. } if reason == "bad-cb-amount"));
+
+        // The old chain is back in place and keeps growing
+        assert_eq!(storage_actor.send(GetChainTip).await.unwrap().unwrap(), Some(old_tip));
+        assert_eq!(storage_actor.send(GetBlockHash { height: 2 }).await.unwrap().unwrap(), Some(old_tip.hash));
+        let info = chain_actor.send(GetChainInfo).await.unwrap().unwrap();
+        assert_eq!(info.best_block_hash, old_tip.hash.to_string());
+        let report = runner.run(&Scenario::parse("[[steps]]\naction = \"mine\"\nblocks = 1\n").unwrap()).await.unwrap();
+        assert_eq!(report.tip_height, 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_deep_reorg_waits_for_acceptance() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.validation.max_reorg_depth = Some(1);
+        let event_manager = EventManager::with_publishers(Vec::new());
+
+        let storage_actor = StorageActor::new(&config).start();
+        let mempool_actor = MempoolActor::new(
+            &config,
+            storage_actor.clone(),
+            MempoolDeltaFeed::new(MEMPOOL_DELTA_BUFFER),
+            Vec::new(),
+            event_manager.clone(),
+        ).start();
+        let chain_actor = ChainActor::new(&config, storage_actor.clone(), mempool_actor.clone(), Vec::new(), event_manager).start();
+        let mut runner = ScenarioRunner::new(bitcoin::Network::Regtest, storage_actor.clone(), mempool_actor, chain_actor.clone()).unwrap();
+
+        // A two block reorg is past the limit, so the fork stays on the side
+        let script = "[[steps]]\naction = \"mine\"\nblocks = 3\n\n[[steps]]\naction = \"reorg\"\ndepth = 2\n";
+        assert!(runner.run(&Scenario::parse(script).unwrap()).await.is_err());
+        let old_tip = storage_actor.send(GetChainTip).await.unwrap().unwrap().unwrap();
+        assert_eq!(old_tip.height, 3);
+        let fork_tip = storage_actor.send(GetStaleBlocks { limit: 1 }).await.unwrap().unwrap().remove(0);
+        assert_eq!(fork_tip.height, 4);
+        let fork_tip: BlockHash = fork_tip.hash.parse().unwrap();
+
+        // Only the held tip can be accepted
+        let accepted = chain_actor.send(AcceptReorg { hash: old_tip.hash }).await.unwrap().unwrap();
+        assert!(accepted.is_none());
+        let record = chain_actor.send(AcceptReorg { hash: fork_tip }).await.unwrap().unwrap().unwrap();
+        assert_eq!(record.disconnected.len(), 2);
+        assert_eq!(record.disconnected[0], old_tip.hash.to_string());
+        assert_eq!(record.connected.len(), 3);
+        let tip = storage_actor.send(GetChainTip).await.unwrap().unwrap().unwrap();
+        assert_eq!(tip, ChainTip { hash: fork_tip, height: 4 });
+
+        // Nothing is held any more
+        assert!(chain_actor.send(AcceptReorg { hash: fork_tip }).await.unwrap().unwrap().is_none());
+    }
+}
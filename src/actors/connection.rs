@@ -0,0 +1,390 @@
+//! Socket side of a peer connection
+//!
+//! Each connection runs as a future on the network actor's context: it reads
+//! bytes into [`PeerProtocol`], writes the replies and queued commands, serves
+//! `getdata` for blocks from storage, syncs headers onto the best header
+//! chain, hands blocks and `notfound` answers to the actor for block fetches,
+//! and reports the handshake outcome and the disconnect back to the actor.
+
+use actix::prelude::*;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::message_blockdata::{GetHeadersMessage, Inventory};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, Witness};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::error::{NetworkResult, StorageError};
+use crate::network::codec;
+use crate::network::protocol::{HandshakeState, PeerProtocol, ProtocolAction};
+use super::instrument::InstrumentedAddr;
+use super::network::NetworkActor;
+use super::storage::StorageActor;
+use super::{AcceptHeaders, BlockFromPeer, BlocksNotFound, DisconnectPeer, GetHeaderLocator, GetRawBlock, PeerHandshakeCompleted};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Headers a `headers` message carries at most; a full one means the peer has more
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+
+/// Requests from the network actor to a running connection
+#[derive(Debug)]
+pub enum PeerCommand {
+    Send(NetworkMessage),
+    Close,
+}
+
+/// Traffic counters shared between a connection and the actor's peer list
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    pub bytes_sent: AtomicU64,
+    pub bytes_received: AtomicU64,
+}
+
+/// The actor's handle on a running connection
+pub struct PeerConnection {
+    pub inbound: bool,
+    pub connected_at: chrono::DateTime<chrono::Utc>,
+    pub stats: Arc<ConnectionStats>,
+    pub commands: mpsc::UnboundedSender<PeerCommand>,
+}
+
+impl PeerConnection {
+    /// Queue a message for the peer, false if the connection already ended
+    pub fn send(&self, message: NetworkMessage) -> bool {
+        self.commands.send(PeerCommand::Send(message)).is_ok()
+    }
+
+    pub fn close(&self) {
+        // The connection may already have ended on its own
+        let _ = self.commands.send(PeerCommand::Close);
+    }
+}
+
+/// Our `version` message for a connection to `peer`; `relay` asks it to announce transactions
+pub fn local_version(peer: SocketAddr, services: u64, nonce: u64, start_height: i32, relay: bool) -> VersionMessage {
+    let unspecified = Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE);
+    let mut version = VersionMessage::new(
+        ServiceFlags::from(services),
+        crate::clock::now_utc().timestamp(),
+        Address::new(&peer, ServiceFlags::NONE),
+        unspecified,
+        nonce,
+        format!("/BitKnotsRS:{}/", env!("CARGO_PKG_VERSION")),
+        start_height,
+    );
+    version.relay = relay;
+    version
+}
+
+/// Everything a connection future needs besides the socket
+pub struct ConnectionContext {
+    pub peer_id: String,
+    pub magic: Magic,
+    pub protocol: PeerProtocol,
+    pub handshake_timeout: Duration,
+    pub stats: Arc<ConnectionStats>,
+    pub commands: mpsc::UnboundedReceiver<PeerCommand>,
+    pub network_actor: Addr<NetworkActor>,
+    /// Where requested blocks are read from
+    pub storage_actor: Addr<StorageActor>,
+}
+
+/// Why a connection ended, and whether the actor already knows
+enum Ended {
+    Remote(String),
+    Local,
+}
+
+pub async fn run_connection(mut stream: TcpStream, context: ConnectionContext) {
+    let ConnectionContext { peer_id, magic, mut protocol, handshake_timeout, stats, mut commands, network_actor, storage_actor } = context;
+    let handshake_deadline = tokio::time::Instant::now() + handshake_timeout;
+    let mut handshake_reported = false;
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+
+    let ended = loop {
+        tokio::select! {
+            read = stream.read(&mut buffer) => {
+                let read = match read {
+                    Ok(0) => break Ended::Remote("connection closed by peer".to_string()),
+                    Ok(read) => read,
+                    Err(e) => break Ended::Remote(format!("read failed: {}", e)),
+                };
+                stats.bytes_received.fetch_add(read as u64, Ordering::Relaxed);
+
+                let mut disconnect = None;
+                let span = tracing::debug_span!("p2p_receive", peer = %peer_id, bytes = read);
+                let actions = span.in_scope(|| protocol.receive(&buffer[..read], Instant::now()));
+                for action in actions {
+                    match action {
+                        ProtocolAction::Send(message) => {
+                            if let Err(e) = write_message(&mut stream, magic, message, &stats).await {
+                                disconnect = Some(format!("write failed: {}", e));
+                                break;
+                            }
+                        }
+                        ProtocolAction::Deliver(NetworkMessage::GetData(items)) => {
+                            if let Err(e) = serve_getdata(&mut stream, magic, &storage_actor, items, &stats).await {
+                                disconnect = Some(format!("write failed: {}", e));
+                                break;
+                            }
+                        }
+                        ProtocolAction::Deliver(NetworkMessage::Headers(headers)) => {
+                            if let Err(reason) = accept_headers(&mut stream, magic, &storage_actor, &peer_id, headers, &stats).await {
+                                disconnect = Some(reason);
+                                break;
+                            }
+                        }
+                        ProtocolAction::Deliver(NetworkMessage::Block(block)) => {
+                            network_actor.do_send(BlockFromPeer { peer_id: peer_id.clone(), block });
+                        }
+                        ProtocolAction::Deliver(NetworkMessage::NotFound(items)) => {
+                            let hashes: Vec<BlockHash> = items.iter().filter_map(|item| requested_block(item).map(|(hash, _)| hash)).collect();
+                            if !hashes.is_empty() {
+                                network_actor.do_send(BlocksNotFound { peer_id: peer_id.clone(), hashes });
+                            }
+                        }
+                        // TODO: Hand transactions and inventory to the chain and mempool
+                        ProtocolAction::Deliver(message) => {
+                            let _span = tracing::debug_span!(parent: &span, "p2p_message", command = %message.cmd()).entered();
+                            debug!("Received {} from peer {}", message.cmd(), peer_id);
+                        }
+                        ProtocolAction::Ignored { command, reason } => debug!("Ignored {} from peer {}: {}", command, peer_id, reason),
+                        ProtocolAction::Misbehaving { score, reason } => warn!("Peer {} misbehaving (score {}): {}", peer_id, score, reason),
+                        ProtocolAction::Disconnect { reason } => disconnect = Some(reason),
+                        ProtocolAction::Violation { violation, tolerated } => {
+                            crate::metrics::record_protocol_violation(violation.as_str(), tolerated);
+                        }
+                    }
+                }
+                if let Some(reason) = disconnect {
+                    break Ended::Remote(reason);
+                }
+
+                if !handshake_reported && protocol.state() == HandshakeState::Established {
+                    handshake_reported = true;
+                    network_actor.do_send(PeerHandshakeCompleted {
+                        peer_id: peer_id.clone(),
+                        success: true,
+                        version: protocol.peer_version(),
+                        user_agent: protocol.peer_user_agent().map(str::to_string),
+                        services: protocol.peer_services(),
+                        detail: None,
+                    });
+                    if let Err(e) = request_headers(&mut stream, magic, &storage_actor, &stats).await {
+                        break Ended::Remote(format!("write failed: {}", e));
+                    }
+                }
+            }
+            command = commands.recv() => match command {
+                Some(PeerCommand::Send(message)) => {
+                    if let Err(e) = write_message(&mut stream, magic, message, &stats).await {
+                        break Ended::Remote(format!("write failed: {}", e));
+                    }
+                }
+                Some(PeerCommand::Close) | None => break Ended::Local,
+            },
+            _ = tokio::time::sleep_until(handshake_deadline), if !handshake_reported => {
+                break Ended::Remote("handshake timed out".to_string());
+            }
+        }
+    };
+
+    let _ = stream.shutdown().await;
+    if let Ended::Remote(reason) = ended {
+        if handshake_reported {
+            network_actor.do_send(DisconnectPeer { peer_id, reason });
+        } else {
+            // The actor disconnects peers whose handshake failed
+            network_actor.do_send(PeerHandshakeCompleted {
+                peer_id,
+                success: false,
+                version: protocol.peer_version(),
+                user_agent: protocol.peer_user_agent().map(str::to_string),
+                services: protocol.peer_services(),
+                detail: Some(reason),
+            });
+        }
+    }
+}
+
+/// Ask the peer for the headers after our best header chain
+async fn request_headers(
+    stream: &mut TcpStream,
+    magic: Magic,
+    storage_actor: &Addr<StorageActor>,
+    stats: &ConnectionStats,
+) -> NetworkResult<()> {
+    let locator = match storage_actor.send_traced(GetHeaderLocator).await {
+        Ok(Ok(locator)) => locator,
+        Ok(Err(e)) => {
+            warn!("Failed to build a header locator: {}", e);
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("Storage unavailable, headers not requested: {}", e);
+            return Ok(());
+        }
+    };
+    let request = GetHeadersMessage::new(locator, BlockHash::all_zeros());
+    write_message(stream, magic, NetworkMessage::GetHeaders(request), stats).await
+}
+
+/// Hand a peer's headers to storage and ask for more after a full batch.
+/// `Err` is the reason to disconnect a peer that sent invalid ones.
+async fn accept_headers(
+    stream: &mut TcpStream,
+    magic: Magic,
+    storage_actor: &Addr<StorageActor>,
+    peer_id: &str,
+    headers: Vec<bitcoin::block::Header>,
+    stats: &ConnectionStats,
+) -> Result<(), String> {
+    let full = headers.len() == MAX_HEADERS_RESULTS;
+    match storage_actor.send_traced(AcceptHeaders { headers }).await {
+        Ok(Ok(Some(best))) => {
+            debug!("Best header {} at height {} after headers from peer {}", best.hash, best.height, peer_id);
+            if full {
+                request_headers(stream, magic, storage_actor, stats).await
+                    .map_err(|e| format!("write failed: {}", e))?;
+            }
+        }
+        Ok(Ok(None)) => debug!("Headers from peer {} do not connect to the best header chain", peer_id),
+        Ok(Err(StorageError::InvalidBlock { hash, reason })) => return Err(format!("invalid header {}: {}", hash, reason)),
+        Ok(Err(e)) => warn!("Failed to store headers from peer {}: {}", peer_id, e),
+        Err(e) => warn!("Storage unavailable, headers from peer {} dropped: {}", peer_id, e),
+    }
+    Ok(())
+}
+
+/// The block an inventory item names, and whether with witnesses
+fn requested_block(item: &Inventory) -> Option<(BlockHash, bool)> {
+    match item {
+        Inventory::Block(hash) => Some((*hash, false)),
+        Inventory::WitnessBlock(hash) => Some((*hash, true)),
+        _ => None,
+    }
+}
+
+/// Answer a `getdata`: every stored block asked for, then one `notfound` for
+/// the rest. Transactions are not served; Core does the same for any it has
+/// not announced to the peer.
+async fn serve_getdata<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    magic: Magic,
+    storage_actor: &Addr<StorageActor>,
+    items: Vec<Inventory>,
+    stats: &ConnectionStats,
+) -> NetworkResult<()> {
+    let mut not_found = Vec::new();
+    for item in items {
+        let Some((hash, witness)) = requested_block(&item) else {
+            not_found.push(item);
+            continue;
+        };
+        match stored_block(storage_actor, hash, witness).await {
+            Some(block) => write_message(stream, magic, NetworkMessage::Block(block), stats).await?,
+            None => not_found.push(item),
+        }
+    }
+    if !not_found.is_empty() {
+        write_message(stream, magic, NetworkMessage::NotFound(not_found), stats).await?;
+    }
+    Ok(())
+}
+
+async fn stored_block(storage_actor: &Addr<StorageActor>, hash: BlockHash, witness: bool) -> Option<Block> {
+    let raw = match storage_actor.send_traced(GetRawBlock { hash }).await {
+        Ok(Ok(raw)) => raw?,
+        Ok(Err(e)) => {
+            warn!("Failed to read block {} for a peer: {}", hash, e);
+            return None;
+        }
+        Err(e) => {
+            warn!("Storage unavailable, block {} not served: {}", hash, e);
+            return None;
+        }
+    };
+    let block = match bitcoin::consensus::deserialize(&raw) {
+        Ok(block) => block,
+        Err(e) => {
+            warn!("Stored block {} does not decode: {}", hash, e);
+            return None;
+        }
+    };
+    Some(if witness { block } else { strip_witnesses(block) })
+}
+
+/// `block` as sent to a peer that asked without `MSG_WITNESS_FLAG`
+fn strip_witnesses(mut block: Block) -> Block {
+    for input in block.txdata.iter_mut().flat_map(|tx| tx.input.iter_mut()) {
+        input.witness = Witness::new();
+    }
+    block
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    magic: Magic,
+    message: NetworkMessage,
+    stats: &ConnectionStats,
+) -> NetworkResult<()> {
+    let sent = codec::write_message(stream, magic, message).await?;
+    stats.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_are_sent_with_witnesses_only_when_asked() {
+        let hash = BlockHash::all_zeros();
+        assert_eq!(requested_block(&Inventory::Block(hash)), Some((hash, false)));
+        assert_eq!(requested_block(&Inventory::WitnessBlock(hash)), Some((hash, true)));
+        assert_eq!(requested_block(&Inventory::WitnessTransaction(bitcoin::Txid::all_zeros())), None);
+
+        let mut block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        block.txdata[0].input[0].witness = Witness::from_slice(&[[0u8; 32]]);
+        let stripped = strip_witnesses(block.clone());
+        assert!(stripped.txdata[0].input[0].witness.is_empty());
+        assert_eq!(stripped.block_hash(), block.block_hash());
+        assert!(bitcoin::consensus::serialize(&stripped).len() < bitcoin::consensus::serialize(&block).len());
+    }
+
+    #[actix_rt::test]
+    async fn test_getdata_answers_notfound_for_blocks_not_stored() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = crate::config::Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let storage_actor = StorageActor::new(&config).start();
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        storage_actor.send(super::super::StoreBlock { block: genesis.clone() }).await.unwrap().unwrap();
+
+        let items = vec![
+            Inventory::WitnessBlock(genesis.block_hash()),
+            Inventory::WitnessBlock(BlockHash::all_zeros()),
+            Inventory::WitnessTransaction(bitcoin::Txid::all_zeros()),
+        ];
+        let (mut ours, mut theirs) = tokio::io::duplex(64 * 1024);
+        let stats = ConnectionStats::default();
+        serve_getdata(&mut ours, Magic::REGTEST, &storage_actor, items.clone(), &stats).await.unwrap();
+
+        // The stored block is sent, everything else is named in one notfound
+        let block = codec::read_message(&mut theirs, Magic::REGTEST).await.unwrap();
+        assert_eq!(block.payload(), &NetworkMessage::Block(genesis));
+        let not_found = codec::read_message(&mut theirs, Magic::REGTEST).await.unwrap();
+        assert_eq!(not_found.payload(), &NetworkMessage::NotFound(items[1..].to_vec()));
+        assert!(stats.bytes_sent.load(Ordering::Relaxed) > 0);
+    }
+}
@@ -0,0 +1,91 @@
+//! Index manager
+//!
+//! Fetches the blocks an address index backfill needs but this node does not
+//! store from peers, one at a time and within a bandwidth limit. The storage
+//! actor indexes each one and drops it without writing it to the block store.
+
+use actix::prelude::*;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use super::instrument::InstrumentedAddr;
+use super::network::NetworkActor;
+use super::storage::StorageActor;
+use super::{FetchBlock, GetBackfillMissingBlock, IndexFetchedBlock};
+
+/// How often to check whether the backfill is waiting for a block
+const BACKFILL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wait before asking peers again when none served a block
+const FETCH_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct IndexManager {
+    storage_actor: Addr<StorageActor>,
+    network_actor: Addr<NetworkActor>,
+    fetch_bytes_per_sec: u64,
+}
+
+impl IndexManager {
+    pub fn new(config: &Config, storage_actor: Addr<StorageActor>, network_actor: Addr<NetworkActor>) -> Self {
+        Self {
+            storage_actor,
+            network_actor,
+            fetch_bytes_per_sec: config.storage.index_backfill_fetch_bytes_per_sec.max(1),
+        }
+    }
+
+    /// Pause after fetching `bytes` so fetches average at most `fetch_bytes_per_sec`
+    fn pause_after(&self, bytes: usize) -> Duration {
+        Duration::from_secs_f64(bytes as f64 / self.fetch_bytes_per_sec as f64)
+    }
+
+    fn fetch_missing_block(&mut self, ctx: &mut Context<Self>) {
+        let storage_actor = self.storage_actor.clone();
+        let network_actor = self.network_actor.clone();
+        ctx.spawn(
+            async move {
+                let Some((height, hash)) = storage_actor.send_traced(GetBackfillMissingBlock).await
+                    .map_err(|e| e.to_string())? else {
+                    return Ok(None);
+                };
+                let block = match network_actor.send(FetchBlock { hash, height }).await {
+                    Ok(Ok(Some(block))) => block,
+                    Ok(Ok(None)) => return Err(format!("no peer served block {}", hash)),
+                    Ok(Err(e)) => return Err(e.to_string()),
+                    Err(e) => return Err(e.to_string()),
+                };
+                let bytes = block.total_size();
+                match storage_actor.send_traced(IndexFetchedBlock { height, block }).await {
+                    Ok(Ok(())) => Ok(Some((height, bytes))),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+            .into_actor(self)
+            .map(|result: Result<Option<(u64, usize)>, String>, actor, ctx| {
+                let pause = match result {
+                    Ok(Some((height, bytes))) => {
+                        debug!("Indexed block {} ({} bytes) fetched from peers", height, bytes);
+                        actor.pause_after(bytes)
+                    }
+                    Ok(None) => BACKFILL_POLL_INTERVAL,
+                    Err(e) => {
+                        warn!("Fetching a block for the address index backfill failed: {}", e);
+                        FETCH_RETRY_INTERVAL
+                    }
+                };
+                ctx.run_later(pause, |actor, ctx| actor.fetch_missing_block(ctx));
+            }),
+        );
+    }
+}
+
+impl Actor for IndexManager {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Index manager started, fetching up to {} bytes/s of missing blocks", self.fetch_bytes_per_sec);
+        self.fetch_missing_block(ctx);
+    }
+}
@@ -0,0 +1,215 @@
+//! Mailbox instrumentation for the chain, mempool and storage actors
+//!
+//! Actix does not expose how many messages wait in a mailbox, so senders wrap
+//! messages in [`Instrumented`] through [`InstrumentedAddr`]: the wrapper
+//! counts itself into the receiving actor's depth gauge when sent and out
+//! again when the actor picks it up or drops it. The actor's handler for the
+//! wrapper records how long the message queued and how long its handler ran,
+//! labelled by actor and message type, inside a tracing span. Handlers that
+//! return futures are only timed up to the point they return them; the time
+//! in queue is what shows one actor backing up behind another.
+//!
+//! The wrapper also carries the sender's current span, and the handler's span
+//! is opened as its child, so an exported trace follows a block or an RPC call
+//! from actor to actor rather than starting over at every mailbox.
+
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use actix::dev::{MessageResponse, OneshotSender, ToEnvelope};
+use actix::prelude::*;
+use tracing::warn;
+
+/// Time in queue plus handling after which a message is logged as slow
+pub const SLOW_MESSAGE: Duration = Duration::from_secs(1);
+
+/// Last path segment of a type name, e.g. `GetBlock` for `bitknotsrs::actors::GetBlock`
+pub fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let base = name.split('<').next().unwrap_or(name);
+    base.rsplit("::").next().unwrap_or(base)
+}
+
+/// A place in an actor's mailbox; leaves the depth gauge when dropped
+struct MailboxTicket {
+    actor: &'static str,
+}
+
+impl MailboxTicket {
+    fn take(actor: &'static str) -> Self {
+        crate::metrics::record_mailbox_enqueued(actor);
+        Self { actor }
+    }
+}
+
+impl Drop for MailboxTicket {
+    fn drop(&mut self) {
+        crate::metrics::record_mailbox_dequeued(self.actor);
+    }
+}
+
+/// A message on its way to an instrumented actor
+pub struct Instrumented<M> {
+    msg: M,
+    enqueued_at: Instant,
+    ticket: MailboxTicket,
+    /// Span the message was sent from
+    parent: tracing::Span,
+}
+
+impl<M: Message> Message for Instrumented<M> {
+    type Result = M::Result;
+}
+
+impl<M> Instrumented<M> {
+    pub fn new<A>(msg: M) -> Self {
+        Self {
+            msg,
+            enqueued_at: Instant::now(),
+            ticket: MailboxTicket::take(short_type_name::<A>()),
+            parent: tracing::Span::current(),
+        }
+    }
+
+    /// Leave the mailbox and run `handle` on the message, recording both halves
+    pub fn dispatch<R>(self, handle: impl FnOnce(M) -> R) -> R {
+        let Self { msg, enqueued_at, ticket, parent } = self;
+        let actor = ticket.actor;
+        drop(ticket);
+
+        let message = short_type_name::<M>();
+        let waited = enqueued_at.elapsed();
+        let span = tracing::debug_span!(
+            parent: &parent, "actor_message", actor, message, waited_ms = waited.as_millis() as u64
+        );
+        let _entered = span.enter();
+
+        let started = Instant::now();
+        let result = handle(msg);
+        let handled = started.elapsed();
+
+        crate::metrics::record_actor_message(actor, message, waited, handled);
+        if waited + handled >= SLOW_MESSAGE {
+            warn!("{} spent {:?} queued and {:?} handling {}", actor, waited, handled, message);
+        }
+        result
+    }
+}
+
+/// `send` and `do_send` for instrumented actors
+pub trait InstrumentedAddr<A: Actor> {
+    fn send_traced<M>(&self, msg: M) -> Request<A, Instrumented<M>>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Instrumented<M>>,
+        A::Context: ToEnvelope<A, Instrumented<M>>;
+
+    fn do_send_traced<M>(&self, msg: M)
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Instrumented<M>>,
+        A::Context: ToEnvelope<A, Instrumented<M>>;
+}
+
+impl<A: Actor> InstrumentedAddr<A> for Addr<A> {
+    fn send_traced<M>(&self, msg: M) -> Request<A, Instrumented<M>>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Instrumented<M>>,
+        A::Context: ToEnvelope<A, Instrumented<M>>,
+    {
+        self.send(Instrumented::new::<A>(msg))
+    }
+
+    fn do_send_traced<M>(&self, msg: M)
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        A: Handler<Instrumented<M>>,
+        A::Context: ToEnvelope<A, Instrumented<M>>,
+    {
+        self.do_send(Instrumented::new::<A>(msg))
+    }
+}
+
+/// Response of an [`Instrumented`] message: the wrapped handler's own response
+pub struct InstrumentedResponse<R, M>(pub R, pub PhantomData<M>);
+
+impl<A, M, R> MessageResponse<A, Instrumented<M>> for InstrumentedResponse<R, M>
+where
+    A: Actor,
+    M: Message,
+    R: MessageResponse<A, M>,
+{
+    fn handle(self, ctx: &mut A::Context, tx: Option<OneshotSender<M::Result>>) {
+        self.0.handle(ctx, tx)
+    }
+}
+
+/// Let an actor accept [`Instrumented`] versions of every message it handles
+macro_rules! instrument_actor {
+    ($actor:ty) => {
+        impl<M> ::actix::Handler<$crate::actors::instrument::Instrumented<M>> for $actor
+        where
+            M: ::actix::Message + Send + 'static,
+            M::Result: Send,
+            $actor: ::actix::Handler<M>,
+        {
+            type Result = $crate::actors::instrument::InstrumentedResponse<<$actor as ::actix::Handler<M>>::Result, M>;
+
+            fn handle(
+                &mut self,
+                msg: $crate::actors::instrument::Instrumented<M>,
+                ctx: &mut Self::Context,
+            ) -> Self::Result {
+                let response = msg.dispatch(|msg| <$actor as ::actix::Handler<M>>::handle(self, msg, ctx));
+                $crate::actors::instrument::InstrumentedResponse(response, ::std::marker::PhantomData)
+            }
+        }
+    };
+}
+
+pub(crate) use instrument_actor;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl Actor for Echo {
+        type Context = Context<Self>;
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "u32")]
+    struct Double(u32);
+
+    impl Handler<Double> for Echo {
+        type Result = u32;
+
+        fn handle(&mut self, msg: Double, _ctx: &mut Self::Context) -> u32 {
+            msg.0 * 2
+        }
+    }
+
+    instrument_actor!(Echo);
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name::<Double>(), "Double");
+        assert_eq!(short_type_name::<crate::actors::GetBlock>(), "GetBlock");
+        assert_eq!(short_type_name::<Vec<crate::actors::GetBlock>>(), "Vec");
+    }
+
+    #[actix_rt::test]
+    async fn test_instrumented_messages_reach_the_handler() {
+        let addr = Echo.start();
+        assert_eq!(addr.send_traced(Double(21)).await.unwrap(), 42);
+        addr.do_send_traced(Double(1));
+        assert_eq!(addr.send(Double(4)).await.unwrap(), 8);
+    }
+}
@@ -1,28 +1,717 @@
 use actix::prelude::*;
-use tracing::{info, error};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use bitcoin::hex::FromHex;
+use tokio::sync::broadcast;
+use tracing::{info, debug, warn, error};
 
-use crate::config::Config;
-use crate::error::StorageError;
-use super::{AddToMempool, GetFromMempool, GetMempoolTxids, GetMempoolInfo, MempoolInfo};
+use crate::amount::{FeeRate, Satoshi};
+use crate::cache::BoundedCache;
+use crate::config::{Config, ScriptFilterMode};
+use crate::error::{PolicyError, SnapshotError, SnapshotResult, StorageError};
+use crate::events::{BitcoinEventType, DoubleSpendSource, EventManager};
+use crate::policy::{check_consensus_sanity, MempoolPolicy, ScriptFilter};
+use crate::script::verify_mempool_transaction;
+use super::{AddToMempool, GetFromMempool, GetMempoolTxids, GetMempoolInfo, MempoolInfo, RemoveFromMempool, GetMempoolSnapshot};
+use super::{AddOrphanTransaction, RemoveBlockTransactions, TransactionAccepted};
+use super::{GetBlockTemplateTransactions, GetMempoolEntry, SubmitMinerTransaction, SubmitTransaction, ExpireMempool};
+use super::{GetMempoolAncestors, GetMempoolDescendants, GetMempoolPackage};
+use super::{ExportMempool, ImportMempool, GetMempoolSpends, EstimateSmartFee, Shutdown, GetUtxos};
+use super::ReloadMempoolPolicy;
+use super::instrument::InstrumentedAddr;
+
+/// Core's default -mempoolexpiry
+const DEFAULT_EXPIRY_HOURS: u64 = 336;
+
+/// Core's default -maxmempool of 300 MB, applied to virtual size
+pub const DEFAULT_MAX_MEMPOOL: u64 = 300_000_000;
+
+/// BIP125 rule 5: transactions one replacement may evict, descendants included
+const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+
+const EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Number of deltas buffered for slow stream subscribers before they lag
+pub const MEMPOOL_DELTA_BUFFER: usize = 4096;
+
+/// Relay floor of 1 sat/vB, also the lowest fee rate ever estimated
+pub const MIN_RELAY_FEE_RATE: FeeRate = FeeRate::from_sat_per_kvb(1000);
+
+/// BIP125 rule 4: a replacement pays for its own relay at this rate on top
+/// of the fees it evicts, matching Core's `-incrementalrelayfee` default
+pub const INCREMENTAL_RELAY_FEE_RATE: FeeRate = FeeRate::from_sat_per_kvb(1000);
+
+/// Block space available to mempool transactions, in vbytes
+const BLOCK_VSIZE: u64 = 1_000_000;
+
+/// Fee rate that would still be mined within `conf_target` blocks if the
+/// mempool were mined in fee rate order, never below the relay floor
+pub fn estimate_fee_rate(entries: impl Iterator<Item = (FeeRate, u64)>, conf_target: u32) -> FeeRate {
+    let mut entries: Vec<(FeeRate, u64)> = entries.collect();
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let capacity = BLOCK_VSIZE * conf_target.max(1) as u64;
+    let mut filled = 0;
+    for (fee_rate, vsize) in entries {
+        filled += vsize;
+        if filled > capacity {
+            // Just outbidding the first transaction left behind is enough
+            return fee_rate.max(MIN_RELAY_FEE_RATE);
+        }
+    }
+    MIN_RELAY_FEE_RATE
+}
+
+/// Size of the child assumed when none is given: one P2WPKH input, one P2WPKH output
+pub const DEFAULT_CPFP_CHILD_VSIZE: u64 = 110;
+
+/// Unconfirmed transactions a child would pay for: the ones it spends and
+/// every in-mempool ancestor of those
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MempoolPackage {
+    pub txids: Vec<bitcoin::Txid>,
+    pub fee: Satoshi,
+    pub vsize: u64,
+}
+
+impl MempoolPackage {
+    pub fn contains(&self, txid: &bitcoin::Txid) -> bool {
+        self.txids.contains(txid)
+    }
+
+    /// Add a transaction the mempool does not hold, such as one about to be broadcast
+    pub fn push(&mut self, txid: bitcoin::Txid, fee: Satoshi, vsize: u64) {
+        self.txids.push(txid);
+        self.fee = self.fee + fee;
+        self.vsize += vsize;
+    }
+
+    pub fn fee_rate(&self) -> FeeRate {
+        FeeRate::from_fee(self.fee, self.vsize)
+    }
+}
+
+/// What a child spending from a package has to pay for miners to take both
+/// at the target fee rate
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CpfpEstimate {
+    pub package_txids: Vec<String>,
+    pub package_fee: Satoshi,
+    pub package_vsize: u64,
+    pub package_fee_rate: FeeRate,
+    pub target_fee_rate: FeeRate,
+    /// False when the package already pays the target on its own
+    pub bump_needed: bool,
+    pub child_vsize: u64,
+    pub child_fee: Satoshi,
+    pub child_fee_rate: FeeRate,
+}
+
+/// Child fee bringing `package` and the child together up to `target`, as
+/// miners select by ancestor fee rate; never below the child's own relay fee
+pub fn cpfp_estimate(package: &MempoolPackage, target: FeeRate, child_vsize: u64) -> CpfpEstimate {
+    let combined = target.fee_for(package.vsize + child_vsize);
+    let child_fee = combined.checked_sub(package.fee)
+        .unwrap_or(Satoshi::ZERO)
+        .max(MIN_RELAY_FEE_RATE.fee_for(child_vsize));
+    CpfpEstimate {
+        package_txids: package.txids.iter().map(|txid| txid.to_string()).collect(),
+        package_fee: package.fee,
+        package_vsize: package.vsize,
+        package_fee_rate: package.fee_rate(),
+        target_fee_rate: target,
+        bump_needed: package.fee_rate() < target,
+        child_vsize,
+        child_fee,
+        child_fee_rate: FeeRate::from_fee(child_fee, child_vsize),
+    }
+}
+
+/// Outputs spent by each input of `tx`, from the UTXO set or unconfirmed
+/// parents in the mempool; `None` for inputs whose coin is missing or spent
+pub async fn resolve_spent_outputs(
+    storage_actor: &Addr<super::storage::StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    tx: &bitcoin::Transaction,
+) -> Result<Vec<Option<bitcoin::TxOut>>, StorageError> {
+    let outpoints: Vec<bitcoin::OutPoint> = tx.input.iter().map(|input| input.previous_output).collect();
+    let utxos = storage_actor.send_traced(GetUtxos { outpoints: outpoints.clone() }).await??;
+
+    let mut spent_outputs = Vec::with_capacity(outpoints.len());
+    for (outpoint, utxo) in outpoints.into_iter().zip(utxos) {
+        spent_outputs.push(match utxo {
+            Some(entry) => Some(entry.output),
+            None => mempool_actor.send_traced(GetFromMempool { txid: outpoint.txid })
+                .await??
+                .and_then(|parent| parent.output.get(outpoint.vout as usize).cloned()),
+        });
+    }
+    Ok(spent_outputs)
+}
+
+/// A single change to the mempool contents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MempoolDelta {
+    Added {
+        txid: String,
+        fee_rate: FeeRate,
+    },
+    Removed {
+        txid: String,
+        reason: RemovalReason,
+    },
+    Replaced {
+        replaced_txid: String,
+        replacement_txid: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    Block,
+    Replaced,
+    Conflict,
+    Evicted,
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SequencedMempoolDelta {
+    pub sequence: u64,
+    #[serde(flatten)]
+    pub delta: MempoolDelta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolSnapshotEntry {
+    pub txid: String,
+    pub fee: Satoshi,
+    pub vsize: u64,
+    pub fee_rate: FeeRate,
+    /// Unix time the transaction entered the mempool
+    pub time: i64,
+}
+
+/// Mempool contents as of `sequence`; deltas with a higher sequence apply on top
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolSnapshot {
+    pub sequence: u64,
+    pub entries: Vec<MempoolSnapshotEntry>,
+}
+
+/// Broadcast feed of sequenced mempool deltas shared between the actor and stream subscribers
+#[derive(Clone)]
+pub struct MempoolDeltaFeed {
+    sender: broadcast::Sender<SequencedMempoolDelta>,
+    sequence: Arc<AtomicU64>,
+}
+
+impl MempoolDeltaFeed {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            sequence: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Assign the next sequence number to a delta and broadcast it
+    pub fn publish(&self, delta: MempoolDelta) -> u64 {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = self.sender.send(SequencedMempoolDelta { sequence, delta });
+        sequence
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedMempoolDelta> {
+        self.sender.subscribe()
+    }
+
+    pub fn current_sequence(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+}
+
+/// Mempool entry as reported by `getmempoolentry`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolEntryInfo {
+    pub txid: String,
+    pub vsize: u64,
+    pub weight: u64,
+    pub fee: Satoshi,
+    pub fee_rate: FeeRate,
+    pub time: i64,
+    /// Submitted through the miner lane
+    pub priority: bool,
+    /// In-mempool parents
+    pub depends: Vec<String>,
+    /// In-mempool children
+    pub spent_by: Vec<String>,
+    /// Counts and virtual sizes of the in-mempool ancestor and descendant
+    /// sets, each including the entry itself
+    pub ancestor_count: u64,
+    pub ancestor_size: u64,
+    pub descendant_count: u64,
+    pub descendant_size: u64,
+}
+
+/// Mempool entry as stored in a node snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MempoolExportEntry {
+    /// Serialized transaction
+    pub hex: String,
+    pub fee: Satoshi,
+    pub fee_rate: FeeRate,
+    pub time: i64,
+    pub priority: bool,
+}
+
+/// Where the mempool is written on shutdown and reloaded from on start, as Core's mempool.dat
+pub const MEMPOOL_FILE: &str = "mempool.json";
+
+const MEMPOOL_FILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct PersistedMempool {
+    version: u32,
+    saved_at: i64,
+    entries: Vec<MempoolExportEntry>,
+}
+
+pub fn save_mempool(path: &Path, entries: Vec<MempoolExportEntry>) -> SnapshotResult<()> {
+    let saved = PersistedMempool { version: MEMPOOL_FILE_VERSION, saved_at: crate::clock::now(), entries };
+    let data = serde_json::to_vec(&saved).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+    // Write then rename so a crash mid-write leaves the previous file
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, data)?;
+    std::fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// The entries saved at `path`, or `None` when nothing was saved
+pub fn load_mempool(path: &Path) -> SnapshotResult<Option<Vec<MempoolExportEntry>>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let saved: PersistedMempool = serde_json::from_slice(&data).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+    if saved.version != MEMPOOL_FILE_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(saved.version));
+    }
+    Ok(Some(saved.entries))
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateTransaction {
+    pub tx: bitcoin::Transaction,
+    pub fee: Satoshi,
+    pub weight: u64,
+    pub priority: bool,
+}
+
+struct MempoolEntry {
+    tx: bitcoin::Transaction,
+    fee: Satoshi,
+    fee_rate: FeeRate,
+    vsize: u64,
+    time: i64,
+    priority: bool,
+    /// In-mempool transactions this one spends from, kept by `insert_entry`
+    parents: HashSet<bitcoin::Txid>,
+    /// In-mempool transactions spending this one
+    children: HashSet<bitcoin::Txid>,
+}
+
+impl MempoolEntry {
+    fn new(tx: bitcoin::Transaction, fee: Satoshi, fee_rate: FeeRate, time: i64, priority: bool) -> Self {
+        Self {
+            vsize: tx.vsize() as u64,
+            tx,
+            fee,
+            fee_rate,
+            time,
+            priority,
+            parents: HashSet::new(),
+            children: HashSet::new(),
+        }
+    }
+}
+
+/// Position in the fee index: miner lane entries above relayed ones, each by fee rate
+/// Field order is the ordering: priority, then fee rate, then txid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct FeeIndexKey {
+    priority: bool,
+    fee_rate: FeeRate,
+    txid: bitcoin::Txid,
+}
+
+impl FeeIndexKey {
+    fn of(txid: bitcoin::Txid, entry: &MempoolEntry) -> Self {
+        Self { priority: entry.priority, fee_rate: entry.fee_rate, txid }
+    }
+}
+
+struct OrphanEntry {
+    tx: bitcoin::Transaction,
+    fee: Satoshi,
+    fee_rate: FeeRate,
+    spent_outputs: Vec<Option<bitcoin::TxOut>>,
+}
 
 pub struct MempoolActor {
     _storage_actor: Addr<super::storage::StorageActor>,
+    entries: HashMap<bitcoin::Txid, MempoolEntry>,
+    /// Every entry, lowest fee rate first
+    by_fee_rate: BTreeSet<FeeIndexKey>,
+    /// Sum of the entries' virtual sizes
+    total_vsize: u64,
+    max_vsize: u64,
+    spent_by: HashMap<bitcoin::OutPoint, bitcoin::Txid>,
+    recent_rejects: BoundedCache<bitcoin::Txid, ()>,
+    orphans: BoundedCache<bitcoin::Txid, OrphanEntry>,
+    deltas: MempoolDeltaFeed,
+    tx_subscribers: Vec<Recipient<TransactionAccepted>>,
+    policy: MempoolPolicy,
+    /// Applies to the relay lane only; the miner lane and snapshot restores bypass it
+    script_filter: ScriptFilter,
+    expiry_secs: i64,
+    event_manager: EventManager,
+    network_name: &'static str,
+    node_id: String,
+    /// Set when `mempool.persist_mempool` is on
+    persist_path: Option<PathBuf>,
 }
 
 impl MempoolActor {
-    pub fn new(config: &Config, storage_actor: Addr<super::storage::StorageActor>) -> Self {
-        info!("Mempool actor initialized");
+    pub fn new(
+        config: &Config,
+        storage_actor: Addr<super::storage::StorageActor>,
+        deltas: MempoolDeltaFeed,
+        tx_subscribers: Vec<Recipient<TransactionAccepted>>,
+        event_manager: EventManager,
+    ) -> Self {
+        let policy = MempoolPolicy::from_config(&config.mempool);
+        info!("Mempool actor initialized with {} policy", policy.profile.as_str());
+        let script_filter = Self::script_filter(config);
         Self {
             _storage_actor: storage_actor,
+            entries: HashMap::new(),
+            by_fee_rate: BTreeSet::new(),
+            total_vsize: 0,
+            max_vsize: config.mempool.max_mempool.unwrap_or(DEFAULT_MAX_MEMPOOL),
+            spent_by: HashMap::new(),
+            recent_rejects: BoundedCache::new("recent_rejects", config.caches.reject_max_bytes)
+                .with_ttl(std::time::Duration::from_secs(config.caches.reject_ttl_secs)),
+            orphans: BoundedCache::new("orphans", config.caches.orphan_max_bytes),
+            deltas,
+            tx_subscribers,
+            policy,
+            script_filter,
+            expiry_secs: (config.mempool.expiry_hours.unwrap_or(DEFAULT_EXPIRY_HOURS) * 3600) as i64,
+            event_manager,
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+            persist_path: config.mempool.persist_mempool.unwrap_or(true).then(|| config.datadir.join(MEMPOOL_FILE)),
+        }
+    }
+
+    /// Mempool transactions spending any input of `tx`, with the first shared outpoint
+    fn conflicts(&self, tx: &bitcoin::Transaction) -> Vec<(bitcoin::Txid, bitcoin::OutPoint)> {
+        let mut seen = HashSet::new();
+        tx.input
+            .iter()
+            .filter_map(|input| {
+                self.spent_by
+                    .get(&input.previous_output)
+                    .map(|txid| (*txid, input.previous_output))
+            })
+            .filter(|(txid, _)| seen.insert(*txid))
+            .collect()
+    }
+
+    fn reject(&mut self, txid: bitcoin::Txid) {
+        self.recent_rejects.insert(txid, (), 0);
+        crate::metrics::record_cache_stats(&self.recent_rejects.stats());
+    }
+
+    fn insert_entry(&mut self, txid: bitcoin::Txid, mut entry: MempoolEntry) {
+        self.remove_entry(&txid);
+        entry.parents = self.parents(&entry.tx).into_iter().collect();
+        // Children usually arrive after their parents, but an import may hold them in any order
+        entry.children = (0..entry.tx.output.len() as u32)
+            .filter_map(|vout| self.spent_by.get(&bitcoin::OutPoint { txid, vout }).copied())
+            .collect();
+        for parent in &entry.parents {
+            if let Some(parent) = self.entries.get_mut(parent) {
+                parent.children.insert(txid);
+            }
+        }
+        for child in &entry.children {
+            if let Some(child) = self.entries.get_mut(child) {
+                child.parents.insert(txid);
+            }
+        }
+        for input in &entry.tx.input {
+            self.spent_by.insert(input.previous_output, txid);
+        }
+        self.by_fee_rate.insert(FeeIndexKey::of(txid, &entry));
+        self.total_vsize += entry.vsize;
+        self.entries.insert(txid, entry);
+        crate::metrics::record_mempool_stats(self.entries.len() as u64, self.total_vsize);
+    }
+
+    fn remove_entry(&mut self, txid: &bitcoin::Txid) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(txid)?;
+        for input in &entry.tx.input {
+            if self.spent_by.get(&input.previous_output) == Some(txid) {
+                self.spent_by.remove(&input.previous_output);
+            }
+        }
+        for parent in &entry.parents {
+            if let Some(parent) = self.entries.get_mut(parent) {
+                parent.children.remove(txid);
+            }
+        }
+        for child in &entry.children {
+            if let Some(child) = self.entries.get_mut(child) {
+                child.parents.remove(txid);
+            }
+        }
+        self.by_fee_rate.remove(&FeeIndexKey::of(*txid, &entry));
+        self.total_vsize -= entry.vsize;
+        crate::metrics::record_mempool_stats(self.entries.len() as u64, self.total_vsize);
+        Some(entry)
+    }
+
+    /// `txid` followed by every entry spending it, directly or through others
+    fn with_descendants(&self, txid: bitcoin::Txid) -> Vec<bitcoin::Txid> {
+        let mut found = vec![txid];
+        found.extend(self.related(&[txid], |entry| &entry.children));
+        found
+    }
+
+    /// `txids` that are in the mempool and every entry spending any of them, each once
+    fn with_all_descendants(&self, txids: impl IntoIterator<Item = bitcoin::Txid>) -> Vec<bitcoin::Txid> {
+        let start: Vec<bitcoin::Txid> = txids.into_iter().filter(|txid| self.entries.contains_key(txid)).collect();
+        let mut seen = HashSet::new();
+        let mut found: Vec<bitcoin::Txid> = start.iter().copied().filter(|txid| seen.insert(*txid)).collect();
+        found.extend(self.related(&found.clone(), |entry| &entry.children));
+        found
+    }
+
+    /// BIP125 rules 3, 4 and 5 for a relayed replacement: it pays what
+    /// everything it evicts paid plus its own relay at the incremental rate,
+    /// and evicts at most 100 transactions
+    fn pays_for_replacement(&self, fee: Satoshi, vsize: u64, replaced: &[bitcoin::Txid]) -> bool {
+        let replaced_fees: Satoshi = replaced.iter().filter_map(|txid| self.entries.get(txid)).map(|entry| entry.fee).sum();
+        replaced.len() <= MAX_REPLACEMENT_CANDIDATES
+            && fee >= replaced_fees + INCREMENTAL_RELAY_FEE_RATE.fee_for(vsize)
+    }
+
+    /// Remove `replaced`, conflicts and their descendants alike, for `replacement`
+    fn replace_entries(&mut self, replaced: &[bitcoin::Txid], replacement: bitcoin::Txid) {
+        for txid in replaced {
+            if self.remove_entry(txid).is_some() {
+                self.deltas.publish(MempoolDelta::Replaced {
+                    replaced_txid: txid.to_string(),
+                    replacement_txid: replacement.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Entries reachable from `start` by following `links`, excluding `start` itself
+    fn related(
+        &self,
+        start: &[bitcoin::Txid],
+        links: impl Fn(&MempoolEntry) -> &HashSet<bitcoin::Txid>,
+    ) -> Vec<bitcoin::Txid> {
+        let mut seen: HashSet<bitcoin::Txid> = start.iter().copied().collect();
+        let mut queue = start.to_vec();
+        let mut found = Vec::new();
+        while let Some(txid) = queue.pop() {
+            let Some(entry) = self.entries.get(&txid) else {
+                continue;
+            };
+            for linked in links(entry) {
+                if seen.insert(*linked) {
+                    found.push(*linked);
+                    queue.push(*linked);
+                }
+            }
+        }
+        found
+    }
+
+    fn ancestors(&self, txid: &bitcoin::Txid) -> Vec<bitcoin::Txid> {
+        self.related(&[*txid], |entry| &entry.parents)
+    }
+
+    fn descendants(&self, txid: &bitcoin::Txid) -> Vec<bitcoin::Txid> {
+        self.related(&[*txid], |entry| &entry.children)
+    }
+
+    /// `txids` that are in the mempool, then their ancestors
+    fn package(&self, txids: Vec<bitcoin::Txid>) -> MempoolPackage {
+        let mut seen = HashSet::new();
+        let start: Vec<bitcoin::Txid> = txids
+            .into_iter()
+            .filter(|txid| self.entries.contains_key(txid) && seen.insert(*txid))
+            .collect();
+        let ancestors = self.related(&start, |entry| &entry.parents);
+
+        let mut package = MempoolPackage::default();
+        for txid in start.into_iter().chain(ancestors) {
+            let entry = &self.entries[&txid];
+            package.push(txid, entry.fee, entry.vsize);
+        }
+        package
+    }
+
+    fn total_vsize_of(&self, txids: &[bitcoin::Txid]) -> u64 {
+        txids.iter().filter_map(|txid| self.entries.get(txid)).map(|entry| entry.vsize).sum()
+    }
+
+    /// Whether adding `tx` keeps its would-be ancestors, and each of their
+    /// descendant sets, within the policy's chain limits
+    fn check_chain_limits(&self, tx: &bitcoin::Transaction) -> Result<(), PolicyError> {
+        let limits = &self.policy.chain_limits;
+        let vsize = tx.vsize() as u64;
+        let parents = self.parents(tx);
+        let mut ancestors = self.related(&parents, |entry| &entry.parents);
+        ancestors.extend(parents);
+
+        if ancestors.len() + 1 > limits.ancestor_count {
+            return Err(PolicyError::TooLongMempoolChain(format!(
+                "too many unconfirmed ancestors [limit: {}]", limits.ancestor_count
+            )));
         }
+        if self.total_vsize_of(&ancestors) + vsize > limits.ancestor_vsize {
+            return Err(PolicyError::TooLongMempoolChain(format!(
+                "exceeds ancestor size limit [limit: {}]", limits.ancestor_vsize
+            )));
+        }
+        for ancestor in &ancestors {
+            let mut descendants = self.descendants(ancestor);
+            descendants.push(*ancestor);
+            if descendants.len() + 1 > limits.descendant_count {
+                return Err(PolicyError::TooLongMempoolChain(format!(
+                    "too many descendants for tx {} [limit: {}]", ancestor, limits.descendant_count
+                )));
+            }
+            if self.total_vsize_of(&descendants) + vsize > limits.descendant_vsize {
+                return Err(PolicyError::TooLongMempoolChain(format!(
+                    "exceeds descendant size limit for tx {} [limit: {}]", ancestor, limits.descendant_vsize
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evict the lowest fee rate entries, with everything spending them, until
+    /// the mempool fits in `max_vsize`; miner lane entries go last
+    fn trim_to_size(&mut self) -> usize {
+        let mut evicted = 0;
+        while self.total_vsize > self.max_vsize {
+            let Some(lowest) = self.by_fee_rate.first().map(|key| key.txid) else {
+                break;
+            };
+            for txid in self.with_descendants(lowest) {
+                if self.remove_entry(&txid).is_some() {
+                    evicted += 1;
+                    self.deltas.publish(MempoolDelta::Removed {
+                        txid: txid.to_string(),
+                        reason: RemovalReason::Evicted,
+                    });
+                }
+            }
+        }
+        if evicted > 0 {
+            info!("Evicted {} transactions to keep the mempool under {} vbytes", evicted, self.max_vsize);
+        }
+        evicted
+    }
+
+    /// Remove entries first seen before `now - expiry`, then everything spending them.
+    /// Runs every [`EXPIRY_CHECK_INTERVAL`] and when `setmocktime` moves the clock.
+    fn expire(&mut self, now: i64) -> usize {
+        let cutoff = now - self.expiry_secs;
+        let expired = self.with_all_descendants(
+            self.entries.iter().filter(|(_, entry)| entry.time < cutoff).map(|(txid, _)| *txid).collect::<Vec<_>>(),
+        );
+
+        for txid in &expired {
+            self.remove_entry(txid);
+            self.deltas.publish(MempoolDelta::Removed {
+                txid: txid.to_string(),
+                reason: RemovalReason::Expired,
+            });
+        }
+        if !expired.is_empty() {
+            info!("Expired {} mempool transactions", expired.len());
+        }
+        expired.len()
+    }
+
+    fn emit_double_spend(
+        &self,
+        txid: bitcoin::Txid,
+        conflicting_txid: bitcoin::Txid,
+        outpoint: bitcoin::OutPoint,
+        source: DoubleSpendSource,
+        block_hash: Option<bitcoin::BlockHash>,
+    ) {
+        warn!("Double spend of {} detected: {} conflicts with mempool transaction {}",
+              outpoint, txid, conflicting_txid);
+
+        let event_manager = self.event_manager.clone();
+        let network = self.network_name;
+        let node_id = self.node_id.clone();
+        let event = BitcoinEventType::DoubleSpendDetected {
+            txid: txid.to_string(),
+            conflicting_txid: conflicting_txid.to_string(),
+            outpoint: outpoint.to_string(),
+            source,
+            block_hash: block_hash.map(|hash| hash.to_string()),
+        };
+        self.event_manager.spawn_delivery(async move {
+            if let Err(e) = event_manager.publish(event, network, &node_id).await {
+                warn!("Failed to publish double spend event: {}", e);
+            }
+        });
     }
 }
 
+super::instrument::instrument_actor!(MempoolActor);
+
 impl Actor for MempoolActor {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("Mempool actor started");
+        if let Some(path) = self.persist_path.clone() {
+            match load_mempool(&path) {
+                Ok(Some(entries)) => {
+                    if let Err(e) = self.import_entries(entries) {
+                        warn!("Ignoring saved mempool {}: {}", path.display(), e);
+                    }
+                    // Whatever aged out while the node was down goes now
+                    self.expire(crate::clock::now());
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Ignoring saved mempool {}: {}", path.display(), e),
+            }
+        }
+        ctx.run_interval(EXPIRY_CHECK_INTERVAL, |actor, _ctx| {
+            actor.expire(crate::clock::now());
+        });
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -30,24 +719,490 @@ impl Actor for MempoolActor {
     }
 }
 
+impl Handler<Shutdown> for MempoolActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        if let Some(path) = &self.persist_path {
+            let entries = self.export_entries();
+            let count = entries.len();
+            match save_mempool(path, entries) {
+                Ok(()) => info!("Saved {} mempool transactions to {}", count, path.display()),
+                Err(e) => error!("Failed to save mempool to {}: {}", path.display(), e),
+            }
+        }
+        ctx.stop();
+    }
+}
+
 impl Handler<AddToMempool> for MempoolActor {
     type Result = Result<(), StorageError>;
 
-    fn handle(&mut self, msg: AddToMempool, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: AddToMempool, ctx: &mut Self::Context) -> Self::Result {
+        let txid = msg.tx.txid();
         info!("Adding transaction to mempool: {} (fee: {}, fee_rate: {})",
-               msg.tx.txid(), msg.fee, msg.fee_rate);
-        // TODO: Validate transaction and add to mempool
+               txid, msg.fee, msg.fee_rate);
+        if self.entries.contains_key(&txid) {
+            debug!("Transaction {} already in mempool", txid);
+            return Ok(());
+        }
+        if self.recent_rejects.contains(&txid) {
+            debug!("Transaction {} was recently rejected", txid);
+            return Ok(());
+        }
+
+        if let Err(e) = self.admit_relayed(msg.tx, msg.fee, msg.fee_rate, &msg.spent_outputs, ctx) {
+            info!("Rejected transaction {}: {}", txid, e);
+            self.reject(txid);
+        }
+        Ok(())
+    }
+}
+
+impl Handler<SubmitTransaction> for MempoolActor {
+    type Result = Result<bitcoin::Txid, PolicyError>;
+
+    fn handle(&mut self, msg: SubmitTransaction, ctx: &mut Self::Context) -> Self::Result {
+        let txid = msg.tx.txid();
+        if self.entries.contains_key(&txid) {
+            return Ok(txid);
+        }
+
+        // Unlike relayed transactions, local ones are checked again even if recently rejected
+        let fee_rate = FeeRate::from_fee(msg.fee, msg.tx.vsize() as u64);
+        info!("Accepting local transaction {} (fee: {})", txid, msg.fee);
+        self.admit_relayed(msg.tx, msg.fee, fee_rate, &msg.spent_outputs, ctx)?;
+        Ok(txid)
+    }
+}
+
+impl Handler<SubmitMinerTransaction> for MempoolActor {
+    type Result = Result<bitcoin::Txid, PolicyError>;
+
+    fn handle(&mut self, msg: SubmitMinerTransaction, ctx: &mut Self::Context) -> Self::Result {
+        let txid = msg.tx.txid();
+        check_consensus_sanity(&msg.tx)?;
+
+        // Already relayed transactions are promoted rather than resubmitted
+        if let Some(entry) = self.entries.get_mut(&txid) {
+            entry.priority = true;
+            info!("Promoted mempool transaction {} to the priority lane", txid);
+            return Ok(txid);
+        }
+        verify_mempool_transaction(&msg.tx, &msg.spent_outputs)?;
+
+        let fee_rate = FeeRate::from_fee(msg.fee, msg.tx.vsize() as u64);
+        info!("Accepting priority transaction {} from miner lane (fee: {})", txid, msg.fee);
+        self.accept(msg.tx, msg.fee, fee_rate, true, ctx)?;
+        Ok(txid)
+    }
+}
+
+impl MempoolActor {
+    fn script_filter(config: &Config) -> ScriptFilter {
+        let script_filter = ScriptFilter::new(config).unwrap_or_else(|e| {
+            error!("Invalid script filter, relaying without one: {}", e);
+            ScriptFilter::default()
+        });
+        info!("Script filter: {} ({} scripts)", script_filter.mode.as_str(), script_filter.len());
+        if script_filter.mode == ScriptFilterMode::Allowlist && script_filter.is_empty() {
+            warn!("Script filter allowlist is empty, every relayed transaction will be refused");
+        }
+        script_filter
+    }
+
+    /// The relay lane: standardness, the script filter, chain limits and
+    /// scripts, then acceptance
+    fn admit_relayed(
+        &mut self,
+        tx: bitcoin::Transaction,
+        fee: Satoshi,
+        fee_rate: FeeRate,
+        spent_outputs: &[bitcoin::TxOut],
+        ctx: &mut Context<Self>,
+    ) -> Result<(), PolicyError> {
+        if let Err(e) = self.script_filter.check(&tx, spent_outputs) {
+            warn!("Script filter refused transaction {}: {}", tx.txid(), e);
+            return Err(e);
+        }
+        check_consensus_sanity(&tx)
+            .and_then(|()| self.policy.check_standard(&tx))
+            .and_then(|()| self.check_chain_limits(&tx))
+            .and_then(|()| verify_mempool_transaction(&tx, spent_outputs))?;
+        self.accept(tx, fee, fee_rate, false, ctx)
+    }
+
+    /// Insert a transaction that passed the checks for its lane, resolving conflicts by replacement
+    fn accept(
+        &mut self,
+        tx: bitcoin::Transaction,
+        fee: Satoshi,
+        fee_rate: FeeRate,
+        priority: bool,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), PolicyError> {
+        let txid = tx.txid();
+        let conflicts = self.conflicts(&tx);
+        if !conflicts.is_empty() {
+            // A conflicting transaction replaces the originals only if policy allows and it pays a higher fee rate.
+            // The miner lane always wins over relayed transactions and only competes on fee rate with itself.
+            let replaced = self.with_all_descendants(conflicts.iter().map(|(conflicting, _)| *conflicting));
+            let replaces = conflicts.iter().all(|(conflicting, _)| {
+                let original = &self.entries[conflicting];
+                match (priority, original.priority) {
+                    (true, false) => true,
+                    (false, true) => false,
+                    (true, true) => fee_rate > original.fee_rate,
+                    (false, false) => self.policy.allows_replacement(&original.tx) && fee_rate > original.fee_rate,
+                }
+            }) && (priority || self.pays_for_replacement(fee, tx.vsize() as u64, &replaced));
+            let source = if replaces { DoubleSpendSource::Replacement } else { DoubleSpendSource::Mempool };
+
+            for (conflicting, outpoint) in &conflicts {
+                self.emit_double_spend(txid, *conflicting, *outpoint, source, None);
+            }
+
+            if !replaces {
+                return Err(PolicyError::ReplacementRejected { conflicts: conflicts.len() });
+            }
+
+            self.replace_entries(&replaced, txid);
+        }
+
+        self.insert_entry(txid, MempoolEntry::new(tx, fee, fee_rate, crate::clock::now(), priority));
+        self.deltas.publish(MempoolDelta::Added {
+            txid: txid.to_string(),
+            fee_rate,
+        });
+        // A full mempool may evict the newcomer itself when it pays the least
+        self.trim_to_size();
+        let Some(entry) = self.entries.get(&txid) else {
+            return Err(PolicyError::MempoolFull);
+        };
+        for subscriber in &self.tx_subscribers {
+            subscriber.do_send(TransactionAccepted { tx: entry.tx.clone() });
+        }
+
+        // Orphans spending this transaction can now be reconsidered
+        let children = self.orphans.drain_matching(|_, orphan| {
+            orphan.tx.input.iter().any(|input| input.previous_output.txid == txid)
+        });
+        if !children.is_empty() {
+            crate::metrics::record_cache_stats(&self.orphans.stats());
+        }
+        for (orphan_txid, mut orphan) in children {
+            for (input, spent) in orphan.tx.input.iter().zip(orphan.spent_outputs.iter_mut()) {
+                if spent.is_none() {
+                    let prevout = input.previous_output;
+                    *spent = self.entries.get(&prevout.txid)
+                        .and_then(|parent| parent.tx.output.get(prevout.vout as usize).cloned());
+                }
+            }
+            let Some(spent_outputs) = orphan.spent_outputs.iter().cloned().collect::<Option<Vec<_>>>() else {
+                debug!("Orphan {} still waits on another parent", orphan_txid);
+                let size = orphan.tx.total_size();
+                self.orphans.insert(orphan_txid, orphan, size);
+                continue;
+            };
+
+            debug!("Reconsidering orphan {} after parent {}", orphan_txid, txid);
+            let retry = AddToMempool { tx: orphan.tx, fee: orphan.fee, fee_rate: orphan.fee_rate, spent_outputs };
+            // Only fails on mailbox errors, which cannot happen for a direct call
+            let _ = <Self as Handler<AddToMempool>>::handle(self, retry, ctx);
+        }
+        Ok(())
+    }
+
+    /// In-mempool parents of an entry
+    fn parents(&self, tx: &bitcoin::Transaction) -> Vec<bitcoin::Txid> {
+        let mut seen = HashSet::new();
+        tx.input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|parent| self.entries.contains_key(parent) && seen.insert(*parent))
+            .collect()
+    }
+}
+
+impl Handler<AddOrphanTransaction> for MempoolActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: AddOrphanTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        let txid = msg.tx.txid();
+        if self.entries.contains_key(&txid) || self.recent_rejects.contains(&txid) {
+            return Ok(());
+        }
+
+        debug!("Holding orphan transaction {}", txid);
+        let size = msg.tx.total_size();
+        let mut spent_outputs = msg.spent_outputs;
+        spent_outputs.resize(msg.tx.input.len(), None);
+        self.orphans.insert(txid, OrphanEntry { tx: msg.tx, fee: msg.fee, fee_rate: msg.fee_rate, spent_outputs }, size);
+        crate::metrics::record_cache_stats(&self.orphans.stats());
         Ok(())
     }
 }
 
+impl Handler<ExportMempool> for MempoolActor {
+    type Result = MessageResult<ExportMempool>;
+
+    fn handle(&mut self, _msg: ExportMempool, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.export_entries())
+    }
+}
+
+impl Handler<ImportMempool> for MempoolActor {
+    type Result = Result<usize, StorageError>;
+
+    fn handle(&mut self, msg: ImportMempool, _ctx: &mut Self::Context) -> Self::Result {
+        self.import_entries(msg.entries)
+    }
+}
+
+impl MempoolActor {
+    fn export_entries(&self) -> Vec<MempoolExportEntry> {
+        let mut entries: Vec<MempoolExportEntry> = self.entries
+            .values()
+            .map(|entry| MempoolExportEntry {
+                hex: bitcoin::consensus::encode::serialize_hex(&entry.tx),
+                fee: entry.fee,
+                fee_rate: entry.fee_rate,
+                time: entry.time,
+                priority: entry.priority,
+            })
+            .collect();
+        // Oldest first keeps parents ahead of their children on import
+        entries.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.hex.cmp(&b.hex)));
+        entries
+    }
+
+    /// Replace the contents with `entries`, all or nothing
+    fn import_entries(&mut self, entries: Vec<MempoolExportEntry>) -> Result<usize, StorageError> {
+        let mut imported = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let bytes = Vec::<u8>::from_hex(&entry.hex)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            let tx: bitcoin::Transaction = bitcoin::consensus::deserialize(&bytes)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            imported.push(MempoolEntry::new(tx, entry.fee, entry.fee_rate, entry.time, entry.priority));
+        }
+
+        let txids: Vec<bitcoin::Txid> = self.entries.keys().copied().collect();
+        for txid in txids {
+            self.remove_entry(&txid);
+            self.deltas.publish(MempoolDelta::Removed {
+                txid: txid.to_string(),
+                reason: RemovalReason::Evicted,
+            });
+        }
+        let count = imported.len();
+        for entry in imported {
+            let txid = entry.tx.txid();
+            let fee_rate = entry.fee_rate;
+            self.insert_entry(txid, entry);
+            self.deltas.publish(MempoolDelta::Added {
+                txid: txid.to_string(),
+                fee_rate,
+            });
+        }
+        self.trim_to_size();
+        info!("Imported {} mempool transactions", count);
+        Ok(count)
+    }
+}
+
+impl Handler<ReloadMempoolPolicy> for MempoolActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReloadMempoolPolicy, _ctx: &mut Self::Context) -> Self::Result {
+        let config = msg.config;
+        self.policy = MempoolPolicy::from_config(&config.mempool);
+        self.script_filter = Self::script_filter(&config);
+        self.max_vsize = config.mempool.max_mempool.unwrap_or(DEFAULT_MAX_MEMPOOL);
+        self.expiry_secs = (config.mempool.expiry_hours.unwrap_or(DEFAULT_EXPIRY_HOURS) * 3600) as i64;
+        info!("Mempool policy reloaded with {} policy", self.policy.profile.as_str());
+        self.trim_to_size();
+    }
+}
+
+impl Handler<ExpireMempool> for MempoolActor {
+    type Result = usize;
+
+    fn handle(&mut self, _msg: ExpireMempool, _ctx: &mut Self::Context) -> Self::Result {
+        self.expire(crate::clock::now())
+    }
+}
+
+impl Handler<GetMempoolSpends> for MempoolActor {
+    type Result = MessageResult<GetMempoolSpends>;
+
+    fn handle(&mut self, msg: GetMempoolSpends, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(msg.outpoints.into_iter().filter(|outpoint| self.spent_by.contains_key(outpoint)).collect())
+    }
+}
+
+impl Handler<RemoveFromMempool> for MempoolActor {
+    type Result = Result<usize, StorageError>;
+
+    fn handle(&mut self, msg: RemoveFromMempool, _ctx: &mut Self::Context) -> Self::Result {
+        let mut removed = 0;
+        for txid in msg.txids {
+            if self.remove_entry(&txid).is_some() {
+                removed += 1;
+                self.deltas.publish(MempoolDelta::Removed {
+                    txid: txid.to_string(),
+                    reason: msg.reason,
+                });
+            }
+        }
+        Ok(removed)
+    }
+}
+
+impl Handler<RemoveBlockTransactions> for MempoolActor {
+    type Result = Result<usize, StorageError>;
+
+    fn handle(&mut self, msg: RemoveBlockTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = msg.block.block_hash();
+        let mut removed = 0;
+
+        for tx in &msg.block.txdata {
+            let txid = tx.txid();
+            if self.remove_entry(&txid).is_some() {
+                removed += 1;
+                self.deltas.publish(MempoolDelta::Removed {
+                    txid: txid.to_string(),
+                    reason: RemovalReason::Block,
+                });
+                continue;
+            }
+
+            if tx.is_coinbase() {
+                continue;
+            }
+
+            let conflicts = self.conflicts(tx);
+            for (conflicting, outpoint) in &conflicts {
+                self.emit_double_spend(txid, *conflicting, *outpoint, DoubleSpendSource::Block, Some(block_hash));
+            }
+            // Whatever spends a conflicted transaction spends outputs that no longer exist
+            for conflicted in self.with_all_descendants(conflicts.into_iter().map(|(conflicting, _)| conflicting)) {
+                if self.remove_entry(&conflicted).is_some() {
+                    removed += 1;
+                    self.deltas.publish(MempoolDelta::Removed {
+                        txid: conflicted.to_string(),
+                        reason: RemovalReason::Conflict,
+                    });
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
 impl Handler<GetFromMempool> for MempoolActor {
     type Result = Result<Option<bitcoin::Transaction>, StorageError>;
 
     fn handle(&mut self, msg: GetFromMempool, _ctx: &mut Self::Context) -> Self::Result {
         info!("Getting transaction from mempool: {}", msg.txid);
-        // TODO: Get actual transaction from mempool
-        Ok(None)
+        Ok(self.entries.get(&msg.txid).map(|entry| entry.tx.clone()))
+    }
+}
+
+impl Handler<GetMempoolEntry> for MempoolActor {
+    type Result = Result<Option<MempoolEntryInfo>, StorageError>;
+
+    fn handle(&mut self, msg: GetMempoolEntry, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(entry) = self.entries.get(&msg.txid) else {
+            return Ok(None);
+        };
+        let ancestors = self.ancestors(&msg.txid);
+        let descendants = self.descendants(&msg.txid);
+        Ok(Some(MempoolEntryInfo {
+            txid: msg.txid.to_string(),
+            vsize: entry.vsize,
+            weight: entry.tx.weight().to_wu(),
+            fee: entry.fee,
+            fee_rate: entry.fee_rate,
+            time: entry.time,
+            priority: entry.priority,
+            depends: entry.parents.iter().map(|txid| txid.to_string()).collect(),
+            spent_by: entry.children.iter().map(|txid| txid.to_string()).collect(),
+            ancestor_count: ancestors.len() as u64 + 1,
+            ancestor_size: self.total_vsize_of(&ancestors) + entry.vsize,
+            descendant_count: descendants.len() as u64 + 1,
+            descendant_size: self.total_vsize_of(&descendants) + entry.vsize,
+        }))
+    }
+}
+
+impl Handler<GetMempoolAncestors> for MempoolActor {
+    type Result = Result<Option<Vec<bitcoin::Txid>>, StorageError>;
+
+    fn handle(&mut self, msg: GetMempoolAncestors, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.entries.contains_key(&msg.txid).then(|| self.ancestors(&msg.txid)))
+    }
+}
+
+impl Handler<GetMempoolDescendants> for MempoolActor {
+    type Result = Result<Option<Vec<bitcoin::Txid>>, StorageError>;
+
+    fn handle(&mut self, msg: GetMempoolDescendants, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.entries.contains_key(&msg.txid).then(|| self.descendants(&msg.txid)))
+    }
+}
+
+impl Handler<GetMempoolPackage> for MempoolActor {
+    type Result = MessageResult<GetMempoolPackage>;
+
+    fn handle(&mut self, msg: GetMempoolPackage, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.package(msg.txids))
+    }
+}
+
+impl Handler<GetBlockTemplateTransactions> for MempoolActor {
+    type Result = Result<Vec<TemplateTransaction>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockTemplateTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let candidates: Vec<(&bitcoin::Txid, &MempoolEntry)> = self.by_fee_rate
+            .iter()
+            .rev()
+            .map(|key| (&key.txid, &self.entries[&key.txid]))
+            .collect();
+
+        // Repeat passes until nothing fits, so children follow their parents
+        // TODO: Select by ancestor package fee rate
+        let mut included = HashSet::new();
+        let mut selected = Vec::new();
+        let mut weight = 0;
+        loop {
+            let before = selected.len();
+            for (txid, entry) in &candidates {
+                if included.contains(*txid) {
+                    continue;
+                }
+                let tx_weight = entry.tx.weight().to_wu();
+                if weight + tx_weight > msg.max_weight {
+                    continue;
+                }
+                if !entry.parents.iter().all(|parent| included.contains(parent)) {
+                    continue;
+                }
+                included.insert(**txid);
+                weight += tx_weight;
+                selected.push(TemplateTransaction {
+                    tx: entry.tx.clone(),
+                    fee: entry.fee,
+                    weight: tx_weight,
+                    priority: entry.priority,
+                });
+            }
+            if selected.len() == before {
+                break;
+            }
+        }
+        Ok(selected)
     }
 }
 
@@ -55,8 +1210,37 @@ impl Handler<GetMempoolTxids> for MempoolActor {
     type Result = Result<Vec<bitcoin::Txid>, StorageError>;
 
     fn handle(&mut self, _msg: GetMempoolTxids, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO: Return actual mempool transaction IDs
-        Ok(vec![])
+        Ok(self.entries.keys().copied().collect())
+    }
+}
+
+impl Handler<GetMempoolSnapshot> for MempoolActor {
+    type Result = Result<MempoolSnapshot, StorageError>;
+
+    fn handle(&mut self, _msg: GetMempoolSnapshot, _ctx: &mut Self::Context) -> Self::Result {
+        // Deltas are only published from this actor, so the sequence matches the entries exactly
+        Ok(MempoolSnapshot {
+            sequence: self.deltas.current_sequence(),
+            entries: self.entries
+                .iter()
+                .map(|(txid, entry)| MempoolSnapshotEntry {
+                    txid: txid.to_string(),
+                    fee: entry.fee,
+                    vsize: entry.vsize,
+                    fee_rate: entry.fee_rate,
+                    time: entry.time,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl Handler<EstimateSmartFee> for MempoolActor {
+    type Result = MessageResult<EstimateSmartFee>;
+
+    fn handle(&mut self, msg: EstimateSmartFee, _ctx: &mut Self::Context) -> Self::Result {
+        let entries = self.entries.values().map(|entry| (entry.fee_rate, entry.vsize));
+        MessageResult(estimate_fee_rate(entries, msg.conf_target))
     }
 }
 
@@ -64,14 +1248,367 @@ impl Handler<GetMempoolInfo> for MempoolActor {
     type Result = Result<MempoolInfo, StorageError>;
 
     fn handle(&mut self, _msg: GetMempoolInfo, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO: Return actual mempool information
+        // TODO: Track memory usage and dynamic minimum fee
         Ok(MempoolInfo {
-            size: 0,
-            bytes: 0,
-            usage: 0,
-            max_mempool: 300_000_000,
-            mempool_min_fee: 0.00001000,
-            min_relay_tx_fee: 0.00001000,
+            size: self.entries.len() as u64,
+            bytes: self.total_vsize,
+            usage: self.total_vsize,
+            max_mempool: self.max_vsize,
+            mempool_min_fee: MIN_RELAY_FEE_RATE,
+            min_relay_tx_fee: MIN_RELAY_FEE_RATE,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_feed_sequences() {
+        let feed = MempoolDeltaFeed::new(16);
+        let mut receiver = feed.subscribe();
+
+        let first = feed.publish(MempoolDelta::Added {
+            txid: "aa".to_string(),
+            fee_rate: FeeRate::from_sat_per_kvb(2500),
+        });
+        let second = feed.publish(MempoolDelta::Removed {
+            txid: "aa".to_string(),
+            reason: RemovalReason::Block,
+        });
+
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert_eq!(feed.current_sequence(), 2);
+        assert_eq!(receiver.try_recv().unwrap().sequence, 1);
+        assert_eq!(receiver.try_recv().unwrap().sequence, 2);
+    }
+
+    #[test]
+    fn test_saved_mempool_round_trip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join(MEMPOOL_FILE);
+        assert!(load_mempool(&path).unwrap().is_none());
+
+        let entries = vec![MempoolExportEntry {
+            hex: "0200000000000000000000".to_string(),
+            fee: Satoshi(1000),
+            fee_rate: FeeRate::from_sat_per_kvb(5000),
+            time: 1_700_000_000,
+            priority: false,
+        }];
+        save_mempool(&path, entries.clone()).unwrap();
+        assert_eq!(load_mempool(&path).unwrap(), Some(entries));
+        assert!(!path.with_extension("partial").exists());
+
+        std::fs::write(&path, br#"{"version":99,"saved_at":0,"entries":[]}"#).unwrap();
+        assert!(matches!(load_mempool(&path), Err(SnapshotError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn test_estimate_fee_rate() {
+        // An almost empty mempool clears in one block at the relay floor
+        let rate = FeeRate::from_sat_per_kvb;
+        assert_eq!(estimate_fee_rate(vec![(rate(20_000), 200)].into_iter(), 1), MIN_RELAY_FEE_RATE);
+
+        // 1.5 blocks of backlog: the next block needs to beat the 5 sat/vB tier, the one after does not
+        let backlog = vec![(rate(5000), 600_000), (rate(30_000), 600_000), (rate(500), 300_000)];
+        assert_eq!(estimate_fee_rate(backlog.clone().into_iter(), 1), rate(5000));
+        assert_eq!(estimate_fee_rate(backlog.clone().into_iter(), 2), MIN_RELAY_FEE_RATE);
+        assert_eq!(estimate_fee_rate(backlog.into_iter(), 0), rate(5000));
+    }
+
+    #[test]
+    fn test_cpfp_estimate() {
+        let parent: bitcoin::Txid = bitcoin::hashes::Hash::from_byte_array([1; 32]);
+        let mut package = MempoolPackage::default();
+        package.push(parent, Satoshi(200), 200);
+        let target = FeeRate::from_sat_per_kvb(10_000);
+
+        // 310 vB at 10 sat/vB is 3100 sat, of which the parent pays 200
+        let estimate = cpfp_estimate(&package, target, DEFAULT_CPFP_CHILD_VSIZE);
+        assert!(estimate.bump_needed);
+        assert_eq!(estimate.child_fee, Satoshi(2900));
+        assert_eq!(estimate.child_fee_rate, FeeRate::from_fee(Satoshi(2900), 110));
+        assert_eq!(estimate.package_fee_rate, FeeRate::from_sat_per_kvb(1000));
+
+        // A package already above the target still needs the child to relay
+        package.push(bitcoin::hashes::Hash::from_byte_array([2; 32]), Satoshi(10_000), 100);
+        let estimate = cpfp_estimate(&package, target, 110);
+        assert!(!estimate.bump_needed);
+        assert_eq!(estimate.child_fee, Satoshi(110));
+    }
+
+    fn spend(parent: bitcoin::Txid, outputs: usize) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint { txid: parent, vout: 0 },
+                script_sig: bitcoin::ScriptBuf::from(vec![0x51]),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(10_000),
+                script_pubkey: bitcoin::ScriptBuf::from(vec![0x51]),
+            }; outputs],
+        }
+    }
+
+    fn entry(tx: bitcoin::Transaction, sat_per_vb: u64, priority: bool) -> MempoolEntry {
+        let fee = Satoshi(sat_per_vb * tx.vsize() as u64);
+        MempoolEntry::new(tx, fee, FeeRate::from_sat_per_kvb(sat_per_vb * 1000), 0, priority)
+    }
+
+    #[actix_rt::test]
+    async fn test_eviction_follows_the_fee_index() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let storage_actor = super::super::storage::StorageActor::new(&config).start();
+
+        let txs: Vec<bitcoin::Transaction> = (0u8..4)
+            .map(|i| spend(bitcoin::hashes::Hash::from_byte_array([i + 1; 32]), 1))
+            .collect();
+        let vsize = txs[0].vsize() as u64;
+        config.mempool.max_mempool = Some(3 * vsize);
+        let mut mempool = MempoolActor::new(
+            &config,
+            storage_actor,
+            MempoolDeltaFeed::new(16),
+            Vec::new(),
+            EventManager::with_publishers(Vec::new()),
+        );
+
+        // The miner lane entry pays nothing but outranks every relayed one
+        mempool.insert_entry(txs[0].txid(), entry(txs[0].clone(), 0, true));
+        mempool.insert_entry(txs[1].txid(), entry(txs[1].clone(), 2, false));
+        mempool.insert_entry(txs[2].txid(), entry(txs[2].clone(), 5, false));
+        let child = spend(txs[1].txid(), 1);
+        mempool.insert_entry(child.txid(), entry(child.clone(), 50, false));
+        assert_eq!(mempool.total_vsize, 3 * vsize + child.vsize() as u64);
+        let order: Vec<_> = mempool.by_fee_rate.iter().map(|key| key.txid).collect();
+        assert_eq!(order, vec![txs[1].txid(), txs[2].txid(), child.txid(), txs[0].txid()]);
+
+        // Over the limit: the lowest fee rate entry goes, taking its child along
+        assert_eq!(mempool.trim_to_size(), 2);
+        assert!(!mempool.entries.contains_key(&child.txid()));
+        assert_eq!(mempool.total_vsize, 2 * vsize);
+
+        mempool.insert_entry(txs[3].txid(), entry(txs[3].clone(), 1, false));
+        mempool.insert_entry(txs[3].txid(), entry(txs[3].clone(), 3, false));
+        assert_eq!(mempool.entries.len(), 3);
+        assert_eq!(mempool.trim_to_size(), 0);
+        assert_eq!(mempool.by_fee_rate.first().map(|key| key.txid), Some(txs[3].txid()));
+        assert_eq!(mempool.with_descendants(txs[3].txid()), vec![txs[3].txid()]);
+    }
+
+    #[actix_rt::test]
+    async fn test_chain_links_and_limits() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.mempool.limit_ancestor_count = Some(3);
+        config.mempool.limit_descendant_count = Some(3);
+        let storage_actor = super::super::storage::StorageActor::new(&config).start();
+        let mut mempool = MempoolActor::new(
+            &config,
+            storage_actor,
+            MempoolDeltaFeed::new(16),
+            Vec::new(),
+            EventManager::with_publishers(Vec::new()),
+        );
+
+        let root = spend(bitcoin::hashes::Hash::from_byte_array([1; 32]), 2);
+        let child = spend(root.txid(), 1);
+        let grandchild = spend(child.txid(), 1);
+        // Children may be inserted before their parents, as an import can do
+        mempool.insert_entry(child.txid(), entry(child.clone(), 1, false));
+        mempool.insert_entry(root.txid(), entry(root.clone(), 1, false));
+        mempool.insert_entry(grandchild.txid(), entry(grandchild.clone(), 1, false));
+
+        let mut ancestors = mempool.ancestors(&grandchild.txid());
+        ancestors.sort();
+        let mut expected = vec![root.txid(), child.txid()];
+        expected.sort();
+        assert_eq!(ancestors, expected);
+        assert_eq!(mempool.descendants(&root.txid()).len(), 2);
+        assert_eq!(mempool.with_descendants(child.txid()), vec![child.txid(), grandchild.txid()]);
+
+        // The package a child of the grandchild pays for is the whole chain, counted once
+        let package = mempool.package(vec![grandchild.txid(), grandchild.txid(), bitcoin::hashes::Hash::all_zeros()]);
+        assert_eq!(package.txids.len(), 3);
+        assert_eq!(package.txids[0], grandchild.txid());
+        assert_eq!(package.vsize, root.vsize() as u64 + child.vsize() as u64 + grandchild.vsize() as u64);
+        assert_eq!(package.fee_rate(), FeeRate::from_sat_per_kvb(1000));
+
+        // A fourth generation exceeds the ancestor limit
+        let too_deep = spend(grandchild.txid(), 1);
+        assert_eq!(
+            mempool.check_chain_limits(&too_deep),
+            Err(PolicyError::TooLongMempoolChain("too many unconfirmed ancestors [limit: 3]".to_string()))
+        );
+        // A second child of the root has only one ancestor, but gives the root a fourth descendant
+        let mut sibling = spend(root.txid(), 1);
+        sibling.input[0].previous_output.vout = 1;
+        assert_eq!(
+            mempool.check_chain_limits(&sibling),
+            Err(PolicyError::TooLongMempoolChain(format!(
+                "too many descendants for tx {} [limit: 3]", root.txid()
+            )))
+        );
+
+        mempool.remove_entry(&child.txid());
+        assert!(mempool.ancestors(&grandchild.txid()).is_empty());
+        assert!(mempool.descendants(&root.txid()).is_empty());
+        assert!(mempool.check_chain_limits(&sibling).is_ok());
+    }
+
+    fn test_mempool(config: &Config) -> MempoolActor {
+        let storage_actor = super::super::storage::StorageActor::new(config).start();
+        MempoolActor::new(
+            config,
+            storage_actor,
+            MempoolDeltaFeed::new(16),
+            Vec::new(),
+            EventManager::with_publishers(Vec::new()),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn test_replacement_evicts_descendants() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let mut mempool = test_mempool(&config);
+        let mut deltas = mempool.deltas.subscribe();
+        let mut ctx = Context::new();
+
+        let parent = spend(bitcoin::hashes::Hash::from_byte_array([1; 32]), 1);
+        let child = spend(parent.txid(), 1);
+        mempool.insert_entry(parent.txid(), entry(parent.clone(), 2, false));
+        mempool.insert_entry(child.txid(), entry(child.clone(), 2, false));
+
+        // Same input as the parent, paying more than parent and child together
+        let mut replacement = parent.clone();
+        replacement.output[0].value = bitcoin::Amount::from_sat(9_000);
+        let fee = Satoshi(20 * replacement.vsize() as u64);
+        let fee_rate = FeeRate::from_fee(fee, replacement.vsize() as u64);
+        mempool.accept(replacement.clone(), fee, fee_rate, false, &mut ctx).unwrap();
+
+        assert!(!mempool.entries.contains_key(&parent.txid()));
+        assert!(!mempool.entries.contains_key(&child.txid()));
+        assert_eq!(mempool.entries.len(), 1);
+        assert_eq!(mempool.total_vsize, replacement.vsize() as u64);
+        let replaced: Vec<String> = std::iter::from_fn(|| deltas.try_recv().ok())
+            .filter_map(|delta| match delta.delta {
+                MempoolDelta::Replaced { replaced_txid, .. } => Some(replaced_txid),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(replaced, vec![parent.txid().to_string(), child.txid().to_string()]);
+    }
+
+    #[actix_rt::test]
+    async fn test_replacement_pays_the_incremental_relay_fee() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let mut mempool = test_mempool(&config);
+        let mut ctx = Context::new();
+
+        let original = spend(bitcoin::hashes::Hash::from_byte_array([1; 32]), 2);
+        let original_entry = entry(original.clone(), 2, false);
+        let original_fee = original_entry.fee;
+        mempool.insert_entry(original.txid(), original_entry);
+
+        // Smaller, so the same fee is a higher fee rate, but it does not pay for its own relay
+        let mut replacement = original.clone();
+        replacement.output.pop();
+        let vsize = replacement.vsize() as u64;
+        assert_eq!(
+            mempool.accept(replacement.clone(), original_fee, FeeRate::from_fee(original_fee, vsize), false, &mut ctx),
+            Err(PolicyError::ReplacementRejected { conflicts: 1 })
+        );
+        assert!(mempool.entries.contains_key(&original.txid()));
+
+        let fee = original_fee + INCREMENTAL_RELAY_FEE_RATE.fee_for(vsize);
+        mempool.accept(replacement.clone(), fee, FeeRate::from_fee(fee, vsize), false, &mut ctx).unwrap();
+        assert!(!mempool.entries.contains_key(&original.txid()));
+        assert!(mempool.entries.contains_key(&replacement.txid()));
+    }
+
+    #[actix_rt::test]
+    async fn test_expiry_removes_descendants() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let mut mempool = test_mempool(&config);
+        let now = mempool.expiry_secs + 10;
+
+        let parent = spend(bitcoin::hashes::Hash::from_byte_array([1; 32]), 1);
+        let child = spend(parent.txid(), 1);
+        let grandchild = spend(child.txid(), 1);
+        let unrelated = spend(bitcoin::hashes::Hash::from_byte_array([2; 32]), 1);
+        mempool.insert_entry(parent.txid(), entry(parent.clone(), 1, false));
+        for tx in [&child, &grandchild, &unrelated] {
+            let mut fresh = entry(tx.clone(), 1, false);
+            fresh.time = now;
+            mempool.insert_entry(tx.txid(), fresh);
+        }
+
+        assert_eq!(mempool.expire(now), 3);
+        assert_eq!(mempool.entries.keys().collect::<Vec<_>>(), vec![&unrelated.txid()]);
+        assert_eq!(mempool.expire(now), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_block_conflict_evicts_descendants() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let mut mempool = test_mempool(&config);
+        let mut ctx = Context::new();
+
+        let parent = spend(bitcoin::hashes::Hash::from_byte_array([1; 32]), 1);
+        let child = spend(parent.txid(), 1);
+        let unrelated = spend(bitcoin::hashes::Hash::from_byte_array([2; 32]), 1);
+        for tx in [&parent, &child, &unrelated] {
+            mempool.insert_entry(tx.txid(), entry(tx.clone(), 1, false));
+        }
+
+        // The block spends the parent's input in another transaction
+        let mut conflict = parent.clone();
+        conflict.output[0].value = bitcoin::Amount::from_sat(5_000);
+        let mut block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        block.txdata.push(conflict);
+        let removed = <MempoolActor as Handler<RemoveBlockTransactions>>::handle(
+            &mut mempool, RemoveBlockTransactions { block }, &mut ctx,
+        ).unwrap();
+
+        assert_eq!(removed, 2);
+        assert!(!mempool.entries.contains_key(&parent.txid()));
+        assert!(!mempool.entries.contains_key(&child.txid()));
+        assert!(mempool.entries.contains_key(&unrelated.txid()));
+        assert!(mempool.spent_by.keys().all(|outpoint| outpoint.txid != parent.txid()));
+    }
+
+    #[test]
+    fn test_delta_serialization() {
+        let delta = SequencedMempoolDelta {
+            sequence: 7,
+            delta: MempoolDelta::Replaced {
+                replaced_txid: "aa".to_string(),
+                replacement_txid: "bb".to_string(),
+            },
+        };
+
+        let json = serde_json::to_value(&delta).unwrap();
+        assert_eq!(json["sequence"], 7);
+        assert_eq!(json["type"], "replaced");
+        assert_eq!(json["replaced_txid"], "aa");
+        assert_eq!(json["replacement_txid"], "bb");
+    }
+}
@@ -1,11 +1,24 @@
 use actix::prelude::*;
-use bitcoin::{Block, Transaction, BlockHash, Txid};
+use bitcoin::{Block, Transaction, TxOut, BlockHash, Txid};
 use serde::{Deserialize, Serialize};
 
+use crate::amount::{FeeRate, Satoshi};
+
+pub mod analytics;
 pub mod storage;
 pub mod network;
 pub mod mempool;
+pub mod watch;
 pub mod chain;
+pub mod index;
+#[cfg(feature = "miner")]
+pub mod stratum;
+#[cfg(feature = "wallet")]
+pub mod wallet;
+pub mod connection;
+pub mod instrument;
+#[cfg(feature = "zmq")]
+pub mod zmq;
 
 // Storage Actor Messages
 #[derive(Message)]
@@ -14,6 +27,42 @@ pub struct StoreBlock {
     pub block: Block,
 }
 
+/// Hand a block to the chain actor and learn what became of it
+#[cfg(feature = "miner")]
+#[derive(Message)]
+#[rtype(result = "Result<BlockSubmission, crate::error::StorageError>")]
+pub struct SubmitBlock {
+    pub block: Block,
+}
+
+/// What became of a submitted block, in `submitblock`'s terms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockSubmission {
+    /// Connected to the active chain, directly or through a reorg
+    Accepted,
+    /// Already stored, on the active chain or a side chain
+    Duplicate,
+    /// Invalidated before
+    DuplicateInvalid,
+    /// Valid so far, but stored on a side chain with less work
+    Inconclusive,
+    /// Refused for the BIP 22 reason given
+    Rejected(String),
+}
+
+impl BlockSubmission {
+    /// `submitblock`'s result: nothing for an accepted block, otherwise the reason
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            BlockSubmission::Accepted => None,
+            BlockSubmission::Duplicate => Some("duplicate".to_string()),
+            BlockSubmission::DuplicateInvalid => Some("duplicate-invalid".to_string()),
+            BlockSubmission::Inconclusive => Some("inconclusive".to_string()),
+            BlockSubmission::Rejected(reason) => Some(reason.clone()),
+        }
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<Option<Block>, crate::error::StorageError>")]
 pub struct GetBlock {
@@ -39,6 +88,16 @@ pub struct NewPeer {
     pub peer_id: String,
     pub address: String,
     pub user_agent: Option<String>,
+    pub inbound: bool,
+}
+
+/// Account for a message received from a peer before it is processed
+#[derive(Message)]
+#[rtype(result = "Result<crate::ratelimit::RateLimitVerdict, crate::error::NetworkError>")]
+pub struct PeerMessageReceived {
+    pub peer_id: String,
+    pub command: String,
+    pub bytes: usize,
 }
 
 #[derive(Message)]
@@ -48,20 +107,139 @@ pub struct DisconnectPeer {
     pub reason: String,
 }
 
+/// Outcome of the version/verack exchange with a connected peer
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::NetworkError>")]
+pub struct PeerHandshakeCompleted {
+    pub peer_id: String,
+    pub success: bool,
+    pub version: Option<u32>,
+    pub user_agent: Option<String>,
+    /// Service bits from the peer's `version` message
+    pub services: Option<u64>,
+    pub detail: Option<String>,
+}
+
+/// Disconnect a peer and refuse its address until the ban expires
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::NetworkError>")]
+pub struct BanPeer {
+    pub peer_id: String,
+    pub reason: String,
+    pub duration_secs: u64,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<Vec<PeerInfo>, crate::error::NetworkError>")]
 pub struct GetPeers;
 
+/// Append a peer lifecycle event to the persistent timeline
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct RecordPeerEvent {
+    pub peer_id: String,
+    pub address: Option<String>,
+    pub kind: PeerEventKind,
+}
+
+/// Append a numbered event to the replay journal
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct JournalEvent {
+    pub event: crate::events::BitcoinEvent,
+}
+
+/// Journaled events numbered after `since_seq`, oldest first; a `limit` of
+/// zero only reads the journal's bounds
+#[derive(Message)]
+#[rtype(result = "Result<EventJournalPage, crate::error::StorageError>")]
+pub struct GetEventJournal {
+    pub since_seq: u64,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventJournalPage {
+    /// Oldest sequence still journaled; a consumer behind it has lost events to retention
+    pub first_seq: Option<u64>,
+    pub last_seq: Option<u64>,
+    pub events: Vec<crate::events::BitcoinEvent>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<PeerTimelineEvent>, crate::error::StorageError>")]
+pub struct GetPeerTimeline {
+    pub after_seq: Option<u64>,
+    pub peer_id: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerEventKind {
+    Connected { inbound: bool },
+    /// Connection refused before any messages were exchanged
+    Rejected { inbound: bool, reason: String },
+    Handshake {
+        success: bool,
+        version: Option<u32>,
+        user_agent: Option<String>,
+        #[serde(default)]
+        services: Option<u64>,
+        detail: Option<String>,
+    },
+    Disconnected { reason: String },
+    Banned { reason: String, until: chrono::DateTime<chrono::Utc> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerTimelineEvent {
+    pub seq: u64,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub peer_id: String,
+    pub address: Option<String>,
+    #[serde(flatten)]
+    pub kind: PeerEventKind,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub id: String,
     pub address: String,
     pub user_agent: Option<String>,
+    pub inbound: bool,
     pub connected_at: chrono::DateTime<chrono::Utc>,
     pub bytes_sent: u64,
     pub bytes_received: u64,
 }
 
+/// Cached answer of a DNS seed, fresh or not
+#[derive(Message)]
+#[rtype(result = "Result<Option<crate::seeds::DnsSeedRecord>, crate::error::StorageError>")]
+pub struct GetDnsSeedRecord {
+    pub seed: String,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct StoreDnsSeedRecord {
+    pub seed: String,
+    pub record: crate::seeds::DnsSeedRecord,
+}
+
+/// Version details of every peer that completed its handshake
+#[derive(Message)]
+#[rtype(result = "Vec<crate::peerstats::PeerVersion>")]
+pub struct GetConnectedPeerVersions;
+
+/// Network conditions reported in `getnetworkinfo` warnings: the IBD
+/// greylist and any partition heuristics that currently fire
+#[derive(Message)]
+#[rtype(result = "Vec<String>")]
+pub struct GetNetworkWarnings;
+
 #[derive(Message)]
 #[rtype(result = "Result<(), crate::error::NetworkError>")]
 pub struct BroadcastTransaction {
@@ -74,11 +252,603 @@ pub struct BroadcastBlock {
     pub block: Block,
 }
 
+/// Ask peers for a block this node does not store. `None` when every peer
+/// asked answered `notfound` or none delivered it in time.
+#[derive(Message)]
+#[rtype(result = "Result<Option<Block>, crate::error::NetworkError>")]
+pub struct FetchBlock {
+    pub hash: BlockHash,
+    /// Its height on the header chain, to tell which pruned peers keep it
+    pub height: u64,
+}
+
+/// A `block` message from a peer
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BlockFromPeer {
+    pub peer_id: String,
+    pub block: Block,
+}
+
+/// Blocks a peer answered `notfound` for
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BlocksNotFound {
+    pub peer_id: String,
+    pub hashes: Vec<BlockHash>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Option<ChainTip>, crate::error::StorageError>")]
+pub struct GetChainTip;
+
+/// Persist a block extending the active chain and apply its UTXO changes
+#[derive(Message)]
+#[rtype(result = "Result<UtxoDelta, crate::error::StorageError>")]
+pub struct ConnectBlock {
+    pub block: Block,
+    pub height: u64,
+}
+
+/// Per-block statistics for the most recent `count` blocks of the active chain
+#[derive(Message)]
+#[rtype(result = "Result<crate::analytics::BlockAnalytics, crate::error::StorageError>")]
+pub struct GetBlockAnalytics {
+    pub count: u64,
+}
+
+/// Daily aggregates for the `days` most recent UTC days up to the tip, oldest first
+#[derive(Message)]
+#[rtype(result = "Result<Vec<crate::analytics::DailyStats>, crate::error::StorageError>")]
+pub struct GetDailyAnalytics {
+    pub days: u32,
+}
+
+/// Block fee rate percentiles and mempool minimum fee samples from `from`
+/// to `to` (Unix times), oldest first
+#[derive(Message)]
+#[rtype(result = "Result<crate::analytics::FeeRateHistory, crate::error::StorageError>")]
+pub struct GetFeeRateHistory {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Persist a mempool fee sample, pruning fee history past its retention
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct RecordMempoolFee {
+    pub sample: crate::analytics::MempoolFeeSample,
+}
+
+/// Scan the UTXO set at the current tip, off the storage actor's thread
+#[derive(Message)]
+#[rtype(result = "Result<Option<crate::analytics::UtxoDistribution>, crate::error::StorageError>")]
+pub struct ComputeUtxoDistribution;
+
+/// Most recently computed UTXO distribution, if a scan has finished
+#[derive(Message)]
+#[rtype(result = "Option<crate::analytics::UtxoDistribution>")]
+pub struct GetUtxoDistribution;
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<UtxoDelta>, crate::error::StorageError>")]
+pub struct GetUtxoDeltas {
+    pub since_height: u64,
+    pub limit: usize,
+}
+
+/// Merkle inclusion proof for a confirmed transaction.
+///
+/// `block_hash` may be given to prove inclusion in a specific block without the transaction index.
+#[derive(Message)]
+#[rtype(result = "Result<Option<TxProof>, crate::error::StorageError>")]
+pub struct GetTxProof {
+    pub txid: Txid,
+    pub block_hash: Option<BlockHash>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<TxProofVerification, crate::error::StorageError>")]
+pub struct VerifyTxProof {
+    pub merkle_block: bitcoin::MerkleBlock,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProof {
+    pub txid: String,
+    pub block_hash: String,
+    pub block_height: Option<u64>,
+    pub position: u32,
+    pub header: String,
+    pub merkle_root: String,
+    /// Sibling hashes from the leaf up to the root
+    pub branch: Vec<String>,
+    /// Serialized `CMerkleBlock`, as returned by `gettxoutproof`
+    pub merkle_block: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProofVerification {
+    pub valid: bool,
+    pub block_hash: String,
+    pub txids: Vec<String>,
+    pub block_known: bool,
+}
+
+/// Active chain headers from `start_height`, taking every `interval`th header
+#[derive(Message)]
+#[rtype(result = "Result<HeaderExport, crate::error::StorageError>")]
+pub struct GetHeaders {
+    pub start_height: u64,
+    pub count: usize,
+    pub interval: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderExport {
+    pub tip_height: Option<u64>,
+    pub tip_hash: Option<String>,
+    pub headers: Vec<HeaderRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderRecord {
+    pub height: u64,
+    pub hash: String,
+    pub header: String,
+    pub chain_work: String,
+}
+
+/// Place headers from a peer on the best header chain; the best header tip
+/// afterwards, `None` when the first header's parent is unknown
+#[derive(Message)]
+#[rtype(result = "Result<Option<ChainTip>, crate::error::StorageError>")]
+pub struct AcceptHeaders {
+    pub headers: Vec<bitcoin::block::Header>,
+}
+
+/// Tip of the best header chain, which may be ahead of the active chain
+#[derive(Message)]
+#[rtype(result = "Result<Option<ChainTip>, crate::error::StorageError>")]
+pub struct GetBestHeader;
+
+/// Height of a header on the active chain or the best header chain
+#[derive(Message)]
+#[rtype(result = "Result<Option<u64>, crate::error::StorageError>")]
+pub struct GetHeaderHeight {
+    pub hash: BlockHash,
+}
+
+/// Block locator over the best header chain for a `getheaders` request
+#[derive(Message)]
+#[rtype(result = "Result<Vec<BlockHash>, crate::error::StorageError>")]
+pub struct GetHeaderLocator;
+
+/// Store a block that does not extend the active tip and work out whether it triggers a reorg
+#[derive(Message)]
+#[rtype(result = "Result<SideBlockOutcome, crate::error::StorageError>")]
+pub struct StoreSideBlock {
+    pub block: Block,
+}
+
+/// Undo the active tip block, archiving it as stale, and return it
+#[derive(Message)]
+#[rtype(result = "Result<Block, crate::error::StorageError>")]
+pub struct DisconnectTip;
+
+/// One set of coins the node keeps, as `getchainstates` reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainstateInfo {
+    pub tip: ChainTip,
+    /// Memory RocksDB holds for recent changes to the chainstate's coins
+    pub coins_tip_cache_bytes: u64,
+    /// Tip of the node snapshot the chainstate was restored from
+    pub snapshot_blockhash: Option<BlockHash>,
+    pub validated: bool,
+}
+
+/// The chainstates, the one validating the blocks behind a restored node
+/// snapshot first and the active one last
+#[derive(Message)]
+#[rtype(result = "Result<Vec<ChainstateInfo>, crate::error::StorageError>")]
+pub struct GetChainstates;
+
+/// Approximate on-disk size of the database in bytes
+#[derive(Message)]
+#[rtype(result = "Result<u64, crate::error::StorageError>")]
+pub struct GetStorageSize;
+
+/// A read on behalf of an API replica
+#[derive(Message)]
+#[rtype(result = "Result<crate::storage::remote::StorageReadResponse, crate::error::StorageError>")]
+pub struct ReadStorage(pub crate::storage::remote::StorageRead);
+
+/// Raw copy of every column family for a node snapshot
+#[derive(Message)]
+#[rtype(result = "Result<crate::storage::ColumnFamilyDump, crate::error::StorageError>")]
+pub struct ExportStorage;
+
+/// Replace the database contents from a snapshot and return the restored tip
+#[derive(Message)]
+#[rtype(result = "Result<Option<ChainTip>, crate::error::StorageError>")]
+pub struct ImportStorage {
+    pub dump: crate::storage::ColumnFamilyDump,
+}
+
+/// Height of a block on the active chain
+#[derive(Message)]
+#[rtype(result = "Result<Option<u64>, crate::error::StorageError>")]
+pub struct GetBlockHeight {
+    pub hash: BlockHash,
+}
+
+/// A block's serialized bytes as stored, without decoding them
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<u8>>, crate::error::StorageError>")]
+pub struct GetRawBlock {
+    pub hash: BlockHash,
+}
+
+/// Where up to `count` active chain blocks from `hash` on are stored; `None`
+/// when `hash` is not on the active chain
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<BlockLocation>>, crate::error::StorageError>")]
+pub struct GetBlockLocations {
+    pub hash: BlockHash,
+    pub count: u64,
+}
+
+/// Where a block's serialized bytes are kept, for tools that read them
+/// directly. Blocks are values in the `blocks` column family under `key`;
+/// `file` and `offset` stay null until blocks are kept in flat files.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockLocation {
+    pub hash: String,
+    pub height: u64,
+    /// `rocksdb`
+    pub store: &'static str,
+    pub column_family: &'static str,
+    /// The RocksDB key, hex
+    pub key: String,
+    pub file: Option<String>,
+    pub offset: Option<u64>,
+    /// Serialized size in bytes
+    pub length: u64,
+}
+
+/// Hash of the active chain block at `height`
+#[derive(Message)]
+#[rtype(result = "Result<Option<BlockHash>, crate::error::StorageError>")]
+pub struct GetBlockHash {
+    pub height: u64,
+}
+
+/// Header of an active chain block with the context `getblockheader` reports
+#[derive(Message)]
+#[rtype(result = "Result<Option<BlockHeaderInfo>, crate::error::StorageError>")]
+pub struct GetBlockHeaderInfo {
+    pub hash: BlockHash,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockHeaderInfo {
+    pub header: bitcoin::block::Header,
+    pub height: u64,
+    pub confirmations: u64,
+    pub chain_work: bitcoin::Work,
+    /// Median time of the last 11 blocks up to and including this one
+    pub median_time: u32,
+    pub next_hash: Option<BlockHash>,
+}
+
+/// Unspent outputs by outpoint, `None` where spent or unknown
+#[derive(Message)]
+#[rtype(result = "Result<Vec<Option<crate::storage::UtxoEntry>>, crate::error::StorageError>")]
+pub struct GetUtxos {
+    pub outpoints: Vec<bitcoin::OutPoint>,
+}
+
+/// Coins paying to any of `script_pubkeys`, found with a full UTXO set scan
+#[derive(Message)]
+#[rtype(result = "Result<Vec<(bitcoin::OutPoint, crate::storage::UtxoEntry)>, crate::error::StorageError>")]
+pub struct ScanUtxos {
+    pub script_pubkeys: std::collections::HashSet<bitcoin::ScriptBuf>,
+}
+
+/// Totals and MuHash of the UTXO set from a full scan, checked against the
+/// commitment kept up to date at every block
+#[derive(Message)]
+#[rtype(result = "Result<UtxoSetInfo, crate::error::StorageError>")]
+pub struct GetUtxoSetInfo;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UtxoSetInfo {
+    pub height: u64,
+    pub best_block: String,
+    pub txouts: u64,
+    pub total_amount: u64,
+    /// MuHash of the scanned coins, in Core's byte order
+    pub muhash: String,
+    /// Whether the stored commitment agrees, `None` when a block connected during the scan
+    pub commitment_matches: Option<bool>,
+}
+
+/// Silent payment tweaks indexed for the block at `height`, `None` if it was never indexed
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<(Txid, bitcoin::secp256k1::PublicKey)>>, crate::error::StorageError>")]
+pub struct GetSilentPaymentTweaks {
+    pub height: u64,
+}
+
+/// Outputs paying the scan keys' owner in indexed blocks from `start_height` to `end_height`
+#[derive(Message)]
+#[rtype(result = "Result<Vec<SilentPaymentMatch>, crate::error::StorageError>")]
+pub struct ScanSilentPayments {
+    pub keys: crate::silentpayments::ScanKeys,
+    pub start_height: u64,
+    pub end_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SilentPaymentMatch {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub height: u64,
+    /// Added to the spend secret to get the output's private key
+    pub tweak: String,
+    pub unspent: bool,
+}
+
+/// Height and hash of the block the address index backfill waits for
+/// because it is not stored here
+#[derive(Message)]
+#[rtype(result = "Option<(u64, BlockHash)>")]
+pub struct GetBackfillMissingBlock;
+
+/// That block, fetched from peers, to index without storing it
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct IndexFetchedBlock {
+    pub height: u64,
+    pub block: Block,
+}
+
+/// Confirmed transactions paying or spending from a script, newest first,
+/// from below `before_height` when given
+#[derive(Message)]
+#[rtype(result = "Result<Vec<AddressTx>, crate::error::StorageError>")]
+pub struct GetAddressTxs {
+    pub script_pubkey: bitcoin::ScriptBuf,
+    pub before_height: Option<u64>,
+    pub limit: usize,
+}
+
+/// A transaction in a script's history, with what it paid to and spent from the script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressTx {
+    pub txid: String,
+    pub height: u64,
+    pub block_hash: Option<String>,
+    pub received: u64,
+    pub sent: u64,
+}
+
+/// Unspent outputs paying a script, newest first
+#[derive(Message)]
+#[rtype(result = "Result<Vec<AddressUtxo>, crate::error::StorageError>")]
+pub struct GetAddressUtxos {
+    pub script_pubkey: bitcoin::ScriptBuf,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub height: u64,
+    pub is_coinbase: bool,
+}
+
+/// Re-read the tip after storage was replaced underneath the chain actor
+#[derive(Message)]
+#[rtype(result = "Result<Option<ChainTip>, crate::error::StorageError>")]
+pub struct ReloadChainTip;
+
+/// Disconnect an active chain block with all its descendants and refuse them from now on.
+/// Returns the disconnected hashes, tip first, or `None` if the block is not on the active chain.
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<BlockHash>>, crate::error::StorageError>")]
+pub struct InvalidateBlock {
+    pub hash: BlockHash,
+}
+
+/// Wait until the active chain reaches `height`, or `timeout` runs out, and
+/// return the tip at that point
+#[derive(Message)]
+#[rtype(result = "Option<ChainTip>")]
+pub struct WaitForHeight {
+    pub height: u64,
+    /// Waits for as long as it takes when `None`
+    pub timeout: Option<std::time::Duration>,
+}
+
+/// Follow a reorg held back by `validation.max_reorg_depth`. Returns the
+/// record once connected, or `None` if no reorg to `hash` is being held.
+#[derive(Message)]
+#[rtype(result = "Result<Option<ReorgRecord>, crate::error::StorageError>")]
+pub struct AcceptReorg {
+    pub hash: BlockHash,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<ReorgRecord, crate::error::StorageError>")]
+pub struct RecordReorg {
+    pub old_tip: ChainTip,
+    pub new_tip: ChainTip,
+    pub fork_height: u64,
+    pub disconnected: Vec<BlockHash>,
+    pub connected: Vec<BlockHash>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<ReorgRecord>, crate::error::StorageError>")]
+pub struct GetReorgs {
+    pub limit: usize,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<StaleBlock>, crate::error::StorageError>")]
+pub struct GetStaleBlocks {
+    pub limit: usize,
+}
+
+pub enum SideBlockOutcome {
+    /// Already on the active chain or in the stale archive
+    AlreadyKnown,
+    /// Parent unknown, block not stored
+    Orphan,
+    /// Archived on a side chain with less work than the active chain
+    Stale { height: u64 },
+    /// The side chain now has more work: disconnect down to the fork, then connect these blocks
+    Reorg {
+        fork_height: u64,
+        connect: Vec<(Block, u64)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleReason {
+    /// Received on a fork that never became the active chain
+    SideChain,
+    /// Was on the active chain until a reorg disconnected it
+    ReorgedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StaleBlock {
+    pub hash: String,
+    pub height: u64,
+    pub prev_hash: String,
+    pub time: u32,
+    pub chain_work: String,
+    pub reason: StaleReason,
+    pub archived_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReorgRecord {
+    pub seq: u64,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub old_tip: String,
+    pub old_height: u64,
+    pub new_tip: String,
+    pub new_height: u64,
+    pub fork_height: u64,
+    /// Blocks removed from the active chain, tip first
+    pub disconnected: Vec<String>,
+    /// Blocks added to the active chain, lowest first
+    pub connected: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub hash: BlockHash,
+    pub height: u64,
+}
+
+/// UTXO set changes caused by connecting one block.
+///
+/// Outputs created and spent within the same block appear in both lists,
+/// so consumers should apply `created` before `spent`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UtxoDelta {
+    pub height: u64,
+    pub block_hash: String,
+    pub created: Vec<CreatedUtxo>,
+    pub spent: Vec<SpentUtxo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CreatedUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: u64,
+    pub script_pubkey: String,
+    pub is_coinbase: bool,
+}
+
+/// A spent outpoint with the coin it consumed, when it was known to the UTXO set
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpentUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value: Option<u64>,
+    pub script_pubkey: Option<String>,
+    pub height: Option<u64>,
+    #[serde(default)]
+    pub is_coinbase: Option<bool>,
+}
+
 // Chain Actor Messages
 #[derive(Message)]
 #[rtype(result = "Result<ChainInfo, crate::error::StorageError>")]
 pub struct GetChainInfo;
 
+/// Notification sent to chain subscribers after a block joins the active chain
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct BlockConnected {
+    pub block: Block,
+    pub height: u64,
+}
+
+// Watch Actor Messages
+#[derive(Message)]
+#[rtype(result = "Result<TxWatchStatus, crate::error::StorageError>")]
+pub struct WatchTransaction {
+    pub txid: Txid,
+    pub confirmations: u32,
+    pub callback_url: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<TxWatchStatus>, crate::error::StorageError>")]
+pub struct GetTxWatches;
+
+#[derive(Message)]
+#[rtype(result = "Result<AddressWatchStatus, crate::error::StorageError>")]
+pub struct WatchAddress {
+    pub address: String,
+    pub script_pubkey: bitcoin::ScriptBuf,
+    pub confirmations: u32,
+    pub callback_url: Option<String>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Vec<AddressWatchStatus>, crate::error::StorageError>")]
+pub struct GetAddressWatches;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressWatchStatus {
+    pub address: String,
+    pub script_pubkey: String,
+    pub confirmations: u32,
+    pub callback_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxWatchStatus {
+    pub txid: String,
+    pub target_confirmations: u32,
+    pub confirmations: u32,
+    pub block_hash: Option<String>,
+    pub block_height: Option<u64>,
+    pub callback_url: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainInfo {
     pub chain: String,
@@ -99,8 +869,105 @@ pub struct ChainInfo {
 #[rtype(result = "Result<(), crate::error::StorageError>")]
 pub struct AddToMempool {
     pub tx: Transaction,
-    pub fee: u64,
-    pub fee_rate: f64,
+    pub fee: Satoshi,
+    pub fee_rate: FeeRate,
+    /// Outputs the inputs spend, in input order, for script verification
+    pub spent_outputs: Vec<TxOut>,
+}
+
+/// Submit a local transaction, from sendrawtransaction, through the relay
+/// lane's checks, returning why it was refused rather than only logging it
+#[derive(Message)]
+#[rtype(result = "Result<Txid, crate::error::PolicyError>")]
+pub struct SubmitTransaction {
+    pub tx: Transaction,
+    pub fee: Satoshi,
+    pub spent_outputs: Vec<TxOut>,
+}
+
+/// Swap in the mempool settings of an updated config. Entries already
+/// accepted stay, apart from any a smaller `max_mempool` evicts.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReloadMempoolPolicy {
+    pub config: crate::config::Config,
+}
+
+/// Submit a transaction through the whitelisted miner lane
+#[derive(Message)]
+#[rtype(result = "Result<Txid, crate::error::PolicyError>")]
+pub struct SubmitMinerTransaction {
+    pub tx: Transaction,
+    /// Zero for transactions paid out of band
+    pub fee: Satoshi,
+    pub spent_outputs: Vec<TxOut>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<Option<mempool::MempoolEntryInfo>, crate::error::StorageError>")]
+pub struct GetMempoolEntry {
+    pub txid: Txid,
+}
+
+/// Every in-mempool ancestor of a transaction, `None` when it is not in the mempool
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<Txid>>, crate::error::StorageError>")]
+pub struct GetMempoolAncestors {
+    pub txid: Txid,
+}
+
+/// The given transactions that are in the mempool, with all their in-mempool
+/// ancestors, as the package a CPFP child would pay for
+#[derive(Message)]
+#[rtype(result = "mempool::MempoolPackage")]
+pub struct GetMempoolPackage {
+    pub txids: Vec<Txid>,
+}
+
+/// Every in-mempool descendant of a transaction, `None` when it is not in the mempool
+#[derive(Message)]
+#[rtype(result = "Result<Option<Vec<Txid>>, crate::error::StorageError>")]
+pub struct GetMempoolDescendants {
+    pub txid: Txid,
+}
+
+/// Transactions for a block template: priority lane first, then by fee rate, parents before children
+#[derive(Message)]
+#[rtype(result = "Result<Vec<mempool::TemplateTransaction>, crate::error::StorageError>")]
+pub struct GetBlockTemplateTransactions {
+    pub max_weight: u64,
+}
+
+/// Hold a transaction with unknown inputs until its parents arrive
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::StorageError>")]
+pub struct AddOrphanTransaction {
+    pub tx: Transaction,
+    pub fee: Satoshi,
+    pub fee_rate: FeeRate,
+    /// Outputs the inputs spend where already known, `None` for those of missing parents
+    pub spent_outputs: Vec<Option<TxOut>>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<usize, crate::error::StorageError>")]
+pub struct RemoveFromMempool {
+    pub txids: Vec<Txid>,
+    pub reason: mempool::RemovalReason,
+}
+
+/// Notification sent to mempool subscribers after a transaction is accepted
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct TransactionAccepted {
+    pub tx: Transaction,
+}
+
+/// Evict transactions confirmed by a newly connected block, along with any they conflict with
+#[derive(Message)]
+#[rtype(result = "Result<usize, crate::error::StorageError>")]
+pub struct RemoveBlockTransactions {
+    pub block: Block,
 }
 
 #[derive(Message)]
@@ -113,6 +980,40 @@ pub struct GetFromMempool {
 #[rtype(result = "Result<Vec<Txid>, crate::error::StorageError>")]
 pub struct GetMempoolTxids;
 
+#[derive(Message)]
+#[rtype(result = "Vec<mempool::MempoolExportEntry>")]
+pub struct ExportMempool;
+
+/// Replace the mempool contents, keeping each entry's original time and lane
+#[derive(Message)]
+#[rtype(result = "Result<usize, crate::error::StorageError>")]
+pub struct ImportMempool {
+    pub entries: Vec<mempool::MempoolExportEntry>,
+}
+
+/// Which of `outpoints` a mempool transaction already spends
+#[derive(Message)]
+#[rtype(result = "std::collections::HashSet<bitcoin::OutPoint>")]
+pub struct GetMempoolSpends {
+    pub outpoints: Vec<bitcoin::OutPoint>,
+}
+
+/// Drop transactions older than the mempool expiry, along with their descendants
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct ExpireMempool;
+
+/// Fee rate to confirm within `conf_target` blocks
+#[derive(Message)]
+#[rtype(result = "FeeRate")]
+pub struct EstimateSmartFee {
+    pub conf_target: u32,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<mempool::MempoolSnapshot, crate::error::StorageError>")]
+pub struct GetMempoolSnapshot;
+
 #[derive(Message)]
 #[rtype(result = "Result<MempoolInfo, crate::error::StorageError>")]
 pub struct GetMempoolInfo;
@@ -123,8 +1024,204 @@ pub struct MempoolInfo {
     pub bytes: u64,
     pub usage: u64,
     pub max_mempool: u64,
-    pub mempool_min_fee: f64,
-    pub min_relay_tx_fee: f64,
+    /// BTC/kvB, as in Core's `getmempoolinfo`
+    #[serde(with = "crate::amount::as_btc_per_kvb")]
+    pub mempool_min_fee: FeeRate,
+    #[serde(with = "crate::amount::as_btc_per_kvb")]
+    pub min_relay_tx_fee: FeeRate,
+}
+
+// Stratum Actor Messages
+/// A miner connected; lines queued on `sender` are written to its socket
+#[cfg(feature = "miner")]
+#[derive(Message)]
+#[rtype(result = "u64")]
+pub struct StratumSessionOpened {
+    pub peer: std::net::SocketAddr,
+    pub sender: tokio::sync::mpsc::UnboundedSender<String>,
+}
+
+/// One JSON-RPC line from a miner, answered with the response line if any
+#[cfg(feature = "miner")]
+#[derive(Message)]
+#[rtype(result = "Option<String>")]
+pub struct StratumRequest {
+    pub session_id: u64,
+    pub line: String,
+}
+
+#[cfg(feature = "miner")]
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct StratumSessionClosed {
+    pub session_id: u64,
+}
+
+#[cfg(feature = "miner")]
+#[derive(Message)]
+#[rtype(result = "Vec<StratumWorkerStats>")]
+pub struct GetStratumWorkers;
+
+#[cfg(feature = "miner")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StratumWorkerStats {
+    pub worker: String,
+    pub shares_accepted: u64,
+    pub shares_rejected: u64,
+    pub blocks_found: u64,
+    pub best_difficulty: f64,
+    pub last_share_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Wallet Actor Messages
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::WalletError>")]
+pub struct CreateWallet {
+    pub name: String,
+    pub account_key: String,
+    pub avoid_reuse: bool,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Vec<String>")]
+pub struct ListWallets;
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::WalletError>")]
+pub struct SetWalletFlag {
+    pub wallet: String,
+    pub flag: String,
+    pub value: bool,
+}
+
+/// Set or, with `None`, clear the bookkeeping note of a transaction
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::WalletError>")]
+pub struct SetTxNote {
+    pub wallet: String,
+    pub txid: Txid,
+    pub note: Option<String>,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<Vec<crate::wallet::HistoryRow>, crate::error::WalletError>")]
+pub struct GetWalletHistory {
+    pub wallet: String,
+}
+
+/// Next receive or change address of a wallet
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<String, crate::error::WalletError>")]
+pub struct GetNewAddress {
+    pub wallet: String,
+    pub change: bool,
+    pub kind: Option<crate::wallet::AddressKind>,
+    pub label: Option<String>,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<Vec<WalletUnspent>, crate::error::WalletError>")]
+pub struct ListUnspent {
+    pub wallet: String,
+    pub min_conf: u32,
+    pub max_conf: u32,
+}
+
+/// Select coins for `outputs` and return the unsigned PSBT
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<crate::wallet::FundedPsbt, crate::error::WalletError>")]
+pub struct FundPsbt {
+    pub wallet: String,
+    pub outputs: Vec<bitcoin::TxOut>,
+    pub lock_time: bitcoin::absolute::LockTime,
+    pub control: crate::wallet::CoinControl,
+}
+
+/// Open a signing proposal for a multisig PSBT
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<crate::multisig::ProposalStatus, crate::error::MultisigError>")]
+pub struct CreateProposal {
+    pub psbt: String,
+    pub description: Option<String>,
+}
+
+/// Merge a cosigner's partially signed copy into a proposal
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<crate::multisig::ProposalStatus, crate::error::MultisigError>")]
+pub struct SubmitProposalSignatures {
+    pub proposal_id: String,
+    pub psbt: String,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<crate::multisig::ProposalStatus, crate::error::MultisigError>")]
+pub struct GetProposal {
+    pub proposal_id: String,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<Vec<crate::multisig::ProposalStatus>, crate::error::MultisigError>")]
+pub struct ListProposals;
+
+/// Keep an output away from wallet coin selection until `duration_secs` pass
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<i64, crate::error::WalletError>")]
+pub struct LeaseOutput {
+    pub id: String,
+    pub outpoint: bitcoin::OutPoint,
+    pub duration_secs: u64,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Result<(), crate::error::WalletError>")]
+pub struct ReleaseOutput {
+    pub id: String,
+    pub outpoint: bitcoin::OutPoint,
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Message)]
+#[rtype(result = "Vec<crate::wallet::Lease>")]
+pub struct ListLeases;
+
+/// Finish in-flight work, release resources and stop; answered once the actor
+/// has drained its mailbox up to this message
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
+#[cfg(feature = "wallet")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletUnspent {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    pub label: Option<String>,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: String,
+    #[serde(with = "crate::amount::as_btc")]
+    pub amount: Satoshi,
+    pub confirmations: u32,
+    pub spendable: bool,
+    /// Paid to one of the wallet's own change addresses
+    pub change: bool,
+    /// Whether the coin is dirty; only reported when the wallet has `avoid_reuse` set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reused: Option<bool>,
 }
 
 #[cfg(test)]
@@ -140,6 +1237,7 @@ mod tests {
             connected_at: chrono::Utc::now(),
             bytes_sent: 1024,
             bytes_received: 2048,
+            inbound: false,
         };
 
         let json = serde_json::to_string(&peer_info).unwrap();
@@ -178,6 +1276,33 @@ mod tests {
         assert_eq!(chain_info.difficulty, deserialized.difficulty);
     }
 
+    #[test]
+    fn test_utxo_delta_serialization() {
+        let delta = UtxoDelta {
+            height: 101,
+            block_hash: "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206".to_string(),
+            created: vec![CreatedUtxo {
+                txid: "aa".repeat(32),
+                vout: 0,
+                value: 5_000_000_000,
+                script_pubkey: "51".to_string(),
+                is_coinbase: true,
+            }],
+            spent: vec![SpentUtxo {
+                txid: "bb".repeat(32),
+                vout: 1,
+                value: None,
+                script_pubkey: None,
+                height: None,
+                is_coinbase: None,
+            }],
+        };
+
+        let json = serde_json::to_string(&delta).unwrap();
+        let deserialized: UtxoDelta = serde_json::from_str(&json).unwrap();
+        assert_eq!(delta, deserialized);
+    }
+
     #[test]
     fn test_mempool_info_serialization() {
         let mempool_info = MempoolInfo {
@@ -185,11 +1310,12 @@ mod tests {
             bytes: 25000,
             usage: 25000,
             max_mempool: 300000000,
-            mempool_min_fee: 0.00001000,
-            min_relay_tx_fee: 0.00001000,
+            mempool_min_fee: FeeRate::from_sat_per_kvb(1000),
+            min_relay_tx_fee: FeeRate::from_sat_per_kvb(1000),
         };
 
         let json = serde_json::to_string(&mempool_info).unwrap();
+        assert!(json.contains(r#""mempool_min_fee":1e-5"#));
         let deserialized: MempoolInfo = serde_json::from_str(&json).unwrap();
 
         assert_eq!(mempool_info.size, deserialized.size);
@@ -1,28 +1,529 @@
 use actix::prelude::*;
-use tracing::{info, error};
+use tracing::{info, debug, warn, error};
 
-use crate::config::Config;
+use bitcoin::hashes::Hash;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::message_blockdata::Inventory;
+use bitcoin::p2p::{Magic, ServiceFlags};
+use bitcoin::{Block, BlockHash};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+use crate::cache::BoundedCache;
+use crate::config::{Config, PeerRateLimitConfig, PeerRoute, ProtocolStrictness, ProxyConfig};
+use crate::events::{BitcoinEventType, EventManager};
+use crate::ibd::IbdGreylist;
+use crate::partition::{PartitionMonitor, PartitionSignal};
+use crate::peerstats::{PeerBreakdown, PeerVersion};
+use crate::seeds::DnsSeedRecord;
+use crate::ratelimit::{MessageClass, PeerRateLimiter, RateLimitVerdict};
 use crate::error::NetworkError;
-use super::{NewPeer, DisconnectPeer, GetPeers, PeerInfo, BroadcastTransaction, BroadcastBlock};
+use crate::network::protocol::PeerProtocol;
+use crate::network::NetworkConstants;
+use super::instrument::InstrumentedAddr;
+use super::{NewPeer, DisconnectPeer, GetPeers, PeerInfo, BroadcastTransaction, BroadcastBlock, PeerMessageReceived};
+use super::{BanPeer, PeerEventKind, PeerHandshakeCompleted, RecordPeerEvent};
+use super::{BlockConnected, GetBlockHeaderInfo, GetChainTip, GetConnectedPeerVersions, GetNetworkWarnings};
+use super::{GetDnsSeedRecord, Shutdown, StoreDnsSeedRecord};
+use super::{BlockFromPeer, BlocksNotFound, FetchBlock};
+use super::connection::{local_version, run_connection, ConnectionContext, ConnectionStats, PeerConnection};
+
+/// How long a block fetch waits for peers to deliver before giving up
+const BLOCK_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Blocks below their tip that peers signalling only NODE_NETWORK_LIMITED
+/// still serve (BIP 159)
+const NODE_NETWORK_LIMITED_MIN_BLOCKS: u64 = 288;
+
+/// A block requested from peers on behalf of API clients and the index manager
+struct BlockFetch {
+    id: u64,
+    height: u64,
+    /// Peers asked so far, the one still expected to answer last
+    tried: Vec<String>,
+    waiters: Vec<oneshot::Sender<Option<Block>>>,
+}
+
+/// A configured peer that may be dialed, with the proxy to reach it through
+struct OutboundCandidate {
+    address: String,
+    proxy: Option<ProxyConfig>,
+}
+
+impl OutboundCandidate {
+    /// `None` when the address's network is not reachable under the proxy settings
+    fn route(config: &Config, address: String) -> Option<Self> {
+        let proxy = match config.route_for(&address) {
+            PeerRoute::Direct => None,
+            PeerRoute::Proxy(proxy) => Some(proxy.clone()),
+            PeerRoute::Unreachable => {
+                info!("Skipping peer {}: network not reachable", address);
+                return None;
+            }
+        };
+        Some(Self { address, proxy })
+    }
+}
+
+/// Bootstrap sources queried once when the actor starts
+struct SeedDiscovery {
+    dns_seeds: &'static [&'static str],
+    /// Tried only when every DNS seed comes back empty
+    fixed_seeds: Vec<String>,
+    port: u16,
+    ttl_secs: u64,
+    /// Routes the discovered addresses
+    config: Config,
+}
+
+impl SeedDiscovery {
+    async fn run(self, storage_actor: Addr<super::storage::StorageActor>) -> Vec<OutboundCandidate> {
+        let mut lookups = tokio::task::JoinSet::new();
+        for seed in self.dns_seeds {
+            lookups.spawn(seed_addresses(storage_actor.clone(), seed, self.port, self.ttl_secs));
+        }
+        let mut addresses = Vec::new();
+        while let Some(result) = lookups.join_next().await {
+            addresses.extend(result.unwrap_or_default());
+        }
+
+        if addresses.is_empty() && !self.fixed_seeds.is_empty() {
+            info!("No DNS seed answered, falling back to {} fixed seeds", self.fixed_seeds.len());
+            addresses = self.fixed_seeds;
+        }
+        addresses.sort();
+        addresses.dedup();
+        addresses.into_iter()
+            .filter_map(|address| OutboundCandidate::route(&self.config, address))
+            .collect()
+    }
+}
+
+/// A seed's fresh cached answer, else a new lookup, else its stale cached answer
+async fn seed_addresses(
+    storage_actor: Addr<super::storage::StorageActor>,
+    seed: &'static str,
+    port: u16,
+    ttl_secs: u64,
+) -> Vec<String> {
+    let cached = match storage_actor.send_traced(GetDnsSeedRecord { seed: seed.to_string() }).await {
+        Ok(Ok(record)) => record,
+        Ok(Err(e)) => {
+            warn!("Failed to read cached DNS seed {}: {}", seed, e);
+            None
+        }
+        Err(e) => {
+            warn!("Storage actor unavailable: {}", e);
+            None
+        }
+    };
+    if let Some(record) = cached.as_ref().filter(|record| record.is_fresh(crate::clock::now_utc())) {
+        debug!("Using cached answer of DNS seed {} ({} addresses)", seed, record.addresses.len());
+        return record.addresses.clone();
+    }
+
+    match crate::seeds::resolve_seed(seed, port).await {
+        Ok(addresses) if !addresses.is_empty() => {
+            debug!("DNS seed {} returned {} addresses", seed, addresses.len());
+            storage_actor.do_send_traced(StoreDnsSeedRecord {
+                seed: seed.to_string(),
+                record: DnsSeedRecord {
+                    addresses: addresses.clone(),
+                    resolved_at: crate::clock::now_utc(),
+                    ttl_secs,
+                },
+            });
+            addresses
+        }
+        result => {
+            if let Err(e) = result {
+                warn!("DNS seed {} failed: {}", seed, e);
+            }
+            let stale = cached.map(|record| record.addresses).unwrap_or_default();
+            if !stale.is_empty() {
+                info!("Reusing {} expired addresses from DNS seed {}", stale.len(), seed);
+            }
+            stale
+        }
+    }
+}
 
 pub struct NetworkActor {
-    _storage_actor: Addr<super::storage::StorageActor>,
+    storage_actor: Addr<super::storage::StorageActor>,
+    outbound_candidates: Vec<OutboundCandidate>,
+    seed_discovery: Option<SeedDiscovery>,
+    recent_inventory: BoundedCache<[u8; 32], ()>,
+    rate_limits: PeerRateLimitConfig,
+    protocol_strictness: ProtocolStrictness,
+    peer_limiters: HashMap<String, PeerRateLimiter>,
+    peer_addresses: HashMap<String, String>,
+    /// What each peer announced during a successful handshake
+    peer_versions: HashMap<String, PeerVersion>,
+    /// Composition last exported to Prometheus, so departed labels can be zeroed
+    published_composition: PeerBreakdown,
+    /// Banned hosts and when their ban expires
+    banned: HashMap<String, chrono::DateTime<chrono::Utc>>,
+    listen: bool,
+    nat_traversal: bool,
+    target_outbound: usize,
+    max_inbound: usize,
+    /// `None` when `[network_config.partition]` is disabled
+    partition: Option<PartitionMonitor>,
+    partition_check_interval: Duration,
+    /// Signals from the latest partition check
+    partition_signals: Vec<PartitionSignal>,
+    ibd_greylist: IbdGreylist,
+    event_manager: EventManager,
+    network_name: &'static str,
+    node_id: String,
+    /// Running connections by peer id
+    connections: HashMap<String, PeerConnection>,
+    next_peer_id: u64,
+    magic: Magic,
+    services: u64,
+    listen_address: String,
+    handshake_timeout: Duration,
+    /// Sent in our `version` so connections to ourselves are spotted
+    local_nonce: u64,
+    best_height: u64,
+    network: bitcoin::Network,
+    /// Blocks being fetched from peers, by hash
+    block_fetches: HashMap<BlockHash, BlockFetch>,
+    next_fetch_id: u64,
+}
+
+/// A connection accepted by the P2P listener, not yet admitted
+#[derive(Message)]
+#[rtype(result = "()")]
+struct InboundConnection {
+    stream: TcpStream,
+    address: SocketAddr,
 }
 
 impl NetworkActor {
-    pub fn new(config: &Config, storage_actor: Addr<super::storage::StorageActor>) -> Self {
+    pub fn new(config: &Config, storage_actor: Addr<super::storage::StorageActor>, event_manager: EventManager) -> Self {
+        let outbound_candidates = config.all_custom_peers()
+            .into_iter()
+            .filter_map(|address| OutboundCandidate::route(config, address))
+            .collect();
+        let seed_discovery = (config.should_use_dns_seeds() || config.should_use_fixed_seeds()).then(|| SeedDiscovery {
+            dns_seeds: if config.should_use_dns_seeds() { crate::seeds::dns_seeds(&config.network) } else { &[] },
+            fixed_seeds: if config.should_use_fixed_seeds() { crate::seeds::fixed_seeds(&config.network) } else { Vec::new() },
+            port: config.network.default_port(),
+            ttl_secs: config.network_config.dns_seed_ttl_secs,
+            config: config.clone(),
+        });
+
+        let partition_config = &config.network_config.partition;
+        let partition = partition_config.enabled.then(|| {
+            PartitionMonitor::new(partition_config, config.network.to_bitcoin_network(), Instant::now())
+        });
+
+        let constants = NetworkConstants::for_network(&config.network);
+
         info!("Network actor initialized");
         Self {
-            _storage_actor: storage_actor,
+            storage_actor,
+            outbound_candidates,
+            seed_discovery,
+            recent_inventory: BoundedCache::new("recent_inventory", config.caches.inventory_max_bytes),
+            rate_limits: config.network_config.peer_rate_limits.clone(),
+            protocol_strictness: config.network_config.protocol_strictness,
+            peer_limiters: HashMap::new(),
+            peer_addresses: HashMap::new(),
+            peer_versions: HashMap::new(),
+            published_composition: PeerBreakdown::default(),
+            banned: HashMap::new(),
+            listen: config.should_listen(),
+            nat_traversal: config.should_use_nat_traversal(),
+            target_outbound: config.target_outbound_peers(),
+            max_inbound: config.max_inbound_peers(),
+            partition,
+            partition_check_interval: Duration::from_secs(partition_config.check_interval_secs.max(1)),
+            partition_signals: Vec::new(),
+            ibd_greylist: IbdGreylist::new(&config.network_config.ibd_greylist, config.network.to_bitcoin_network()),
+            event_manager,
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+            connections: HashMap::new(),
+            next_peer_id: 0,
+            magic: Magic::from_bytes(constants.magic_bytes),
+            services: constants.services,
+            listen_address: format!("0.0.0.0:{}", config.effective_listen_port()),
+            handshake_timeout: Duration::from_secs(config.network_config.connection_timeout_secs.max(1)),
+            local_nonce: uuid::Uuid::new_v4().as_u64_pair().0,
+            best_height: 0,
+            network: config.network.to_bitcoin_network(),
+            block_fetches: HashMap::new(),
+            next_fetch_id: 0,
         }
     }
 }
 
+impl NetworkActor {
+    /// Record an inventory hash, returning false if it was already seen recently
+    fn mark_inventory_seen(&mut self, hash: [u8; 32]) -> bool {
+        if self.recent_inventory.contains(&hash) {
+            return false;
+        }
+        self.recent_inventory.insert(hash, (), 0);
+        crate::metrics::record_cache_stats(&self.recent_inventory.stats());
+        true
+    }
+
+    /// Append to the persistent peer timeline without waiting for the write
+    fn record_peer_event(&self, peer_id: &str, address: Option<String>, kind: PeerEventKind) {
+        self.storage_actor.do_send_traced(RecordPeerEvent {
+            peer_id: peer_id.to_string(),
+            address,
+            kind,
+        });
+    }
+
+    /// Re-run the partition heuristics, publishing `PartitionSuspected` when they start firing
+    fn check_partition(&mut self) {
+        let Some(monitor) = self.partition.as_mut() else {
+            return;
+        };
+        let signals = monitor.check(Instant::now(), self.peer_addresses.values().map(|address| peer_host(address)));
+
+        if self.partition_signals.is_empty() && !signals.is_empty() {
+            for signal in &signals {
+                warn!("Possible partition: {}", signal.describe());
+            }
+            let event = BitcoinEventType::PartitionSuspected {
+                signals: signals.clone(),
+                peer_count: self.peer_addresses.len(),
+            };
+            let event_manager = self.event_manager.clone();
+            let network = self.network_name;
+            let node_id = self.node_id.clone();
+            self.event_manager.spawn_delivery(async move {
+                if let Err(e) = event_manager.publish(event, network, &node_id).await {
+                    warn!("Failed to publish partition event: {}", e);
+                }
+            });
+        } else if !self.partition_signals.is_empty() && signals.is_empty() {
+            info!("Partition heuristics cleared");
+        }
+        self.partition_signals = signals;
+    }
+
+    /// Track the tip for our `version` start height and the IBD greylist
+    fn tip_changed(&mut self, height: u64, tip_time: u32) {
+        self.best_height = self.best_height.max(height);
+        if self.ibd_greylist.tip_changed(tip_time, crate::clock::now_utc().timestamp()) {
+            info!("Initial block download complete ({:.2}% verified), accepting inbound peers and transactions normally",
+                  self.ibd_greylist.progress().unwrap_or(1.0) * 100.0);
+        }
+    }
+
+    /// Load the stored tip so the first handshakes and the greylist start from it
+    fn load_tip(&self, ctx: &mut Context<Self>) {
+        let storage_actor = self.storage_actor.clone();
+        ctx.spawn(
+            async move {
+                let tip = storage_actor.send_traced(GetChainTip).await.ok()?.ok()??;
+                let info = storage_actor.send_traced(GetBlockHeaderInfo { hash: tip.hash }).await.ok()?.ok()??;
+                Some((tip.height, info.header.time))
+            }
+            .into_actor(self)
+            .map(|tip, actor, _ctx| match tip {
+                Some((height, tip_time)) => actor.tip_changed(height, tip_time),
+                None => warn!("Could not load the chain tip for the network actor"),
+            }),
+        );
+    }
+
+    /// Ask the next handshaken peer not yet asked for a block, full nodes
+    /// before pruned ones, which are skipped for blocks deeper than they
+    /// keep; false when none is left
+    fn request_block(&mut self, hash: BlockHash) -> bool {
+        let Some(fetch) = self.block_fetches.get_mut(&hash) else {
+            return false;
+        };
+        // Our tip stands in for the peers' own, which they do not report after the handshake
+        let within_limited_window = self.best_height.saturating_sub(fetch.height) < NODE_NETWORK_LIMITED_MIN_BLOCKS;
+        let mut candidates: Vec<(&String, bool)> = self.peer_versions.iter()
+            .filter(|(peer_id, _)| !fetch.tried.contains(peer_id))
+            .filter_map(|(peer_id, version)| {
+                let services = ServiceFlags::from(version.services.unwrap_or(0));
+                let full = services.has(ServiceFlags::NETWORK);
+                let limited = services.has(ServiceFlags::NETWORK_LIMITED);
+                (full || limited && within_limited_window).then_some((peer_id, full))
+            })
+            .collect();
+        candidates.sort_by_key(|(peer_id, full)| (!full, *peer_id));
+
+        let request = NetworkMessage::GetData(vec![Inventory::WitnessBlock(hash)]);
+        for (peer_id, _) in candidates {
+            if self.connections.get(peer_id).is_some_and(|connection| connection.send(request.clone())) {
+                debug!("Requesting block {} from peer {}", hash, peer_id);
+                fetch.tried.push(peer_id.clone());
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Move a fetch on from the peer it waits for when that peer cannot deliver
+    fn block_unavailable(&mut self, hash: BlockHash, peer_id: &str) {
+        let waiting_on_peer = self.block_fetches.get(&hash)
+            .and_then(|fetch| fetch.tried.last())
+            .is_some_and(|last| last == peer_id);
+        if waiting_on_peer && !self.request_block(hash) {
+            debug!("No peer left to fetch block {} from", hash);
+            self.finish_block_fetch(hash, None);
+        }
+    }
+
+    /// Hand a fetched block, or `None`, to everyone waiting for it
+    fn finish_block_fetch(&mut self, hash: BlockHash, block: Option<Block>) {
+        if let Some(fetch) = self.block_fetches.remove(&hash) {
+            for waiter in fetch.waiters {
+                // The API request may have gone away
+                let _ = waiter.send(block.clone());
+            }
+        }
+    }
+
+    fn refresh_peer_composition(&mut self) {
+        let composition = PeerBreakdown::from_peers(self.peer_versions.values()).by_family();
+        crate::metrics::record_peer_composition(&composition, &self.published_composition);
+        self.published_composition = composition;
+    }
+
+    /// Admission rules shared by dialed and accepted peers; registers the peer when it passes
+    fn admit_peer(&mut self, peer_id: &str, address: &str, inbound: bool) -> Result<(), NetworkError> {
+        let inbound_count = self.connections.values().filter(|connection| connection.inbound).count();
+        let rejection = if inbound && !self.listen {
+            Some("inbound connections are disabled")
+        } else if inbound && inbound_count >= self.max_inbound {
+            Some("inbound slots are full")
+        } else if inbound && self.ibd_greylist.inbound_limit().is_some_and(|limit| inbound_count >= limit) {
+            Some("inbound connections are limited during initial block download")
+        } else if self.is_banned(peer_host(address)) {
+            Some("address is banned")
+        } else {
+            None
+        };
+
+        if let Some(reason) = rejection {
+            warn!("Rejecting peer {} from {}: {}", peer_id, address, reason);
+            self.record_peer_event(peer_id, Some(address.to_string()), PeerEventKind::Rejected {
+                inbound,
+                reason: reason.to_string(),
+            });
+            return Err(NetworkError::ConnectionFailed {
+                peer: address.to_string(),
+                reason: reason.to_string(),
+            });
+        }
+
+        info!("New peer connected: {} from {}", peer_id, address);
+        crate::metrics::record_peer_connected();
+        self.record_peer_event(peer_id, Some(address.to_string()), PeerEventKind::Connected { inbound });
+        self.peer_limiters.insert(peer_id.to_string(), PeerRateLimiter::new(&self.rate_limits, Instant::now()));
+        self.peer_addresses.insert(peer_id.to_string(), address.to_string());
+        Ok(())
+    }
+
+    fn start_listener(&self, ctx: &mut Context<Self>) {
+        let listen_address = self.listen_address.clone();
+        let strictness = self.protocol_strictness;
+        let network_actor = ctx.address();
+        ctx.spawn(
+            async move {
+                let listener = match TcpListener::bind(&listen_address).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        error!("Failed to listen for peers on {}: {}", listen_address, e);
+                        return;
+                    }
+                };
+                info!("Listening for peers on {} ({} protocol handling)", listen_address, strictness.as_str());
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, address)) => network_actor.do_send(InboundConnection { stream, address }),
+                        Err(e) => {
+                            warn!("Failed to accept peer connection: {}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+            }
+            .into_actor(self),
+        );
+    }
+
+    fn is_banned(&mut self, host: &str) -> bool {
+        match self.banned.get(host) {
+            Some(until) if *until > crate::clock::now_utc() => true,
+            Some(_) => {
+                self.banned.remove(host);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+fn log_candidate(candidate: &OutboundCandidate) {
+    match &candidate.proxy {
+        Some(proxy) => info!("Outbound candidate {} via proxy {}", candidate.address, proxy.address),
+        None => info!("Outbound candidate {} (direct)", candidate.address),
+    }
+}
+
+/// Host part of a `host:port` peer address, used as the ban key
+fn peer_host(address: &str) -> &str {
+    address
+        .rsplit_once(':')
+        .map(|(host, _)| host.trim_start_matches('[').trim_end_matches(']'))
+        .unwrap_or(address)
+}
+
 impl Actor for NetworkActor {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("Network actor started");
+        self.load_tip(ctx);
+        if self.partition.is_some() {
+            ctx.run_interval(self.partition_check_interval, |actor, _ctx| actor.check_partition());
+        }
+        if self.listen {
+            // TODO: NAT port mapping
+            info!("Accepting up to {} inbound peers (NAT traversal: {}), targeting {} outbound",
+                  self.max_inbound, self.nat_traversal, self.target_outbound);
+            self.start_listener(ctx);
+        } else {
+            info!("Running outbound-only, targeting {} outbound peers", self.target_outbound);
+        }
+
+        // TODO: Dial candidates through their SOCKS5 proxy
+        for candidate in &self.outbound_candidates {
+            log_candidate(candidate);
+        }
+
+        if let Some(discovery) = self.seed_discovery.take() {
+            ctx.spawn(discovery.run(self.storage_actor.clone()).into_actor(self).map(|candidates, actor, _ctx| {
+                info!("Seed discovery found {} outbound candidates", candidates.len());
+                for candidate in candidates {
+                    if actor.outbound_candidates.iter().all(|known| known.address != candidate.address) {
+                        log_candidate(&candidate);
+                        actor.outbound_candidates.push(candidate);
+                    }
+                }
+            }));
+        }
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -30,13 +531,68 @@ impl Actor for NetworkActor {
     }
 }
 
+impl Handler<Shutdown> for NetworkActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        info!("Closing {} peer connections", self.connections.len());
+        for connection in self.connections.values() {
+            connection.close();
+        }
+        // Stopping drops the listener and connection futures spawned on this context
+        ctx.stop();
+    }
+}
+
 impl Handler<NewPeer> for NetworkActor {
     type Result = Result<(), NetworkError>;
 
     fn handle(&mut self, msg: NewPeer, _ctx: &mut Self::Context) -> Self::Result {
-        info!("New peer connected: {} from {}", msg.peer_id, msg.address);
-        // TODO: Implement peer connection logic
-        Ok(())
+        self.admit_peer(&msg.peer_id, &msg.address, msg.inbound)
+    }
+}
+
+impl Handler<InboundConnection> for NetworkActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: InboundConnection, ctx: &mut Self::Context) -> Self::Result {
+        let peer_id = self.next_peer_id.to_string();
+        self.next_peer_id += 1;
+        // Dropping the stream refuses the connection
+        if self.admit_peer(&peer_id, &msg.address.to_string(), true).is_err() {
+            return;
+        }
+
+        let version = local_version(
+            msg.address,
+            self.services,
+            self.local_nonce,
+            self.best_height as i32,
+            self.ibd_greylist.relay_transactions(),
+        );
+        let protocol = PeerProtocol::new(self.magic, &self.rate_limits, Instant::now())
+            .with_strictness(self.protocol_strictness)
+            .responding_with(version);
+        let (commands, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let stats = Arc::new(ConnectionStats::default());
+        self.connections.insert(peer_id.clone(), PeerConnection {
+            inbound: true,
+            connected_at: crate::clock::now_utc(),
+            stats: stats.clone(),
+            commands,
+        });
+
+        let context = ConnectionContext {
+            peer_id,
+            magic: self.magic,
+            protocol,
+            handshake_timeout: self.handshake_timeout,
+            stats,
+            commands: receiver,
+            network_actor: ctx.address(),
+            storage_actor: self.storage_actor.clone(),
+        };
+        ctx.spawn(run_connection(msg.stream, context).into_actor(self));
     }
 }
 
@@ -45,17 +601,164 @@ impl Handler<DisconnectPeer> for NetworkActor {
 
     fn handle(&mut self, msg: DisconnectPeer, _ctx: &mut Self::Context) -> Self::Result {
         info!("Peer disconnected: {} ({})", msg.peer_id, msg.reason);
-        // TODO: Implement peer disconnection logic
+        if let Some(connection) = self.connections.remove(&msg.peer_id) {
+            connection.close();
+        }
+        self.peer_limiters.remove(&msg.peer_id);
+        let address = self.peer_addresses.remove(&msg.peer_id);
+        if address.is_some() {
+            crate::metrics::record_peer_disconnected();
+        }
+        if self.peer_versions.remove(&msg.peer_id).is_some() {
+            self.refresh_peer_composition();
+        }
+        let fetches: Vec<BlockHash> = self.block_fetches.keys().copied().collect();
+        for hash in fetches {
+            self.block_unavailable(hash, &msg.peer_id);
+        }
+        self.record_peer_event(&msg.peer_id, address, PeerEventKind::Disconnected { reason: msg.reason });
+        Ok(())
+    }
+}
+
+impl Handler<PeerHandshakeCompleted> for NetworkActor {
+    type Result = Result<(), NetworkError>;
+
+    fn handle(&mut self, msg: PeerHandshakeCompleted, ctx: &mut Self::Context) -> Self::Result {
+        let address = self.peer_addresses
+            .get(&msg.peer_id)
+            .cloned()
+            .ok_or_else(|| NetworkError::PeerDisconnected { peer: msg.peer_id.clone() })?;
+
+        if msg.success {
+            self.peer_versions.insert(msg.peer_id.clone(), PeerVersion {
+                user_agent: msg.user_agent.clone(),
+                services: msg.services,
+                version: msg.version,
+            });
+            self.refresh_peer_composition();
+        }
+        self.record_peer_event(&msg.peer_id, Some(address), PeerEventKind::Handshake {
+            success: msg.success,
+            version: msg.version,
+            user_agent: msg.user_agent,
+            services: msg.services,
+            detail: msg.detail.clone(),
+        });
+
+        if !msg.success {
+            ctx.notify(DisconnectPeer {
+                peer_id: msg.peer_id,
+                reason: format!("handshake failed: {}", msg.detail.unwrap_or_default()),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Handler<BanPeer> for NetworkActor {
+    type Result = Result<(), NetworkError>;
+
+    fn handle(&mut self, msg: BanPeer, ctx: &mut Self::Context) -> Self::Result {
+        let address = self.peer_addresses
+            .get(&msg.peer_id)
+            .cloned()
+            .ok_or_else(|| NetworkError::PeerDisconnected { peer: msg.peer_id.clone() })?;
+
+        let until = crate::clock::now_utc() + chrono::Duration::seconds(msg.duration_secs as i64);
+        warn!("Banning peer {} ({}) until {}: {}", msg.peer_id, address, until, msg.reason);
+        self.banned.insert(peer_host(&address).to_string(), until);
+        self.record_peer_event(&msg.peer_id, Some(address), PeerEventKind::Banned {
+            reason: msg.reason.clone(),
+            until,
+        });
+
+        ctx.notify(DisconnectPeer {
+            peer_id: msg.peer_id,
+            reason: format!("banned: {}", msg.reason),
+        });
         Ok(())
     }
 }
 
+impl Handler<PeerMessageReceived> for NetworkActor {
+    type Result = Result<RateLimitVerdict, NetworkError>;
+
+    fn handle(&mut self, msg: PeerMessageReceived, ctx: &mut Self::Context) -> Self::Result {
+        let limiter = self.peer_limiters
+            .get_mut(&msg.peer_id)
+            .ok_or_else(|| NetworkError::PeerDisconnected { peer: msg.peer_id.clone() })?;
+
+        let verdict = limiter.check(&msg.command, msg.bytes, Instant::now());
+        match verdict {
+            RateLimitVerdict::Accept => {}
+            RateLimitVerdict::Throttle { misbehavior } => {
+                let class = MessageClass::from_command(&msg.command);
+                crate::metrics::record_peer_message_throttled(class.as_str());
+                debug!("Throttling {} from peer {} (misbehavior: {})", msg.command, msg.peer_id, misbehavior);
+            }
+            RateLimitVerdict::Disconnect { misbehavior } => {
+                crate::metrics::record_peer_rate_limit_disconnect();
+                warn!("Disconnecting peer {} for message flooding (misbehavior: {})", msg.peer_id, misbehavior);
+                ctx.notify(DisconnectPeer {
+                    peer_id: msg.peer_id,
+                    reason: "rate limit exceeded".to_string(),
+                });
+            }
+        }
+        Ok(verdict)
+    }
+}
+
 impl Handler<GetPeers> for NetworkActor {
     type Result = Result<Vec<PeerInfo>, NetworkError>;
 
     fn handle(&mut self, _msg: GetPeers, _ctx: &mut Self::Context) -> Self::Result {
-        // TODO: Return actual peer list
-        Ok(vec![])
+        let mut peers: Vec<PeerInfo> = self.connections.iter()
+            .filter_map(|(peer_id, connection)| {
+                let address = self.peer_addresses.get(peer_id)?;
+                Some(PeerInfo {
+                    id: peer_id.clone(),
+                    address: address.clone(),
+                    user_agent: self.peer_versions.get(peer_id).and_then(|version| version.user_agent.clone()),
+                    inbound: connection.inbound,
+                    connected_at: connection.connected_at,
+                    bytes_sent: connection.stats.bytes_sent.load(Ordering::Relaxed),
+                    bytes_received: connection.stats.bytes_received.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        peers.sort_by(|a, b| a.connected_at.cmp(&b.connected_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(peers)
+    }
+}
+
+impl Handler<BlockConnected> for NetworkActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlockConnected, _ctx: &mut Self::Context) -> Self::Result {
+        self.tip_changed(msg.height, msg.block.header.time);
+        if let Some(monitor) = self.partition.as_mut() {
+            monitor.tip_advanced(Instant::now(), msg.block.header.difficulty_float());
+        }
+    }
+}
+
+impl Handler<GetConnectedPeerVersions> for NetworkActor {
+    type Result = MessageResult<GetConnectedPeerVersions>;
+
+    fn handle(&mut self, _msg: GetConnectedPeerVersions, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.peer_versions.values().cloned().collect())
+    }
+}
+
+impl Handler<GetNetworkWarnings> for NetworkActor {
+    type Result = MessageResult<GetNetworkWarnings>;
+
+    fn handle(&mut self, _msg: GetNetworkWarnings, _ctx: &mut Self::Context) -> Self::Result {
+        let ibd = self.ibd_greylist.warning();
+        let partition = self.partition_signals.iter().map(|signal| signal.describe());
+        MessageResult(ibd.into_iter().chain(partition).collect())
     }
 }
 
@@ -63,8 +766,18 @@ impl Handler<BroadcastTransaction> for NetworkActor {
     type Result = Result<(), NetworkError>;
 
     fn handle(&mut self, msg: BroadcastTransaction, _ctx: &mut Self::Context) -> Self::Result {
-        info!("Broadcasting transaction: {}", msg.tx.txid());
-        // TODO: Implement transaction broadcasting
+        let txid = msg.tx.txid();
+        if !self.mark_inventory_seen(txid.to_byte_array()) {
+            debug!("Transaction {} already announced", txid);
+            return Ok(());
+        }
+        // Peers are sent the transaction itself, since getdata for
+        // announced inventory is not served yet
+        let peers = self.connections.iter()
+            .filter(|(peer_id, _)| self.peer_versions.contains_key(*peer_id))
+            .filter(|(_, connection)| connection.send(NetworkMessage::Tx(msg.tx.clone())))
+            .count();
+        info!("Broadcast transaction {} to {} peers", txid, peers);
         Ok(())
     }
 }
@@ -73,8 +786,182 @@ impl Handler<BroadcastBlock> for NetworkActor {
     type Result = Result<(), NetworkError>;
 
     fn handle(&mut self, msg: BroadcastBlock, _ctx: &mut Self::Context) -> Self::Result {
-        info!("Broadcasting block: {}", msg.block.block_hash());
+        let block_hash = msg.block.block_hash();
+        if !self.mark_inventory_seen(block_hash.to_byte_array()) {
+            debug!("Block {} already announced", block_hash);
+            return Ok(());
+        }
+        info!("Broadcasting block: {}", block_hash);
         // TODO: Implement block broadcasting
         Ok(())
     }
-}
\ No newline at end of file
+}
+impl Handler<FetchBlock> for NetworkActor {
+    type Result = ResponseFuture<Result<Option<Block>, NetworkError>>;
+
+    fn handle(&mut self, msg: FetchBlock, ctx: &mut Self::Context) -> Self::Result {
+        let hash = msg.hash;
+        let (sender, receiver) = oneshot::channel();
+        if let Some(fetch) = self.block_fetches.get_mut(&hash) {
+            fetch.waiters.push(sender);
+        } else {
+            let id = self.next_fetch_id;
+            self.next_fetch_id += 1;
+            self.block_fetches.insert(hash, BlockFetch { id, height: msg.height, tried: Vec::new(), waiters: vec![sender] });
+            if !self.request_block(hash) {
+                self.block_fetches.remove(&hash);
+                return Box::pin(async { Err(NetworkError::NoPeers) });
+            }
+            ctx.run_later(BLOCK_FETCH_TIMEOUT, move |actor, _ctx| {
+                if actor.block_fetches.get(&hash).is_some_and(|fetch| fetch.id == id) {
+                    debug!("Fetching block {} timed out", hash);
+                    actor.finish_block_fetch(hash, None);
+                }
+            });
+        }
+        Box::pin(async move { Ok(receiver.await.ok().flatten()) })
+    }
+}
+
+impl Handler<BlockFromPeer> for NetworkActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlockFromPeer, _ctx: &mut Self::Context) -> Self::Result {
+        let hash = msg.block.block_hash();
+        if !self.block_fetches.contains_key(&hash) {
+            debug!("Ignoring unrequested block {} from peer {}", hash, msg.peer_id);
+            return;
+        }
+        // The hash commits to the header; the checks tie the transactions to it
+        match super::chain::check_block(&msg.block, self.network) {
+            Ok(()) => self.finish_block_fetch(hash, Some(msg.block)),
+            Err(reason) => {
+                warn!("Peer {} sent block {} that fails {}", msg.peer_id, hash, reason);
+                self.block_unavailable(hash, &msg.peer_id);
+            }
+        }
+    }
+}
+
+impl Handler<BlocksNotFound> for NetworkActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlocksNotFound, _ctx: &mut Self::Context) -> Self::Result {
+        for hash in msg.hashes {
+            self.block_unavailable(hash, &msg.peer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::connection::PeerCommand;
+    use tokio::sync::mpsc;
+
+    /// A handshaken peer whose outgoing messages land in the returned receiver
+    fn test_peer(actor: &mut NetworkActor, peer_id: &str, services: ServiceFlags) -> mpsc::UnboundedReceiver<PeerCommand> {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        actor.connections.insert(peer_id.to_string(), PeerConnection {
+            inbound: false,
+            connected_at: crate::clock::now_utc(),
+            stats: Arc::new(ConnectionStats::default()),
+            commands,
+        });
+        actor.peer_versions.insert(peer_id.to_string(), PeerVersion {
+            user_agent: None,
+            services: Some(services.to_u64()),
+            version: Some(70016),
+        });
+        receiver
+    }
+
+    /// The block a peer was last sent a `getdata` for
+    fn requested(receiver: &mut mpsc::UnboundedReceiver<PeerCommand>) -> Option<BlockHash> {
+        match receiver.try_recv() {
+            Ok(PeerCommand::Send(NetworkMessage::GetData(items))) => match items[..] {
+                [Inventory::WitnessBlock(hash)] => Some(hash),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_block_fetch_moves_on_from_peers_without_the_block() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.network_config.listen = false;
+        config.network_config.enable_dns_seeds = false;
+        config.network_config.use_fixed_seeds = false;
+        let storage_actor = super::super::storage::StorageActor::new(&config).start();
+        let mut actor = NetworkActor::new(&config, storage_actor, EventManager::with_publishers(Vec::new()));
+        let mut pruned = test_peer(&mut actor, "peer-0", ServiceFlags::NETWORK_LIMITED);
+        let mut full = test_peer(&mut actor, "peer-1", ServiceFlags::NETWORK);
+        let addr = actor.start();
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let hash = genesis.block_hash();
+
+        // The full node is asked first, then the pruned one after a notfound
+        let fetch = actix_rt::spawn(addr.send(FetchBlock { hash, height: 0 }));
+        addr.send(GetPeers).await.unwrap().unwrap();
+        assert_eq!(requested(&mut full), Some(hash));
+        assert_eq!(requested(&mut pruned), None);
+        addr.send(BlocksNotFound { peer_id: "peer-1".to_string(), hashes: vec![hash] }).await.unwrap();
+        assert_eq!(requested(&mut pruned), Some(hash));
+        addr.send(BlockFromPeer { peer_id: "peer-0".to_string(), block: genesis.clone() }).await.unwrap();
+        assert_eq!(fetch.await.unwrap().unwrap().unwrap(), Some(genesis));
+
+        // Nobody has it
+        let fetch = actix_rt::spawn(addr.send(FetchBlock { hash, height: 0 }));
+        addr.send(GetPeers).await.unwrap().unwrap();
+        assert_eq!(requested(&mut full), Some(hash));
+        addr.send(BlocksNotFound { peer_id: "peer-1".to_string(), hashes: vec![hash] }).await.unwrap();
+        addr.send(BlocksNotFound { peer_id: "peer-0".to_string(), hashes: vec![hash] }).await.unwrap();
+        assert_eq!(requested(&mut pruned), Some(hash));
+        assert_eq!(fetch.await.unwrap().unwrap().unwrap(), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_block_fetch_skips_pruned_peers_for_deep_blocks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.network_config.listen = false;
+        config.network_config.enable_dns_seeds = false;
+        config.network_config.use_fixed_seeds = false;
+        let storage_actor = super::super::storage::StorageActor::new(&config).start();
+        let mut actor = NetworkActor::new(&config, storage_actor, EventManager::with_publishers(Vec::new()));
+        let mut pruned = test_peer(&mut actor, "peer-0", ServiceFlags::NETWORK_LIMITED | ServiceFlags::WITNESS);
+        let mut full = test_peer(&mut actor, "peer-1", ServiceFlags::NETWORK | ServiceFlags::WITNESS);
+        let _light = test_peer(&mut actor, "peer-2", ServiceFlags::WITNESS);
+        actor.best_height = 1000;
+        let addr = actor.start();
+        let hash = BlockHash::all_zeros();
+
+        // 288 blocks deep is past what a pruned peer keeps, and the full node has not got it
+        let fetch = actix_rt::spawn(addr.send(FetchBlock { hash, height: 712 }));
+        addr.send(GetPeers).await.unwrap().unwrap();
+        assert_eq!(requested(&mut full), Some(hash));
+        addr.send(BlocksNotFound { peer_id: "peer-1".to_string(), hashes: vec![hash] }).await.unwrap();
+        assert_eq!(fetch.await.unwrap().unwrap().unwrap(), None);
+        assert_eq!(requested(&mut pruned), None);
+
+        // One block shallower is still within its window
+        let fetch = actix_rt::spawn(addr.send(FetchBlock { hash, height: 713 }));
+        addr.send(GetPeers).await.unwrap().unwrap();
+        assert_eq!(requested(&mut full), Some(hash));
+        addr.send(BlocksNotFound { peer_id: "peer-1".to_string(), hashes: vec![hash] }).await.unwrap();
+        assert_eq!(requested(&mut pruned), Some(hash));
+        addr.send(BlocksNotFound { peer_id: "peer-0".to_string(), hashes: vec![hash] }).await.unwrap();
+        assert_eq!(fetch.await.unwrap().unwrap().unwrap(), None);
+    }
+
+    #[test]
+    fn test_peer_host() {
+        assert_eq!(peer_host("203.0.113.5:8333"), "203.0.113.5");
+        assert_eq!(peer_host("[2001:db8::1]:8333"), "2001:db8::1");
+        assert_eq!(peer_host("example.onion"), "example.onion");
+    }
+}
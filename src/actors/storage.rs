@@ -1,101 +1,3011 @@
 use actix::prelude::*;
-use tracing::{info, error};
+use tracing::{info, warn, error};
 use bitcoin::hashes::Hash;
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::{Amount, Block, MerkleBlock, OutPoint, ScriptBuf, TxMerkleNode, TxOut, Txid, Work};
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
-use crate::storage::Storage;
+use crate::maintenance::CompactionScheduler;
+use crate::muhash::{coin_bytes, MuHash3072};
+use crate::script::ScriptChecks;
+use crate::storage::keys::{AddressEntryKind, AddressKey, BlockKey, ChainStateKey, StorageKey, TxKey, UtxoKey};
+use crate::startup::{self, StartupStage};
+use crate::storage::{AddressEntry, HeaderEntry, Storage, TxLocation, UtxoCommitment, UtxoEntry, CF_BLOCKS, CF_UTXOS};
 use crate::error::{StorageError, StorageResult};
-use super::{StoreBlock, GetBlock, AddTransaction, GetTransaction};
+use super::{StoreBlock, GetBlock, AddTransaction, GetTransaction, GetChainTip, ConnectBlock, GetUtxoDeltas};
+use super::{ChainTip, UtxoDelta, CreatedUtxo, SpentUtxo};
+use super::{GetTxProof, VerifyTxProof, TxProof, TxProofVerification};
+use super::{GetHeaders, HeaderExport, HeaderRecord};
+use super::{RecordPeerEvent, GetPeerTimeline, PeerTimelineEvent};
+use super::{EventJournalPage, GetEventJournal, JournalEvent};
+use super::{GetBlockAnalytics, GetDailyAnalytics, GetFeeRateHistory, RecordMempoolFee, ComputeUtxoDistribution};
+use super::{StoreSideBlock, SideBlockOutcome, DisconnectTip, RecordReorg, GetReorgs, GetStaleBlocks};
+use super::{ReorgRecord, StaleBlock, StaleReason, GetBlockHeight, ScanUtxos, ExportStorage, ImportStorage, GetStorageSize, ReadStorage};
+use super::{ChainstateInfo, GetChainstates};
+use super::{AcceptHeaders, GetBestHeader, GetHeaderHeight, GetHeaderLocator};
+use super::{GetSilentPaymentTweaks, ScanSilentPayments, SilentPaymentMatch};
+use super::{AddressTx, AddressUtxo, GetAddressTxs, GetAddressUtxos, GetBackfillMissingBlock, IndexFetchedBlock};
+use super::{BlockHeaderInfo, GetBlockHash, GetBlockHeaderInfo, GetUtxos};
+use super::{BlockLocation, GetBlockLocations, GetRawBlock};
+use super::{GetDnsSeedRecord, GetUtxoSetInfo, Shutdown, StoreDnsSeedRecord, UtxoSetInfo};
+use crate::analytics::{day_index, BlockAnalytics, BlockFeeRates, BlockStats, DailyStats, FeeRateHistory, RollingStats, UtxoDistribution};
+
+/// Blocks one `GetBlockLocations` request can cover
+pub const MAX_BLOCK_LOCATIONS: u64 = 1000;
+
+/// Coins between startup progress reports while the UTXO set commitment is rebuilt
+const UTXO_SCAN_REPORT_INTERVAL: u64 = 100_000;
+
+/// Timeline pruning runs once per this many appended events
+const PEER_TIMELINE_PRUNE_INTERVAL: u64 = 256;
+
+/// Events read from the timeline per batch while filtering a query
+const PEER_TIMELINE_SCAN_BATCH: usize = 1000;
+
+/// Event journal pruning runs once per this many journaled events
+const EVENT_JOURNAL_PRUNE_INTERVAL: u64 = 256;
+
+/// Blocks between address index backfill progress reports
+const ADDRESS_BACKFILL_LOG_INTERVAL: u64 = 10_000;
+
+/// Blocks between background validation progress log lines
+const BACKGROUND_VALIDATION_LOG_INTERVAL: u64 = 10_000;
+
+/// Blocks a coinbase output must wait before it can be spent
+const COINBASE_MATURITY: u64 = 100;
+
+/// How far below the best header a run of headers from a peer may fork
+const HEADER_FORK_SEARCH_DEPTH: u64 = 144;
 
 pub struct StorageActor {
     storage: Storage,
+    network: bitcoin::Network,
+    next_peer_event_seq: u64,
+    peer_timeline_max_events: u64,
+    event_journal_max_events: u64,
+    /// How long fee rate history is kept, forever when `None`
+    fee_history_retention: Option<Duration>,
+    silent_payment_index: bool,
+    address_index: bool,
+    /// Next height and end of the address index backfill, while one is running
+    address_backfill: Option<(u64, u64)>,
+    /// Height and hash of the block the backfill stopped at because it is
+    /// not stored here, until the index manager fetches it from peers
+    backfill_missing_block: Option<(u64, bitcoin::BlockHash)>,
+    backfill_blocks_per_sec: u64,
+    /// Present while the blocks behind a restored node snapshot are validated
+    background: Option<BackgroundValidation>,
+    background_config: crate::config::StorageConfig,
+    background_blocks_per_sec: u64,
+    background_scheduled: bool,
+    /// Present when scheduled compaction is enabled
+    compaction: Option<CompactionScheduler>,
+    compaction_check_interval: Duration,
+    compacting: bool,
+    /// Present when scheduled backups are enabled
+    backups: Option<BackupSchedule>,
+    backing_up: bool,
+    script_checks: ScriptChecks,
+}
+
+/// Where and how often the database is backed up
+struct BackupSchedule {
+    path: PathBuf,
+    keep: usize,
+    interval: Duration,
+}
+
+/// The chainstate that validates the blocks behind a restored node snapshot.
+/// It replays the active chain from genesis into a UTXO set of its own,
+/// checking every script, until the snapshot's tip, where that set has to
+/// match the one the snapshot was restored with.
+struct BackgroundValidation {
+    storage: Storage,
+    /// The restored tip and the commitment to the UTXO set restored with it
+    base: ChainTip,
+    base_muhash: MuHash3072,
+    /// Last block replayed and the commitment to the UTXO set after it
+    tip: Option<ChainTip>,
+    muhash: MuHash3072,
+    /// Set once a block or the final UTXO set is rejected; nothing more is replayed
+    failed: bool,
+}
+
+impl BackgroundValidation {
+    /// Replay up to `limit` blocks of the active chain, returning whether
+    /// the snapshot's tip has been reached
+    fn replay(&mut self, active: &Storage, network: bitcoin::Network, limit: u64) -> StorageResult<bool> {
+        for _ in 0..limit {
+            let height = self.tip.map(|tip| tip.height + 1).unwrap_or(0);
+            if height > self.base.height {
+                break;
+            }
+            let missing = |what: &str| StorageError::Corruption {
+                component: format!("{} for height {}", what, height),
+            };
+            let entry = active.get_header_entry(height)?.ok_or_else(|| missing("header"))?;
+            let data = active.get_block(&BlockKey(entry.header.block_hash()))?.ok_or_else(|| missing("block data"))?;
+            let block: Block = bitcoin::consensus::deserialize(&data)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            self.connect(&block, height, network)?;
+        }
+        Ok(self.tip.is_some_and(|tip| tip.height >= self.base.height))
+    }
+
+    /// Fully validate a block against the replayed UTXO set and add it,
+    /// progress and commitment in the same write
+    fn connect(&mut self, block: &Block, height: u64, network: bitcoin::Network) -> StorageResult<()> {
+        let hash = block.block_hash();
+        let invalid = |reason: &str| StorageError::InvalidBlock {
+            hash: hash.to_string(),
+            reason: reason.to_string(),
+        };
+        match self.tip {
+            Some(tip) if block.header.prev_blockhash != tip.hash => return Err(invalid("bad-prevblk")),
+            Some(_) => super::chain::check_block(block, network).map_err(invalid)?,
+            None if hash != bitcoin::blockdata::constants::genesis_block(network).block_hash() => {
+                return Err(invalid("not the genesis block"));
+            }
+            None => {}
+        }
+        if height == self.base.height && hash != self.base.hash {
+            return Err(invalid("does not lead to the snapshot's tip"));
+        }
+
+        let flags = crate::script::block_flags(network, height, &hash);
+        let storage = &self.storage;
+        StorageActor::plan_block(block, height, network, Some(flags), |prevout| {
+            storage.get_utxo(&UtxoKey::new(prevout.txid, prevout.vout))?
+                .map(|data| UtxoEntry::decode(&data))
+                .transpose()
+        })?;
+
+        // Coins created and spent within the block never enter the set
+        let mut created = HashMap::new();
+        let mut spent = Vec::new();
+        if height > 0 {
+            for tx in &block.txdata {
+                let txid = tx.txid();
+                if !tx.is_coinbase() {
+                    for input in &tx.input {
+                        if created.remove(&input.previous_output).is_none() {
+                            spent.push(input.previous_output);
+                        }
+                    }
+                }
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if !output.script_pubkey.is_op_return() {
+                        created.insert(OutPoint { txid, vout: vout as u32 }, UtxoEntry {
+                            height,
+                            is_coinbase: tx.is_coinbase(),
+                            output: output.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let batch = self.storage.write_batch()?;
+        let mut muhash = self.muhash.clone();
+        for outpoint in &spent {
+            let key = UtxoKey::new(outpoint.txid, outpoint.vout);
+            // Checked by plan_block, and the batch has not touched it yet
+            let coin = self.storage.get_utxo(&key)?.map(|data| UtxoEntry::decode(&data)).transpose()?
+                .ok_or_else(|| invalid("bad-txns-inputs-missingorspent"))?;
+            muhash.remove(&coin_bytes(outpoint, coin.height, coin.is_coinbase, &coin.output));
+            batch.delete_utxo(&key)?;
+        }
+        for (outpoint, coin) in &created {
+            muhash.insert(&coin_bytes(outpoint, coin.height, coin.is_coinbase, &coin.output));
+            batch.store_utxo(&UtxoKey::new(outpoint.txid, outpoint.vout), &coin.encode())?;
+        }
+        let tip = ChainTip { hash, height };
+        batch.store_chain_state(&ChainStateKey::Tip, &encode_chain_tip(&tip))?;
+        batch.store_chain_state(&ChainStateKey::UtxoCommitment, &UtxoCommitment { block_hash: hash, muhash: muhash.clone() }.encode())?;
+        batch.commit()?;
+        self.tip = Some(tip);
+        self.muhash = muhash;
+        Ok(())
+    }
 }
 
 impl StorageActor {
     pub fn new(config: &Config) -> Self {
+        startup::enter(StartupStage::OpeningDatabase);
         let storage = Storage::new(&config.storage)
             .expect("Failed to initialize storage");
 
+        let next_peer_event_seq = match storage.last_peer_event_seq() {
+            Ok(last) => last.map(|seq| seq + 1).unwrap_or(0),
+            Err(e) => {
+                error!("Failed to read peer timeline: {}", e);
+                0
+            }
+        };
+
+        let compaction = if config.storage.compaction.enabled {
+            match CompactionScheduler::new(&config.storage.compaction) {
+                Ok(scheduler) => Some(scheduler),
+                Err(e) => {
+                    error!("Scheduled compaction disabled: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let network = config.network.to_bitcoin_network();
+        let mut script_checks = ScriptChecks::new(config).unwrap_or_else(|e| {
+            error!("Assume-valid ignored, verifying all scripts: {}", e);
+            ScriptChecks::all(network)
+        });
+        // A restarted node may already have connected past the assume-valid height
+        if let Some(height) = script_checks.assume_valid_height() {
+            match storage.get_header_entry(height) {
+                Ok(Some(entry)) => script_checks.observe(height, &entry.header.block_hash()),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read the header at the assume-valid height: {}", e),
+            }
+        }
+
+        let backups = config.storage.backup_enabled.then(|| BackupSchedule {
+            path: config.storage.backup_path(),
+            keep: config.storage.backup_keep,
+            interval: Duration::from_secs(config.storage.backup_interval_hours.max(1) * 3600),
+        });
+
         info!("Storage actor initialized");
 
-        Self { storage }
+        let mut actor = Self {
+            storage,
+            network,
+            next_peer_event_seq,
+            peer_timeline_max_events: config.storage.peer_timeline_max_events,
+            event_journal_max_events: config.storage.event_journal_max_events,
+            fee_history_retention: (config.analytics.fee_history_retention_days > 0)
+                .then(|| Duration::from_secs(config.analytics.fee_history_retention_days * 86_400)),
+            silent_payment_index: config.storage.silent_payment_index,
+            address_index: config.storage.address_index,
+            address_backfill: None,
+            backfill_missing_block: None,
+            backfill_blocks_per_sec: config.storage.index_backfill_blocks_per_sec.max(1),
+            background: None,
+            background_config: crate::config::StorageConfig {
+                rocks_db_path: config.storage.background_chainstate_path(),
+                ..config.storage.clone()
+            },
+            background_blocks_per_sec: config.storage.background_validation_blocks_per_sec.max(1),
+            background_scheduled: false,
+            compaction,
+            compaction_check_interval: Duration::from_secs(config.storage.compaction.check_interval_secs.max(1)),
+            compacting: false,
+            backups,
+            backing_up: false,
+            script_checks,
+        };
+        // Recovery and pruning are up to the full node behind a replica
+        if actor.storage.is_remote() {
+            return actor;
+        }
+        if let Err(e) = actor.recover_connect_intent() {
+            error!("Failed to recover an interrupted block connection: {}", e);
+        }
+        if let Err(e) = actor.check_utxo_commitment() {
+            error!("Failed to check the UTXO set commitment: {}", e);
+        }
+        if let Err(e) = actor.seed_best_headers() {
+            error!("Failed to build the best header chain: {}", e);
+        }
+        actor.prune_peer_timeline();
+        if let Err(e) = actor.prune_event_journal() {
+            warn!("Failed to prune event journal: {}", e);
+        }
+        match actor.load_address_backfill() {
+            Ok(backfill) => actor.address_backfill = backfill,
+            Err(e) => error!("Failed to load the address index backfill: {}", e),
+        }
+        match actor.open_background_validation() {
+            Ok(background) => actor.background = background,
+            Err(e) => error!("Failed to open the background chainstate: {}", e),
+        }
+        actor
     }
-}
 
-impl Actor for StorageActor {
-    type Context = Context<Self>;
+    /// Keep only the most recent `peer_timeline_max_events` events
+    fn prune_peer_timeline(&self) {
+        let cutoff = self.next_peer_event_seq.saturating_sub(self.peer_timeline_max_events);
+        if cutoff > 0 {
+            if let Err(e) = self.storage.prune_peer_events_before(cutoff) {
+                warn!("Failed to prune peer timeline: {}", e);
+            }
+        }
+    }
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
-        info!("Storage actor started");
+    /// Keep only the most recent `event_journal_max_events` events
+    fn prune_event_journal(&self) -> StorageResult<()> {
+        let Some(last) = self.storage.last_event_seq()? else {
+            return Ok(());
+        };
+        let cutoff = (last + 1).saturating_sub(self.event_journal_max_events);
+        if cutoff > 0 {
+            self.storage.prune_events_before(cutoff)?;
+        }
+        Ok(())
     }
+}
 
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
-        info!("Storage actor stopped");
+fn encode_chain_tip(tip: &ChainTip) -> Vec<u8> {
+    let mut data = tip.hash.to_byte_array().to_vec();
+    data.extend_from_slice(&tip.height.to_be_bytes());
+    data
+}
+
+fn decode_work(hex: &str) -> StorageResult<Work> {
+    let bytes: [u8; 32] = Vec::<u8>::from_hex(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| StorageError::Corruption {
+            component: format!("chain work {}", hex),
+        })?;
+    Ok(Work::from_be_bytes(bytes))
+}
+
+fn encode_backfill(next: u64, end: u64) -> Vec<u8> {
+    [next.to_be_bytes(), end.to_be_bytes()].concat()
+}
+
+fn decode_backfill(data: &[u8]) -> StorageResult<(u64, u64)> {
+    let (next, end) = data.split_at_checked(8)
+        .filter(|(_, end)| end.len() == 8)
+        .ok_or_else(|| StorageError::Corruption {
+            component: format!("address index backfill record has {} bytes", data.len()),
+        })?;
+    Ok((
+        u64::from_be_bytes(next.try_into().expect("split at 8")),
+        u64::from_be_bytes(end.try_into().expect("checked length")),
+    ))
+}
+
+fn decode_chain_tip(data: &[u8]) -> StorageResult<ChainTip> {
+    if data.len() != 40 {
+        return Err(StorageError::Corruption {
+            component: format!("chain tip record has {} bytes", data.len()),
+        });
     }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&data[..32]);
+    let mut height = [0u8; 8];
+    height.copy_from_slice(&data[32..]);
+
+    Ok(ChainTip {
+        hash: bitcoin::BlockHash::from_byte_array(hash),
+        height: u64::from_be_bytes(height),
+    })
 }
 
-impl Handler<StoreBlock> for StorageActor {
-    type Result = Result<(), StorageError>;
+/// A restored snapshot's tip followed by the commitment to its UTXO set
+fn encode_snapshot_base(base: &ChainTip, muhash: &MuHash3072) -> Vec<u8> {
+    let mut data = encode_chain_tip(base);
+    data.extend(muhash.to_bytes());
+    data
+}
 
-    fn handle(&mut self, msg: StoreBlock, _ctx: &mut Self::Context) -> Self::Result {
-        let block_hash = msg.block.block_hash();
-        let block_data = bitcoin::consensus::serialize(&msg.block);
+fn decode_snapshot_base(data: &[u8]) -> StorageResult<(ChainTip, MuHash3072)> {
+    let (tip, muhash) = data.split_at_checked(40)
+        .and_then(|(tip, muhash)| Some((tip, MuHash3072::from_bytes(muhash)?)))
+        .ok_or_else(|| StorageError::Corruption {
+            component: format!("snapshot base record has {} bytes", data.len()),
+        })?;
+    Ok((decode_chain_tip(tip)?, muhash))
+}
 
-        self.storage.store_block(&block_hash.to_byte_array(), &block_data)?;
+/// Sibling hashes needed to fold the leaf at `index` up to the merkle root
+fn merkle_branch(txids: &[Txid], mut index: usize) -> Vec<TxMerkleNode> {
+    let mut layer: Vec<TxMerkleNode> = txids
+        .iter()
+        .map(|txid| TxMerkleNode::from_byte_array(txid.to_byte_array()))
+        .collect();
+    let mut branch = Vec::new();
 
-        info!("Stored block: {}", block_hash);
-        Ok(())
+    while layer.len() > 1 {
+        // Odd layers pair their last node with itself
+        if !layer.len().is_multiple_of(2) {
+            layer.push(layer[layer.len() - 1]);
+        }
+        branch.push(layer[index ^ 1]);
+        layer = layer
+            .chunks(2)
+            .map(|pair| {
+                let mut engine = TxMerkleNode::engine();
+                bitcoin::hashes::HashEngine::input(&mut engine, pair[0].as_byte_array());
+                bitcoin::hashes::HashEngine::input(&mut engine, pair[1].as_byte_array());
+                TxMerkleNode::from_engine(engine)
+            })
+            .collect();
+        index /= 2;
     }
+
+    branch
 }
 
-impl Handler<GetBlock> for StorageActor {
-    type Result = Result<Option<bitcoin::Block>, StorageError>;
+fn build_tx_proof(block: &Block, txid: Txid, height: Option<u64>) -> Option<TxProof> {
+    let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+    let position = txids.iter().position(|candidate| *candidate == txid)?;
+    let merkle_block = MerkleBlock::from_header_txids_with_predicate(&block.header, &txids, |candidate| *candidate == txid);
 
-    fn handle(&mut self, msg: GetBlock, _ctx: &mut Self::Context) -> Self::Result {
-        match self.storage.get_block(&msg.hash.to_byte_array())? {
-            Some(block_data) => {
-                match bitcoin::consensus::deserialize(&block_data) {
-                    Ok(block) => Ok(Some(block)),
-                    Err(e) => {
-                        error!("Failed to deserialize block {}: {}", msg.hash, e);
-                        Err(StorageError::Serialization(e.to_string()))
-                    }
+    Some(TxProof {
+        txid: txid.to_string(),
+        block_hash: block.block_hash().to_string(),
+        block_height: height,
+        position: position as u32,
+        header: bitcoin::consensus::encode::serialize_hex(&block.header),
+        merkle_root: block.header.merkle_root.to_string(),
+        branch: merkle_branch(&txids, position).iter().map(|node| node.to_string()).collect(),
+        merkle_block: bitcoin::consensus::encode::serialize_hex(&merkle_block),
+    })
+}
+
+impl StorageActor {
+    fn load_tip(&self) -> StorageResult<Option<ChainTip>> {
+        match self.storage.get_chain_state(&ChainStateKey::Tip)? {
+            Some(data) => Ok(Some(decode_chain_tip(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn load_block_stats(&self, height: u64) -> StorageResult<Option<BlockStats>> {
+        match self.storage.get_block_stats(height)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| StorageError::Serialization(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn load_daily_stats(&self, day: u32) -> StorageResult<Option<DailyStats>> {
+        match self.storage.get_daily_stats(day)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| StorageError::Serialization(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Height and cumulative work of a block on the active chain or in the stale archive
+    fn block_position(&self, hash: &bitcoin::BlockHash) -> StorageResult<Option<(u64, Work)>> {
+        if let Some(height) = self.storage.get_block_height(&BlockKey(*hash))? {
+            let entry = self.storage.get_header_entry(height)?.ok_or_else(|| StorageError::Corruption {
+                component: format!("header entry for height {}", height),
+            })?;
+            return Ok(Some((height, entry.chain_work)));
+        }
+        match self.load_stale_block(hash)? {
+            Some(stale) => Ok(Some((stale.height, decode_work(&stale.chain_work)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn load_stale_block(&self, hash: &bitcoin::BlockHash) -> StorageResult<Option<StaleBlock>> {
+        match self.storage.get_stale_block(&BlockKey(*hash))? {
+            Some(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| StorageError::Serialization(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn archive_stale_block(&self, block: &Block, height: u64, chain_work: Work, reason: StaleReason) -> StorageResult<()> {
+        let record = StaleBlock {
+            hash: block.block_hash().to_string(),
+            height,
+            prev_hash: block.header.prev_blockhash.to_string(),
+            time: block.header.time,
+            chain_work: chain_work.to_be_bytes().to_lower_hex_string(),
+            reason,
+            archived_at: chrono::Utc::now(),
+        };
+        let data = serde_json::to_vec(&record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_stale_block(&BlockKey(block.block_hash()), &data)
+    }
+
+    /// Take a disconnected block's statistics back out of its day's aggregate
+    fn unrecord_block_stats(&self, height: u64) -> StorageResult<()> {
+        let Some(stats) = self.load_block_stats(height)? else {
+            return Ok(());
+        };
+        let day = day_index(stats.time);
+        if let Some(mut daily) = self.load_daily_stats(day)? {
+            daily.remove_block(&stats);
+            let daily_data = serde_json::to_vec(&daily)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            self.storage.store_daily_stats(day, &daily_data)?;
+        }
+        self.storage.delete_block_fee_rates(stats.time, height)?;
+        self.storage.delete_block_stats(height)
+    }
+
+    /// Store the block's statistics and fee rates, and fold the statistics
+    /// into its day's aggregate
+    fn record_block_stats(&self, block: &Block, height: u64, delta: &UtxoDelta) -> StorageResult<()> {
+        let stats = BlockStats::from_block(block, height, self.network);
+        let spent: HashMap<(String, u32), u64> = delta.spent.iter()
+            .filter_map(|coin| Some(((coin.txid.clone(), coin.vout), coin.value?)))
+            .collect();
+        let fee_rates = BlockFeeRates::from_block(block, height, |outpoint| {
+            spent.get(&(outpoint.txid.to_string(), outpoint.vout)).copied()
+        });
+        let fee_rates_data = serde_json::to_vec(&fee_rates)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_block_fee_rates(fee_rates.time, height, &fee_rates_data)?;
+
+        let day = day_index(stats.time);
+        let mut daily = self.load_daily_stats(day)?.unwrap_or_else(|| DailyStats::new(day));
+        daily.add_block(&stats);
+
+        let stats_data = serde_json::to_vec(&stats)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let daily_data = serde_json::to_vec(&daily)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_block_stats(height, &stats_data)?;
+        self.storage.store_daily_stats(day, &daily_data)
+
+    }
+
+    /// Work out a block's UTXO changes without writing anything. Every input
+    /// must spend a mature coin in the UTXO set and no transaction, nor the
+    /// coinbase, may pay out more than it takes in; input scripts are
+    /// verified under `script_flags` when given.
+    fn plan_connect(&self, block: &Block, height: u64, script_flags: Option<u32>) -> StorageResult<UtxoDelta> {
+        Self::plan_block(block, height, self.network, script_flags, |prevout| {
+            self.storage.get_utxo(&UtxoKey::new(prevout.txid, prevout.vout))?
+                .map(|data| UtxoEntry::decode(&data))
+                .transpose()
+        })
+    }
+
+    /// `plan_connect` against any UTXO set, with coins from before the block
+    /// looked up through `coin_at`
+    fn plan_block(
+        block: &Block,
+        height: u64,
+        network: bitcoin::Network,
+        script_flags: Option<u32>,
+        mut coin_at: impl FnMut(&OutPoint) -> StorageResult<Option<UtxoEntry>>,
+    ) -> StorageResult<UtxoDelta> {
+        let block_hash = block.block_hash();
+        let invalid = |reason: &str| StorageError::InvalidBlock {
+            hash: block_hash.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut delta = UtxoDelta {
+            height,
+            block_hash: block_hash.to_string(),
+            created: Vec::new(),
+            spent: Vec::new(),
+        };
+        // The genesis coinbase is unspendable and never enters the UTXO set
+        if height == 0 {
+            return Ok(delta);
+        }
+
+        // Coins created earlier in this block, which later transactions may spend
+        let mut created_here: HashMap<OutPoint, UtxoEntry> = HashMap::new();
+        // Coins from the UTXO set already spent in this block
+        let mut spent_here: HashSet<OutPoint> = HashSet::new();
+        let mut fees = Amount::ZERO;
+        let mut unverified = 0;
+        for tx in &block.txdata {
+            let txid = tx.txid();
+            let is_coinbase = tx.is_coinbase();
+
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey.is_op_return() {
+                    continue;
                 }
+                created_here.insert(OutPoint { txid, vout: vout as u32 }, UtxoEntry {
+                    height,
+                    is_coinbase,
+                    output: output.clone(),
+                });
+                delta.created.push(CreatedUtxo {
+                    txid: txid.to_string(),
+                    vout: vout as u32,
+                    value: output.value.to_sat(),
+                    script_pubkey: output.script_pubkey.to_hex_string(),
+                    is_coinbase,
+                });
             }
-            None => Ok(None),
+
+            if is_coinbase {
+                continue;
+            }
+
+            let mut spent_outputs = Vec::with_capacity(tx.input.len());
+            let mut value_in = Amount::ZERO;
+            for input in &tx.input {
+                let prevout = input.previous_output;
+                let coin = match created_here.remove(&prevout) {
+                    Some(entry) => entry,
+                    None if spent_here.insert(prevout) => coin_at(&prevout)?
+                        .ok_or_else(|| invalid("bad-txns-inputs-missingorspent"))?,
+                    None => return Err(invalid("bad-txns-inputs-missingorspent")),
+                };
+                if coin.is_coinbase && height < coin.height + COINBASE_MATURITY {
+                    return Err(invalid("bad-txns-premature-spend-of-coinbase"));
+                }
+                value_in = value_in.checked_add(coin.output.value)
+                    .filter(|total| coin.output.value <= Amount::MAX_MONEY && *total <= Amount::MAX_MONEY)
+                    .ok_or_else(|| invalid("bad-txns-inputvalues-outofrange"))?;
+
+                delta.spent.push(SpentUtxo {
+                    txid: prevout.txid.to_string(),
+                    vout: prevout.vout,
+                    value: Some(coin.output.value.to_sat()),
+                    script_pubkey: Some(coin.output.script_pubkey.to_hex_string()),
+                    height: Some(coin.height),
+                    is_coinbase: Some(coin.is_coinbase),
+                });
+                spent_outputs.push(coin.output);
+            }
+
+            let value_out = tx.output.iter().try_fold(Amount::ZERO, |total, output| total.checked_add(output.value));
+            match value_out.and_then(|value_out| value_in.checked_sub(value_out)) {
+                Some(fee) => fees += fee,
+                None => return Err(invalid("bad-txns-in-belowout")),
+            }
+
+            if let Some(flags) = script_flags {
+                unverified += spent_outputs.iter().filter(|spent| crate::script::is_unverifiable(spent)).count();
+                crate::script::verify_transaction(tx, &spent_outputs, flags).map_err(|e| StorageError::InvalidBlock {
+                    hash: block_hash.to_string(),
+                    reason: format!("transaction {}: {}", txid, e),
+                })?;
+            }
+        }
+
+        let claimed = block.txdata[0].output.iter().try_fold(Amount::ZERO, |total, output| total.checked_add(output.value));
+        let allowed = Amount::from_sat(crate::analytics::block_subsidy(height, network)) + fees;
+        if claimed.is_none_or(|claimed| claimed > allowed) {
+            return Err(invalid("bad-cb-amount"));
+        }
+        if unverified > 0 {
+            warn!("Block {} has {} taproot or later witness inputs the script library cannot verify, accepted unchecked",
+                  block_hash, unverified);
         }
+        Ok(delta)
     }
-}
 
-impl Handler<AddTransaction> for StorageActor {
-    type Result = Result<(), StorageError>;
+    /// Write a planned block connection, tip last. Every step overwrites
+    /// rather than adds, so running it again after a crash ends in the same state.
+    fn apply_connect(&self, block: &Block, height: u64, delta: &UtxoDelta) -> StorageResult<()> {
+        let block_hash = block.block_hash();
+        let mut silent_payment_tweaks = Vec::new();
 
-    fn handle(&mut self, msg: AddTransaction, _ctx: &mut Self::Context) -> Self::Result {
-        let txid = msg.tx.txid();
-        let tx_data = bitcoin::consensus::serialize(&msg.tx);
+        if height > 0 {
+            let mut spent = delta.spent.iter();
+            for tx in &block.txdata {
+                let txid = tx.txid();
+                let is_coinbase = tx.is_coinbase();
+
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if output.script_pubkey.is_op_return() {
+                        continue;
+                    }
+                    let entry = UtxoEntry {
+                        height,
+                        is_coinbase,
+                        output: output.clone(),
+                    };
+                    self.storage.store_utxo(&UtxoKey::new(txid, vout as u32), &entry.encode())?;
+                }
 
-        self.storage.store_transaction(&txid.to_byte_array(), &tx_data)?;
+                if is_coinbase {
+                    continue;
+                }
 
-        info!("Stored transaction: {}", txid);
+                let mut prevouts = Vec::with_capacity(tx.input.len());
+                for (input, coin) in tx.input.iter().zip(spent.by_ref()) {
+                    let prevout = input.previous_output;
+                    self.storage.delete_utxo(&UtxoKey::new(prevout.txid, prevout.vout))?;
+                    if let (true, Some(value), Some(script_hex)) = (self.silent_payment_index, coin.value, &coin.script_pubkey) {
+                        prevouts.push(TxOut {
+                            value: Amount::from_sat(value),
+                            script_pubkey: ScriptBuf::from_hex(script_hex)
+                                .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                        });
+                    }
+                }
+
+                if self.silent_payment_index {
+                    if let Some(tweak) = crate::silentpayments::transaction_tweak(tx, &prevouts) {
+                        silent_payment_tweaks.push((txid, tweak));
+                    }
+                }
+            }
+        }
+
+        let prev_work = match height.checked_sub(1) {
+            Some(prev_height) => self.storage.get_header_entry(prev_height)?.map(|entry| entry.chain_work),
+            None => None,
+        };
+        let header_entry = HeaderEntry {
+            header: block.header,
+            chain_work: match prev_work {
+                Some(work) => work + block.header.work(),
+                None => block.header.work(),
+            },
+        };
+        self.storage.store_header_entry(height, &header_entry)?;
+        self.adopt_best_headers(height, &[header_entry])?;
+        self.storage.store_block_height(&BlockKey(block_hash), height)?;
+        self.storage.delete_stale_block(&BlockKey(block_hash))?;
+
+        for (position, tx) in block.txdata.iter().enumerate() {
+            let location = TxLocation {
+                block_hash,
+                height,
+                position: position as u32,
+            };
+            self.storage.store_tx_location(&TxKey(tx.txid()), &location)?;
+        }
+
+        let delta_data = serde_json::to_vec(delta)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_utxo_delta(height, &delta_data)?;
+        // Folding into the daily aggregate is the one additive step; stored
+        // block stats mark it done (disconnecting removes them)
+        if self.load_block_stats(height)?.is_none() {
+            self.record_block_stats(block, height, delta)?;
+        }
+        if self.silent_payment_index {
+            self.storage.store_silent_payment_tweaks(height, &crate::silentpayments::encode_tweaks(&silent_payment_tweaks))?;
+        }
+        if self.address_index {
+            self.update_address_index(block, height, delta, true)?;
+        }
+
+        self.advance_utxo_commitment(block, height, delta, block.header.prev_blockhash, block_hash)?;
+
+        let tip = ChainTip { hash: block_hash, height };
+        self.storage.store_chain_state(&ChainStateKey::Tip, &encode_chain_tip(&tip))
+    }
+
+    /// Add a block's outputs and spends to the address index, or remove them.
+    /// Spends of coins that were unknown when the block connected are not indexed.
+    fn update_address_index(&self, block: &Block, height: u64, delta: &UtxoDelta, connect: bool) -> StorageResult<()> {
+        if height == 0 {
+            return Ok(());
+        }
+        let write = |key: AddressKey, entry: AddressEntry| {
+            if connect {
+                self.storage.store_address_entry(&key, &entry)
+            } else {
+                self.storage.delete_address_entry(&key)
+            }
+        };
+
+        let mut spent = delta.spent.iter();
+        for tx in &block.txdata {
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey.is_op_return() {
+                    continue;
+                }
+                let key = AddressKey {
+                    script_hash: AddressKey::script_hash(&output.script_pubkey),
+                    height,
+                    txid,
+                    kind: AddressEntryKind::Funding,
+                    index: vout as u32,
+                };
+                write(key, AddressEntry { value: output.value.to_sat(), prevout: None })?;
+            }
+
+            if tx.is_coinbase() {
+                continue;
+            }
+            for (index, (input, coin)) in tx.input.iter().zip(spent.by_ref()).enumerate() {
+                let (Some(value), Some(script_hex)) = (coin.value, &coin.script_pubkey) else {
+                    continue;
+                };
+                let script_pubkey = ScriptBuf::from_hex(script_hex)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                let key = AddressKey {
+                    script_hash: AddressKey::script_hash(&script_pubkey),
+                    height,
+                    txid,
+                    kind: AddressEntryKind::Spending,
+                    index: index as u32,
+                };
+                write(key, AddressEntry { value, prevout: Some(input.previous_output) })?;
+            }
+        }
         Ok(())
     }
-}
 
-impl Handler<GetTransaction> for StorageActor {
-    type Result = Result<Option<bitcoin::Transaction>, StorageError>;
+    fn load_utxo_commitment(&self) -> StorageResult<Option<UtxoCommitment>> {
+        match self.storage.get_chain_state(&ChainStateKey::UtxoCommitment)? {
+            Some(data) => Ok(Some(UtxoCommitment::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
 
-    fn handle(&mut self, msg: GetTransaction, _ctx: &mut Self::Context) -> Self::Result {
-        match self.storage.get_transaction(&msg.txid.to_byte_array())? {
-            Some(tx_data) => {
-                match bitcoin::consensus::deserialize(&tx_data) {
-                    Ok(tx) => Ok(Some(tx)),
-                    Err(e) => {
-                        error!("Failed to deserialize transaction {}: {}", msg.txid, e);
-                        Err(StorageError::Serialization(e.to_string()))
+    /// Move the UTXO commitment across a block, forwards on connect and
+    /// backwards on disconnect. Already at `to` means a replayed connection;
+    /// anywhere but `from` means it is stale and gets rebuilt at the next start.
+    fn advance_utxo_commitment(
+        &self,
+        block: &Block,
+        height: u64,
+        delta: &UtxoDelta,
+        from: bitcoin::BlockHash,
+        to: bitcoin::BlockHash,
+    ) -> StorageResult<()> {
+        let Some(mut commitment) = self.load_utxo_commitment()? else {
+            return Ok(());
+        };
+        if commitment.block_hash == to {
+            return Ok(());
+        }
+        if commitment.block_hash != from {
+            warn!("UTXO commitment is for block {}, not {}; it will be rebuilt on restart", commitment.block_hash, from);
+            return Ok(());
+        }
+
+        let mut created = Vec::new();
+        if height > 0 {
+            for tx in &block.txdata {
+                let txid = tx.txid();
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if !output.script_pubkey.is_op_return() {
+                        created.push(coin_bytes(&OutPoint { txid, vout: vout as u32 }, height, tx.is_coinbase(), output));
                     }
                 }
             }
+        }
+        let mut spent = Vec::new();
+        for coin in &delta.spent {
+            let (Some(value), Some(script_hex), Some(coin_height)) = (coin.value, &coin.script_pubkey, coin.height) else {
+                continue;
+            };
+            let txid: Txid = coin.txid.parse()
+                .map_err(|e: bitcoin::hashes::hex::HexToArrayError| StorageError::Serialization(e.to_string()))?;
+            let output = TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::from_hex(script_hex)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?,
+            };
+            spent.push(coin_bytes(&OutPoint { txid, vout: coin.vout }, coin_height, coin.is_coinbase.unwrap_or(false), &output));
+        }
+
+        let connecting = to == block.block_hash();
+        let (added, removed) = if connecting { (created, spent) } else { (spent, created) };
+        for coin in &added {
+            commitment.muhash.insert(coin);
+        }
+        for coin in &removed {
+            commitment.muhash.remove(coin);
+        }
+        commitment.block_hash = to;
+        self.storage.store_chain_state(&ChainStateKey::UtxoCommitment, &commitment.encode())
+    }
+
+    fn scan_utxo_commitment(storage: &Storage) -> StorageResult<(MuHash3072, u64, u64)> {
+        Self::scan_utxo_commitment_with_progress(storage, |_| {})
+    }
+
+    /// The scan, calling `report` with the coins visited so far every `UTXO_SCAN_REPORT_INTERVAL` coins
+    fn scan_utxo_commitment_with_progress(
+        storage: &Storage,
+        mut report: impl FnMut(u64),
+    ) -> StorageResult<(MuHash3072, u64, u64)> {
+        let mut muhash = MuHash3072::new();
+        let (mut txouts, mut total_amount) = (0u64, 0u64);
+        storage.for_each_utxo(|outpoint, entry| {
+            muhash.insert(&coin_bytes(&outpoint, entry.height, entry.is_coinbase, &entry.output));
+            txouts += 1;
+            total_amount += entry.output.value.to_sat();
+            if txouts.is_multiple_of(UTXO_SCAN_REPORT_INTERVAL) {
+                report(txouts);
+            }
+        })?;
+        Ok((muhash, txouts, total_amount))
+    }
+
+    /// Startup integrity check: the commitment must belong to the current tip.
+    /// A missing or mismatched one is rebuilt with a full scan.
+    fn check_utxo_commitment(&self) -> StorageResult<()> {
+        let commitment = self.load_utxo_commitment()?;
+        let Some(tip) = self.load_tip()? else {
+            let empty = UtxoCommitment { block_hash: bitcoin::BlockHash::all_zeros(), muhash: MuHash3072::new() };
+            return self.storage.store_chain_state(&ChainStateKey::UtxoCommitment, &empty.encode());
+        };
+
+        match commitment {
+            Some(commitment) if commitment.block_hash == tip.hash => {
+                info!("UTXO set commitment matches the tip at height {}", tip.height);
+                return Ok(());
+            }
+            Some(commitment) => error!(
+                "UTXO set commitment is for block {} but the tip is {} at height {}, rebuilding it",
+                commitment.block_hash, tip.hash, tip.height
+            ),
+            None => info!("Computing the UTXO set commitment at height {}", tip.height),
+        }
+
+        startup::enter(StartupStage::BuildingUtxoCommitment);
+        let estimate = self.storage.estimate_num_keys(CF_UTXOS)?.unwrap_or(0);
+        let (muhash, txouts, _) = Self::scan_utxo_commitment_with_progress(&self.storage, |done| {
+            // The estimate can fall short of the real count
+            startup::progress(done, estimate.max(done));
+        })?;
+        info!("UTXO set commitment computed over {} coins", txouts);
+        let commitment = UtxoCommitment { block_hash: tip.hash, muhash };
+        self.storage.store_chain_state(&ChainStateKey::UtxoCommitment, &commitment.encode())
+    }
+
+    /// Finish a block connection that a crash interrupted, from the intent
+    /// record that versions before atomic commits wrote ahead of it
+    fn recover_connect_intent(&mut self) -> StorageResult<()> {
+        let Some(data) = self.storage.get_chain_state(&ChainStateKey::ConnectIntent)? else {
+            return Ok(());
+        };
+        let delta: UtxoDelta = serde_json::from_slice(&data)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let block_hash: bitcoin::BlockHash = delta.block_hash.parse()
+            .map_err(|e: bitcoin::hashes::hex::HexToArrayError| StorageError::Serialization(e.to_string()))?;
+        let block = self.load_block(&block_hash)?.ok_or_else(|| StorageError::Corruption {
+            component: format!("block data for the interrupted connection of {}", block_hash),
+        })?;
+
+        startup::enter(StartupStage::RecoveringBlock);
+        warn!("Connection of block {} at height {} was interrupted, rolling it forward", block_hash, delta.height);
+        self.atomically(|actor| {
+            actor.apply_connect(&block, delta.height, &delta)?;
+            actor.storage.delete_chain_state(&ChainStateKey::ConnectIntent)
+        })
+    }
+
+    /// Run `write` with every storage write it makes collected into one
+    /// batch, committed atomically across column families once it succeeds
+    fn atomically<T>(&mut self, write: impl FnOnce(&Self) -> StorageResult<T>) -> StorageResult<T> {
+        let batch = self.storage.write_batch()?;
+        let committed = std::mem::replace(&mut self.storage, batch);
+        let result = write(self);
+        let batch = std::mem::replace(&mut self.storage, committed);
+        let value = result?;
+        batch.commit()?;
+        Ok(value)
+    }
+
+    fn load_block(&self, hash: &bitcoin::BlockHash) -> StorageResult<Option<Block>> {
+        match self.storage.get_block(&BlockKey(*hash))? {
+            Some(data) => bitcoin::consensus::deserialize(&data)
+                .map(Some)
+                .map_err(|e| StorageError::Serialization(e.to_string())),
             None => Ok(None),
         }
     }
-}
\ No newline at end of file
+
+    /// Start compacting the next due column family, one at a time and off the actor
+    fn schedule_compaction(&mut self, ctx: &mut Context<Self>) {
+        if self.compacting {
+            return;
+        }
+        let now = crate::clock::now_utc();
+        let Some(cf_name) = self.compaction.as_ref().and_then(|scheduler| scheduler.next_due(now)) else {
+            return;
+        };
+
+        self.compacting = true;
+        info!("Compacting column family {}", cf_name);
+        let storage = self.storage.clone();
+        let started = Instant::now();
+        let compaction = {
+            let cf_name = cf_name.clone();
+            async move { tokio::task::spawn_blocking(move || storage.compact_cf(&cf_name)).await }
+        };
+        ctx.spawn(compaction.into_actor(self).map(move |result, actor, _ctx| {
+            actor.compacting = false;
+            let elapsed = started.elapsed();
+            let result = result.map_err(|e| StorageError::Task(e.to_string())).and_then(|result| result);
+            crate::metrics::record_storage_operation("compaction", elapsed, result.is_ok());
+            match result {
+                Ok(()) => info!("Compacted column family {} in {:?}", cf_name, elapsed),
+                Err(e) => error!("Failed to compact column family {}: {}", cf_name, e),
+            }
+            // Failures count too, so a broken column family is not retried every check
+            if let Some(scheduler) = actor.compaction.as_mut() {
+                scheduler.record(&cf_name, crate::clock::now_utc());
+            }
+        }));
+    }
+
+    /// Where the address index backfill stands, starting one if the index
+    /// was just enabled on a chain that already has blocks
+    fn load_address_backfill(&self) -> StorageResult<Option<(u64, u64)>> {
+        let record = self.storage.get_chain_state(&ChainStateKey::AddressIndexBackfill)?;
+        if !self.address_index {
+            // Blocks connected while the index is off are missing from it, so
+            // enabling it again starts over
+            if record.is_some() {
+                self.storage.delete_chain_state(&ChainStateKey::AddressIndexBackfill)?;
+            }
+            return Ok(None);
+        }
+
+        let (next, end) = match record {
+            Some(data) => decode_backfill(&data)?,
+            None => {
+                let end = self.load_tip()?.map(|tip| tip.height + 1).unwrap_or(0);
+                self.storage.store_chain_state(&ChainStateKey::AddressIndexBackfill, &encode_backfill(0, end))?;
+                if end > 0 {
+                    info!("Address index enabled, backfilling {} stored blocks", end);
+                }
+                (0, end)
+            }
+        };
+        Ok((next < end).then_some((next, end)))
+    }
+
+    /// Add the next second's worth of stored blocks to the address index,
+    /// stopping at the first one this node does not store
+    fn backfill_address_index(&mut self) {
+        let Some((from, end)) = self.address_backfill else {
+            return;
+        };
+        let stop = (from + self.backfill_blocks_per_sec).min(end);
+
+        let result = self.atomically(|actor| {
+            let mut height = from;
+            let mut missing_block = None;
+            while height < stop {
+                // A reorg may have lowered the tip; blocks above it are indexed as they connect
+                let Some(entry) = actor.storage.get_header_entry(height)? else {
+                    break;
+                };
+                let hash = entry.header.block_hash();
+                let Some(block) = actor.load_block(&hash)? else {
+                    missing_block = Some((height, hash));
+                    break;
+                };
+                actor.index_backfill_block(&block, height)?;
+                height += 1;
+            }
+            let next = if height < stop && missing_block.is_none() { end } else { height };
+            actor.storage.store_chain_state(&ChainStateKey::AddressIndexBackfill, &encode_backfill(next, end))?;
+            Ok((next, missing_block))
+        });
+
+        match result {
+            Ok((next, missing_block)) => {
+                if missing_block.is_some() && self.backfill_missing_block != missing_block {
+                    info!("Address index backfill waiting for block {} from peers", next);
+                }
+                self.backfill_missing_block = missing_block;
+                self.advance_address_backfill(from, next, end);
+            }
+            Err(e) => {
+                // Retried from the same height at the next tick and the next start
+                error!("Address index backfill failed at height {}: {}", from, e);
+            }
+        }
+    }
+
+    /// Add a block the backfill is waiting for, fetched from peers, to the
+    /// address index; the block itself is not stored
+    fn index_fetched_block(&mut self, height: u64, block: &Block) -> StorageResult<()> {
+        let (Some((from, end)), Some((missing_height, missing_hash))) = (self.address_backfill, self.backfill_missing_block) else {
+            return Ok(());
+        };
+        if height != missing_height || block.block_hash() != missing_hash {
+            return Err(StorageError::InvalidBlock {
+                hash: block.block_hash().to_string(),
+                reason: format!("not the block the address index backfill waits for at height {}", missing_height),
+            });
+        }
+
+        self.atomically(|actor| {
+            actor.index_backfill_block(block, height)?;
+            actor.storage.store_chain_state(&ChainStateKey::AddressIndexBackfill, &encode_backfill(height + 1, end))
+        })?;
+        self.backfill_missing_block = None;
+        self.advance_address_backfill(from, height + 1, end);
+        Ok(())
+    }
+
+    /// Add an active chain block to the address index from its UTXO delta
+    fn index_backfill_block(&self, block: &Block, height: u64) -> StorageResult<()> {
+        let delta: UtxoDelta = match self.storage.get_utxo_delta(height)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?,
+            None => {
+                return Err(StorageError::Corruption {
+                    component: format!("UTXO delta for height {}", height),
+                });
+            }
+        };
+        self.update_address_index(block, height, &delta, true)
+    }
+
+    fn advance_address_backfill(&mut self, from: u64, next: u64, end: u64) {
+        if next >= end {
+            info!("Address index backfill complete");
+            self.address_backfill = None;
+            return;
+        }
+        if next / ADDRESS_BACKFILL_LOG_INTERVAL > from / ADDRESS_BACKFILL_LOG_INTERVAL {
+            info!("Address index backfilled to height {} of {}", next, end);
+        }
+        self.address_backfill = Some((next, end));
+    }
+
+    /// The restored node snapshot's tip and UTXO set commitment, until the
+    /// blocks behind it have been validated
+    fn load_snapshot_base(&self) -> StorageResult<Option<(ChainTip, MuHash3072)>> {
+        match self.storage.get_chain_state(&ChainStateKey::SnapshotBase)? {
+            Some(data) => Ok(Some(decode_snapshot_base(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Open the background chainstate where its last replay stopped
+    fn open_background_validation(&self) -> StorageResult<Option<BackgroundValidation>> {
+        let Some((base, base_muhash)) = self.load_snapshot_base()? else {
+            return Ok(None);
+        };
+        let storage = Storage::new(&self.background_config)?;
+        let tip = match storage.get_chain_state(&ChainStateKey::Tip)? {
+            Some(data) => Some(decode_chain_tip(&data)?),
+            None => None,
+        };
+        let muhash = match storage.get_chain_state(&ChainStateKey::UtxoCommitment)? {
+            Some(data) => UtxoCommitment::decode(&data)?.muhash,
+            None => MuHash3072::new(),
+        };
+        match tip {
+            Some(tip) => info!("Validating the blocks behind the restored snapshot from height {} of {}", tip.height + 1, base.height),
+            None => info!("Validating the {} blocks behind the restored snapshot", base.height + 1),
+        }
+        Ok(Some(BackgroundValidation { storage, base, base_muhash, tip, muhash, failed: false }))
+    }
+
+    /// Record a restored tip as a snapshot still to be validated and start
+    /// the background chainstate that validates it
+    fn start_background_validation(&mut self, base: &ChainTip) -> StorageResult<()> {
+        // Committed to from the restored coins themselves, not the dump's own record
+        let (muhash, _, _) = Self::scan_utxo_commitment(&self.storage)?;
+        self.storage.store_chain_state(&ChainStateKey::SnapshotBase, &encode_snapshot_base(base, &muhash))?;
+        self.background = self.open_background_validation()?;
+        Ok(())
+    }
+
+    /// Drop the background chainstate and its database
+    fn remove_background_validation(&mut self) {
+        self.background = None;
+        let path = &self.background_config.rocks_db_path;
+        if let Err(e) = std::fs::remove_dir_all(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove the background chainstate at {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn schedule_background_validation(&mut self, ctx: &mut Context<Self>) {
+        if self.background.is_some() && !self.background_scheduled {
+            self.background_scheduled = true;
+            ctx.run_interval(Duration::from_secs(1), |actor, _ctx| actor.validate_in_background());
+        }
+    }
+
+    /// Replay the next second's worth of blocks behind a restored snapshot,
+    /// marking the snapshot validated once its UTXO set is reproduced
+    fn validate_in_background(&mut self) {
+        let Some(background) = self.background.as_mut().filter(|background| !background.failed) else {
+            return;
+        };
+        let from = background.tip.map(|tip| tip.height + 1).unwrap_or(0);
+        match background.replay(&self.storage, self.network, self.background_blocks_per_sec) {
+            Ok(true) if background.muhash.same_set(&background.base_muhash) => {
+                let base = background.base;
+                match self.storage.delete_chain_state(&ChainStateKey::SnapshotBase) {
+                    Ok(()) => {
+                        info!("Restored snapshot at height {} validated from genesis", base.height);
+                        self.remove_background_validation();
+                    }
+                    Err(e) => error!("Failed to mark the restored snapshot validated: {}", e),
+                }
+            }
+            Ok(true) => {
+                error!("The UTXO set replayed to height {} does not match the restored snapshot's; it stays unvalidated",
+                       background.base.height);
+                background.failed = true;
+            }
+            Ok(false) => {
+                let next = background.tip.map(|tip| tip.height + 1).unwrap_or(0);
+                if next / BACKGROUND_VALIDATION_LOG_INTERVAL > from / BACKGROUND_VALIDATION_LOG_INTERVAL {
+                    info!("Background chainstate validated to height {} of {}", next - 1, background.base.height);
+                }
+            }
+            Err(StorageError::InvalidBlock { hash, reason }) => {
+                error!("Block {} behind the restored snapshot is invalid ({}); the snapshot stays unvalidated", hash, reason);
+                background.failed = true;
+            }
+            Err(e) => {
+                // Retried from the same height at the next tick and the next start
+                error!("Background validation failed at height {}: {}", from, e);
+            }
+        }
+    }
+
+    /// Take an incremental backup off the actor, unless one is still running
+    fn schedule_backup(&mut self, ctx: &mut Context<Self>) {
+        let Some(schedule) = self.backups.as_ref() else {
+            return;
+        };
+        if self.backing_up {
+            return;
+        }
+
+        self.backing_up = true;
+        let storage = self.storage.clone();
+        let (path, keep) = (schedule.path.clone(), schedule.keep);
+        let started = Instant::now();
+        let backup = async move { tokio::task::spawn_blocking(move || storage.backup(&path, keep)).await };
+        ctx.spawn(backup.into_actor(self).map(move |result, actor, _ctx| {
+            actor.backing_up = false;
+            let elapsed = started.elapsed();
+            let result = result.map_err(|e| StorageError::Task(e.to_string())).and_then(|result| result);
+            crate::metrics::record_storage_operation("backup", elapsed, result.is_ok());
+            match result {
+                Ok(backup) => info!("Backup {} took {:?} ({} bytes)", backup.id, elapsed, backup.size),
+                Err(e) => error!("Scheduled backup failed: {}", e),
+            }
+        }));
+    }
+}
+
+super::instrument::instrument_actor!(StorageActor);
+
+impl Actor for StorageActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Storage actor started");
+        if self.compaction.is_some() {
+            ctx.run_interval(self.compaction_check_interval, |actor, ctx| actor.schedule_compaction(ctx));
+        }
+        if let Some(schedule) = &self.backups {
+            ctx.run_interval(schedule.interval, |actor, ctx| actor.schedule_backup(ctx));
+        }
+        if self.address_backfill.is_some() {
+            ctx.run_interval(Duration::from_secs(1), |actor, _ctx| actor.backfill_address_index());
+        }
+        self.schedule_background_validation(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!("Storage actor stopped");
+    }
+}
+
+impl Handler<Shutdown> for StorageActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        // Everything queued ahead of this has been written; nothing comes after it
+        match self.storage.close() {
+            Ok(()) => info!("Storage closed"),
+            Err(e) => error!("Failed to close storage cleanly: {}", e),
+        }
+        ctx.stop();
+    }
+}
+
+impl Handler<StoreBlock> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: StoreBlock, _ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = msg.block.block_hash();
+        let block_data = bitcoin::consensus::serialize(&msg.block);
+
+        self.storage.store_block(&BlockKey(block_hash), &block_data)?;
+
+        info!("Stored block: {}", block_hash);
+        Ok(())
+    }
+}
+
+impl Handler<GetBlock> for StorageActor {
+    type Result = Result<Option<bitcoin::Block>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlock, _ctx: &mut Self::Context) -> Self::Result {
+        match self.storage.get_block(&BlockKey(msg.hash))? {
+            Some(block_data) => {
+                match bitcoin::consensus::deserialize(&block_data) {
+                    Ok(block) => Ok(Some(block)),
+                    Err(e) => {
+                        error!("Failed to deserialize block {}: {}", msg.hash, e);
+                        Err(StorageError::Serialization(e.to_string()))
+                    }
+                }
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Handler<AddTransaction> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: AddTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        let txid = msg.tx.txid();
+        let tx_data = bitcoin::consensus::serialize(&msg.tx);
+
+        self.storage.store_transaction(&TxKey(txid), &tx_data)?;
+
+        info!("Stored transaction: {}", txid);
+        Ok(())
+    }
+}
+
+impl Handler<GetTransaction> for StorageActor {
+    type Result = Result<Option<bitcoin::Transaction>, StorageError>;
+
+    fn handle(&mut self, msg: GetTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        match self.storage.get_transaction(&TxKey(msg.txid))? {
+            Some(tx_data) => {
+                match bitcoin::consensus::deserialize(&tx_data) {
+                    Ok(tx) => Ok(Some(tx)),
+                    Err(e) => {
+                        error!("Failed to deserialize transaction {}: {}", msg.txid, e);
+                        Err(StorageError::Serialization(e.to_string()))
+                    }
+                }
+            }
+            // Confirmed transactions are only kept inside their block
+            None => {
+                let Some(location) = self.storage.get_tx_location(&TxKey(msg.txid))? else {
+                    return Ok(None);
+                };
+                let Some(block_data) = self.storage.get_block(&BlockKey(location.block_hash))? else {
+                    return Ok(None);
+                };
+                let block: bitcoin::Block = bitcoin::consensus::deserialize(&block_data)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                Ok(block.txdata.into_iter().nth(location.position as usize))
+            }
+        }
+    }
+}
+
+impl Handler<GetChainTip> for StorageActor {
+    type Result = Result<Option<ChainTip>, StorageError>;
+
+    fn handle(&mut self, _msg: GetChainTip, _ctx: &mut Self::Context) -> Self::Result {
+        self.load_tip()
+    }
+}
+
+impl StorageActor {
+    /// Script flags for the block `hash` connecting at `height`, `None` when
+    /// it is an ancestor of the assume-valid block on the best header chain
+    fn script_flags(&mut self, height: u64, hash: &bitcoin::BlockHash) -> Option<u32> {
+        let storage = &self.storage;
+        self.script_checks.flags_for(height, hash, |height| {
+            storage.get_best_header(height).ok().flatten().map(|entry| entry.header.block_hash())
+        })
+    }
+
+    fn best_header(&self) -> StorageResult<Option<ChainTip>> {
+        Ok(self.storage.best_header_tip()?.map(|(height, entry)| ChainTip {
+            hash: entry.header.block_hash(),
+            height,
+        }))
+    }
+
+    /// Start the best header chain from the active chain, for databases
+    /// written before it was kept
+    fn seed_best_headers(&self) -> StorageResult<()> {
+        let (None, Some(tip)) = (self.storage.best_header_tip()?, self.load_tip()?) else {
+            return Ok(());
+        };
+        info!("Building the best header chain from {} connected headers", tip.height + 1);
+        for height in 0..=tip.height {
+            let entry = self.storage.get_header_entry(height)?.ok_or_else(|| StorageError::Corruption {
+                component: format!("header entry for height {}", height),
+            })?;
+            self.storage.store_best_header(height, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Make the headers in `entries`, the first at `first_height`, the best
+    /// header chain if they end with more work than it. Where the chain they
+    /// build on is the active one, the active headers below replace any the
+    /// best header chain held there.
+    fn adopt_best_headers(&self, first_height: u64, entries: &[HeaderEntry]) -> StorageResult<()> {
+        let Some(last) = entries.last() else {
+            return Ok(());
+        };
+        let best = self.storage.best_header_tip()?;
+        if best.as_ref().is_some_and(|(_, tip)| tip.chain_work >= last.chain_work) {
+            return Ok(());
+        }
+
+        for (height, entry) in (first_height..).zip(entries) {
+            self.storage.store_best_header(height, entry)?;
+        }
+        let last_height = first_height + entries.len() as u64 - 1;
+        if let Some((best_height, _)) = best {
+            for height in last_height + 1..=best_height {
+                self.storage.delete_best_header(height)?;
+            }
+        }
+
+        let mut expected = entries[0].header.prev_blockhash;
+        for height in (0..first_height).rev() {
+            let held = self.storage.get_best_header(height)?;
+            if held.is_some_and(|entry| entry.header.block_hash() == expected) {
+                break;
+            }
+            let entry = self.storage.get_header_entry(height)?
+                .filter(|entry| entry.header.block_hash() == expected)
+                .ok_or_else(|| StorageError::Corruption {
+                    component: format!("active header at height {} for the best header chain", height),
+                })?;
+            self.storage.store_best_header(height, &entry)?;
+            expected = entry.header.prev_blockhash;
+        }
+        Ok(())
+    }
+
+    /// Height and cumulative work of a header near the top of the best header
+    /// chain or of a block on the active chain
+    fn header_position(&self, hash: &bitcoin::BlockHash) -> StorageResult<Option<(u64, Work)>> {
+        if let Some((tip_height, _)) = self.storage.best_header_tip()? {
+            for height in (tip_height.saturating_sub(HEADER_FORK_SEARCH_DEPTH)..=tip_height).rev() {
+                if let Some(entry) = self.storage.get_best_header(height)?.filter(|entry| entry.header.block_hash() == *hash) {
+                    return Ok(Some((height, entry.chain_work)));
+                }
+            }
+        }
+        let Some(height) = self.storage.get_block_height(&BlockKey(*hash))? else {
+            return Ok(None);
+        };
+        let entry = self.storage.get_header_entry(height)?.ok_or_else(|| StorageError::Corruption {
+            component: format!("header entry for height {}", height),
+        })?;
+        Ok(Some((height, entry.chain_work)))
+    }
+
+    /// Check a run of headers from a peer links up and meets its targets, then
+    /// place it on the best header chain; false when the first one's parent is
+    /// unknown. Difficulty adjustments are not checked; the blocks are, once
+    /// they connect.
+    fn accept_headers(&self, headers: &[bitcoin::block::Header]) -> StorageResult<bool> {
+        let Some(first) = headers.first() else {
+            return Ok(true);
+        };
+        let Some((parent_height, mut chain_work)) = self.header_position(&first.prev_blockhash)? else {
+            return Ok(false);
+        };
+
+        let pow_limit = bitcoin::consensus::Params::new(self.network).pow_limit;
+        let mut prev_hash = first.prev_blockhash;
+        let mut entries = Vec::with_capacity(headers.len());
+        for header in headers {
+            let invalid = |reason: &str| StorageError::InvalidBlock {
+                hash: header.block_hash().to_string(),
+                reason: reason.to_string(),
+            };
+            if header.prev_blockhash != prev_hash {
+                return Err(invalid("bad-prevblk"));
+            }
+            let target = header.target();
+            if target > pow_limit || header.validate_pow(target).is_err() {
+                return Err(invalid("high-hash"));
+            }
+            chain_work = chain_work + header.work();
+            entries.push(HeaderEntry { header: *header, chain_work });
+            prev_hash = header.block_hash();
+        }
+
+        self.adopt_best_headers(parent_height + 1, &entries)?;
+        Ok(true)
+    }
+
+    /// Hashes down the best header chain from its tip to genesis, one per
+    /// block for the first ten and then doubling the step, as `getheaders` expects
+    fn header_locator(&self) -> StorageResult<Vec<bitcoin::BlockHash>> {
+        let Some((tip_height, _)) = self.storage.best_header_tip()? else {
+            return Ok(Vec::new());
+        };
+        let mut locator = Vec::new();
+        let (mut height, mut step) = (tip_height, 1);
+        loop {
+            if let Some(entry) = self.storage.get_best_header(height)? {
+                locator.push(entry.header.block_hash());
+            }
+            if height == 0 {
+                return Ok(locator);
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+    }
+}
+
+impl Handler<AcceptHeaders> for StorageActor {
+    type Result = Result<Option<ChainTip>, StorageError>;
+
+    fn handle(&mut self, msg: AcceptHeaders, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.atomically(|actor| actor.accept_headers(&msg.headers))? {
+            return Ok(None);
+        }
+        self.best_header()
+    }
+}
+
+impl Handler<GetBestHeader> for StorageActor {
+    type Result = Result<Option<ChainTip>, StorageError>;
+
+    fn handle(&mut self, _msg: GetBestHeader, _ctx: &mut Self::Context) -> Self::Result {
+        self.best_header()
+    }
+}
+
+impl Handler<GetHeaderHeight> for StorageActor {
+    type Result = Result<Option<u64>, StorageError>;
+
+    fn handle(&mut self, msg: GetHeaderHeight, _ctx: &mut Self::Context) -> Self::Result {
+        match self.storage.get_block_height(&BlockKey(msg.hash))? {
+            Some(height) => Ok(Some(height)),
+            None => self.storage.best_header_height(&msg.hash),
+        }
+    }
+}
+
+impl Handler<GetHeaderLocator> for StorageActor {
+    type Result = Result<Vec<bitcoin::BlockHash>, StorageError>;
+
+    fn handle(&mut self, _msg: GetHeaderLocator, _ctx: &mut Self::Context) -> Self::Result {
+        self.header_locator()
+    }
+}
+
+impl Handler<ConnectBlock> for StorageActor {
+    type Result = Result<UtxoDelta, StorageError>;
+
+    fn handle(&mut self, msg: ConnectBlock, _ctx: &mut Self::Context) -> Self::Result {
+        if self.storage.get_chain_state(&ChainStateKey::ConnectIntent)?.is_some() {
+            return Err(StorageError::Corruption {
+                component: "chain state: an earlier block connection did not finish, restart to recover".to_string(),
+            });
+        }
+
+        let block_hash = msg.block.block_hash();
+        let script_flags = self.script_flags(msg.height, &block_hash);
+        let delta = self.plan_connect(&msg.block, msg.height, script_flags)?;
+
+        // The block, its UTXO changes, indexes and the new tip land in one
+        // write, so a crash leaves either all of them or none
+        self.atomically(|actor| {
+            actor.storage.store_block(&BlockKey(block_hash), &bitcoin::consensus::serialize(&msg.block))?;
+            actor.apply_connect(&msg.block, msg.height, &delta)
+        })?;
+
+        info!("Connected block {} at height {} ({} created, {} spent)",
+              block_hash, msg.height, delta.created.len(), delta.spent.len());
+        Ok(delta)
+    }
+}
+
+impl Handler<StoreSideBlock> for StorageActor {
+    type Result = Result<SideBlockOutcome, StorageError>;
+
+    fn handle(&mut self, msg: StoreSideBlock, _ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = msg.block.block_hash();
+        if self.block_position(&block_hash)?.is_some() {
+            return Ok(SideBlockOutcome::AlreadyKnown);
+        }
+        let Some((parent_height, parent_work)) = self.block_position(&msg.block.header.prev_blockhash)? else {
+            return Ok(SideBlockOutcome::Orphan);
+        };
+        let height = parent_height + 1;
+        let chain_work = parent_work + msg.block.header.work();
+
+        self.storage.store_block(&BlockKey(block_hash), &bitcoin::consensus::serialize(&msg.block))?;
+        self.archive_stale_block(&msg.block, height, chain_work, StaleReason::SideChain)?;
+
+        let tip = self.load_tip()?.ok_or_else(|| StorageError::Corruption {
+            component: "chain tip".to_string(),
+        })?;
+        let tip_work = self.storage.get_header_entry(tip.height)?
+            .map(|entry| entry.chain_work)
+            .ok_or_else(|| StorageError::Corruption {
+                component: format!("header entry for height {}", tip.height),
+            })?;
+        if chain_work <= tip_work {
+            return Ok(SideBlockOutcome::Stale { height });
+        }
+
+        // Walk the side chain back to where it forks from the active chain
+        let mut connect = vec![(msg.block, height)];
+        let mut prev_hash = connect[0].0.header.prev_blockhash;
+        let fork_height = loop {
+            if let Some(height) = self.storage.get_block_height(&BlockKey(prev_hash))? {
+                break height;
+            }
+            let stale = self.load_stale_block(&prev_hash)?.ok_or_else(|| StorageError::Corruption {
+                component: format!("side chain block {}", prev_hash),
+            })?;
+            let block = self.load_block(&prev_hash)?.ok_or_else(|| StorageError::Corruption {
+                component: format!("block data for {}", prev_hash),
+            })?;
+            prev_hash = block.header.prev_blockhash;
+            connect.push((block, stale.height));
+        };
+        connect.reverse();
+
+        info!("Side chain tip {} at height {} has more work, reorganizing from height {}",
+              block_hash, height, fork_height);
+        Ok(SideBlockOutcome::Reorg { fork_height, connect })
+    }
+}
+
+impl StorageActor {
+    /// Undo the tip block's writes and make its parent the tip
+    fn disconnect_tip(&self) -> StorageResult<Block> {
+        let tip = self.load_tip()?.ok_or_else(|| StorageError::Corruption {
+            component: "chain tip".to_string(),
+        })?;
+        let prev_height = tip.height.checked_sub(1).ok_or_else(|| StorageError::Corruption {
+            component: "cannot disconnect the genesis block".to_string(),
+        })?;
+        let missing = |what: &str| StorageError::Corruption {
+            component: format!("{} for height {}", what, tip.height),
+        };
+
+        let block = self.load_block(&tip.hash)?.ok_or_else(|| missing("block data"))?;
+        let header_entry = self.storage.get_header_entry(tip.height)?.ok_or_else(|| missing("header entry"))?;
+        let prev_entry = self.storage.get_header_entry(prev_height)?.ok_or_else(|| missing("parent header entry"))?;
+        let delta: UtxoDelta = match self.storage.get_utxo_delta(tip.height)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?,
+            None => return Err(missing("UTXO delta")),
+        };
+
+        for created in &delta.created {
+            let txid: Txid = created.txid.parse()
+                .map_err(|e: bitcoin::hashes::hex::HexToArrayError| StorageError::Serialization(e.to_string()))?;
+            self.storage.delete_utxo(&UtxoKey::new(txid, created.vout))?;
+        }
+
+        // Coins created earlier in this same block were just removed above
+        for spent in &delta.spent {
+            let (Some(value), Some(script_hex), Some(height)) = (spent.value, &spent.script_pubkey, spent.height) else {
+                warn!("Cannot restore {}:{} on disconnect: coin was unknown when spent", spent.txid, spent.vout);
+                continue;
+            };
+            if height == tip.height {
+                continue;
+            }
+            let txid: Txid = spent.txid.parse()
+                .map_err(|e: bitcoin::hashes::hex::HexToArrayError| StorageError::Serialization(e.to_string()))?;
+            let entry = UtxoEntry {
+                height,
+                is_coinbase: spent.is_coinbase.unwrap_or(false),
+                output: TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: ScriptBuf::from_hex(script_hex)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?,
+                },
+            };
+            self.storage.store_utxo(&UtxoKey::new(txid, spent.vout), &entry.encode())?;
+        }
+
+        for tx in &block.txdata {
+            let key = TxKey(tx.txid());
+            if self.storage.get_tx_location(&key)?.is_some_and(|location| location.block_hash == tip.hash) {
+                self.storage.delete_tx_location(&key)?;
+            }
+        }
+
+        self.unrecord_block_stats(tip.height)?;
+        self.storage.delete_silent_payment_tweaks(tip.height)?;
+        if self.address_index {
+            self.update_address_index(&block, tip.height, &delta, false)?;
+        }
+        self.storage.delete_utxo_delta(tip.height)?;
+        self.storage.delete_header_entry(tip.height)?;
+        self.storage.delete_block_height(&BlockKey(tip.hash))?;
+        self.archive_stale_block(&block, tip.height, header_entry.chain_work, StaleReason::ReorgedOut)?;
+        self.advance_utxo_commitment(&block, tip.height, &delta, tip.hash, prev_entry.header.block_hash())?;
+
+        let new_tip = ChainTip {
+            hash: prev_entry.header.block_hash(),
+            height: prev_height,
+        };
+        self.storage.store_chain_state(&ChainStateKey::Tip, &encode_chain_tip(&new_tip))?;
+
+        info!("Disconnected block {} at height {}", tip.hash, tip.height);
+        Ok(block)
+    }
+}
+
+impl Handler<DisconnectTip> for StorageActor {
+    type Result = Result<Block, StorageError>;
+
+    fn handle(&mut self, _msg: DisconnectTip, _ctx: &mut Self::Context) -> Self::Result {
+        self.atomically(|actor| actor.disconnect_tip())
+    }
+}
+
+impl Handler<RecordReorg> for StorageActor {
+    type Result = Result<ReorgRecord, StorageError>;
+
+    fn handle(&mut self, msg: RecordReorg, _ctx: &mut Self::Context) -> Self::Result {
+        let seq = self.storage.get_recent_reorgs(1)?
+            .first()
+            .map(|(seq, _)| seq + 1)
+            .unwrap_or(0);
+        let record = ReorgRecord {
+            seq,
+            timestamp: chrono::Utc::now(),
+            old_tip: msg.old_tip.hash.to_string(),
+            old_height: msg.old_tip.height,
+            new_tip: msg.new_tip.hash.to_string(),
+            new_height: msg.new_tip.height,
+            fork_height: msg.fork_height,
+            disconnected: msg.disconnected.iter().map(|hash| hash.to_string()).collect(),
+            connected: msg.connected.iter().map(|hash| hash.to_string()).collect(),
+        };
+        let data = serde_json::to_vec(&record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_reorg(seq, &data)?;
+        Ok(record)
+    }
+}
+
+impl Handler<GetReorgs> for StorageActor {
+    type Result = Result<Vec<ReorgRecord>, StorageError>;
+
+    fn handle(&mut self, msg: GetReorgs, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.get_recent_reorgs(msg.limit)?
+            .into_iter()
+            .map(|(_, data)| serde_json::from_slice(&data)
+                .map_err(|e| StorageError::Serialization(e.to_string())))
+            .collect()
+    }
+}
+
+impl Handler<GetStaleBlocks> for StorageActor {
+    type Result = Result<Vec<StaleBlock>, StorageError>;
+
+    fn handle(&mut self, msg: GetStaleBlocks, _ctx: &mut Self::Context) -> Self::Result {
+        let mut blocks = self.storage.get_stale_blocks()?
+            .into_iter()
+            .map(|data| serde_json::from_slice::<StaleBlock>(&data)
+                .map_err(|e| StorageError::Serialization(e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        blocks.sort_by(|a, b| b.height.cmp(&a.height).then(b.archived_at.cmp(&a.archived_at)));
+        blocks.truncate(msg.limit);
+        Ok(blocks)
+    }
+}
+
+impl Handler<GetBlockAnalytics> for StorageActor {
+    type Result = Result<BlockAnalytics, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockAnalytics, _ctx: &mut Self::Context) -> Self::Result {
+        let mut blocks = Vec::new();
+        if let Some(tip) = self.load_tip()? {
+            let start = (tip.height + 1).saturating_sub(msg.count);
+            for height in start..=tip.height {
+                if let Some(stats) = self.load_block_stats(height)? {
+                    blocks.push(stats);
+                }
+            }
+        }
+
+        Ok(BlockAnalytics {
+            summary: RollingStats::from_blocks(&blocks),
+            blocks,
+        })
+    }
+}
+
+impl Handler<ComputeUtxoDistribution> for StorageActor {
+    type Result = ResponseFuture<Result<Option<UtxoDistribution>, StorageError>>;
+
+    fn handle(&mut self, _msg: ComputeUtxoDistribution, _ctx: &mut Self::Context) -> Self::Result {
+        let tip = match self.load_tip() {
+            Ok(Some(tip)) => tip,
+            Ok(None) => return Box::pin(async { Ok(None) }),
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let storage = self.storage.clone();
+
+        // A full UTXO set scan takes a while, so keep it off the actor
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut distribution = UtxoDistribution::new(tip.height, tip.hash.to_string());
+                storage.for_each_utxo(|_, entry| distribution.add(entry.height, entry.output.value.to_sat()))?;
+                Ok(Some(distribution))
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+impl Handler<GetUtxoSetInfo> for StorageActor {
+    type Result = ResponseFuture<Result<UtxoSetInfo, StorageError>>;
+
+    fn handle(&mut self, _msg: GetUtxoSetInfo, _ctx: &mut Self::Context) -> Self::Result {
+        let tip = match self.load_tip() {
+            Ok(Some(tip)) => tip,
+            Ok(None) => return Box::pin(async { Err(StorageError::Corruption { component: "chain tip".to_string() }) }),
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let commitment = match self.load_utxo_commitment() {
+            Ok(commitment) => commitment,
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let storage = self.storage.clone();
+
+        // Same full scan as ComputeUtxoDistribution, plus a MuHash finalization
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let (muhash, txouts, total_amount) = Self::scan_utxo_commitment(&storage)?;
+                let tip_after = match storage.get_chain_state(&ChainStateKey::Tip)? {
+                    Some(data) => Some(decode_chain_tip(&data)?),
+                    None => None,
+                };
+                let commitment_matches = match commitment {
+                    _ if tip_after.map(|after| after.hash) != Some(tip.hash) => None,
+                    Some(commitment) if commitment.block_hash == tip.hash => Some(commitment.muhash.same_set(&muhash)),
+                    _ => Some(false),
+                };
+                if commitment_matches == Some(false) {
+                    error!("UTXO set at height {} does not match its commitment", tip.height);
+                }
+                Ok(UtxoSetInfo {
+                    height: tip.height,
+                    best_block: tip.hash.to_string(),
+                    txouts,
+                    total_amount,
+                    muhash: muhash.digest_hex(),
+                    commitment_matches,
+                })
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+impl Handler<GetBlockHash> for StorageActor {
+    type Result = Result<Option<bitcoin::BlockHash>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockHash, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.storage.get_header_entry(msg.height)?.map(|entry| entry.header.block_hash()))
+    }
+}
+
+impl Handler<GetRawBlock> for StorageActor {
+    type Result = Result<Option<Vec<u8>>, StorageError>;
+
+    fn handle(&mut self, msg: GetRawBlock, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.get_block(&BlockKey(msg.hash))
+    }
+}
+
+impl Handler<GetBlockLocations> for StorageActor {
+    type Result = Result<Option<Vec<BlockLocation>>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockLocations, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(start) = self.storage.get_block_height(&BlockKey(msg.hash))? else {
+            return Ok(None);
+        };
+        let mut locations = Vec::new();
+        for height in start..start.saturating_add(msg.count.min(MAX_BLOCK_LOCATIONS)) {
+            let Some(entry) = self.storage.get_header_entry(height)? else {
+                break;
+            };
+            let key = BlockKey(entry.header.block_hash());
+            // Headers can run ahead of the blocks downloaded
+            let Some(length) = self.storage.get_block_size(&key)? else {
+                break;
+            };
+            locations.push(BlockLocation {
+                hash: key.0.to_string(),
+                height,
+                store: "rocksdb",
+                column_family: CF_BLOCKS,
+                key: key.encode().to_lower_hex_string(),
+                file: None,
+                offset: None,
+                length,
+            });
+        }
+        Ok(Some(locations))
+    }
+}
+
+impl Handler<GetBlockHeaderInfo> for StorageActor {
+    type Result = Result<Option<BlockHeaderInfo>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockHeaderInfo, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(height) = self.storage.get_block_height(&BlockKey(msg.hash))? else {
+            return Ok(None);
+        };
+        let (Some(entry), Some(tip)) = (self.storage.get_header_entry(height)?, self.load_tip()?) else {
+            return Ok(None);
+        };
+
+        let mut times = vec![entry.header.time];
+        for past in (height.saturating_sub(10)..height).rev() {
+            match self.storage.get_header_entry(past)? {
+                Some(past) => times.push(past.header.time),
+                None => break,
+            }
+        }
+        times.sort_unstable();
+
+        Ok(Some(BlockHeaderInfo {
+            header: entry.header,
+            height,
+            confirmations: tip.height.saturating_sub(height) + 1,
+            chain_work: entry.chain_work,
+            median_time: times[times.len() / 2],
+            next_hash: self.storage.get_header_entry(height + 1)?.map(|next| next.header.block_hash()),
+        }))
+    }
+}
+
+impl Handler<GetUtxos> for StorageActor {
+    type Result = Result<Vec<Option<UtxoEntry>>, StorageError>;
+
+    fn handle(&mut self, msg: GetUtxos, _ctx: &mut Self::Context) -> Self::Result {
+        msg.outpoints
+            .iter()
+            .map(|outpoint| match self.storage.get_utxo(&UtxoKey::from(*outpoint))? {
+                Some(data) => UtxoEntry::decode(&data).map(Some),
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+impl Handler<ScanUtxos> for StorageActor {
+    type Result = ResponseFuture<Result<Vec<(bitcoin::OutPoint, UtxoEntry)>, StorageError>>;
+
+    fn handle(&mut self, msg: ScanUtxos, _ctx: &mut Self::Context) -> Self::Result {
+        let storage = self.storage.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut coins = Vec::new();
+                storage.for_each_utxo(|outpoint, entry| {
+                    if msg.script_pubkeys.contains(&entry.output.script_pubkey) {
+                        coins.push((outpoint, entry));
+                    }
+                })?;
+                Ok(coins)
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+fn load_silent_payment_tweaks(storage: &Storage, height: u64) -> StorageResult<Option<Vec<(Txid, bitcoin::secp256k1::PublicKey)>>> {
+    match storage.get_silent_payment_tweaks(height)? {
+        Some(data) => crate::silentpayments::decode_tweaks(&data)
+            .map(Some)
+            .map_err(|e| StorageError::Corruption { component: e.to_string() }),
+        None => Ok(None),
+    }
+}
+
+impl Handler<GetSilentPaymentTweaks> for StorageActor {
+    type Result = Result<Option<Vec<(Txid, bitcoin::secp256k1::PublicKey)>>, StorageError>;
+
+    fn handle(&mut self, msg: GetSilentPaymentTweaks, _ctx: &mut Self::Context) -> Self::Result {
+        load_silent_payment_tweaks(&self.storage, msg.height)
+    }
+}
+
+impl Handler<ScanSilentPayments> for StorageActor {
+    type Result = ResponseFuture<Result<Vec<SilentPaymentMatch>, StorageError>>;
+
+    fn handle(&mut self, msg: ScanSilentPayments, _ctx: &mut Self::Context) -> Self::Result {
+        let tip = match self.load_tip() {
+            Ok(Some(tip)) => tip,
+            Ok(None) => return Box::pin(async { Ok(Vec::new()) }),
+            Err(e) => return Box::pin(async move { Err(e) }),
+        };
+        let storage = self.storage.clone();
+
+        // Only transactions with a stored tweak are read back from their blocks
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let end_height = msg.end_height.unwrap_or(tip.height).min(tip.height);
+                let mut matches = Vec::new();
+                for height in msg.start_height..=end_height {
+                    let Some(tweaks) = load_silent_payment_tweaks(&storage, height)? else {
+                        continue;
+                    };
+                    if tweaks.is_empty() {
+                        continue;
+                    }
+                    let Some(entry) = storage.get_header_entry(height)? else {
+                        continue;
+                    };
+                    let Some(data) = storage.get_block(&BlockKey(entry.header.block_hash()))? else {
+                        continue;
+                    };
+                    let block: Block = bitcoin::consensus::deserialize(&data)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+                    for (txid, tweak) in tweaks {
+                        let Some(tx) = block.txdata.iter().find(|tx| tx.txid() == txid) else {
+                            continue;
+                        };
+                        for detected in crate::silentpayments::scan_outputs(&msg.keys, &tweak, tx) {
+                            matches.push(SilentPaymentMatch {
+                                txid: txid.to_string(),
+                                vout: detected.vout,
+                                value: tx.output[detected.vout as usize].value.to_sat(),
+                                height,
+                                tweak: detected.tweak.to_lower_hex_string(),
+                                unspent: storage.get_utxo(&UtxoKey::new(txid, detected.vout))?.is_some(),
+                            });
+                        }
+                    }
+                }
+                Ok(matches)
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+impl Handler<GetStorageSize> for StorageActor {
+    type Result = Result<u64, StorageError>;
+
+    fn handle(&mut self, _msg: GetStorageSize, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.get_database_size()
+    }
+}
+
+impl Handler<ReadStorage> for StorageActor {
+    type Result = Result<crate::storage::remote::StorageReadResponse, StorageError>;
+
+    fn handle(&mut self, msg: ReadStorage, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.serve_read(&msg.0)
+    }
+}
+
+impl Handler<ExportStorage> for StorageActor {
+    type Result = Result<crate::storage::ColumnFamilyDump, StorageError>;
+
+    fn handle(&mut self, _msg: ExportStorage, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.export_column_families()
+    }
+}
+
+impl Handler<ImportStorage> for StorageActor {
+    type Result = Result<Option<ChainTip>, StorageError>;
+
+    fn handle(&mut self, msg: ImportStorage, ctx: &mut Self::Context) -> Self::Result {
+        self.storage.import_column_families(&msg.dump)?;
+        self.next_peer_event_seq = self.storage.last_peer_event_seq()?.map(|seq| seq + 1).unwrap_or(0);
+        self.seed_best_headers()?;
+        // A replay behind an earlier restore no longer applies
+        self.remove_background_validation();
+        let tip = self.load_tip()?;
+        match &tip {
+            Some(tip) => {
+                self.start_background_validation(tip)?;
+                self.schedule_background_validation(ctx);
+            }
+            None => self.storage.delete_chain_state(&ChainStateKey::SnapshotBase)?,
+        }
+        Ok(tip)
+    }
+}
+
+impl Handler<GetChainstates> for StorageActor {
+    type Result = Result<Vec<ChainstateInfo>, StorageError>;
+
+    fn handle(&mut self, _msg: GetChainstates, _ctx: &mut Self::Context) -> Self::Result {
+        let mut chainstates = Vec::new();
+        if let Some(background) = &self.background {
+            if let Some(tip) = background.tip {
+                chainstates.push(ChainstateInfo {
+                    tip,
+                    coins_tip_cache_bytes: background.storage.memtable_bytes(CF_UTXOS)?.unwrap_or(0),
+                    snapshot_blockhash: None,
+                    validated: true,
+                });
+            }
+        }
+        let Some(tip) = self.load_tip()? else {
+            return Ok(chainstates);
+        };
+        let snapshot_base = self.load_snapshot_base()?;
+        chainstates.push(ChainstateInfo {
+            tip,
+            coins_tip_cache_bytes: self.storage.memtable_bytes(CF_UTXOS)?.unwrap_or(0),
+            snapshot_blockhash: snapshot_base.as_ref().map(|(base, _)| base.hash),
+            validated: snapshot_base.is_none(),
+        });
+        Ok(chainstates)
+    }
+}
+
+impl Handler<GetBlockHeight> for StorageActor {
+    type Result = Result<Option<u64>, StorageError>;
+
+    fn handle(&mut self, msg: GetBlockHeight, _ctx: &mut Self::Context) -> Self::Result {
+        self.storage.get_block_height(&BlockKey(msg.hash))
+    }
+}
+
+impl Handler<GetDailyAnalytics> for StorageActor {
+    type Result = Result<Vec<DailyStats>, StorageError>;
+
+    fn handle(&mut self, msg: GetDailyAnalytics, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(tip) = self.load_tip()? else {
+            return Ok(Vec::new());
+        };
+        let Some(tip_stats) = self.load_block_stats(tip.height)? else {
+            return Ok(Vec::new());
+        };
+
+        let last_day = day_index(tip_stats.time);
+        let first_day = (last_day + 1).saturating_sub(msg.days);
+        let mut days = Vec::new();
+        for day in first_day..=last_day {
+            if let Some(stats) = self.load_daily_stats(day)? {
+                days.push(stats);
+            }
+        }
+        Ok(days)
+    }
+}
+
+impl Handler<GetFeeRateHistory> for StorageActor {
+    type Result = Result<FeeRateHistory, StorageError>;
+
+    fn handle(&mut self, msg: GetFeeRateHistory, _ctx: &mut Self::Context) -> Self::Result {
+        fn decode<T: serde::de::DeserializeOwned>(records: Vec<Vec<u8>>) -> StorageResult<Vec<T>> {
+            records.iter()
+                .map(|data| serde_json::from_slice(data).map_err(|e| StorageError::Serialization(e.to_string())))
+                .collect()
+        }
+        let (from, to) = (msg.from.max(0), msg.to.max(0));
+        let clamp = |time: i64| u32::try_from(time).unwrap_or(u32::MAX);
+        let blocks = decode(self.storage.get_block_fee_rates(clamp(from), clamp(to))?)?;
+        let mempool = decode(self.storage.get_mempool_fees(from as u64, to as u64)?)?;
+        Ok(FeeRateHistory { from: msg.from, to: msg.to, blocks, mempool })
+    }
+}
+
+impl Handler<RecordMempoolFee> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: RecordMempoolFee, _ctx: &mut Self::Context) -> Self::Result {
+        let data = serde_json::to_vec(&msg.sample)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_mempool_fee(msg.sample.time.max(0) as u64, &data)?;
+        if let Some(retention) = self.fee_history_retention {
+            let cutoff = msg.sample.time - retention.as_secs() as i64;
+            if cutoff > 0 {
+                self.storage.prune_fee_history_before(cutoff as u64)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Handler<RecordPeerEvent> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: RecordPeerEvent, _ctx: &mut Self::Context) -> Self::Result {
+        let seq = self.next_peer_event_seq;
+        let event = PeerTimelineEvent {
+            seq,
+            timestamp: chrono::Utc::now(),
+            peer_id: msg.peer_id,
+            address: msg.address,
+            kind: msg.kind,
+        };
+        let data = serde_json::to_vec(&event)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        self.storage.store_peer_event(seq, &data)?;
+        self.next_peer_event_seq += 1;
+
+        if self.next_peer_event_seq.is_multiple_of(PEER_TIMELINE_PRUNE_INTERVAL) {
+            self.prune_peer_timeline();
+        }
+        Ok(())
+    }
+}
+
+impl Handler<JournalEvent> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: JournalEvent, _ctx: &mut Self::Context) -> Self::Result {
+        let seq = msg.event.sequence;
+        let data = serde_json::to_vec(&msg.event)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_event(seq, &data)?;
+
+        if seq.is_multiple_of(EVENT_JOURNAL_PRUNE_INTERVAL) {
+            if let Err(e) = self.prune_event_journal() {
+                warn!("Failed to prune event journal: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Handler<GetEventJournal> for StorageActor {
+    type Result = Result<EventJournalPage, StorageError>;
+
+    fn handle(&mut self, msg: GetEventJournal, _ctx: &mut Self::Context) -> Self::Result {
+        let first_seq = self.storage.get_events(0, 1)?.first().map(|(seq, _)| *seq);
+        let last_seq = self.storage.last_event_seq()?;
+        let events = match msg.limit {
+            0 => Vec::new(),
+            limit => self.storage.get_events(msg.since_seq.saturating_add(1), limit)?
+                .into_iter()
+                .map(|(_, data)| serde_json::from_slice(&data).map_err(|e| StorageError::Serialization(e.to_string())))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(EventJournalPage { first_seq, last_seq, events })
+    }
+}
+
+impl Handler<GetDnsSeedRecord> for StorageActor {
+    type Result = Result<Option<crate::seeds::DnsSeedRecord>, StorageError>;
+
+    fn handle(&mut self, msg: GetDnsSeedRecord, _ctx: &mut Self::Context) -> Self::Result {
+        match self.storage.get_dns_seed(&msg.seed)? {
+            Some(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| StorageError::Serialization(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Handler<StoreDnsSeedRecord> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: StoreDnsSeedRecord, _ctx: &mut Self::Context) -> Self::Result {
+        let data = serde_json::to_vec(&msg.record)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        self.storage.store_dns_seed(&msg.seed, &data)
+    }
+}
+
+impl Handler<GetPeerTimeline> for StorageActor {
+    type Result = Result<Vec<PeerTimelineEvent>, StorageError>;
+
+    fn handle(&mut self, msg: GetPeerTimeline, _ctx: &mut Self::Context) -> Self::Result {
+        let mut events = Vec::new();
+        let mut from_seq = msg.after_seq.map(|seq| seq + 1).unwrap_or(0);
+
+        while events.len() < msg.limit {
+            let batch = self.storage.get_peer_events(from_seq, PEER_TIMELINE_SCAN_BATCH)?;
+            let exhausted = batch.len() < PEER_TIMELINE_SCAN_BATCH;
+
+            for (seq, data) in batch {
+                from_seq = seq + 1;
+                let event: PeerTimelineEvent = serde_json::from_slice(&data)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+                // Events are appended in time order, so nothing later can match
+                if msg.until.is_some_and(|until| event.timestamp > until) {
+                    return Ok(events);
+                }
+                if msg.since.is_some_and(|since| event.timestamp < since) {
+                    continue;
+                }
+                if msg.peer_id.as_ref().is_some_and(|peer_id| *peer_id != event.peer_id) {
+                    continue;
+                }
+
+                events.push(event);
+                if events.len() >= msg.limit {
+                    break;
+                }
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+impl Handler<GetUtxoDeltas> for StorageActor {
+    type Result = Result<Vec<UtxoDelta>, StorageError>;
+
+    fn handle(&mut self, msg: GetUtxoDeltas, _ctx: &mut Self::Context) -> Self::Result {
+        let mut deltas = Vec::new();
+        let mut height = msg.since_height + 1;
+
+        // Deltas exist for every height of the active chain, so stop at the first gap
+        while deltas.len() < msg.limit {
+            match self.storage.get_utxo_delta(height)? {
+                Some(data) => {
+                    let delta = serde_json::from_slice(&data)
+                        .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                    deltas.push(delta);
+                }
+                None => break,
+            }
+            height += 1;
+        }
+
+        Ok(deltas)
+    }
+}
+
+impl Handler<GetBackfillMissingBlock> for StorageActor {
+    type Result = Option<(u64, bitcoin::BlockHash)>;
+
+    fn handle(&mut self, _msg: GetBackfillMissingBlock, _ctx: &mut Self::Context) -> Self::Result {
+        self.backfill_missing_block
+    }
+}
+
+impl Handler<IndexFetchedBlock> for StorageActor {
+    type Result = Result<(), StorageError>;
+
+    fn handle(&mut self, msg: IndexFetchedBlock, _ctx: &mut Self::Context) -> Self::Result {
+        self.index_fetched_block(msg.height, &msg.block)
+    }
+}
+
+impl Handler<GetAddressTxs> for StorageActor {
+    type Result = ResponseFuture<Result<Vec<AddressTx>, StorageError>>;
+
+    fn handle(&mut self, msg: GetAddressTxs, _ctx: &mut Self::Context) -> Self::Result {
+        let storage = self.storage.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let script_hash = AddressKey::script_hash(&msg.script_pubkey);
+                let max_height = match msg.before_height {
+                    Some(0) => return Ok(Vec::new()),
+                    Some(height) => height - 1,
+                    None => u64::MAX,
+                };
+                let mut txs: Vec<AddressTx> = Vec::new();
+                let mut current = None;
+                // Entries of one transaction are adjacent, so one past the
+                // limit means the last kept transaction is complete
+                storage.for_each_address_entry(&script_hash, max_height, |key, entry| {
+                    if current != Some((key.height, key.txid)) {
+                        if txs.len() == msg.limit {
+                            return Ok(false);
+                        }
+                        current = Some((key.height, key.txid));
+                        let block_hash = storage.get_header_entry(key.height)?.map(|entry| entry.header.block_hash().to_string());
+                        txs.push(AddressTx { txid: key.txid.to_string(), height: key.height, block_hash, received: 0, sent: 0 });
+                    }
+                    let tx = txs.last_mut().expect("pushed above");
+                    match key.kind {
+                        AddressEntryKind::Funding => tx.received += entry.value,
+                        AddressEntryKind::Spending => tx.sent += entry.value,
+                    }
+                    Ok(true)
+                })?;
+                Ok(txs)
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+impl Handler<GetAddressUtxos> for StorageActor {
+    type Result = ResponseFuture<Result<Vec<AddressUtxo>, StorageError>>;
+
+    fn handle(&mut self, msg: GetAddressUtxos, _ctx: &mut Self::Context) -> Self::Result {
+        let storage = self.storage.clone();
+
+        // The UTXO set is the authority on what is still unspent
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let script_hash = AddressKey::script_hash(&msg.script_pubkey);
+                let mut utxos = Vec::new();
+                storage.for_each_address_entry(&script_hash, u64::MAX, |key, _| {
+                    if key.kind != AddressEntryKind::Funding {
+                        return Ok(true);
+                    }
+                    if let Some(data) = storage.get_utxo(&UtxoKey::new(key.txid, key.index))? {
+                        let coin = UtxoEntry::decode(&data)?;
+                        utxos.push(AddressUtxo {
+                            txid: key.txid.to_string(),
+                            vout: key.index,
+                            value: coin.output.value.to_sat(),
+                            height: coin.height,
+                            is_coinbase: coin.is_coinbase,
+                        });
+                    }
+                    Ok(utxos.len() < msg.limit)
+                })?;
+                Ok(utxos)
+            })
+            .await
+            .map_err(|e| StorageError::Task(e.to_string()))?
+        })
+    }
+}
+
+impl Handler<GetTxProof> for StorageActor {
+    type Result = Result<Option<TxProof>, StorageError>;
+
+    fn handle(&mut self, msg: GetTxProof, _ctx: &mut Self::Context) -> Self::Result {
+        let location = self.storage.get_tx_location(&TxKey(msg.txid))?;
+        let block_hash = match (msg.block_hash, location) {
+            (Some(hash), _) => hash,
+            (None, Some(location)) => location.block_hash,
+            (None, None) => return Ok(None),
+        };
+        let height = location
+            .filter(|location| location.block_hash == block_hash)
+            .map(|location| location.height);
+
+        match self.load_block(&block_hash)? {
+            Some(block) => Ok(build_tx_proof(&block, msg.txid, height)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Handler<VerifyTxProof> for StorageActor {
+    type Result = Result<TxProofVerification, StorageError>;
+
+    fn handle(&mut self, msg: VerifyTxProof, _ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = msg.merkle_block.header.block_hash();
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        let valid = msg.merkle_block.extract_matches(&mut matches, &mut indexes).is_ok();
+
+        Ok(TxProofVerification {
+            valid,
+            block_hash: block_hash.to_string(),
+            txids: if valid { matches.iter().map(|txid| txid.to_string()).collect() } else { Vec::new() },
+            block_known: self.storage.get_block(&BlockKey(block_hash))?.is_some(),
+        })
+    }
+}
+
+impl Handler<GetHeaders> for StorageActor {
+    type Result = Result<HeaderExport, StorageError>;
+
+    fn handle(&mut self, msg: GetHeaders, _ctx: &mut Self::Context) -> Self::Result {
+        let tip = match self.storage.get_chain_state(&ChainStateKey::Tip)? {
+            Some(data) => Some(decode_chain_tip(&data)?),
+            None => None,
+        };
+
+        let mut headers = Vec::new();
+        let mut height = msg.start_height;
+        while headers.len() < msg.count {
+            match self.storage.get_header_entry(height)? {
+                Some(entry) => headers.push(HeaderRecord {
+                    height,
+                    hash: entry.header.block_hash().to_string(),
+                    header: bitcoin::consensus::encode::serialize_hex(&entry.header),
+                    chain_work: bitcoin::hex::DisplayHex::to_lower_hex_string(&entry.chain_work.to_be_bytes()[..]),
+                }),
+                None => break,
+            }
+            height += msg.interval;
+        }
+
+        Ok(HeaderExport {
+            tip_height: tip.map(|tip| tip.height),
+            tip_hash: tip.map(|tip| tip.hash.to_string()),
+            headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold_branch(txid: Txid, branch: &[TxMerkleNode], mut index: usize) -> TxMerkleNode {
+        let mut node = TxMerkleNode::from_byte_array(txid.to_byte_array());
+        for sibling in branch {
+            let (left, right) = if index.is_multiple_of(2) { (node, *sibling) } else { (*sibling, node) };
+            let mut engine = TxMerkleNode::engine();
+            bitcoin::hashes::HashEngine::input(&mut engine, left.as_byte_array());
+            bitcoin::hashes::HashEngine::input(&mut engine, right.as_byte_array());
+            node = TxMerkleNode::from_engine(engine);
+            index /= 2;
+        }
+        node
+    }
+
+    #[test]
+    fn test_merkle_branch_folds_to_root() {
+        let txids: Vec<Txid> = (0u8..5).map(|i| Txid::from_byte_array([i; 32])).collect();
+        let root = bitcoin::merkle_tree::calculate_root(txids.iter().map(|txid| txid.to_raw_hash()))
+            .map(TxMerkleNode::from_raw_hash)
+            .unwrap();
+
+        for (index, txid) in txids.iter().enumerate() {
+            let branch = merkle_branch(&txids, index);
+            assert_eq!(branch.len(), 3);
+            assert_eq!(fold_branch(*txid, &branch, index), root);
+        }
+        assert!(merkle_branch(&txids[..1], 0).is_empty());
+    }
+
+    fn child_block(parent: &Block) -> Block {
+        let coinbase = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from(vec![0x51, 0x51]),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut { value: Amount::from_btc(50.0).unwrap(), script_pubkey: ScriptBuf::from(vec![0x51]) }],
+        };
+        let mut header = parent.header;
+        header.prev_blockhash = parent.block_hash();
+        header.time += 600;
+        Block { header, txdata: vec![coinbase] }
+    }
+
+    /// `header` with its nonce ground until it meets the regtest target
+    fn solved(mut header: bitcoin::block::Header) -> bitcoin::block::Header {
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn test_assume_valid_follows_the_best_header_chain() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let mut block1 = child_block(&genesis);
+        block1.header = solved(block1.header);
+        let mut block2 = child_block(&block1);
+        block2.header = solved(block2.header);
+        let mut other = child_block(&genesis);
+        other.header.time += 1;
+        other.header = solved(other.header);
+
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.validation.assume_valid_block = Some(block2.block_hash().to_string());
+        config.validation.assume_valid_height = Some(2);
+        let mut actor = StorageActor::new(&config);
+        let delta = actor.plan_connect(&genesis, 0, None).unwrap();
+        actor.apply_connect(&genesis, 0, &delta).unwrap();
+
+        // Nothing is known about the assume-valid block yet
+        assert!(actor.script_flags(1, &block1.block_hash()).is_some());
+
+        assert!(actor.accept_headers(&[block1.header, block2.header]).unwrap());
+        assert_eq!(actor.best_header().unwrap(), Some(ChainTip { hash: block2.block_hash(), height: 2 }));
+        assert_eq!(actor.header_locator().unwrap(), vec![block2.block_hash(), block1.block_hash(), genesis.block_hash()]);
+
+        // Its ancestors skip script checks before it has connected; other blocks do not
+        assert_eq!(actor.script_flags(1, &block1.block_hash()), None);
+        assert!(actor.script_flags(1, &other.block_hash()).is_some());
+
+        // Headers that do not link up are refused, ones with an unknown parent left alone
+        let refused = actor.accept_headers(&[block2.header, block1.header]);
+        assert!(matches!(refused, Err(StorageError::InvalidBlock { reason, .. }) if reason == "bad-prevblk"));
+        let mut unknown_parent = other.header;
+        unknown_parent.prev_blockhash = bitcoin::BlockHash::from_byte_array([9; 32]);
+        assert!(!actor.accept_headers(&[unknown_parent]).unwrap());
+    }
+
+    #[test]
+    fn test_interrupted_connection_rolls_forward() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block = child_block(&genesis);
+        {
+            let actor = StorageActor::new(&config);
+            let delta = actor.plan_connect(&genesis, 0, None).unwrap();
+            actor.apply_connect(&genesis, 0, &delta).unwrap();
+
+            // Crash right after the intent is written, as versions before atomic commits could
+            let delta = actor.plan_connect(&block, 1, None).unwrap();
+            assert_eq!(delta.created.len(), 1);
+            actor.storage.store_block(&BlockKey(block.block_hash()), &bitcoin::consensus::serialize(&block)).unwrap();
+            actor.storage.store_chain_state(&ChainStateKey::ConnectIntent, &serde_json::to_vec(&delta).unwrap()).unwrap();
+            assert_eq!(actor.load_tip().unwrap().unwrap().height, 0);
+        }
+
+        let actor = StorageActor::new(&config);
+        assert_eq!(actor.storage.get_chain_state(&ChainStateKey::ConnectIntent).unwrap(), None);
+        let tip = actor.load_tip().unwrap().unwrap();
+        assert_eq!((tip.hash, tip.height), (block.block_hash(), 1));
+        let coinbase = UtxoKey::new(block.txdata[0].txid(), 0);
+        assert!(actor.storage.get_utxo(&coinbase).unwrap().is_some());
+
+        // Rolling forward again leaves the daily aggregate alone
+        let day = day_index(block.header.time);
+        let daily = actor.load_daily_stats(day).unwrap();
+        assert!(daily.is_some());
+        let delta = actor.plan_connect(&block, 1, None).unwrap();
+        actor.apply_connect(&block, 1, &delta).unwrap();
+        assert_eq!(actor.load_daily_stats(day).unwrap(), daily);
+    }
+
+    #[test]
+    fn test_connect_rejects_invalid_spends() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block1 = child_block(&genesis);
+        let actor = StorageActor::new(&config);
+        for (block, height) in [(&genesis, 0), (&block1, 1)] {
+            let delta = actor.plan_connect(block, height, None).unwrap();
+            actor.apply_connect(block, height, &delta).unwrap();
+        }
+
+        let spending = |previous_output: OutPoint, value: u64| {
+            let mut block = child_block(&block1);
+            block.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x52, 0x52]);
+            block.txdata.push(bitcoin::Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![bitcoin::TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: bitcoin::Sequence::MAX,
+                    witness: bitcoin::Witness::new(),
+                }],
+                output: vec![TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::from(vec![0x51]) }],
+            });
+            block
+        };
+        let reason = |block: &Block, height| match actor.plan_connect(block, height, None) {
+            Err(StorageError::InvalidBlock { reason, .. }) => reason,
+            other => panic!("expected an invalid block, got {:?}", other.map(|delta| delta.height)),
+        };
+
+        let coinbase = OutPoint { txid: block1.txdata[0].txid(), vout: 0 };
+        let missing = OutPoint { txid: Txid::from_byte_array([7; 32]), vout: 0 };
+        assert_eq!(reason(&spending(missing, 1_000), 2), "bad-txns-inputs-missingorspent");
+        assert_eq!(reason(&spending(coinbase, 1_000), 2), "bad-txns-premature-spend-of-coinbase");
+        assert_eq!(reason(&spending(coinbase, 5_000_000_001), 101), "bad-txns-in-belowout");
+        let mut greedy = spending(coinbase, 4_900_000_000);
+        greedy.txdata[0].output[0].value = Amount::from_sat(5_100_000_001);
+        assert_eq!(reason(&greedy, 101), "bad-cb-amount");
+        let mut double_spend = spending(coinbase, 1_000);
+        let mut second = double_spend.txdata[1].clone();
+        second.output[0].value = Amount::from_sat(2_000);
+        double_spend.txdata.push(second);
+        assert_eq!(reason(&double_spend, 101), "bad-txns-inputs-missingorspent");
+        assert!(actor.plan_connect(&spending(coinbase, 4_900_000_000), 101, None).is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_height_index_follows_connect_and_disconnect() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block = child_block(&genesis);
+        let actor = StorageActor::new(&config);
+        for (block, height) in [(&genesis, 0), (&block, 1)] {
+            // Disconnecting reads the block back
+            actor.storage.store_block(&BlockKey(block.block_hash()), &bitcoin::consensus::serialize(block)).unwrap();
+            let delta = actor.plan_connect(block, height, None).unwrap();
+            actor.apply_connect(block, height, &delta).unwrap();
+        }
+        let addr = actor.start();
+        assert_eq!(addr.send(GetBlockHash { height: 1 }).await.unwrap().unwrap(), Some(block.block_hash()));
+        assert_eq!(addr.send(GetBlockHash { height: 2 }).await.unwrap().unwrap(), None);
+
+        addr.send(DisconnectTip).await.unwrap().unwrap();
+        assert_eq!(addr.send(GetBlockHash { height: 1 }).await.unwrap().unwrap(), None);
+        assert_eq!(addr.send(GetBlockHash { height: 0 }).await.unwrap().unwrap(), Some(genesis.block_hash()));
+    }
+
+    #[actix_rt::test]
+    async fn test_restored_snapshot_is_marked_unvalidated() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("source");
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let source = StorageActor::new(&config);
+        let delta = source.plan_connect(&genesis, 0, None).unwrap();
+        source.apply_connect(&genesis, 0, &delta).unwrap();
+        let source = source.start();
+        let chainstates = source.send(GetChainstates).await.unwrap().unwrap();
+        assert_eq!(chainstates.len(), 1);
+        assert!(chainstates[0].validated && chainstates[0].snapshot_blockhash.is_none());
+        let dump = source.send(ExportStorage).await.unwrap().unwrap();
+
+        config.storage.rocks_db_path = temp_dir.path().join("restored");
+        let restored = StorageActor::new(&config).start();
+        restored.send(ImportStorage { dump }).await.unwrap().unwrap();
+        let chainstates = restored.send(GetChainstates).await.unwrap().unwrap();
+        let active = chainstates.last().unwrap();
+        assert_eq!(active.snapshot_blockhash, Some(genesis.block_hash()));
+        assert!(!active.validated);
+    }
+
+    /// Blocks on top of `parent` with distinct coinbases, each solved
+    fn solved_chain(parent: &Block, count: u8) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for height in 1..=count {
+            let mut block = child_block(blocks.last().unwrap_or(parent));
+            block.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x01, height]);
+            block.header.merkle_root = block.compute_merkle_root().unwrap();
+            block.header = solved(block.header);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    /// A node with genesis and three blocks connected, as a restore leaves it
+    fn restored_node(config: &Config) -> (StorageActor, Vec<Block>) {
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let actor = StorageActor::new(config);
+        let delta = actor.plan_connect(&genesis, 0, None).unwrap();
+        actor.apply_connect(&genesis, 0, &delta).unwrap();
+        let blocks = solved_chain(&genesis, 3);
+        for (height, block) in (1..).zip(&blocks) {
+            let delta = actor.plan_connect(block, height, None).unwrap();
+            actor.apply_connect(block, height, &delta).unwrap();
+        }
+        (actor, blocks)
+    }
+
+    #[test]
+    fn test_background_validation_marks_a_restored_snapshot_validated() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.storage.background_validation_blocks_per_sec = 2;
+        let (mut actor, blocks) = restored_node(&config);
+        let base = ChainTip { hash: blocks[2].block_hash(), height: 3 };
+        actor.start_background_validation(&base).unwrap();
+        assert!(temp_dir.path().join("background_chainstate").exists());
+
+        // Genesis and the first block
+        actor.validate_in_background();
+        let background = actor.background.as_ref().unwrap();
+        assert_eq!(background.tip, Some(ChainTip { hash: blocks[0].block_hash(), height: 1 }));
+        assert!(actor.load_snapshot_base().unwrap().is_some());
+
+        actor.validate_in_background();
+        assert!(actor.background.is_none());
+        assert!(actor.load_snapshot_base().unwrap().is_none());
+        assert!(!temp_dir.path().join("background_chainstate").exists());
+    }
+
+    #[test]
+    fn test_background_validation_rejects_a_mismatched_utxo_set() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let (mut actor, blocks) = restored_node(&config);
+        // A snapshot missing a coin its blocks created
+        actor.storage.delete_utxo(&UtxoKey::new(blocks[0].txdata[0].txid(), 0)).unwrap();
+        let base = ChainTip { hash: blocks[2].block_hash(), height: 3 };
+        actor.start_background_validation(&base).unwrap();
+
+        actor.validate_in_background();
+        let background = actor.background.as_ref().unwrap();
+        assert!(background.failed);
+        assert_eq!(background.tip, Some(base));
+        assert!(actor.load_snapshot_base().unwrap().is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_address_index_follows_connect_and_disconnect() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.storage.address_index = true;
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block1 = child_block(&genesis);
+        // The coinbase matures a hundred blocks later; the blocks in between pay elsewhere
+        let mut chain = vec![genesis.clone(), block1.clone()];
+        for height in 2..=100u8 {
+            let mut filler = child_block(chain.last().unwrap());
+            filler.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x01, height]);
+            filler.txdata[0].output[0].script_pubkey = ScriptBuf::from(vec![0x52]);
+            chain.push(filler);
+        }
+        let mut block2 = child_block(chain.last().unwrap());
+        block2.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x52, 0x52]);
+        let script_pubkey = ScriptBuf::from(vec![0x51]);
+        block2.txdata.push(bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint { txid: block1.txdata[0].txid(), vout: 0 },
+                script_sig: ScriptBuf::new(),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![TxOut { value: Amount::from_btc(49.0).unwrap(), script_pubkey: script_pubkey.clone() }],
+        });
+        chain.push(block2.clone());
+
+        let actor = StorageActor::new(&config);
+        for (height, block) in chain.iter().enumerate() {
+            actor.storage.store_block(&BlockKey(block.block_hash()), &bitcoin::consensus::serialize(block)).unwrap();
+            let delta = actor.plan_connect(block, height as u64, None).unwrap();
+            actor.apply_connect(block, height as u64, &delta).unwrap();
+        }
+        let addr = actor.start();
+        let txs = |before_height, limit| addr.send(GetAddressTxs { script_pubkey: script_pubkey.clone(), before_height, limit });
+        let utxos = || addr.send(GetAddressUtxos { script_pubkey: script_pubkey.clone(), limit: 10 });
+
+        let history = txs(None, 10).await.unwrap().unwrap();
+        assert_eq!(history.iter().map(|tx| tx.height).collect::<Vec<_>>(), vec![101, 101, 1]);
+        let spend = history.iter().find(|tx| tx.txid == block2.txdata[1].txid().to_string()).unwrap();
+        assert_eq!((spend.received, spend.sent), (49 * 100_000_000, 50 * 100_000_000));
+        assert_eq!(history[2].block_hash, Some(block1.block_hash().to_string()));
+        assert_eq!(txs(None, 1).await.unwrap().unwrap().len(), 1);
+        let older = txs(Some(101), 10).await.unwrap().unwrap();
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].txid, block1.txdata[0].txid().to_string());
+        assert_eq!(utxos().await.unwrap().unwrap().len(), 2);
+
+        // The spend is forgotten and the coin it consumed comes back
+        addr.send(DisconnectTip).await.unwrap().unwrap();
+        assert_eq!(txs(None, 10).await.unwrap().unwrap().len(), 1);
+        let unspent = utxos().await.unwrap().unwrap();
+        assert_eq!(unspent.len(), 1);
+        assert_eq!((unspent[0].height, unspent[0].is_coinbase), (1, true));
+    }
+
+    #[actix_rt::test]
+    async fn test_address_index_backfills_stored_blocks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        config.storage.index_backfill_blocks_per_sec = 2;
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block1 = child_block(&genesis);
+        let mut block2 = child_block(&block1);
+        block2.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x52, 0x52]);
+        {
+            let actor = StorageActor::new(&config);
+            for (block, height) in [(&genesis, 0), (&block1, 1), (&block2, 2)] {
+                actor.storage.store_block(&BlockKey(block.block_hash()), &bitcoin::consensus::serialize(block)).unwrap();
+                let delta = actor.plan_connect(block, height, None).unwrap();
+                actor.apply_connect(block, height, &delta).unwrap();
+            }
+        }
+
+        // Enabled afterwards, the index fills in two blocks per tick
+        config.storage.address_index = true;
+        let mut actor = StorageActor::new(&config);
+        assert_eq!(actor.address_backfill, Some((0, 3)));
+        actor.backfill_address_index();
+        assert_eq!(actor.address_backfill, Some((2, 3)));
+        actor.backfill_address_index();
+        assert_eq!(actor.address_backfill, None);
+
+        let script_pubkey = block1.txdata[0].output[0].script_pubkey.clone();
+        let addr = actor.start();
+        let history = addr.send(GetAddressTxs { script_pubkey, before_height: None, limit: 10 }).await.unwrap().unwrap();
+        assert_eq!(history.iter().map(|tx| tx.height).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[actix_rt::test]
+    async fn test_address_index_backfill_indexes_fetched_blocks_without_storing_them() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block1 = child_block(&genesis);
+        let mut block2 = child_block(&block1);
+        block2.txdata[0].input[0].script_sig = ScriptBuf::from(vec![0x52, 0x52]);
+        {
+            let actor = StorageActor::new(&config);
+            for (block, height) in [(&genesis, 0), (&block1, 1), (&block2, 2)] {
+                // Block 1 is not stored here
+                if height != 1 {
+                    actor.storage.store_block(&BlockKey(block.block_hash()), &bitcoin::consensus::serialize(block)).unwrap();
+                }
+                let delta = actor.plan_connect(block, height, None).unwrap();
+                actor.apply_connect(block, height, &delta).unwrap();
+            }
+        }
+
+        config.storage.address_index = true;
+        let mut actor = StorageActor::new(&config);
+        actor.backfill_address_index();
+        assert_eq!(actor.address_backfill, Some((1, 3)));
+        assert_eq!(actor.backfill_missing_block, Some((1, block1.block_hash())));
+        actor.backfill_address_index();
+        assert_eq!(actor.address_backfill, Some((1, 3)));
+
+        // Only the block the backfill waits for is taken
+        assert!(actor.index_fetched_block(1, &block2).is_err());
+        actor.index_fetched_block(1, &block1).unwrap();
+        assert_eq!(actor.backfill_missing_block, None);
+        assert_eq!(actor.address_backfill, Some((2, 3)));
+        assert_eq!(actor.load_block(&block1.block_hash()).unwrap(), None);
+        actor.backfill_address_index();
+        assert_eq!(actor.address_backfill, None);
+
+        let script_pubkey = block1.txdata[0].output[0].script_pubkey.clone();
+        let addr = actor.start();
+        let history = addr.send(GetAddressTxs { script_pubkey, before_height: None, limit: 10 }).await.unwrap().unwrap();
+        assert_eq!(history.iter().map(|tx| tx.height).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_utxo_commitment_follows_the_tip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let block = child_block(&genesis);
+        let actor = StorageActor::new(&config);
+        assert_eq!(actor.load_utxo_commitment().unwrap().unwrap().block_hash, bitcoin::BlockHash::all_zeros());
+        for (block, height) in [(&genesis, 0), (&block, 1)] {
+            let delta = actor.plan_connect(block, height, None).unwrap();
+            actor.apply_connect(block, height, &delta).unwrap();
+        }
+
+        let commitment = actor.load_utxo_commitment().unwrap().unwrap();
+        assert_eq!(commitment.block_hash, block.block_hash());
+        let (scanned, txouts, _) = StorageActor::scan_utxo_commitment(&actor.storage).unwrap();
+        assert_eq!(txouts, 1);
+        assert!(commitment.muhash.same_set(&scanned));
+
+        // A commitment left behind by the tip is rebuilt from the UTXO set on startup
+        let stale = UtxoCommitment { block_hash: genesis.block_hash(), muhash: MuHash3072::new() };
+        actor.storage.store_chain_state(&ChainStateKey::UtxoCommitment, &stale.encode()).unwrap();
+        drop(actor);
+        let actor = StorageActor::new(&config);
+        let rebuilt = actor.load_utxo_commitment().unwrap().unwrap();
+        assert_eq!(rebuilt.block_hash, block.block_hash());
+        assert!(rebuilt.muhash.same_set(&scanned));
+    }
+}
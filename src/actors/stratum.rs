@@ -0,0 +1,480 @@
+use actix::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::time::Duration;
+use bitcoin::hex::DisplayHex;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{Config, StratumConfig};
+use crate::error::StratumError;
+use crate::mining::fetch_template;
+use crate::mining::CoinbaseSpec;
+use crate::stratum::{coinbase_spec, network_difficulty, ShareOutcome, StratumJob, Submission};
+use crate::stratum::{EXTRANONCE1_SIZE, VERSION_ROLLING_MASK};
+use super::instrument::InstrumentedAddr;
+use super::chain::ChainActor;
+use super::mempool::MempoolActor;
+use super::storage::StorageActor;
+use super::{GetChainTip, GetStratumWorkers, Shutdown, StoreBlock, StratumRequest, StratumSessionClosed, StratumSessionOpened};
+use super::StratumWorkerStats;
+
+/// Jobs kept around for late submissions after a newer job was sent
+const MAX_ACTIVE_JOBS: usize = 8;
+
+/// Longest request line accepted from a miner
+const MAX_LINE_BYTES: u64 = 16 * 1024;
+
+const TIP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct StratumSession {
+    peer: SocketAddr,
+    sender: mpsc::UnboundedSender<String>,
+    extranonce1: [u8; EXTRANONCE1_SIZE],
+    subscribed: bool,
+    workers: HashSet<String>,
+    version_mask: Option<u32>,
+}
+
+/// Serves Stratum v1 jobs built from block templates to directly connected miners
+pub struct StratumActor {
+    config: StratumConfig,
+    network: bitcoin::Network,
+    coinbase: Option<CoinbaseSpec>,
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    chain_actor: Addr<ChainActor>,
+    jobs: VecDeque<StratumJob>,
+    next_job_id: u64,
+    sessions: HashMap<u64, StratumSession>,
+    next_session_id: u64,
+    workers: HashMap<String, StratumWorkerStats>,
+    refreshing: bool,
+}
+
+impl StratumActor {
+    pub fn new(
+        config: &Config,
+        storage_actor: Addr<StorageActor>,
+        mempool_actor: Addr<MempoolActor>,
+        chain_actor: Addr<ChainActor>,
+    ) -> Self {
+        let network = config.network.to_bitcoin_network();
+        let coinbase = if config.stratum.enabled {
+            match coinbase_spec(config) {
+                Ok(spec) => Some(spec),
+                Err(e) => {
+                    error!("Stratum disabled: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        info!("Stratum actor initialized");
+        Self {
+            config: config.stratum.clone(),
+            network,
+            coinbase,
+            storage_actor,
+            mempool_actor,
+            chain_actor,
+            jobs: VecDeque::new(),
+            next_job_id: 1,
+            sessions: HashMap::new(),
+            next_session_id: 1,
+            workers: HashMap::new(),
+            refreshing: false,
+        }
+    }
+
+    /// Build a new job from the current template and push it to subscribed miners
+    fn refresh_job(&mut self, ctx: &mut Context<Self>, clean_jobs: bool) {
+        if self.refreshing || self.coinbase.is_none() {
+            return;
+        }
+        self.refreshing = true;
+
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+        let network = self.network;
+        ctx.spawn(
+            async move { fetch_template(&storage_actor, &mempool_actor, network).await }
+                .into_actor(self)
+                .map(move |result, actor, _ctx| {
+                    actor.refreshing = false;
+                    let template = match result {
+                        Ok(Some(template)) => template,
+                        Ok(None) => return,
+                        Err(e) => {
+                            warn!("Failed to build stratum job: {}", e);
+                            return;
+                        }
+                    };
+                    let Some(coinbase) = actor.coinbase.as_ref() else {
+                        return;
+                    };
+
+                    let id = format!("{:x}", actor.next_job_id);
+                    actor.next_job_id += 1;
+                    let job = StratumJob::new(id, template, coinbase);
+                    // A new tip invalidates all older work
+                    let clean_jobs = clean_jobs
+                        || actor.jobs.back().is_none_or(|last| last.previous_block_hash() != job.previous_block_hash());
+                    if clean_jobs {
+                        actor.jobs.clear();
+                    }
+                    debug!("Stratum job {} at height {}", job.id, job.template.height);
+
+                    let notify = notification("mining.notify", job.notify_params(clean_jobs));
+                    actor.jobs.push_back(job);
+                    while actor.jobs.len() > MAX_ACTIVE_JOBS {
+                        actor.jobs.pop_front();
+                    }
+                    for session in actor.sessions.values().filter(|session| session.subscribed) {
+                        let _ = session.sender.send(notify.clone());
+                    }
+                }),
+        );
+    }
+
+    fn poll_tip(&mut self, ctx: &mut Context<Self>) {
+        let current = self.jobs.back().map(|job| job.previous_block_hash());
+        let storage_actor = self.storage_actor.clone();
+        ctx.spawn(
+            async move { storage_actor.send_traced(GetChainTip).await }
+                .into_actor(self)
+                .map(move |result, actor, ctx| {
+                    if let Ok(Ok(Some(tip))) = result {
+                        if current != Some(tip.hash) {
+                            actor.refresh_job(ctx, true);
+                        }
+                    }
+                }),
+        );
+    }
+
+    /// Share target for this job, never above what a block needs so regtest blocks always count
+    fn share_difficulty(&self, job: &StratumJob) -> f64 {
+        self.config.share_difficulty.min(network_difficulty(job.template.bits))
+    }
+
+    fn handle_line(&mut self, session_id: u64, line: &str, ctx: &mut Context<Self>) -> Option<String> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("Ignoring malformed stratum line from session {}: {}", session_id, e);
+                return None;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or_else(|| json!([]));
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+
+        let result = match method {
+            "mining.subscribe" => self.subscribe(session_id),
+            "mining.authorize" => self.authorize(session_id, &params),
+            "mining.configure" => Ok(self.configure(session_id, &params)),
+            "mining.extranonce.subscribe" => Ok(json!(true)),
+            "mining.submit" => self.submit(session_id, &params, ctx),
+            other => Err(StratumError::Malformed(format!("Unknown method {}", other))),
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "id": id, "result": result, "error": null }),
+            Err(e) => json!({ "id": id, "result": null, "error": [e.code(), e.to_string(), null] }),
+        };
+
+        // Work goes out after the subscribe response so the miner knows its extranonce first
+        if method == "mining.subscribe" {
+            if let Some(session) = self.sessions.get(&session_id) {
+                let _ = session.sender.send(response.to_string());
+                if let Some(job) = self.jobs.back() {
+                    let _ = session.sender.send(notification("mining.set_difficulty", json!([self.share_difficulty(job)])));
+                    let _ = session.sender.send(notification("mining.notify", job.notify_params(true)));
+                }
+            }
+            return None;
+        }
+        Some(response.to_string())
+    }
+
+    fn subscribe(&mut self, session_id: u64) -> Result<Value, StratumError> {
+        let session = self.sessions.get_mut(&session_id).ok_or(StratumError::NotSubscribed)?;
+        session.subscribed = true;
+        let subscription = format!("{:x}", session_id);
+        Ok(json!([
+            [["mining.set_difficulty", subscription], ["mining.notify", subscription]],
+            session.extranonce1.to_lower_hex_string(),
+            self.config.extranonce2_size,
+        ]))
+    }
+
+    fn authorize(&mut self, session_id: u64, params: &Value) -> Result<Value, StratumError> {
+        let worker = params.get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| StratumError::Malformed("missing worker".to_string()))?;
+        let session = self.sessions.get_mut(&session_id).ok_or(StratumError::NotSubscribed)?;
+        // Solo mining: any worker name is accepted and only used for stats
+        info!("Stratum worker {} authorized from {}", worker, session.peer);
+        session.workers.insert(worker.to_string());
+        self.workers.entry(worker.to_string()).or_insert_with(|| StratumWorkerStats {
+            worker: worker.to_string(),
+            ..Default::default()
+        });
+        Ok(json!(true))
+    }
+
+    /// BIP310 version rolling negotiation
+    fn configure(&mut self, session_id: u64, params: &Value) -> Value {
+        let extensions = params.get(0).and_then(Value::as_array).cloned().unwrap_or_default();
+        if !extensions.iter().any(|extension| extension == "version-rolling") {
+            return json!({});
+        }
+
+        let requested = params.get(1)
+            .and_then(|options| options.get("version-rolling.mask"))
+            .and_then(Value::as_str)
+            .and_then(|mask| u32::from_str_radix(mask, 16).ok())
+            .unwrap_or(VERSION_ROLLING_MASK);
+        let mask = requested & VERSION_ROLLING_MASK;
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            session.version_mask = Some(mask);
+        }
+        json!({ "version-rolling": true, "version-rolling.mask": format!("{:08x}", mask) })
+    }
+
+    fn submit(&mut self, session_id: u64, params: &Value, ctx: &mut Context<Self>) -> Result<Value, StratumError> {
+        let submission = Submission::from_params(params)?;
+        let session = self.sessions.get(&session_id).ok_or(StratumError::NotSubscribed)?;
+        if !session.subscribed {
+            return Err(StratumError::NotSubscribed);
+        }
+        if !session.workers.contains(&submission.worker) {
+            return Err(StratumError::Unauthorized);
+        }
+        let extranonce1 = session.extranonce1;
+        let version_mask = session.version_mask;
+
+        let outcome = match self.jobs.iter().position(|job| job.id == submission.job_id) {
+            Some(index) => {
+                let share_difficulty = self.share_difficulty(&self.jobs[index]);
+                self.jobs[index].check_submission(&submission, &extranonce1, version_mask, share_difficulty)
+            }
+            None => Err(StratumError::JobNotFound),
+        };
+
+        let stats = self.workers.entry(submission.worker.clone()).or_insert_with(|| StratumWorkerStats {
+            worker: submission.worker.clone(),
+            ..Default::default()
+        });
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                stats.shares_rejected += 1;
+                crate::metrics::record_stratum_share("rejected");
+                debug!("Rejected share from {}: {}", submission.worker, e);
+                return Err(e);
+            }
+        };
+
+        let difficulty = match &outcome {
+            ShareOutcome::Share { difficulty } | ShareOutcome::Block { difficulty, .. } => *difficulty,
+        };
+        stats.shares_accepted += 1;
+        stats.best_difficulty = stats.best_difficulty.max(difficulty);
+        stats.last_share_at = Some(chrono::Utc::now());
+        crate::metrics::record_stratum_share("accepted");
+
+        if let ShareOutcome::Block { block, .. } = outcome {
+            stats.blocks_found += 1;
+            self.submit_block(*block, submission.worker, ctx);
+        }
+        Ok(json!(true))
+    }
+
+    /// Hand a solved block to the chain actor, the same path blocks from peers take
+    fn submit_block(&mut self, block: bitcoin::Block, worker: String, ctx: &mut Context<Self>) {
+        let block_hash = block.block_hash();
+        info!("Stratum worker {} found block {}", worker, block_hash);
+        crate::metrics::record_stratum_block_found();
+
+        let chain_actor = self.chain_actor.clone();
+        ctx.spawn(
+            async move { chain_actor.send_traced(StoreBlock { block }).await }
+                .into_actor(self)
+                .map(move |result, actor, ctx| {
+                    match result {
+                        Ok(Ok(())) => info!("Submitted stratum block {}", block_hash),
+                        Ok(Err(e)) => error!("Stratum block {} rejected: {}", block_hash, e),
+                        Err(e) => error!("Failed to submit stratum block {}: {}", block_hash, e),
+                    }
+                    actor.refresh_job(ctx, true);
+                }),
+        );
+    }
+}
+
+fn notification(method: &str, params: Value) -> String {
+    json!({ "id": null, "method": method, "params": params }).to_string()
+}
+
+async fn serve_connection(stream: TcpStream, peer: SocketAddr, actor: Addr<StratumActor>) {
+    let (reader, mut writer) = stream.into_split();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let Ok(session_id) = actor.send(StratumSessionOpened { peer, sender: sender.clone() }).await else {
+        return;
+    };
+
+    // Writes both responses and notifications queued by the actor
+    actix::spawn(async move {
+        while let Some(line) = receiver.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err() || writer.write_all(b"\n").await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_LINE_BYTES).read_line(&mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) if !line.ends_with('\n') => {
+                warn!("Dropping stratum connection {}: request line too long", peer);
+                break;
+            }
+            Ok(_) => {}
+        }
+        let request = line.trim();
+        if request.is_empty() {
+            continue;
+        }
+
+        match actor.send(StratumRequest { session_id, line: request.to_string() }).await {
+            Ok(Some(response)) => {
+                if sender.send(response).is_err() {
+                    break;
+                }
+            }
+            Ok(None) => {}
+            Err(_) => break,
+        }
+    }
+
+    actor.do_send(StratumSessionClosed { session_id });
+}
+
+impl Actor for StratumActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if self.coinbase.is_none() {
+            return;
+        }
+        info!("Stratum actor started");
+
+        let address = format!("{}:{}", self.config.host, self.config.port);
+        let actor = ctx.address();
+        // On the actor's context so the listener goes away when the actor stops
+        ctx.spawn(async move {
+            let listener = match TcpListener::bind(&address).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Failed to bind stratum listener on {}: {}", address, e);
+                    return;
+                }
+            };
+            info!("Stratum server listening on {}", address);
+
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        actix::spawn(serve_connection(stream, peer, actor.clone()));
+                    }
+                    Err(e) => warn!("Failed to accept stratum connection: {}", e),
+                }
+            }
+        }.into_actor(self));
+
+        self.refresh_job(ctx, true);
+        ctx.run_interval(TIP_POLL_INTERVAL, |actor, ctx| actor.poll_tip(ctx));
+        if self.config.job_refresh_secs > 0 {
+            ctx.run_interval(Duration::from_secs(self.config.job_refresh_secs), |actor, ctx| {
+                actor.refresh_job(ctx, false)
+            });
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!("Stratum actor stopped");
+    }
+}
+
+impl Handler<Shutdown> for StratumActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        // Dropping the senders ends each session's writer; readers give up on
+        // their next request once the actor is gone
+        self.sessions.clear();
+        crate::metrics::record_stratum_sessions(0);
+        ctx.stop();
+    }
+}
+
+impl Handler<StratumSessionOpened> for StratumActor {
+    type Result = u64;
+
+    fn handle(&mut self, msg: StratumSessionOpened, _ctx: &mut Self::Context) -> Self::Result {
+        let session_id = self.next_session_id;
+        self.next_session_id += 1;
+        info!("Stratum connection {} from {}", session_id, msg.peer);
+
+        self.sessions.insert(session_id, StratumSession {
+            peer: msg.peer,
+            sender: msg.sender,
+            // Unique per connection so miners never search the same space
+            extranonce1: (session_id as u32).to_be_bytes(),
+            subscribed: false,
+            workers: HashSet::new(),
+            version_mask: None,
+        });
+        crate::metrics::record_stratum_sessions(self.sessions.len());
+        session_id
+    }
+}
+
+impl Handler<StratumRequest> for StratumActor {
+    type Result = Option<String>;
+
+    fn handle(&mut self, msg: StratumRequest, ctx: &mut Self::Context) -> Self::Result {
+        self.handle_line(msg.session_id, &msg.line, ctx)
+    }
+}
+
+impl Handler<StratumSessionClosed> for StratumActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: StratumSessionClosed, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(session) = self.sessions.remove(&msg.session_id) {
+            info!("Stratum connection {} from {} closed", msg.session_id, session.peer);
+        }
+        crate::metrics::record_stratum_sessions(self.sessions.len());
+    }
+}
+
+impl Handler<GetStratumWorkers> for StratumActor {
+    type Result = Vec<StratumWorkerStats>;
+
+    fn handle(&mut self, _msg: GetStratumWorkers, _ctx: &mut Self::Context) -> Self::Result {
+        let mut workers: Vec<StratumWorkerStats> = self.workers.values().cloned().collect();
+        workers.sort_by(|a, b| a.worker.cmp(&b.worker));
+        workers
+    }
+}
@@ -0,0 +1,358 @@
+use actix::prelude::*;
+use bitcoin::hex::DisplayHex;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use tracing::{info, error};
+
+use crate::config::Config;
+use crate::error::{MultisigError, WalletError, WalletResult};
+use crate::multisig::{Proposal, ProposalStatus};
+use crate::wallet::{AccountKey, FundedPsbt, HistoryRow, LeaseBook, Wallet, WalletCoin};
+use super::instrument::InstrumentedAddr;
+use super::{CreateWallet, FundPsbt, GetNewAddress, GetWalletHistory, ListUnspent, ListWallets, SetTxNote, SetWalletFlag, WalletUnspent};
+use super::{BlockConnected, CreateProposal, GetProposal, ListProposals, SubmitProposalSignatures};
+use super::{LeaseOutput, ListLeases, ReleaseOutput};
+use super::{GetChainTip, GetMempoolSpends, ScanUtxos};
+use super::mempool::MempoolActor;
+use super::storage::StorageActor;
+
+/// Loaded watch-only wallets, persisted as JSON under `<datadir>/wallets`,
+/// the multisig proposals being coordinated and outputs leased to other applications
+pub struct WalletActor {
+    wallets: BTreeMap<String, Wallet>,
+    proposals: BTreeMap<String, Proposal>,
+    leases: LeaseBook,
+    datadir: PathBuf,
+    network: bitcoin::Network,
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+}
+
+impl WalletActor {
+    pub fn new(config: &Config, storage_actor: Addr<StorageActor>, mempool_actor: Addr<MempoolActor>) -> Self {
+        let wallets = match Wallet::load_all(&config.datadir) {
+            Ok(wallets) => wallets.into_iter().map(|wallet| (wallet.name.clone(), wallet)).collect(),
+            Err(e) => {
+                error!("Failed to load wallets: {}", e);
+                BTreeMap::new()
+            }
+        };
+        let proposals = match Proposal::load_all(&config.datadir) {
+            Ok(proposals) => proposals.into_iter().map(|proposal| (proposal.id.clone(), proposal)).collect(),
+            Err(e) => {
+                error!("Failed to load multisig proposals: {}", e);
+                BTreeMap::new()
+            }
+        };
+        let leases = LeaseBook::load(&config.datadir).unwrap_or_else(|e| {
+            error!("Failed to load output leases: {}", e);
+            LeaseBook::default()
+        });
+        info!("Wallet actor initialized with {} wallets and {} multisig proposals", wallets.len(), proposals.len());
+
+        Self {
+            wallets,
+            proposals,
+            leases,
+            datadir: config.datadir.clone(),
+            network: config.network.to_bitcoin_network(),
+            storage_actor,
+            mempool_actor,
+        }
+    }
+
+    fn wallet(&self, name: &str) -> WalletResult<&Wallet> {
+        self.wallets.get(name).ok_or_else(|| WalletError::NotFound(name.to_string()))
+    }
+
+    fn wallet_mut(&mut self, name: &str) -> WalletResult<&mut Wallet> {
+        self.wallets.get_mut(name).ok_or_else(|| WalletError::NotFound(name.to_string()))
+    }
+
+    /// Unspent outputs paying the wallet, minus those a mempool transaction
+    /// already spends; `reused` is filled in by `Wallet::mark_reused`
+    fn coins(&self, name: &str) -> WalletResult<impl std::future::Future<Output = WalletResult<Vec<WalletCoin>>>> {
+        let scripts = self.wallet(name)?.scripts()?;
+        let storage_actor = self.storage_actor.clone();
+        let mempool_actor = self.mempool_actor.clone();
+
+        Ok(async move {
+            let tip_height = storage_actor.send_traced(GetChainTip).await??.map(|tip| tip.height);
+            let script_pubkeys: HashSet<_> = scripts.keys().cloned().collect();
+            let utxos = storage_actor.send_traced(ScanUtxos { script_pubkeys }).await??;
+            let spent = mempool_actor.send_traced(GetMempoolSpends {
+                outpoints: utxos.iter().map(|(outpoint, _)| *outpoint).collect(),
+            }).await?;
+
+            let mut coins: Vec<WalletCoin> = utxos.iter()
+                .filter(|(outpoint, _)| !spent.contains(outpoint))
+                .map(|(outpoint, entry)| WalletCoin {
+                    outpoint: *outpoint,
+                    txout: entry.output.clone(),
+                    confirmations: tip_height
+                        .filter(|tip| *tip >= entry.height)
+                        .map(|tip| (tip - entry.height + 1) as u32)
+                        .unwrap_or(0),
+                    is_coinbase: entry.is_coinbase,
+                    address: scripts[&entry.output.script_pubkey],
+                    reused: false,
+                })
+                .collect();
+            coins.sort_by_key(|coin| coin.outpoint);
+            Ok(coins)
+        })
+    }
+}
+
+impl Actor for WalletActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<CreateWallet> for WalletActor {
+    type Result = Result<(), WalletError>;
+
+    fn handle(&mut self, msg: CreateWallet, _ctx: &mut Self::Context) -> Self::Result {
+        if self.wallets.contains_key(&msg.name) {
+            return Err(WalletError::AlreadyExists(msg.name));
+        }
+        let account_key: AccountKey = msg.account_key.parse()?;
+        let mut wallet = Wallet::new(&msg.name, &account_key)?;
+        wallet.avoid_reuse = msg.avoid_reuse;
+        wallet.save(&self.datadir)?;
+        info!("Created wallet {}", msg.name);
+        self.wallets.insert(msg.name, wallet);
+        Ok(())
+    }
+}
+
+impl Handler<ListWallets> for WalletActor {
+    type Result = MessageResult<ListWallets>;
+
+    fn handle(&mut self, _msg: ListWallets, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.wallets.keys().cloned().collect())
+    }
+}
+
+impl Handler<SetWalletFlag> for WalletActor {
+    type Result = Result<(), WalletError>;
+
+    fn handle(&mut self, msg: SetWalletFlag, _ctx: &mut Self::Context) -> Self::Result {
+        let datadir = self.datadir.clone();
+        let wallet = self.wallet_mut(&msg.wallet)?;
+        let flag = match msg.flag.as_str() {
+            "avoid_reuse" => &mut wallet.avoid_reuse,
+            other => return Err(WalletError::InvalidRequest(format!("unknown wallet flag {}", other))),
+        };
+        if *flag == msg.value {
+            return Err(WalletError::InvalidRequest(format!("wallet flag {} is already set to {}", msg.flag, msg.value)));
+        }
+        *flag = msg.value;
+        wallet.save(&datadir)?;
+        info!("Wallet {} flag {} set to {}", msg.wallet, msg.flag, msg.value);
+        Ok(())
+    }
+}
+
+impl Handler<SetTxNote> for WalletActor {
+    type Result = Result<(), WalletError>;
+
+    fn handle(&mut self, msg: SetTxNote, _ctx: &mut Self::Context) -> Self::Result {
+        let datadir = self.datadir.clone();
+        let wallet = self.wallet_mut(&msg.wallet)?;
+        wallet.set_note(msg.txid, msg.note);
+        wallet.save(&datadir)
+    }
+}
+
+impl Handler<GetWalletHistory> for WalletActor {
+    type Result = Result<Vec<HistoryRow>, WalletError>;
+
+    fn handle(&mut self, msg: GetWalletHistory, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.wallet(&msg.wallet)?.history_rows())
+    }
+}
+
+impl Handler<CreateProposal> for WalletActor {
+    type Result = Result<ProposalStatus, MultisigError>;
+
+    fn handle(&mut self, msg: CreateProposal, _ctx: &mut Self::Context) -> Self::Result {
+        let proposal = Proposal::new(&msg.psbt, msg.description)?;
+        if self.proposals.contains_key(&proposal.id) {
+            return Err(MultisigError::AlreadyExists(proposal.id));
+        }
+        proposal.save(&self.datadir)?;
+        info!("Opened multisig proposal {}", proposal.id);
+        let status = proposal.status()?;
+        self.proposals.insert(proposal.id.clone(), proposal);
+        Ok(status)
+    }
+}
+
+impl Handler<SubmitProposalSignatures> for WalletActor {
+    type Result = Result<ProposalStatus, MultisigError>;
+
+    fn handle(&mut self, msg: SubmitProposalSignatures, _ctx: &mut Self::Context) -> Self::Result {
+        let proposal = self.proposals.get_mut(&msg.proposal_id)
+            .ok_or_else(|| MultisigError::NotFound(msg.proposal_id.clone()))?;
+        // Merge into a copy so a rejected submission leaves the proposal untouched
+        let mut updated = proposal.clone();
+        let added = updated.merge(&msg.psbt)?;
+        if added > 0 {
+            updated.save(&self.datadir)?;
+            info!("Merged {} signatures into multisig proposal {}", added, msg.proposal_id);
+            *proposal = updated;
+        }
+        proposal.status()
+    }
+}
+
+impl Handler<GetProposal> for WalletActor {
+    type Result = Result<ProposalStatus, MultisigError>;
+
+    fn handle(&mut self, msg: GetProposal, _ctx: &mut Self::Context) -> Self::Result {
+        self.proposals.get(&msg.proposal_id)
+            .ok_or(MultisigError::NotFound(msg.proposal_id))?
+            .status()
+    }
+}
+
+impl Handler<ListProposals> for WalletActor {
+    type Result = Result<Vec<ProposalStatus>, MultisigError>;
+
+    fn handle(&mut self, _msg: ListProposals, _ctx: &mut Self::Context) -> Self::Result {
+        self.proposals.values().map(Proposal::status).collect()
+    }
+}
+
+impl Handler<LeaseOutput> for WalletActor {
+    type Result = Result<i64, WalletError>;
+
+    fn handle(&mut self, msg: LeaseOutput, _ctx: &mut Self::Context) -> Self::Result {
+        let expiration = self.leases.lease(&msg.id, msg.outpoint, msg.duration_secs, crate::clock::now())?;
+        self.leases.save(&self.datadir)?;
+        Ok(expiration)
+    }
+}
+
+impl Handler<ReleaseOutput> for WalletActor {
+    type Result = Result<(), WalletError>;
+
+    fn handle(&mut self, msg: ReleaseOutput, _ctx: &mut Self::Context) -> Self::Result {
+        self.leases.release(&msg.id, msg.outpoint, crate::clock::now())?;
+        self.leases.save(&self.datadir)
+    }
+}
+
+impl Handler<ListLeases> for WalletActor {
+    type Result = MessageResult<ListLeases>;
+
+    fn handle(&mut self, _msg: ListLeases, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.leases.active(crate::clock::now()))
+    }
+}
+
+impl Handler<GetNewAddress> for WalletActor {
+    type Result = Result<String, WalletError>;
+
+    fn handle(&mut self, msg: GetNewAddress, _ctx: &mut Self::Context) -> Self::Result {
+        let network = self.network;
+        let datadir = self.datadir.clone();
+        let wallet = self.wallet_mut(&msg.wallet)?;
+        let address = wallet.new_address(msg.change, msg.kind, msg.label, network)?;
+        wallet.save(&datadir)?;
+        Ok(address.address)
+    }
+}
+
+impl Handler<ListUnspent> for WalletActor {
+    type Result = AtomicResponse<Self, Result<Vec<WalletUnspent>, WalletError>>;
+
+    fn handle(&mut self, msg: ListUnspent, _ctx: &mut Self::Context) -> Self::Result {
+        let coins = match self.coins(&msg.wallet) {
+            Ok(coins) => coins,
+            Err(e) => return AtomicResponse::new(Box::pin(fut::ready(Err(e)))),
+        };
+
+        AtomicResponse::new(Box::pin(coins.into_actor(self).map(move |coins, actor, _ctx| {
+            let mut coins = coins?;
+            let datadir = actor.datadir.clone();
+            let now = crate::clock::now();
+            let leases = &actor.leases;
+            let wallet = actor.wallets.get_mut(&msg.wallet).ok_or_else(|| WalletError::NotFound(msg.wallet.clone()))?;
+            if wallet.mark_reused(&mut coins) {
+                wallet.save(&datadir)?;
+            }
+            coins.retain(|coin| !leases.is_leased(&coin.outpoint, now));
+            Ok(coins
+                .into_iter()
+                .filter(|coin| coin.confirmations >= msg.min_conf && coin.confirmations <= msg.max_conf)
+                .map(|coin| {
+                    let address = &wallet.addresses[coin.address];
+                    WalletUnspent {
+                        txid: coin.outpoint.txid.to_string(),
+                        vout: coin.outpoint.vout,
+                        address: address.address.clone(),
+                        label: address.label.clone(),
+                        script_pubkey: coin.txout.script_pubkey.as_bytes().to_lower_hex_string(),
+                        amount: coin.txout.value.into(),
+                        confirmations: coin.confirmations,
+                        spendable: coin.is_mature(),
+                        change: address.is_change(),
+                        reused: wallet.avoid_reuse.then_some(coin.reused),
+                    }
+                })
+                .collect())
+        })))
+    }
+}
+
+impl Handler<FundPsbt> for WalletActor {
+    type Result = AtomicResponse<Self, Result<FundedPsbt, WalletError>>;
+
+    fn handle(&mut self, msg: FundPsbt, _ctx: &mut Self::Context) -> Self::Result {
+        let coins = match self.coins(&msg.wallet) {
+            Ok(coins) => coins,
+            Err(e) => return AtomicResponse::new(Box::pin(fut::ready(Err(e)))),
+        };
+
+        // Atomic so two fundings never hand out the same change address
+        AtomicResponse::new(Box::pin(coins.into_actor(self).map(move |coins, actor, _ctx| {
+            let mut coins = coins?;
+            let network = actor.network;
+            let datadir = actor.datadir.clone();
+            let now = crate::clock::now();
+            if let Some(leased) = msg.control.inputs.iter().find(|outpoint| actor.leases.is_leased(outpoint, now)) {
+                return Err(WalletError::OutputLeased(*leased));
+            }
+            coins.retain(|coin| !actor.leases.is_leased(&coin.outpoint, now));
+            let wallet = actor.wallet_mut(&msg.wallet)?;
+            wallet.mark_reused(&mut coins);
+            let funded = crate::wallet::fund_psbt(wallet, &coins, msg.outputs, msg.lock_time, &msg.control, network)?;
+            wallet.save(&datadir)?;
+            Ok(funded)
+        })))
+    }
+}
+
+impl Handler<BlockConnected> for WalletActor {
+    type Result = ();
+
+    /// Record history and mark addresses used as soon as a block pays them,
+    /// so coins arriving later are dirty even once the first one has been spent
+    fn handle(&mut self, msg: BlockConnected, _ctx: &mut Self::Context) -> Self::Result {
+        for wallet in self.wallets.values_mut() {
+            let scripts = match wallet.scripts() {
+                Ok(scripts) => scripts,
+                Err(e) => {
+                    error!("Wallet {} has an unreadable address: {}", wallet.name, e);
+                    continue;
+                }
+            };
+            if wallet.apply_block(&msg.block, msg.height, &scripts) {
+                if let Err(e) = wallet.save(&self.datadir) {
+                    error!("Failed to save wallet {}: {}", wallet.name, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use actix::prelude::*;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, BlockHash, ScriptBuf, Transaction, Txid};
+use std::collections::{HashMap, HashSet};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{ApiError, StorageError};
+use crate::events::{BitcoinEvent, BitcoinEventType, EventManager};
+use super::{BlockConnected, GetTxWatches, TxWatchStatus, WatchTransaction};
+use super::{AddressWatchStatus, GetAddressWatches, TransactionAccepted, WatchAddress};
+
+/// Highest confirmation target a watch may request
+pub const MAX_WATCH_CONFIRMATIONS: u32 = 100;
+
+struct TxWatch {
+    target: u32,
+    callback_url: Option<String>,
+    confirmed_in: Option<(BlockHash, u64)>,
+    notified: u32,
+}
+
+impl TxWatch {
+    fn confirmations(&self, tip_height: Option<u64>) -> u32 {
+        match (self.confirmed_in, tip_height) {
+            (Some((_, height)), Some(tip)) if tip >= height => (tip - height + 1) as u32,
+            _ => 0,
+        }
+    }
+}
+
+/// Resolve an address, `addr(...)` or `raw(<hex>)` descriptor to the scriptPubKey it pays to
+pub fn parse_watch_target(target: &str, network: bitcoin::Network) -> Result<ScriptBuf, ApiError> {
+    // Descriptor checksums are optional and not verified
+    let target = target.split('#').next().unwrap_or(target).trim();
+
+    if let Some(hex) = target.strip_prefix("raw(").and_then(|rest| rest.strip_suffix(')')) {
+        return ScriptBuf::from_hex(hex)
+            .map_err(|e| ApiError::InvalidRequest(format!("Invalid script hex: {}", e)));
+    }
+
+    let address = target
+        .strip_prefix("addr(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .unwrap_or(target);
+    if address.contains('(') {
+        return Err(ApiError::InvalidRequest(format!("Unsupported descriptor: {}", target)));
+    }
+
+    address
+        .parse::<Address<NetworkUnchecked>>()
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid address: {}", e)))?
+        .require_network(network)
+        .map(|address| address.script_pubkey())
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid address: {}", e)))
+}
+
+struct AddressWatch {
+    address: String,
+    confirmations: u32,
+    callback_url: Option<String>,
+}
+
+/// Tracks registered transactions until they reach their confirmation target,
+/// and reports payments to watched scriptPubKeys
+pub struct WatchActor {
+    watches: HashMap<Txid, TxWatch>,
+    address_watches: HashMap<ScriptBuf, AddressWatch>,
+    reported_payments: HashSet<Txid>,
+    tip_height: Option<u64>,
+    #[cfg(feature = "http-client")]
+    client: reqwest::Client,
+    event_manager: EventManager,
+    network_name: &'static str,
+    node_id: String,
+}
+
+impl WatchActor {
+    pub fn new(config: &Config, event_manager: EventManager) -> Self {
+        #[cfg(feature = "http-client")]
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.events.webhook.timeout_secs))
+            .build()
+            .unwrap_or_default();
+
+        let mut address_watches = HashMap::new();
+        for watch in &config.watch.addresses {
+            match parse_watch_target(&watch.address, config.network.to_bitcoin_network()) {
+                Ok(script_pubkey) => {
+                    address_watches.insert(script_pubkey, AddressWatch {
+                        address: watch.address.clone(),
+                        confirmations: watch.confirmations,
+                        callback_url: watch.callback_url.clone(),
+                    });
+                }
+                Err(e) => warn!("Skipping configured address watch {}: {}", watch.address, e),
+            }
+        }
+
+        info!("Watch actor initialized with {} address watches", address_watches.len());
+        Self {
+            watches: HashMap::new(),
+            address_watches,
+            reported_payments: HashSet::new(),
+            tip_height: None,
+            #[cfg(feature = "http-client")]
+            client,
+            event_manager,
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+        }
+    }
+
+    fn status(&self, txid: &Txid, watch: &TxWatch) -> TxWatchStatus {
+        TxWatchStatus {
+            txid: txid.to_string(),
+            target_confirmations: watch.target,
+            confirmations: watch.confirmations(self.tip_height),
+            block_hash: watch.confirmed_in.map(|(hash, _)| hash.to_string()),
+            block_height: watch.confirmed_in.map(|(_, height)| height),
+            callback_url: watch.callback_url.clone(),
+        }
+    }
+
+    fn address_status(script_pubkey: &ScriptBuf, watch: &AddressWatch) -> AddressWatchStatus {
+        AddressWatchStatus {
+            address: watch.address.clone(),
+            script_pubkey: script_pubkey.to_hex_string(),
+            confirmations: watch.confirmations,
+            callback_url: watch.callback_url.clone(),
+        }
+    }
+
+    /// Report outputs paying watched scriptPubKeys the first time their transaction is seen,
+    /// then follow it with a transaction watch for confirmation updates
+    fn match_payments(
+        &mut self,
+        tx: &Transaction,
+        confirmed_in: Option<(BlockHash, u64)>,
+        events: &mut Vec<(BitcoinEvent, Option<String>)>,
+    ) {
+        if self.address_watches.is_empty() {
+            return;
+        }
+
+        let txid = tx.txid();
+        if self.reported_payments.contains(&txid) {
+            return;
+        }
+
+        for (vout, output) in tx.output.iter().enumerate() {
+            let Some(watch) = self.address_watches.get(&output.script_pubkey) else {
+                continue;
+            };
+
+            info!("Payment of {} sat to watched address {} in {}", output.value.to_sat(), watch.address, txid);
+            events.push((
+                BitcoinEvent::new(
+                    BitcoinEventType::PaymentReceived {
+                        address: watch.address.clone(),
+                        txid: txid.to_string(),
+                        vout: vout as u32,
+                        value: output.value.to_sat(),
+                        confirmations: if confirmed_in.is_some() { 1 } else { 0 },
+                        block_hash: confirmed_in.map(|(hash, _)| hash.to_string()),
+                        block_height: confirmed_in.map(|(_, height)| height),
+                    },
+                    self.network_name,
+                    &self.node_id,
+                ),
+                watch.callback_url.clone(),
+            ));
+
+            self.reported_payments.insert(txid);
+            self.watches.entry(txid).or_insert(TxWatch {
+                target: watch.confirmations,
+                callback_url: watch.callback_url.clone(),
+                confirmed_in: None,
+                notified: 0,
+            });
+        }
+    }
+
+    /// Publish events in order, posting each one to its watch callback as well
+    fn deliver(&self, events: Vec<(BitcoinEvent, Option<String>)>) {
+        if events.is_empty() {
+            return;
+        }
+
+        let event_manager = self.event_manager.clone();
+        #[cfg(feature = "http-client")]
+        let client = self.client.clone();
+        self.event_manager.spawn_delivery(async move {
+            for (event, callback_url) in events {
+                // Signed once so publishers and the callback carry the same signature
+                let event = event_manager.sign(event);
+                if let Err(e) = event_manager.publish_event(&event).await {
+                    warn!("Failed to publish watch event: {}", e);
+                }
+                #[cfg(not(feature = "http-client"))]
+                if let Some(url) = callback_url {
+                    warn!("Watch callback to {} skipped, this build has no http-client feature", url);
+                }
+                #[cfg(feature = "http-client")]
+                if let Some(url) = callback_url {
+                    match client.post(&url).json(&event).send().await {
+                        Ok(response) if !response.status().is_success() => {
+                            warn!("Watch callback {} returned status: {}", url, response.status());
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to send watch callback to {}: {}", url, e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Actor for WatchActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        info!("Watch actor started");
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!("Watch actor stopped");
+    }
+}
+
+impl Handler<WatchTransaction> for WatchActor {
+    type Result = Result<TxWatchStatus, StorageError>;
+
+    fn handle(&mut self, msg: WatchTransaction, _ctx: &mut Self::Context) -> Self::Result {
+        info!("Watching transaction {} for {} confirmations", msg.txid, msg.confirmations);
+        // TODO: Look up transactions that confirmed before the watch was registered
+        let watch = self.watches.entry(msg.txid).or_insert(TxWatch {
+            target: msg.confirmations,
+            callback_url: None,
+            confirmed_in: None,
+            notified: 0,
+        });
+        watch.target = msg.confirmations;
+        watch.callback_url = msg.callback_url;
+
+        Ok(self.status(&msg.txid, &self.watches[&msg.txid]))
+    }
+}
+
+impl Handler<GetTxWatches> for WatchActor {
+    type Result = Result<Vec<TxWatchStatus>, StorageError>;
+
+    fn handle(&mut self, _msg: GetTxWatches, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.watches.iter().map(|(txid, watch)| self.status(txid, watch)).collect())
+    }
+}
+
+impl Handler<WatchAddress> for WatchActor {
+    type Result = Result<AddressWatchStatus, StorageError>;
+
+    fn handle(&mut self, msg: WatchAddress, _ctx: &mut Self::Context) -> Self::Result {
+        info!("Watching address {} for payments", msg.address);
+        let watch = AddressWatch {
+            address: msg.address,
+            confirmations: msg.confirmations,
+            callback_url: msg.callback_url,
+        };
+        let status = Self::address_status(&msg.script_pubkey, &watch);
+        self.address_watches.insert(msg.script_pubkey, watch);
+        Ok(status)
+    }
+}
+
+impl Handler<GetAddressWatches> for WatchActor {
+    type Result = Result<Vec<AddressWatchStatus>, StorageError>;
+
+    fn handle(&mut self, _msg: GetAddressWatches, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.address_watches
+            .iter()
+            .map(|(script_pubkey, watch)| Self::address_status(script_pubkey, watch))
+            .collect())
+    }
+}
+
+impl Handler<TransactionAccepted> for WatchActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransactionAccepted, _ctx: &mut Self::Context) -> Self::Result {
+        let mut events = Vec::new();
+        self.match_payments(&msg.tx, None, &mut events);
+        self.deliver(events);
+    }
+}
+
+impl Handler<BlockConnected> for WatchActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlockConnected, _ctx: &mut Self::Context) -> Self::Result {
+        let block_hash = msg.block.block_hash();
+        let mut events = Vec::new();
+
+        // A block at or below a confirming height means that block was disconnected
+        for (txid, watch) in self.watches.iter_mut() {
+            let reverted = match watch.confirmed_in {
+                Some((hash, height)) => height > msg.height || (height == msg.height && hash != block_hash),
+                None => false,
+            };
+            if reverted {
+                let (hash, height) = watch.confirmed_in.take().unwrap();
+                watch.notified = 0;
+                events.push((
+                    BitcoinEvent::new(
+                        BitcoinEventType::TxConfirmationReverted {
+                            txid: txid.to_string(),
+                            block_hash: hash.to_string(),
+                            block_height: height,
+                        },
+                        self.network_name,
+                        &self.node_id,
+                    ),
+                    watch.callback_url.clone(),
+                ));
+            }
+        }
+
+        for tx in &msg.block.txdata {
+            self.match_payments(tx, Some((block_hash, msg.height)), &mut events);
+            if let Some(watch) = self.watches.get_mut(&tx.txid()) {
+                watch.confirmed_in = Some((block_hash, msg.height));
+            }
+        }
+
+        self.tip_height = Some(msg.height);
+
+        let mut completed = Vec::new();
+        for (txid, watch) in self.watches.iter_mut() {
+            let Some((hash, height)) = watch.confirmed_in else {
+                continue;
+            };
+            let confirmations = watch.confirmations(self.tip_height);
+            for count in (watch.notified + 1)..=confirmations.min(watch.target) {
+                events.push((
+                    BitcoinEvent::new(
+                        BitcoinEventType::TxConfirmed {
+                            txid: txid.to_string(),
+                            confirmations: count,
+                            target_confirmations: watch.target,
+                            block_hash: hash.to_string(),
+                            block_height: height,
+                        },
+                        self.network_name,
+                        &self.node_id,
+                    ),
+                    watch.callback_url.clone(),
+                ));
+            }
+            watch.notified = watch.notified.max(confirmations.min(watch.target));
+            if confirmations >= watch.target {
+                completed.push(*txid);
+            }
+        }
+
+        for txid in completed {
+            info!("Transaction {} reached its confirmation target", txid);
+            self.watches.remove(&txid);
+            self.reported_payments.remove(&txid);
+        }
+
+        self.deliver(events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_watch_confirmations() {
+        let mut watch = TxWatch {
+            target: 3,
+            callback_url: None,
+            confirmed_in: None,
+            notified: 0,
+        };
+        assert_eq!(watch.confirmations(Some(100)), 0);
+
+        watch.confirmed_in = Some((BlockHash::all_zeros(), 100));
+        assert_eq!(watch.confirmations(Some(100)), 1);
+        assert_eq!(watch.confirmations(Some(102)), 3);
+        assert_eq!(watch.confirmations(Some(99)), 0);
+        assert_eq!(watch.confirmations(None), 0);
+    }
+
+    #[test]
+    fn test_parse_watch_target() {
+        let network = bitcoin::Network::Regtest;
+        let address = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+        let expected = parse_watch_target(address, network).unwrap();
+
+        assert_eq!(parse_watch_target(&format!("addr({})", address), network).unwrap(), expected);
+        assert_eq!(parse_watch_target(&format!("addr({})#checksum", address), network).unwrap(), expected);
+        assert_eq!(
+            parse_watch_target(&format!("raw({})", expected.to_hex_string()), network).unwrap(),
+            expected
+        );
+        assert!(parse_watch_target(address, bitcoin::Network::Bitcoin).is_err());
+        assert!(parse_watch_target("wpkh(02abcdef)", network).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use actix::prelude::*;
+use bitcoin::hashes::Hash;
+use bitcoin::Transaction;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use tracing::{error, info};
+
+use crate::config::Config;
+use crate::error::{EventError, EventResult};
+use crate::events::PublisherEndpoint;
+use super::{BlockConnected, TransactionAccepted};
+
+/// Topics published in Bitcoin Core's wire format
+pub const CORE_TOPICS: [&str; 4] = ["hashblock", "hashtx", "rawblock", "rawtx"];
+
+/// Outbound queue limit per socket, matching Core's `-zmqpub*hwm` default
+pub const DEFAULT_HIGH_WATER_MARK: i32 = 1000;
+
+/// Build the `[topic, body, sequence]` frames of a Core ZMQ notification;
+/// the sequence number is little endian and counted per topic
+pub fn notification_frames(topic: &str, body: Vec<u8>, sequence: u32) -> [Vec<u8>; 3] {
+    [topic.as_bytes().to_vec(), body, sequence.to_le_bytes().to_vec()]
+}
+
+/// `hashblock`/`hashtx` bodies carry the hash in display order, reversed from
+/// its serialization
+fn hash_body<H: Hash<Bytes = [u8; 32]>>(hash: H) -> Vec<u8> {
+    let mut bytes = hash.to_byte_array();
+    bytes.reverse();
+    bytes.to_vec()
+}
+
+/// Address the notification socket binds to, if `[network_config.zmq]` enables one
+pub fn bind_address(config: &Config) -> Option<String> {
+    let zmq = &config.network_config.zmq;
+    if !zmq.enabled {
+        return None;
+    }
+    zmq.pub_port.map(|port| format!("tcp://{}:{}", config.rpc.host, port))
+}
+
+/// The Core-compatible endpoints this node publishes, as reported by `getzmqnotifications`
+pub fn endpoints(config: &Config) -> Vec<PublisherEndpoint> {
+    let Some(address) = bind_address(config) else {
+        return Vec::new();
+    };
+    let topics: Vec<String> = config.network_config.zmq.topics.iter()
+        .filter(|topic| CORE_TOPICS.contains(&topic.as_str()))
+        .cloned()
+        .collect();
+    if topics.is_empty() {
+        return Vec::new();
+    }
+
+    vec![PublisherEndpoint {
+        address,
+        topics,
+        high_water_mark: Some(DEFAULT_HIGH_WATER_MARK as u32),
+        acknowledged: None,
+    }]
+}
+
+/// Publishes `hashblock`, `hashtx`, `rawblock` and `rawtx` notifications the
+/// way bitcoind's `-zmqpub*` options do, so LND, Core Lightning, electrs and
+/// BTCPay can use this node in place of bitcoind.
+///
+/// ZMQ sockets must not be shared between threads, so the socket lives on a
+/// dedicated thread and the actor only hands it serialized payloads.
+pub struct ZmqNotifier {
+    sender: Option<mpsc::Sender<(&'static str, Vec<u8>)>>,
+    topics: Vec<String>,
+}
+
+impl ZmqNotifier {
+    pub fn new(config: &Config) -> EventResult<Self> {
+        let Some(endpoint) = endpoints(config).into_iter().next() else {
+            return Ok(Self { sender: None, topics: Vec::new() });
+        };
+        let PublisherEndpoint { address, topics, .. } = endpoint;
+
+        let context = zmq::Context::new();
+        let socket = context.socket(zmq::PUB)
+            .map_err(|e| EventError::PublisherUnavailable(format!("zmq socket: {}", e)))?;
+        socket.set_sndhwm(DEFAULT_HIGH_WATER_MARK)
+            .map_err(|e| EventError::PublisherUnavailable(format!("zmq sndhwm: {}", e)))?;
+        socket.bind(&address)
+            .map_err(|e| EventError::PublisherUnavailable(format!("zmq bind {}: {}", address, e)))?;
+        info!("ZMQ notifications ({}) published on {}", topics.join(", "), address);
+
+        let (sender, receiver) = mpsc::channel::<(&'static str, Vec<u8>)>();
+        std::thread::Builder::new()
+            .name("zmq-notifier".to_string())
+            .spawn(move || {
+                // Keep the context alive for as long as the socket
+                let _context = context;
+                let mut sequences: HashMap<&'static str, u32> = HashMap::new();
+                for (topic, body) in receiver {
+                    let sequence = sequences.entry(topic).or_insert(0);
+                    if let Err(e) = socket.send_multipart(notification_frames(topic, body, *sequence), 0) {
+                        error!("Failed to publish ZMQ {} notification: {}", topic, e);
+                    }
+                    *sequence = sequence.wrapping_add(1);
+                }
+            })
+            .map_err(|e| EventError::PublisherUnavailable(format!("zmq thread: {}", e)))?;
+
+        Ok(Self { sender: Some(sender), topics })
+    }
+
+    fn publish(&self, topic: &'static str, body: impl FnOnce() -> Vec<u8>) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if !self.topics.iter().any(|t| t == topic) {
+            return;
+        }
+        if sender.send((topic, body())).is_err() {
+            error!("ZMQ notifier thread has stopped");
+        }
+    }
+
+    fn publish_transaction(&self, tx: &Transaction) {
+        self.publish("hashtx", || hash_body(tx.txid()));
+        self.publish("rawtx", || bitcoin::consensus::serialize(tx));
+    }
+}
+
+impl Actor for ZmqNotifier {
+    type Context = Context<Self>;
+}
+
+impl Handler<BlockConnected> for ZmqNotifier {
+    type Result = ();
+
+    fn handle(&mut self, msg: BlockConnected, _ctx: &mut Self::Context) -> Self::Result {
+        // Like Core, confirmed transactions are announced again ahead of their block
+        for tx in &msg.block.txdata {
+            self.publish_transaction(tx);
+        }
+        self.publish("hashblock", || hash_body(msg.block.block_hash()));
+        self.publish("rawblock", || bitcoin::consensus::serialize(&msg.block));
+    }
+}
+
+impl Handler<TransactionAccepted> for ZmqNotifier {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransactionAccepted, _ctx: &mut Self::Context) -> Self::Result {
+        self.publish_transaction(&msg.tx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_notification_format() {
+        let frames = notification_frames("rawtx", vec![0xde, 0xad], 258);
+        assert_eq!(frames[0], b"rawtx".to_vec());
+        assert_eq!(frames[1], vec![0xde, 0xad]);
+        assert_eq!(frames[2], vec![0x02, 0x01, 0x00, 0x00]);
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Bitcoin).block_hash();
+        let body = hash_body(genesis);
+        assert_eq!(bitcoin::hex::DisplayHex::to_lower_hex_string(&body[..]), genesis.to_string());
+
+        let mut config = Config::default_regtest();
+        config.network_config.zmq.pub_port = Some(28444);
+        let endpoints = endpoints(&config);
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(endpoints[0].address, "tcp://127.0.0.1:28444");
+        assert_eq!(endpoints[0].topics, CORE_TOPICS.map(String::from).to_vec());
+
+        config.network_config.zmq.enabled = false;
+        assert!(super::endpoints(&config).is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+//! Satoshi amounts and fee rates as integers
+//!
+//! Fees and fee rates are whole numbers inside the node, so sums, comparisons
+//! and the mempool's fee ordering are exact. Floats appear only where a value
+//! is written out: RPC reports BTC and BTC/kvB as Core does (see [`as_btc`]
+//! and [`as_btc_per_kvb`]), while events, the REST API and mempool snapshots
+//! carry satoshis and sat/vB.
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const SAT_PER_BTC: u64 = 100_000_000;
+
+/// A whole number of satoshis, serialized as an integer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Satoshi(pub u64);
+
+impl Satoshi {
+    pub const ZERO: Satoshi = Satoshi(0);
+
+    pub const fn from_sat(sats: u64) -> Self {
+        Satoshi(sats)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// A BTC amount as RPC callers pass it, rounded to the nearest satoshi;
+    /// `None` for negative, non-finite or out of range values
+    pub fn from_btc(btc: f64) -> Option<Self> {
+        let sats = (btc * SAT_PER_BTC as f64).round();
+        (sats.is_finite() && sats >= 0.0 && sats <= u64::MAX as f64).then_some(Satoshi(sats as u64))
+    }
+
+    /// BTC as a number, for interfaces that report amounts the way Core does
+    pub fn to_btc(self) -> f64 {
+        bitcoin::Amount::from_sat(self.0).to_btc()
+    }
+
+    pub fn checked_sub(self, other: Satoshi) -> Option<Satoshi> {
+        self.0.checked_sub(other.0).map(Satoshi)
+    }
+}
+
+impl Add for Satoshi {
+    type Output = Satoshi;
+
+    fn add(self, other: Satoshi) -> Satoshi {
+        Satoshi(self.0 + other.0)
+    }
+}
+
+impl Sub for Satoshi {
+    type Output = Satoshi;
+
+    fn sub(self, other: Satoshi) -> Satoshi {
+        Satoshi(self.0 - other.0)
+    }
+}
+
+impl Sum for Satoshi {
+    fn sum<I: Iterator<Item = Satoshi>>(iter: I) -> Satoshi {
+        Satoshi(iter.map(|amount| amount.0).sum())
+    }
+}
+
+impl From<bitcoin::Amount> for Satoshi {
+    fn from(amount: bitcoin::Amount) -> Self {
+        Satoshi(amount.to_sat())
+    }
+}
+
+impl From<Satoshi> for bitcoin::Amount {
+    fn from(amount: Satoshi) -> Self {
+        bitcoin::Amount::from_sat(amount.0)
+    }
+}
+
+impl fmt::Display for Satoshi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat", self.0)
+    }
+}
+
+/// Satoshis per 1000 virtual bytes, the unit Core keeps fee rates in.
+/// Serialized as sat/vB, which the thousandths make exact in decimal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    pub const ZERO: FeeRate = FeeRate(0);
+
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> Self {
+        FeeRate(sat_per_kvb)
+    }
+
+    /// A sat/vB rate as callers pass it, rounded to the nearest sat/kvB;
+    /// `None` for negative or non-finite values
+    pub fn from_sat_per_vb(sat_per_vb: f64) -> Option<Self> {
+        let sat_per_kvb = (sat_per_vb * 1000.0).round();
+        (sat_per_kvb.is_finite() && sat_per_kvb >= 0.0 && sat_per_kvb <= u64::MAX as f64)
+            .then_some(FeeRate(sat_per_kvb as u64))
+    }
+
+    /// A BTC/kvB rate, as `maxfeerate` and Core's fee RPCs use
+    pub fn from_btc_per_kvb(btc_per_kvb: f64) -> Option<Self> {
+        Satoshi::from_btc(btc_per_kvb).map(|sats| FeeRate(sats.0))
+    }
+
+    /// Rate paid by `fee` over `vsize` virtual bytes, rounded down as Core's `CFeeRate` does
+    pub fn from_fee(fee: Satoshi, vsize: u64) -> Self {
+        if vsize == 0 {
+            return FeeRate::ZERO;
+        }
+        FeeRate((fee.0 as u128 * 1000 / vsize as u128).min(u64::MAX as u128) as u64)
+    }
+
+    pub const fn to_sat_per_kvb(self) -> u64 {
+        self.0
+    }
+
+    pub fn to_sat_per_vb(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+
+    pub fn to_btc_per_kvb(self) -> f64 {
+        Satoshi(self.0).to_btc()
+    }
+
+    /// Fee for `vsize` virtual bytes at this rate, rounded up as in `CFeeRate::GetFee`
+    pub fn fee_for(self, vsize: u64) -> Satoshi {
+        Satoshi((self.0 as u128 * vsize as u128).div_ceil(1000).min(u64::MAX as u128) as u64)
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat/vB", self.to_sat_per_vb())
+    }
+}
+
+impl Serialize for FeeRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_sat_per_vb())
+    }
+}
+
+impl<'de> Deserialize<'de> for FeeRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sat_per_vb = f64::deserialize(deserializer)?;
+        FeeRate::from_sat_per_vb(sat_per_vb)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid fee rate {}", sat_per_vb)))
+    }
+}
+
+/// `#[serde(with = "crate::amount::as_btc")]`: a [`Satoshi`] field written in BTC
+pub mod as_btc {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(amount: &Satoshi, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(amount.to_btc())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Satoshi, D::Error> {
+        let btc = f64::deserialize(deserializer)?;
+        Satoshi::from_btc(btc).ok_or_else(|| serde::de::Error::custom(format!("invalid amount {}", btc)))
+    }
+}
+
+/// `#[serde(with = "crate::amount::as_btc_per_kvb")]`: a [`FeeRate`] field written in BTC/kvB
+pub mod as_btc_per_kvb {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(rate: &FeeRate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(rate.to_btc_per_kvb())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FeeRate, D::Error> {
+        let btc_per_kvb = f64::deserialize(deserializer)?;
+        FeeRate::from_btc_per_kvb(btc_per_kvb)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid fee rate {}", btc_per_kvb)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_conversions_are_exact() {
+        // 0.1 + 0.2 BTC is not 0.3 in floats, but is in satoshis
+        assert_eq!(Satoshi::from_btc(0.1).unwrap() + Satoshi::from_btc(0.2).unwrap(), Satoshi::from_btc(0.3).unwrap());
+        assert_eq!(Satoshi::from_btc(0.00001).unwrap(), Satoshi(1000));
+        assert_eq!(Satoshi::from_btc(-0.1), None);
+        assert_eq!(Satoshi(2_100_000_000_000_000).to_btc(), 21_000_000.0);
+
+        let rate = FeeRate::from_fee(Satoshi(1000), 141);
+        assert_eq!(rate.to_sat_per_kvb(), 7092);
+        assert_eq!(rate.fee_for(141), Satoshi(1000));
+        assert_eq!(FeeRate::from_sat_per_vb(1.0).unwrap(), FeeRate::from_btc_per_kvb(0.00001).unwrap());
+        assert_eq!(FeeRate::from_sat_per_vb(f64::NAN), None);
+        assert_eq!(FeeRate::from_fee(Satoshi(1000), 0), FeeRate::ZERO);
+    }
+
+    #[test]
+    fn test_serialization_per_interface() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Core {
+            #[serde(with = "as_btc")]
+            amount: Satoshi,
+            #[serde(with = "as_btc_per_kvb")]
+            fee_rate: FeeRate,
+        }
+        let core = Core { amount: Satoshi(5_000_000_000), fee_rate: FeeRate::from_sat_per_kvb(1000) };
+        let value = serde_json::to_value(&core).unwrap();
+        assert_eq!(value, json!({ "amount": 50.0, "fee_rate": 0.00001 }));
+        assert_eq!(serde_json::from_value::<Core>(value).unwrap(), core);
+
+        // Natively, amounts are satoshis and rates sat/vB
+        let rate = FeeRate::from_fee(Satoshi(2500), 1000);
+        assert_eq!(serde_json::to_value(rate).unwrap(), json!(2.5));
+        assert_eq!(serde_json::from_value::<FeeRate>(json!(2.5)).unwrap(), rate);
+        assert_eq!(serde_json::to_value(Satoshi(2500)).unwrap(), json!(2500));
+        assert!(serde_json::from_value::<FeeRate>(json!(-1.0)).is_err());
+    }
+}
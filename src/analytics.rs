@@ -0,0 +1,467 @@
+//! Rolling chain statistics
+//!
+//! Per-block fee and fullness figures are computed when a block is connected
+//! and folded into per-day aggregates, both persisted in the stats column
+//! family so dashboards can query them without an external ETL job. UTXO set
+//! distribution (coin age and amount buckets) is computed by a periodic scan.
+//! Fee rate history, per-block percentiles and sampled mempool minimum
+//! fees, is kept in the same column family for as long as
+//! `analytics.fee_history_retention_days` allows.
+
+use bitcoin::{Block, Network, OutPoint, Weight};
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::amount::{FeeRate, Satoshi};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+const MAX_BLOCK_WEIGHT: u64 = Weight::MAX_BLOCK.to_wu();
+
+/// Initial block subsidy in satoshis
+const INITIAL_SUBSIDY: u64 = 50 * 100_000_000;
+
+fn halving_interval(network: Network) -> u64 {
+    match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    }
+}
+
+/// Block subsidy at `height`, excluding fees
+pub fn block_subsidy(height: u64, network: Network) -> u64 {
+    let halvings = height / halving_interval(network);
+    if halvings >= 64 {
+        0
+    } else {
+        INITIAL_SUBSIDY >> halvings
+    }
+}
+
+/// Days since the Unix epoch for a block timestamp, used as the daily stats key
+pub fn day_index(timestamp: u32) -> u32 {
+    (timestamp as u64 / SECONDS_PER_DAY) as u32
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockStats {
+    pub height: u64,
+    pub block_hash: String,
+    pub time: u32,
+    /// Transactions excluding the coinbase
+    pub tx_count: u64,
+    pub weight: u64,
+    pub weight_utilization: f64,
+    /// Fees claimed by the coinbase beyond the subsidy
+    pub total_fees: u64,
+    pub subsidy: u64,
+    /// Mean fee rate over non-coinbase transactions, in sat/vB
+    pub fee_rate: f64,
+}
+
+impl BlockStats {
+    pub fn from_block(block: &Block, height: u64, network: Network) -> Self {
+        let subsidy = block_subsidy(height, network);
+        let coinbase_value: u64 = block.txdata
+            .first()
+            .map(|tx| tx.output.iter().map(|output| output.value.to_sat()).sum())
+            .unwrap_or(0);
+        let total_fees = coinbase_value.saturating_sub(subsidy);
+
+        let vsize: u64 = block.txdata
+            .iter()
+            .skip(1)
+            .map(|tx| tx.vsize() as u64)
+            .sum();
+        let weight = block.weight().to_wu();
+
+        Self {
+            height,
+            block_hash: block.block_hash().to_string(),
+            time: block.header.time,
+            tx_count: block.txdata.len().saturating_sub(1) as u64,
+            weight,
+            weight_utilization: weight as f64 / MAX_BLOCK_WEIGHT as f64,
+            total_fees,
+            subsidy,
+            fee_rate: if vsize > 0 { total_fees as f64 / vsize as f64 } else { 0.0 },
+        }
+    }
+}
+
+/// Aggregates for all blocks timestamped on one UTC day
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    pub blocks: u64,
+    pub tx_count: u64,
+    pub total_fees: u64,
+    pub total_weight: u64,
+    pub mean_weight_utilization: f64,
+    pub first_height: u64,
+    pub last_height: u64,
+}
+
+impl DailyStats {
+    pub fn new(day: u32) -> Self {
+        let date = DateTime::from_timestamp(day as i64 * SECONDS_PER_DAY as i64, 0)
+            .map(|time| time.date_naive())
+            .unwrap_or_default();
+        Self {
+            date,
+            blocks: 0,
+            tx_count: 0,
+            total_fees: 0,
+            total_weight: 0,
+            mean_weight_utilization: 0.0,
+            first_height: 0,
+            last_height: 0,
+        }
+    }
+
+    pub fn add_block(&mut self, stats: &BlockStats) {
+        if self.blocks == 0 || stats.height < self.first_height {
+            self.first_height = stats.height;
+        }
+        self.last_height = self.last_height.max(stats.height);
+        self.blocks += 1;
+        self.tx_count += stats.tx_count;
+        self.total_fees += stats.total_fees;
+        self.total_weight += stats.weight;
+        self.update_utilization();
+    }
+
+    /// Take a disconnected block back out of the aggregate
+    pub fn remove_block(&mut self, stats: &BlockStats) {
+        self.blocks = self.blocks.saturating_sub(1);
+        self.tx_count = self.tx_count.saturating_sub(stats.tx_count);
+        self.total_fees = self.total_fees.saturating_sub(stats.total_fees);
+        self.total_weight = self.total_weight.saturating_sub(stats.weight);
+        if stats.height == self.last_height && self.last_height > self.first_height {
+            self.last_height -= 1;
+        }
+        self.update_utilization();
+    }
+
+    fn update_utilization(&mut self) {
+        self.mean_weight_utilization = if self.blocks > 0 {
+            self.total_weight as f64 / (self.blocks as f64 * MAX_BLOCK_WEIGHT as f64)
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Summary over the most recent blocks of the active chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RollingStats {
+    pub blocks: u64,
+    pub tx_count: u64,
+    pub total_fees: u64,
+    pub mean_fees_per_block: f64,
+    pub mean_weight_utilization: f64,
+    pub mean_fee_rate: f64,
+}
+
+impl RollingStats {
+    pub fn from_blocks(blocks: &[BlockStats]) -> Self {
+        let count = blocks.len() as u64;
+        let tx_count = blocks.iter().map(|b| b.tx_count).sum();
+        let total_fees = blocks.iter().map(|b| b.total_fees).sum();
+        let mean = |value: f64| if count > 0 { value / count as f64 } else { 0.0 };
+
+        Self {
+            blocks: count,
+            tx_count,
+            total_fees,
+            mean_fees_per_block: mean(total_fees as f64),
+            mean_weight_utilization: mean(blocks.iter().map(|b| b.weight_utilization).sum()),
+            mean_fee_rate: mean(blocks.iter().map(|b| b.fee_rate).sum()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockAnalytics {
+    pub summary: RollingStats,
+    pub blocks: Vec<BlockStats>,
+}
+
+/// Blocks per day at the ten minute target spacing
+const BLOCKS_PER_DAY: u64 = 144;
+
+/// Upper bounds (exclusive) of the coin age bands, in blocks
+const AGE_BANDS: &[(&str, Option<u64>)] = &[
+    ("<1d", Some(BLOCKS_PER_DAY)),
+    ("1d-1w", Some(BLOCKS_PER_DAY * 7)),
+    ("1w-1m", Some(BLOCKS_PER_DAY * 30)),
+    ("1m-6m", Some(BLOCKS_PER_DAY * 180)),
+    ("6m-1y", Some(BLOCKS_PER_DAY * 365)),
+    ("1y-2y", Some(BLOCKS_PER_DAY * 365 * 2)),
+    ("2y-5y", Some(BLOCKS_PER_DAY * 365 * 5)),
+    ("5y-10y", Some(BLOCKS_PER_DAY * 365 * 10)),
+    (">10y", None),
+];
+
+/// Upper bounds (exclusive) of the amount buckets, in satoshis
+const AMOUNT_BUCKETS: &[(&str, Option<u64>)] = &[
+    ("<1k sat", Some(1_000)),
+    ("1k-10k sat", Some(10_000)),
+    ("10k-100k sat", Some(100_000)),
+    ("100k-1M sat", Some(1_000_000)),
+    ("0.01-0.1 BTC", Some(10_000_000)),
+    ("0.1-1 BTC", Some(100_000_000)),
+    ("1-10 BTC", Some(1_000_000_000)),
+    ("10-100 BTC", Some(10_000_000_000)),
+    ("100-1k BTC", Some(100_000_000_000)),
+    (">=1k BTC", None),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DistributionBand {
+    pub label: String,
+    pub min: u64,
+    /// Exclusive upper bound, absent for the last band
+    pub max: Option<u64>,
+    pub count: u64,
+    pub value: u64,
+}
+
+fn bands(bounds: &[(&str, Option<u64>)]) -> Vec<DistributionBand> {
+    let mut min = 0;
+    bounds
+        .iter()
+        .map(|(label, max)| {
+            let band = DistributionBand {
+                label: label.to_string(),
+                min,
+                max: *max,
+                count: 0,
+                value: 0,
+            };
+            min = max.unwrap_or(u64::MAX);
+            band
+        })
+        .collect()
+}
+
+fn add_to_band(bands: &mut [DistributionBand], key: u64, value: u64) {
+    if let Some(band) = bands.iter_mut().find(|band| band.max.is_none_or(|max| key < max)) {
+        band.count += 1;
+        band.value += value;
+    }
+}
+
+/// UTXO set breakdown by coin age (in blocks) and amount at a given tip
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UtxoDistribution {
+    pub height: u64,
+    pub block_hash: String,
+    pub computed_at: DateTime<chrono::Utc>,
+    pub total_count: u64,
+    pub total_value: u64,
+    pub by_age: Vec<DistributionBand>,
+    pub by_amount: Vec<DistributionBand>,
+}
+
+impl UtxoDistribution {
+    pub fn new(height: u64, block_hash: String) -> Self {
+        Self {
+            height,
+            block_hash,
+            computed_at: chrono::Utc::now(),
+            total_count: 0,
+            total_value: 0,
+            by_age: bands(AGE_BANDS),
+            by_amount: bands(AMOUNT_BUCKETS),
+        }
+    }
+
+    /// Count a coin created at `created_height` holding `value` satoshis
+    pub fn add(&mut self, created_height: u64, value: u64) {
+        self.total_count += 1;
+        self.total_value += value;
+        add_to_band(&mut self.by_age, self.height.saturating_sub(created_height), value);
+        add_to_band(&mut self.by_amount, value, value);
+    }
+}
+
+/// Percentiles reported per block, as in Core's `getblockstats`
+const FEE_RATE_PERCENTILES: [u64; 5] = [10, 25, 50, 75, 90];
+
+/// Fee rates paid in one block, each percentile weighted by virtual size as
+/// in Core's `getblockstats`, so a large transaction counts for more than a small one
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockFeeRates {
+    pub height: u64,
+    pub block_hash: String,
+    pub time: u32,
+    /// Transactions whose fee is known, excluding the coinbase
+    pub tx_count: u64,
+    pub min: FeeRate,
+    pub p10: FeeRate,
+    pub p25: FeeRate,
+    pub median: FeeRate,
+    pub p75: FeeRate,
+    pub p90: FeeRate,
+    pub max: FeeRate,
+}
+
+impl BlockFeeRates {
+    /// `spent_value` gives the value of each coin the block spends; a
+    /// transaction spending a coin it has no value for is left out
+    pub fn from_block(block: &Block, height: u64, spent_value: impl Fn(&OutPoint) -> Option<u64>) -> Self {
+        let mut rates: Vec<(FeeRate, u64)> = block.txdata
+            .iter()
+            .skip(1)
+            .filter_map(|tx| {
+                let inputs = tx.input.iter().map(|input| spent_value(&input.previous_output)).sum::<Option<u64>>()?;
+                let outputs: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+                let vsize = tx.vsize() as u64;
+                Some((FeeRate::from_fee(Satoshi(inputs.checked_sub(outputs)?), vsize), vsize))
+            })
+            .collect();
+        rates.sort();
+
+        let total: u64 = rates.iter().map(|(_, vsize)| vsize).sum();
+        let mut percentiles = [FeeRate::ZERO; 5];
+        let mut weight = 0;
+        let mut next = 0;
+        for (rate, vsize) in &rates {
+            weight += vsize;
+            while next < percentiles.len() && weight * 100 >= total * FEE_RATE_PERCENTILES[next] {
+                percentiles[next] = *rate;
+                next += 1;
+            }
+        }
+        let [p10, p25, median, p75, p90] = percentiles;
+
+        Self {
+            height,
+            block_hash: block.block_hash().to_string(),
+            time: block.header.time,
+            tx_count: rates.len() as u64,
+            min: rates.first().map_or(FeeRate::ZERO, |(rate, _)| *rate),
+            p10,
+            p25,
+            median,
+            p75,
+            p90,
+            max: rates.last().map_or(FeeRate::ZERO, |(rate, _)| *rate),
+        }
+    }
+}
+
+/// The mempool's minimum fee rate and size at one moment
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MempoolFeeSample {
+    /// Unix time of the sample
+    pub time: i64,
+    /// Rate a transaction needs to enter the mempool, raised above the relay
+    /// minimum while the mempool is full
+    pub min_fee: FeeRate,
+    pub size: u64,
+    pub bytes: u64,
+}
+
+/// Fee rate time series between two times, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRateHistory {
+    pub from: i64,
+    pub to: i64,
+    pub blocks: Vec<BlockFeeRates>,
+    pub mempool: Vec<MempoolFeeSample>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_subsidy_halvings() {
+        assert_eq!(block_subsidy(0, Network::Bitcoin), 5_000_000_000);
+        assert_eq!(block_subsidy(210_000, Network::Bitcoin), 2_500_000_000);
+        assert_eq!(block_subsidy(150, Network::Regtest), 2_500_000_000);
+        assert_eq!(block_subsidy(150 * 64, Network::Regtest), 0);
+    }
+
+    #[test]
+    fn test_daily_aggregation() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let stats = BlockStats::from_block(&genesis, 0, Network::Regtest);
+        assert_eq!(stats.tx_count, 0);
+        assert_eq!(stats.total_fees, 0);
+
+        let mut daily = DailyStats::new(day_index(genesis.header.time));
+        assert_eq!(daily.date.to_string(), "2011-02-02");
+
+        daily.add_block(&BlockStats { height: 5, total_fees: 300, tx_count: 2, ..stats.clone() });
+        daily.add_block(&BlockStats { height: 4, total_fees: 200, tx_count: 1, ..stats.clone() });
+        assert_eq!(daily.blocks, 2);
+        assert_eq!(daily.total_fees, 500);
+        assert_eq!((daily.first_height, daily.last_height), (4, 5));
+        assert!((daily.mean_weight_utilization - stats.weight_utilization).abs() < 1e-12);
+
+        daily.remove_block(&BlockStats { height: 5, total_fees: 300, tx_count: 2, ..stats.clone() });
+        assert_eq!((daily.blocks, daily.total_fees, daily.tx_count), (1, 200, 1));
+        assert_eq!(daily.last_height, 4);
+
+        let rolling = RollingStats::from_blocks(&[stats.clone(), BlockStats { total_fees: 100, ..stats }]);
+        assert_eq!(rolling.mean_fees_per_block, 50.0);
+    }
+
+    #[test]
+    fn test_block_fee_rate_percentiles() {
+        use bitcoin::absolute::LockTime;
+        use bitcoin::{Amount, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let spend = |vout: u32, value: u64| Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: genesis.txdata[0].txid(), vout },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::new() }],
+        };
+        let mut block = genesis.clone();
+        // Every spent coin holds 100,000 sat, so the fees are 100,000 less the output
+        block.txdata.extend([spend(0, 99_000), spend(1, 95_000), spend(2, 90_000), spend(3, 50_000)]);
+        let rates = BlockFeeRates::from_block(&block, 1, |outpoint| (outpoint.vout < 3).then_some(100_000));
+
+        // The fourth spends a coin of unknown value
+        assert_eq!(rates.tx_count, 3);
+        let vsize = block.txdata[1].vsize() as u64;
+        assert_eq!(rates.min, FeeRate::from_fee(Satoshi(1_000), vsize));
+        assert_eq!(rates.p25, rates.min);
+        assert_eq!(rates.median, FeeRate::from_fee(Satoshi(5_000), vsize));
+        assert_eq!(rates.p90, FeeRate::from_fee(Satoshi(10_000), vsize));
+        assert_eq!(rates.max, rates.p90);
+
+        let empty = BlockFeeRates::from_block(&genesis, 0, |_| None);
+        assert_eq!((empty.tx_count, empty.median), (0, FeeRate::ZERO));
+    }
+
+    #[test]
+    fn test_utxo_distribution_bands() {
+        let mut distribution = UtxoDistribution::new(10_000, String::new());
+        distribution.add(10_000, 500);
+        distribution.add(9_000, 1_000);
+        distribution.add(0, 5_000_000_000);
+
+        assert_eq!(distribution.total_count, 3);
+        assert_eq!(distribution.total_value, 5_000_001_500);
+
+        let age: Vec<u64> = distribution.by_age.iter().map(|band| band.count).collect();
+        // Height 0 is 10,000 blocks old, between one and six months
+        assert_eq!(age, vec![1, 1, 0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(distribution.by_age[2].min, BLOCKS_PER_DAY * 7);
+
+        assert_eq!(distribution.by_amount[0].count, 1);
+        assert_eq!(distribution.by_amount[1].value, 1_000);
+        assert_eq!(distribution.by_amount[7].value, 5_000_000_000);
+    }
+}
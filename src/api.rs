@@ -1,10 +1,49 @@
-use actix_web::{web, HttpResponse, Result as ActixResult};
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
-use tracing::{info, error};
+use std::sync::{Arc, Mutex};
+use tracing::{info, error, warn};
 
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::{GetAddressWatches, GetTxWatches, GetUtxoDeltas, WatchAddress, WatchTransaction};
+use crate::actors::{GetAddressTxs, GetAddressUtxos};
+use crate::actors::{GetEventJournal, GetHeaders, GetPeerTimeline, GetTxProof, VerifyTxProof};
+use crate::actors::{GetBlockAnalytics, GetDailyAnalytics, GetFeeRateHistory, GetUtxoDistribution};
+use crate::actors::{GetReorgs, GetStaleBlocks, SubmitMinerTransaction};
+use crate::actors::{EstimateSmartFee, GetMempoolPackage};
+#[cfg(feature = "miner")]
+use crate::actors::GetStratumWorkers;
+#[cfg(feature = "wallet")]
+use crate::actors::GetWalletHistory;
+use crate::actors::analytics::AnalyticsActor;
+use crate::actors::{GetConnectedPeerVersions, PeerEventKind};
+use crate::actors::{GetBlockHeaderInfo, GetChainTip, GetMempoolInfo, GetPeers, GetStorageSize, MempoolInfo};
+use crate::actors::{ChainTip, GetBlock, GetBlockHash, GetBlockHeight, GetFromMempool, GetTransaction};
+use crate::actors::{FetchBlock, GetBlockLocations, GetHeaderHeight, GetRawBlock, StoreBlock};
+use crate::actors::chain::{ChainActor, ChainTipFeed};
+use crate::actors::mempool::{cpfp_estimate, resolve_spent_outputs, MempoolActor, MempoolDeltaFeed, DEFAULT_CPFP_CHILD_VSIZE};
+use crate::actors::network::NetworkActor;
+use crate::actors::storage::{StorageActor, MAX_BLOCK_LOCATIONS};
+#[cfg(feature = "miner")]
+use crate::actors::stratum::StratumActor;
+#[cfg(feature = "wallet")]
+use crate::actors::wallet::WalletActor;
+use crate::actors::watch::{parse_watch_target, WatchActor, MAX_WATCH_CONFIRMATIONS};
+use crate::broadcast::Broadcaster;
+use crate::cache::BoundedCache;
 use crate::config::Config;
-use crate::events::EventManager;
-use crate::error::ApiResult;
+use crate::events::{BitcoinEvent, EventManager, EventTopic, PublisherStatus};
+use crate::peerstats::{PeerBreakdown, PeerVersion};
+use crate::amount::Satoshi;
+use crate::policy::BroadcastLimits;
+use crate::error::{ApiResult, BroadcastError};
+use crate::error::SnapshotError;
+use crate::snapshot::{restore_snapshot, snapshot_path, take_snapshot, NodeSnapshot};
+use crate::mempoolcapture::{capture_dir, diff_captures, list_captures, read_compressed, MempoolCapture};
+use crate::streams::{sse_events, EventStreamSession, MempoolDeltaSession};
+use crate::rpc::RpcService;
+use crate::rpc::ws::RpcWebSocketSession;
 
 #[derive(Serialize)]
 pub struct HealthResponse {
@@ -31,6 +70,8 @@ pub struct StatsResponse {
     pub peers_connected: u64,
     pub uptime_seconds: u64,
     pub memory_usage_mb: f64,
+    /// Delivery counters, latency and circuit state per event publisher
+    pub publishers: Vec<PublisherStatus>,
 }
 
 pub async fn health() -> ActixResult<HttpResponse> {
@@ -44,6 +85,13 @@ pub async fn health() -> ActixResult<HttpResponse> {
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Startup stage and progress, 503 until the node is ready
+pub async fn startup() -> ActixResult<HttpResponse> {
+    let status = crate::startup::status();
+    let mut response = if status.ready { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+    Ok(response.json(status))
+}
+
 pub async fn node_info(
     _config: web::Data<Config>,
 ) -> ActixResult<HttpResponse> {
@@ -60,7 +108,7 @@ pub async fn node_info(
     Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn stats() -> ActixResult<HttpResponse> {
+pub async fn stats(event_manager: web::Data<EventManager>) -> ActixResult<HttpResponse> {
     // TODO: Get actual metrics
     let response = StatsResponse {
         blocks_processed: 0,
@@ -68,15 +116,247 @@ pub async fn stats() -> ActixResult<HttpResponse> {
         peers_connected: 0,
         uptime_seconds: 0,
         memory_usage_mb: 0.0,
+        publishers: event_manager.publisher_statuses().await,
     };
 
     Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn peers() -> ActixResult<HttpResponse> {
-    // TODO: Get actual peer list from network actor
-    let peers: Vec<serde_json::Value> = vec![];
-    Ok(HttpResponse::Ok().json(peers))
+/// How long each actor gets to answer a metrics snapshot query
+const SNAPSHOT_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Serialize)]
+pub struct ChainMetrics {
+    pub height: u64,
+    pub best_block_hash: String,
+    pub time: u32,
+    pub median_time: u32,
+    pub difficulty: f64,
+    pub chainwork: String,
+}
+
+#[derive(Serialize)]
+pub struct NetworkMetrics {
+    pub peer_count: usize,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Serialize)]
+pub struct StorageMetrics {
+    pub size_bytes: u64,
+}
+
+/// Everything `/metrics.json` reports. A section is null when its actor failed
+/// or did not answer in time, with the reason under `errors`.
+#[derive(Serialize)]
+pub struct MetricsSnapshot {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub chain: Option<ChainMetrics>,
+    pub mempool: Option<MempoolInfo>,
+    pub network: Option<NetworkMetrics>,
+    pub storage: Option<StorageMetrics>,
+    pub process: crate::metrics::ProcessMetrics,
+    pub errors: std::collections::BTreeMap<&'static str, String>,
+}
+
+/// Await an actor reply, folding mailbox errors, handler errors and timeouts into one message
+async fn snapshot_query<T, E: std::fmt::Display>(
+    request: impl std::future::Future<Output = Result<Result<T, E>, actix::MailboxError>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(SNAPSHOT_QUERY_TIMEOUT, request).await {
+        Ok(Ok(Ok(value))) => Ok(value),
+        Ok(Ok(Err(e))) => Err(e.to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no reply within {}s", SNAPSHOT_QUERY_TIMEOUT.as_secs())),
+    }
+}
+
+fn snapshot_section<T>(
+    errors: &mut std::collections::BTreeMap<&'static str, String>,
+    name: &'static str,
+    result: Result<T, String>,
+) -> Option<T> {
+    result.map_err(|e| errors.insert(name, e)).ok()
+}
+
+async fn chain_metrics(storage_actor: &Addr<StorageActor>) -> Result<Option<ChainMetrics>, String> {
+    let Some(tip) = snapshot_query(storage_actor.send_traced(GetChainTip)).await? else {
+        return Ok(None);
+    };
+    let info = snapshot_query(storage_actor.send_traced(GetBlockHeaderInfo { hash: tip.hash }))
+        .await?
+        .ok_or_else(|| format!("no header stored for tip {}", tip.hash))?;
+
+    Ok(Some(ChainMetrics {
+        height: info.height,
+        best_block_hash: tip.hash.to_string(),
+        time: info.header.time,
+        median_time: info.median_time,
+        difficulty: info.header.difficulty_float(),
+        chainwork: crate::rpc::chain_work_hex(&info),
+    }))
+}
+
+/// A consolidated status snapshot for clients that would rather make one call
+/// than scrape the Prometheus endpoint. The chain, mempool, network and storage
+/// queries run in parallel, so a slow actor delays the response by at most
+/// `SNAPSHOT_QUERY_TIMEOUT`.
+pub async fn metrics_json(
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+    network_actor: web::Data<Addr<NetworkActor>>,
+) -> ActixResult<HttpResponse> {
+    let (chain, mempool, peers, storage) = tokio::join!(
+        chain_metrics(&storage_actor),
+        snapshot_query(mempool_actor.send_traced(GetMempoolInfo)),
+        snapshot_query(network_actor.send(GetPeers)),
+        snapshot_query(storage_actor.send_traced(GetStorageSize)),
+    );
+
+    let mut errors = std::collections::BTreeMap::new();
+    let chain = snapshot_section(&mut errors, "chain", chain).flatten();
+    let mempool = snapshot_section(&mut errors, "mempool", mempool);
+    let network = snapshot_section(&mut errors, "network", peers).map(|peers| NetworkMetrics {
+        peer_count: peers.len(),
+        bytes_sent: peers.iter().map(|p| p.bytes_sent).sum(),
+        bytes_received: peers.iter().map(|p| p.bytes_received).sum(),
+    });
+    let storage = snapshot_section(&mut errors, "storage", storage).map(|size_bytes| StorageMetrics { size_bytes });
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-cache"))
+        .json(MetricsSnapshot {
+            timestamp: chrono::Utc::now(),
+            chain,
+            mempool,
+            network,
+            storage,
+            process: crate::metrics::process_metrics(),
+            errors,
+        }))
+}
+
+pub async fn peers(network_actor: web::Data<Addr<NetworkActor>>) -> ActixResult<HttpResponse> {
+    match network_actor.send(GetPeers).await {
+        Ok(Ok(peers)) => Ok(HttpResponse::Ok().json(peers)),
+        Ok(Err(e)) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+        Err(e) => {
+            error!("Network actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PeerSummaryResponse {
+    pub connected: PeerBreakdown,
+    /// Distinct addresses with a successful handshake in the persisted peer
+    /// timeline, using the latest handshake from each
+    pub seen: PeerBreakdown,
+    /// Oldest event the timeline still holds, bounding how far back `seen` reaches
+    pub seen_since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Network composition from this node's vantage point: connected peers and
+/// every peer the timeline remembers, by user agent, service bit and protocol version.
+pub async fn peer_summary(
+    storage_actor: web::Data<Addr<StorageActor>>,
+    network_actor: web::Data<Addr<NetworkActor>>,
+) -> ActixResult<HttpResponse> {
+    let history = GetPeerTimeline {
+        after_seq: None,
+        peer_id: None,
+        since: None,
+        until: None,
+        limit: usize::MAX,
+    };
+    let (connected, history) = tokio::join!(
+        network_actor.send(GetConnectedPeerVersions),
+        storage_actor.send_traced(history),
+    );
+    let connected = match connected {
+        Ok(connected) => connected,
+        Err(e) => {
+            error!("Network actor unavailable: {}", e);
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+    let events = match history {
+        Ok(Ok(events)) => events,
+        Ok(Err(e)) => {
+            error!("Failed to load peer timeline: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+
+    let seen_since = events.first().map(|event| event.timestamp);
+    let mut seen = std::collections::HashMap::new();
+    for event in events {
+        if let PeerEventKind::Handshake { success: true, version, user_agent, services, .. } = event.kind {
+            let key = event.address.unwrap_or(event.peer_id);
+            seen.insert(key, PeerVersion { user_agent, services, version });
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(PeerSummaryResponse {
+        connected: PeerBreakdown::from_peers(&connected),
+        seen: PeerBreakdown::from_peers(seen.values()),
+        seen_since,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct PeerTimelineQuery {
+    /// Only events after this sequence number, for paging
+    pub after: Option<u64>,
+    pub peer: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_PEER_TIMELINE_LIMIT: usize = 100;
+const MAX_PEER_TIMELINE_LIMIT: usize = 1000;
+
+/// Persisted peer lifecycle events, oldest first, for reconstructing network behaviour around an incident
+pub async fn peer_timeline(
+    query: web::Query<PeerTimelineQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let query = query.into_inner();
+    let request = GetPeerTimeline {
+        after_seq: query.after,
+        peer_id: query.peer,
+        since: query.since,
+        until: query.until,
+        limit: query.limit.unwrap_or(DEFAULT_PEER_TIMELINE_LIMIT).min(MAX_PEER_TIMELINE_LIMIT),
+    };
+
+    match storage_actor.send_traced(request).await {
+        Ok(Ok(events)) => Ok(HttpResponse::Ok().json(events)),
+        Ok(Err(e)) => {
+            error!("Failed to load peer timeline: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
 }
 
 pub async fn mempool() -> ActixResult<HttpResponse> {
@@ -93,30 +373,1263 @@ pub async fn mempool() -> ActixResult<HttpResponse> {
     Ok(HttpResponse::Ok().json(mempool_info))
 }
 
+#[derive(Deserialize)]
+pub struct MempoolStreamQuery {
+    pub snapshot: Option<bool>,
+}
+
+pub async fn mempool_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<MempoolStreamQuery>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+    deltas: web::Data<MempoolDeltaFeed>,
+) -> ActixResult<HttpResponse> {
+    let session = MempoolDeltaSession::new(
+        mempool_actor.get_ref().clone(),
+        deltas.get_ref().clone(),
+        query.snapshot.unwrap_or(true),
+    );
+    ws::start(session, &req, stream)
+}
+
+#[derive(Deserialize)]
+pub struct EventStreamQuery {
+    /// Comma-separated topics to start with, every topic when left out
+    pub topics: Option<String>,
+}
+
+/// Live events over a WebSocket; clients send `{"subscribe": [..]}` or
+/// `{"unsubscribe": [..]}` to change topics
+pub async fn event_stream(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<EventStreamQuery>,
+    event_manager: web::Data<EventManager>,
+) -> ActixResult<HttpResponse> {
+    let topics = match parse_topics(query.topics.as_deref()) {
+        Ok(topics) => topics,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    ws::start(EventStreamSession::new(event_manager.get_ref().clone(), topics), &req, stream)
+}
+
+/// Parse comma-separated topics, every topic when none are given
+fn parse_topics(topics: Option<&str>) -> Result<std::collections::HashSet<EventTopic>, String> {
+    match topics {
+        None => Ok(EventTopic::ALL.into_iter().collect()),
+        Some(topics) => topics.split(',').filter(|topic| !topic.is_empty()).map(str::parse).collect(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SseQuery {
+    pub topics: Option<String>,
+    /// Resume after this sequence on the first connection; reconnects send `Last-Event-ID`
+    pub since_seq: Option<u64>,
+}
+
+/// Live events as Server-Sent Events, resuming from the journal after `Last-Event-ID`
+pub async fn sse_stream(
+    req: HttpRequest,
+    query: web::Query<SseQuery>,
+    event_manager: web::Data<EventManager>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let topics = match parse_topics(query.topics.as_deref()) {
+        Ok(topics) => topics,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    let last_event_id = req.headers().get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    // Subscribed before the journal is read so nothing falls in between
+    let live = event_manager.subscribe();
+    let mut replay: Vec<BitcoinEvent> = Vec::new();
+    let since_seq = last_event_id.or(query.since_seq);
+    if let Some(since_seq) = since_seq {
+        loop {
+            let request = GetEventJournal {
+                since_seq: replay.last().map(|event| event.sequence).unwrap_or(since_seq),
+                limit: MAX_EVENT_REPLAY_LIMIT,
+            };
+            let page = match storage_actor.send_traced(request).await {
+                Ok(Ok(page)) => page,
+                Ok(Err(e)) => {
+                    error!("Failed to read event journal: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(serde_json::json!({ "error": e.to_string() })));
+                }
+                Err(e) => {
+                    error!("Storage actor unavailable: {}", e);
+                    return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() })));
+                }
+            };
+            let exhausted = page.events.len() < MAX_EVENT_REPLAY_LIMIT;
+            replay.extend(page.events);
+            if exhausted {
+                break;
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        // Keep reverse proxies from buffering the stream
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(sse_events(live, since_seq.unwrap_or(0), replay, topics)))
+}
+
+/// JSON-RPC over WebSocket, with the RPC server's credentials; see [`crate::rpc::ws`]
+pub async fn rpc_websocket(
+    req: HttpRequest,
+    stream: web::Payload,
+    rpc_service: Option<web::Data<RpcService>>,
+    tip_feed: web::Data<ChainTipFeed>,
+    deltas: web::Data<MempoolDeltaFeed>,
+) -> ActixResult<HttpResponse> {
+    let Some(rpc_service) = rpc_service else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "error": "RPC is disabled" })));
+    };
+    let authorization = req.headers().get(actix_web::http::header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if !rpc_service.auth.authorized_basic(authorization) {
+        warn!("Rejected RPC WebSocket with missing or incorrect credentials");
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\""))
+            .finish());
+    }
+    let session = RpcWebSocketSession::new(
+        rpc_service.io.clone(),
+        rpc_service.auth.clone(),
+        rpc_service.json_format,
+        tip_feed.get_ref().clone(),
+        deltas.get_ref().clone(),
+    );
+    ws::start(session, &req, stream)
+}
+
+/// Per-endpoint request counts, error rates and latency percentiles over the usage window
+pub async fn usage_report(usage: web::Data<crate::usage::UsageTracker>) -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usage.report()))
+}
+
+pub async fn event_publishers(event_manager: web::Data<EventManager>) -> ActixResult<HttpResponse> {
+    let publishers = event_manager.publisher_statuses().await;
+    Ok(HttpResponse::Ok().json(publishers))
+}
+
+#[derive(Deserialize)]
+pub struct EventReplayQuery {
+    /// Only events with a higher sequence number; 0 replays the whole journal
+    pub since_seq: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_EVENT_REPLAY_LIMIT: usize = 100;
+const MAX_EVENT_REPLAY_LIMIT: usize = 1000;
+
+/// Journaled events after `since_seq`, oldest first, for consumers to fill a
+/// gap in their webhook, NATS or ZMQ feed
+pub async fn event_replay(
+    query: web::Query<EventReplayQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let request = GetEventJournal {
+        since_seq: query.since_seq.unwrap_or(0),
+        limit: query.limit.unwrap_or(DEFAULT_EVENT_REPLAY_LIMIT).clamp(1, MAX_EVENT_REPLAY_LIMIT),
+    };
+
+    match storage_actor.send_traced(request).await {
+        Ok(Ok(page)) => Ok(HttpResponse::Ok().json(page)),
+        Ok(Err(e)) => {
+            error!("Failed to read event journal: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WebhookAckRequest {
+    pub endpoint: String,
+    /// Every event up to this `X-Event-Sequence` has been processed
+    pub sequence: u64,
+}
+
+/// Checkpoint a webhook consumer; acknowledged events are not sent to it again.
+/// The caller needs the RPC credentials, or an `X-Signature-256` over the body
+/// made with the endpoint's webhook secret
+pub async fn webhook_ack(
+    req: HttpRequest,
+    body: web::Bytes,
+    config: web::Data<Config>,
+    rpc_service: Option<web::Data<RpcService>>,
+    event_manager: web::Data<EventManager>,
+) -> ActixResult<HttpResponse> {
+    let ack: WebhookAckRequest = match serde_json::from_slice(&body) {
+        Ok(ack) => ack,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))),
+    };
+    let authorization = req.headers().get(actix_web::http::header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    let rpc_authorized = rpc_service.is_some_and(|rpc_service| rpc_service.auth.authorized_basic(authorization));
+    let signature = req.headers().get("X-Signature-256").and_then(|value| value.to_str().ok());
+    let signed = config.events.webhook.endpoints.iter()
+        .find(|endpoint| endpoint.url() == ack.endpoint)
+        .and_then(|endpoint| endpoint.secret())
+        .zip(signature)
+        .is_some_and(|(secret, given)| token_matches(given, &crate::events::webhook_signature(secret, &body)));
+    if !rpc_authorized && !signed {
+        warn!("Rejected webhook acknowledgement for {} without credentials or signature", ack.endpoint);
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\""))
+            .json(serde_json::json!({ "error": "Not authorized to acknowledge webhook events" })));
+    }
+
+    match event_manager.acknowledge("webhook", &ack.endpoint, ack.sequence).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "endpoint": ack.endpoint,
+            "acknowledged": ack.sequence,
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+/// The node's identity key, with the signed node-info document when `identity.announce` is on
+pub async fn identity(config: web::Data<Config>, event_manager: web::Data<EventManager>) -> ActixResult<HttpResponse> {
+    let Some(identity) = event_manager.identity() else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "error": "node identity is not loaded" })));
+    };
+    if !config.identity.announce {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "pubkey": identity.pubkey() })));
+    }
+    Ok(HttpResponse::Ok().json(identity.announcement(&config, crate::clock::now())))
+}
+
+/// This node's role, the current leader and the registered members
+pub async fn cluster(cluster: web::Data<crate::cluster::Cluster>) -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(cluster.status()))
+}
+
+#[derive(Deserialize)]
+pub struct ChainTipQuery {
+    /// Long-poll: hold the request (e.g. `30s`) while the tip still matches `If-None-Match`
+    pub wait: Option<String>,
+}
+
+/// Longest a tip long-poll is held open
+const MAX_TIP_WAIT: std::time::Duration = std::time::Duration::from_secs(120);
+
+/// Parse a `wait` of whole seconds, with or without an `s` suffix
+fn parse_tip_wait(wait: &str) -> Option<std::time::Duration> {
+    let secs = wait.strip_suffix('s').unwrap_or(wait).parse().ok()?;
+    Some(std::time::Duration::from_secs(secs).min(MAX_TIP_WAIT))
+}
+
+fn tip_etag(tip: &ChainTip) -> String {
+    format!("\"{}-{}\"", tip.height, tip.hash)
+}
+
+/// The active chain tip, cheap enough to poll.
+///
+/// Clients send back the ETag in `If-None-Match` and get a 304 while the tip is
+/// unchanged; with `?wait=30s` the request is held until a new tip arrives or
+/// the wait runs out.
+pub async fn chain_tip(
+    req: HttpRequest,
+    query: web::Query<ChainTipQuery>,
+    tip_feed: web::Data<ChainTipFeed>,
+) -> ActixResult<HttpResponse> {
+    let wait = match query.wait.as_deref().map(parse_tip_wait) {
+        None => std::time::Duration::ZERO,
+        Some(Some(wait)) => wait,
+        Some(None) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "wait must be a number of seconds, e.g. 30s"
+            })));
+        }
+    };
+    let known_etag = req
+        .headers()
+        .get(actix_web::http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let mut tip = tip_feed.current();
+    if let (Some(current), Some(known)) = (tip, known_etag.as_deref()) {
+        if known == tip_etag(&current) && !wait.is_zero() {
+            tip = tip_feed.wait_for_change(current.hash, wait).await;
+        }
+    }
+
+    let Some(tip) = tip else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "No chain tip yet"
+        })));
+    };
+    let etag = tip_etag(&tip);
+    if known_etag.as_deref() == Some(etag.as_str()) {
+        return Ok(HttpResponse::NotModified().insert_header(("ETag", etag)).finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header(("ETag", etag))
+        .insert_header(("Cache-Control", "no-cache"))
+        .json(serde_json::json!({
+            "height": tip.height,
+            "hash": tip.hash.to_string(),
+        })))
+}
+
+#[derive(Deserialize)]
+pub struct UtxoDeltasQuery {
+    pub since_height: u64,
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_UTXO_DELTA_LIMIT: usize = 100;
+const MAX_UTXO_DELTA_LIMIT: usize = 1000;
+
+/// Per-block UTXO deltas above `since_height`, for indexers catching up after downtime
+pub async fn utxo_deltas(
+    query: web::Query<UtxoDeltasQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_UTXO_DELTA_LIMIT).min(MAX_UTXO_DELTA_LIMIT);
+    let request = GetUtxoDeltas {
+        since_height: query.since_height,
+        limit,
+    };
+
+    match storage_actor.send_traced(request).await {
+        Ok(Ok(deltas)) => Ok(HttpResponse::Ok().json(deltas)),
+        Ok(Err(e)) => {
+            error!("Failed to load UTXO deltas: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BlockAnalyticsQuery {
+    pub count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct DailyAnalyticsQuery {
+    pub days: Option<u32>,
+}
+
+const DEFAULT_ANALYTICS_BLOCKS: u64 = 144;
+const MAX_ANALYTICS_BLOCKS: u64 = 2016;
+const DEFAULT_ANALYTICS_DAYS: u32 = 30;
+const MAX_ANALYTICS_DAYS: u32 = 366;
+
+/// Fee and fullness statistics for recent blocks with a rolling summary
+pub async fn block_analytics(
+    query: web::Query<BlockAnalyticsQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let count = query.count.unwrap_or(DEFAULT_ANALYTICS_BLOCKS).min(MAX_ANALYTICS_BLOCKS);
+
+    match storage_actor.send_traced(GetBlockAnalytics { count }).await {
+        Ok(Ok(analytics)) => Ok(HttpResponse::Ok().json(analytics)),
+        Ok(Err(e)) => {
+            error!("Failed to load block analytics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+/// Per-day fee totals, transaction counts and block fullness
+pub async fn daily_analytics(
+    query: web::Query<DailyAnalyticsQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let days = query.days.unwrap_or(DEFAULT_ANALYTICS_DAYS).min(MAX_ANALYTICS_DAYS);
+
+    match storage_actor.send_traced(GetDailyAnalytics { days }).await {
+        Ok(Ok(stats)) => Ok(HttpResponse::Ok().json(stats)),
+        Ok(Err(e)) => {
+            error!("Failed to load daily analytics: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FeeRateHistoryQuery {
+    /// Unix time, a week before `to` by default
+    pub from: Option<i64>,
+    /// Unix time, now by default
+    pub to: Option<i64>,
+}
+
+const DEFAULT_FEE_HISTORY_SECS: i64 = 7 * 86_400;
+
+/// Fee rate percentiles of the blocks timestamped between `from` and `to`,
+/// with the mempool minimum fee sampled over the same span, for fee charts
+pub async fn fee_rate_history(
+    query: web::Query<FeeRateHistoryQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - DEFAULT_FEE_HISTORY_SECS);
+    if from > to {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from is after to"
+        })));
+    }
+
+    match storage_actor.send_traced(GetFeeRateHistory { from, to }).await {
+        Ok(Ok(history)) => Ok(HttpResponse::Ok().json(history)),
+        Ok(Err(e)) => {
+            error!("Failed to load fee rate history: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+/// Latest UTXO set breakdown by coin age and amount from the periodic scan
+pub async fn utxo_distribution(
+    analytics_actor: web::Data<Addr<AnalyticsActor>>,
+) -> ActixResult<HttpResponse> {
+    match analytics_actor.send(GetUtxoDistribution).await {
+        Ok(Some(distribution)) => Ok(HttpResponse::Ok().json(distribution)),
+        Ok(None) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "UTXO distribution has not been computed yet"
+        }))),
+        Err(e) => {
+            error!("Analytics actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChainHistoryQuery {
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_CHAIN_HISTORY_LIMIT: usize = 50;
+const MAX_CHAIN_HISTORY_LIMIT: usize = 1000;
+
+/// Recorded chain reorganizations, newest first
+pub async fn reorgs(
+    query: web::Query<ChainHistoryQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_CHAIN_HISTORY_LIMIT).min(MAX_CHAIN_HISTORY_LIMIT);
+
+    match storage_actor.send_traced(GetReorgs { limit }).await {
+        Ok(Ok(records)) => Ok(HttpResponse::Ok().json(records)),
+        Ok(Err(e)) => {
+            error!("Failed to load reorg history: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+/// Archived side chain and reorged-out blocks, highest first
+pub async fn stale_blocks(
+    query: web::Query<ChainHistoryQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_CHAIN_HISTORY_LIMIT).min(MAX_CHAIN_HISTORY_LIMIT);
+
+    match storage_actor.send_traced(GetStaleBlocks { limit }).await {
+        Ok(Ok(blocks)) => Ok(HttpResponse::Ok().json(blocks)),
+        Ok(Err(e)) => {
+            error!("Failed to load stale blocks: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Storage actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WatchTxRequest {
+    pub txid: String,
+    pub confirmations: u32,
+    pub callback_url: Option<String>,
+}
+
+/// Register a transaction to be reported as it confirms, up to the requested depth
+pub async fn watch_tx(
+    request: web::Json<WatchTxRequest>,
+    watch_actor: web::Data<Addr<WatchActor>>,
+) -> ActixResult<HttpResponse> {
+    let txid = match request.txid.parse::<bitcoin::Txid>() {
+        Ok(txid) => txid,
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid txid"
+            })));
+        }
+    };
+
+    if request.confirmations == 0 || request.confirmations > MAX_WATCH_CONFIRMATIONS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("confirmations must be between 1 and {}", MAX_WATCH_CONFIRMATIONS)
+        })));
+    }
+
+    let message = WatchTransaction {
+        txid,
+        confirmations: request.confirmations,
+        callback_url: request.callback_url.clone(),
+    };
+
+    match watch_actor.send(message).await {
+        Ok(Ok(status)) => Ok(HttpResponse::Created().json(status)),
+        Ok(Err(e)) => {
+            error!("Failed to register transaction watch: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Watch actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+pub async fn tx_watches(watch_actor: web::Data<Addr<WatchActor>>) -> ActixResult<HttpResponse> {
+    match watch_actor.send(GetTxWatches).await {
+        Ok(Ok(watches)) => Ok(HttpResponse::Ok().json(watches)),
+        Ok(Err(e)) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WatchAddressRequest {
+    pub address: String,
+    pub confirmations: Option<u32>,
+    pub callback_url: Option<String>,
+}
+
+const DEFAULT_PAYMENT_CONFIRMATIONS: u32 = 6;
+
+/// Register an address or descriptor to be reported when it receives funds
+pub async fn watch_address(
+    request: web::Json<WatchAddressRequest>,
+    config: web::Data<Config>,
+    watch_actor: web::Data<Addr<WatchActor>>,
+) -> ActixResult<HttpResponse> {
+    let script_pubkey = match parse_watch_target(&request.address, config.network.to_bitcoin_network()) {
+        Ok(script_pubkey) => script_pubkey,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    let confirmations = request.confirmations.unwrap_or(DEFAULT_PAYMENT_CONFIRMATIONS);
+    if confirmations == 0 || confirmations > MAX_WATCH_CONFIRMATIONS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("confirmations must be between 1 and {}", MAX_WATCH_CONFIRMATIONS)
+        })));
+    }
+
+    let message = WatchAddress {
+        address: request.address.clone(),
+        script_pubkey,
+        confirmations,
+        callback_url: request.callback_url.clone(),
+    };
+
+    match watch_actor.send(message).await {
+        Ok(Ok(status)) => Ok(HttpResponse::Created().json(status)),
+        Ok(Err(e)) => {
+            error!("Failed to register address watch: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => {
+            error!("Watch actor unavailable: {}", e);
+            Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+    }
+}
+
+pub async fn address_watches(watch_actor: web::Data<Addr<WatchActor>>) -> ActixResult<HttpResponse> {
+    match watch_actor.send(GetAddressWatches).await {
+        Ok(Ok(watches)) => Ok(HttpResponse::Ok().json(watches)),
+        Ok(Err(e)) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AddressTxsQuery {
+    pub before_height: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct AddressUtxosQuery {
+    pub limit: Option<usize>,
+}
+
+const DEFAULT_ADDRESS_LIMIT: usize = 50;
+const MAX_ADDRESS_LIMIT: usize = 1000;
+
+/// The script behind an address path segment, which may also be a `raw()` script
+fn address_script(address: &str, config: &Config) -> Result<bitcoin::ScriptBuf, HttpResponse> {
+    if !config.storage.address_index {
+        return Err(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Address index is disabled (set storage.address_index)"
+        })));
+    }
+    parse_watch_target(address, config.network.to_bitcoin_network()).map_err(|e| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        }))
+    })
+}
+
+fn address_limit(limit: Option<usize>) -> Result<usize, HttpResponse> {
+    match limit.unwrap_or(DEFAULT_ADDRESS_LIMIT) {
+        limit @ 1..=MAX_ADDRESS_LIMIT => Ok(limit),
+        _ => Err(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("limit must be between 1 and {}", MAX_ADDRESS_LIMIT)
+        }))),
+    }
+}
+
+/// Confirmed transactions paying or spending from an address, newest first;
+/// page back by passing the last height seen as `before_height`
+pub async fn address_txs(
+    path: web::Path<String>,
+    query: web::Query<AddressTxsQuery>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let (script_pubkey, limit) = match (address_script(&path, &config), address_limit(query.limit)) {
+        (Ok(script_pubkey), Ok(limit)) => (script_pubkey, limit),
+        (Err(response), _) | (_, Err(response)) => return Ok(response),
+    };
+
+    let message = GetAddressTxs { script_pubkey, before_height: query.before_height, limit };
+    match storage_actor.send_traced(message).await {
+        Ok(Ok(txs)) => Ok(HttpResponse::Ok().json(txs)),
+        Ok(Err(e)) => Ok(storage_unavailable(e)),
+        Err(e) => Ok(storage_unavailable(e)),
+    }
+}
+
+/// Confirmed unspent outputs paying an address, newest first
+pub async fn address_utxos(
+    path: web::Path<String>,
+    query: web::Query<AddressUtxosQuery>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let (script_pubkey, limit) = match (address_script(&path, &config), address_limit(query.limit)) {
+        (Ok(script_pubkey), Ok(limit)) => (script_pubkey, limit),
+        (Err(response), _) | (_, Err(response)) => return Ok(response),
+    };
+
+    match storage_actor.send_traced(GetAddressUtxos { script_pubkey, limit }).await {
+        Ok(Ok(utxos)) => Ok(HttpResponse::Ok().json(utxos)),
+        Ok(Err(e)) => Ok(storage_unavailable(e)),
+        Err(e) => Ok(storage_unavailable(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TxProofQuery {
+    pub block_hash: Option<String>,
+}
+
+/// SPV inclusion proof for a confirmed transaction
+pub async fn tx_proof(
+    path: web::Path<String>,
+    query: web::Query<TxProofQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let txid = match path.parse::<bitcoin::Txid>() {
+        Ok(txid) => txid,
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid txid"
+            })));
+        }
+    };
+    let block_hash = match query.block_hash.as_deref().map(str::parse::<bitcoin::BlockHash>) {
+        Some(Ok(hash)) => Some(hash),
+        Some(Err(_)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid block_hash"
+            })));
+        }
+        None => None,
+    };
+
+    match storage_actor.send_traced(GetTxProof { txid, block_hash }).await {
+        Ok(Ok(Some(proof))) => Ok(HttpResponse::Ok().json(proof)),
+        Ok(Ok(None)) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Transaction not found in any indexed block"
+        }))),
+        Ok(Err(e)) => {
+            error!("Failed to build proof for {}: {}", txid, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })))
+        }
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyTxProofRequest {
+    /// Hex serialized merkle block, as returned in `merkle_block`
+    pub proof: String,
+}
+
+pub async fn verify_tx_proof(
+    request: web::Json<VerifyTxProofRequest>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    use bitcoin::hex::FromHex;
+
+    let merkle_block = match Vec::<u8>::from_hex(&request.proof)
+        .ok()
+        .and_then(|bytes| bitcoin::consensus::deserialize::<bitcoin::MerkleBlock>(&bytes).ok())
+    {
+        Some(merkle_block) => merkle_block,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Proof is not a valid serialized merkle block"
+            })));
+        }
+    };
+
+    match storage_actor.send_traced(VerifyTxProof { merkle_block }).await {
+        Ok(Ok(verification)) => Ok(HttpResponse::Ok().json(verification)),
+        Ok(Err(e)) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HeadersQuery {
+    pub start: Option<u64>,
+    pub count: Option<usize>,
+    pub interval: Option<u64>,
+    pub format: Option<String>,
+}
+
+/// Same limit as a P2P `headers` message
+const MAX_HEADERS_PER_REQUEST: usize = 2000;
+
+/// Export the active header chain for seeding SPV clients.
+///
+/// The ETag tracks the chain tip, so clients can poll with `If-None-Match` and
+/// continue from `X-Next-Start-Height` once the tip moves.
+pub async fn headers(
+    req: HttpRequest,
+    query: web::Query<HeadersQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let binary = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("binary") => true,
+        Some(other) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Unsupported format: {}", other)
+            })));
+        }
+    };
+
+    let start_height = query.start.unwrap_or(0);
+    let interval = query.interval.unwrap_or(1).max(1);
+    let request = GetHeaders {
+        start_height,
+        count: query.count.unwrap_or(MAX_HEADERS_PER_REQUEST).min(MAX_HEADERS_PER_REQUEST),
+        interval,
+    };
+
+    let export = match storage_actor.send_traced(request).await {
+        Ok(Ok(export)) => export,
+        Ok(Err(e)) => {
+            error!("Failed to export headers: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    let etag = format!(
+        "\"{}-{}\"",
+        export.tip_height.map(|height| height.to_string()).unwrap_or_default(),
+        export.tip_hash.clone().unwrap_or_default()
+    );
+    let not_modified = req
+        .headers()
+        .get(actix_web::http::header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value == etag)
+        .unwrap_or(false);
+    if not_modified {
+        return Ok(HttpResponse::NotModified().insert_header(("ETag", etag)).finish());
+    }
+
+    let next_start = export
+        .headers
+        .last()
+        .map(|record| record.height + interval)
+        .unwrap_or(start_height);
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(("ETag", etag))
+        .insert_header(("X-Next-Start-Height", next_start.to_string()));
+    if let Some(tip_height) = export.tip_height {
+        response.insert_header(("X-Tip-Height", tip_height.to_string()));
+    }
+
+    if binary {
+        use bitcoin::hex::FromHex;
+
+        // Raw 80-byte headers back to back, in height order
+        let mut body = Vec::with_capacity(export.headers.len() * 80);
+        for record in &export.headers {
+            if let Ok(bytes) = Vec::<u8>::from_hex(&record.header) {
+                body.extend_from_slice(&bytes);
+            }
+        }
+        Ok(response.content_type("application/octet-stream").body(body))
+    } else {
+        Ok(response.json(export))
+    }
+}
+
+/// Serialized responses for immutable resources (blocks by hash, transactions by
+/// txid), shared between HTTP workers so repeat requests skip storage and JSON encoding
+#[derive(Clone)]
+pub struct ResponseCache {
+    entries: Arc<Mutex<BoundedCache<String, web::Bytes>>>,
+}
+
+impl ResponseCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(BoundedCache::new("api_responses", max_bytes))),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<web::Bytes> {
+        self.entries.lock().unwrap().get(&key.to_string()).cloned()
+    }
+
+    pub fn insert(&self, key: String, body: web::Bytes) {
+        let mut entries = self.entries.lock().unwrap();
+        let size = body.len() + key.len();
+        entries.insert(key, body, size);
+        crate::metrics::record_cache_stats(&entries.stats());
+    }
+}
+
+/// Marks a response as safe for clients and proxies to keep forever
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+fn cached_json(body: web::Bytes, hit: bool, cache_control: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Cache-Control", cache_control))
+        .insert_header(("X-Cache", if hit { "HIT" } else { "MISS" }))
+        .body(body)
+}
+
+fn storage_unavailable(e: impl std::fmt::Display) -> HttpResponse {
+    error!("Storage request failed: {}", e);
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({
+        "error": e.to_string()
+    }))
+}
+
 #[derive(Deserialize)]
 pub struct GetBlockQuery {
     pub hash: Option<String>,
     pub height: Option<u64>,
+    /// Decode every transaction instead of listing txids
+    pub verbose: Option<bool>,
+    /// With `height`, hold the request (e.g. `30s`) until the chain reaches it
+    pub wait_for_height: Option<String>,
+}
+
+/// Active chain blocks by hash or height; by-hash responses are cached and immutable
+pub async fn get_block(
+    query: web::Query<GetBlockQuery>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    tip_feed: web::Data<ChainTipFeed>,
+    cache: web::Data<ResponseCache>,
+) -> ActixResult<HttpResponse> {
+    if let (None, Some(height), Some(wait)) = (&query.hash, query.height, &query.wait_for_height) {
+        let Some(wait) = parse_tip_wait(wait) else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "wait_for_height must be a number of seconds"
+            })));
+        };
+        // Clients that just submitted a block can ask for it before it is connected
+        tip_feed.wait_for_height(height, Some(wait)).await;
+    }
+    let (hash, by_hash) = match (&query.hash, query.height) {
+        (Some(hash), _) => match hash.parse::<bitcoin::BlockHash>() {
+            Ok(hash) => (hash, true),
+            Err(_) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Invalid block hash"
+                })));
+            }
+        },
+        (None, Some(height)) => match storage_actor.send_traced(GetBlockHash { height }).await {
+            Ok(Ok(Some(hash))) => (hash, false),
+            Ok(Ok(None)) => {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Block height out of range"
+                })));
+            }
+            Ok(Err(e)) => return Ok(storage_unavailable(e)),
+            Err(e) => return Ok(storage_unavailable(e)),
+        },
+        (None, None) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Either hash or height parameter is required"
+            })));
+        }
+    };
+    block_response(hash, by_hash, query.verbose.unwrap_or(false), None, &config, &storage_actor, &cache).await
+}
+
+#[derive(Deserialize)]
+pub struct BlockPathQuery {
+    pub verbose: Option<bool>,
+    /// Ask for a block this node does not store to be fetched from peers
+    pub fetch: Option<bool>,
+    /// Keep a block fetched from peers in the block store
+    pub store: Option<bool>,
+}
+
+/// How `/block/{hash}?fetch=true` gets a block this node does not store
+struct PeerFetch<'a> {
+    network_actor: &'a Addr<NetworkActor>,
+    store: bool,
+}
+
+/// `GET /block/{hash}`, the path form of `/block?hash=`
+pub async fn get_block_by_hash(
+    path: web::Path<String>,
+    query: web::Query<BlockPathQuery>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    network_actor: web::Data<Addr<NetworkActor>>,
+    cache: web::Data<ResponseCache>,
+) -> ActixResult<HttpResponse> {
+    let Ok(hash) = path.parse::<bitcoin::BlockHash>() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid block hash"
+        })));
+    };
+    let verbose = query.verbose.unwrap_or(false);
+    let fetch = query.fetch.unwrap_or(false).then(|| PeerFetch {
+        network_actor: network_actor.get_ref(),
+        store: query.store.unwrap_or(false),
+    });
+    block_response(hash, true, verbose, fetch, &config, &storage_actor, &cache).await
+}
+
+#[derive(Deserialize)]
+pub struct BlockLocationsQuery {
+    /// Active chain blocks to cover from the given one on, 1 by default
+    pub count: Option<u64>,
+}
+
+/// `GET /block/{hash}/locations`, as the `getblocklocations` RPC
+pub async fn block_locations(
+    path: web::Path<String>,
+    query: web::Query<BlockLocationsQuery>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let Ok(hash) = path.parse::<bitcoin::BlockHash>() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid block hash"
+        })));
+    };
+    let count = query.count.unwrap_or(1);
+    if count > MAX_BLOCK_LOCATIONS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("count must be at most {}", MAX_BLOCK_LOCATIONS)
+        })));
+    }
+
+    match storage_actor.send_traced(GetBlockLocations { hash, count }).await {
+        Ok(Ok(Some(locations))) => Ok(HttpResponse::Ok().json(locations)),
+        Ok(Ok(None)) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Block not found"
+        }))),
+        Ok(Err(e)) => Ok(storage_unavailable(e)),
+        Err(e) => Ok(storage_unavailable(e)),
+    }
 }
 
-pub async fn get_block(query: web::Query<GetBlockQuery>) -> ActixResult<HttpResponse> {
-    if query.hash.is_none() && query.height.is_none() {
+/// `GET /block/{hash}/raw`: the serialized block as stored, or the one byte
+/// range a `Range` header asks for, so indexers can fetch just what they need
+pub async fn raw_block(
+    req: HttpRequest,
+    path: web::Path<String>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    use actix_web::http::header;
+
+    let Ok(hash) = path.parse::<bitcoin::BlockHash>() else {
         return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Either hash or height parameter is required"
+            "error": "Invalid block hash"
         })));
+    };
+    let data = match storage_actor.send_traced(GetRawBlock { hash }).await {
+        Ok(Ok(Some(data))) => data,
+        Ok(Ok(None)) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Block not found"
+            })));
+        }
+        Ok(Err(e)) => return Ok(storage_unavailable(e)),
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+
+    let length = data.len() as u64;
+    let range = req.headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<header::Range>().ok());
+    let (mut response, body) = match range {
+        // Several ranges, like a header that does not parse, get the whole block
+        Some(header::Range::Bytes(specs)) if specs.len() == 1 => match specs[0].to_satisfiable_range(length) {
+            Some((start, end)) => {
+                let mut response = HttpResponse::PartialContent();
+                response.insert_header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length)));
+                (response, data[start as usize..=end as usize].to_vec())
+            }
+            None => {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", length)))
+                    .finish());
+            }
+        },
+        _ => (HttpResponse::Ok(), data),
+    };
+    Ok(response
+        .content_type("application/octet-stream")
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CACHE_CONTROL, IMMUTABLE))
+        // Offsets refer to the stored bytes, so the compression middleware must leave them alone
+        .insert_header(header::ContentEncoding::Identity)
+        .body(body))
+}
+
+async fn block_response(
+    hash: bitcoin::BlockHash,
+    by_hash: bool,
+    verbose: bool,
+    fetch: Option<PeerFetch<'_>>,
+    config: &Config,
+    storage_actor: &Addr<StorageActor>,
+    cache: &ResponseCache,
+) -> ActixResult<HttpResponse> {
+    // Heights can be reorged onto another block, hashes cannot
+    let cache_control = if by_hash { IMMUTABLE } else { "no-cache" };
+
+    let key = format!("block:{}:{}", hash, verbose);
+    if let Some(body) = cache.get(&key) {
+        return Ok(cached_json(body, true, cache_control));
     }
 
-    // TODO: Get actual block data from storage actor
+    let block = match storage_actor.send_traced(GetBlock { hash }).await {
+        Ok(Ok(Some(block))) => block,
+        Ok(Ok(None)) => match &fetch {
+            Some(fetch) => match fetch_from_peers(hash, fetch, storage_actor).await {
+                Ok(block) => block,
+                Err(response) => return Ok(response),
+            },
+            None => {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Block not found"
+                })));
+            }
+        },
+        Ok(Err(e)) => return Ok(storage_unavailable(e)),
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+    let height = match storage_actor.send_traced(GetBlockHeight { hash }).await {
+        Ok(Ok(height)) => height,
+        Ok(Err(e)) => return Ok(storage_unavailable(e)),
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+
+    let network = config.network.to_bitcoin_network();
+    let header = &block.header;
+    // Only facts fixed by the block itself, so the body can be cached
     let block_info = serde_json::json!({
-        "hash": "0000000000000000000000000000000000000000000000000000000000000000",
-        "height": 0,
-        "time": 0,
-        "nonce": 0,
-        "difficulty": 1.0,
-        "tx": []
+        "hash": hash.to_string(),
+        "height": height,
+        "version": header.version.to_consensus(),
+        "merkleroot": header.merkle_root.to_string(),
+        "time": header.time,
+        "nonce": header.nonce,
+        "bits": format!("{:08x}", header.bits.to_consensus()),
+        "difficulty": header.difficulty_float(),
+        "previousblockhash": header.prev_blockhash.to_string(),
+        "size": block.total_size(),
+        "weight": block.weight().to_wu(),
+        "nTx": block.txdata.len(),
+        "tx": if verbose {
+            block.txdata.iter().map(|tx| crate::rpc::tx_json(tx, network)).collect::<Vec<_>>()
+        } else {
+            block.txdata.iter().map(|tx| serde_json::json!(tx.txid().to_string())).collect()
+        },
     });
 
-    Ok(HttpResponse::Ok().json(block_info))
+    let body = web::Bytes::from(serde_json::to_vec(&block_info)?);
+    // A block off the active chain has no height yet, which may change
+    if height.is_some() {
+        cache.insert(key, body.clone());
+    }
+    Ok(cached_json(body, false, cache_control))
+}
+
+/// Get a block this node does not store from peers, keeping it if asked to
+async fn fetch_from_peers(
+    hash: bitcoin::BlockHash,
+    fetch: &PeerFetch<'_>,
+    storage_actor: &Addr<StorageActor>,
+) -> Result<bitcoin::Block, HttpResponse> {
+    // Only blocks on the best header chain are asked for, so the hash is
+    // known to commit to a header with valid work that links to genesis
+    let height = match storage_actor.send_traced(GetHeaderHeight { hash }).await {
+        Ok(Ok(Some(height))) => height,
+        Ok(Ok(None)) => {
+            return Err(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Block not found"
+            })));
+        }
+        Ok(Err(e)) => return Err(storage_unavailable(e)),
+        Err(e) => return Err(storage_unavailable(e)),
+    };
+
+    let block = match fetch.network_actor.send(FetchBlock { hash, height }).await {
+        Ok(Ok(Some(block))) => block,
+        Ok(Ok(None)) => {
+            return Err(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "No peer served the block"
+            })));
+        }
+        Ok(Err(e)) => {
+            return Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+        Err(e) => {
+            error!("Network actor unavailable: {}", e);
+            return Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    if fetch.store {
+        match storage_actor.send_traced(StoreBlock { block: block.clone() }).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(storage_unavailable(e)),
+            Err(e) => return Err(storage_unavailable(e)),
+        }
+    }
+    Ok(block)
 }
 
 #[derive(Deserialize)]
@@ -124,50 +1637,705 @@ pub struct GetTransactionQuery {
     pub txid: String,
 }
 
-pub async fn get_transaction(query: web::Query<GetTransactionQuery>) -> ActixResult<HttpResponse> {
-    // TODO: Get actual transaction data from storage actor
-    let tx_info = serde_json::json!({
-        "txid": query.txid,
-        "size": 0,
-        "vsize": 0,
-        "weight": 0,
-        "fee": 0,
-        "vin": [],
-        "vout": []
-    });
+/// Decoded transaction; stored transactions are cached, mempool ones are not
+pub async fn get_transaction(
+    query: web::Query<GetTransactionQuery>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+    cache: web::Data<ResponseCache>,
+) -> ActixResult<HttpResponse> {
+    let Ok(txid) = query.txid.parse::<bitcoin::Txid>() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid txid"
+        })));
+    };
+
+    let key = format!("tx:{}", txid);
+    if let Some(body) = cache.get(&key) {
+        return Ok(cached_json(body, true, IMMUTABLE));
+    }
 
-    Ok(HttpResponse::Ok().json(tx_info))
+    let network = config.network.to_bitcoin_network();
+    let stored = match storage_actor.send_traced(GetTransaction { txid }).await {
+        Ok(Ok(tx)) => tx,
+        Ok(Err(e)) => return Ok(storage_unavailable(e)),
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+    if let Some(tx) = stored {
+        let body = web::Bytes::from(serde_json::to_vec(&crate::rpc::tx_json(&tx, network))?);
+        cache.insert(key, body.clone());
+        return Ok(cached_json(body, false, IMMUTABLE));
+    }
+
+    // Unconfirmed transactions may still be replaced or change witness
+    match mempool_actor.send_traced(GetFromMempool { txid }).await {
+        Ok(Ok(Some(tx))) => Ok(HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-cache"))
+            .json(crate::rpc::tx_json(&tx, network))),
+        Ok(Ok(None)) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Transaction not found"
+        }))),
+        Ok(Err(e)) => Ok(storage_unavailable(e)),
+        Err(e) => Ok(storage_unavailable(e)),
+    }
 }
 
 #[derive(Deserialize)]
 pub struct SendRawTransactionRequest {
     pub hex: String,
+    /// BTC/kvB, as in the RPC; 0 accepts any fee rate
+    pub maxfeerate: Option<f64>,
+    /// BTC an unspendable output may carry
+    pub maxburnamount: Option<f64>,
 }
 
 pub async fn send_raw_transaction(
     req: web::Json<SendRawTransactionRequest>,
-    _event_manager: web::Data<EventManager>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+    network_actor: web::Data<Addr<NetworkActor>>,
+    event_manager: web::Data<EventManager>,
 ) -> ActixResult<HttpResponse> {
-    // TODO: Validate and broadcast transaction
-    info!("Received raw transaction: {}", req.hex);
+    let limits = match BroadcastLimits::from_btc(req.maxfeerate, req.maxburnamount) {
+        Ok(limits) => limits,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
 
-    // For now, return a dummy txid
-    let response = serde_json::json!({
-        "txid": "0000000000000000000000000000000000000000000000000000000000000000"
-    });
+    let broadcaster = Broadcaster::new(
+        &config,
+        storage_actor.get_ref().clone(),
+        mempool_actor.get_ref().clone(),
+        network_actor.get_ref().clone(),
+        event_manager.get_ref().clone(),
+    );
+    match broadcaster.submit(&req.hex, limits).await {
+        Ok(txid) => Ok(HttpResponse::Ok().json(serde_json::json!({ "txid": txid.to_string() }))),
+        Err(BroadcastError::Decode) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Not a valid serialized transaction"
+        }))),
+        Err(BroadcastError::Unavailable(e)) => Ok(storage_unavailable(e)),
+        Err(e) => Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
 
-    Ok(HttpResponse::Ok().json(response))
+#[derive(Deserialize)]
+pub struct CpfpRequest {
+    /// A mempool transaction to bump
+    pub txid: Option<String>,
+    /// Or a serialized transaction, which need not be in the mempool
+    pub hex: Option<String>,
+    /// Blocks to confirm within, 1 by default
+    pub conf_target: Option<u32>,
+    /// Virtual size of the child, a one-in one-out P2WPKH spend by default
+    pub child_vsize: Option<u64>,
+}
+
+/// Fee a child needs to pay so the stuck transaction and its unconfirmed
+/// ancestors confirm within `conf_target` blocks along with it
+pub async fn cpfp_fee(
+    request: web::Json<CpfpRequest>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+) -> ActixResult<HttpResponse> {
+    use bitcoin::hex::FromHex;
+
+    let bad_request = |error: &str| Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": error })));
+    let conf_target = request.conf_target.unwrap_or(1);
+    if !(1..=1008).contains(&conf_target) {
+        return bad_request("conf_target must be between 1 and 1008");
+    }
+    let child_vsize = request.child_vsize.unwrap_or(DEFAULT_CPFP_CHILD_VSIZE);
+    if child_vsize == 0 {
+        return bad_request("child_vsize must be positive");
+    }
+
+    let package = match (&request.txid, &request.hex) {
+        (Some(txid), None) => {
+            let Ok(txid) = txid.parse::<bitcoin::Txid>() else {
+                return bad_request("Invalid txid");
+            };
+            let package = match mempool_actor.send_traced(GetMempoolPackage { txids: vec![txid] }).await {
+                Ok(package) => package,
+                Err(e) => return Ok(storage_unavailable(e)),
+            };
+            if !package.contains(&txid) {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({ "error": "Transaction not in mempool" })));
+            }
+            package
+        }
+        (None, Some(hex)) => {
+            let Some(tx) = Vec::<u8>::from_hex(hex)
+                .ok()
+                .and_then(|bytes| bitcoin::consensus::deserialize::<bitcoin::Transaction>(&bytes).ok())
+            else {
+                return bad_request("Not a valid serialized transaction");
+            };
+            let txid = tx.txid();
+            // Parents first: the transaction itself is only added when the mempool lacks it
+            let mut txids: Vec<bitcoin::Txid> = tx.input.iter().map(|input| input.previous_output.txid).collect();
+            txids.insert(0, txid);
+            let mut package = match mempool_actor.send_traced(GetMempoolPackage { txids }).await {
+                Ok(package) => package,
+                Err(e) => return Ok(storage_unavailable(e)),
+            };
+            if !package.contains(&txid) {
+                let spent_outputs = match resolve_spent_outputs(&storage_actor, &mempool_actor, &tx).await {
+                    Ok(spent_outputs) => spent_outputs.into_iter().collect::<Option<Vec<_>>>(),
+                    Err(e) => return Ok(storage_unavailable(e)),
+                };
+                let Some(spent_outputs) = spent_outputs else {
+                    return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                        "error": "bad-txns-inputs-missingorspent"
+                    })));
+                };
+                let input_value: Satoshi = spent_outputs.iter().map(|output| Satoshi::from(output.value)).sum();
+                let output_value: Satoshi = tx.output.iter().map(|output| Satoshi::from(output.value)).sum();
+                let Some(fee) = input_value.checked_sub(output_value) else {
+                    return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                        "error": "bad-txns-in-belowout"
+                    })));
+                };
+                package.txids.insert(0, txid);
+                package.fee = package.fee + fee;
+                package.vsize += tx.vsize() as u64;
+            }
+            package
+        }
+        _ => return bad_request("Pass either txid or hex"),
+    };
+
+    let target = match mempool_actor.send_traced(EstimateSmartFee { conf_target }).await {
+        Ok(target) => target,
+        Err(e) => return Ok(storage_unavailable(e)),
+    };
+    let mut estimate = serde_json::json!(cpfp_estimate(&package, target, child_vsize));
+    estimate["conf_target"] = serde_json::json!(conf_target);
+    Ok(HttpResponse::Ok().json(estimate))
+}
+
+#[derive(Deserialize)]
+pub struct MinerTransactionRequest {
+    pub hex: String,
+    /// Fee in satoshis; omit for transactions paid out of band
+    pub fee: Option<Satoshi>,
+}
+
+/// Compare tokens without short-circuiting on the first differing byte
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Point reads and scans for API replicas, see [`crate::storage::remote`]
+pub async fn storage_read(
+    req: HttpRequest,
+    request: web::Json<crate::storage::remote::StorageRead>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+) -> ActixResult<HttpResponse> {
+    let read_api = &config.storage.read_api;
+    if !read_api.enabled {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Storage reads are not served"
+        })));
+    }
+    let token_valid = req.headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .zip(read_api.token.as_deref())
+        .is_some_and(|(given, expected)| token_matches(given, expected));
+    if !token_valid {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Not authorized for storage reads"
+        })));
+    }
+
+    match storage_actor.send_traced(crate::actors::ReadStorage(request.into_inner())).await {
+        Ok(Ok(response)) => Ok(HttpResponse::Ok().json(response)),
+        Ok(Err(e)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e.to_string() }))),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": e.to_string() }))),
+    }
+}
+
+/// Priority submission lane for a co-located miner or pool
+pub async fn submit_miner_transaction(
+    req: HttpRequest,
+    request: web::Json<MinerTransactionRequest>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+) -> ActixResult<HttpResponse> {
+    use bitcoin::hex::FromHex;
+
+    let lane = &config.mempool.miner_lane;
+    if !lane.enabled {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Miner lane is disabled"
+        })));
+    }
+
+    let host_allowed = req.peer_addr()
+        .is_some_and(|addr| lane.allowed_hosts.iter().any(|host| host.parse() == Ok(addr.ip())));
+    let token_valid = req.headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .zip(lane.token.as_deref())
+        .is_some_and(|(given, expected)| token_matches(given, expected));
+    if !host_allowed || !token_valid {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Not authorized for the miner lane"
+        })));
+    }
+
+    let tx = match Vec::<u8>::from_hex(&request.hex)
+        .ok()
+        .and_then(|bytes| bitcoin::consensus::deserialize::<bitcoin::Transaction>(&bytes).ok())
+    {
+        Some(tx) => tx,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Not a valid serialized transaction"
+            })));
+        }
+    };
+
+    // Scripts are consensus, so even this lane needs the coins being spent
+    let spent_outputs = match resolve_spent_outputs(&storage_actor, &mempool_actor, &tx).await {
+        Ok(spent_outputs) => spent_outputs.into_iter().collect::<Option<Vec<_>>>(),
+        Err(e) => {
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+    let Some(spent_outputs) = spent_outputs else {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "bad-txns-inputs-missingorspent"
+        })));
+    };
+
+    let submission = SubmitMinerTransaction { tx, fee: request.fee.unwrap_or_default(), spent_outputs };
+    match mempool_actor.send_traced(submission).await {
+        Ok(Ok(txid)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "txid": txid.to_string(),
+            "priority": true
+        }))),
+        Ok(Err(e)) => Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+/// Share counts per stratum worker
+#[cfg(feature = "miner")]
+pub async fn stratum_workers(
+    config: web::Data<Config>,
+    stratum_actor: web::Data<Addr<StratumActor>>,
+) -> ActixResult<HttpResponse> {
+    if !config.stratum.enabled {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Stratum is disabled"
+        })));
+    }
+
+    match stratum_actor.send(GetStratumWorkers).await {
+        Ok(workers) => Ok(HttpResponse::Ok().json(workers)),
+        Err(e) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SnapshotRequest {
+    pub name: String,
+}
+
+fn snapshot_error_response(e: SnapshotError) -> HttpResponse {
+    let body = serde_json::json!({ "error": e.to_string() });
+    match e {
+        SnapshotError::NotRegtest => HttpResponse::Forbidden().json(body),
+        SnapshotError::NotFound(_) => HttpResponse::NotFound().json(body),
+        SnapshotError::InvalidName(_)
+        | SnapshotError::UnsupportedVersion(_)
+        | SnapshotError::NetworkMismatch { .. }
+        | SnapshotError::Invalid(_) => HttpResponse::BadRequest().json(body),
+        SnapshotError::Mailbox(_) => HttpResponse::ServiceUnavailable().json(body),
+        SnapshotError::Io(_) | SnapshotError::Storage(_) => {
+            error!("Snapshot failed: {}", e);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+fn require_regtest(config: &Config) -> Result<(), SnapshotError> {
+    match config.network {
+        crate::config::Network::Regtest => Ok(()),
+        _ => Err(SnapshotError::NotRegtest),
+    }
+}
+
+/// Archive the full node state under `<datadir>/snapshots/<name>.json`
+pub async fn create_snapshot(
+    request: web::Json<SnapshotRequest>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+) -> ActixResult<HttpResponse> {
+    let result = async {
+        require_regtest(&config)?;
+        let path = snapshot_path(&config.datadir, &request.name)?;
+        let snapshot = take_snapshot(&storage_actor, &mempool_actor, config.network.as_str()).await?;
+        let summary = snapshot.summary(&request.name);
+        web::block(move || snapshot.save(&path))
+            .await
+            .map_err(|e| SnapshotError::Io(std::io::Error::other(e.to_string())))??;
+        Ok::<_, SnapshotError>(summary)
+    }.await;
+
+    match result {
+        Ok(summary) => {
+            info!("Created snapshot {} at height {:?}", summary.name, summary.tip_height);
+            Ok(HttpResponse::Created().json(summary))
+        }
+        Err(e) => Ok(snapshot_error_response(e)),
+    }
+}
+
+/// Replace the running node's chain, UTXO set and mempool with a saved snapshot
+pub async fn restore_from_snapshot(
+    request: web::Json<SnapshotRequest>,
+    config: web::Data<Config>,
+    storage_actor: web::Data<Addr<StorageActor>>,
+    mempool_actor: web::Data<Addr<MempoolActor>>,
+    chain_actor: web::Data<Addr<ChainActor>>,
+) -> ActixResult<HttpResponse> {
+    let result = async {
+        require_regtest(&config)?;
+        let path = snapshot_path(&config.datadir, &request.name)?;
+        if !path.exists() {
+            return Err(SnapshotError::NotFound(request.name.clone()));
+        }
+        let snapshot = web::block(move || NodeSnapshot::load(&path))
+            .await
+            .map_err(|e| SnapshotError::Io(std::io::Error::other(e.to_string())))??;
+        let summary = snapshot.summary(&request.name);
+        restore_snapshot(snapshot, &storage_actor, &mempool_actor, &chain_actor, config.network.as_str()).await?;
+        Ok(summary)
+    }.await;
+
+    match result {
+        Ok(summary) => {
+            info!("Restored snapshot {} at height {:?}", summary.name, summary.tip_height);
+            Ok(HttpResponse::Ok().json(summary))
+        }
+        Err(e) => Ok(snapshot_error_response(e)),
+    }
+}
+
+/// Run blocking file work for a mempool snapshot handler
+async fn capture_files<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, SnapshotError> + Send + 'static,
+) -> Result<T, SnapshotError> {
+    web::block(work)
+        .await
+        .map_err(|e| SnapshotError::Io(std::io::Error::other(e.to_string())))?
+}
+
+/// Periodic mempool snapshots kept under `<datadir>/mempool_snapshots`, oldest first
+pub async fn mempool_snapshots(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    let dir = capture_dir(&config.datadir);
+    match capture_files(move || list_captures(&dir)).await {
+        Ok(captures) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "interval_secs": config.analytics.mempool_snapshot_interval_secs,
+            "retention_hours": config.analytics.mempool_snapshot_retention_hours,
+            "snapshots": captures,
+        }))),
+        Err(e) => Ok(snapshot_error_response(e)),
+    }
+}
+
+/// One mempool snapshot as the gzip compressed JSON it is stored as
+pub async fn mempool_snapshot_download(
+    path: web::Path<i64>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let id = path.into_inner();
+    let dir = capture_dir(&config.datadir);
+    match capture_files(move || read_compressed(&dir, id)).await {
+        Ok(data) => Ok(HttpResponse::Ok()
+            .content_type("application/gzip")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"mempool-{}.json.gz\"", id)))
+            .body(data)),
+        Err(e) => Ok(snapshot_error_response(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MempoolSnapshotDiffQuery {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Transactions that entered and left the mempool between two snapshots
+pub async fn mempool_snapshot_diff(
+    query: web::Query<MempoolSnapshotDiffQuery>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let MempoolSnapshotDiffQuery { from, to } = query.into_inner();
+    let dir = capture_dir(&config.datadir);
+    let result = capture_files(move || {
+        let from = MempoolCapture::load(&dir, from)?;
+        let to = MempoolCapture::load(&dir, to)?;
+        Ok(diff_captures(&from, &to))
+    }).await;
+    match result {
+        Ok(diff) => Ok(HttpResponse::Ok().json(diff)),
+        Err(e) => Ok(snapshot_error_response(e)),
+    }
+}
+
+/// Wallet history as CSV for bookkeeping, with fiat columns when a price
+/// source is configured; needs the RPC credentials, like the wallet RPCs
+#[cfg(feature = "wallet")]
+pub async fn wallet_history_csv(
+    req: HttpRequest,
+    path: web::Path<String>,
+    rpc_service: Option<web::Data<RpcService>>,
+    wallet_actor: web::Data<Addr<WalletActor>>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let authorization = req.headers().get(actix_web::http::header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if !rpc_service.is_some_and(|rpc_service| rpc_service.auth.authorized_basic(authorization)) {
+        warn!("Rejected wallet history export with missing or incorrect credentials");
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\""))
+            .json(serde_json::json!({ "error": "Not authorized for wallet history" })));
+    }
+    let name = path.into_inner();
+    let rows = match wallet_actor.send(GetWalletHistory { wallet: name.clone() }).await {
+        Ok(Ok(rows)) => rows,
+        Ok(Err(crate::error::WalletError::NotFound(_))) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Wallet not found: {}", name)
+            })));
+        }
+        Ok(Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    #[cfg(feature = "http-client")]
+    let prices = match crate::wallet::PriceSource::from_config(&config.wallet) {
+        Some(source) => source.prices(rows.iter().map(|row| row.time.date_naive())).await,
+        None => Default::default(),
+    };
+    #[cfg(not(feature = "http-client"))]
+    let prices = {
+        if config.wallet.price_url.is_some() {
+            warn!("Fiat prices are configured, but this build has no http-client feature");
+        }
+        Default::default()
+    };
+    let csv = crate::wallet::history_csv(&rows, &config.wallet.fiat_currency, &prices);
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(actix_web::http::header::ContentDisposition::attachment(history_filename(&name)))
+        .body(csv))
+}
+
+/// Download name for a wallet's history; wallet names may hold quotes, path
+/// separators or control characters, which are replaced
+#[cfg(feature = "wallet")]
+fn history_filename(wallet: &str) -> String {
+    let wallet: String = wallet.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    format!("{}-history.csv", wallet)
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("")
-            .route("/info", web::get().to(node_info))
-            .route("/stats", web::get().to(stats))
-            .route("/peers", web::get().to(peers))
-            .route("/mempool", web::get().to(mempool))
-            .route("/block", web::get().to(get_block))
-            .route("/transaction", web::get().to(get_transaction))
-            .route("/sendrawtransaction", web::post().to(send_raw_transaction))
-    );
-}
\ No newline at end of file
+    let scope = web::scope("")
+        .route("/info", web::get().to(node_info))
+        .route("/stats", web::get().to(stats))
+        .route("/metrics.json", web::get().to(metrics_json))
+        .route("/peers", web::get().to(peers))
+        .route("/peers/timeline", web::get().to(peer_timeline))
+        .route("/peers/summary", web::get().to(peer_summary))
+        .route("/mempool", web::get().to(mempool))
+        .route("/mempool/stream", web::get().to(mempool_stream))
+        .route("/mempool/snapshots", web::get().to(mempool_snapshots))
+        .route("/mempool/snapshots/diff", web::get().to(mempool_snapshot_diff))
+        .route("/mempool/snapshots/{id}", web::get().to(mempool_snapshot_download))
+        .route("/rpc/ws", web::get().to(rpc_websocket))
+        .route("/ws", web::get().to(event_stream))
+        .route("/stream", web::get().to(sse_stream))
+        .route("/events", web::get().to(event_replay))
+        .route("/events/publishers", web::get().to(event_publishers))
+        .route("/events/webhook/ack", web::post().to(webhook_ack))
+        .route("/identity", web::get().to(identity))
+        .route("/cluster", web::get().to(cluster))
+        .route("/storage/read", web::post().to(storage_read))
+        .route("/chain/tip", web::get().to(chain_tip))
+        .route("/utxo-deltas", web::get().to(utxo_deltas))
+        .route("/analytics/blocks", web::get().to(block_analytics))
+        .route("/analytics/daily", web::get().to(daily_analytics))
+        .route("/analytics/utxo-distribution", web::get().to(utxo_distribution))
+        .route("/analytics/feerates", web::get().to(fee_rate_history))
+        .route("/watch/tx", web::post().to(watch_tx))
+        .route("/watch/tx", web::get().to(tx_watches))
+        .route("/watch/address", web::post().to(watch_address))
+        .route("/watch/address", web::get().to(address_watches))
+        .route("/address/{address}/txs", web::get().to(address_txs))
+        .route("/address/{address}/utxos", web::get().to(address_utxos))
+        .route("/tx/proof/verify", web::post().to(verify_tx_proof))
+        .route("/tx/{txid}/proof", web::get().to(tx_proof))
+        .route("/headers", web::get().to(headers))
+        .route("/reorgs", web::get().to(reorgs))
+        .route("/stale-blocks", web::get().to(stale_blocks))
+        .route("/block", web::get().to(get_block))
+        .route("/block/{hash}", web::get().to(get_block_by_hash))
+        .route("/block/{hash}/raw", web::get().to(raw_block))
+        .route("/block/{hash}/locations", web::get().to(block_locations))
+        .route("/transaction", web::get().to(get_transaction))
+        .route("/sendrawtransaction", web::post().to(send_raw_transaction))
+        .route("/fees/cpfp", web::post().to(cpfp_fee))
+        .route("/miner/transactions", web::post().to(submit_miner_transaction))
+        .route("/admin/usage", web::get().to(usage_report))
+        .route("/admin/snapshot", web::post().to(create_snapshot))
+        .route("/admin/restore", web::post().to(restore_from_snapshot));
+    #[cfg(feature = "miner")]
+    let scope = scope.route("/stratum/workers", web::get().to(stratum_workers));
+    #[cfg(feature = "wallet")]
+    let scope = scope.route("/wallet/{name}/history.csv", web::get().to(wallet_history_csv));
+    cfg.service(scope);
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{call_service, init_service, read_body, read_body_json, TestRequest};
+    use actix_web::App;
+
+    /// A coinbase-only block on top of `parent`
+    fn child_block(parent: &bitcoin::Block) -> bitcoin::Block {
+        let coinbase = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::null(),
+                script_sig: bitcoin::ScriptBuf::from(vec![0x51, 0x51]),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_btc(50.0).unwrap(),
+                script_pubkey: bitcoin::ScriptBuf::from(vec![0x51]),
+            }],
+        };
+        let mut header = parent.header;
+        header.prev_blockhash = parent.block_hash();
+        header.time += 600;
+        bitcoin::Block { header, txdata: vec![coinbase] }
+    }
+
+    /// Storage holding the regtest genesis block and one child, both connected
+    async fn two_block_chain(config: &Config) -> (Addr<StorageActor>, Vec<bitcoin::Block>) {
+        let storage_actor = StorageActor::new(config).start();
+        let genesis = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        let child = child_block(&genesis);
+        let blocks = vec![genesis, child];
+        for (height, block) in blocks.iter().enumerate() {
+            let connect = crate::actors::ConnectBlock { block: block.clone(), height: height as u64 };
+            storage_actor.send(connect).await.unwrap().unwrap();
+        }
+        (storage_actor, blocks)
+    }
+
+    #[actix_rt::test]
+    async fn test_raw_block_byte_ranges() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let (storage_actor, blocks) = two_block_chain(&config).await;
+        let app = init_service(App::new()
+            .app_data(web::Data::new(storage_actor))
+            .route("/block/{hash}/raw", web::get().to(raw_block))).await;
+
+        let raw = bitcoin::consensus::serialize(&blocks[0]);
+        let length = raw.len();
+        let uri = format!("/block/{}/raw", blocks[0].block_hash());
+        let request = |range: &str| TestRequest::get().uri(&uri).insert_header((header::RANGE, range)).to_request();
+        let content_range = |response: &actix_web::dev::ServiceResponse| {
+            response.headers().get(header::CONTENT_RANGE).unwrap().to_str().unwrap().to_string()
+        };
+
+        let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(read_body(response).await, raw);
+
+        // The 80-byte header
+        let response = call_service(&app, request("bytes=0-79")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(content_range(&response), format!("bytes 0-79/{}", length));
+        assert_eq!(read_body(response).await, bitcoin::consensus::serialize(&blocks[0].header));
+
+        // The last 10 bytes
+        let response = call_service(&app, request("bytes=-10")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(content_range(&response), format!("bytes {}-{}/{}", length - 10, length - 1, length));
+        assert_eq!(read_body(response).await, raw[length - 10..]);
+
+        // Starting past the end
+        let response = call_service(&app, request(&format!("bytes={}-", length))).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(content_range(&response), format!("bytes */{}", length));
+
+        let unknown = format!("/block/{}/raw", blocks[1].header.merkle_root);
+        let response = call_service(&app, TestRequest::get().uri(&unknown).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_block_locations_stop_at_the_last_stored_block() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let (storage_actor, blocks) = two_block_chain(&config).await;
+        let app = init_service(App::new()
+            .app_data(web::Data::new(storage_actor))
+            .route("/block/{hash}/locations", web::get().to(block_locations))).await;
+
+        let uri = format!("/block/{}/locations?count=5", blocks[0].block_hash());
+        let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let locations: Vec<serde_json::Value> = read_body_json(response).await;
+        let covered: Vec<(String, u64)> = locations.iter()
+            .map(|location| (location["hash"].as_str().unwrap().to_string(), location["height"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(covered, vec![(blocks[0].block_hash().to_string(), 0), (blocks[1].block_hash().to_string(), 1)]);
+        let lengths: Vec<u64> = locations.iter().map(|location| location["length"].as_u64().unwrap()).collect();
+        assert_eq!(lengths, blocks.iter().map(|block| bitcoin::consensus::serialize(block).len() as u64).collect::<Vec<_>>());
+
+        let uri = format!("/block/{}/locations?count={}", blocks[0].block_hash(), MAX_BLOCK_LOCATIONS + 1);
+        let response = call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,165 @@
+//! BIP21 `bitcoin:` payment URIs
+//!
+//! Addresses are checked against the node's network both when building and
+//! when parsing, so a testnet URI can never be handed to a mainnet payer.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Amount, Denomination, Network};
+
+use crate::error::{Bip21Error, Bip21Result};
+
+const SCHEME: &str = "bitcoin:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    pub address: Address,
+    pub amount: Option<Amount>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    /// Parameters this node does not interpret, such as `lightning`, kept verbatim
+    pub extras: BTreeMap<String, String>,
+}
+
+impl PaymentUri {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            amount: None,
+            label: None,
+            message: None,
+            extras: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(uri: &str, network: Network) -> Bip21Result<Self> {
+        let scheme_len = SCHEME.len().min(uri.len());
+        if !uri[..scheme_len].eq_ignore_ascii_case(SCHEME) {
+            return Err(Bip21Error::Scheme);
+        }
+        let rest = &uri[scheme_len..];
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let address = Address::<NetworkUnchecked>::from_str(address)
+            .map_err(|e| Bip21Error::Address(e.to_string()))?
+            .require_network(network)
+            .map_err(|_| Bip21Error::WrongNetwork(network.to_string()))?;
+        let mut payment = Self::new(address);
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            let duplicate = match key {
+                "amount" => {
+                    let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+                        .map_err(|_| Bip21Error::Amount(value.clone()))?;
+                    payment.amount.replace(amount).is_some()
+                }
+                "label" => payment.label.replace(value).is_some(),
+                "message" => payment.message.replace(value).is_some(),
+                key if key.starts_with("req-") => return Err(Bip21Error::UnsupportedRequirement(key.to_string())),
+                key => payment.extras.insert(key.to_string(), value).is_some(),
+            };
+            if duplicate {
+                return Err(Bip21Error::DuplicateParameter(key.to_string()));
+            }
+        }
+        Ok(payment)
+    }
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", SCHEME, self.address)?;
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", amount.to_string_in(Denomination::Bitcoin)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+        if let Some(message) = &self.message {
+            params.push(format!("message={}", percent_encode(message)));
+        }
+        for (key, value) in &self.extras {
+            params.push(format!("{}={}", key, percent_encode(value)));
+        }
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Escape everything outside the RFC 3986 unreserved set
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> Bip21Result<String> {
+    let invalid = || Bip21Error::Encoding(value.to_string());
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or_else(invalid)?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGTEST_ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+    #[test]
+    fn test_payment_uri_roundtrip() {
+        let address = Address::from_str(REGTEST_ADDRESS).unwrap().require_network(Network::Regtest).unwrap();
+        let mut payment = PaymentUri::new(address);
+        payment.amount = Some(Amount::from_sat(150_000));
+        payment.label = Some("Luke-Jr".to_string());
+        payment.message = Some("Donation for project xyz & more".to_string());
+
+        let uri = payment.to_string();
+        assert_eq!(
+            uri,
+            format!("bitcoin:{}?amount=0.0015&label=Luke-Jr&message=Donation%20for%20project%20xyz%20%26%20more", REGTEST_ADDRESS)
+        );
+        assert_eq!(PaymentUri::parse(&uri, Network::Regtest).unwrap(), payment);
+    }
+
+    #[test]
+    fn test_payment_uri_rejections() {
+        let uri = format!("BITCOIN:{}?amount=1&lightning=lnbcrt1", REGTEST_ADDRESS.to_uppercase());
+        let payment = PaymentUri::parse(&uri, Network::Regtest).unwrap();
+        assert_eq!(payment.amount, Some(Amount::ONE_BTC));
+        assert_eq!(payment.extras["lightning"], "lnbcrt1");
+
+        let uri = format!("bitcoin:{}", REGTEST_ADDRESS);
+        assert!(matches!(PaymentUri::parse(&uri, Network::Bitcoin), Err(Bip21Error::WrongNetwork(_))));
+        assert!(matches!(
+            PaymentUri::parse(&format!("{}?req-somethingyoudontunderstand=50", uri), Network::Regtest),
+            Err(Bip21Error::UnsupportedRequirement(_))
+        ));
+        assert!(matches!(PaymentUri::parse(&format!("{}?amount=1,5", uri), Network::Regtest), Err(Bip21Error::Amount(_))));
+        assert!(matches!(PaymentUri::parse(&format!("{}?label=%zz", uri), Network::Regtest), Err(Bip21Error::Encoding(_))));
+        assert!(matches!(PaymentUri::parse(REGTEST_ADDRESS, Network::Regtest), Err(Bip21Error::Scheme)));
+    }
+}
@@ -0,0 +1,129 @@
+//! Local transaction submission
+//!
+//! `sendrawtransaction` over RPC and REST both go through [`Broadcaster`]:
+//! decode, apply the caller's [`BroadcastLimits`], resolve the coins being
+//! spent, hand the transaction to the mempool's relay lane, then announce it
+//! to peers and publish a `TransactionAdded` event.
+
+use actix::Addr;
+use bitcoin::hex::FromHex;
+use bitcoin::{OutPoint, Transaction, Txid};
+use tracing::warn;
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::mempool::{resolve_spent_outputs, MempoolActor};
+use crate::actors::network::NetworkActor;
+use crate::actors::storage::StorageActor;
+use crate::actors::{BroadcastTransaction, GetMempoolEntry, GetUtxos, SubmitTransaction};
+use crate::amount::{FeeRate, Satoshi};
+use crate::config::Config;
+use crate::error::BroadcastError;
+use crate::events::{BitcoinEventType, EventManager};
+use crate::policy::BroadcastLimits;
+
+#[derive(Clone)]
+pub struct Broadcaster {
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    network_actor: Addr<NetworkActor>,
+    event_manager: EventManager,
+    network_name: &'static str,
+    node_id: String,
+}
+
+fn unavailable(e: impl std::fmt::Display) -> BroadcastError {
+    BroadcastError::Unavailable(e.to_string())
+}
+
+impl Broadcaster {
+    pub fn new(
+        config: &Config,
+        storage_actor: Addr<StorageActor>,
+        mempool_actor: Addr<MempoolActor>,
+        network_actor: Addr<NetworkActor>,
+        event_manager: EventManager,
+    ) -> Self {
+        Self {
+            storage_actor,
+            mempool_actor,
+            network_actor,
+            event_manager,
+            network_name: config.network.as_str(),
+            node_id: config.events.k8s.node_name.clone(),
+        }
+    }
+
+    /// Accept a hex serialized transaction into the mempool and relay it
+    pub async fn submit(&self, hex: &str, limits: BroadcastLimits) -> Result<Txid, BroadcastError> {
+        let tx: Transaction = Vec::<u8>::from_hex(hex)
+            .ok()
+            .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+            .ok_or(BroadcastError::Decode)?;
+        limits.check_burn(&tx).map_err(BroadcastError::Limit)?;
+        let txid = tx.txid();
+
+        // Already accepted, so only relay it to peers that have not seen it
+        let in_mempool = self.mempool_actor.send_traced(GetMempoolEntry { txid })
+            .await
+            .map_err(unavailable)?
+            .map_err(unavailable)?;
+        if in_mempool.is_some() {
+            self.relay(tx).await;
+            return Ok(txid);
+        }
+
+        let spent_outputs = resolve_spent_outputs(&self.storage_actor, &self.mempool_actor, &tx)
+            .await
+            .map_err(unavailable)?
+            .into_iter()
+            .collect::<Option<Vec<_>>>();
+        let Some(spent_outputs) = spent_outputs else {
+            // Outputs still in the UTXO set mean the transaction already confirmed
+            let own_outputs = (0..tx.output.len() as u32).map(|vout| OutPoint { txid, vout }).collect();
+            let confirmed = self.storage_actor.send_traced(GetUtxos { outpoints: own_outputs })
+                .await
+                .map_err(unavailable)?
+                .map_err(unavailable)?;
+            if confirmed.iter().any(Option::is_some) {
+                return Err(BroadcastError::AlreadyInChain);
+            }
+            return Err(BroadcastError::MissingInputs);
+        };
+
+        let input_value: u64 = spent_outputs.iter().map(|output| output.value.to_sat()).sum();
+        let output_value: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+        let fee = Satoshi(input_value.checked_sub(output_value).ok_or(BroadcastError::InBelowOut)?);
+        limits.check_fee(&tx, fee).map_err(BroadcastError::Limit)?;
+
+        let size = tx.vsize() as u64;
+        self.mempool_actor.send_traced(SubmitTransaction { tx: tx.clone(), fee, spent_outputs })
+            .await
+            .map_err(unavailable)?
+            .map_err(BroadcastError::Rejected)?;
+
+        self.relay(tx).await;
+        let event = BitcoinEventType::TransactionAdded {
+            txid: txid.to_string(),
+            size,
+            fee,
+            fee_rate: FeeRate::from_fee(fee, size),
+        };
+        let (event_manager, network, node_id) = (self.event_manager.clone(), self.network_name, self.node_id.clone());
+        self.event_manager.spawn_delivery(async move {
+            if let Err(e) = event_manager.publish(event, network, &node_id).await {
+                warn!("Failed to publish transaction event: {}", e);
+            }
+        });
+        Ok(txid)
+    }
+
+    /// A transaction in the mempool stays there if no peer takes it, so this only logs
+    async fn relay(&self, tx: Transaction) {
+        let txid = tx.txid();
+        match self.network_actor.send(BroadcastTransaction { tx }).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Could not broadcast transaction {}: {}", txid, e),
+            Err(e) => warn!("Could not broadcast transaction {}: {}", txid, e),
+        }
+    }
+}
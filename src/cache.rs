@@ -0,0 +1,221 @@
+//! Memory-bounded LRU caches
+//!
+//! Used for peer-influenced state (seen inventory, rejected transactions,
+//! orphans) so that adversarial peers cannot grow it without limit.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Bookkeeping cost charged per entry on top of the caller-supplied size
+const ENTRY_OVERHEAD_BYTES: usize = 64;
+
+struct CacheEntry<V> {
+    value: V,
+    size: usize,
+    inserted_at: Instant,
+    tick: u64,
+}
+
+/// Occupancy snapshot of a cache, exported as metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub evictions: u64,
+}
+
+/// LRU cache bounded by the approximate bytes held rather than entry count
+pub struct BoundedCache<K, V> {
+    name: &'static str,
+    max_bytes: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<K, CacheEntry<V>>,
+    order: BTreeMap<u64, K>,
+    next_tick: u64,
+    used_bytes: usize,
+    evictions: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> BoundedCache<K, V> {
+    pub fn new(name: &'static str, max_bytes: usize) -> Self {
+        Self {
+            name,
+            max_bytes,
+            ttl: None,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_tick: 0,
+            used_bytes: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Expire entries this long after insertion
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn entry_cost(size: usize) -> usize {
+        size + std::mem::size_of::<K>() + ENTRY_OVERHEAD_BYTES
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn is_expired(&self, entry: &CacheEntry<V>) -> bool {
+        self.ttl
+            .map(|ttl| entry.inserted_at.elapsed() >= ttl)
+            .unwrap_or(false)
+    }
+
+    /// Insert or replace an entry of `size` payload bytes, evicting the least
+    /// recently used entries to make room. Entries larger than the whole cache are dropped.
+    pub fn insert(&mut self, key: K, value: V, size: usize) {
+        self.remove(&key);
+
+        let cost = Self::entry_cost(size);
+        if cost > self.max_bytes {
+            return;
+        }
+        while self.used_bytes + cost > self.max_bytes {
+            if !self.evict_oldest() {
+                break;
+            }
+        }
+
+        let tick = self.tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, CacheEntry {
+            value,
+            size: cost,
+            inserted_at: Instant::now(),
+            tick,
+        });
+        self.used_bytes += cost;
+    }
+
+    /// Look up an entry, marking it as recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let expired = self.entries.get(key).map(|entry| self.is_expired(entry))?;
+        if expired {
+            self.remove(key);
+            return None;
+        }
+
+        let tick = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, key.clone());
+        entry.tick = tick;
+        Some(&entry.value)
+    }
+
+    pub fn contains(&mut self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.used_bytes -= entry.size;
+        Some(entry.value)
+    }
+
+    /// Remove and return every entry matching `predicate`
+    pub fn drain_matching(&mut self, mut predicate: impl FnMut(&K, &V) -> bool) -> Vec<(K, V)> {
+        let keys: Vec<K> = self.entries
+            .iter()
+            .filter(|(key, entry)| predicate(key, &entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| self.remove(&key).map(|value| (key, value)))
+            .collect()
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let Some((_, key)) = self.order.pop_first() else {
+            return false;
+        };
+        if let Some(entry) = self.entries.remove(&key) {
+            self.used_bytes -= entry.size;
+            self.evictions += 1;
+        }
+        true
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            name: self.name,
+            entries: self.entries.len(),
+            bytes: self.used_bytes,
+            max_bytes: self.max_bytes,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cost = BoundedCache::<u32, ()>::entry_cost(100);
+        let mut cache = BoundedCache::new("test", cost * 2);
+
+        cache.insert(1u32, (), 100);
+        cache.insert(2, (), 100);
+        assert!(cache.contains(&1));
+
+        // 2 is now the least recently used entry
+        cache.insert(3, (), 100);
+        assert!(cache.contains(&1));
+        assert!(!cache.contains(&2));
+        assert!(cache.contains(&3));
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.bytes, cost * 2);
+        assert_eq!(stats.evictions, 1);
+    }
+
+    #[test]
+    fn test_oversized_entries_are_dropped() {
+        let mut cache = BoundedCache::new("test", 256);
+        cache.insert(1u32, "big", 1024);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let mut cache = BoundedCache::new("test", 1024).with_ttl(Duration::from_millis(0));
+        cache.insert(1u32, (), 0);
+        assert!(!cache.contains(&1));
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_replace_and_drain() {
+        let mut cache = BoundedCache::new("test", 4096);
+        cache.insert(1u32, 10, 8);
+        cache.insert(1, 20, 8);
+        cache.insert(2, 30, 8);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.get(&1), Some(&20));
+
+        let drained = cache.drain_matching(|_, value| *value >= 20);
+        assert_eq!(drained.len(), 2);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
@@ -0,0 +1,53 @@
+//! Node wall clock with a regtest mock time override
+//!
+//! Code that compares against the current time (block timestamps, mempool
+//! expiry, peer bans) reads it from here so `setmocktime` can move it.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use chrono::{DateTime, TimeZone, Utc};
+
+/// Unix time set by `setmocktime`, zero when the system clock is used
+static MOCK_TIME: AtomicI64 = AtomicI64::new(0);
+
+/// Override the clock, or go back to the system clock with zero
+pub fn set_mock_time(time: i64) {
+    MOCK_TIME.store(time, Ordering::SeqCst);
+}
+
+pub fn mock_time() -> Option<i64> {
+    match MOCK_TIME.load(Ordering::SeqCst) {
+        0 => None,
+        time => Some(time),
+    }
+}
+
+/// Current unix time in seconds
+pub fn now() -> i64 {
+    mock_time().unwrap_or_else(|| Utc::now().timestamp())
+}
+
+pub fn now_utc() -> DateTime<Utc> {
+    match mock_time() {
+        Some(time) => Utc.timestamp_opt(time, 0).single().unwrap_or_else(Utc::now),
+        None => Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_time_overrides_clock() {
+        // Stay close to the real time so tests running alongside are unaffected
+        let mock = Utc::now().timestamp() + 60;
+        set_mock_time(mock);
+        assert_eq!(now(), mock);
+        assert_eq!(now_utc().timestamp(), mock);
+
+        set_mock_time(0);
+        assert_eq!(mock_time(), None);
+        assert!(now() < mock);
+    }
+}
@@ -0,0 +1,627 @@
+//! Cluster coordination
+//!
+//! Nodes of a fleet share an etcd cluster, reached through its v3 HTTP
+//! gateway. Each node keeps a member key alive under a lease and campaigns
+//! for `<prefix>/<network>/leader` with a create-if-absent transaction; the
+//! node whose lease holds that key is the leader and runs the expensive
+//! duties, currently the UTXO distribution scan, and shares their results
+//! for the others to serve. Every node keeps serving reads. When the
+//! leader stops refreshing its lease, the key expires and the next
+//! campaign elects another node.
+//!
+//! Events can go on the same bus: `ClusterEventPublisher` puts each one
+//! under `<prefix>/<network>/events/`, for consumers to watch, with a lease
+//! that drops it again after `event_ttl_secs`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bitcoin::base64::engine::general_purpose::STANDARD;
+use bitcoin::base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{ClusterConfig, Config};
+use crate::error::{ClusterError, ClusterResult, EventResult};
+use crate::events::{BitcoinEvent, EventPublisher, PublisherEndpoint};
+
+/// The name a node campaigns under when `cluster.node_name` is unset
+pub fn node_name(config: &ClusterConfig, pubkey: &str) -> String {
+    config.node_name.clone().unwrap_or_else(|| format!("node-{}", &pubkey[..16.min(pubkey.len())]))
+}
+
+/// The key after every key starting with `prefix`, as etcd range ends go
+fn prefix_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // Every key
+    vec![0]
+}
+
+/// etcd's JSON gateway encodes int64 as strings
+fn int64(value: &Value) -> Option<i64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        value => value.as_i64(),
+    }
+}
+
+fn decode(value: &Value) -> ClusterResult<String> {
+    let bytes = STANDARD
+        .decode(value.as_str().unwrap_or_default())
+        .map_err(|e| ClusterError::Response(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|e| ClusterError::Response(e.to_string()))
+}
+
+/// A key-value pair from a range response
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyValue {
+    key: String,
+    value: String,
+    /// 0 for keys without a lease
+    lease: i64,
+}
+
+fn key_values(response: &Value) -> ClusterResult<Vec<KeyValue>> {
+    let Some(kvs) = response.get("kvs").and_then(Value::as_array) else {
+        // Left out when the range is empty
+        return Ok(Vec::new());
+    };
+    kvs.iter()
+        .map(|kv| Ok(KeyValue {
+            key: decode(&kv["key"])?,
+            value: decode(&kv["value"])?,
+            lease: kv.get("lease").and_then(int64).unwrap_or(0),
+        }))
+        .collect()
+}
+
+/// Client for the etcd v3 JSON gateway
+#[cfg_attr(not(feature = "http-client"), allow(dead_code))]
+pub struct EtcdClient {
+    #[cfg(feature = "http-client")]
+    client: reqwest::Client,
+    #[cfg(feature = "http-client")]
+    endpoints: Vec<String>,
+}
+
+impl EtcdClient {
+    #[cfg(feature = "http-client")]
+    pub fn new(config: &ClusterConfig) -> ClusterResult<Self> {
+        let client = reqwest::Client::builder()
+            // A request may not outlast a lease refresh interval
+            .timeout(Duration::from_secs((config.lease_ttl_secs / 3).max(1)))
+            .build()?;
+        let endpoints = config.etcd_endpoints.iter().map(|e| e.trim_end_matches('/').to_string()).collect();
+        Ok(Self { client, endpoints })
+    }
+
+    #[cfg(not(feature = "http-client"))]
+    pub fn new(_config: &ClusterConfig) -> ClusterResult<Self> {
+        Err(ClusterError::NoHttpClient)
+    }
+
+    #[cfg(not(feature = "http-client"))]
+    async fn call(&self, _path: &str, _body: Value) -> ClusterResult<Value> {
+        Err(ClusterError::NoHttpClient)
+    }
+
+    /// POST to the first endpoint that answers
+    #[cfg(feature = "http-client")]
+    async fn call(&self, path: &str, body: Value) -> ClusterResult<Value> {
+        let mut last_error = ClusterError::Response("no etcd endpoints".to_string());
+        for endpoint in &self.endpoints {
+            let url = format!("{}/v3/{}", endpoint, path);
+            match self.client.post(&url).json(&body).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response.json().await?),
+                Ok(response) => {
+                    last_error = ClusterError::Status {
+                        endpoint: endpoint.clone(),
+                        status: response.status().as_u16(),
+                        body: response.text().await.unwrap_or_default(),
+                    };
+                }
+                Err(e) => last_error = e.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    pub async fn grant(&self, ttl_secs: u64) -> ClusterResult<i64> {
+        let response = self.call("lease/grant", json!({ "TTL": ttl_secs })).await?;
+        response.get("ID").and_then(int64).ok_or_else(|| ClusterError::Response(response.to_string()))
+    }
+
+    pub async fn keep_alive(&self, lease: i64) -> ClusterResult<()> {
+        let response = self.call("lease/keepalive", json!({ "ID": lease.to_string() })).await?;
+        // A stream in gRPC, so the gateway wraps the message in `result`
+        let ttl = response.get("result").unwrap_or(&response).get("TTL").and_then(int64).unwrap_or(0);
+        if ttl <= 0 {
+            return Err(ClusterError::LeaseExpired(lease));
+        }
+        Ok(())
+    }
+
+    pub async fn revoke(&self, lease: i64) -> ClusterResult<()> {
+        self.call("lease/revoke", json!({ "ID": lease.to_string() })).await.map(drop)
+    }
+
+    fn put_request(key: &str, value: &str, lease: Option<i64>) -> Value {
+        let mut put = json!({ "key": STANDARD.encode(key), "value": STANDARD.encode(value) });
+        if let Some(lease) = lease {
+            put["lease"] = json!(lease.to_string());
+        }
+        put
+    }
+
+    pub async fn put(&self, key: &str, value: &str, lease: Option<i64>) -> ClusterResult<()> {
+        self.call("kv/put", Self::put_request(key, value, lease)).await.map(drop)
+    }
+
+    async fn get(&self, key: &str) -> ClusterResult<Option<KeyValue>> {
+        let response = self.call("kv/range", json!({ "key": STANDARD.encode(key) })).await?;
+        Ok(key_values(&response)?.into_iter().next())
+    }
+
+    async fn list(&self, prefix: &str) -> ClusterResult<Vec<KeyValue>> {
+        let request = json!({ "key": STANDARD.encode(prefix), "range_end": STANDARD.encode(prefix_end(prefix)) });
+        key_values(&self.call("kv/range", request).await?)
+    }
+
+    /// Put `key` under `lease` unless it exists, and return its holder either way
+    async fn create_or_get(&self, key: &str, value: &str, lease: i64) -> ClusterResult<KeyValue> {
+        let encoded = STANDARD.encode(key);
+        let request = json!({
+            "compare": [{ "key": encoded, "result": "EQUAL", "target": "CREATE", "create_revision": "0" }],
+            "success": [{ "request_put": Self::put_request(key, value, Some(lease)) }],
+            "failure": [{ "request_range": { "key": encoded } }],
+        });
+        let response = self.call("kv/txn", request).await?;
+        if response.get("succeeded").and_then(Value::as_bool).unwrap_or(false) {
+            return Ok(KeyValue { key: key.to_string(), value: value.to_string(), lease });
+        }
+        let range = response.pointer("/responses/0/response_range").cloned().unwrap_or(Value::Null);
+        match key_values(&range)?.into_iter().next() {
+            Some(holder) => Ok(holder),
+            // Expired between the compare and the range; the next campaign gets it
+            None => Ok(KeyValue { key: key.to_string(), value: String::new(), lease: 0 }),
+        }
+    }
+}
+
+/// What a node registers about itself under the members prefix
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Member {
+    pub node: String,
+    pub pubkey: String,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterRole {
+    /// Clustering is off; the node runs every duty itself
+    Standalone,
+    Leader,
+    Follower,
+}
+
+/// The node's view of the cluster, served at `/api/v1/cluster`
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterStatus {
+    pub node: String,
+    pub role: ClusterRole,
+    /// Node holding the leader key, if any
+    pub leader: Option<String>,
+    pub leader_since: Option<DateTime<Utc>>,
+    pub members: Vec<Member>,
+    /// The last failure to reach etcd, cleared by the next success
+    pub last_error: Option<String>,
+}
+
+struct Keys {
+    leader: String,
+    members: String,
+    shared: String,
+}
+
+impl Keys {
+    fn new(config: &Config) -> Self {
+        let base = format!("{}/{}", config.cluster.prefix.trim_end_matches('/'), config.network.as_str());
+        Self {
+            leader: format!("{}/leader", base),
+            members: format!("{}/members/", base),
+            shared: format!("{}/shared/", base),
+        }
+    }
+}
+
+struct Inner {
+    etcd: Option<EtcdClient>,
+    keys: Keys,
+    ttl_secs: u64,
+    member: Member,
+    leader: AtomicBool,
+    status: Mutex<ClusterStatus>,
+    lease: Mutex<Option<i64>>,
+    stop: watch::Sender<bool>,
+}
+
+/// Shared handle to this node's membership and leadership
+#[derive(Clone)]
+pub struct Cluster {
+    inner: Arc<Inner>,
+}
+
+impl Cluster {
+    /// A cluster handle for `config`, campaigning is left to [`Self::start`]
+    pub fn new(config: &Config, pubkey: &str) -> ClusterResult<Self> {
+        let node = node_name(&config.cluster, pubkey);
+        let etcd = config.cluster.enabled.then(|| EtcdClient::new(&config.cluster)).transpose()?;
+        let role = if etcd.is_some() { ClusterRole::Follower } else { ClusterRole::Standalone };
+        let member = Member {
+            node: node.clone(),
+            pubkey: pubkey.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: Utc::now(),
+        };
+        Ok(Self {
+            inner: Arc::new(Inner {
+                // A standalone node does every duty itself
+                leader: AtomicBool::new(etcd.is_none()),
+                etcd,
+                keys: Keys::new(config),
+                ttl_secs: config.cluster.lease_ttl_secs,
+                status: Mutex::new(ClusterStatus {
+                    node,
+                    role,
+                    leader: None,
+                    leader_since: None,
+                    members: vec![member.clone()],
+                    last_error: None,
+                }),
+                member,
+                lease: Mutex::new(None),
+                stop: watch::channel(false).0,
+            }),
+        })
+    }
+
+    /// Keep the membership and campaign in the background until [`Self::resign`]
+    pub fn start(&self) {
+        if self.inner.etcd.is_none() {
+            return;
+        }
+        info!("Joining cluster as {}", self.inner.member.node);
+        let cluster = self.clone();
+        tokio::spawn(async move { cluster.run().await });
+    }
+
+    /// Whether this node should run the leader's duties
+    pub fn is_leader(&self) -> bool {
+        self.inner.leader.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> ClusterStatus {
+        self.inner.status.lock().expect("cluster lock poisoned").clone()
+    }
+
+    fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.inner.ttl_secs / 3)
+    }
+
+    fn set_leader(&self, leader: bool) {
+        if self.inner.leader.swap(leader, Ordering::SeqCst) != leader {
+            if leader {
+                info!("Elected cluster leader as {}", self.inner.member.node);
+            } else {
+                warn!("No longer the cluster leader");
+            }
+        }
+    }
+
+    async fn run(&self) {
+        let mut stop = self.inner.stop.subscribe();
+        while !*stop.borrow() {
+            let failure = tokio::select! {
+                result = self.session() => result.err(),
+                _ = stop.wait_for(|stop| *stop) => None,
+            };
+            if let Some(e) = failure {
+                warn!("Cluster session ended: {}", e);
+                self.set_leader(false);
+                let mut status = self.inner.status.lock().expect("cluster lock poisoned");
+                status.role = ClusterRole::Follower;
+                status.last_error = Some(e.to_string());
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.refresh_interval()) => {}
+                _ = stop.wait_for(|stop| *stop) => {}
+            }
+        }
+    }
+
+    /// Register under a fresh lease, then campaign and refresh until it fails
+    async fn session(&self) -> ClusterResult<()> {
+        let etcd = self.inner.etcd.as_ref().expect("sessions only run with etcd configured");
+        let lease = etcd.grant(self.inner.ttl_secs).await?;
+        *self.inner.lease.lock().expect("cluster lock poisoned") = Some(lease);
+        let member = serde_json::to_string(&self.inner.member).expect("members always serialize");
+        etcd.put(&format!("{}{}", self.inner.keys.members, self.inner.member.node), &member, Some(lease)).await?;
+        loop {
+            self.campaign(lease).await?;
+            tokio::time::sleep(self.refresh_interval()).await;
+            etcd.keep_alive(lease).await?;
+        }
+    }
+
+    /// Try for the leader key and refresh the view of the cluster
+    async fn campaign(&self, lease: i64) -> ClusterResult<()> {
+        let etcd = self.inner.etcd.as_ref().expect("campaigns only run with etcd configured");
+        let holder = etcd.create_or_get(&self.inner.keys.leader, &self.inner.member.node, lease).await?;
+        // Our own name under another lease is a previous run whose key has
+        // yet to expire, not this node
+        let leading = holder.lease == lease;
+        let members = etcd.list(&self.inner.keys.members).await?
+            .into_iter()
+            .filter_map(|kv| serde_json::from_str(&kv.value).ok())
+            .collect();
+
+        self.set_leader(leading);
+        let mut status = self.inner.status.lock().expect("cluster lock poisoned");
+        let leader = (!holder.value.is_empty()).then_some(holder.value);
+        if status.leader != leader {
+            status.leader_since = leader.is_some().then(Utc::now);
+        }
+        status.role = if leading { ClusterRole::Leader } else { ClusterRole::Follower };
+        status.leader = leader;
+        status.members = members;
+        status.last_error = None;
+        Ok(())
+    }
+
+    /// Publish a leader duty's result for the other nodes to serve
+    pub async fn share(&self, name: &str, value: &impl Serialize) -> ClusterResult<()> {
+        let Some(etcd) = &self.inner.etcd else { return Ok(()) };
+        let value = serde_json::to_string(value).map_err(|e| ClusterError::Response(e.to_string()))?;
+        etcd.put(&format!("{}{}", self.inner.keys.shared, name), &value, None).await
+    }
+
+    /// The latest result the leader shared under `name`
+    pub async fn shared<T: DeserializeOwned>(&self, name: &str) -> ClusterResult<Option<T>> {
+        let Some(etcd) = &self.inner.etcd else { return Ok(None) };
+        match etcd.get(&format!("{}{}", self.inner.keys.shared, name)).await? {
+            Some(kv) => serde_json::from_str(&kv.value).map(Some).map_err(|e| ClusterError::Response(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Stop campaigning and give up leadership and membership at once,
+    /// rather than when the lease runs out
+    pub async fn resign(&self) {
+        self.inner.stop.send_replace(true);
+        self.set_leader(false);
+        let lease = self.inner.lease.lock().expect("cluster lock poisoned").take();
+        if let (Some(etcd), Some(lease)) = (&self.inner.etcd, lease) {
+            match etcd.revoke(lease).await {
+                Ok(()) => info!("Left the cluster"),
+                Err(e) => warn!("Could not revoke cluster lease {}: {}", lease, e),
+            }
+        }
+    }
+}
+
+/// Puts every event on the cluster bus
+pub struct ClusterEventPublisher {
+    etcd: EtcdClient,
+    prefix: String,
+    ttl_secs: u64,
+    /// Leases are shared by the events of half a TTL, so each stays on the
+    /// bus between half and the whole of `event_ttl_secs`
+    lease: tokio::sync::Mutex<Option<(i64, Instant)>>,
+}
+
+impl ClusterEventPublisher {
+    pub fn new(config: &Config) -> ClusterResult<Self> {
+        let base = format!("{}/{}", config.cluster.prefix.trim_end_matches('/'), config.network.as_str());
+        info!("Cluster event publisher initialized under {}/events/", base);
+        Ok(Self {
+            etcd: EtcdClient::new(&config.cluster)?,
+            prefix: format!("{}/events/", base),
+            ttl_secs: config.cluster.event_ttl_secs,
+            lease: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn lease(&self) -> ClusterResult<i64> {
+        let mut lease = self.lease.lock().await;
+        match *lease {
+            Some((id, granted)) if granted.elapsed() < Duration::from_secs(self.ttl_secs / 2) => Ok(id),
+            _ => {
+                let id = self.etcd.grant(self.ttl_secs).await?;
+                *lease = Some((id, Instant::now()));
+                Ok(id)
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventPublisher for ClusterEventPublisher {
+    async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
+        let payload = serde_json::to_string(event)
+            .map_err(|e| crate::error::EventError::Serialization(e.to_string()))?;
+        // Keys sort by time, so a range read returns events in order
+        let key = format!("{}{:020}-{}", self.prefix, event.timestamp.timestamp_micros(), event.id);
+        let lease = self.lease().await?;
+        self.etcd.put(&key, &payload, Some(lease)).await?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "cluster"
+    }
+
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    fn endpoints(&self) -> Vec<PublisherEndpoint> {
+        self.etcd.endpoints
+            .iter()
+            .map(|endpoint| PublisherEndpoint {
+                address: format!("{}{}", endpoint, self.prefix),
+                topics: Vec::new(),
+                high_water_mark: None,
+                acknowledged: None,
+            })
+            .collect()
+    }
+}
+
+// The tests run the client against a fake etcd gateway
+#[cfg(all(test, feature = "http-client"))]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::collections::BTreeMap;
+
+    /// Just enough of the etcd gateway for leases, puts, ranges and the campaign transaction
+    #[derive(Default)]
+    struct FakeEtcd {
+        kvs: BTreeMap<Vec<u8>, (Vec<u8>, i64)>,
+        leases: Vec<i64>,
+        next_lease: i64,
+    }
+
+    impl FakeEtcd {
+        fn range(&self, request: &Value) -> Value {
+            let key = STANDARD.decode(request["key"].as_str().unwrap()).unwrap();
+            let end = request.get("range_end").map(|end| STANDARD.decode(end.as_str().unwrap()).unwrap());
+            let kvs: Vec<Value> = self.kvs.iter()
+                .filter(|(k, _)| match &end {
+                    Some(end) => **k >= key && *k < end,
+                    None => **k == key,
+                })
+                .map(|(k, (v, lease))| json!({
+                    "key": STANDARD.encode(k), "value": STANDARD.encode(v), "lease": lease.to_string(),
+                }))
+                .collect();
+            if kvs.is_empty() { json!({}) } else { json!({ "kvs": kvs }) }
+        }
+
+        fn put(&mut self, request: &Value) {
+            let key = STANDARD.decode(request["key"].as_str().unwrap()).unwrap();
+            let value = STANDARD.decode(request["value"].as_str().unwrap()).unwrap();
+            let lease = request.get("lease").and_then(int64).unwrap_or(0);
+            self.kvs.insert(key, (value, lease));
+        }
+
+        fn handle(&mut self, path: &str, request: Value) -> Value {
+            match path {
+                "lease/grant" => {
+                    self.next_lease += 1;
+                    self.leases.push(self.next_lease);
+                    json!({ "ID": self.next_lease.to_string(), "TTL": request["TTL"].to_string() })
+                }
+                "lease/keepalive" => {
+                    let ttl = if self.leases.contains(&int64(&request["ID"]).unwrap()) { "10" } else { "0" };
+                    json!({ "result": { "ID": request["ID"], "TTL": ttl } })
+                }
+                "lease/revoke" => {
+                    let lease = int64(&request["ID"]).unwrap();
+                    self.leases.retain(|id| *id != lease);
+                    self.kvs.retain(|_, (_, held)| *held != lease);
+                    json!({})
+                }
+                "kv/put" => {
+                    self.put(&request);
+                    json!({})
+                }
+                "kv/range" => self.range(&request),
+                "kv/txn" => {
+                    let key = STANDARD.decode(request["compare"][0]["key"].as_str().unwrap()).unwrap();
+                    if self.kvs.contains_key(&key) {
+                        let range = self.range(&request["failure"][0]["request_range"]);
+                        json!({ "succeeded": false, "responses": [{ "response_range": range }] })
+                    } else {
+                        self.put(&request["success"][0]["request_put"]);
+                        json!({ "succeeded": true, "responses": [{ "response_put": {} }] })
+                    }
+                }
+                _ => unreachable!("unexpected etcd call {}", path),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_leader_election_and_failover() {
+        let etcd = web::Data::new(Mutex::new(FakeEtcd::default()));
+        let server_etcd = etcd.clone();
+        let server = HttpServer::new(move || {
+            App::new().app_data(server_etcd.clone()).route(
+                "/v3/{path:.*}",
+                web::post().to(|path: web::Path<String>, body: web::Json<Value>, etcd: web::Data<Mutex<FakeEtcd>>| async move {
+                    HttpResponse::Ok().json(etcd.lock().unwrap().handle(&path, body.into_inner()))
+                }),
+            )
+        })
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_rt::spawn(server.run());
+
+        let mut config = Config::default_regtest();
+        config.cluster.enabled = true;
+        config.cluster.etcd_endpoints = vec![format!("http://{}", address)];
+        let standalone = Cluster::new(&Config::default_regtest(), "ab").unwrap();
+        assert!(standalone.is_leader());
+        assert_eq!(standalone.status().role, ClusterRole::Standalone);
+
+        let a = Cluster::new(&config, "aaaaaaaaaaaaaaaaaaaa").unwrap();
+        let b = Cluster::new(&config, "bbbbbbbbbbbbbbbbbbbb").unwrap();
+        assert!(!a.is_leader());
+
+        let etcd_client = EtcdClient::new(&config.cluster).unwrap();
+        let lease_a = etcd_client.grant(10).await.unwrap();
+        *a.inner.lease.lock().unwrap() = Some(lease_a);
+        let lease_b = etcd_client.grant(10).await.unwrap();
+        a.campaign(lease_a).await.unwrap();
+        b.campaign(lease_b).await.unwrap();
+        assert!(a.is_leader());
+        assert!(!b.is_leader());
+        assert_eq!(b.status().leader.as_deref(), Some("node-aaaaaaaaaaaaaaaa"));
+        assert_eq!(b.status().role, ClusterRole::Follower);
+
+        // Followers serve what the leader computed
+        a.share("answer", &42u32).await.unwrap();
+        assert_eq!(b.shared::<u32>("answer").await.unwrap(), Some(42));
+        assert_eq!(b.shared::<u32>("missing").await.unwrap(), None);
+
+        // Resigning drops the key at once and the next campaign hands over
+        a.resign().await;
+        assert!(!a.is_leader());
+        assert!(etcd_client.keep_alive(lease_a).await.is_err());
+        b.campaign(lease_b).await.unwrap();
+        assert!(b.is_leader());
+        assert_eq!(b.status().leader.as_deref(), Some("node-bbbbbbbbbbbbbbbb"));
+        assert!(etcd.lock().unwrap().kvs.values().all(|(_, lease)| *lease != lease_a));
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end("/a/"), b"/a0".to_vec());
+        assert_eq!(prefix_end("a\u{7f}"), b"a\x80".to_vec());
+    }
+}
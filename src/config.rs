@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::str::FromStr;
 use crate::error::{ConfigError, ConfigResult};
@@ -14,6 +15,504 @@ pub struct Config {
     pub metrics: MetricsConfig,
     pub logging: LoggingConfig,
     pub events: EventsConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    #[serde(default)]
+    pub caches: CacheConfig,
+    #[serde(default)]
+    pub analytics: AnalyticsConfig,
+    #[serde(default)]
+    pub mempool: MempoolConfig,
+    #[serde(default)]
+    pub stratum: StratumConfig,
+    #[serde(default)]
+    pub mining: MiningConfig,
+    #[serde(default)]
+    pub wallet: WalletConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub runtime: RuntimeConfig,
+    #[serde(default)]
+    pub identity: IdentityConfig,
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+}
+
+/// Threads the node runs on
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Event loop threads the actors are spread over; 1 keeps them all on
+    /// the main one, 2 gives validation its own and more add worker arbiters
+    pub arbiters: usize,
+    /// Threads each arbiter may start for RocksDB scans, compaction and
+    /// other blocking work
+    pub blocking_threads: usize,
+    /// CPU cores the validation arbiter and its blocking threads are pinned to
+    pub validation_cores: Vec<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            arbiters: 1,
+            // tokio's default
+            blocking_threads: 512,
+            validation_cores: Vec::new(),
+        }
+    }
+}
+
+/// Block validation shortcuts and limits
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ValidationConfig {
+    /// Block hash whose scripts, and those of the blocks below it, are not
+    /// verified; set together with `assume_valid_height`
+    pub assume_valid_block: Option<String>,
+    pub assume_valid_height: Option<u64>,
+    /// Reorgs disconnecting more blocks than this wait for `acceptreorg`
+    /// instead of being followed automatically
+    pub max_reorg_depth: Option<u64>,
+}
+
+/// The signed node-info document served next to the node's identity key
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdentityConfig {
+    /// Serve the signed document at `/api/v1/identity`, not just the key
+    pub announce: bool,
+    pub alias: Option<String>,
+    /// How to reach the operator, e.g. an email address or URL
+    pub contact: Option<String>,
+}
+
+/// Coordination with other nodes of a fleet through etcd
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    /// Name this node registers and campaigns under; defaults to
+    /// `node-` and the start of its identity key
+    pub node_name: Option<String>,
+    /// etcd v3 HTTP gateway URLs, tried in turn
+    pub etcd_endpoints: Vec<String>,
+    /// Key prefix shared by every node of the cluster
+    pub prefix: String,
+    /// Lease TTL for the leader and member keys; a node that stops
+    /// refreshing its lease loses leadership after this long
+    pub lease_ttl_secs: u64,
+    /// Also put every event on the bus under `<prefix>/<network>/events/`
+    pub publish_events: bool,
+    /// How long events stay on the bus
+    pub event_ttl_secs: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_name: None,
+            etcd_endpoints: vec!["http://127.0.0.1:2379".to_string()],
+            prefix: "/bitknotsrs".to_string(),
+            lease_ttl_secs: 10,
+            publish_events: true,
+            event_ttl_secs: 300,
+        }
+    }
+}
+
+/// How long each component gets to drain during shutdown before it is aborted
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    pub api_secs: u64,
+    pub rpc_secs: u64,
+    pub miner_secs: u64,
+    pub network_secs: u64,
+    pub mempool_secs: u64,
+    pub chain_secs: u64,
+    pub storage_secs: u64,
+    /// Webhook and watch callback deliveries still in flight
+    pub events_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            api_secs: 10,
+            rpc_secs: 5,
+            miner_secs: 5,
+            network_secs: 5,
+            mempool_secs: 10,
+            chain_secs: 30,
+            storage_secs: 30,
+            events_secs: 10,
+        }
+    }
+}
+
+/// Wallet history export
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WalletConfig {
+    /// Currency of the fiat columns in history CSV exports
+    pub fiat_currency: String,
+    /// Daily price endpoint with `{date}` (YYYY-MM-DD) and `{currency}`
+    /// placeholders; fiat columns are left empty when unset
+    pub price_url: Option<String>,
+    /// JSON pointer to the price in the endpoint's response
+    pub price_pointer: String,
+    pub price_timeout_secs: u64,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            fiat_currency: "USD".to_string(),
+            price_url: None,
+            price_pointer: "/price".to_string(),
+            price_timeout_secs: 10,
+        }
+    }
+}
+
+/// Stratum v1 endpoint for solo mining against the node
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StratumConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Address the coinbase pays to
+    pub payout_address: Option<String>,
+    /// Minimum difficulty for a submission to count as a share
+    pub share_difficulty: f64,
+    /// Seconds between job updates picking up new mempool transactions
+    pub job_refresh_secs: u64,
+    pub extranonce2_size: usize,
+}
+
+impl Default for StratumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: 3333,
+            payout_address: None,
+            share_difficulty: 1.0,
+            job_refresh_secs: 30,
+            extranonce2_size: 4,
+        }
+    }
+}
+
+impl StratumConfig {
+    /// Script for `payout_address`, which takes precedence over `[mining]` payouts
+    pub fn payout_script(&self, network: bitcoin::Network) -> ConfigResult<Option<bitcoin::ScriptBuf>> {
+        self.payout_address
+            .as_deref()
+            .map(|address| address_script("stratum.payout_address", address, network))
+            .transpose()
+    }
+}
+
+/// Coinbase layout for blocks built by the node
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MiningConfig {
+    /// Appended to the coinbase scriptSig after the extra nonce
+    pub coinbase_message: String,
+    /// Extra nonce bytes reserved in the coinbase scriptSig for local mining
+    pub extra_nonce_size: usize,
+    /// Coinbase outputs; the block reward is split by weight
+    pub payouts: Vec<CoinbasePayout>,
+}
+
+impl Default for MiningConfig {
+    fn default() -> Self {
+        Self {
+            coinbase_message: "/bitknotsrs/".to_string(),
+            extra_nonce_size: 8,
+            payouts: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CoinbasePayout {
+    pub address: String,
+    /// Relative share, usually a percentage
+    pub weight: f64,
+}
+
+impl MiningConfig {
+    pub fn payout_scripts(&self, network: bitcoin::Network) -> ConfigResult<Vec<(bitcoin::ScriptBuf, f64)>> {
+        self.payouts
+            .iter()
+            .map(|payout| {
+                if !payout.weight.is_finite() || payout.weight <= 0.0 {
+                    return Err(ConfigError::InvalidValue {
+                        field: "mining.payouts.weight".to_string(),
+                        value: format!("{} for {}", payout.weight, payout.address),
+                    });
+                }
+                Ok((address_script("mining.payouts.address", &payout.address, network)?, payout.weight))
+            })
+            .collect()
+    }
+}
+
+fn address_script(field: &str, address: &str, network: bitcoin::Network) -> ConfigResult<bitcoin::ScriptBuf> {
+    let invalid = |value: String| ConfigError::InvalidValue {
+        field: field.to_string(),
+        value,
+    };
+    let parsed: bitcoin::Address<bitcoin::address::NetworkUnchecked> = address
+        .parse()
+        .map_err(|e| invalid(format!("{}: {}", address, e)))?;
+    let parsed = parsed
+        .require_network(network)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(parsed.script_pubkey())
+}
+
+/// Named bundle of mempool standardness rules
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyProfile {
+    /// Bitcoin Core defaults
+    #[default]
+    CoreDefault,
+    /// Bitcoin Knots defaults: small datacarrier, no bare multisig
+    KnotsStrict,
+    /// Relay anything consensus-valid up to the block weight
+    Permissive,
+}
+
+impl PolicyProfile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyProfile::CoreDefault => "core-default",
+            PolicyProfile::KnotsStrict => "knots-strict",
+            PolicyProfile::Permissive => "permissive",
+        }
+    }
+}
+
+/// Which mempool conflicts may be replaced
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RbfPolicy {
+    /// Any conflict paying a higher fee rate replaces
+    Full,
+    /// Only transactions signalling BIP125 can be replaced
+    OptIn,
+    /// First seen wins
+    Disabled,
+}
+
+/// What the script filter does with the scripts it lists
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptFilterMode {
+    /// Relay regardless of the scripts involved
+    #[default]
+    Off,
+    /// Refuse transactions that pay to or spend from a listed script
+    Blocklist,
+    /// Refuse transactions that pay to or spend from any script not listed
+    Allowlist,
+}
+
+impl ScriptFilterMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptFilterMode::Off => "off",
+            ScriptFilterMode::Blocklist => "blocklist",
+            ScriptFilterMode::Allowlist => "allowlist",
+        }
+    }
+}
+
+/// Operator list of scriptPubKeys the relay lane refuses, or solely accepts.
+///
+/// `mode` must be set for the lists to take effect, so the node's stance is
+/// stated in the config rather than implied by a non-empty list.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScriptFilterConfig {
+    pub mode: ScriptFilterMode,
+    pub addresses: Vec<String>,
+    /// Hex scriptPubKeys
+    pub scripts: Vec<String>,
+    /// Account keys, `[fingerprint/path]xpub` or a bare xpub, whose receive
+    /// and change P2WPKH and P2TR scripts are listed
+    pub xpubs: Vec<String>,
+    /// Addresses derived per chain and kind of each xpub
+    pub xpub_lookahead: u32,
+}
+
+impl Default for ScriptFilterConfig {
+    fn default() -> Self {
+        Self {
+            mode: ScriptFilterMode::Off,
+            addresses: Vec::new(),
+            scripts: Vec::new(),
+            xpubs: Vec::new(),
+            xpub_lookahead: 1000,
+        }
+    }
+}
+
+impl ScriptFilterConfig {
+    /// Every listed scriptPubKey, xpub derivations included
+    pub fn script_pubkeys(&self, network: bitcoin::Network) -> ConfigResult<HashSet<bitcoin::ScriptBuf>> {
+        use bitcoin::hex::FromHex;
+        use crate::wallet::{AccountKey, AddressKind, CHANGE_CHAIN, RECEIVE_CHAIN};
+
+        let mut scripts = HashSet::new();
+        for address in &self.addresses {
+            scripts.insert(address_script("mempool.script_filter.addresses", address, network)?);
+        }
+        for script in &self.scripts {
+            let bytes = Vec::<u8>::from_hex(script).map_err(|e| ConfigError::InvalidValue {
+                field: "mempool.script_filter.scripts".to_string(),
+                value: format!("{}: {}", script, e),
+            })?;
+            scripts.insert(bitcoin::ScriptBuf::from_bytes(bytes));
+        }
+        for xpub in &self.xpubs {
+            let invalid = |e: crate::error::WalletError| ConfigError::InvalidValue {
+                field: "mempool.script_filter.xpubs".to_string(),
+                value: format!("{}: {}", xpub, e),
+            };
+            let key: AccountKey = xpub.parse().map_err(invalid)?;
+            for chain in [RECEIVE_CHAIN, CHANGE_CHAIN] {
+                for index in 0..self.xpub_lookahead {
+                    for kind in [AddressKind::Bech32, AddressKind::Bech32m] {
+                        scripts.insert(key.address(chain, index, kind, network).map_err(invalid)?.script_pubkey());
+                    }
+                }
+            }
+        }
+        Ok(scripts)
+    }
+}
+
+/// Mempool policy profile with optional per-field overrides
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MempoolConfig {
+    pub policy_profile: PolicyProfile,
+    pub datacarrier: Option<bool>,
+    /// Total OP_RETURN scriptPubKey bytes allowed per transaction
+    pub max_datacarrier_bytes: Option<usize>,
+    /// Fee rate in sat/kvB used to derive the dust threshold
+    pub dust_relay_fee: Option<u64>,
+    pub permit_bare_multisig: Option<bool>,
+    pub rbf: Option<RbfPolicy>,
+    pub max_standard_tx_weight: Option<u64>,
+    /// Hours before an unconfirmed transaction is dropped, 336 when unset
+    pub expiry_hours: Option<u64>,
+    /// Total virtual size kept before the lowest fee rate transactions are
+    /// evicted, 300000000 when unset
+    pub max_mempool: Option<u64>,
+    /// Unconfirmed ancestors a transaction may have, itself included, 25 when unset
+    pub limit_ancestor_count: Option<usize>,
+    /// Total virtual size of those ancestors in kvB, 101 when unset
+    pub limit_ancestor_size: Option<u64>,
+    /// Unconfirmed descendants any mempool transaction may have, itself
+    /// included, 25 when unset
+    pub limit_descendant_count: Option<usize>,
+    /// Total virtual size of those descendants in kvB, 101 when unset
+    pub limit_descendant_size: Option<u64>,
+    /// Write the mempool to `<datadir>/mempool.json` on shutdown and reload
+    /// it on start, true when unset
+    pub persist_mempool: Option<bool>,
+    pub script_filter: ScriptFilterConfig,
+    pub miner_lane: MinerLaneConfig,
+}
+
+/// Authenticated submission lane for a co-located miner or pool.
+///
+/// Transactions submitted here skip standardness policy (never consensus
+/// checks) and are placed first in block templates.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MinerLaneConfig {
+    pub enabled: bool,
+    /// Bearer token expected in the Authorization header
+    pub token: Option<String>,
+    /// Client IPs allowed to use the lane
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for MinerLaneConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            token: None,
+            allowed_hosts: vec!["127.0.0.1".to_string(), "::1".to_string()],
+        }
+    }
+}
+
+/// Background chain analytics jobs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Seconds between UTXO set distribution scans; 0 disables them
+    pub utxo_distribution_interval_secs: u64,
+    /// Seconds between compressed mempool snapshots under
+    /// `<datadir>/mempool_snapshots`; 0 (the default) disables them
+    pub mempool_snapshot_interval_secs: u64,
+    /// Hours mempool snapshots are kept before they are deleted
+    pub mempool_snapshot_retention_hours: u64,
+    /// Seconds between samples of the mempool minimum fee for the fee rate
+    /// history; 0 disables them
+    pub mempool_fee_interval_secs: u64,
+    /// Days of fee rate history kept; 0 keeps it all
+    pub fee_history_retention_days: u64,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            utxo_distribution_interval_secs: 3600,
+            mempool_snapshot_interval_secs: 0,
+            mempool_snapshot_retention_hours: 168,
+            mempool_fee_interval_secs: 600,
+            fee_history_retention_days: 365,
+        }
+    }
+}
+
+/// Byte limits for caches holding peer-supplied data
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub inventory_max_bytes: usize,
+    pub reject_max_bytes: usize,
+    pub reject_ttl_secs: u64,
+    pub orphan_max_bytes: usize,
+    /// Serialized REST responses for blocks and transactions
+    pub response_max_bytes: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            inventory_max_bytes: 4 * 1024 * 1024,
+            reject_max_bytes: 1024 * 1024,
+            reject_ttl_secs: 600,
+            orphan_max_bytes: 5 * 1024 * 1024,
+            response_max_bytes: 32 * 1024 * 1024,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -39,6 +538,34 @@ impl FromStr for Network {
     }
 }
 
+impl Network {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    /// Default P2P port for the network
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Network::Mainnet => 8333,
+            Network::Testnet => 18333,
+            Network::Regtest => 18444,
+        }
+    }
+
+    /// The equivalent rust-bitcoin network, used for consensus constants and address encoding
+    pub fn to_bitcoin_network(&self) -> bitcoin::Network {
+        match self {
+            Network::Mainnet => bitcoin::Network::Bitcoin,
+            Network::Testnet => bitcoin::Network::Testnet,
+            Network::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ApiConfig {
     pub host: String,
@@ -46,6 +573,39 @@ pub struct ApiConfig {
     pub enabled: bool,
     pub cors_enabled: bool,
     pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub usage: UsageConfig,
+}
+
+/// Per-endpoint request accounting behind `/api/v1/admin/usage`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// Requests older than this drop out of the counts and percentiles
+    pub window_secs: u64,
+    /// p95 latency objective for every endpoint, in milliseconds
+    pub latency_slo_ms: Option<u64>,
+    /// p95 latency objectives for single endpoints, keyed by route such as `/api/v1/block/{hash}`
+    pub endpoint_latency_slo_ms: std::collections::BTreeMap<String, u64>,
+    /// Share of requests answered with a 5xx status the objective allows, e.g. 0.01
+    pub error_rate_slo: Option<f64>,
+    /// Publish an `SloBreached` event when an endpoint starts missing an objective
+    pub slo_events: bool,
+    /// Requests an endpoint needs in the window before its objectives are judged
+    pub min_requests: u64,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 300,
+            latency_slo_ms: None,
+            endpoint_latency_slo_ms: std::collections::BTreeMap::new(),
+            error_rate_slo: None,
+            slo_events: false,
+            min_requests: 20,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -62,6 +622,22 @@ pub struct RpcConfig {
     pub password: Option<String>,
     pub enabled: bool,
     pub allowed_methods: Vec<String>,
+    /// How `getblock` and `getrawtransaction` results are written; see [`crate::rpc::canonical`]
+    #[serde(default)]
+    pub json_format: RpcJsonFormat,
+}
+
+/// Output layout for clients that parse RPC results as text
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcJsonFormat {
+    /// Keys sorted, amounts as the shortest float that round-trips
+    #[default]
+    Native,
+    /// Bitcoin Core's key order, amounts as 8-decimal BTC
+    Core,
+    /// Bitcoin Core's key order, amounts as integer satoshis
+    Sats,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -72,6 +648,140 @@ pub struct StorageConfig {
     pub compression: CompressionType,
     pub backup_enabled: bool,
     pub backup_interval_hours: u64,
+    /// Where RocksDB backups are kept, `backups` next to `rocks_db_path` by default
+    #[serde(default)]
+    pub backup_path: Option<PathBuf>,
+    /// Backups kept after each new one; files shared between backups are stored once
+    #[serde(default = "default_backup_keep")]
+    pub backup_keep: usize,
+    /// Peer lifecycle events kept for post-mortems before the oldest are pruned
+    #[serde(default = "default_peer_timeline_max_events")]
+    pub peer_timeline_max_events: u64,
+    /// Published events kept for `/api/v1/events` replay before the oldest are pruned
+    #[serde(default = "default_event_journal_max_events")]
+    pub event_journal_max_events: u64,
+    /// Keep BIP 352 silent payment tweaks for every connected block
+    #[serde(default)]
+    pub silent_payment_index: bool,
+    /// Index funding and spending outpoints by script hash for address lookups
+    #[serde(default)]
+    pub address_index: bool,
+    /// Stored blocks added to a newly enabled index per second
+    #[serde(default = "default_index_backfill_blocks_per_sec")]
+    pub index_backfill_blocks_per_sec: u64,
+    /// Bytes per second of blocks this node does not store fetched from peers
+    /// for a newly enabled index, and discarded once indexed
+    #[serde(default = "default_index_backfill_fetch_bytes_per_sec")]
+    pub index_backfill_fetch_bytes_per_sec: u64,
+    /// Blocks behind a restored node snapshot validated per second
+    #[serde(default = "default_background_validation_blocks_per_sec")]
+    pub background_validation_blocks_per_sec: u64,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    /// Read through to a full node instead of opening RocksDB
+    #[serde(default)]
+    pub remote: RemoteStorageConfig,
+    /// Serve storage reads to API replicas
+    #[serde(default)]
+    pub read_api: StorageReadApiConfig,
+}
+
+/// Where an API replica reads its storage from
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RemoteStorageConfig {
+    pub enabled: bool,
+    /// The full node's REST API, e.g. `http://bitknotsrs-0:8332`
+    pub url: String,
+    /// The full node's `storage.read_api.token`
+    pub token: Option<String>,
+    pub timeout_secs: u64,
+}
+
+impl Default for RemoteStorageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "http://127.0.0.1:8332".to_string(),
+            token: None,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// `POST /api/v1/storage/read` for API replicas
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageReadApiConfig {
+    pub enabled: bool,
+    /// Bearer token replicas authenticate with
+    pub token: Option<String>,
+}
+
+fn default_peer_timeline_max_events() -> u64 {
+    10_000
+}
+
+fn default_event_journal_max_events() -> u64 {
+    100_000
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_index_backfill_blocks_per_sec() -> u64 {
+    100
+}
+
+fn default_index_backfill_fetch_bytes_per_sec() -> u64 {
+    1_000_000
+}
+
+fn default_background_validation_blocks_per_sec() -> u64 {
+    100
+}
+
+impl StorageConfig {
+    pub fn backup_path(&self) -> PathBuf {
+        self.backup_path.clone().unwrap_or_else(|| self.rocks_db_path.with_file_name("backups"))
+    }
+
+    /// The chainstate that validates the blocks behind a restored node
+    /// snapshot, next to `rocks_db_path`
+    pub fn background_chainstate_path(&self) -> PathBuf {
+        self.rocks_db_path.with_file_name("background_chainstate")
+    }
+}
+
+/// Scheduled manual compaction, kept to quiet hours so it does not compete
+/// with block validation for disk bandwidth
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompactionConfig {
+    pub enabled: bool,
+    /// UTC `HH:MM-HH:MM` windows compaction may start in; empty allows any time
+    pub windows: Vec<String>,
+    pub check_interval_secs: u64,
+    /// Compact each column family at most this often
+    pub min_interval_hours: u64,
+    /// RocksDB rate limit on flush and compaction writes, 0 for unlimited
+    pub rate_limit_bytes_per_sec: u64,
+    /// Column families to compact; empty means all of them
+    pub column_families: Vec<String>,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            windows: Vec::new(),
+            check_interval_secs: 600,
+            min_interval_hours: 24,
+            rate_limit_bytes_per_sec: 0,
+            column_families: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -88,16 +798,250 @@ pub enum CompressionType {
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct NetworkConfig {
+    /// Accept inbound connections; `false` runs the node outbound-only
+    #[serde(default = "default_listen")]
+    pub listen: bool,
     pub listen_port: u16,
     pub max_peers: usize,
     pub connection_timeout_secs: u64,
     pub discovery_interval_secs: u64,
     pub custom_peers: Vec<String>,
     pub enable_dns_seeds: bool,
+    /// How long a DNS seed's answer is reused before querying it again
+    #[serde(default = "default_dns_seed_ttl_secs")]
+    pub dns_seed_ttl_secs: u64,
+    /// Fall back to the embedded seed list when no DNS seed answers
+    #[serde(default = "default_use_fixed_seeds")]
+    pub use_fixed_seeds: bool,
     pub enable_peer_exchange: bool,
+    /// Map the listen port through UPnP/NAT-PMP; ignored when not listening
+    #[serde(default)]
+    pub nat_traversal: bool,
+    #[serde(default)]
+    pub proxy: ProxyRoutingConfig,
+    #[serde(default)]
+    pub peer_rate_limits: PeerRateLimitConfig,
+    /// How malformed but recoverable P2P messages are handled
+    #[serde(default)]
+    pub protocol_strictness: ProtocolStrictness,
+    #[serde(default)]
+    pub partition: PartitionConfig,
+    #[serde(default)]
+    pub ibd_greylist: IbdGreylistConfig,
     pub zmq: ZmqConfig,
 }
 
+/// Handling of P2P messages that break the protocol in ways we can recover from
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolStrictness {
+    /// Disconnect on the first violation, for conformance testing other implementations
+    Strict,
+    /// Drop or penalize the message and keep the connection, as Core does
+    #[default]
+    Compat,
+}
+
+impl ProtocolStrictness {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolStrictness::Strict => "strict",
+            ProtocolStrictness::Compat => "compat",
+        }
+    }
+}
+
+/// Heuristics for noticing that we may be eclipsed or on a partitioned network
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PartitionConfig {
+    pub enabled: bool,
+    pub check_interval_secs: u64,
+    /// Suspect a partition after this many expected block intervals without a new tip
+    pub stale_tip_intervals: f64,
+    /// Window, in expected block intervals, over which chainwork growth is compared to the tip's difficulty
+    pub work_window_intervals: f64,
+    /// Suspect a partition when the window gained less than this share of the work it should have
+    pub min_work_ratio: f64,
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_secs: 60,
+            stale_tip_intervals: 6.0,
+            work_window_intervals: 12.0,
+            min_work_ratio: 0.25,
+        }
+    }
+}
+
+/// Limits on inbound peers while the node is still in initial block download
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IbdGreylistConfig {
+    pub enabled: bool,
+    /// Refuse every inbound connection instead of capping them at `max_inbound`
+    pub refuse_inbound: bool,
+    pub max_inbound: usize,
+    /// Normal service resumes, for good, once verification progress reaches this
+    pub resume_progress: f64,
+}
+
+impl Default for IbdGreylistConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            refuse_inbound: false,
+            max_inbound: 2,
+            resume_progress: 0.9999,
+        }
+    }
+}
+
+/// Token bucket settings for one message class
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageRateLimit {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+/// Per-peer limits on received P2P messages
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PeerRateLimitConfig {
+    pub addr: MessageRateLimit,
+    pub inv: MessageRateLimit,
+    pub headers: MessageRateLimit,
+    pub other: MessageRateLimit,
+    pub bytes_per_second: f64,
+    pub bytes_burst: f64,
+    /// Misbehavior score added each time a limit is exceeded
+    pub misbehavior_per_violation: u32,
+    pub disconnect_threshold: u32,
+}
+
+impl Default for PeerRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            addr: MessageRateLimit { per_second: 0.1, burst: 10.0 },
+            inv: MessageRateLimit { per_second: 50.0, burst: 500.0 },
+            headers: MessageRateLimit { per_second: 5.0, burst: 50.0 },
+            other: MessageRateLimit { per_second: 100.0, burst: 1000.0 },
+            bytes_per_second: 4.0 * 1024.0 * 1024.0,
+            bytes_burst: 32.0 * 1024.0 * 1024.0,
+            misbehavior_per_violation: 1,
+            disconnect_threshold: 100,
+        }
+    }
+}
+
+/// Address families a peer address can belong to, as named by `-onlynet`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum NetType {
+    Ipv4,
+    Ipv6,
+    Onion,
+    I2p,
+    Cjdns,
+}
+
+impl NetType {
+    pub const ALL: [NetType; 5] = [NetType::Ipv4, NetType::Ipv6, NetType::Onion, NetType::I2p, NetType::Cjdns];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetType::Ipv4 => "ipv4",
+            NetType::Ipv6 => "ipv6",
+            NetType::Onion => "onion",
+            NetType::I2p => "i2p",
+            NetType::Cjdns => "cjdns",
+        }
+    }
+
+    /// Classify a `host:port` peer address; unresolved hostnames count as IPv4
+    pub fn classify(peer: &str) -> NetType {
+        let host = if let Some(rest) = peer.strip_prefix('[') {
+            rest.split(']').next().unwrap_or(rest)
+        } else if peer.matches(':').count() == 1 {
+            peer.split(':').next().unwrap_or(peer)
+        } else {
+            peer
+        };
+
+        if host.ends_with(".onion") {
+            return NetType::Onion;
+        }
+        if host.ends_with(".i2p") {
+            return NetType::I2p;
+        }
+        match host.parse::<std::net::IpAddr>() {
+            // CJDNS addresses live in fc00::/8
+            Ok(std::net::IpAddr::V6(ip)) if ip.segments()[0] & 0xff00 == 0xfc00 => NetType::Cjdns,
+            Ok(std::net::IpAddr::V6(_)) => NetType::Ipv6,
+            _ => NetType::Ipv4,
+        }
+    }
+}
+
+/// A SOCKS5 proxy, optionally authenticated
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyConfig {
+    pub address: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Use fresh random credentials per connection for Tor stream isolation
+    #[serde(default)]
+    pub randomize_credentials: bool,
+}
+
+/// Which proxy each address family is dialed through
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ProxyRoutingConfig {
+    /// Proxy for clearnet connections, also used for onion when `onion` is unset
+    pub default: Option<ProxyConfig>,
+    pub onion: Option<ProxyConfig>,
+    pub i2p: Option<ProxyConfig>,
+    /// Only dial these address families; empty allows all
+    #[serde(default)]
+    pub onlynet: Vec<NetType>,
+}
+
+/// How an outbound connection to a peer should be made
+#[derive(Debug, Clone, Copy)]
+pub enum PeerRoute<'a> {
+    Direct,
+    Proxy(&'a ProxyConfig),
+    Unreachable,
+}
+
+/// Reachability of one address family, in the shape of `getnetworkinfo.networks`
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkReachability {
+    pub name: &'static str,
+    pub limited: bool,
+    pub reachable: bool,
+    pub proxy: String,
+    pub proxy_randomize_credentials: bool,
+}
+
+fn default_listen() -> bool {
+    true
+}
+
+fn default_dns_seed_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_use_fixed_seeds() -> bool {
+    true
+}
+
+/// Outbound connections a node aims for, matching Core's full-relay plus block-relay-only slots
+pub const DEFAULT_OUTBOUND_PEERS: usize = 10;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ZmqConfig {
     pub enabled: bool,
@@ -113,14 +1057,43 @@ pub struct MetricsConfig {
     pub port: u16,
     pub path: String,
     pub otel: OpenTelemetryConfig,
+    #[serde(default)]
+    pub system: SystemMonitorConfig,
+}
+
+/// Sampling of the node's own memory, CPU, file descriptors and disk space
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SystemMonitorConfig {
+    /// How often to sample; 0 disables the monitor
+    pub interval_secs: u64,
+    /// Publish a `LowDiskSpace` event when the data directory's disk has less free
+    pub low_disk_free_mb: u64,
+}
+
+impl Default for SystemMonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 15,
+            low_disk_free_mb: 10_240,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OpenTelemetryConfig {
     pub enabled: bool,
+    /// OTLP/gRPC collector, `http://localhost:4317` when unset or empty
     pub endpoint: Option<String>,
     pub service_name: String,
     pub service_version: String,
+    /// Share of traces exported, from 0.0 to 1.0
+    #[serde(default = "default_otel_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+fn default_otel_sample_ratio() -> f64 {
+    1.0
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -149,6 +1122,8 @@ pub struct EventsConfig {
     pub zmq: ZmqEventConfig,
     pub k8s: K8sEventConfig,
     pub webhook: WebhookEventConfig,
+    #[serde(default)]
+    pub nats: NatsEventConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -169,11 +1144,100 @@ pub struct K8sEventConfig {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WebhookEventConfig {
     pub enabled: bool,
-    pub endpoints: Vec<String>,
+    pub endpoints: Vec<WebhookEndpointConfig>,
     pub timeout_secs: u64,
     pub retry_attempts: u32,
 }
 
+/// A webhook URL, or a table with the URL, its signing secret and the events it wants
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum WebhookEndpointConfig {
+    Url(String),
+    Options {
+        url: String,
+        /// HMAC-SHA256 key the request body is signed with
+        secret: Option<String>,
+        /// Event types such as `BlockAdded` sent to this endpoint; every event when empty
+        #[serde(default)]
+        event_types: Vec<String>,
+    },
+}
+
+impl WebhookEndpointConfig {
+    pub fn url(&self) -> &str {
+        match self {
+            WebhookEndpointConfig::Url(url) | WebhookEndpointConfig::Options { url, .. } => url,
+        }
+    }
+
+    pub fn secret(&self) -> Option<&str> {
+        match self {
+            WebhookEndpointConfig::Url(_) => None,
+            WebhookEndpointConfig::Options { secret, .. } => secret.as_deref(),
+        }
+    }
+
+    pub fn event_types(&self) -> &[String] {
+        match self {
+            WebhookEndpointConfig::Url(_) => &[],
+            WebhookEndpointConfig::Options { event_types, .. } => event_types,
+        }
+    }
+
+    pub fn accepts(&self, event_type: &str) -> bool {
+        let event_types = self.event_types();
+        event_types.is_empty() || event_types.iter().any(|accepted| accepted == event_type)
+    }
+}
+
+/// Events published to NATS subjects `<subject_prefix>.<network>.<kind>.<action>`,
+/// e.g. `bitcoin.main.block.added`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NatsEventConfig {
+    pub enabled: bool,
+    pub url: String,
+    pub subject_prefix: String,
+    /// Publish through JetStream and wait for the server to store each event
+    pub jetstream: bool,
+    /// JetStream stream over the node's subjects, created if missing
+    pub stream: String,
+    pub credentials_file: Option<PathBuf>,
+}
+
+impl Default for NatsEventConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "nats://127.0.0.1:4222".to_string(),
+            subject_prefix: "bitcoin".to_string(),
+            jetstream: false,
+            stream: "BITCOIN_EVENTS".to_string(),
+            credentials_file: None,
+        }
+    }
+}
+
+/// Watches registered at startup, in addition to those added through the API
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WatchConfig {
+    #[serde(default)]
+    pub addresses: Vec<AddressWatchConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AddressWatchConfig {
+    /// Address, `addr(...)` or `raw(<hex>)` descriptor
+    pub address: String,
+    #[serde(default = "default_payment_confirmations")]
+    pub confirmations: u32,
+    pub callback_url: Option<String>,
+}
+
+fn default_payment_confirmations() -> u32 {
+    6
+}
+
 impl Config {
     pub fn load(path: &str) -> ConfigResult<Self> {
         let content = std::fs::read_to_string(path)
@@ -182,7 +1246,14 @@ impl Config {
                 value: format!("Cannot read {}: {}", path, e),
             })?;
 
-        let config: Config = toml::from_str(&content)
+        let mut tree: toml::Value = toml::from_str(&content)
+            .map_err(|e| ConfigError::InvalidValue {
+                field: "config_format".to_string(),
+                value: format!("Invalid TOML: {}", e),
+            })?;
+        crate::secrets::resolve(&mut tree)?;
+
+        let config: Config = tree.try_into()
             .map_err(|e| ConfigError::InvalidValue {
                 field: "config_format".to_string(),
                 value: format!("Invalid TOML: {}", e),
@@ -192,6 +1263,13 @@ impl Config {
         Ok(config)
     }
 
+    /// The config as JSON with every credential masked, for printing
+    pub fn redacted(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("the config always serializes");
+        crate::secrets::redact(&mut value);
+        value
+    }
+
     pub fn validate(&self) -> ConfigResult<()> {
         // Validate ports are not conflicting
         let mut ports = vec![self.api.port, self.rpc.port, self.metrics.port];
@@ -210,6 +1288,135 @@ impl Config {
             }
         }
 
+        if self.network_config.nat_traversal && !self.network_config.listen {
+            tracing::warn!("network_config.nat_traversal is ignored because listen is disabled");
+        }
+
+        self.validate_proxy_routing()?;
+
+        if self.rpc.user.is_some() != self.rpc.password.is_some() {
+            return Err(ConfigError::InvalidValue {
+                field: "rpc".to_string(),
+                value: "user and password must be set together, or both left out to use the cookie file".to_string(),
+            });
+        }
+        if let Some(unknown) = self.rpc.allowed_methods.iter().find(|name| crate::rpc::registry::lookup(name).is_none()) {
+            return Err(ConfigError::InvalidValue {
+                field: "rpc.allowed_methods".to_string(),
+                value: format!("{} is not an RPC method", unknown),
+            });
+        }
+
+        if self.storage.remote.enabled && self.storage.remote.token.as_deref().is_none_or(str::is_empty) {
+            return Err(ConfigError::MissingField("storage.remote.token".to_string()));
+        }
+        if self.storage.read_api.enabled && self.storage.read_api.token.as_deref().is_none_or(str::is_empty) {
+            return Err(ConfigError::MissingField("storage.read_api.token".to_string()));
+        }
+        if self.storage.remote.enabled && self.storage.read_api.enabled {
+            return Err(ConfigError::InvalidValue {
+                field: "storage.read_api".to_string(),
+                value: "a replica has no local storage to serve".to_string(),
+            });
+        }
+        if self.mempool.miner_lane.enabled && self.mempool.miner_lane.token.as_deref().is_none_or(str::is_empty) {
+            return Err(ConfigError::MissingField("mempool.miner_lane.token".to_string()));
+        }
+
+        let payouts = self.mining.payout_scripts(self.network.to_bitcoin_network())?;
+        // Coinbase scriptSigs are capped at 100 bytes, up to 5 of which hold the BIP34 height
+        let extra_nonce_size = self.mining.extra_nonce_size.max(4 + self.stratum.extranonce2_size);
+        if self.mining.coinbase_message.len() + extra_nonce_size > 95 {
+            return Err(ConfigError::InvalidValue {
+                field: "mining.coinbase_message".to_string(),
+                value: format!("{} bytes plus {} extra nonce bytes exceed 95", self.mining.coinbase_message.len(), extra_nonce_size),
+            });
+        }
+
+        if self.stratum.enabled {
+            if self.stratum.payout_script(self.network.to_bitcoin_network())?.is_none() && payouts.is_empty() {
+                return Err(ConfigError::MissingField("stratum.payout_address".to_string()));
+            }
+            if !(1..=8).contains(&self.stratum.extranonce2_size) {
+                return Err(ConfigError::InvalidValue {
+                    field: "stratum.extranonce2_size".to_string(),
+                    value: format!("{} is outside 1..=8", self.stratum.extranonce2_size),
+                });
+            }
+            if self.network == Network::Mainnet {
+                tracing::warn!("Stratum is meant for regtest and testnet solo mining");
+            }
+        }
+
+        if self.mempool.max_mempool == Some(0) {
+            return Err(ConfigError::InvalidValue {
+                field: "mempool.max_mempool".to_string(),
+                value: "must be greater than 0".to_string(),
+            });
+        }
+
+        for (field, limit) in [
+            ("limit_ancestor_count", self.mempool.limit_ancestor_count.map(|count| count as u64)),
+            ("limit_ancestor_size", self.mempool.limit_ancestor_size),
+            ("limit_descendant_count", self.mempool.limit_descendant_count.map(|count| count as u64)),
+            ("limit_descendant_size", self.mempool.limit_descendant_size),
+        ] {
+            if limit == Some(0) {
+                return Err(ConfigError::InvalidValue {
+                    field: format!("mempool.{}", field),
+                    value: "must be greater than 0".to_string(),
+                });
+            }
+        }
+
+        if let Some(weight) = self.mempool.max_standard_tx_weight {
+            if weight == 0 || weight > bitcoin::Weight::MAX_BLOCK.to_wu() {
+                return Err(ConfigError::InvalidValue {
+                    field: "mempool.max_standard_tx_weight".to_string(),
+                    value: format!("{} is outside 1..={}", weight, bitcoin::Weight::MAX_BLOCK.to_wu()),
+                });
+            }
+        }
+
+        crate::maintenance::CompactionScheduler::new(&self.storage.compaction)?;
+
+        if self.runtime.arbiters == 0 || self.runtime.blocking_threads == 0 {
+            return Err(ConfigError::InvalidValue {
+                field: "runtime".to_string(),
+                value: "arbiters and blocking_threads must be at least 1".to_string(),
+            });
+        }
+        if !self.runtime.validation_cores.is_empty() && self.runtime.arbiters < 2 {
+            return Err(ConfigError::InvalidValue {
+                field: "runtime.validation_cores".to_string(),
+                value: "pinning needs a validation arbiter, set runtime.arbiters to 2 or more".to_string(),
+            });
+        }
+        crate::script::ScriptChecks::new(self)?;
+        if self.cluster.enabled {
+            if self.cluster.etcd_endpoints.is_empty() {
+                return Err(ConfigError::InvalidValue {
+                    field: "cluster.etcd_endpoints".to_string(),
+                    value: "at least one endpoint is needed with the cluster enabled".to_string(),
+                });
+            }
+            // Refreshed every third of the TTL, in whole seconds
+            if self.cluster.lease_ttl_secs < 3 || self.cluster.event_ttl_secs < 3 {
+                return Err(ConfigError::InvalidValue {
+                    field: "cluster".to_string(),
+                    value: "lease_ttl_secs and event_ttl_secs must be at least 3".to_string(),
+                });
+            }
+        }
+        let filter = &self.mempool.script_filter;
+        if filter.mode == ScriptFilterMode::Off
+            && !(filter.addresses.is_empty() && filter.scripts.is_empty() && filter.xpubs.is_empty())
+        {
+            tracing::warn!("mempool.script_filter lists are ignored because mode is off");
+        }
+        // Checked in every mode, so switching one on cannot find a bad entry
+        filter.script_pubkeys(self.network.to_bitcoin_network())?;
+
         // Validate data directory
         if !self.datadir.exists() {
             std::fs::create_dir_all(&self.datadir)
@@ -228,13 +1435,104 @@ impl Config {
         if self.network_config.listen_port != 0 {
             self.network_config.listen_port
         } else {
-            // Return network-specific default ports
-            match self.network {
-                Network::Mainnet => 8333,
-                Network::Testnet => 18333,
-                Network::Regtest => 18444,
+            self.network.default_port()
+        }
+    }
+
+    /// Whether an inbound P2P listener should be started
+    pub fn should_listen(&self) -> bool {
+        self.network_config.listen
+    }
+
+    /// NAT traversal only makes sense for a node that accepts inbound connections
+    pub fn should_use_nat_traversal(&self) -> bool {
+        self.network_config.listen && self.network_config.nat_traversal
+    }
+
+    /// Number of outbound connections to maintain
+    pub fn target_outbound_peers(&self) -> usize {
+        self.network_config.max_peers.min(DEFAULT_OUTBOUND_PEERS)
+    }
+
+    /// Inbound slots left after outbound targets; none when running outbound-only
+    pub fn max_inbound_peers(&self) -> usize {
+        if self.should_listen() {
+            self.network_config.max_peers - self.target_outbound_peers()
+        } else {
+            0
+        }
+    }
+
+    /// Every network in `onlynet` must have a way to be reached
+    fn validate_proxy_routing(&self) -> ConfigResult<()> {
+        for net in &self.network_config.proxy.onlynet {
+            if matches!(self.route_for_net(*net), PeerRoute::Unreachable) {
+                let reason = if cfg!(not(feature = "tor")) && matches!(net, NetType::Onion) {
+                    "this build has no tor feature"
+                } else {
+                    "has no proxy configured"
+                };
+                return Err(ConfigError::InvalidValue {
+                    field: "network_config.proxy.onlynet".to_string(),
+                    value: format!("{} is listed but {}", net.as_str(), reason),
+                });
             }
         }
+        Ok(())
+    }
+
+    /// Decide whether and through which proxy a peer address is dialed
+    pub fn route_for(&self, peer: &str) -> PeerRoute<'_> {
+        self.route_for_net(NetType::classify(peer))
+    }
+
+    fn route_for_net(&self, net: NetType) -> PeerRoute<'_> {
+        let proxy = &self.network_config.proxy;
+        if !proxy.onlynet.is_empty() && !proxy.onlynet.contains(&net) {
+            return PeerRoute::Unreachable;
+        }
+
+        match net {
+            #[cfg(feature = "tor")]
+            NetType::Onion => match proxy.onion.as_ref().or(proxy.default.as_ref()) {
+                Some(onion_proxy) => PeerRoute::Proxy(onion_proxy),
+                None => PeerRoute::Unreachable,
+            },
+            #[cfg(not(feature = "tor"))]
+            NetType::Onion => PeerRoute::Unreachable,
+            NetType::I2p => match proxy.i2p.as_ref() {
+                Some(i2p_proxy) => PeerRoute::Proxy(i2p_proxy),
+                None => PeerRoute::Unreachable,
+            },
+            // CJDNS is a mesh reached through its own interface, never a SOCKS proxy
+            NetType::Cjdns => PeerRoute::Direct,
+            NetType::Ipv4 | NetType::Ipv6 => match proxy.default.as_ref() {
+                Some(default_proxy) => PeerRoute::Proxy(default_proxy),
+                None => PeerRoute::Direct,
+            },
+        }
+    }
+
+    /// Per-network reachability and proxy settings for `getnetworkinfo`
+    pub fn network_reachability(&self) -> Vec<NetworkReachability> {
+        let onlynet = &self.network_config.proxy.onlynet;
+        NetType::ALL
+            .iter()
+            .map(|net| {
+                let route = self.route_for_net(*net);
+                let (proxy, randomize) = match route {
+                    PeerRoute::Proxy(proxy) => (proxy.address.clone(), proxy.randomize_credentials),
+                    _ => (String::new(), false),
+                };
+                NetworkReachability {
+                    name: net.as_str(),
+                    limited: !onlynet.is_empty() && !onlynet.contains(net),
+                    reachable: !matches!(route, PeerRoute::Unreachable),
+                    proxy,
+                    proxy_randomize_credentials: randomize,
+                }
+            })
+            .collect()
     }
 
     /// Check if DNS seed discovery should be enabled for this network
@@ -243,6 +1541,11 @@ impl Config {
         self.network_config.enable_dns_seeds && matches!(self.network, Network::Mainnet | Network::Testnet)
     }
 
+    /// Whether the embedded fixed seeds may be dialed when DNS seeding finds nothing
+    pub fn should_use_fixed_seeds(&self) -> bool {
+        self.network_config.use_fixed_seeds && matches!(self.network, Network::Mainnet | Network::Testnet)
+    }
+
     /// Get custom peers combined with network-specific localhost peers
     pub fn all_custom_peers(&self) -> Vec<String> {
         let mut peers = self.network_config.custom_peers.clone();
@@ -269,6 +1572,7 @@ impl Config {
                     requests_per_minute: 100,
                     burst_size: 10,
                 }),
+                usage: UsageConfig::default(),
             },
             rpc: RpcConfig {
                 host: "127.0.0.1".to_string(),
@@ -280,9 +1584,10 @@ impl Config {
                     "getblockchaininfo".to_string(),
                     "getbestblockhash".to_string(),
                     "getblock".to_string(),
-                    "gettransaction".to_string(),
+                    "getrawtransaction".to_string(),
                     "sendrawtransaction".to_string(),
                 ],
+                json_format: RpcJsonFormat::Native,
             },
             storage: StorageConfig {
                 rocks_db_path: PathBuf::from("./data/rocksdb"),
@@ -291,15 +1596,36 @@ impl Config {
                 compression: CompressionType::Lz4,
                 backup_enabled: false,
                 backup_interval_hours: 24,
+                backup_path: None,
+                backup_keep: default_backup_keep(),
+                peer_timeline_max_events: default_peer_timeline_max_events(),
+                event_journal_max_events: default_event_journal_max_events(),
+                silent_payment_index: false,
+                address_index: false,
+                index_backfill_blocks_per_sec: default_index_backfill_blocks_per_sec(),
+                index_backfill_fetch_bytes_per_sec: default_index_backfill_fetch_bytes_per_sec(),
+                background_validation_blocks_per_sec: default_background_validation_blocks_per_sec(),
+                compaction: CompactionConfig::default(),
+                remote: RemoteStorageConfig::default(),
+                read_api: StorageReadApiConfig::default(),
             },
             network_config: NetworkConfig {
+                listen: true,
                 listen_port: 18444,
                 max_peers: 8,
                 connection_timeout_secs: 30,
                 discovery_interval_secs: 60,
                 custom_peers: vec![],
                 enable_dns_seeds: true,
+                dns_seed_ttl_secs: default_dns_seed_ttl_secs(),
+                use_fixed_seeds: default_use_fixed_seeds(),
                 enable_peer_exchange: true,
+                nat_traversal: false,
+                proxy: ProxyRoutingConfig::default(),
+                peer_rate_limits: PeerRateLimitConfig::default(),
+                protocol_strictness: ProtocolStrictness::default(),
+                partition: PartitionConfig::default(),
+                ibd_greylist: IbdGreylistConfig::default(),
                 zmq: ZmqConfig {
                     enabled: true,
                     pub_port: Some(28332),
@@ -322,7 +1648,9 @@ impl Config {
                     endpoint: None,
                     service_name: "bitknotsrs".to_string(),
                     service_version: env!("CARGO_PKG_VERSION").to_string(),
+                    sample_ratio: default_otel_sample_ratio(),
                 },
+                system: SystemMonitorConfig::default(),
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
@@ -360,10 +1688,40 @@ impl Config {
                     timeout_secs: 10,
                     retry_attempts: 3,
                 },
+                nats: NatsEventConfig::default(),
             },
+            watch: WatchConfig::default(),
+            caches: CacheConfig::default(),
+            analytics: AnalyticsConfig::default(),
+            mempool: MempoolConfig::default(),
+            stratum: StratumConfig::default(),
+            mining: MiningConfig::default(),
+            wallet: WalletConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            validation: ValidationConfig::default(),
+            runtime: RuntimeConfig::default(),
+            identity: IdentityConfig::default(),
+            cluster: ClusterConfig::default(),
         }
     }
 
+    /// Turn off everything that writes to storage, for an instance whose
+    /// reads go to a full node
+    pub fn api_replica(mut self) -> Self {
+        self.network_config.listen = false;
+        self.network_config.custom_peers.clear();
+        self.network_config.enable_dns_seeds = false;
+        self.network_config.use_fixed_seeds = false;
+        self.stratum.enabled = false;
+        self.storage.compaction.enabled = false;
+        self.storage.backup_enabled = false;
+        self.analytics.utxo_distribution_interval_secs = 0;
+        self.analytics.mempool_snapshot_interval_secs = 0;
+        self.analytics.mempool_fee_interval_secs = 0;
+        self.mempool.persist_mempool = Some(false);
+        self
+    }
+
     pub fn test_config() -> Self {
         let mut config = Self::default_regtest();
         config.datadir = PathBuf::from("./test_data");
@@ -449,4 +1807,106 @@ mod tests {
         assert!(!mainnet_peers.contains(&"127.0.0.1:18444".to_string()));
         assert!(!mainnet_peers.contains(&"localhost:18444".to_string()));
     }
+
+    #[test]
+    fn test_listen_toggle() {
+        let mut config = Config::default_regtest();
+        config.network_config.max_peers = 125;
+        config.network_config.nat_traversal = true;
+
+        assert!(config.should_listen());
+        assert!(config.should_use_nat_traversal());
+        assert_eq!(config.target_outbound_peers(), 10);
+        assert_eq!(config.max_inbound_peers(), 115);
+
+        // Outbound-only keeps outbound targets and drops inbound slots and NAT traversal
+        config.network_config.listen = false;
+        assert!(!config.should_listen());
+        assert!(!config.should_use_nat_traversal());
+        assert_eq!(config.target_outbound_peers(), 10);
+        assert_eq!(config.max_inbound_peers(), 0);
+
+        // Small peer limits are all spent on outbound connections
+        config.network_config.listen = true;
+        config.network_config.max_peers = 8;
+        assert_eq!(config.target_outbound_peers(), 8);
+        assert_eq!(config.max_inbound_peers(), 0);
+    }
+
+    #[test]
+    fn test_listen_defaults_to_true() {
+        let network_config: NetworkConfig = toml::from_str(r#"
+            listen_port = 8333
+            max_peers = 8
+            connection_timeout_secs = 30
+            discovery_interval_secs = 60
+            custom_peers = []
+            enable_dns_seeds = true
+            enable_peer_exchange = true
+
+            [zmq]
+            enabled = false
+            sub_endpoints = []
+            topics = []
+        "#).unwrap();
+
+        assert!(network_config.listen);
+        assert!(!network_config.nat_traversal);
+        assert!(network_config.use_fixed_seeds);
+        assert_eq!(network_config.dns_seed_ttl_secs, 3600);
+    }
+
+    #[test]
+    fn test_classify_peer_addresses() {
+        assert_eq!(NetType::classify("1.2.3.4:8333"), NetType::Ipv4);
+        assert_eq!(NetType::classify("seed.example.com:8333"), NetType::Ipv4);
+        assert_eq!(NetType::classify("[2001:db8::1]:8333"), NetType::Ipv6);
+        assert_eq!(NetType::classify("2001:db8::1"), NetType::Ipv6);
+        assert_eq!(NetType::classify("[fc00::1]:8333"), NetType::Cjdns);
+        assert_eq!(NetType::classify("abcdefghijklmnop.onion:8333"), NetType::Onion);
+        assert_eq!(NetType::classify("abcdef.b32.i2p:0"), NetType::I2p);
+    }
+
+    #[cfg(feature = "tor")]
+    #[test]
+    fn test_proxy_routing() {
+        let mut config = Config::default_regtest();
+        assert!(matches!(config.route_for("1.2.3.4:8333"), PeerRoute::Direct));
+        assert!(matches!(config.route_for("abc.onion:8333"), PeerRoute::Unreachable));
+
+        config.network_config.proxy.onion = Some(ProxyConfig {
+            address: "127.0.0.1:9050".to_string(),
+            username: None,
+            password: None,
+            randomize_credentials: true,
+        });
+        config.network_config.proxy.onlynet = vec![NetType::Onion];
+
+        assert!(matches!(config.route_for("abc.onion:8333"), PeerRoute::Proxy(proxy) if proxy.address == "127.0.0.1:9050"));
+        assert!(matches!(config.route_for("1.2.3.4:8333"), PeerRoute::Unreachable));
+        assert!(config.validate_proxy_routing().is_ok());
+
+        let networks = config.network_reachability();
+        let onion = networks.iter().find(|net| net.name == "onion").unwrap();
+        assert!(onion.reachable && !onion.limited && onion.proxy_randomize_credentials);
+        let ipv4 = networks.iter().find(|net| net.name == "ipv4").unwrap();
+        assert!(!ipv4.reachable && ipv4.limited);
+
+        config.network_config.proxy.onlynet = vec![NetType::I2p];
+        assert!(config.validate_proxy_routing().is_err());
+    }
+
+    #[test]
+    fn test_mempool_policy_profile_from_toml() {
+        let mempool: MempoolConfig = toml::from_str(
+            "policy_profile = \"knots-strict\"\nmax_datacarrier_bytes = 80\nrbf = \"opt_in\"",
+        ).unwrap();
+        assert_eq!(mempool.policy_profile, PolicyProfile::KnotsStrict);
+        assert_eq!(mempool.max_datacarrier_bytes, Some(80));
+        assert_eq!(mempool.rbf, Some(RbfPolicy::OptIn));
+        assert_eq!(mempool.dust_relay_fee, None);
+
+        let defaults: MempoolConfig = toml::from_str("").unwrap();
+        assert_eq!(defaults.policy_profile, PolicyProfile::CoreDefault);
+    }
 }
@@ -0,0 +1,279 @@
+//! Interactive JSON-RPC console
+//!
+//! `bitknotsrs console` reads `method arg...` lines, sends them to the node's
+//! RPC server and prints the result. Arguments that parse as JSON are sent as
+//! JSON, anything else as a string, so `getblock <hash> 2` and
+//! `walletcreatefundedpsbt w [] [{"bcrt1...":0.1}]` both work unquoted. Method
+//! names complete on tab from the node's `help` listing, and history is kept
+//! in the datadir between sessions.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::error::{ConsoleError, ConsoleResult};
+
+const HISTORY_FILE: &str = "console_history";
+
+/// JSON-RPC over HTTP with the node's configured credentials or its cookie
+pub struct RpcClient {
+    url: String,
+    auth: Option<(String, String)>,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    pub fn new(config: &Config, url: Option<String>) -> Self {
+        let url = url.unwrap_or_else(|| format!("http://{}:{}/", config.rpc.host, config.rpc.port));
+        // Without a configured password the node authenticates with its cookie file
+        let auth = match (&config.rpc.user, &config.rpc.password) {
+            (Some(user), Some(password)) => Some((user.clone(), password.clone())),
+            _ => crate::rpc::auth::read_cookie(&config.datadir).ok(),
+        };
+        Self { url, auth, http: reqwest::Client::new(), next_id: AtomicU64::new(1) }
+    }
+
+    pub async fn call(&self, method: &str, params: Vec<Value>) -> ConsoleResult<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut request = self.http
+            .post(&self.url)
+            .json(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        if let Some((user, password)) = &self.auth {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        // 401 for bad credentials and 403 for methods outside rpc.allowed_methods come without a body
+        let mut response: Value = request.send().await?.error_for_status()?.json().await?;
+        match response.get("error") {
+            Some(error) if !error.is_null() => Err(ConsoleError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            }),
+            _ => Ok(response["result"].take()),
+        }
+    }
+
+    /// Method names from the node's `help` listing
+    pub async fn method_names(&self) -> ConsoleResult<Vec<String>> {
+        let help = self.call("help", Vec::new()).await?;
+        Ok(help.as_str().map(parse_help_listing).unwrap_or_default())
+    }
+}
+
+/// The names under "Available commands:" in the `help` text
+fn parse_help_listing(help: &str) -> Vec<String> {
+    let mut names: Vec<String> = help.lines()
+        .skip_while(|line| !line.starts_with("Available commands"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Split a console line into the method and its parameters. Words are
+/// separated by whitespace outside quotes and brackets; each parameter is its
+/// JSON value if it parses as one and a string otherwise.
+pub fn parse_line(line: &str) -> ConsoleResult<Option<(String, Vec<Value>)>> {
+    let mut words = split_words(line)?.into_iter();
+    let Some(method) = words.next() else {
+        return Ok(None);
+    };
+    let params = words
+        .map(|word| serde_json::from_str(&word).unwrap_or(Value::String(word)))
+        .collect();
+    Ok(Some((method, params)))
+}
+
+fn split_words(line: &str) -> ConsoleResult<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        if in_string {
+            word.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                word.push(c);
+            }
+            '[' | '{' => {
+                depth += 1;
+                word.push(c);
+            }
+            ']' | '}' => {
+                depth = depth.checked_sub(1).ok_or_else(|| ConsoleError::Parse(format!("unbalanced '{}'", c)))?;
+                word.push(c);
+            }
+            c if c.is_whitespace() && depth == 0 => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+
+    if in_string {
+        return Err(ConsoleError::Parse("unterminated string".to_string()));
+    }
+    if depth > 0 {
+        return Err(ConsoleError::Parse("unclosed bracket".to_string()));
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Results as bitcoin-cli prints them: strings bare, everything else pretty JSON
+fn format_result(result: &Value) -> String {
+    match result {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string()),
+    }
+}
+
+/// Completes the method name, the first word of the line
+struct MethodCompleter {
+    methods: Vec<String>,
+}
+
+impl Completer for MethodCompleter {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let prefix = &line[..pos];
+        if prefix.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+        let candidates = self.methods.iter()
+            .filter(|method| method.starts_with(prefix))
+            .map(|method| Pair { display: method.clone(), replacement: method.clone() })
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for MethodCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for MethodCompleter {}
+impl Validator for MethodCompleter {}
+impl Helper for MethodCompleter {}
+
+/// Run the console until `exit`, `quit`, Ctrl-D or Ctrl-C
+pub fn run(config: &Config, url: Option<String>) -> ConsoleResult<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let client = RpcClient::new(config, url);
+
+    let methods = match runtime.block_on(client.method_names()) {
+        Ok(methods) => methods,
+        Err(e) => {
+            eprintln!("Could not list RPC methods from {} ({}), tab completion is off", client.url, e);
+            Vec::new()
+        }
+    };
+
+    let mut editor: Editor<MethodCompleter, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(MethodCompleter { methods }));
+    let history: PathBuf = config.datadir.join(HISTORY_FILE);
+    // Missing on the first run
+    let _ = editor.load_history(&history);
+
+    println!("Connected to {}. Type a method and its arguments, `help` for the list, `exit` to leave.", client.url);
+    loop {
+        let line = match editor.readline("bitknotsrs> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+
+        let output = parse_line(line).and_then(|parsed| match parsed {
+            Some((method, params)) => runtime.block_on(client.call(&method, params)),
+            None => Ok(Value::Null),
+        });
+        match output {
+            Ok(result) => {
+                let formatted = format_result(&result);
+                if !formatted.is_empty() {
+                    println!("{}", formatted);
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&config.datadir).map_err(ReadlineError::from)
+        .and_then(|()| editor.save_history(&history))
+    {
+        eprintln!("Could not save console history to {}: {}", history.display(), e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("   ").unwrap(), None);
+        assert_eq!(parse_line("getblockcount").unwrap(), Some(("getblockcount".to_string(), vec![])));
+
+        let (method, params) = parse_line("getblock 0f9188f1 2").unwrap().unwrap();
+        assert_eq!(method, "getblock");
+        assert_eq!(params, vec![json!("0f9188f1"), json!(2)]);
+
+        let (_, params) = parse_line(r#"walletcreatefundedpsbt w [] [{"bcrt1q": 0.1}] 0 {"fee_rate": 2}"#).unwrap().unwrap();
+        assert_eq!(params, vec![json!("w"), json!([]), json!([{ "bcrt1q": 0.1 }]), json!(0), json!({ "fee_rate": 2 })]);
+
+        let (_, params) = parse_line(r#"settxnote w aa "rent, \"march\"" true"#).unwrap().unwrap();
+        assert_eq!(params, vec![json!("w"), json!("aa"), json!("rent, \"march\""), json!(true)]);
+
+        assert!(parse_line("getblock [1, 2").is_err());
+        assert!(parse_line(r#"help "getblock"#).is_err());
+        assert!(parse_line("help ]").is_err());
+    }
+
+    #[test]
+    fn test_parse_help_listing() {
+        let help = "Available commands:\ngetblockcount\ngetblock\n\ngetblock\n";
+        assert_eq!(parse_help_listing(help), vec!["getblock".to_string(), "getblockcount".to_string()]);
+        assert!(parse_help_listing("getblock \"blockhash\"").is_empty());
+    }
+}
@@ -23,6 +23,9 @@ pub enum NodeError {
     #[error("Event publishing error: {0}")]
     Events(#[from] EventError),
 
+    #[error("Cluster error: {0}")]
+    Cluster(#[from] ClusterError),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -35,6 +38,13 @@ pub enum NodeError {
     #[error("Actor mailbox error: {0}")]
     Mailbox(#[from] actix::MailboxError),
 
+    #[error("Scenario error: {0}")]
+    Scenario(#[from] ScenarioError),
+
+    #[cfg(feature = "console")]
+    #[error("Console error: {0}")]
+    Console(#[from] ConsoleError),
+
     #[error("Generic error: {0}")]
     Generic(#[from] anyhow::Error),
 }
@@ -62,8 +72,23 @@ pub enum StorageError {
     #[error("Corruption detected in {component}")]
     Corruption { component: String },
 
+    #[error("Invalid block {hash}: {reason}")]
+    InvalidBlock { hash: String, reason: String },
+
     #[error("Serialization error: {0}")]
     Serialization(String),
+
+    #[error("Actor mailbox error: {0}")]
+    Mailbox(#[from] actix::MailboxError),
+
+    #[error("Background task failed: {0}")]
+    Task(String),
+
+    #[error("Storage reads through to {url} and cannot be written")]
+    ReadOnly { url: String },
+
+    #[error("Remote storage read failed: {0}")]
+    Remote(String),
 }
 
 #[derive(Error, Debug)]
@@ -77,8 +102,17 @@ pub enum NetworkError {
     #[error("Peer disconnected: {peer}")]
     PeerDisconnected { peer: String },
 
+    #[error("No connected peers")]
+    NoPeers,
+
     #[error("ZMQ error: {0}")]
     Zmq(String),
+
+    #[error("Invalid capture file: {0}")]
+    InvalidCapture(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 #[derive(Error, Debug)]
@@ -133,9 +167,364 @@ pub enum EventError {
 
     #[error("Event serialization error: {0}")]
     Serialization(String),
+
+    #[error("Event journal error: {0}")]
+    Journal(String),
+
+    #[error("Node identity error: {0}")]
+    Identity(#[from] IdentityError),
+
+    #[error("Cluster bus error: {0}")]
+    Cluster(#[from] ClusterError),
+}
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("Invalid identity key in {path}: {reason}")]
+    InvalidKey { path: String, reason: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum ClusterError {
+    #[cfg(feature = "http-client")]
+    #[error("etcd request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[cfg(not(feature = "http-client"))]
+    #[error("Clustering needs the http-client feature")]
+    NoHttpClient,
+
+    #[error("etcd {endpoint} answered {status}: {body}")]
+    Status { endpoint: String, status: u16, body: String },
+
+    #[error("Unexpected etcd response: {0}")]
+    Response(String),
+
+    #[error("Lease {0} expired")]
+    LeaseExpired(i64),
+}
+
+/// Reasons a transaction fails the mempool standardness policy
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    #[error("tx-size: weight {weight} exceeds {max}")]
+    TxTooLarge { weight: u64, max: u64 },
+
+    #[error("datacarrier: OP_RETURN outputs are not relayed")]
+    DatacarrierDisabled,
+
+    #[error("datacarrier: {size} bytes of OP_RETURN data exceeds {max}")]
+    DatacarrierTooLarge { size: usize, max: usize },
+
+    #[error("dust: output {vout} of {value} sat is below {threshold}")]
+    Dust { vout: usize, value: u64, threshold: u64 },
+
+    #[error("bare-multisig: output {vout}")]
+    BareMultisig { vout: usize },
+
+    #[error("txn-mempool-conflict: conflicts with {conflicts} mempool transactions")]
+    ReplacementRejected { conflicts: usize },
+
+    #[error("mempool full")]
+    MempoolFull,
+
+    #[error("too-long-mempool-chain: {0}")]
+    TooLongMempoolChain(String),
+
+    #[error("Fee exceeds maximum configured by user (maxfeerate): {fee} sat is above {max}")]
+    MaxFeeExceeded { fee: u64, max: u64 },
+
+    #[error("Unspendable output exceeds maximum configured by user (maxburnamount): output {vout} burns {value} sat, above {max}")]
+    MaxBurnExceeded { vout: usize, value: u64, max: u64 },
+
+    #[error("script-filter: the {mode} refuses {script}")]
+    ScriptFiltered { mode: &'static str, script: String },
+
+    #[error("mandatory-script-verify-flag-failed: input {input}: {reason}")]
+    ScriptVerification { input: usize, reason: String },
+
+    #[error("non-mandatory-script-verify-flag (Witness version reserved for soft-fork upgrades): input {input}")]
+    UnverifiableWitness { input: usize },
+
+    /// Consensus rule broken; no lane may bypass these
+    #[error("{0}")]
+    Consensus(&'static str),
+}
+
+/// Why `sendrawtransaction` did not take a transaction
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BroadcastError {
+    #[error("TX decode failed")]
+    Decode,
+
+    /// Above the caller's maxfeerate or maxburnamount
+    #[error("{0}")]
+    Limit(PolicyError),
+
+    #[error("Transaction already in block chain")]
+    AlreadyInChain,
+
+    #[error("bad-txns-inputs-missingorspent")]
+    MissingInputs,
+
+    #[error("bad-txns-in-belowout")]
+    InBelowOut,
+
+    /// Refused by the mempool
+    #[error("{0}")]
+    Rejected(PolicyError),
+
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+/// Stratum submission rejections, with the error codes miners expect
+#[cfg(feature = "miner")]
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum StratumError {
+    #[error("Job not found")]
+    JobNotFound,
+
+    #[error("Duplicate share")]
+    Duplicate,
+
+    #[error("Low difficulty share of {0}")]
+    LowDifficulty(f64),
+
+    #[error("Unauthorized worker")]
+    Unauthorized,
+
+    #[error("Not subscribed")]
+    NotSubscribed,
+
+    #[error("{0}")]
+    Malformed(String),
+}
+
+#[cfg(feature = "miner")]
+impl StratumError {
+    pub fn code(&self) -> i32 {
+        match self {
+            StratumError::Malformed(_) => 20,
+            StratumError::JobNotFound => 21,
+            StratumError::Duplicate => 22,
+            StratumError::LowDifficulty(_) => 23,
+            StratumError::Unauthorized => 24,
+            StratumError::NotSubscribed => 25,
+        }
+    }
+}
+
+#[cfg(feature = "console")]
+#[derive(Error, Debug)]
+pub enum ConsoleError {
+    #[error("error code {code}: {message}")]
+    Rpc { code: i64, message: String },
+
+    #[error("RPC request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid command line: {0}")]
+    Parse(String),
+
+    #[error("Line editor error: {0}")]
+    Readline(#[from] rustyline::error::ReadlineError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 // Result type aliases for convenience
+#[derive(Error, Debug)]
+pub enum ScenarioError {
+    #[error("Invalid scenario: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Scenarios only run on regtest")]
+    NotRegtest,
+
+    #[error("Step {step}: {reason}")]
+    Step { step: usize, reason: String },
+
+    #[error("Step {step}: {wallet} has {available} sat spendable, {needed} sat needed")]
+    InsufficientFunds { step: usize, wallet: String, available: u64, needed: u64 },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Actor mailbox error: {0}")]
+    Mailbox(#[from] actix::MailboxError),
+}
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Snapshots are only available on regtest")]
+    NotRegtest,
+
+    #[error("Invalid snapshot name: {0}")]
+    InvalidName(String),
+
+    #[error("Snapshot not found: {0}")]
+    NotFound(String),
+
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Snapshot is for {found}, node runs {expected}")]
+    NetworkMismatch { expected: String, found: String },
+
+    #[error("Invalid snapshot data: {0}")]
+    Invalid(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Actor mailbox error: {0}")]
+    Mailbox(#[from] actix::MailboxError),
+}
+
+#[derive(Error, Debug)]
+pub enum UrError {
+    #[error("Malformed UR: {0}")]
+    Malformed(String),
+
+    #[error("Invalid bytewords")]
+    Bytewords,
+
+    #[error("UR checksum mismatch")]
+    Checksum,
+
+    #[error("Invalid UR CBOR payload")]
+    Cbor,
+
+    #[error("UR part does not belong to the same message")]
+    Inconsistent,
+
+    #[error("Unexpected UR type: {0}")]
+    UnexpectedType(String),
+
+    #[error("Invalid PSBT: {0}")]
+    Psbt(String),
+}
+
+#[derive(Error, Debug)]
+pub enum Bip21Error {
+    #[error("URI must start with bitcoin:")]
+    Scheme,
+
+    #[error("Invalid address: {0}")]
+    Address(String),
+
+    #[error("Address is not valid for {0}")]
+    WrongNetwork(String),
+
+    #[error("Invalid amount: {0}")]
+    Amount(String),
+
+    #[error("Invalid percent encoding: {0}")]
+    Encoding(String),
+
+    #[error("Duplicate parameter: {0}")]
+    DuplicateParameter(String),
+
+    #[error("Unsupported required parameter: {0}")]
+    UnsupportedRequirement(String),
+}
+
+#[derive(Error, Debug)]
+pub enum SilentPaymentError {
+    #[error("Invalid silent payment address: {0}")]
+    Address(String),
+
+    #[error("Silent payment address is not valid for {0}")]
+    WrongNetwork(String),
+
+    #[error("Invalid key: {0}")]
+    Key(String),
+
+    #[error("Invalid silent payment index record: {0}")]
+    Index(String),
+}
+
+#[cfg(feature = "wallet")]
+#[derive(Error, Debug)]
+pub enum MultisigError {
+    #[error("Invalid multisig descriptor: {0}")]
+    Descriptor(String),
+
+    #[error("Invalid proposal PSBT: {0}")]
+    Psbt(String),
+
+    #[error("Rejected signature: {0}")]
+    Signature(String),
+
+    #[error("Proposal not found: {0}")]
+    NotFound(String),
+
+    #[error("Proposal already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum WalletError {
+    #[error("Invalid wallet name: {0}")]
+    InvalidName(String),
+
+    #[error("Wallet not found: {0}")]
+    NotFound(String),
+
+    #[error("Wallet already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid account key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Unknown address type: {0}")]
+    InvalidAddressType(String),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Input not found or not spendable: {0}")]
+    UnknownInput(bitcoin::OutPoint),
+
+    #[error("Output is leased: {0}")]
+    OutputLeased(bitcoin::OutPoint),
+
+    #[error("No lease on output: {0}")]
+    LeaseNotFound(bitcoin::OutPoint),
+
+    #[error("Insufficient funds: {available} sat available, {needed} sat needed")]
+    InsufficientFunds { available: u64, needed: u64 },
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Actor mailbox error: {0}")]
+    Mailbox(#[from] actix::MailboxError),
+}
+
 pub type NodeResult<T> = Result<T, NodeError>;
 pub type ConfigResult<T> = Result<T, ConfigError>;
 pub type StorageResult<T> = Result<T, StorageError>;
@@ -144,6 +533,18 @@ pub type RpcResult<T> = Result<T, RpcError>;
 pub type ApiResult<T> = Result<T, ApiError>;
 pub type MetricsResult<T> = Result<T, MetricsError>;
 pub type EventResult<T> = Result<T, EventError>;
+pub type IdentityResult<T> = Result<T, IdentityError>;
+pub type ClusterResult<T> = Result<T, ClusterError>;
+pub type ScenarioResult<T> = Result<T, ScenarioError>;
+#[cfg(feature = "console")]
+pub type ConsoleResult<T> = Result<T, ConsoleError>;
+pub type SnapshotResult<T> = Result<T, SnapshotError>;
+pub type UrResult<T> = Result<T, UrError>;
+pub type Bip21Result<T> = Result<T, Bip21Error>;
+pub type SilentPaymentResult<T> = Result<T, SilentPaymentError>;
+pub type WalletResult<T> = Result<T, WalletError>;
+#[cfg(feature = "wallet")]
+pub type MultisigResult<T> = Result<T, MultisigError>;
 
 #[cfg(test)]
 mod tests {
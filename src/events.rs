@@ -1,16 +1,56 @@
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::collections::BTreeMap;
+use std::future::Future;
+#[cfg(feature = "http-client")]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch, Notify, RwLock};
 use tracing::{info, error, warn};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-use crate::config::{Config, EventsConfig};
+use actix::Addr;
+
+use crate::actors::storage::StorageActor;
+use crate::amount::{FeeRate, Satoshi};
+use crate::actors::{CreatedUtxo, GetEventJournal, JournalEvent, SpentUtxo};
+use crate::analytics::UtxoDistribution;
+use crate::partition::PartitionSignal;
+use crate::config::{Config, EventsConfig, WebhookEndpointConfig};
 use crate::error::{EventError, EventResult};
+use crate::identity::{signing_payload, NodeIdentity};
 
 #[derive(Clone)]
 pub struct EventManager {
-    publishers: Arc<RwLock<Vec<Box<dyn EventPublisher + Send + Sync>>>>,
+    publishers: Arc<RwLock<Vec<RegisteredPublisher>>>,
+    deliveries: Arc<DeliveryTracker>,
+    /// Signs every event when set
+    identity: Option<Arc<NodeIdentity>>,
+    /// Numbers and persists every event once storage is up
+    journal: Arc<OnceLock<EventJournal>>,
+    /// Every published event, for in-process subscribers such as `/api/v1/ws`
+    live: broadcast::Sender<BitcoinEvent>,
+}
+
+/// Events buffered per live subscriber before it starts missing them
+pub const LIVE_EVENT_BUFFER: usize = 1024;
+
+/// Journaled events read at a time while replaying to publishers at startup
+const JOURNAL_REPLAY_PAGE: usize = 1000;
+
+/// Where published events are kept for replay, see [`EventManager::attach_journal`]
+struct EventJournal {
+    storage: Addr<StorageActor>,
+    next_sequence: AtomicU64,
+}
+
+/// Background deliveries started with [`EventManager::spawn_delivery`]
+struct DeliveryTracker {
+    pending: AtomicUsize,
+    idle: Notify,
+    abort: watch::Sender<bool>,
 }
 
 #[async_trait::async_trait]
@@ -18,15 +58,186 @@ pub trait EventPublisher: Send + Sync {
     async fn publish(&self, event: &BitcoinEvent) -> EventResult<()>;
     fn name(&self) -> &str;
     fn is_enabled(&self) -> bool;
+
+    /// Endpoints this publisher delivers to, used for status introspection
+    fn endpoints(&self) -> Vec<PublisherEndpoint> {
+        Vec::new()
+    }
+
+    /// Attempts repeated inside `publish` since startup, for publishers that retry
+    fn retried(&self) -> u64 {
+        0
+    }
+
+    /// Record that `endpoint` has processed every event up to `sequence`
+    fn acknowledge(&self, _endpoint: &str, _sequence: u64) -> EventResult<()> {
+        Err(EventError::PublisherUnavailable(format!("{} does not take acknowledgements", self.name())))
+    }
+
+    /// The lowest checkpoint among consumers that acknowledge, from which
+    /// journaled events are replayed at startup
+    fn replay_since(&self) -> Option<u64> {
+        None
+    }
+
+    /// Deliver a journaled event again to the consumers whose checkpoint is below it
+    async fn replay(&self, _event: &BitcoinEvent) -> EventResult<()> {
+        Ok(())
+    }
+}
+
+/// Consecutive failures after which a publisher's circuit opens
+const CIRCUIT_FAILURE_THRESHOLD: u64 = 5;
+/// How long an open circuit skips the publisher before one delivery is tried again
+const CIRCUIT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A single delivery target of a publisher (ZMQ socket, webhook URL, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublisherEndpoint {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub high_water_mark: Option<u32>,
+    /// Last sequence the consumer acknowledged, for publishers that take acknowledgements
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acknowledged: Option<u64>,
+}
+
+/// Health and delivery counters for a registered publisher
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublisherStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub healthy: bool,
+    pub delivered: u64,
+    pub failed: u64,
+    pub consecutive_failures: u64,
+    pub retried: u64,
+    /// Deliveries skipped while the circuit was open
+    pub short_circuited: u64,
+    pub circuit_open: bool,
+    pub last_latency_ms: Option<f64>,
+    pub mean_latency_ms: Option<f64>,
+    pub last_delivery: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub endpoints: Vec<PublisherEndpoint>,
+}
+
+struct RegisteredPublisher {
+    publisher: Box<dyn EventPublisher + Send + Sync>,
+    stats: PublisherStats,
+}
+
+#[derive(Default)]
+struct PublisherStats {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    consecutive_failures: AtomicU64,
+    short_circuited: AtomicU64,
+    /// Summed over delivered and failed attempts, for the mean
+    latency_micros: AtomicU64,
+    last_latency_micros: AtomicU64,
+    last_delivery: Mutex<Option<DateTime<Utc>>>,
+    last_error: Mutex<Option<String>>,
+    last_failure: Mutex<Option<Instant>>,
+}
+
+impl PublisherStats {
+    fn record_success(&self, name: &str, elapsed: Duration) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.record_latency(elapsed);
+        *self.last_delivery.lock().unwrap() = Some(Utc::now());
+        crate::metrics::record_event_delivery(name, true, elapsed);
+    }
+
+    fn record_failure(&self, name: &str, error: &EventError, elapsed: Duration) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+        self.record_latency(elapsed);
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+        *self.last_failure.lock().unwrap() = Some(Instant::now());
+        crate::metrics::record_event_delivery(name, false, elapsed);
+    }
+
+    fn record_latency(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.latency_micros.fetch_add(micros, Ordering::Relaxed);
+        self.last_latency_micros.store(micros, Ordering::Relaxed);
+    }
+
+    /// Open after enough consecutive failures, until the cooldown since the last one runs out
+    fn circuit_open(&self) -> bool {
+        self.consecutive_failures.load(Ordering::Relaxed) >= CIRCUIT_FAILURE_THRESHOLD
+            && self.last_failure.lock().unwrap().is_some_and(|at| at.elapsed() < CIRCUIT_COOLDOWN)
+    }
+}
+
+impl RegisteredPublisher {
+    fn new(publisher: Box<dyn EventPublisher + Send + Sync>) -> Self {
+        Self {
+            publisher,
+            stats: PublisherStats::default(),
+        }
+    }
+
+    fn status(&self) -> PublisherStatus {
+        let consecutive_failures = self.stats.consecutive_failures.load(Ordering::Relaxed);
+        let delivered = self.stats.delivered.load(Ordering::Relaxed);
+        let failed = self.stats.failed.load(Ordering::Relaxed);
+        let attempts = delivered + failed;
+        let millis = |micros: u64| micros as f64 / 1000.0;
+        PublisherStatus {
+            name: self.publisher.name().to_string(),
+            enabled: self.publisher.is_enabled(),
+            healthy: self.publisher.is_enabled() && consecutive_failures == 0,
+            delivered,
+            failed,
+            consecutive_failures,
+            retried: self.publisher.retried(),
+            short_circuited: self.stats.short_circuited.load(Ordering::Relaxed),
+            circuit_open: self.stats.circuit_open(),
+            last_latency_ms: (attempts > 0).then(|| millis(self.stats.last_latency_micros.load(Ordering::Relaxed))),
+            mean_latency_ms: (attempts > 0).then(|| millis(self.stats.latency_micros.load(Ordering::Relaxed) / attempts)),
+            last_delivery: *self.stats.last_delivery.lock().unwrap(),
+            last_error: self.stats.last_error.lock().unwrap().clone(),
+            endpoints: self.publisher.endpoints(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BitcoinEvent {
     pub id: String,
+    /// Position in the node's event journal, gapless and increasing across
+    /// restarts; 0 for events published before the journal was attached
+    #[serde(default)]
+    pub sequence: u64,
     pub timestamp: DateTime<Utc>,
     pub event_type: BitcoinEventType,
     pub network: String,
     pub node_id: String,
+    /// The emitting node's identity key, see [`crate::identity`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_pubkey: Option<String>,
+    /// BIP340 signature by `node_pubkey` over the rest of the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl BitcoinEvent {
+    pub fn new(event_type: BitcoinEventType, network: &str, node_id: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            sequence: 0,
+            timestamp: Utc::now(),
+            event_type,
+            network: network.to_string(),
+            node_id: node_id.to_string(),
+            node_pubkey: None,
+            signature: None,
+        }
+    }
+
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,8 +253,8 @@ pub enum BitcoinEventType {
     TransactionAdded {
         txid: String,
         size: u64,
-        fee: u64,
-        fee_rate: f64,
+        fee: Satoshi,
+        fee_rate: FeeRate,
     },
     PeerConnected {
         peer_id: String,
@@ -55,16 +266,66 @@ pub enum BitcoinEventType {
         address: String,
         reason: String,
     },
+    UtxoSetChanged {
+        height: u64,
+        block_hash: String,
+        created: Vec<CreatedUtxo>,
+        spent: Vec<SpentUtxo>,
+    },
+    UtxoDistributionUpdated {
+        distribution: UtxoDistribution,
+    },
+    TxConfirmed {
+        txid: String,
+        confirmations: u32,
+        target_confirmations: u32,
+        block_hash: String,
+        block_height: u64,
+    },
+    TxConfirmationReverted {
+        txid: String,
+        block_hash: String,
+        block_height: u64,
+    },
+    PaymentReceived {
+        address: String,
+        txid: String,
+        vout: u32,
+        value: u64,
+        confirmations: u32,
+        block_hash: Option<String>,
+        block_height: Option<u64>,
+    },
+    DoubleSpendDetected {
+        txid: String,
+        conflicting_txid: String,
+        outpoint: String,
+        source: DoubleSpendSource,
+        block_hash: Option<String>,
+    },
     ChainReorg {
         old_tip: String,
         new_tip: String,
         depth: u64,
     },
+    /// A side chain with more work forks deeper than `validation.max_reorg_depth`
+    /// and is not followed until an operator calls `acceptreorg`
+    ReorgHeld {
+        old_tip: String,
+        new_tip: String,
+        fork_height: u64,
+        depth: u64,
+        max_depth: u64,
+    },
     MempoolUpdate {
         tx_count: u64,
         total_size: u64,
-        min_fee_rate: f64,
-        max_fee_rate: f64,
+        min_fee_rate: FeeRate,
+        max_fee_rate: FeeRate,
+    },
+    PartitionSuspected {
+        signals: Vec<PartitionSignal>,
+        peer_count: usize,
     },
     SyncProgress {
         current_height: u64,
@@ -80,6 +341,130 @@ pub enum BitcoinEventType {
         reason: String,
         uptime_seconds: u64,
     },
+    /// An API endpoint started missing an objective in `api.usage`
+    SloBreached {
+        endpoint: String,
+        /// `p95_latency_ms` or `error_rate`
+        objective: String,
+        value: f64,
+        target: f64,
+        window_secs: u64,
+    },
+    /// Free space on the data directory's disk fell below `metrics.system.low_disk_free_mb`
+    LowDiskSpace {
+        path: String,
+        available_bytes: u64,
+        total_bytes: u64,
+        threshold_bytes: u64,
+    },
+}
+
+/// Groups of event types a live subscriber can choose between
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventTopic {
+    Blocks,
+    Transactions,
+    Peers,
+    Mempool,
+    Utxos,
+    Node,
+}
+
+impl EventTopic {
+    pub const ALL: [EventTopic; 6] = [
+        EventTopic::Blocks,
+        EventTopic::Transactions,
+        EventTopic::Peers,
+        EventTopic::Mempool,
+        EventTopic::Utxos,
+        EventTopic::Node,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventTopic::Blocks => "blocks",
+            EventTopic::Transactions => "transactions",
+            EventTopic::Peers => "peers",
+            EventTopic::Mempool => "mempool",
+            EventTopic::Utxos => "utxos",
+            EventTopic::Node => "node",
+        }
+    }
+}
+
+impl std::str::FromStr for EventTopic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventTopic::ALL.into_iter()
+            .find(|topic| topic.as_str() == s)
+            .ok_or_else(|| format!("unknown topic {}", s))
+    }
+}
+
+impl BitcoinEventType {
+    /// The topic live subscribers receive this event under
+    pub fn topic(&self) -> EventTopic {
+        match self {
+            BitcoinEventType::BlockAdded { .. }
+            | BitcoinEventType::ChainReorg { .. }
+            | BitcoinEventType::ReorgHeld { .. } => EventTopic::Blocks,
+            BitcoinEventType::TransactionAdded { .. }
+            | BitcoinEventType::TxConfirmed { .. }
+            | BitcoinEventType::TxConfirmationReverted { .. }
+            | BitcoinEventType::PaymentReceived { .. }
+            | BitcoinEventType::DoubleSpendDetected { .. } => EventTopic::Transactions,
+            BitcoinEventType::PeerConnected { .. }
+            | BitcoinEventType::PeerDisconnected { .. }
+            | BitcoinEventType::PartitionSuspected { .. } => EventTopic::Peers,
+            BitcoinEventType::MempoolUpdate { .. } => EventTopic::Mempool,
+            BitcoinEventType::UtxoSetChanged { .. }
+            | BitcoinEventType::UtxoDistributionUpdated { .. } => EventTopic::Utxos,
+            BitcoinEventType::SyncProgress { .. }
+            | BitcoinEventType::NodeStarted { .. }
+            | BitcoinEventType::NodeStopping { .. }
+            | BitcoinEventType::SloBreached { .. }
+            | BitcoinEventType::LowDiskSpace { .. } => EventTopic::Node,
+        }
+    }
+
+    /// The `type` tag the event is serialized with
+    pub fn name(&self) -> &'static str {
+        match self {
+            BitcoinEventType::BlockAdded { .. } => "BlockAdded",
+            BitcoinEventType::TransactionAdded { .. } => "TransactionAdded",
+            BitcoinEventType::PeerConnected { .. } => "PeerConnected",
+            BitcoinEventType::PeerDisconnected { .. } => "PeerDisconnected",
+            BitcoinEventType::UtxoSetChanged { .. } => "UtxoSetChanged",
+            BitcoinEventType::UtxoDistributionUpdated { .. } => "UtxoDistributionUpdated",
+            BitcoinEventType::TxConfirmed { .. } => "TxConfirmed",
+            BitcoinEventType::TxConfirmationReverted { .. } => "TxConfirmationReverted",
+            BitcoinEventType::PaymentReceived { .. } => "PaymentReceived",
+            BitcoinEventType::DoubleSpendDetected { .. } => "DoubleSpendDetected",
+            BitcoinEventType::ChainReorg { .. } => "ChainReorg",
+            BitcoinEventType::ReorgHeld { .. } => "ReorgHeld",
+            BitcoinEventType::MempoolUpdate { .. } => "MempoolUpdate",
+            BitcoinEventType::PartitionSuspected { .. } => "PartitionSuspected",
+            BitcoinEventType::SyncProgress { .. } => "SyncProgress",
+            BitcoinEventType::NodeStarted { .. } => "NodeStarted",
+            BitcoinEventType::NodeStopping { .. } => "NodeStopping",
+            BitcoinEventType::SloBreached { .. } => "SloBreached",
+            BitcoinEventType::LowDiskSpace { .. } => "LowDiskSpace",
+        }
+    }
+}
+
+/// Where a transaction conflicting with the mempool was seen
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DoubleSpendSource {
+    /// Relayed transaction rejected for conflicting with the mempool
+    Mempool,
+    /// Relayed transaction that replaced the mempool transaction (RBF)
+    Replacement,
+    /// Confirmed block transaction that evicted the mempool transaction
+    Block,
 }
 
 impl EventManager {
@@ -93,46 +478,255 @@ impl EventManager {
         //     publishers.push(Box::new(zmq_publisher));
         // }
 
-        // Initialize Kubernetes publisher (disabled for simplicity)
-        // TODO: Re-enable when needed
-        // if config.events.enabled_publishers.contains(&"k8s".to_string()) && config.events.k8s.enabled {
-        //     let k8s_publisher = K8sEventPublisher::new(&config.events.k8s).await?;
-        //     publishers.push(Box::new(k8s_publisher));
-        // }
+        // Initialize Kubernetes publisher
+        if config.events.enabled_publishers.contains(&"k8s".to_string()) && config.events.k8s.enabled {
+            #[cfg(feature = "k8s-events")]
+            publishers.push(Box::new(K8sEventPublisher::new(&config.events.k8s).await?));
+            #[cfg(not(feature = "k8s-events"))]
+            warn!("Kubernetes events are configured, but this build has no k8s-events feature");
+        }
 
         // Initialize Webhook publisher
         if config.events.enabled_publishers.contains(&"webhook".to_string()) && config.events.webhook.enabled {
-            let webhook_publisher = WebhookEventPublisher::new(&config.events.webhook).await?;
-            publishers.push(Box::new(webhook_publisher));
+            #[cfg(feature = "http-client")]
+            publishers.push(Box::new(WebhookEventPublisher::new(&config.events.webhook, &config.datadir).await?));
+            #[cfg(not(feature = "http-client"))]
+            warn!("Webhook events are configured, but this build has no http-client feature");
+        }
+
+        if config.events.enabled_publishers.contains(&"nats".to_string()) && config.events.nats.enabled {
+            #[cfg(feature = "nats")]
+            publishers.push(Box::new(NatsEventPublisher::new(&config.events.nats, config.network.as_str()).await?));
+            #[cfg(not(feature = "nats"))]
+            warn!("NATS events are configured, but this build has no nats feature");
+        }
+
+        if config.cluster.enabled && config.cluster.publish_events {
+            publishers.push(Box::new(crate::cluster::ClusterEventPublisher::new(config)?));
         }
 
         info!("Event manager initialized with {} publishers", publishers.len());
 
-        Ok(Self {
+        let identity = NodeIdentity::load_or_create(&config.datadir)?;
+        Ok(Self::with_publishers(publishers).with_identity(identity))
+    }
+
+    pub fn with_identity(mut self, identity: NodeIdentity) -> Self {
+        self.identity = Some(Arc::new(identity));
+        self
+    }
+
+    pub fn identity(&self) -> Option<&NodeIdentity> {
+        self.identity.as_deref()
+    }
+
+    /// Number and persist every event published from now on, continuing
+    /// after the last sequence the journal holds, and replay what consumers
+    /// have not acknowledged in the background
+    pub async fn attach_journal(&self, storage: Addr<StorageActor>) -> EventResult<()> {
+        let page = storage.send(GetEventJournal { since_seq: 0, limit: 0 }).await
+            .map_err(|e| EventError::Journal(e.to_string()))?
+            .map_err(|e| EventError::Journal(e.to_string()))?;
+        let next_sequence = page.last_seq.map(|seq| seq + 1).unwrap_or(1);
+        let journal = EventJournal { storage: storage.clone(), next_sequence: AtomicU64::new(next_sequence) };
+        if self.journal.set(journal).is_err() {
+            warn!("Event journal already attached");
+            return Ok(());
+        }
+        info!("Event journal attached, next sequence {}", next_sequence);
+        if let Some(last_seq) = page.last_seq {
+            let manager = self.clone();
+            self.spawn_delivery(async move { manager.replay_unacknowledged(storage, last_seq).await });
+        }
+        Ok(())
+    }
+
+    /// Hand each publisher that takes acknowledgements the journaled events up
+    /// to `last_seq` after its lowest checkpoint; later events go out live
+    async fn replay_unacknowledged(&self, storage: Addr<StorageActor>, last_seq: u64) {
+        let publishers = self.publishers.read().await;
+        for registered in publishers.iter().filter(|registered| registered.publisher.is_enabled()) {
+            let publisher = &registered.publisher;
+            let Some(mut since_seq) = publisher.replay_since() else {
+                continue;
+            };
+            let mut replayed = 0;
+            'pages: while since_seq < last_seq {
+                let page = match storage.send(GetEventJournal { since_seq, limit: JOURNAL_REPLAY_PAGE }).await {
+                    Ok(Ok(page)) => page,
+                    Ok(Err(e)) => {
+                        warn!("Failed to read event journal to replay to {}: {}", publisher.name(), e);
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("Storage unavailable, nothing replayed to {}: {}", publisher.name(), e);
+                        break;
+                    }
+                };
+                if let Some(first_seq) = page.first_seq.filter(|first_seq| replayed == 0 && *first_seq > since_seq + 1) {
+                    warn!("Events {} to {} were pruned from the journal before {} replayed them",
+                        since_seq + 1, first_seq - 1, publisher.name());
+                }
+                let Some(last) = page.events.last().map(|event| event.sequence) else {
+                    break;
+                };
+                for event in page.events.iter().take_while(|event| event.sequence <= last_seq) {
+                    // A consumer that does not answer gets the rest at the next start
+                    if let Err(e) = publisher.replay(event).await {
+                        warn!("Stopped replaying events to {} at {}: {}", publisher.name(), event.sequence, e);
+                        break 'pages;
+                    }
+                    replayed += 1;
+                }
+                since_seq = last;
+            }
+            if replayed > 0 {
+                info!("Replayed {} journaled events to {}", replayed, publisher.name());
+            }
+        }
+    }
+
+    /// Number an event in the journal and stamp it with the node's key and
+    /// signature; each step is skipped without a journal or an identity
+    pub fn sign(&self, mut event: BitcoinEvent) -> BitcoinEvent {
+        if event.sequence == 0 {
+            if let Some(journal) = self.journal.get() {
+                event.sequence = journal.next_sequence.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        if let Some(identity) = &self.identity {
+            event.node_pubkey = Some(identity.pubkey().to_string());
+            event.signature = Some(identity.sign(&signing_payload(&event)));
+        }
+        event
+    }
+
+    pub fn with_publishers(publishers: Vec<Box<dyn EventPublisher + Send + Sync>>) -> Self {
+        let publishers = publishers.into_iter().map(RegisteredPublisher::new).collect();
+        Self {
             publishers: Arc::new(RwLock::new(publishers)),
-        })
+            deliveries: Arc::new(DeliveryTracker {
+                pending: AtomicUsize::new(0),
+                idle: Notify::new(),
+                abort: watch::channel(false).0,
+            }),
+            identity: None,
+            journal: Arc::new(OnceLock::new()),
+            live: broadcast::channel(LIVE_EVENT_BUFFER).0,
+        }
+    }
+
+    /// Run a delivery on its own task rather than the emitting actor's
+    /// context, so it outlives that actor during shutdown and [`Self::drain`]
+    /// can wait for it
+    pub fn spawn_delivery(&self, delivery: impl Future<Output = ()> + Send + 'static) {
+        let tracker = self.deliveries.clone();
+        let mut aborted = tracker.abort.subscribe();
+        tracker.pending.fetch_add(1, Ordering::SeqCst);
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = delivery => {}
+                _ = aborted.wait_for(|aborted| *aborted) => warn!("Event delivery aborted by shutdown"),
+            }
+            if tracker.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                tracker.idle.notify_waiters();
+            }
+        });
+    }
+
+    /// Wait until every spawned delivery has finished
+    pub async fn drain(&self) {
+        loop {
+            let idle = self.deliveries.idle.notified();
+            if self.deliveries.pending.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    /// Drop deliveries still running, e.g. webhooks to an endpoint that stopped answering
+    pub fn abort_deliveries(&self) {
+        self.deliveries.abort.send_replace(true);
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<BitcoinEvent> {
+        self.live.subscribe()
+    }
+
+    /// Move a consumer's checkpoint on the named publisher forward, to at
+    /// most the last sequence the journal has handed out
+    pub async fn acknowledge(&self, publisher: &str, endpoint: &str, sequence: u64) -> EventResult<()> {
+        let journal = self.journal.get()
+            .ok_or_else(|| EventError::PublisherUnavailable("events are not numbered without the journal".to_string()))?;
+        let last = journal.next_sequence.load(Ordering::SeqCst).saturating_sub(1);
+        if sequence > last {
+            return Err(EventError::PublishFailed(format!(
+                "sequence {} has not been published yet, the last was {}", sequence, last
+            )));
+        }
+        let publishers = self.publishers.read().await;
+        let registered = publishers.iter()
+            .find(|registered| registered.publisher.name() == publisher)
+            .ok_or_else(|| EventError::PublisherUnavailable(format!("{} is not enabled", publisher)))?;
+        registered.publisher.acknowledge(endpoint, sequence)
+    }
+
+    /// Snapshot of every registered publisher with its health and delivery counters
+    pub async fn publisher_statuses(&self) -> Vec<PublisherStatus> {
+        let publishers = self.publishers.read().await;
+        publishers.iter().map(RegisteredPublisher::status).collect()
     }
 
     pub async fn publish(&self, event_type: BitcoinEventType, network: &str, node_id: &str) -> EventResult<()> {
-        let event = BitcoinEvent {
-            id: Uuid::new_v4().to_string(),
-            timestamp: Utc::now(),
-            event_type,
-            network: network.to_string(),
-            node_id: node_id.to_string(),
-        };
+        self.publish_event(&BitcoinEvent::new(event_type, network, node_id)).await
+    }
 
+    /// Deliver an already constructed event, for callers that also send it
+    /// elsewhere; events not yet numbered or signed are on the way out, and
+    /// each is journaled before any publisher sees it
+    pub async fn publish_event(&self, event: &BitcoinEvent) -> EventResult<()> {
+        let journal = self.journal.get();
+        let signed;
+        let event = if (journal.is_some() && event.sequence == 0) || (self.identity.is_some() && event.signature.is_none()) {
+            signed = self.sign(event.clone());
+            &signed
+        } else {
+            event
+        };
+        if let Some(journal) = journal {
+            match journal.storage.send(JournalEvent { event: event.clone() }).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Failed to journal event {}: {}", event.sequence, e),
+                Err(e) => warn!("Storage unavailable, event {} not journaled: {}", event.sequence, e),
+            }
+        }
+        // Nobody listening is not an error
+        let _ = self.live.send(event.clone());
         let publishers = self.publishers.read().await;
         let mut errors = Vec::new();
 
-        for publisher in publishers.iter() {
-            if publisher.is_enabled() {
-                if let Err(e) = publisher.publish(&event).await {
-                    error!("Failed to publish event via {}: {}", publisher.name(), e);
-                    errors.push(e);
-                } else {
-                    info!("Event published via {}: {}", publisher.name(), event.id);
-                }
+        for registered in publishers.iter() {
+            let publisher = &registered.publisher;
+            if !publisher.is_enabled() {
+                continue;
+            }
+            if registered.stats.circuit_open() {
+                warn!("Skipping event {} for {}: circuit open after repeated failures", event.id, publisher.name());
+                registered.stats.short_circuited.fetch_add(1, Ordering::Relaxed);
+                crate::metrics::record_event_short_circuited(publisher.name());
+                errors.push(EventError::PublishFailed(format!("{} circuit open", publisher.name())));
+                continue;
+            }
+            let started = Instant::now();
+            if let Err(e) = publisher.publish(event).await {
+                error!("Failed to publish event via {}: {}", publisher.name(), e);
+                registered.stats.record_failure(publisher.name(), &e, started.elapsed());
+                errors.push(e);
+            } else {
+                info!("Event published via {}: {}", publisher.name(), event.id);
+                registered.stats.record_success(publisher.name(), started.elapsed());
             }
         }
 
@@ -150,6 +744,7 @@ impl EventManager {
 // TODO: Implement proper thread-safe ZMQ wrapper
 
 // Kubernetes Event Publisher
+#[cfg(feature = "k8s-events")]
 pub struct K8sEventPublisher {
     client: kube::Client,
     events_api: kube::Api<k8s_openapi::api::core::v1::Event>,
@@ -159,6 +754,7 @@ pub struct K8sEventPublisher {
     enabled: bool,
 }
 
+#[cfg(feature = "k8s-events")]
 impl K8sEventPublisher {
     pub async fn new(config: &crate::config::K8sEventConfig) -> EventResult<Self> {
         let client = kube::Client::try_default().await
@@ -178,6 +774,7 @@ impl K8sEventPublisher {
     }
 }
 
+#[cfg(feature = "k8s-events")]
 #[async_trait::async_trait]
 impl EventPublisher for K8sEventPublisher {
     async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
@@ -188,9 +785,13 @@ impl EventPublisher for K8sEventPublisher {
 
         let event_type = match &event.event_type {
             BitcoinEventType::BlockAdded { .. } => "block",
-            BitcoinEventType::TransactionAdded { .. } => "transaction",
+            BitcoinEventType::TransactionAdded { .. }
+            | BitcoinEventType::TxConfirmed { .. }
+            | BitcoinEventType::TxConfirmationReverted { .. }
+            | BitcoinEventType::PaymentReceived { .. }
+            | BitcoinEventType::DoubleSpendDetected { .. } => "transaction",
             BitcoinEventType::PeerConnected { .. } | BitcoinEventType::PeerDisconnected { .. } => "peer",
-            BitcoinEventType::ChainReorg { .. } => "chain",
+            BitcoinEventType::ChainReorg { .. } | BitcoinEventType::ReorgHeld { .. } => "chain",
             _ => "general",
         };
 
@@ -205,6 +806,9 @@ impl EventPublisher for K8sEventPublisher {
             BitcoinEventType::TransactionAdded { txid, fee, .. } => {
                 ("NewTransaction".to_string(), format!("New transaction {} with fee {}", txid, fee))
             }
+            BitcoinEventType::DoubleSpendDetected { txid, conflicting_txid, .. } => {
+                ("DoubleSpend".to_string(), format!("Transaction {} double spends {}", txid, conflicting_txid))
+            }
             BitcoinEventType::PeerConnected { peer_id, address, .. } => {
                 ("PeerConnected".to_string(), format!("Peer {} connected from {}", peer_id, address))
             }
@@ -214,6 +818,15 @@ impl EventPublisher for K8sEventPublisher {
             BitcoinEventType::ChainReorg { old_tip, new_tip, depth } => {
                 ("ChainReorg".to_string(), format!("Chain reorg from {} to {} (depth: {})", old_tip, new_tip, depth))
             }
+            BitcoinEventType::ReorgHeld { old_tip, new_tip, depth, max_depth, .. } => {
+                ("ReorgHeld".to_string(), format!("Reorg from {} to {} held for confirmation (depth: {}, max: {})", old_tip, new_tip, depth, max_depth))
+            }
+            BitcoinEventType::SloBreached { endpoint, objective, value, target, .. } => {
+                ("SloBreached".to_string(), format!("{} missed its {} objective: {:.3} against {:.3}", endpoint, objective, value, target))
+            }
+            BitcoinEventType::LowDiskSpace { path, available_bytes, total_bytes, .. } => {
+                ("LowDiskSpace".to_string(), format!("{} has {} of {} bytes free", path, available_bytes, total_bytes))
+            }
             _ => ("BitcoinEvent".to_string(), "Bitcoin node event".to_string()),
         };
 
@@ -231,7 +844,13 @@ impl EventPublisher for K8sEventPublisher {
             },
             reason: Some(reason),
             message: Some(message),
-            type_: Some("Normal".to_string()),
+            // A held reorg, a missed objective or a filling disk needs an operator, everything else is informational
+            type_: Some(match &event.event_type {
+                BitcoinEventType::ReorgHeld { .. }
+                | BitcoinEventType::SloBreached { .. }
+                | BitcoinEventType::LowDiskSpace { .. } => "Warning".to_string(),
+                _ => "Normal".to_string(),
+            }),
             action: Some(format!("Bitcoin{}", event_type)),
             first_timestamp: Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(event.timestamp)),
             last_timestamp: Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(event.timestamp)),
@@ -251,24 +870,209 @@ impl EventPublisher for K8sEventPublisher {
     fn is_enabled(&self) -> bool {
         self.enabled
     }
+
+    fn endpoints(&self) -> Vec<PublisherEndpoint> {
+        vec![PublisherEndpoint {
+            address: format!("k8s://{}/{}", self.namespace, self.node_name),
+            topics: self.event_types.clone(),
+            high_water_mark: None,
+            acknowledged: None,
+        }]
+    }
+}
+
+/// NATS subject suffix for an event type tag: `BlockAdded` becomes `block.added`
+pub fn nats_subject_suffix(event_type: &str) -> String {
+    let mut suffix = String::with_capacity(event_type.len() + 4);
+    for (i, c) in event_type.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            suffix.push('.');
+        }
+        suffix.push(c.to_ascii_lowercase());
+    }
+    suffix
+}
+
+// NATS Event Publisher
+#[cfg(feature = "nats")]
+pub struct NatsEventPublisher {
+    client: async_nats::Client,
+    /// Set when events go through JetStream
+    jetstream: Option<async_nats::jetstream::Context>,
+    url: String,
+    /// `<subject_prefix>.<network>`
+    subject_base: String,
+    enabled: bool,
+}
+
+#[cfg(feature = "nats")]
+impl NatsEventPublisher {
+    pub async fn new(config: &crate::config::NatsEventConfig, network: &str) -> EventResult<Self> {
+        let unavailable = |e: &dyn std::fmt::Display| EventError::PublisherUnavailable(format!("nats {}: {}", config.url, e));
+        let options = match &config.credentials_file {
+            Some(path) => async_nats::ConnectOptions::with_credentials_file(path.clone()).await.map_err(|e| unavailable(&e))?,
+            None => async_nats::ConnectOptions::new(),
+        };
+        // The first connection has to succeed; after that the client reconnects by itself
+        let url = config.url.clone();
+        let client = options
+            .max_reconnects(None)
+            .event_callback(move |event| {
+                let url = url.clone();
+                async move {
+                    match event {
+                        async_nats::Event::Connected => info!("Connected to NATS at {}", url),
+                        async_nats::Event::Disconnected => warn!("Lost connection to NATS at {}, reconnecting", url),
+                        other => warn!("NATS {}: {}", url, other),
+                    }
+                }
+            })
+            .connect(config.url.as_str())
+            .await
+            .map_err(|e| unavailable(&e))?;
+
+        let subject_base = format!("{}.{}", config.subject_prefix, network);
+        let jetstream = if config.jetstream {
+            let context = async_nats::jetstream::new(client.clone());
+            context
+                .get_or_create_stream(async_nats::jetstream::stream::Config {
+                    name: config.stream.clone(),
+                    subjects: vec![format!("{}.>", subject_base)],
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| unavailable(&e))?;
+            Some(context)
+        } else {
+            None
+        };
+
+        info!(
+            "NATS event publisher initialized on {} under {}.>{}",
+            config.url,
+            subject_base,
+            if jetstream.is_some() { format!(" (JetStream stream {})", config.stream) } else { String::new() },
+        );
+        Ok(Self {
+            client,
+            jetstream,
+            url: config.url.clone(),
+            subject_base,
+            enabled: config.enabled,
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait::async_trait]
+impl EventPublisher for NatsEventPublisher {
+    async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
+        let subject = format!("{}.{}", self.subject_base, nats_subject_suffix(event.event_type.name()));
+        let payload = serde_json::to_vec(event).map_err(|e| EventError::Serialization(e.to_string()))?;
+
+        match &self.jetstream {
+            Some(jetstream) => {
+                // JetStream drops a second message with the same ID, so retries cannot duplicate an event
+                let mut headers = async_nats::HeaderMap::new();
+                headers.insert("Nats-Msg-Id", event.id.as_str());
+                jetstream
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await
+                    .map_err(|e| EventError::PublishFailed(e.to_string()))?
+                    .await
+                    .map_err(|e| EventError::PublishFailed(e.to_string()))?;
+            }
+            None => self.client
+                .publish(subject, payload.into())
+                .await
+                .map_err(|e| EventError::PublishFailed(e.to_string()))?,
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "nats"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn endpoints(&self) -> Vec<PublisherEndpoint> {
+        vec![PublisherEndpoint {
+            address: self.url.clone(),
+            topics: vec![format!("{}.>", self.subject_base)],
+            high_water_mark: None,
+            acknowledged: None,
+        }]
+    }
+}
+
+/// File in the datadir holding the webhook consumer checkpoints
+#[cfg(feature = "http-client")]
+const WEBHOOK_CHECKPOINTS_FILE: &str = "webhook_checkpoints.json";
+
+/// Kept on disk so acknowledged events are not sent again after a restart;
+/// written on acknowledgement only, never per delivery
+#[cfg(feature = "http-client")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WebhookCheckpoints {
+    /// Highest journal sequence each endpoint has acknowledged
+    acknowledged: BTreeMap<String, u64>,
+}
+
+#[cfg(feature = "http-client")]
+impl WebhookCheckpoints {
+    fn load(path: &Path) -> EventResult<Self> {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| EventError::Serialization(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(EventError::PublisherUnavailable(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, data)?;
+        std::fs::rename(partial, path)
+    }
+}
+
+/// `X-Signature-256` value for a webhook body: `sha256=` and the hex HMAC-SHA256 under `secret`
+pub fn webhook_signature(secret: &str, payload: &[u8]) -> String {
+    use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+    use bitcoin::hex::DisplayHex;
+
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(payload);
+    format!("sha256={}", hmac::Hmac::from_engine(engine).to_byte_array().to_lower_hex_string())
 }
 
 // Webhook Event Publisher
+#[cfg(feature = "http-client")]
 pub struct WebhookEventPublisher {
     client: reqwest::Client,
-    endpoints: Vec<String>,
+    endpoints: Vec<WebhookEndpointConfig>,
     timeout: std::time::Duration,
     retry_attempts: u32,
+    retried: AtomicU64,
+    checkpoints: Mutex<WebhookCheckpoints>,
+    checkpoints_path: PathBuf,
     enabled: bool,
 }
 
+#[cfg(feature = "http-client")]
 impl WebhookEventPublisher {
-    pub async fn new(config: &crate::config::WebhookEventConfig) -> EventResult<Self> {
+    pub async fn new(config: &crate::config::WebhookEventConfig, datadir: &Path) -> EventResult<Self> {
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(config.timeout_secs))
             .build()
             .map_err(|e| EventError::PublishFailed(e.to_string()))?;
 
+        let checkpoints_path = datadir.join(WEBHOOK_CHECKPOINTS_FILE);
+        let checkpoints = WebhookCheckpoints::load(&checkpoints_path)?;
+
         info!("Webhook event publisher initialized with {} endpoints", config.endpoints.len());
 
         Ok(Self {
@@ -276,29 +1080,57 @@ impl WebhookEventPublisher {
             endpoints: config.endpoints.clone(),
             timeout: std::time::Duration::from_secs(config.timeout_secs),
             retry_attempts: config.retry_attempts,
+            retried: AtomicU64::new(0),
+            checkpoints: Mutex::new(checkpoints),
+            checkpoints_path,
             enabled: config.enabled,
         })
     }
+
+    fn acknowledged(&self, endpoint: &str) -> Option<u64> {
+        self.checkpoints.lock().unwrap().acknowledged.get(endpoint).copied()
+    }
 }
 
-#[async_trait::async_trait]
-impl EventPublisher for WebhookEventPublisher {
-    async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
+#[cfg(feature = "http-client")]
+impl WebhookEventPublisher {
+    /// Post `event` to every endpoint taking its type that has not acknowledged
+    /// it; when `replaying`, only to endpoints holding a checkpoint
+    async fn deliver(&self, event: &BitcoinEvent, replaying: bool) -> EventResult<()> {
         let payload = serde_json::to_string(event)
             .map_err(|e| EventError::Serialization(e.to_string()))?;
 
-        for endpoint in &self.endpoints {
+        let event_type = event.event_type.name();
+        let mut undelivered = Vec::new();
+        for config in self.endpoints.iter().filter(|config| config.accepts(event_type)) {
+            let endpoint = config.url();
+            // Events are only numbered once the journal is attached; an
+            // unnumbered event is never covered by a checkpoint
+            let acknowledged = self.acknowledged(endpoint);
+            if replaying && acknowledged.is_none() {
+                continue;
+            }
+            if event.sequence != 0 && acknowledged.is_some_and(|acknowledged| acknowledged >= event.sequence) {
+                continue;
+            }
+            let signature = config.secret().map(|secret| webhook_signature(secret, payload.as_bytes()));
             let mut attempts = 0;
             let mut success = false;
 
             while attempts <= self.retry_attempts && !success {
-                match self.client
+                let mut request = self.client
                     .post(endpoint)
                     .header("Content-Type", "application/json")
-                    .body(payload.clone())
-                    .send()
-                    .await
-                {
+                    .header("Idempotency-Key", &event.id)
+                    .header("X-Event-Id", &event.id)
+                    .header("X-Event-Type", event_type);
+                if event.sequence != 0 {
+                    request = request.header("X-Event-Sequence", event.sequence);
+                }
+                if let Some(signature) = &signature {
+                    request = request.header("X-Signature-256", signature);
+                }
+                match request.body(payload.clone()).send().await {
                     Ok(response) => {
                         if response.status().is_success() {
                             success = true;
@@ -313,17 +1145,42 @@ impl EventPublisher for WebhookEventPublisher {
 
                 attempts += 1;
                 if !success && attempts <= self.retry_attempts {
+                    self.retried.fetch_add(1, Ordering::Relaxed);
+                    crate::metrics::record_event_retry(self.name());
                     tokio::time::sleep(std::time::Duration::from_millis(1000 * attempts as u64)).await;
                 }
             }
 
             if !success {
                 error!("Failed to deliver webhook to {} after {} attempts", endpoint, self.retry_attempts + 1);
+                undelivered.push(endpoint);
             }
         }
 
+        if !undelivered.is_empty() {
+            return Err(EventError::PublishFailed(format!("webhook not delivered to {}", undelivered.join(", "))));
+        }
         Ok(())
     }
+}
+
+#[cfg(feature = "http-client")]
+#[async_trait::async_trait]
+impl EventPublisher for WebhookEventPublisher {
+    async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
+        self.deliver(event, false).await
+    }
+
+    async fn replay(&self, event: &BitcoinEvent) -> EventResult<()> {
+        self.deliver(event, true).await
+    }
+
+    fn replay_since(&self) -> Option<u64> {
+        let checkpoints = self.checkpoints.lock().unwrap();
+        self.endpoints.iter()
+            .filter_map(|config| checkpoints.acknowledged.get(config.url()).copied())
+            .min()
+    }
 
     fn name(&self) -> &str {
         "webhook"
@@ -332,4 +1189,377 @@ impl EventPublisher for WebhookEventPublisher {
     fn is_enabled(&self) -> bool {
         self.enabled
     }
-}
\ No newline at end of file
+
+    fn retried(&self) -> u64 {
+        self.retried.load(Ordering::Relaxed)
+    }
+
+    fn acknowledge(&self, endpoint: &str, sequence: u64) -> EventResult<()> {
+        if !self.endpoints.iter().any(|configured| configured.url() == endpoint) {
+            return Err(EventError::PublishFailed(format!("{} is not a webhook endpoint", endpoint)));
+        }
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        // Checkpoints only move forward, so a late or repeated acknowledgement is harmless
+        let acknowledged = checkpoints.acknowledged.entry(endpoint.to_string()).or_insert(0);
+        *acknowledged = (*acknowledged).max(sequence);
+        checkpoints.save(&self.checkpoints_path)
+            .map_err(|e| EventError::PublisherUnavailable(format!("{}: {}", self.checkpoints_path.display(), e)))
+    }
+
+    fn endpoints(&self) -> Vec<PublisherEndpoint> {
+        self.endpoints
+            .iter()
+            .map(|config| PublisherEndpoint {
+                address: config.url().to_string(),
+                topics: config.event_types().to_vec(),
+                high_water_mark: None,
+                acknowledged: Some(self.acknowledged(config.url()).unwrap_or(0)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockPublisher {
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl EventPublisher for MockPublisher {
+        async fn publish(&self, _event: &BitcoinEvent) -> EventResult<()> {
+            if self.fail {
+                Err(EventError::PublishFailed("mock failure".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn name(&self) -> &str {
+            if self.fail { "failing" } else { "working" }
+        }
+
+        fn is_enabled(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publisher_status_counters() {
+        let manager = EventManager::with_publishers(vec![
+            Box::new(MockPublisher { fail: false }),
+            Box::new(MockPublisher { fail: true }),
+        ]);
+
+        let event = BitcoinEventType::NodeStopping {
+            reason: "test".to_string(),
+            uptime_seconds: 0,
+        };
+        manager.publish(event.clone(), "regtest", "node").await.unwrap();
+        manager.publish(event, "regtest", "node").await.unwrap();
+
+        let statuses = manager.publisher_statuses().await;
+        assert_eq!(statuses.len(), 2);
+
+        let working = statuses.iter().find(|s| s.name == "working").unwrap();
+        assert_eq!(working.delivered, 2);
+        assert_eq!(working.failed, 0);
+        assert!(working.healthy);
+        assert!(working.last_delivery.is_some());
+
+        let failing = statuses.iter().find(|s| s.name == "failing").unwrap();
+        assert_eq!(failing.delivered, 0);
+        assert_eq!(failing.failed, 2);
+        assert_eq!(failing.consecutive_failures, 2);
+        assert!(!failing.healthy);
+        assert_eq!(failing.last_error.as_deref(), Some("Failed to publish event: mock failure"));
+        assert!(failing.mean_latency_ms.is_some());
+        assert!(!failing.circuit_open);
+    }
+
+    #[tokio::test]
+    async fn test_failing_publisher_opens_circuit() {
+        let manager = EventManager::with_publishers(vec![
+            Box::new(MockPublisher { fail: false }),
+            Box::new(MockPublisher { fail: true }),
+        ]);
+        let event = BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 0 };
+        for _ in 0..CIRCUIT_FAILURE_THRESHOLD + 2 {
+            manager.publish(event.clone(), "regtest", "node").await.unwrap();
+        }
+
+        let statuses = manager.publisher_statuses().await;
+        let failing = statuses.iter().find(|s| s.name == "failing").unwrap();
+        assert!(failing.circuit_open);
+        assert_eq!(failing.failed, CIRCUIT_FAILURE_THRESHOLD);
+        assert_eq!(failing.short_circuited, 2);
+
+        // The working publisher is unaffected
+        let working = statuses.iter().find(|s| s.name == "working").unwrap();
+        assert_eq!(working.delivered, CIRCUIT_FAILURE_THRESHOLD + 2);
+        assert!(!working.circuit_open);
+    }
+
+    struct CapturingPublisher(Arc<Mutex<Vec<BitcoinEvent>>>);
+
+    #[async_trait::async_trait]
+    impl EventPublisher for CapturingPublisher {
+        async fn publish(&self, event: &BitcoinEvent) -> EventResult<()> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "capturing"
+        }
+
+        fn is_enabled(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_are_signed_with_node_identity() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let identity = NodeIdentity::from_secret(&[7; 32]).unwrap();
+        let pubkey = identity.pubkey().to_string();
+        let manager = EventManager::with_publishers(vec![Box::new(CapturingPublisher(captured.clone()))])
+            .with_identity(identity);
+
+        let event = BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 5 };
+        manager.publish(event.clone(), "regtest", "node").await.unwrap();
+        let signed = captured.lock().unwrap()[0].clone();
+        assert_eq!(signed.node_pubkey.as_deref(), Some(pubkey.as_str()));
+
+        // A receiver checks the event as it arrives over the wire
+        let received: BitcoinEvent = serde_json::from_str(&serde_json::to_string(&signed).unwrap()).unwrap();
+        assert!(crate::identity::verify_event(&received));
+        let mut tampered = received.clone();
+        tampered.node_id = "other-node".to_string();
+        assert!(!crate::identity::verify_event(&tampered));
+
+        // Without an identity events go out as before
+        let unsigned = EventManager::with_publishers(Vec::new()).sign(BitcoinEvent::new(event, "regtest", "node"));
+        assert!(!crate::identity::verify_event(&unsigned));
+        assert!(!serde_json::to_string(&unsigned).unwrap().contains("node_pubkey"));
+    }
+
+    #[actix_rt::test]
+    async fn test_events_are_journaled_and_replayed() {
+        use actix::Actor;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let storage = StorageActor::new(&config).start();
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let manager = EventManager::with_publishers(vec![Box::new(CapturingPublisher(captured.clone()))])
+            .with_identity(NodeIdentity::from_secret(&[7; 32]).unwrap());
+        manager.attach_journal(storage.clone()).await.unwrap();
+
+        let event = BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 5 };
+        for _ in 0..3 {
+            manager.publish(event.clone(), "regtest", "node").await.unwrap();
+        }
+        let delivered = captured.lock().unwrap().clone();
+        assert_eq!(delivered.iter().map(|event| event.sequence).collect::<Vec<_>>(), vec![1, 2, 3]);
+        // The sequence is covered by the signature
+        let mut renumbered = delivered[0].clone();
+        renumbered.sequence = 9;
+        assert!(crate::identity::verify_event(&delivered[0]));
+        assert!(!crate::identity::verify_event(&renumbered));
+
+        let page = storage.send(GetEventJournal { since_seq: 1, limit: 10 }).await.unwrap().unwrap();
+        assert_eq!((page.first_seq, page.last_seq), (Some(1), Some(3)));
+        assert_eq!(page.events.iter().map(|event| event.id.as_str()).collect::<Vec<_>>(), vec![delivered[1].id.as_str(), delivered[2].id.as_str()]);
+
+        // A restarted node continues the sequence
+        let restarted = EventManager::with_publishers(Vec::new());
+        restarted.attach_journal(storage).await.unwrap();
+        assert_eq!(restarted.sign(BitcoinEvent::new(event, "regtest", "node")).sequence, 4);
+    }
+
+    /// Takes acknowledgements, with every consumer checkpointed at `since`
+    struct CheckpointedPublisher {
+        since: u64,
+        replayed: Arc<Mutex<Vec<u64>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventPublisher for CheckpointedPublisher {
+        async fn publish(&self, _event: &BitcoinEvent) -> EventResult<()> {
+            Ok(())
+        }
+
+        async fn replay(&self, event: &BitcoinEvent) -> EventResult<()> {
+            self.replayed.lock().unwrap().push(event.sequence);
+            Ok(())
+        }
+
+        fn replay_since(&self) -> Option<u64> {
+            Some(self.since)
+        }
+
+        fn name(&self) -> &str {
+            "checkpointed"
+        }
+
+        fn is_enabled(&self) -> bool {
+            true
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_unacknowledged_events_are_replayed_at_startup() {
+        use actix::Actor;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let storage = StorageActor::new(&config).start();
+
+        let manager = EventManager::with_publishers(Vec::new());
+        manager.attach_journal(storage.clone()).await.unwrap();
+        let event = BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 5 };
+        for _ in 0..4 {
+            manager.publish(event.clone(), "regtest", "node").await.unwrap();
+        }
+
+        let replayed = Arc::new(Mutex::new(Vec::new()));
+        let restarted = EventManager::with_publishers(vec![
+            Box::new(CheckpointedPublisher { since: 2, replayed: replayed.clone() }),
+        ]);
+        restarted.attach_journal(storage).await.unwrap();
+        restarted.drain().await;
+        assert_eq!(*replayed.lock().unwrap(), vec![3, 4]);
+
+        // Events published after startup go out live, not as a replay
+        restarted.publish(event, "regtest", "node").await.unwrap();
+        restarted.drain().await;
+        assert_eq!(*replayed.lock().unwrap(), vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_live_subscribers_receive_events() {
+        let manager = EventManager::with_publishers(Vec::new());
+        let mut live = manager.subscribe();
+
+        let event = BitcoinEventType::PeerConnected {
+            peer_id: "1".to_string(),
+            address: "127.0.0.1:18444".to_string(),
+            user_agent: None,
+        };
+        manager.publish(event, "regtest", "node").await.unwrap();
+        let received = live.recv().await.unwrap();
+        assert_eq!(received.event_type.name(), "PeerConnected");
+        assert_eq!(received.event_type.topic(), EventTopic::Peers);
+
+        assert_eq!("mempool".parse::<EventTopic>(), Ok(EventTopic::Mempool));
+        assert!("mempools".parse::<EventTopic>().is_err());
+        let command: crate::streams::TopicCommand = serde_json::from_str(r#"{"unsubscribe": ["blocks", "utxos"]}"#).unwrap();
+        assert_eq!(command, crate::streams::TopicCommand::Unsubscribe(vec![EventTopic::Blocks, EventTopic::Utxos]));
+    }
+
+    #[test]
+    fn test_nats_subject_suffix() {
+        assert_eq!(nats_subject_suffix("BlockAdded"), "block.added");
+        assert_eq!(nats_subject_suffix("UtxoSetChanged"), "utxo.set.changed");
+        assert_eq!(nats_subject_suffix("ReorgHeld"), "reorg.held");
+
+        let event = BitcoinEvent::new(BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 0 }, "regtest", "node");
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["event_type"]["type"], event.event_type.name());
+        assert_eq!(nats_subject_suffix(event.event_type.name()), "node.stopping");
+    }
+
+    #[test]
+    fn test_webhook_signature_and_filters() {
+        // HMAC-SHA256 of the usual sample under the key "key"
+        assert_eq!(
+            webhook_signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+        );
+
+        let config: crate::config::WebhookEventConfig = toml::from_str(r#"
+            enabled = true
+            timeout_secs = 10
+            retry_attempts = 0
+            endpoints = [
+                "https://all.example/hook",
+                { url = "https://blocks.example/hook", secret = "s3cret", event_types = ["BlockAdded", "ChainReorg"] },
+            ]
+        "#).unwrap();
+        let [all, blocks] = &config.endpoints[..] else { panic!("two endpoints") };
+        assert_eq!(all.secret(), None);
+        assert!(all.accepts("TxConfirmed"));
+        assert_eq!(blocks.url(), "https://blocks.example/hook");
+        assert_eq!(blocks.secret(), Some("s3cret"));
+        assert!(blocks.accepts("BlockAdded"));
+        assert!(!blocks.accepts("TxConfirmed"));
+    }
+
+    #[cfg(feature = "http-client")]
+    #[tokio::test]
+    async fn test_webhook_checkpoints_skip_acknowledged_events() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let endpoint = format!("http://{}/hook", listener.local_addr().unwrap());
+        let config = crate::config::WebhookEventConfig {
+            enabled: true,
+            endpoints: vec![WebhookEndpointConfig::Url(endpoint.clone())],
+            timeout_secs: 1,
+            retry_attempts: 0,
+        };
+        let webhook = WebhookEventPublisher::new(&config, temp_dir.path()).await.unwrap();
+        assert!(webhook.acknowledge("http://elsewhere/hook", 1).is_err());
+        webhook.acknowledge(&endpoint, 2).unwrap();
+        webhook.acknowledge(&endpoint, 1).unwrap();
+        assert_eq!(webhook.endpoints()[0].acknowledged, Some(2));
+
+        // Checkpoints are kept across a restart
+        let restarted = WebhookEventPublisher::new(&config, temp_dir.path()).await.unwrap();
+        assert_eq!(restarted.endpoints()[0].acknowledged, Some(2));
+
+        // A replayed event at or below the checkpoint is not sent
+        let mut event = BitcoinEvent::new(
+            BitcoinEventType::NodeStopping { reason: "test".to_string(), uptime_seconds: 5 },
+            "regtest",
+            "node",
+        );
+        for sequence in [1, 2] {
+            event.sequence = sequence;
+            restarted.publish(&event).await.unwrap();
+            assert_eq!(listener.accept().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        }
+        // The next one is, and nobody answers it
+        event.sequence = 3;
+        assert!(restarted.publish(&event).await.is_err());
+        assert!(listener.accept().is_ok());
+
+        // Without a journal there are no sequences to acknowledge
+        let manager = EventManager::with_publishers(vec![Box::new(restarted)]);
+        assert!(manager.acknowledge("webhook", &endpoint, 3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain_and_abort_deliveries() {
+        let manager = EventManager::with_publishers(Vec::new());
+        manager.drain().await;
+
+        let (done, finished) = tokio::sync::oneshot::channel();
+        manager.spawn_delivery(async move {
+            let _ = done.send(());
+        });
+        manager.spawn_delivery(std::future::pending());
+        finished.await.unwrap();
+
+        let wait = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, manager.drain()).await.is_err());
+        manager.abort_deliveries();
+        assert!(tokio::time::timeout(wait, manager.drain()).await.is_ok());
+    }
+}
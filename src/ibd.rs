@@ -0,0 +1,138 @@
+//! Initial block download detection and the inbound greylist
+//!
+//! While the node is far behind, serving peers and relaying transactions it
+//! cannot validate yet only competes with the sync for bandwidth and CPU. The
+//! greylist caps or refuses inbound connections and turns off transaction
+//! relay until verification progress reaches the configured threshold.
+
+use bitcoin::blockdata::constants::genesis_block;
+
+use crate::config::IbdGreylistConfig;
+
+/// A tip older than this means the node is still syncing, like Core's `-maxtipage` default
+pub const MAX_TIP_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// Share of the chain's history up to `now` that the tip covers, by time.
+/// Transaction density grew over the years, so early in a sync this runs
+/// ahead of Core's transaction count based estimate.
+pub fn verification_progress(network: bitcoin::Network, tip_time: u32, now: i64) -> f64 {
+    let genesis_time = genesis_block(network).header.time as i64;
+    if now <= genesis_time {
+        return 1.0;
+    }
+    ((tip_time as i64 - genesis_time) as f64 / (now - genesis_time) as f64).clamp(0.0, 1.0)
+}
+
+pub fn is_initial_block_download(tip_time: u32, now: i64) -> bool {
+    now - tip_time as i64 > MAX_TIP_AGE_SECS
+}
+
+pub struct IbdGreylist {
+    config: IbdGreylistConfig,
+    network: bitcoin::Network,
+    /// `None` until the first tip is seen
+    progress: Option<f64>,
+    active: bool,
+}
+
+impl IbdGreylist {
+    /// Starts active when enabled, so peers arriving before the tip is loaded are greylisted too
+    pub fn new(config: &IbdGreylistConfig, network: bitcoin::Network) -> Self {
+        Self {
+            config: config.clone(),
+            network,
+            progress: None,
+            active: config.enabled,
+        }
+    }
+
+    /// Update progress from a new tip; returns true when this lifts the greylist.
+    /// Like Core's IBD flag it latches, so a later stall does not re-enable it.
+    pub fn tip_changed(&mut self, tip_time: u32, now: i64) -> bool {
+        let progress = verification_progress(self.network, tip_time, now);
+        self.progress = Some(progress);
+        if self.active && progress >= self.config.resume_progress {
+            self.active = false;
+            return true;
+        }
+        false
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        self.progress
+    }
+
+    /// Inbound connections allowed right now, `None` when unrestricted
+    pub fn inbound_limit(&self) -> Option<usize> {
+        match (self.active, self.config.refuse_inbound) {
+            (false, _) => None,
+            (true, true) => Some(0),
+            (true, false) => Some(self.config.max_inbound),
+        }
+    }
+
+    /// Whether to ask peers for transactions in our `version` message
+    pub fn relay_transactions(&self) -> bool {
+        !self.active
+    }
+
+    /// `getnetworkinfo` warning while the greylist is active
+    pub fn warning(&self) -> Option<String> {
+        if !self.active {
+            return None;
+        }
+        let progress = match self.progress {
+            Some(progress) => format!("{:.2}% verified", progress * 100.0),
+            None => "loading chain tip".to_string(),
+        };
+        let inbound = if self.config.refuse_inbound {
+            "inbound connections are refused".to_string()
+        } else {
+            format!("inbound connections are limited to {}", self.config.max_inbound)
+        };
+        Some(format!("Initial block download in progress ({}): {} and transaction relay is paused", progress, inbound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verification_progress() {
+        let genesis_time = genesis_block(bitcoin::Network::Bitcoin).header.time;
+        let now = genesis_time as i64 + 1_000_000;
+        assert_eq!(verification_progress(bitcoin::Network::Bitcoin, genesis_time, now), 0.0);
+        assert_eq!(verification_progress(bitcoin::Network::Bitcoin, genesis_time + 250_000, now), 0.25);
+        assert_eq!(verification_progress(bitcoin::Network::Bitcoin, now as u32 + 60, now), 1.0);
+
+        assert!(is_initial_block_download(genesis_time, now));
+        assert!(!is_initial_block_download(now as u32 - 600, now));
+    }
+
+    #[test]
+    fn test_greylist_lifts_once() {
+        let config = IbdGreylistConfig { enabled: true, resume_progress: 0.99, ..Default::default() };
+        let genesis_time = genesis_block(bitcoin::Network::Regtest).header.time;
+        let now = genesis_time as i64 + 1_000_000;
+        let mut greylist = IbdGreylist::new(&config, bitcoin::Network::Regtest);
+        assert_eq!(greylist.inbound_limit(), Some(2));
+        assert!(greylist.warning().unwrap().contains("loading chain tip"));
+
+        assert!(!greylist.tip_changed(genesis_time + 500_000, now));
+        assert!(!greylist.relay_transactions());
+        assert!(greylist.warning().unwrap().contains("50.00% verified"));
+
+        assert!(greylist.tip_changed(now as u32, now));
+        assert_eq!(greylist.inbound_limit(), None);
+        assert!(greylist.warning().is_none());
+
+        // A stalled tip afterwards does not bring it back
+        assert!(!greylist.tip_changed(genesis_time, now + 1_000_000));
+        assert!(greylist.relay_transactions());
+
+        let refusing = IbdGreylistConfig { enabled: true, refuse_inbound: true, ..Default::default() };
+        assert_eq!(IbdGreylist::new(&refusing, bitcoin::Network::Regtest).inbound_limit(), Some(0));
+        assert_eq!(IbdGreylist::new(&IbdGreylistConfig::default(), bitcoin::Network::Regtest).inbound_limit(), None);
+    }
+}
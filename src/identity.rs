@@ -0,0 +1,180 @@
+//! Node identity key
+//!
+//! Each node keeps a secp256k1 key in `<datadir>/node_identity.key`, created
+//! on first start and kept across restarts. Events carry its x-only public
+//! key and a BIP340 signature, and `/api/v1/identity` serves a signed
+//! node-info document, so a fleet can tell which node emitted what. Every
+//! signature is over the SHA256 of the compact JSON of the signed object,
+//! keys sorted, without its `signature` member.
+
+use std::io::Write;
+use std::path::Path;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::error::{IdentityError, IdentityResult};
+
+pub const IDENTITY_FILE: &str = "node_identity.key";
+
+/// 32 bytes from the OS generator, by way of two v4 UUIDs' random bits hashed together
+pub fn random_bytes() -> [u8; 32] {
+    let mut engine = sha256::Hash::engine();
+    for _ in 0..2 {
+        engine.input(uuid::Uuid::new_v4().as_bytes());
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Bytes a signature commits to: `value` as compact JSON without its `signature` member
+pub fn signing_payload(value: &impl Serialize) -> Vec<u8> {
+    let mut value = serde_json::to_value(value).expect("signed objects always serialize");
+    if let Value::Object(map) = &mut value {
+        map.remove("signature");
+    }
+    // serde_json's maps are ordered by key
+    serde_json::to_vec(&value).expect("JSON values always serialize")
+}
+
+/// Check a hex BIP340 signature by a hex x-only key over `payload`
+pub fn verify(pubkey: &str, signature: &str, payload: &[u8]) -> bool {
+    let (Ok(pubkey), Ok(signature)) = (pubkey.parse::<XOnlyPublicKey>(), signature.parse::<Signature>()) else {
+        return false;
+    };
+    let message = Message::from_digest(sha256::Hash::hash(payload).to_byte_array());
+    Secp256k1::verification_only().verify_schnorr(&signature, &message, &pubkey).is_ok()
+}
+
+/// Whether an event carries a valid signature by its `node_pubkey`
+pub fn verify_event(event: &crate::events::BitcoinEvent) -> bool {
+    match (&event.node_pubkey, &event.signature) {
+        (Some(pubkey), Some(signature)) => verify(pubkey, signature, &signing_payload(event)),
+        _ => false,
+    }
+}
+
+pub struct NodeIdentity {
+    keypair: Keypair,
+    pubkey: String,
+}
+
+impl NodeIdentity {
+    pub fn from_secret(secret: &[u8]) -> Result<Self, bitcoin::secp256k1::Error> {
+        let keypair = Keypair::from_seckey_slice(&Secp256k1::signing_only(), secret)?;
+        let pubkey = keypair.x_only_public_key().0.to_string();
+        Ok(Self { keypair, pubkey })
+    }
+
+    /// The key in `datadir`, generated and written there on first use
+    pub fn load_or_create(datadir: &Path) -> IdentityResult<Self> {
+        let path = datadir.join(IDENTITY_FILE);
+        let invalid = |reason: String| IdentityError::InvalidKey { path: path.display().to_string(), reason };
+        match std::fs::read_to_string(&path) {
+            Ok(hex) => {
+                let secret = Vec::<u8>::from_hex(hex.trim()).map_err(|e| invalid(e.to_string()))?;
+                Self::from_secret(&secret).map_err(|e| invalid(e.to_string()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // An out of range secret is astronomically unlikely, but cheap to retry
+                let identity = loop {
+                    if let Ok(identity) = Self::from_secret(&random_bytes()) {
+                        break identity;
+                    }
+                };
+                std::fs::create_dir_all(datadir)?;
+                let mut options = std::fs::OpenOptions::new();
+                // Never replace a key another process wrote in the meantime
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                let mut file = options.open(&path)?;
+                writeln!(file, "{}", identity.keypair.secret_bytes().to_lower_hex_string())?;
+                file.sync_all()?;
+                tracing::info!("Generated node identity {} in {}", identity.pubkey, path.display());
+                Ok(identity)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Hex x-only public key
+    pub fn pubkey(&self) -> &str {
+        &self.pubkey
+    }
+
+    /// Hex BIP340 signature over `payload`
+    pub fn sign(&self, payload: &[u8]) -> String {
+        let message = Message::from_digest(sha256::Hash::hash(payload).to_byte_array());
+        Secp256k1::signing_only().sign_schnorr_no_aux_rand(&message, &self.keypair).to_string()
+    }
+
+    /// The node-info document for `/api/v1/identity`, signed at `timestamp`
+    pub fn announcement(&self, config: &Config, timestamp: i64) -> NodeAnnouncement {
+        let services = crate::network::constants::NetworkConstants::for_network(&config.network).services;
+        let mut announcement = NodeAnnouncement {
+            pubkey: self.pubkey.clone(),
+            alias: config.identity.alias.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            network: config.network.as_str().to_string(),
+            services: format!("{:016x}", services),
+            contact: config.identity.contact.clone(),
+            timestamp,
+            signature: String::new(),
+        };
+        announcement.signature = self.sign(&signing_payload(&announcement));
+        announcement
+    }
+}
+
+/// What a node says about itself, signed with its identity key
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAnnouncement {
+    pub pubkey: String,
+    pub alias: Option<String>,
+    pub version: String,
+    pub network: String,
+    /// P2P service bits, as Core's `localservices`
+    pub services: String,
+    pub contact: Option<String>,
+    pub timestamp: i64,
+    pub signature: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_persists_and_signs() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = NodeIdentity::load_or_create(dir.path()).unwrap();
+        assert_eq!(NodeIdentity::load_or_create(dir.path()).unwrap().pubkey(), identity.pubkey());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join(IDENTITY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut config = Config::default_regtest();
+        config.identity.contact = Some("ops@example.com".to_string());
+        let announcement = identity.announcement(&config, 1_700_000_000);
+        assert_eq!(announcement.services, "0000000000000001");
+        assert!(verify(identity.pubkey(), &announcement.signature, &signing_payload(&announcement)));
+
+        // Any field changed after signing breaks the signature
+        let mut forged = announcement.clone();
+        forged.contact = Some("attacker@example.com".to_string());
+        assert!(!verify(identity.pubkey(), &forged.signature, &signing_payload(&forged)));
+        let other = NodeIdentity::from_secret(&[1; 32]).unwrap();
+        assert!(!verify(other.pubkey(), &announcement.signature, &signing_payload(&announcement)));
+
+        std::fs::write(dir.path().join(IDENTITY_FILE), "not hex\n").unwrap();
+        assert!(matches!(NodeIdentity::load_or_create(dir.path()), Err(IdentityError::InvalidKey { .. })));
+    }
+}
@@ -1,10 +1,48 @@
+pub mod amount;
+pub mod bip21;
+pub mod broadcast;
+pub mod cache;
+pub mod clock;
+pub mod cluster;
 pub mod config;
+#[cfg(feature = "console")]
+pub mod console;
 pub mod logging;
+pub mod maintenance;
+pub mod mempoolcapture;
 pub mod metrics;
+pub mod mining;
+pub mod policy;
 pub mod events;
+pub mod ibd;
+pub mod identity;
 pub mod api;
+pub mod analytics;
+pub mod partition;
+pub mod peerstats;
+pub mod ratelimit;
 pub mod rpc;
+pub mod scenario;
+pub mod script;
+pub mod secrets;
+pub mod seeds;
+pub mod settings;
+pub mod shutdown;
+pub mod silentpayments;
+pub mod snapshot;
+pub mod startup;
 pub mod storage;
+#[cfg(feature = "miner")]
+pub mod stratum;
+pub mod streams;
+pub mod sysmonitor;
+pub mod topology;
+pub mod ur;
+pub mod usage;
+#[cfg(feature = "wallet")]
+pub mod multisig;
+pub mod muhash;
+pub mod wallet;
 pub mod actors;
 pub mod error;
 pub mod network;
@@ -1,20 +1,30 @@
 use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
     fmt::{self, format::FmtSpan},
     layer::SubscriberExt,
     util::SubscriberInitExt,
     EnvFilter, Layer,
 };
-use tracing_opentelemetry::OpenTelemetryLayer;
-use opentelemetry::{global, trace::TraceError};
-use opentelemetry_jaeger::new_agent_pipeline;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use opentelemetry::{global, trace::TraceError, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self as sdktrace, Sampler};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io;
 
 use crate::config::{LoggingConfig, LogFormat, OpenTelemetryConfig};
 use crate::error::{NodeError, NodeResult};
 
-pub fn init(config: &LoggingConfig) -> NodeResult<()> {
-    let env_filter = EnvFilter::try_from_default_env()
+/// Where spans go when `metrics.otel.endpoint` is unset, a collector's OTLP/gRPC port
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+
+pub fn init(config: &LoggingConfig, otel: &OpenTelemetryConfig) -> NodeResult<()> {
+    // Each output filters on its own, so spans can reach the collector
+    // below the log level
+    let env_filter = || EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(&config.level));
 
     let mut layers = Vec::new();
@@ -29,6 +39,7 @@ pub fn init(config: &LoggingConfig) -> NodeResult<()> {
                 .with_target(true)
                 .with_thread_ids(true)
                 .with_thread_names(true)
+                .with_filter(env_filter())
                 .boxed();
             layers.push(console_layer);
         }
@@ -39,6 +50,7 @@ pub fn init(config: &LoggingConfig) -> NodeResult<()> {
                 .with_target(true)
                 .with_thread_ids(true)
                 .with_thread_names(true)
+                .with_filter(env_filter())
                 .boxed();
             layers.push(console_layer);
         }
@@ -47,6 +59,7 @@ pub fn init(config: &LoggingConfig) -> NodeResult<()> {
                 .compact()
                 .with_span_events(FmtSpan::CLOSE)
                 .with_target(true)
+                .with_filter(env_filter())
                 .boxed();
             layers.push(console_layer);
         }
@@ -73,14 +86,33 @@ pub fn init(config: &LoggingConfig) -> NodeResult<()> {
                 .with_target(true)
                 .with_thread_ids(true)
                 .with_thread_names(true)
+                .with_filter(env_filter())
                 .boxed();
 
             layers.push(file_layer);
         }
     }
 
+    // Trace export layer. Only this crate's spans are exported, down to the
+    // per-message debug spans; the exporter's own gRPC client would
+    // otherwise trace its exports.
+    let otel_error = if otel.enabled {
+        match otlp_tracer(otel) {
+            Ok(tracer) => {
+                let otel_layer = tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), LevelFilter::DEBUG))
+                    .boxed();
+                layers.push(otel_layer);
+                None
+            }
+            Err(e) => Some(e),
+        }
+    } else {
+        None
+    };
+
     let registry = tracing_subscriber::registry()
-        .with(env_filter)
         .with(layers);
 
     registry.init();
@@ -95,26 +127,60 @@ pub fn init(config: &LoggingConfig) -> NodeResult<()> {
         }
     }
 
+    match otel_error {
+        Some(e) => tracing::warn!("OpenTelemetry trace export unavailable: {}", e),
+        None if otel.enabled => tracing::info!(
+            "Exporting traces to {} as {} {} (sample ratio {})",
+            otlp_endpoint(otel), otel.service_name, otel.service_version, otel.sample_ratio
+        ),
+        None => {}
+    }
+
     Ok(())
 }
 
-pub fn init_opentelemetry(config: &OpenTelemetryConfig) -> Result<(), TraceError> {
-    if !config.enabled {
-        return Err(TraceError::Other("OpenTelemetry disabled".into()));
+fn otlp_endpoint(config: &OpenTelemetryConfig) -> &str {
+    config.endpoint.as_deref().filter(|endpoint| !endpoint.is_empty()).unwrap_or(DEFAULT_OTLP_ENDPOINT)
+}
+
+/// A tracer batching spans to the OTLP collector from a thread of its own,
+/// since the node's runtimes are single threaded
+fn otlp_tracer(config: &OpenTelemetryConfig) -> Result<sdktrace::Tracer, TraceError> {
+    if !(0.0..=1.0).contains(&config.sample_ratio) {
+        return Err(TraceError::Other(format!("sample_ratio {} is not between 0 and 1", config.sample_ratio).into()));
     }
+    global::set_text_map_propagator(TraceContextPropagator::new());
 
-    // Simplified OpenTelemetry initialization
-    tracing::info!("OpenTelemetry would be initialized here");
-    tracing::info!("Service name: {}", config.service_name);
-    tracing::info!("Service version: {}", config.service_version);
+    let resource = Resource::new(vec![
+        KeyValue::new("service.name", config.service_name.clone()),
+        KeyValue::new("service.version", config.service_version.clone()),
+    ]);
+    // Callers that sent a sampled trace context are always followed
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio)));
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(otlp_endpoint(config)))
+        .with_trace_config(sdktrace::config().with_resource(resource).with_sampler(sampler))
+        .install_batch(opentelemetry_sdk::runtime::TokioCurrentThread)
+}
 
-    if let Some(endpoint) = &config.endpoint {
-        tracing::info!("OTEL endpoint: {}", endpoint);
+/// Continue the trace a caller started: make `span` a child of the W3C
+/// `traceparent` (and `tracestate`) it sent. Without trace export this does nothing.
+pub fn set_remote_parent(span: &tracing::Span, traceparent: Option<&str>, tracestate: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let mut carrier = HashMap::new();
+    carrier.insert("traceparent".to_string(), traceparent.to_string());
+    if let Some(tracestate) = tracestate {
+        carrier.insert("tracestate".to_string(), tracestate.to_string());
     }
-
-    Ok(())
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    span.set_parent(context);
 }
 
+/// Flush spans still queued for the collector. Blocks until the exporter is
+/// done, so call it off the runtime threads.
 pub fn shutdown_opentelemetry() {
     global::shutdown_tracer_provider();
     tracing::info!("OpenTelemetry shutdown complete");
@@ -1,15 +1,62 @@
 use actix::prelude::*;
-use actix_web::{web, App, HttpServer};
-use clap::Parser;
+use actix_web::{middleware, web, App, HttpServer};
+use clap::{Parser, Subcommand};
 use tracing::{info, warn, error};
 
+// Conversions for every interface, not all of which the binary reaches
+#[allow(dead_code)]
+mod amount;
+mod bip21;
+mod broadcast;
+mod cache;
+mod clock;
+mod cluster;
 mod config;
+#[cfg(feature = "console")]
+mod console;
 mod logging;
+mod maintenance;
+mod mempoolcapture;
 mod metrics;
+// Block templates are only served by the miner feature, scenarios use a part of it
+#[cfg_attr(not(feature = "miner"), allow(dead_code))]
+mod mining;
+mod policy;
 mod events;
+mod ibd;
+// Signature verification is for event consumers using the library
+#[allow(dead_code)]
+mod identity;
+mod analytics;
 mod api;
+mod network;
+mod partition;
+mod peerstats;
+mod ratelimit;
 mod rpc;
+mod scenario;
+mod script;
+mod secrets;
+mod seeds;
+mod settings;
+mod shutdown;
+mod silentpayments;
+mod snapshot;
+mod startup;
 mod storage;
+#[cfg(feature = "miner")]
+mod stratum;
+mod streams;
+mod sysmonitor;
+mod topology;
+mod ur;
+mod usage;
+#[cfg(feature = "wallet")]
+mod multisig;
+mod muhash;
+// Without the wallet feature only the key and address helpers are used
+#[cfg_attr(not(feature = "wallet"), allow(dead_code))]
+mod wallet;
 mod actors;
 mod error;
 
@@ -31,10 +78,39 @@ struct Cli {
 
     #[arg(long)]
     generate_config: bool,
+
+    /// Print the effective config as JSON, credentials redacted, and exit
+    #[arg(long)]
+    print_config: bool,
+
+    /// Replace the database with a backup before starting, the newest one unless an id is given
+    #[arg(long, value_name = "BACKUP_ID")]
+    restore: Option<Option<u32>>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a regtest scenario script against the datadir and print the resulting chain state as JSON
+    Scenario {
+        script: String,
+
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Open an interactive JSON-RPC console to the running node
+    #[cfg(feature = "console")]
+    Console {
+        /// RPC server URL, the configured rpc host and port by default
+        #[arg(long)]
+        rpc_url: Option<String>,
+    },
 }
 
-#[actix_web::main]
-async fn main() -> Result<(), NodeError> {
+fn main() -> Result<(), NodeError> {
+    metrics::mark_process_start();
     let cli = Cli::parse();
 
     // Generate default config if requested
@@ -56,13 +132,66 @@ async fn main() -> Result<(), NodeError> {
     if let Some(datadir) = cli.datadir {
         config.datadir = datadir.into();
     }
+    // Overrides written by setconfig go on top of the file and the command line
+    let settings = settings::Settings::load(config)?;
+    let mut config = settings.config();
+    if config.storage.remote.enabled {
+        config = config.api_replica();
+    }
+
+    if cli.print_config {
+        println!("{:#}", config.redacted());
+        return Ok(());
+    }
+
+    // The console is a client of a running node and starts no actors
+    #[cfg(feature = "console")]
+    if let Some(Command::Console { rpc_url }) = cli.command {
+        return Ok(console::run(&config, rpc_url)?);
+    }
 
+    // The System arbiter's runtime needs the topology settings before it starts
+    let blocking_threads = config.runtime.blocking_threads;
+    actix_web::rt::System::with_tokio_rt(move || {
+        topology::tokio_runtime(blocking_threads, Vec::new()).expect("Failed to build the main runtime")
+    })
+    .block_on(run(config, settings, cli.command, cli.restore))
+}
+
+async fn run(
+    config: Config,
+    settings: settings::Settings,
+    command: Option<Command>,
+    restore: Option<Option<u32>>,
+) -> Result<(), NodeError> {
     // Initialize logging
-    logging::init(&config.logging)?;
+    logging::init(&config.logging, &config.metrics.otel)?;
+
+    if let Some(Command::Scenario { script, output }) = command {
+        return run_scenario(&config, &script, output).await;
+    }
 
     info!("Starting BitKnotsRS node");
     info!("Network: {:?}", config.network);
     info!("Data directory: {:?}", config.datadir);
+    if config.storage.remote.enabled {
+        info!("API replica of {}: P2P, stratum, compaction, backups and analytics scans are off",
+              config.storage.remote.url);
+    }
+    let overrides = settings.overrides();
+    if !overrides.is_empty() {
+        let keys: Vec<&str> = overrides.keys().map(String::as_str).collect();
+        info!("Overriding {} from {}", keys.join(", "), settings.path().display());
+    }
+
+    // Answer /startup on the API address until the API server binds it
+    let startup_server = match startup::StartupServer::start((config.api.host.as_str(), config.api.port)) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            warn!("Startup status endpoint unavailable: {}", e);
+            None
+        }
+    };
 
     // Initialize metrics
     let _metrics_handle = if config.metrics.enabled {
@@ -74,39 +203,285 @@ async fn main() -> Result<(), NodeError> {
     // Initialize event publishers
     let event_manager = events::EventManager::new(&config).await?;
 
-    // Start actor system
-    let system = System::new();
+    // Join the cluster, if configured, before the actors that defer to its leader
+    let pubkey = event_manager.identity().map(|identity| identity.pubkey().to_string()).unwrap_or_default();
+    let cluster = cluster::Cluster::new(&config, &pubkey)?;
+    cluster.start();
+
+    // Actors run on the System arbiter unless the topology spreads them out
+    let mut topology = topology::Topology::new(&config.runtime);
+    use topology::Placement::{Validation, Worker};
+
+    // Restore before the storage actor opens the database
+    if let Some(backup_id) = restore {
+        let backup = storage::Storage::restore_from_backup(&config.storage, backup_id)?;
+        info!("Restored backup {} taken at {}", backup.id, backup.timestamp);
+    }
 
     // Initialize storage
-    let storage_actor = actors::storage::StorageActor::new(&config).start();
+    let storage_actor = topology.start(Validation, actors::storage::StorageActor::new(&config));
+    startup::enter(startup::StartupStage::StartingActors);
+    // A replica reads the full node's journal rather than keeping its own
+    if !config.storage.remote.enabled {
+        event_manager.attach_journal(storage_actor.clone()).await?;
+    }
 
     // Initialize other core actors
-    let _network_actor = actors::network::NetworkActor::new(&config, storage_actor.clone()).start();
-    let _mempool_actor = actors::mempool::MempoolActor::new(&config, storage_actor.clone()).start();
-    let _chain_actor = actors::chain::ChainActor::new(&config, storage_actor.clone()).start();
+    let network_actor = topology.start(
+        Worker,
+        actors::network::NetworkActor::new(&config, storage_actor.clone(), event_manager.clone()),
+    );
+    // Fetches blocks the address index backfill needs and the node does not store
+    let _index_manager = (config.storage.address_index && !config.storage.remote.enabled).then(|| {
+        topology.start(Worker, actors::index::IndexManager::new(&config, storage_actor.clone(), network_actor.clone()))
+    });
+    let mempool_deltas = actors::mempool::MempoolDeltaFeed::new(actors::mempool::MEMPOOL_DELTA_BUFFER);
+    let watch_actor = topology.start(Worker, actors::watch::WatchActor::new(&config, event_manager.clone()));
+    #[cfg(feature = "zmq")]
+    let zmq_notifier = topology.start(Worker, actors::zmq::ZmqNotifier::new(&config)?);
+    let mut mempool_listeners = vec![watch_actor.clone().recipient()];
+    #[cfg(feature = "zmq")]
+    mempool_listeners.push(zmq_notifier.clone().recipient());
+    let mempool_actor = topology.start(Validation, actors::mempool::MempoolActor::new(
+        &config,
+        storage_actor.clone(),
+        mempool_deltas.clone(),
+        mempool_listeners,
+        event_manager.clone(),
+    ));
+    #[cfg(feature = "wallet")]
+    let wallet_actor = topology.start(
+        Worker,
+        actors::wallet::WalletActor::new(&config, storage_actor.clone(), mempool_actor.clone()),
+    );
+    let mut chain_listeners = vec![watch_actor.clone().recipient()];
+    #[cfg(feature = "wallet")]
+    chain_listeners.push(wallet_actor.clone().recipient());
+    #[cfg(feature = "zmq")]
+    chain_listeners.push(zmq_notifier.recipient());
+    chain_listeners.push(network_actor.clone().recipient());
+    let chain = actors::chain::ChainActor::new(
+        &config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        chain_listeners,
+        event_manager.clone(),
+    );
+    let tip_feed = chain.tip_feed();
+    let chain_actor = topology.start(Validation, chain);
+    let analytics_actor = topology.start(Worker, actors::analytics::AnalyticsActor::new(
+        &config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        event_manager.clone(),
+        cluster.clone(),
+    ));
+    #[cfg(feature = "miner")]
+    let stratum_actor = topology.start(Worker, actors::stratum::StratumActor::new(
+        &config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        chain_actor.clone(),
+    ));
+
+    // Kept for shutdown; the HTTP server closure takes the originals
+    #[cfg(feature = "miner")]
+    let shutdown_stratum_actor = stratum_actor.clone();
+    let shutdown_network_actor = network_actor.clone();
+    let shutdown_mempool_actor = mempool_actor.clone();
+    let shutdown_chain_actor = chain_actor.clone();
+    let shutdown_storage_actor = storage_actor.clone();
 
     // Start HTTP API server
+    let rpc_actors = rpc::RpcActors {
+        storage: storage_actor.clone(),
+        mempool: mempool_actor.clone(),
+        network: network_actor.clone(),
+        chain: chain_actor.clone(),
+        #[cfg(feature = "wallet")]
+        wallet: wallet_actor.clone(),
+    };
+    // Built ahead of the API server, which serves the WebSocket bridge to it
+    let rpc_service = if config.rpc.enabled {
+        Some(rpc::RpcService::new(&config, settings, event_manager.clone(), rpc_actors)?)
+    } else {
+        None
+    };
+    let api_rpc_service = rpc_service.clone();
     let config_clone = config.clone();
+    let api_event_manager = event_manager.clone();
+    let api_chain_actor = chain_actor.clone();
+    let response_cache = api::ResponseCache::new(config.caches.response_max_bytes);
+    let api_cluster = cluster.clone();
+    let usage_tracker = usage::UsageTracker::new(&config.api.usage);
+    if config.api.usage.slo_events {
+        actix::spawn(usage_tracker.clone().run_slo_checks(event_manager.clone(), config.clone()));
+    }
+    if config.metrics.system.interval_secs > 0 {
+        actix::spawn(sysmonitor::SystemMonitor::new(&config).run(event_manager.clone(), config.clone()));
+    }
     let api_server = HttpServer::new(move || {
-        App::new()
+        let usage = usage_tracker.clone();
+        let app = App::new()
+            .wrap(middleware::Compress::default())
+            // Outermost, so the recorded latency includes compression
+            .wrap_fn(move |req, srv| {
+                use actix_web::dev::Service;
+                let usage = usage.clone();
+                let started = std::time::Instant::now();
+                let method = req.method().to_string();
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    let (route, status) = match &response {
+                        Ok(response) => (response.request().match_pattern(), response.status()),
+                        Err(e) => (None, e.as_response_error().status_code()),
+                    };
+                    let route = route.unwrap_or_else(|| usage::UNMATCHED.to_string());
+                    usage.record(format!("{} {}", method, route), status.as_u16(), started.elapsed());
+                    response
+                }
+            })
+            .app_data(web::Data::new(usage_tracker.clone()))
             .app_data(web::Data::new(config_clone.clone()))
-            .app_data(web::Data::new(event_manager.clone()))
-            .route("/health", web::get().to(api::health))
+            .app_data(web::Data::new(api_event_manager.clone()))
+            .app_data(web::Data::new(storage_actor.clone()))
+            .app_data(web::Data::new(mempool_actor.clone()))
+            .app_data(web::Data::new(network_actor.clone()))
+            .app_data(web::Data::new(watch_actor.clone()))
+            .app_data(web::Data::new(analytics_actor.clone()))
+            .app_data(web::Data::new(api_chain_actor.clone()))
+            .app_data(web::Data::new(mempool_deltas.clone()))
+            .app_data(web::Data::new(tip_feed.clone()))
+            .app_data(web::Data::new(response_cache.clone()))
+            .app_data(web::Data::new(api_cluster.clone()));
+        #[cfg(feature = "miner")]
+        let app = app.app_data(web::Data::new(stratum_actor.clone()));
+        #[cfg(feature = "wallet")]
+        let app = app.app_data(web::Data::new(wallet_actor.clone()));
+        let app = match &api_rpc_service {
+            Some(rpc_service) => app.app_data(web::Data::new(rpc_service.clone())),
+            None => app,
+        };
+        app.route("/health", web::get().to(api::health))
+            .route("/startup", web::get().to(api::startup))
             .service(web::scope("/api/v1").configure(api::configure))
     })
-    .bind(format!("{}:{}", config.api.host, config.api.port))?;
+    // Bounds how long open requests and WebSocket clients hold up the API stage
+    .shutdown_timeout(config.shutdown.api_secs)
+    // Signals start the node's own shutdown below rather than just the server's
+    .disable_signals();
+    if let Some(startup_server) = startup_server {
+        startup_server.stop();
+    }
+    let api_server = api_server.bind(format!("{}:{}", config.api.host, config.api.port))?;
 
     info!("API server starting on {}:{}", config.api.host, config.api.port);
 
     // Start RPC server
-    let _rpc_server = if config.rpc.enabled {
-        Some(rpc::start_server(&config).await?)
-    } else {
-        None
+    let rpc_server = match rpc_service {
+        Some(rpc_service) => Some(rpc::start_server(&config, rpc_service).await?),
+        None => None,
     };
+    startup::enter(startup::StartupStage::Ready);
 
-    // Run the server
-    api_server.run().await?;
+    // Run until SIGINT or SIGTERM, or until the server stops by itself
+    let api_server = api_server.run();
+    let api_handle = api_server.handle();
+    let reason = tokio::select! {
+        signal = shutdown::signal() => signal,
+        result = api_server => {
+            result?;
+            "API server stopped"
+        }
+    };
 
+    info!("Shutting down: {}", reason);
+    actix::spawn(async {
+        let signal = shutdown::signal().await;
+        warn!("{} during shutdown, exiting now", signal);
+        std::process::exit(1);
+    });
+    let stopping = events::BitcoinEventType::NodeStopping {
+        reason: reason.to_string(),
+        uptime_seconds: metrics::process_metrics().uptime_seconds,
+    };
+    // Delivered by the events stage, which runs last
+    if let Err(e) = event_manager.publish(stopping, config.network.as_str(), &config.events.k8s.node_name).await {
+        warn!("Failed to publish NodeStopping: {}", e);
+    }
+    // Hand leadership over now rather than when the lease runs out
+    cluster.resign().await;
+    let mut plan = shutdown::ShutdownPlan::new(&config.shutdown);
+    let abort_handle = api_handle.clone();
+    plan.add(shutdown::Component::Api, async move { api_handle.stop(true).await })
+        .on_abort(shutdown::Component::Api, move || {
+            actix::spawn(abort_handle.stop(false));
+        });
+    if let Some(rpc_server) = rpc_server {
+        plan.add(shutdown::Component::Rpc, rpc_server.close());
+    }
+    #[cfg(feature = "miner")]
+    plan.add(shutdown::Component::Miner, shutdown::stop_actor(shutdown_stratum_actor));
+    plan.add(shutdown::Component::Network, shutdown::stop_actor(shutdown_network_actor))
+        .add(shutdown::Component::Mempool, shutdown::stop_actor(shutdown_mempool_actor))
+        .add(shutdown::Component::Chain, shutdown::stop_actor(shutdown_chain_actor))
+        .add(shutdown::Component::Storage, shutdown::stop_actor(shutdown_storage_actor));
+    let events = event_manager.clone();
+    plan.add(shutdown::Component::Events, async move { events.drain().await })
+        .on_abort(shutdown::Component::Events, move || event_manager.abort_deliveries());
+
+    let aborted: Vec<&str> = plan.run().await.into_iter()
+        .filter(|(_, outcome)| *outcome == shutdown::StageOutcome::Aborted)
+        .map(|(component, _)| component.as_str())
+        .collect();
+    topology.stop();
+    if config.metrics.otel.enabled {
+        // Flushing blocks until the collector has the last spans
+        let _ = tokio::task::spawn_blocking(logging::shutdown_opentelemetry).await;
+    }
+    if aborted.is_empty() {
+        info!("Shutdown complete");
+    } else {
+        warn!("Shutdown complete, forced to abort: {}", aborted.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Drive an in-process chain through a scenario script, then exit
+async fn run_scenario(config: &Config, script: &str, output: Option<String>) -> Result<(), NodeError> {
+    let scenario = scenario::Scenario::parse(&std::fs::read_to_string(script)?)?;
+    let event_manager = events::EventManager::new(config).await?;
+
+    let storage_actor = actors::storage::StorageActor::new(config).start();
+    let mempool_actor = actors::mempool::MempoolActor::new(
+        config,
+        storage_actor.clone(),
+        actors::mempool::MempoolDeltaFeed::new(actors::mempool::MEMPOOL_DELTA_BUFFER),
+        Vec::new(),
+        event_manager.clone(),
+    ).start();
+    let chain_actor = actors::chain::ChainActor::new(
+        config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        Vec::new(),
+        event_manager,
+    ).start();
+
+    info!("Running scenario {} with {} steps", script, scenario.steps.len());
+    let mut runner = scenario::ScenarioRunner::new(
+        config.network.to_bitcoin_network(),
+        storage_actor,
+        mempool_actor,
+        chain_actor,
+    )?;
+    let report = runner.run(&scenario).await?;
+    let json = serde_json::to_string_pretty(&report).map_err(anyhow::Error::from)?;
+    match output {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{}", json),
+    }
     Ok(())
 }
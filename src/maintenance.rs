@@ -0,0 +1,162 @@
+//! Storage maintenance scheduling
+//!
+//! Manual compaction rewrites a whole column family, which on a busy node
+//! takes disk bandwidth from block validation. The scheduler only lets it
+//! start inside the configured off-peak windows, hands out one column family
+//! per check and leaves each alone for `min_interval_hours` afterwards.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::config::CompactionConfig;
+use crate::error::{ConfigError, ConfigResult};
+use crate::storage::ALL_COLUMN_FAMILIES;
+
+/// A daily UTC time range; ranges past midnight wrap around
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    start_minute: u32,
+    end_minute: u32,
+}
+
+impl MaintenanceWindow {
+    /// Parse `HH:MM-HH:MM`
+    pub fn parse(spec: &str) -> ConfigResult<Self> {
+        let invalid = |reason: &str| ConfigError::InvalidValue {
+            field: "storage.compaction.windows".to_string(),
+            value: format!("{}: {}", spec, reason),
+        };
+        let (start, end) = spec.split_once('-').ok_or_else(|| invalid("expected HH:MM-HH:MM"))?;
+        let window = Self {
+            start_minute: parse_minute(start.trim()).ok_or_else(|| invalid("bad start time"))?,
+            end_minute: parse_minute(end.trim()).ok_or_else(|| invalid("bad end time"))?,
+        };
+        if window.start_minute == window.end_minute {
+            return Err(invalid("window is empty"));
+        }
+        Ok(window)
+    }
+
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        let minute = time.hour() * 60 + time.minute();
+        if self.start_minute < self.end_minute {
+            (self.start_minute..self.end_minute).contains(&minute)
+        } else {
+            minute >= self.start_minute || minute < self.end_minute
+        }
+    }
+}
+
+fn parse_minute(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+pub struct CompactionScheduler {
+    windows: Vec<MaintenanceWindow>,
+    column_families: Vec<String>,
+    min_interval: chrono::Duration,
+    /// When each column family was last compacted since startup
+    last_compacted: HashMap<String, DateTime<Utc>>,
+}
+
+impl CompactionScheduler {
+    pub fn new(config: &CompactionConfig) -> ConfigResult<Self> {
+        let windows = config.windows.iter().map(|spec| MaintenanceWindow::parse(spec)).collect::<ConfigResult<_>>()?;
+        if let Some(unknown) = config.column_families.iter().find(|cf| !ALL_COLUMN_FAMILIES.contains(&cf.as_str())) {
+            return Err(ConfigError::InvalidValue {
+                field: "storage.compaction.column_families".to_string(),
+                value: format!("unknown column family {}", unknown),
+            });
+        }
+        let column_families = if config.column_families.is_empty() {
+            ALL_COLUMN_FAMILIES.iter().map(|cf| cf.to_string()).collect()
+        } else {
+            config.column_families.clone()
+        };
+
+        Ok(Self {
+            windows,
+            column_families,
+            min_interval: chrono::Duration::hours(config.min_interval_hours as i64),
+            last_compacted: HashMap::new(),
+        })
+    }
+
+    pub fn in_window(&self, now: DateTime<Utc>) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|window| window.contains(now))
+    }
+
+    /// The column family to compact now, if any: inside a window, the one
+    /// compacted longest ago among those past `min_interval`
+    pub fn next_due(&self, now: DateTime<Utc>) -> Option<String> {
+        if !self.in_window(now) {
+            return None;
+        }
+        self.column_families.iter()
+            .filter(|cf| self.last_compacted.get(*cf).is_none_or(|last| now - *last >= self.min_interval))
+            .min_by_key(|cf| self.last_compacted.get(*cf).copied())
+            .cloned()
+    }
+
+    pub fn record(&mut self, column_family: &str, now: DateTime<Utc>) {
+        self.last_compacted.insert(column_family.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_windows() {
+        let night = MaintenanceWindow::parse("23:30-04:00").unwrap();
+        assert!(night.contains(at(23, 45)));
+        assert!(night.contains(at(3, 59)));
+        assert!(!night.contains(at(4, 0)));
+        assert!(!night.contains(at(12, 0)));
+
+        let morning = MaintenanceWindow::parse("02:00 - 05:00").unwrap();
+        assert!(morning.contains(at(2, 0)));
+        assert!(!morning.contains(at(5, 0)));
+
+        assert!(MaintenanceWindow::parse("02:00").is_err());
+        assert!(MaintenanceWindow::parse("25:00-03:00").is_err());
+        assert!(MaintenanceWindow::parse("03:00-03:00").is_err());
+    }
+
+    #[test]
+    fn test_scheduler_rotates_column_families() {
+        let config = CompactionConfig {
+            enabled: true,
+            windows: vec!["01:00-05:00".to_string()],
+            column_families: vec!["utxos".to_string(), "blocks".to_string()],
+            ..Default::default()
+        };
+        let mut scheduler = CompactionScheduler::new(&config).unwrap();
+        assert_eq!(scheduler.next_due(at(12, 0)), None);
+
+        assert_eq!(scheduler.next_due(at(1, 0)).as_deref(), Some("utxos"));
+        scheduler.record("utxos", at(1, 0));
+        assert_eq!(scheduler.next_due(at(1, 10)).as_deref(), Some("blocks"));
+        scheduler.record("blocks", at(1, 10));
+        assert_eq!(scheduler.next_due(at(1, 20)), None);
+
+        // A day later the one compacted first is due again
+        let next_night = at(1, 5) + chrono::Duration::days(1);
+        assert_eq!(scheduler.next_due(next_night).as_deref(), Some("utxos"));
+
+        let unknown = CompactionConfig { column_families: vec!["nope".to_string()], ..Default::default() };
+        assert!(CompactionScheduler::new(&unknown).is_err());
+        let anytime = CompactionScheduler::new(&CompactionConfig::default()).unwrap();
+        assert!(anytime.in_window(at(12, 0)));
+        assert_eq!(anytime.next_due(at(12, 0)).as_deref(), Some(ALL_COLUMN_FAMILIES[0]));
+    }
+}
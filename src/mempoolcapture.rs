@@ -0,0 +1,264 @@
+//! Periodic compressed mempool snapshots for research
+//!
+//! With `analytics.mempool_snapshot_interval_secs` set, the analytics actor
+//! writes what the mempool holds (txid, fee, vsize, fee rate and when it was
+//! first seen) to `<datadir>/mempool_snapshots/<unix time>.json.gz` and drops
+//! snapshots older than `analytics.mempool_snapshot_retention_hours`. The REST
+//! API lists them, serves the compressed files as they are and diffs any two,
+//! so propagation and fee dynamics can be studied from this node's view.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::actors::mempool::{MempoolSnapshot, MempoolSnapshotEntry};
+use crate::amount::Satoshi;
+use crate::error::{SnapshotError, SnapshotResult};
+
+pub const MEMPOOL_SNAPSHOT_VERSION: u32 = 1;
+
+const EXTENSION: &str = ".json.gz";
+
+/// Where mempool snapshots are kept under the data directory
+pub fn capture_dir(datadir: &Path) -> PathBuf {
+    datadir.join("mempool_snapshots")
+}
+
+fn capture_path(dir: &Path, id: i64) -> PathBuf {
+    dir.join(format!("{}{}", id, EXTENSION))
+}
+
+/// The mempool as this node saw it at `captured_at`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MempoolCapture {
+    pub version: u32,
+    pub network: String,
+    pub captured_at: DateTime<Utc>,
+    pub tip_height: Option<u64>,
+    /// Mempool delta sequence the entries are current as of
+    pub sequence: u64,
+    pub entries: Vec<MempoolSnapshotEntry>,
+}
+
+impl MempoolCapture {
+    pub fn new(network: &str, captured_at: DateTime<Utc>, tip_height: Option<u64>, snapshot: MempoolSnapshot) -> Self {
+        let mut entries = snapshot.entries;
+        entries.sort_by(|a, b| a.txid.cmp(&b.txid));
+        Self {
+            version: MEMPOOL_SNAPSHOT_VERSION,
+            network: network.to_string(),
+            captured_at,
+            tip_height,
+            sequence: snapshot.sequence,
+            entries,
+        }
+    }
+
+    /// Snapshots are named by the second they were taken
+    pub fn id(&self) -> i64 {
+        self.captured_at.timestamp()
+    }
+
+    pub fn save(&self, dir: &Path) -> SnapshotResult<MempoolCaptureSummary> {
+        std::fs::create_dir_all(dir)?;
+        let path = capture_path(dir, self.id());
+        let data = serde_json::to_vec(self).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data)?;
+        let compressed = encoder.finish()?;
+        // Write then rename so a listing never shows a truncated file
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, &compressed)?;
+        std::fs::rename(partial, &path)?;
+        Ok(MempoolCaptureSummary { id: self.id(), captured_at: self.captured_at, bytes: compressed.len() as u64 })
+    }
+
+    pub fn load(dir: &Path, id: i64) -> SnapshotResult<Self> {
+        let compressed = read_compressed(dir, id)?;
+        let mut data = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut data)
+            .map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+        let capture: Self = serde_json::from_slice(&data).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+        if capture.version != MEMPOOL_SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(capture.version));
+        }
+        Ok(capture)
+    }
+}
+
+/// The gzip file of a snapshot as it is stored
+pub fn read_compressed(dir: &Path, id: i64) -> SnapshotResult<Vec<u8>> {
+    std::fs::read(capture_path(dir, id)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => SnapshotError::NotFound(id.to_string()),
+        _ => SnapshotError::Io(e),
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MempoolCaptureSummary {
+    pub id: i64,
+    pub captured_at: DateTime<Utc>,
+    /// Compressed size on disk
+    pub bytes: u64,
+}
+
+/// Saved snapshots, oldest first
+pub fn list_captures(dir: &Path) -> SnapshotResult<Vec<MempoolCaptureSummary>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut captures = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name.to_str()
+            .and_then(|name| name.strip_suffix(EXTENSION))
+            .and_then(|id| id.parse::<i64>().ok()) else {
+            continue;
+        };
+        let Some(captured_at) = DateTime::from_timestamp(id, 0) else {
+            continue;
+        };
+        captures.push(MempoolCaptureSummary { id, captured_at, bytes: entry.metadata()?.len() });
+    }
+    captures.sort_by_key(|capture| capture.id);
+    Ok(captures)
+}
+
+/// Delete snapshots taken more than `retention` before `now`, returning how many went
+pub fn prune_captures(dir: &Path, retention: Duration, now: DateTime<Utc>) -> SnapshotResult<usize> {
+    let cutoff = now.timestamp() - retention.as_secs() as i64;
+    let mut removed = 0;
+    for capture in list_captures(dir)?.into_iter().take_while(|capture| capture.id < cutoff) {
+        std::fs::remove_file(capture_path(dir, capture.id))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Count, size and fees of a set of mempool entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EntryTotals {
+    pub count: usize,
+    pub vsize: u64,
+    pub fee: Satoshi,
+}
+
+impl EntryTotals {
+    fn of<'a>(entries: impl IntoIterator<Item = &'a MempoolSnapshotEntry>) -> Self {
+        entries.into_iter().fold(Self::default(), |totals, entry| Self {
+            count: totals.count + 1,
+            vsize: totals.vsize + entry.vsize,
+            fee: totals.fee + entry.fee,
+        })
+    }
+}
+
+/// What changed in the mempool between two snapshots. Removed transactions
+/// were mined, replaced, evicted or expired; the snapshots alone don't say which.
+#[derive(Debug, Clone, Serialize)]
+pub struct MempoolCaptureDiff {
+    pub from: i64,
+    pub to: i64,
+    pub elapsed_secs: i64,
+    pub added: Vec<MempoolSnapshotEntry>,
+    pub removed: Vec<MempoolSnapshotEntry>,
+    pub added_totals: EntryTotals,
+    pub removed_totals: EntryTotals,
+    /// Transactions in both snapshots
+    pub unchanged: usize,
+}
+
+pub fn diff_captures(from: &MempoolCapture, to: &MempoolCapture) -> MempoolCaptureDiff {
+    let before: HashMap<&str, &MempoolSnapshotEntry> =
+        from.entries.iter().map(|entry| (entry.txid.as_str(), entry)).collect();
+    let after: HashMap<&str, &MempoolSnapshotEntry> =
+        to.entries.iter().map(|entry| (entry.txid.as_str(), entry)).collect();
+
+    let added: Vec<MempoolSnapshotEntry> = to.entries.iter()
+        .filter(|entry| !before.contains_key(entry.txid.as_str()))
+        .cloned()
+        .collect();
+    let removed: Vec<MempoolSnapshotEntry> = from.entries.iter()
+        .filter(|entry| !after.contains_key(entry.txid.as_str()))
+        .cloned()
+        .collect();
+
+    MempoolCaptureDiff {
+        from: from.id(),
+        to: to.id(),
+        elapsed_secs: to.id() - from.id(),
+        added_totals: EntryTotals::of(&added),
+        removed_totals: EntryTotals::of(&removed),
+        unchanged: to.entries.len() - added.len(),
+        added,
+        removed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::FeeRate;
+
+    fn entry(txid: &str, fee: u64, vsize: u64) -> MempoolSnapshotEntry {
+        MempoolSnapshotEntry {
+            txid: txid.to_string(),
+            fee: Satoshi(fee),
+            vsize,
+            fee_rate: FeeRate::from_fee(Satoshi(fee), vsize),
+            time: 1_700_000_000,
+        }
+    }
+
+    fn capture(at: i64, entries: Vec<MempoolSnapshotEntry>) -> MempoolCapture {
+        let snapshot = MempoolSnapshot { sequence: at as u64, entries };
+        MempoolCapture::new("regtest", DateTime::from_timestamp(at, 0).unwrap(), Some(100), snapshot)
+    }
+
+    #[test]
+    fn test_save_list_and_prune() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = capture_dir(temp_dir.path());
+        assert!(list_captures(&dir).unwrap().is_empty());
+
+        for at in [1_700_000_000, 1_700_000_600, 1_700_003_600] {
+            capture(at, vec![entry("aa", 1000, 200)]).save(&dir).unwrap();
+        }
+        let ids: Vec<i64> = list_captures(&dir).unwrap().iter().map(|capture| capture.id).collect();
+        assert_eq!(ids, vec![1_700_000_000, 1_700_000_600, 1_700_003_600]);
+
+        let loaded = MempoolCapture::load(&dir, 1_700_000_600).unwrap();
+        assert_eq!(loaded.entries[0].fee_rate.to_sat_per_vb(), 5.0);
+        assert_eq!(read_compressed(&dir, 1_700_000_600).unwrap()[..2], [0x1f, 0x8b]);
+        assert!(matches!(MempoolCapture::load(&dir, 1), Err(SnapshotError::NotFound(_))));
+
+        // Keep the last hour as of the newest snapshot
+        let now = DateTime::from_timestamp(1_700_003_600, 0).unwrap();
+        assert_eq!(prune_captures(&dir, Duration::from_secs(3000), now).unwrap(), 1);
+        assert_eq!(list_captures(&dir).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_diff_captures() {
+        let from = capture(1_700_000_000, vec![entry("aa", 1000, 200), entry("bb", 500, 100)]);
+        let to = capture(1_700_000_600, vec![entry("bb", 500, 100), entry("cc", 3000, 150), entry("dd", 200, 100)]);
+
+        let diff = diff_captures(&from, &to);
+        assert_eq!(diff.elapsed_secs, 600);
+        assert_eq!(diff.added.iter().map(|entry| entry.txid.as_str()).collect::<Vec<_>>(), vec!["cc", "dd"]);
+        assert_eq!(diff.added_totals, EntryTotals { count: 2, vsize: 250, fee: Satoshi(3200) });
+        assert_eq!(diff.removed_totals, EntryTotals { count: 1, vsize: 200, fee: Satoshi(1000) });
+        assert_eq!(diff.removed[0].txid, "aa");
+        assert_eq!(diff.unchanged, 1);
+    }
+}
@@ -1,10 +1,15 @@
 use metrics::{counter, gauge, histogram};
 use metrics_exporter_prometheus::PrometheusBuilder;
-use std::time::Duration;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
 use actix_web::{web, App, HttpServer, HttpResponse, Result as ActixResult};
 use tracing::{info, error};
 
+use crate::cache::CacheStats;
+use crate::peerstats::PeerBreakdown;
+use crate::sysmonitor::SystemStats;
 use crate::config::MetricsConfig;
 use crate::error::{MetricsError, MetricsResult};
 
@@ -93,6 +98,13 @@ pub fn record_mempool_stats(tx_count: u64, total_size: u64) {
     gauge!("bitcoin_mempool_bytes").set(total_size as f64);
 }
 
+pub fn record_cache_stats(stats: &CacheStats) {
+    gauge!("bitcoin_cache_entries", "cache" => stats.name).set(stats.entries as f64);
+    gauge!("bitcoin_cache_bytes", "cache" => stats.name).set(stats.bytes as f64);
+    gauge!("bitcoin_cache_max_bytes", "cache" => stats.name).set(stats.max_bytes as f64);
+    counter!("bitcoin_cache_evictions_total", "cache" => stats.name).absolute(stats.evictions);
+}
+
 pub fn record_peer_connected() {
     counter!("bitcoin_peer_connections_total").increment(1);
     // Note: peer count should be updated separately
@@ -102,6 +114,52 @@ pub fn record_peer_disconnected() {
     counter!("bitcoin_peer_disconnections_total").increment(1);
 }
 
+/// Connected peers by user agent family, service bit and protocol version;
+/// labels present in `previous` but gone from `current` are set to zero
+pub fn record_peer_composition(current: &PeerBreakdown, previous: &PeerBreakdown) {
+    let series = [
+        ("bitcoin_peers_by_user_agent", "user_agent", &current.user_agents, &previous.user_agents),
+        ("bitcoin_peers_by_service", "service", &current.services, &previous.services),
+        ("bitcoin_peers_by_protocol_version", "version", &current.protocol_versions, &previous.protocol_versions),
+    ];
+    for (name, label, current, previous) in series {
+        for stale in previous.keys().filter(|key| !current.contains_key(*key)) {
+            gauge!(name, label => stale.clone()).set(0.0);
+        }
+        for (value, count) in current {
+            gauge!(name, label => value.clone()).set(*count as f64);
+        }
+    }
+}
+
+pub fn record_peer_message_throttled(class: &'static str) {
+    counter!("bitcoin_p2p_messages_throttled_total", "class" => class).increment(1);
+}
+
+pub fn record_peer_rate_limit_disconnect() {
+    counter!("bitcoin_p2p_rate_limit_disconnects_total").increment(1);
+}
+
+pub fn record_protocol_violation(violation: &'static str, tolerated: bool) {
+    let handling = if tolerated { "tolerated" } else { "disconnected" };
+    counter!("bitcoin_p2p_protocol_violations_total", "violation" => violation, "handling" => handling).increment(1);
+}
+
+#[cfg(feature = "miner")]
+pub fn record_stratum_share(result: &'static str) {
+    counter!("bitcoin_stratum_shares_total", "result" => result).increment(1);
+}
+
+#[cfg(feature = "miner")]
+pub fn record_stratum_block_found() {
+    counter!("bitcoin_stratum_blocks_found_total").increment(1);
+}
+
+#[cfg(feature = "miner")]
+pub fn record_stratum_sessions(count: usize) {
+    gauge!("bitcoin_stratum_sessions").set(count as f64);
+}
+
 pub fn record_peer_count(count: u64) {
     gauge!("bitcoin_peers_connected").set(count as f64);
 }
@@ -134,13 +192,94 @@ pub fn record_rpc_request(method: &str, duration: Duration, success: bool) {
     }
 }
 
+/// One event handed to a publisher, with how long the publisher took
+pub fn record_event_delivery(publisher: &str, success: bool, duration: Duration) {
+    let result = if success { "delivered" } else { "failed" };
+    counter!("bitcoin_event_deliveries_total", "publisher" => publisher.to_string(), "result" => result).increment(1);
+    histogram!("bitcoin_event_delivery_duration_seconds", "publisher" => publisher.to_string())
+        .record(duration.as_secs_f64());
+}
+
+pub fn record_event_retry(publisher: &str) {
+    counter!("bitcoin_event_delivery_retries_total", "publisher" => publisher.to_string()).increment(1);
+}
+
+pub fn record_event_short_circuited(publisher: &str) {
+    counter!("bitcoin_event_deliveries_total", "publisher" => publisher.to_string(), "result" => "circuit_open").increment(1);
+}
+
+pub fn record_mailbox_enqueued(actor: &'static str) {
+    gauge!("bitcoin_actor_mailbox_depth", "actor" => actor).increment(1.0);
+}
+
+pub fn record_mailbox_dequeued(actor: &'static str) {
+    gauge!("bitcoin_actor_mailbox_depth", "actor" => actor).decrement(1.0);
+}
+
+/// How long a message waited in an actor's mailbox and how long its handler ran
+pub fn record_actor_message(actor: &'static str, message: &'static str, waited: Duration, handled: Duration) {
+    histogram!("bitcoin_actor_mailbox_wait_seconds", "actor" => actor, "message" => message).record(waited.as_secs_f64());
+    histogram!("bitcoin_actor_message_duration_seconds", "actor" => actor, "message" => message)
+        .record(handled.as_secs_f64());
+}
+
 pub fn record_node_uptime(uptime: Duration) {
     gauge!("bitcoin_node_uptime_seconds").set(uptime.as_secs_f64());
 }
 
-pub fn record_system_stats(memory_bytes: u64, cpu_percent: f64) {
-    gauge!("bitcoin_node_memory_usage_bytes").set(memory_bytes as f64);
-    gauge!("bitcoin_node_cpu_usage_percent").set(cpu_percent);
+pub fn record_system_stats(stats: &SystemStats) {
+    gauge!("bitcoin_node_memory_usage_bytes").set(stats.memory_bytes as f64);
+    gauge!("bitcoin_node_cpu_usage_percent").set(stats.cpu_percent);
+    if let Some(open_fds) = stats.open_fds {
+        gauge!("bitcoin_node_open_fds").set(open_fds as f64);
+    }
+    if let Some(disk) = &stats.disk {
+        gauge!("bitcoin_node_disk_available_bytes").set(disk.available_bytes as f64);
+        gauge!("bitcoin_node_disk_total_bytes").set(disk.total_bytes as f64);
+    }
+}
+
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Record when the node started, for uptime reporting; later calls are ignored
+pub fn mark_process_start() {
+    PROCESS_START.get_or_init(Instant::now);
+}
+
+/// Process-level figures for status snapshots
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessMetrics {
+    pub version: &'static str,
+    pub pid: u32,
+    pub uptime_seconds: u64,
+    /// Resident set size, where the platform exposes it
+    pub rss_bytes: Option<u64>,
+    pub threads: Option<u64>,
+}
+
+pub fn process_metrics() -> ProcessMetrics {
+    let (rss_bytes, threads) = std::fs::read_to_string("/proc/self/status")
+        .map(|status| parse_proc_status(&status))
+        .unwrap_or((None, None));
+
+    ProcessMetrics {
+        version: env!("CARGO_PKG_VERSION"),
+        pid: std::process::id(),
+        uptime_seconds: PROCESS_START.get_or_init(Instant::now).elapsed().as_secs(),
+        rss_bytes,
+        threads,
+    }
+}
+
+/// Pull `VmRSS` (reported in kB) and `Threads` out of `/proc/<pid>/status`
+fn parse_proc_status(status: &str) -> (Option<u64>, Option<u64>) {
+    let field = |name: &str| {
+        status.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    (field("VmRSS:").map(|kb| kb * 1024), field("Threads:"))
 }
 
 // Utility macro for timing operations with metrics
@@ -0,0 +1,335 @@
+//! Block template assembly for getblocktemplate and local mining
+//!
+//! Templates build on the stored chain tip with transactions chosen by the
+//! mempool, where the miner lane's priority transactions always come first.
+
+use actix::Addr;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::hex::DisplayHex;
+use bitcoin::hash_types::WitnessMerkleNode;
+use bitcoin::absolute::LockTime;
+use bitcoin::block::Header;
+use bitcoin::script::Builder;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf, Sequence};
+use bitcoin::{Transaction, TxIn, TxMerkleNode, TxOut, Weight, Witness};
+use serde_json::{json, Value};
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::mempool::{MempoolActor, TemplateTransaction};
+use crate::actors::storage::StorageActor;
+use crate::actors::{GetBlock, GetBlockTemplateTransactions, GetChainTip};
+use crate::analytics::block_subsidy;
+use crate::config::MiningConfig;
+use crate::error::{ConfigResult, StorageError};
+
+/// Weight kept free for the coinbase transaction
+pub const COINBASE_RESERVED_WEIGHT: u64 = 4000;
+
+/// BIP141 witness commitment header
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+/// How the coinbase pays out and what its scriptSig carries
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoinbaseSpec {
+    /// Output scripts with relative weights
+    pub payouts: Vec<(ScriptBuf, f64)>,
+    pub message: Vec<u8>,
+    pub extra_nonce_size: usize,
+}
+
+impl CoinbaseSpec {
+    pub fn from_config(config: &MiningConfig, network: Network) -> ConfigResult<Self> {
+        Ok(Self {
+            payouts: config.payout_scripts(network)?,
+            message: config.coinbase_message.as_bytes().to_vec(),
+            extra_nonce_size: config.extra_nonce_size,
+        })
+    }
+
+    /// Split `value` across the payouts by weight. Rounding leftovers go to
+    /// the first payout so the full reward is always claimed.
+    pub fn outputs(&self, value: u64) -> Vec<TxOut> {
+        let total: f64 = self.payouts.iter().map(|(_, weight)| weight).sum();
+        let mut outputs: Vec<TxOut> = self.payouts
+            .iter()
+            .map(|(script, weight)| TxOut {
+                value: Amount::from_sat((value as f64 * weight / total).floor() as u64),
+                script_pubkey: script.clone(),
+            })
+            .collect();
+
+        let paid: u64 = outputs.iter().map(|output| output.value.to_sat()).sum();
+        if let Some(first) = outputs.first_mut() {
+            first.value += Amount::from_sat(value.saturating_sub(paid));
+        }
+        outputs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    pub version: i32,
+    pub previous_block_hash: BlockHash,
+    pub height: u64,
+    pub bits: CompactTarget,
+    pub min_time: u32,
+    pub cur_time: u32,
+    pub transactions: Vec<TemplateTransaction>,
+    /// Subsidy plus fees of the selected transactions
+    pub coinbase_value: u64,
+}
+
+impl BlockTemplate {
+    pub fn new(tip: &Block, tip_height: u64, transactions: Vec<TemplateTransaction>, network: Network, now: u32) -> Self {
+        let height = tip_height + 1;
+        let fees: u64 = transactions.iter().map(|tx| tx.fee.to_sat()).sum();
+        // TODO: Use median time past and retarget bits at difficulty adjustment boundaries
+        let min_time = tip.header.time + 1;
+        Self {
+            version: 0x2000_0000,
+            previous_block_hash: tip.block_hash(),
+            height,
+            bits: tip.header.bits,
+            min_time,
+            cur_time: now.max(min_time),
+            transactions,
+            coinbase_value: block_subsidy(height, network) + fees,
+        }
+    }
+
+    /// BIP34 height push that starts the coinbase scriptSig
+    pub fn coinbase_height_push(&self) -> ScriptBuf {
+        Builder::new().push_int(self.height as i64).into_script()
+    }
+
+    /// Coinbase splitting `coinbase_value` as `spec` describes, with `extra_nonce`
+    /// placed right after the height push and the message after that. The
+    /// witness is left empty so the serialization matches what the txid commits to.
+    pub fn coinbase_transaction(&self, spec: &CoinbaseSpec, extra_nonce: &[u8]) -> Transaction {
+        let mut script_sig = self.coinbase_height_push().into_bytes();
+        script_sig.extend_from_slice(extra_nonce);
+        script_sig.extend_from_slice(&spec.message);
+
+        let mut output = spec.outputs(self.coinbase_value);
+        output.push(TxOut { value: Amount::ZERO, script_pubkey: self.witness_commitment_script() });
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::from_bytes(script_sig),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output,
+        }
+    }
+
+    /// Full block from a solved header and this template's coinbase
+    pub fn assemble_block(&self, header: Header, mut coinbase: Transaction) -> Block {
+        // Witness reserved value matching the commitment
+        coinbase.input[0].witness = Witness::from_slice(&[[0u8; 32]]);
+        let mut txdata = Vec::with_capacity(self.transactions.len() + 1);
+        txdata.push(coinbase);
+        txdata.extend(self.transactions.iter().map(|entry| entry.tx.clone()));
+        Block { header, txdata }
+    }
+
+    /// This template's block, timestamped `time`, with the nonce ground until
+    /// the header meets its target. `None` after `max_tries` nonces; only
+    /// practical where the target is trivial, as on regtest.
+    pub fn solve(&self, spec: &CoinbaseSpec, extra_nonce: &[u8], time: u32, max_tries: u64) -> Option<Block> {
+        let header = Header {
+            version: bitcoin::block::Version::from_consensus(self.version),
+            prev_blockhash: self.previous_block_hash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: self.bits,
+            nonce: 0,
+        };
+        let mut block = self.assemble_block(header, self.coinbase_transaction(spec, extra_nonce));
+        block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+
+        let target = block.header.target();
+        for nonce in 0..max_tries.min(u64::from(u32::MAX) + 1) {
+            block.header.nonce = nonce as u32;
+            if block.header.validate_pow(target).is_ok() {
+                return Some(block);
+            }
+        }
+        None
+    }
+
+    /// BIP141 commitment output script, using an all-zero witness reserved value
+    pub fn witness_commitment_script(&self) -> ScriptBuf {
+        let wtxids = self.transactions.iter().map(|entry| entry.tx.wtxid().to_raw_hash());
+        let root = bitcoin::merkle_tree::calculate_root(std::iter::once(sha256d::Hash::all_zeros()).chain(wtxids))
+            .expect("iterator is never empty");
+        let commitment = Block::compute_witness_commitment(&WitnessMerkleNode::from_raw_hash(root), &[0u8; 32]);
+
+        let mut data = [0u8; 36];
+        data[..4].copy_from_slice(&WITNESS_COMMITMENT_HEADER);
+        data[4..].copy_from_slice(commitment.as_byte_array());
+        ScriptBuf::new_op_return(data)
+    }
+
+    /// BIP22 getblocktemplate response
+    pub fn to_json(&self) -> Value {
+        let transactions: Vec<Value> = self.transactions
+            .iter()
+            .map(|entry| {
+                let txid = entry.tx.txid();
+                // BIP22 dependencies are 1-based indices into this list
+                let depends: Vec<usize> = self.transactions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| entry.tx.input.iter().any(|input| input.previous_output.txid == other.tx.txid()))
+                    .map(|(index, _)| index + 1)
+                    .collect();
+                json!({
+                    "data": bitcoin::consensus::serialize(&entry.tx).to_lower_hex_string(),
+                    "txid": txid.to_string(),
+                    "hash": entry.tx.wtxid().to_string(),
+                    "depends": depends,
+                    "fee": entry.fee.to_sat(),
+                    "weight": entry.weight,
+                    "priority": entry.priority,
+                })
+            })
+            .collect();
+
+        json!({
+            "capabilities": ["proposal"],
+            "version": self.version,
+            "rules": ["segwit"],
+            "previousblockhash": self.previous_block_hash.to_string(),
+            "transactions": transactions,
+            "coinbasevalue": self.coinbase_value,
+            "default_witness_commitment": self.witness_commitment_script().as_bytes().to_lower_hex_string(),
+            "mintime": self.min_time,
+            "curtime": self.cur_time,
+            "bits": format!("{:08x}", self.bits.to_consensus()),
+            "height": self.height,
+            "weightlimit": Weight::MAX_BLOCK.to_wu(),
+        })
+    }
+}
+
+/// Template on top of the current tip, or `None` before the genesis block is stored
+pub async fn fetch_template(
+    storage_actor: &Addr<StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    network: Network,
+) -> Result<Option<BlockTemplate>, StorageError> {
+    let max_weight = Weight::MAX_BLOCK.to_wu() - COINBASE_RESERVED_WEIGHT;
+    let transactions = mempool_actor.send_traced(GetBlockTemplateTransactions { max_weight }).await??;
+    template_on_tip(storage_actor, transactions, network).await
+}
+
+/// Template with exactly `transactions` on top of the current tip
+pub async fn template_on_tip(
+    storage_actor: &Addr<StorageActor>,
+    transactions: Vec<TemplateTransaction>,
+    network: Network,
+) -> Result<Option<BlockTemplate>, StorageError> {
+    let Some(tip) = storage_actor.send_traced(GetChainTip).await?? else {
+        return Ok(None);
+    };
+    let tip_block = storage_actor
+        .send_traced(GetBlock { hash: tip.hash })
+        .await??
+        .ok_or_else(|| StorageError::Corruption { component: format!("tip block {}", tip.hash) })?;
+
+    let now = crate::clock::now() as u32;
+    Ok(Some(BlockTemplate::new(&tip_block, tip.height, transactions, network, now)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_on_genesis() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let template = BlockTemplate::new(&genesis, 0, Vec::new(), Network::Regtest, 0);
+
+        assert_eq!(template.height, 1);
+        assert_eq!(template.coinbase_value, 50 * 100_000_000);
+        assert_eq!(template.cur_time, genesis.header.time + 1);
+
+        let json = template.to_json();
+        assert_eq!(json["previousblockhash"], genesis.block_hash().to_string());
+        assert_eq!(json["bits"], "207fffff");
+        assert!(json["default_witness_commitment"].as_str().unwrap().starts_with("6a24aa21a9ed"));
+    }
+
+    #[test]
+    fn test_assembled_block_commits_to_witnesses() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let template = BlockTemplate::new(&genesis, 0, Vec::new(), Network::Regtest, 0);
+        let spec = CoinbaseSpec {
+            payouts: vec![(ScriptBuf::new_op_return([1u8; 4]), 1.0)],
+            message: b"/test/".to_vec(),
+            extra_nonce_size: 8,
+        };
+        let coinbase = template.coinbase_transaction(&spec, &[1u8; 8]);
+        assert!(coinbase.is_coinbase());
+
+        let header = Header {
+            version: bitcoin::block::Version::from_consensus(template.version),
+            prev_blockhash: template.previous_block_hash,
+            merkle_root: bitcoin::TxMerkleNode::from_raw_hash(coinbase.txid().to_raw_hash()),
+            time: template.cur_time,
+            bits: template.bits,
+            nonce: 0,
+        };
+        let block = template.assemble_block(header, coinbase);
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+        assert!(block.txdata[0].input[0].script_sig.as_bytes().ends_with(b"/test/"));
+    }
+
+    #[test]
+    fn test_solve_meets_regtest_target() {
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let template = BlockTemplate::new(&genesis, 0, Vec::new(), Network::Regtest, 0);
+        let spec = CoinbaseSpec {
+            payouts: vec![(ScriptBuf::new_op_return([1u8; 4]), 1.0)],
+            message: Vec::new(),
+            extra_nonce_size: 8,
+        };
+
+        let block = template.solve(&spec, &[0u8; 8], template.cur_time, 1_000_000).unwrap();
+        assert_eq!(block.header.prev_blockhash, genesis.block_hash());
+        assert!(block.header.validate_pow(block.header.target()).is_ok());
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+
+        // Mainnet difficulty is out of reach of a couple of nonces
+        let mainnet = bitcoin::blockdata::constants::genesis_block(Network::Bitcoin);
+        let template = BlockTemplate::new(&mainnet, 0, Vec::new(), Network::Bitcoin, 0);
+        assert!(template.solve(&spec, &[0u8; 8], template.cur_time, 2).is_none());
+    }
+
+    #[test]
+    fn test_coinbase_split_by_weight() {
+        let script = |byte: u8| ScriptBuf::new_op_return([byte]);
+        let spec = CoinbaseSpec {
+            payouts: vec![(script(1), 50.0), (script(2), 30.0), (script(3), 20.0)],
+            message: Vec::new(),
+            extra_nonce_size: 4,
+        };
+
+        let values: Vec<u64> = spec.outputs(1_000_001).iter().map(|output| output.value.to_sat()).collect();
+        assert_eq!(values, vec![500_001, 300_000, 200_000]);
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let template = BlockTemplate::new(&genesis, 0, Vec::new(), Network::Regtest, 0);
+        let coinbase = template.coinbase_transaction(&spec, &[0u8; 4]);
+        // Three payouts plus the witness commitment
+        assert_eq!(coinbase.output.len(), 4);
+        assert_eq!(coinbase.output.iter().map(|output| output.value.to_sat()).sum::<u64>(), template.coinbase_value);
+    }
+}
@@ -0,0 +1,267 @@
+//! MuHash3072 commitments over the UTXO set
+//!
+//! Each coin maps to a number modulo the prime 2^3072 - 1103717; the set's
+//! commitment is their product, so adding and removing coins are a multiply
+//! into the numerator or the denominator and the result does not depend on
+//! order. Coins are serialized the way Bitcoin Core's `gettxoutsetinfo muhash`
+//! does, so the two can be compared at the same height.
+
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{OutPoint, TxOut};
+
+const LIMBS: usize = 48;
+/// 2^3072 minus the modulus
+const MODULUS_OFFSET: u64 = 1_103_717;
+
+/// Serialized size of a [`MuHash3072`] accumulator
+pub const MUHASH_STATE_SIZE: usize = 2 * 8 * LIMBS;
+
+/// Integer modulo 2^3072 - 1103717, little-endian limbs
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Num3072([u64; LIMBS]);
+
+impl Num3072 {
+    fn one() -> Self {
+        let mut limbs = [0u64; LIMBS];
+        limbs[0] = 1;
+        Self(limbs)
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0u64; LIMBS];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().expect("8 bytes"));
+        }
+        let mut num = Self(limbs);
+        num.reduce_once();
+        num
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        self.0.iter().flat_map(|limb| limb.to_le_bytes()).collect()
+    }
+
+    /// Add a small value, returning the carry out of the top limb
+    fn add_small(&mut self, mut value: u128) -> u128 {
+        for limb in self.0.iter_mut() {
+            if value == 0 {
+                break;
+            }
+            let sum = *limb as u128 + value;
+            *limb = sum as u64;
+            value = sum >> 64;
+        }
+        value
+    }
+
+    /// Values at or above the modulus are those whose top bits are all set
+    /// and whose lowest limb is within the offset of overflowing
+    fn reduce_once(&mut self) {
+        let overflows = self.0[1..].iter().all(|&limb| limb == u64::MAX) && self.0[0] > u64::MAX - MODULUS_OFFSET;
+        if overflows {
+            // Subtracting the modulus is adding the offset modulo 2^3072
+            self.add_small(MODULUS_OFFSET as u128);
+        }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut wide = [0u64; 2 * LIMBS];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &b) in other.0.iter().enumerate() {
+                let cur = wide[i + j] as u128 + a as u128 * b as u128 + carry;
+                wide[i + j] = cur as u64;
+                carry = cur >> 64;
+            }
+            wide[i + LIMBS] = carry as u64;
+        }
+
+        // high * 2^3072 is congruent to high * offset
+        let mut result = [0u64; LIMBS];
+        let mut carry = 0u128;
+        for i in 0..LIMBS {
+            let cur = wide[i] as u128 + wide[i + LIMBS] as u128 * MODULUS_OFFSET as u128 + carry;
+            result[i] = cur as u64;
+            carry = cur >> 64;
+        }
+        let mut num = Self(result);
+        let mut overflow = carry;
+        while overflow != 0 {
+            overflow = num.add_small(overflow * MODULUS_OFFSET as u128);
+        }
+        num.reduce_once();
+        num
+    }
+
+    /// Inverse by Fermat's little theorem, raising to the modulus minus two
+    fn inverse(&self) -> Self {
+        let mut exponent = [u64::MAX; LIMBS];
+        exponent[0] = u64::MAX - MODULUS_OFFSET - 1;
+
+        let mut result = Self::one();
+        for limb in exponent.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.mul(&result);
+                if limb >> bit & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Map arbitrary data into the group: ChaCha20 keystream keyed by its SHA-256
+fn to_num3072(data: &[u8]) -> Num3072 {
+    let key = sha256::Hash::hash(data).to_byte_array();
+    let mut keystream = [0u8; 8 * LIMBS];
+    for (counter, block) in keystream.chunks_exact_mut(64).enumerate() {
+        block.copy_from_slice(&chacha20_block(&key, counter as u32));
+    }
+    Num3072::from_le_bytes(&keystream)
+}
+
+fn chacha20_block(key: &[u8; 32], counter: u32) -> [u8; 64] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (word, chunk) in initial[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().expect("4 bytes"));
+    }
+    // Zero nonce
+    initial[12] = counter;
+
+    let mut state = initial;
+    for _ in 0..10 {
+        for (a, b, c, d) in [(0, 4, 8, 12), (1, 5, 9, 13), (2, 6, 10, 14), (3, 7, 11, 15),
+                             (0, 5, 10, 15), (1, 6, 11, 12), (2, 7, 8, 13), (3, 4, 9, 14)] {
+            state[a] = state[a].wrapping_add(state[b]);
+            state[d] = (state[d] ^ state[a]).rotate_left(16);
+            state[c] = state[c].wrapping_add(state[d]);
+            state[b] = (state[b] ^ state[c]).rotate_left(12);
+            state[a] = state[a].wrapping_add(state[b]);
+            state[d] = (state[d] ^ state[a]).rotate_left(8);
+            state[c] = state[c].wrapping_add(state[d]);
+            state[b] = (state[b] ^ state[c]).rotate_left(7);
+        }
+    }
+
+    let mut block = [0u8; 64];
+    for (i, chunk) in block.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&state[i].wrapping_add(initial[i]).to_le_bytes());
+    }
+    block
+}
+
+/// Core's serialization of a coin for the commitment: outpoint, height and
+/// coinbase flag packed as `height * 2 + coinbase`, then the output
+pub fn coin_bytes(outpoint: &OutPoint, height: u64, is_coinbase: bool, output: &TxOut) -> Vec<u8> {
+    let mut data = serialize(outpoint);
+    data.extend_from_slice(&((height as u32) << 1 | is_coinbase as u32).to_le_bytes());
+    data.extend(serialize(output));
+    data
+}
+
+/// Running commitment; removals are kept in a separate denominator so
+/// updates never need the expensive inverse
+#[derive(Clone, PartialEq, Eq)]
+pub struct MuHash3072 {
+    numerator: Num3072,
+    denominator: Num3072,
+}
+
+impl Default for MuHash3072 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MuHash3072 {
+    /// The commitment to the empty set
+    pub fn new() -> Self {
+        Self { numerator: Num3072::one(), denominator: Num3072::one() }
+    }
+
+    pub fn insert(&mut self, data: &[u8]) {
+        self.numerator = self.numerator.mul(&to_num3072(data));
+    }
+
+    pub fn remove(&mut self, data: &[u8]) {
+        self.denominator = self.denominator.mul(&to_num3072(data));
+    }
+
+    /// SHA-256 of the normalized 384 byte value
+    pub fn digest(&self) -> [u8; 32] {
+        let value = self.numerator.mul(&self.denominator.inverse());
+        sha256::Hash::hash(&value.to_le_bytes()).to_byte_array()
+    }
+
+    /// The digest in the byte order Core prints it
+    pub fn digest_hex(&self) -> String {
+        let mut digest = self.digest();
+        digest.reverse();
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Whether both commit to the same set, without normalizing either
+    pub fn same_set(&self, other: &Self) -> bool {
+        self.numerator.mul(&other.denominator) == other.numerator.mul(&self.denominator)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.numerator.to_le_bytes();
+        bytes.extend(self.denominator.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != MUHASH_STATE_SIZE {
+            return None;
+        }
+        let (numerator, denominator) = bytes.split_at(MUHASH_STATE_SIZE / 2);
+        Some(Self {
+            numerator: Num3072::from_le_bytes(numerator),
+            denominator: Num3072::from_le_bytes(denominator),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_int(i: u8) -> [u8; 32] {
+        let mut data = [0u8; 32];
+        data[0] = i;
+        data
+    }
+
+    #[test]
+    fn test_core_vector() {
+        // From Bitcoin Core's crypto_tests muhash_tests
+        let mut acc = MuHash3072::new();
+        acc.insert(&from_int(0));
+        acc.insert(&from_int(1));
+        acc.remove(&from_int(2));
+        assert_eq!(acc.digest_hex(), "10d312b100cbd32ada024a6646e40d3482fcff103668d2625f10002a607d5863");
+    }
+
+    #[test]
+    fn test_order_independence_and_round_trip() {
+        let mut forward = MuHash3072::new();
+        let mut backward = MuHash3072::new();
+        for i in 0..4 {
+            forward.insert(&from_int(i));
+            backward.insert(&from_int(3 - i));
+        }
+        backward.insert(&from_int(9));
+        backward.remove(&from_int(9));
+        assert_eq!(forward.digest(), backward.digest());
+        assert!(forward.same_set(&backward));
+        assert!(!forward.same_set(&MuHash3072::new()));
+
+        let restored = MuHash3072::from_bytes(&backward.to_bytes()).unwrap();
+        assert_eq!(restored.digest(), forward.digest());
+        assert!(MuHash3072::from_bytes(&[0u8; 10]).is_none());
+    }
+}
@@ -0,0 +1,394 @@
+//! Multisig coordination: sortedmulti descriptors from cosigner xpubs and
+//! signing proposals that collect partial signatures
+//!
+//! The node holds no keys here either. A proposal is an unsigned PSBT that
+//! cosigners fetch, sign and hand back; each returned PSBT is checked against
+//! the witness script and merged until every input reaches the threshold.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use bitcoin::bip32::ChildNumber;
+use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_PUSHNUM_16};
+use bitcoin::blockdata::script::{Builder, Instruction};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::{Address, Network, Script, ScriptBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{MultisigError, MultisigResult};
+use crate::wallet::AccountKey;
+
+/// Keys allowed in one P2WSH CHECKMULTISIG by standardness
+pub const MAX_COSIGNERS: usize = 20;
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+fn polymod(c: u64, value: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+    for (bit, generator) in [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd].into_iter().enumerate() {
+        if c0 & (1 << bit) != 0 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// BIP 380 descriptor checksum
+pub fn descriptor_checksum(descriptor: &str) -> MultisigResult<String> {
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET.find(ch)
+            .ok_or_else(|| MultisigError::Descriptor(format!("invalid character {:?}", ch)))? as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8).map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char).collect())
+}
+
+/// `wsh(sortedmulti(...))` over cosigner account keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigDescriptor {
+    pub threshold: usize,
+    pub cosigners: Vec<AccountKey>,
+}
+
+impl MultisigDescriptor {
+    pub fn new(threshold: usize, cosigners: Vec<AccountKey>) -> MultisigResult<Self> {
+        if cosigners.is_empty() || cosigners.len() > MAX_COSIGNERS {
+            return Err(MultisigError::Descriptor(format!("between 1 and {} cosigners are required", MAX_COSIGNERS)));
+        }
+        if threshold == 0 || threshold > cosigners.len() {
+            return Err(MultisigError::Descriptor(format!("threshold {} out of range for {} cosigners", threshold, cosigners.len())));
+        }
+        if (1..cosigners.len()).any(|i| cosigners[..i].iter().any(|key| key.xpub == cosigners[i].xpub)) {
+            return Err(MultisigError::Descriptor("duplicate cosigner key".to_string()));
+        }
+        Ok(Self { threshold, cosigners })
+    }
+
+    /// Descriptor text for the receive (0) or change (1) chain, with checksum
+    pub fn to_string_for_chain(&self, chain: u32) -> MultisigResult<String> {
+        let keys: Vec<String> = self.cosigners.iter().map(|key| format!("{}/{}/*", key, chain)).collect();
+        let descriptor = format!("wsh(sortedmulti({},{}))", self.threshold, keys.join(","));
+        let checksum = descriptor_checksum(&descriptor)?;
+        Ok(format!("{}#{}", descriptor, checksum))
+    }
+
+    pub fn witness_script(&self, chain: u32, index: u32) -> MultisigResult<ScriptBuf> {
+        let path = [
+            ChildNumber::from_normal_idx(chain).map_err(|e| MultisigError::Descriptor(e.to_string()))?,
+            ChildNumber::from_normal_idx(index).map_err(|e| MultisigError::Descriptor(e.to_string()))?,
+        ];
+        let secp = Secp256k1::verification_only();
+        let mut keys = self.cosigners.iter()
+            .map(|key| key.xpub.derive_pub(&secp, &path).map(|derived| derived.public_key.serialize()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| MultisigError::Descriptor(e.to_string()))?;
+        keys.sort();
+
+        let mut builder = Builder::new().push_int(self.threshold as i64);
+        for key in &keys {
+            builder = builder.push_slice(key);
+        }
+        Ok(builder.push_int(keys.len() as i64).push_opcode(OP_CHECKMULTISIG).into_script())
+    }
+
+    pub fn address(&self, chain: u32, index: u32, network: Network) -> MultisigResult<Address> {
+        Ok(Address::p2wsh(&self.witness_script(chain, index)?, network))
+    }
+}
+
+impl fmt::Display for MultisigDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_string_for_chain(0) {
+            Ok(descriptor) => f.write_str(&descriptor),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+fn pushnum(instruction: Option<Result<Instruction<'_>, bitcoin::script::Error>>) -> Option<usize> {
+    match instruction {
+        Some(Ok(Instruction::Op(op))) if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+            Some((op.to_u8() - OP_PUSHNUM_1.to_u8() + 1) as usize)
+        }
+        // Key counts above 16 are pushed as a script number
+        Some(Ok(Instruction::PushBytes(bytes))) if bytes.len() == 1 => Some(bytes.as_bytes()[0] as usize),
+        _ => None,
+    }
+}
+
+/// Threshold and keys of a bare `m <keys> n CHECKMULTISIG` script
+pub fn parse_multisig(script: &Script) -> Option<(usize, Vec<PublicKey>)> {
+    let mut instructions = script.instructions();
+    let threshold = pushnum(instructions.next())?;
+    let mut keys = Vec::new();
+    let count = loop {
+        match instructions.next() {
+            Some(Ok(Instruction::PushBytes(bytes))) if bytes.len() == 33 => {
+                keys.push(PublicKey::from_slice(bytes.as_bytes()).ok()?);
+            }
+            other => break pushnum(other)?,
+        }
+    };
+    let ends_with_checkmultisig = matches!(instructions.next(), Some(Ok(Instruction::Op(op))) if op == OP_CHECKMULTISIG);
+    (ends_with_checkmultisig && instructions.next().is_none() && count == keys.len() && threshold <= count)
+        .then_some((threshold, keys))
+}
+
+/// PSBT being signed by cosigners, persisted as `<datadir>/multisig/<id>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Unsigned txid, so every cosigner's copy maps to the same proposal
+    pub id: String,
+    pub description: Option<String>,
+    pub threshold: usize,
+    /// Base64 PSBT with every signature merged so far
+    pub psbt: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalStatus {
+    pub proposal_id: String,
+    pub description: Option<String>,
+    pub threshold: usize,
+    /// Partial signatures collected for each input
+    pub signatures: Vec<usize>,
+    pub complete: bool,
+    pub psbt: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn parse_psbt(psbt: &str) -> MultisigResult<Psbt> {
+    psbt.parse().map_err(|e: bitcoin::psbt::PsbtParseError| MultisigError::Psbt(e.to_string()))
+}
+
+impl Proposal {
+    /// Start a proposal; every input must spend a P2WSH multisig output with
+    /// its witness script and UTXO filled in
+    pub fn new(psbt: &str, description: Option<String>) -> MultisigResult<Self> {
+        let psbt = parse_psbt(psbt)?;
+        let mut threshold = None;
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            let script = input.witness_script.as_ref()
+                .ok_or_else(|| MultisigError::Psbt(format!("input {} has no witness script", index)))?;
+            let (required, _) = parse_multisig(script)
+                .ok_or_else(|| MultisigError::Psbt(format!("input {} is not a multisig spend", index)))?;
+            if input.witness_utxo.is_none() {
+                return Err(MultisigError::Psbt(format!("input {} has no witness UTXO", index)));
+            }
+            if *threshold.get_or_insert(required) != required {
+                return Err(MultisigError::Psbt("inputs have different thresholds".to_string()));
+            }
+        }
+        let threshold = threshold.ok_or_else(|| MultisigError::Psbt("PSBT has no inputs".to_string()))?;
+
+        let now = Utc::now();
+        Ok(Self {
+            id: psbt.unsigned_tx.txid().to_string(),
+            description,
+            threshold,
+            psbt: psbt.to_string(),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    /// Merge a cosigner's PSBT after checking each new signature, returning how
+    /// many were added
+    pub fn merge(&mut self, signed: &str) -> MultisigResult<usize> {
+        let mut psbt = parse_psbt(&self.psbt)?;
+        let signed = parse_psbt(signed)?;
+        if signed.unsigned_tx != psbt.unsigned_tx {
+            return Err(MultisigError::Psbt("PSBT spends a different transaction".to_string()));
+        }
+
+        let secp = Secp256k1::verification_only();
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let mut added = 0;
+        for (index, (input, signed_input)) in psbt.inputs.iter().zip(&signed.inputs).enumerate() {
+            let (Some(script), Some(utxo)) = (&input.witness_script, &input.witness_utxo) else {
+                continue;
+            };
+            let (_, keys) = parse_multisig(script).unwrap_or_default();
+            for (key, signature) in &signed_input.partial_sigs {
+                if input.partial_sigs.contains_key(key) {
+                    continue;
+                }
+                if !keys.contains(&key.inner) {
+                    return Err(MultisigError::Signature(format!("input {} signed by a key outside the multisig", index)));
+                }
+                let sighash = cache.p2wsh_signature_hash(index, script, utxo.value, signature.hash_ty)
+                    .map_err(|e| MultisigError::Signature(e.to_string()))?;
+                secp.verify_ecdsa(&Message::from_digest(sighash.to_byte_array()), &signature.sig, &key.inner)
+                    .map_err(|_| MultisigError::Signature(format!("invalid signature on input {}", index)))?;
+                added += 1;
+            }
+        }
+
+        psbt.combine(signed).map_err(|e| MultisigError::Psbt(e.to_string()))?;
+        self.psbt = psbt.to_string();
+        self.updated_at = Utc::now();
+        Ok(added)
+    }
+
+    pub fn status(&self) -> MultisigResult<ProposalStatus> {
+        let psbt = parse_psbt(&self.psbt)?;
+        let signatures: Vec<usize> = psbt.inputs.iter().map(|input| input.partial_sigs.len()).collect();
+        Ok(ProposalStatus {
+            proposal_id: self.id.clone(),
+            description: self.description.clone(),
+            threshold: self.threshold,
+            complete: signatures.iter().all(|count| *count >= self.threshold),
+            signatures,
+            psbt: self.psbt.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        })
+    }
+
+    fn dir(datadir: &Path) -> PathBuf {
+        datadir.join("multisig")
+    }
+
+    pub fn save(&self, datadir: &Path) -> MultisigResult<()> {
+        let dir = Self::dir(datadir);
+        std::fs::create_dir_all(&dir)?;
+        let data = serde_json::to_vec_pretty(self).map_err(|e| MultisigError::Serialization(e.to_string()))?;
+        let path = dir.join(format!("{}.json", self.id));
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, data)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    pub fn load_all(datadir: &Path) -> MultisigResult<Vec<Self>> {
+        let dir = Self::dir(datadir);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut proposals = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let data = std::fs::read(&path)?;
+            proposals.push(serde_json::from_slice(&data).map_err(|e| MultisigError::Serialization(e.to_string()))?);
+        }
+        Ok(proposals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::{absolute, transaction, Amount, EcdsaSighashType, OutPoint, Transaction, TxIn, TxOut, Txid};
+
+    fn cosigner(seed: u8) -> (Xpriv, AccountKey) {
+        let secp = Secp256k1::new();
+        let xpriv = Xpriv::new_master(Network::Testnet, &[seed; 32]).unwrap();
+        let key = AccountKey { xpub: bitcoin::bip32::Xpub::from_priv(&secp, &xpriv), origin: (xpriv.fingerprint(&secp), "m/48'/1'/0'/2'".parse().unwrap()) };
+        (xpriv, key)
+    }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        // BIP 380 test vector
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert!(descriptor_checksum("raw(dead\u{e9}beef)").is_err());
+
+        let keys: Vec<AccountKey> = (1..=3).map(|seed| cosigner(seed).1).collect();
+        let descriptor = MultisigDescriptor::new(2, keys.clone()).unwrap();
+        let text = descriptor.to_string();
+        assert!(text.starts_with(&format!("wsh(sortedmulti(2,{}/0/*,", keys[0])));
+        let (body, checksum) = text.split_once('#').unwrap();
+        assert_eq!(descriptor_checksum(body).unwrap(), checksum);
+
+        // Key order does not change the script
+        let reversed = MultisigDescriptor::new(2, keys.iter().rev().cloned().collect()).unwrap();
+        assert_eq!(descriptor.witness_script(0, 5).unwrap(), reversed.witness_script(0, 5).unwrap());
+        let (threshold, script_keys) = parse_multisig(&descriptor.witness_script(0, 5).unwrap()).unwrap();
+        assert_eq!((threshold, script_keys.len()), (2, 3));
+        assert!(MultisigDescriptor::new(4, keys.clone()).is_err());
+        assert!(MultisigDescriptor::new(1, vec![keys[0].clone(), keys[0].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_proposal_collects_signatures() {
+        let secp = Secp256k1::new();
+        let cosigners: Vec<(Xpriv, AccountKey)> = (1..=3).map(cosigner).collect();
+        let descriptor = MultisigDescriptor::new(2, cosigners.iter().map(|(_, key)| key.clone()).collect()).unwrap();
+        let script = descriptor.witness_script(0, 0).unwrap();
+        let utxo = TxOut { value: Amount::from_sat(100_000), script_pubkey: ScriptBuf::new_p2wsh(&script.wscript_hash()) };
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0 }, ..TxIn::default() }],
+            output: vec![TxOut { value: Amount::from_sat(99_000), script_pubkey: utxo.script_pubkey.clone() }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_script = Some(script.clone());
+        psbt.inputs[0].witness_utxo = Some(utxo.clone());
+        let mut proposal = Proposal::new(&psbt.to_string(), Some("rent".to_string())).unwrap();
+        assert_eq!(proposal.id, psbt.unsigned_tx.txid().to_string());
+        assert_eq!(proposal.threshold, 2);
+
+        let sign = |xpriv: &Xpriv, psbt: &Psbt| {
+            let path = [ChildNumber::from_normal_idx(0).unwrap(), ChildNumber::from_normal_idx(0).unwrap()];
+            let key = xpriv.derive_priv(&secp, &path).unwrap().private_key;
+            let sighash = SighashCache::new(&psbt.unsigned_tx)
+                .p2wsh_signature_hash(0, &script, utxo.value, EcdsaSighashType::All)
+                .unwrap();
+            let sig = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &key);
+            let mut signed = psbt.clone();
+            signed.inputs[0].partial_sigs.insert(
+                bitcoin::PublicKey::new(key.public_key(&secp)),
+                bitcoin::ecdsa::Signature { sig, hash_ty: EcdsaSighashType::All },
+            );
+            signed
+        };
+
+        assert_eq!(proposal.merge(&sign(&cosigners[0].0, &psbt).to_string()).unwrap(), 1);
+        assert!(!proposal.status().unwrap().complete);
+        // Resubmitting adds nothing; a second cosigner completes the threshold
+        assert_eq!(proposal.merge(&sign(&cosigners[0].0, &psbt).to_string()).unwrap(), 0);
+        assert_eq!(proposal.merge(&sign(&cosigners[2].0, &psbt).to_string()).unwrap(), 1);
+        let status = proposal.status().unwrap();
+        assert_eq!(status.signatures, vec![2]);
+        assert!(status.complete);
+
+        // Signatures from outside the multisig or over another transaction are refused
+        let outsider = cosigner(9).0;
+        assert!(matches!(proposal.merge(&sign(&outsider, &psbt).to_string()), Err(MultisigError::Signature(_))));
+        let mut other = psbt.clone();
+        other.unsigned_tx.lock_time = absolute::LockTime::from_consensus(1);
+        assert!(matches!(proposal.merge(&other.to_string()), Err(MultisigError::Psbt(_))));
+    }
+}
@@ -11,14 +11,18 @@ pub struct NetworkConstants {
     /// Magic bytes for network message identification
     pub magic_bytes: [u8; 4],
     /// Default P2P port for the network
+    #[allow(dead_code)]
     pub default_port: u16,
     /// DNS seed nodes for peer discovery
+    #[allow(dead_code)]
     pub dns_seeds: Vec<&'static str>,
     /// Bitcoin protocol version
+    #[allow(dead_code)]
     pub protocol_version: u32,
     /// Node services bitfield
     pub services: u64,
     /// Network name for logging/identification
+    #[allow(dead_code)]
     pub name: &'static str,
 }
 
@@ -37,18 +41,9 @@ impl NetworkConstants {
         Self {
             magic_bytes: [0xf9, 0xbe, 0xb4, 0xd9],
             default_port: 8333,
-            dns_seeds: vec![
-                "seed.bitcoin.sipa.be",
-                "dnsseed.bluematt.me",
-                "dnsseed.bitcoin.dashjr.org",
-                "seed.bitcoinstats.com",
-                "seed.bitcoin.jonasschnelli.ch",
-                "seed.btc.petertodd.org",
-                "seed.bitcoin.sprovoost.nl",
-                "dnsseed.emzy.de",
-            ],
+            dns_seeds: crate::seeds::dns_seeds(&Network::Mainnet).to_vec(),
             protocol_version: 70016,
-            services: 0x01, // NODE_NETWORK
+            services: 0x409, // NODE_NETWORK | NODE_WITNESS | NODE_NETWORK_LIMITED
             name: "mainnet",
         }
     }
@@ -58,14 +53,9 @@ impl NetworkConstants {
         Self {
             magic_bytes: [0x0b, 0x11, 0x09, 0x07],
             default_port: 18333,
-            dns_seeds: vec![
-                "testnet-seed.bitcoin.jonasschnelli.ch",
-                "seed.tbtc.petertodd.org",
-                "seed.testnet.bitcoin.sprovoost.nl",
-                "testnet-seed.bluematt.me",
-            ],
+            dns_seeds: crate::seeds::dns_seeds(&Network::Testnet).to_vec(),
             protocol_version: 70016,
-            services: 0x01, // NODE_NETWORK
+            services: 0x409, // NODE_NETWORK | NODE_WITNESS | NODE_NETWORK_LIMITED
             name: "testnet",
         }
     }
@@ -77,17 +67,19 @@ impl NetworkConstants {
             default_port: 18444,
             dns_seeds: vec![], // No DNS seeds for regtest
             protocol_version: 70016,
-            services: 0x01, // NODE_NETWORK
+            services: 0x409, // NODE_NETWORK | NODE_WITNESS | NODE_NETWORK_LIMITED
             name: "regtest",
         }
     }
 
     /// Check if this network uses DNS seed discovery
+    #[allow(dead_code)]
     pub fn uses_dns_seeds(&self) -> bool {
         !self.dns_seeds.is_empty()
     }
 
     /// Get localhost peers for regtest network
+    #[allow(dead_code)]
     pub fn localhost_peers(&self) -> Vec<String> {
         match self.name {
             "regtest" => vec![
@@ -3,6 +3,11 @@
 //! This module provides network-specific constants, peer discovery,
 //! connection management, and protocol message handling for Bitcoin networks.
 
+pub mod codec;
 pub mod constants;
+pub mod protocol;
+// Capture replay is only reached through the library and tests
+#[allow(dead_code)]
+pub mod replay;
 
 pub use constants::*;
\ No newline at end of file
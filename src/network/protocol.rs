@@ -0,0 +1,447 @@
+//! Socket-free P2P protocol state machine
+//!
+//! Bytes received from a peer are framed, decoded and run through the
+//! handshake and rate limiting rules here. The caller owns the socket and
+//! acts on the returned [`ProtocolAction`]s, which keeps the state machine
+//! deterministic and lets recorded traffic be replayed without a network.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use bitcoin::consensus::encode::serialize;
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::Magic;
+
+use crate::config::{PeerRateLimitConfig, ProtocolStrictness};
+use crate::error::NetworkResult;
+use crate::ratelimit::{PeerRateLimiter, RateLimitVerdict};
+use super::codec::{self, MessageHeader, MESSAGE_HEADER_SIZE};
+
+/// Misbehavior score that gets a peer disconnected, matching the rate limiter default
+const MISBEHAVIOR_DISCONNECT_SCORE: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeState {
+    AwaitingVersion,
+    AwaitingVerack,
+    Established,
+    Disconnected,
+}
+
+/// Recoverable ways a peer can break the protocol, counted per connection.
+/// Framing errors (wrong magic, oversized length) always disconnect and are not listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolViolation {
+    BadChecksum,
+    /// Command field with non-printable bytes or data after the NUL padding
+    InvalidCommand,
+    MalformedPayload,
+    /// Payload bytes left over after the message decoded
+    TrailingData,
+    BeforeVersion,
+    BeforeVerack,
+    DuplicateHandshake,
+}
+
+impl ProtocolViolation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolViolation::BadChecksum => "bad_checksum",
+            ProtocolViolation::InvalidCommand => "invalid_command",
+            ProtocolViolation::MalformedPayload => "malformed_payload",
+            ProtocolViolation::TrailingData => "trailing_data",
+            ProtocolViolation::BeforeVersion => "before_version",
+            ProtocolViolation::BeforeVerack => "before_verack",
+            ProtocolViolation::DuplicateHandshake => "duplicate_handshake",
+        }
+    }
+}
+
+/// What the connection owner should do in response to received bytes
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolAction {
+    /// A decoded message that passed the handshake and rate limits
+    Deliver(NetworkMessage),
+    /// Reply to the peer
+    Send(NetworkMessage),
+    /// Message dropped without penalty beyond the given reason
+    Ignored { command: String, reason: String },
+    Misbehaving { score: u32, reason: String },
+    Disconnect { reason: String },
+    /// Follows the actions taken for a violation; `tolerated` is false when it cost the connection
+    Violation { violation: ProtocolViolation, tolerated: bool },
+}
+
+/// Protocol state for a single peer connection
+pub struct PeerProtocol {
+    magic: Magic,
+    strictness: ProtocolStrictness,
+    state: HandshakeState,
+    buffer: Vec<u8>,
+    limiter: PeerRateLimiter,
+    misbehavior: u32,
+    violations: BTreeMap<ProtocolViolation, u64>,
+    peer_version: Option<u32>,
+    peer_user_agent: Option<String>,
+    peer_services: Option<u64>,
+    /// Our own `version`, sent in reply to the peer's when we are the listening side
+    local_version: Option<VersionMessage>,
+}
+
+impl PeerProtocol {
+    pub fn new(magic: Magic, rate_limits: &PeerRateLimitConfig, now: Instant) -> Self {
+        Self {
+            magic,
+            strictness: ProtocolStrictness::default(),
+            state: HandshakeState::AwaitingVersion,
+            buffer: Vec::new(),
+            limiter: PeerRateLimiter::new(rate_limits, now),
+            misbehavior: 0,
+            violations: BTreeMap::new(),
+            peer_version: None,
+            peer_user_agent: None,
+            peer_services: None,
+            local_version: None,
+        }
+    }
+
+    /// Answer the peer's `version` with ours before the `verack`, as the accepting side of a connection does
+    pub fn responding_with(mut self, version: VersionMessage) -> Self {
+        self.local_version = Some(version);
+        self
+    }
+
+    pub fn with_strictness(mut self, strictness: ProtocolStrictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    pub fn state(&self) -> HandshakeState {
+        self.state
+    }
+
+    // Read back by capture replay
+    #[allow(dead_code)]
+    pub fn misbehavior(&self) -> u32 {
+        self.misbehavior
+    }
+
+    #[allow(dead_code)]
+    pub fn violations(&self) -> &BTreeMap<ProtocolViolation, u64> {
+        &self.violations
+    }
+
+    pub fn peer_version(&self) -> Option<u32> {
+        self.peer_version
+    }
+
+    pub fn peer_user_agent(&self) -> Option<&str> {
+        self.peer_user_agent.as_deref()
+    }
+
+    pub fn peer_services(&self) -> Option<u64> {
+        self.peer_services
+    }
+
+    /// Feed bytes read from the connection, which need not be aligned to message boundaries
+    pub fn receive(&mut self, bytes: &[u8], now: Instant) -> Vec<ProtocolAction> {
+        let mut actions = Vec::new();
+        if self.state == HandshakeState::Disconnected {
+            return actions;
+        }
+        self.buffer.extend_from_slice(bytes);
+
+        while self.state != HandshakeState::Disconnected {
+            match self.next_frame() {
+                Ok(Some(frame)) => self.process_frame(&frame, now, &mut actions),
+                Ok(None) => break,
+                Err(e) => self.disconnect(e.to_string(), &mut actions),
+            }
+        }
+        actions
+    }
+
+    /// Split the next complete message off the buffer, if one has arrived
+    fn next_frame(&mut self) -> NetworkResult<Option<Vec<u8>>> {
+        let frame_len = codec::frame_len(&self.buffer, self.magic)?;
+        Ok(frame_len.map(|frame_len| self.buffer.drain(..frame_len).collect()))
+    }
+
+    fn process_frame(&mut self, frame: &[u8], now: Instant, actions: &mut Vec<ProtocolAction>) {
+        let header = MessageHeader::parse(frame[..MESSAGE_HEADER_SIZE].try_into().expect("header size"));
+        let command = header.command_name();
+
+        match self.limiter.check(&command, frame.len(), now) {
+            RateLimitVerdict::Accept => {}
+            RateLimitVerdict::Throttle { .. } => {
+                actions.push(ProtocolAction::Ignored { command, reason: "rate limited".to_string() });
+                return;
+            }
+            RateLimitVerdict::Disconnect { .. } => {
+                self.disconnect("rate limit exceeded".to_string(), actions);
+                return;
+            }
+        }
+
+        if !command_is_valid(&header.command) {
+            self.violate(ProtocolViolation::InvalidCommand, format!("invalid command {:?}", command), actions, |_, reason, actions| {
+                actions.push(ProtocolAction::Ignored { command: command.clone(), reason });
+            });
+            return;
+        }
+
+        if codec::checksum(&frame[MESSAGE_HEADER_SIZE..]) != header.checksum {
+            self.violate(ProtocolViolation::BadChecksum, format!("bad checksum on {} message", command), actions, |protocol, reason, actions| {
+                protocol.misbehave(10, reason, actions);
+            });
+            return;
+        }
+
+        let message = match codec::decode(frame) {
+            Ok(message) => message,
+            Err(e) => {
+                // Core drops undecodable messages and penalizes the sender
+                self.violate(ProtocolViolation::MalformedPayload, format!("malformed {} message: {}", command, e), actions, |protocol, reason, actions| {
+                    protocol.misbehave(10, reason, actions);
+                });
+                return;
+            }
+        };
+
+        // The decoder ignores anything past the fields it knows. A `version`
+        // without the optional relay flag re-encodes longer, never shorter.
+        let trailing = serialize(&message).len() < frame.len();
+        let message = message.payload().clone();
+        if trailing {
+            self.violate(ProtocolViolation::TrailingData, format!("trailing bytes after {} message", command), actions, |protocol, _, actions| {
+                protocol.handle_message(message, actions);
+            });
+            return;
+        }
+
+        self.handle_message(message, actions);
+    }
+
+    /// Count a violation, then either apply the compat handling or, when
+    /// strict, disconnect
+    fn violate(
+        &mut self,
+        violation: ProtocolViolation,
+        reason: String,
+        actions: &mut Vec<ProtocolAction>,
+        tolerate: impl FnOnce(&mut Self, String, &mut Vec<ProtocolAction>),
+    ) {
+        *self.violations.entry(violation).or_default() += 1;
+        let tolerated = self.strictness == ProtocolStrictness::Compat;
+        if tolerated {
+            tolerate(self, reason, actions);
+        } else {
+            self.disconnect(format!("strict protocol: {}", reason), actions);
+        }
+        actions.push(ProtocolAction::Violation { violation, tolerated });
+    }
+
+    fn handle_message(&mut self, message: NetworkMessage, actions: &mut Vec<ProtocolAction>) {
+        match (self.state, message) {
+            (HandshakeState::AwaitingVersion, NetworkMessage::Version(version)) => {
+                if self.local_version.as_ref().is_some_and(|local| local.nonce == version.nonce) {
+                    self.disconnect("connected to self".to_string(), actions);
+                    return;
+                }
+                self.peer_version = Some(version.version);
+                self.peer_user_agent = Some(version.user_agent);
+                self.peer_services = Some(version.services.to_u64());
+                self.state = HandshakeState::AwaitingVerack;
+                if let Some(local) = &self.local_version {
+                    actions.push(ProtocolAction::Send(NetworkMessage::Version(local.clone())));
+                }
+                actions.push(ProtocolAction::Send(NetworkMessage::Verack));
+            }
+            (HandshakeState::AwaitingVersion, other) => {
+                let command = other.cmd().to_string();
+                self.violate(ProtocolViolation::BeforeVersion, format!("{} received before version", command), actions, |_, _, actions| {
+                    actions.push(ProtocolAction::Ignored { command, reason: "received before version".to_string() });
+                });
+            }
+            (_, NetworkMessage::Version(_)) => {
+                self.violate(ProtocolViolation::DuplicateHandshake, "duplicate version message".to_string(), actions, |protocol, reason, actions| {
+                    protocol.misbehave(1, reason, actions);
+                });
+            }
+            (HandshakeState::AwaitingVerack, NetworkMessage::Verack) => {
+                self.state = HandshakeState::Established;
+            }
+            (HandshakeState::AwaitingVerack, other) => {
+                let command = other.cmd().to_string();
+                let ignored = ProtocolAction::Ignored { command: command.clone(), reason: "received before verack".to_string() };
+                // BIP 155, BIP 339 and BIP 330 negotiation belongs between version and verack
+                if matches!(command.as_str(), "sendaddrv2" | "wtxidrelay" | "sendtxrcncl") {
+                    actions.push(ignored);
+                } else {
+                    self.violate(ProtocolViolation::BeforeVerack, format!("{} received before verack", command), actions, |_, _, actions| {
+                        actions.push(ignored);
+                    });
+                }
+            }
+            (_, NetworkMessage::Verack) => {
+                self.violate(ProtocolViolation::DuplicateHandshake, "duplicate verack message".to_string(), actions, |protocol, reason, actions| {
+                    protocol.misbehave(1, reason, actions);
+                });
+            }
+            (_, NetworkMessage::Ping(nonce)) => {
+                actions.push(ProtocolAction::Send(NetworkMessage::Pong(nonce)));
+            }
+            (_, other) => actions.push(ProtocolAction::Deliver(other)),
+        }
+    }
+
+    fn misbehave(&mut self, score: u32, reason: String, actions: &mut Vec<ProtocolAction>) {
+        self.misbehavior = self.misbehavior.saturating_add(score);
+        actions.push(ProtocolAction::Misbehaving { score: self.misbehavior, reason });
+        if self.misbehavior >= MISBEHAVIOR_DISCONNECT_SCORE {
+            self.disconnect("misbehavior threshold reached".to_string(), actions);
+        }
+    }
+
+    fn disconnect(&mut self, reason: String, actions: &mut Vec<ProtocolAction>) {
+        self.state = HandshakeState::Disconnected;
+        self.buffer.clear();
+        actions.push(ProtocolAction::Disconnect { reason });
+    }
+}
+
+/// Printable ASCII, then NUL padding only, as Core's `IsCommandValid` requires
+fn command_is_valid(command: &[u8]) -> bool {
+    let name_len = command.iter().position(|&b| b == 0).unwrap_or(command.len());
+    command[..name_len].iter().all(|&b| (0x20..=0x7e).contains(&b))
+        && command[name_len..].iter().all(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: NetworkMessage) -> Vec<u8> {
+        codec::encode(Magic::REGTEST, message)
+    }
+
+    #[test]
+    fn test_handshake_and_ping() {
+        let now = Instant::now();
+        let mut protocol = PeerProtocol::new(Magic::REGTEST, &PeerRateLimitConfig::default(), now);
+
+        let ping = protocol.receive(&frame(NetworkMessage::Ping(7)), now);
+        assert!(matches!(ping[0], ProtocolAction::Ignored { .. }));
+
+        let mut bytes = frame(NetworkMessage::Verack);
+        bytes.extend(frame(NetworkMessage::Ping(8)));
+        let version = crate::network::replay::version_message(0);
+        let actions = protocol.receive(&frame(NetworkMessage::Version(version)), now);
+        assert_eq!(actions, vec![ProtocolAction::Send(NetworkMessage::Verack)]);
+
+        // Split the remaining bytes mid-message to exercise framing
+        assert!(protocol.receive(&bytes[..30], now).is_empty());
+        let actions = protocol.receive(&bytes[30..], now);
+        assert_eq!(actions, vec![ProtocolAction::Send(NetworkMessage::Pong(8))]);
+        assert_eq!(protocol.state(), HandshakeState::Established);
+    }
+
+    #[test]
+    fn test_responder_sends_version_first() {
+        let now = Instant::now();
+        let mut local = crate::network::replay::version_message(5);
+        local.nonce = 42;
+        let mut protocol = PeerProtocol::new(Magic::REGTEST, &PeerRateLimitConfig::default(), now)
+            .responding_with(local.clone());
+
+        let mut remote = crate::network::replay::version_message(0);
+        remote.nonce = 7;
+        let actions = protocol.receive(&frame(NetworkMessage::Version(remote)), now);
+        assert_eq!(actions, vec![
+            ProtocolAction::Send(NetworkMessage::Version(local.clone())),
+            ProtocolAction::Send(NetworkMessage::Verack),
+        ]);
+        assert_eq!(protocol.peer_user_agent(), Some("/bitknotsrs-replay/"));
+        assert_eq!(protocol.peer_services(), Some(1));
+
+        // Our own nonce coming back means we dialed ourselves
+        let mut looped = PeerProtocol::new(Magic::REGTEST, &PeerRateLimitConfig::default(), now)
+            .responding_with(local.clone());
+        let actions = looped.receive(&frame(NetworkMessage::Version(local)), now);
+        assert!(matches!(actions[..], [ProtocolAction::Disconnect { .. }]));
+    }
+
+    /// Frame an arbitrary payload with a correct length and checksum
+    fn raw_frame(command: &[u8; 12], payload: &[u8]) -> Vec<u8> {
+        let mut bytes = Magic::REGTEST.to_bytes().to_vec();
+        bytes.extend_from_slice(command);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&codec::checksum(payload));
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_strictness_levels() {
+        let now = Instant::now();
+        let established = |strictness| {
+            let mut protocol = PeerProtocol::new(Magic::REGTEST, &PeerRateLimitConfig::default(), now)
+                .with_strictness(strictness);
+            protocol.receive(&frame(NetworkMessage::Version(crate::network::replay::version_message(0))), now);
+            protocol.receive(&frame(NetworkMessage::Verack), now);
+            protocol
+        };
+        let mut padded_ping = 8u64.to_le_bytes().to_vec();
+        padded_ping.extend([0, 0]);
+        let padded_ping = raw_frame(b"ping\0\0\0\0\0\0\0\0", &padded_ping);
+        let bad_command = raw_frame(b"ping\0x\0\0\0\0\0\0", &8u64.to_le_bytes());
+
+        let mut compat = established(ProtocolStrictness::Compat);
+        assert_eq!(compat.receive(&padded_ping, now), vec![
+            ProtocolAction::Send(NetworkMessage::Pong(8)),
+            ProtocolAction::Violation { violation: ProtocolViolation::TrailingData, tolerated: true },
+        ]);
+        let actions = compat.receive(&bad_command, now);
+        assert!(matches!(actions[0], ProtocolAction::Ignored { .. }));
+        assert_eq!(compat.state(), HandshakeState::Established);
+        assert_eq!(compat.violations()[&ProtocolViolation::InvalidCommand], 1);
+
+        let mut strict = established(ProtocolStrictness::Strict);
+        let actions = strict.receive(&padded_ping, now);
+        assert!(matches!(actions[..], [
+            ProtocolAction::Disconnect { .. },
+            ProtocolAction::Violation { violation: ProtocolViolation::TrailingData, tolerated: false },
+        ]));
+        assert_eq!(strict.state(), HandshakeState::Disconnected);
+    }
+
+    #[test]
+    fn test_getdata_is_delivered_after_handshake() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::p2p::message_blockdata::Inventory;
+
+        let now = Instant::now();
+        let mut protocol = PeerProtocol::new(Magic::REGTEST, &PeerRateLimitConfig::default(), now);
+        protocol.receive(&frame(NetworkMessage::Version(crate::network::replay::version_message(0))), now);
+        protocol.receive(&frame(NetworkMessage::Verack), now);
+
+        let items = vec![
+            Inventory::WitnessBlock(bitcoin::BlockHash::all_zeros()),
+            Inventory::WitnessTransaction(bitcoin::Txid::all_zeros()),
+        ];
+        // Served by the connection, which reads the blocks from storage
+        let actions = protocol.receive(&frame(NetworkMessage::GetData(items.clone())), now);
+        assert_eq!(actions, vec![ProtocolAction::Deliver(NetworkMessage::GetData(items))]);
+    }
+
+    #[test]
+    fn test_wrong_magic_disconnects() {
+        let now = Instant::now();
+        let mut protocol = PeerProtocol::new(Magic::BITCOIN, &PeerRateLimitConfig::default(), now);
+        let actions = protocol.receive(&frame(NetworkMessage::Verack), now);
+        assert!(matches!(actions[0], ProtocolAction::Disconnect { .. }));
+        assert_eq!(protocol.state(), HandshakeState::Disconnected);
+        assert!(protocol.receive(&frame(NetworkMessage::Verack), now).is_empty());
+    }
+}
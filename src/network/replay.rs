@@ -0,0 +1,300 @@
+//! Deterministic replay of recorded P2P traffic
+//!
+//! A capture is a small pcap-like file of timestamped byte chunks exchanged
+//! with one peer. Replaying feeds the inbound chunks into [`PeerProtocol`]
+//! using the recorded timestamps as the clock, so a sequence that crashed or
+//! misbehaved under fuzzing reproduces exactly and can be kept as a unit test.
+//!
+//! File layout, all integers little endian:
+//!
+//! ```text
+//! "BKRP" | version: u16 | network magic: [u8; 4]
+//! repeated: timestamp_micros: u64 | direction: u8 | length: u32 | data
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bitcoin::p2p::message::{NetworkMessage, MAX_MSG_SIZE};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+
+use crate::config::{PeerRateLimitConfig, ProtocolStrictness};
+use crate::error::{NetworkError, NetworkResult};
+use super::codec;
+use super::protocol::{HandshakeState, PeerProtocol, ProtocolAction, ProtocolViolation};
+
+pub const CAPTURE_MAGIC: [u8; 4] = *b"BKRP";
+pub const CAPTURE_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// One chunk of bytes as seen on the wire
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub timestamp_micros: u64,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn inbound(timestamp_micros: u64, data: Vec<u8>) -> Self {
+        Self { timestamp_micros, direction: Direction::Inbound, data }
+    }
+
+    /// Inbound record holding a single well-formed message
+    pub fn message(timestamp_micros: u64, magic: Magic, message: NetworkMessage) -> Self {
+        Self::inbound(timestamp_micros, codec::encode(magic, message))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub magic: Magic,
+    pub records: Vec<CaptureRecord>,
+}
+
+impl Capture {
+    pub fn new(magic: Magic) -> Self {
+        Self { magic, records: Vec::new() }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> NetworkResult<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> NetworkResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> NetworkResult<Self> {
+        let mut header = [0u8; 10];
+        reader.read_exact(&mut header)?;
+        if header[..4] != CAPTURE_MAGIC {
+            return Err(NetworkError::InvalidCapture("not a capture file".to_string()));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != CAPTURE_VERSION {
+            return Err(NetworkError::InvalidCapture(format!("unsupported version {}", version)));
+        }
+        let mut capture = Capture::new(Magic::from_bytes([header[6], header[7], header[8], header[9]]));
+
+        loop {
+            let mut record_header = [0u8; 13];
+            match reader.read_exact(&mut record_header[..1]) {
+                Ok(()) => reader.read_exact(&mut record_header[1..])?,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+
+            let timestamp_micros = u64::from_le_bytes(record_header[..8].try_into().expect("8 bytes"));
+            let direction = match record_header[8] {
+                0 => Direction::Inbound,
+                1 => Direction::Outbound,
+                other => return Err(NetworkError::InvalidCapture(format!("invalid direction {}", other))),
+            };
+            // Bound allocations so corrupt captures cannot exhaust memory
+            let length = u32::from_le_bytes(record_header[9..].try_into().expect("4 bytes")) as usize;
+            if length > MAX_MSG_SIZE {
+                return Err(NetworkError::InvalidCapture(format!("record of {} bytes exceeds limit", length)));
+            }
+
+            let mut data = vec![0u8; length];
+            reader.read_exact(&mut data)?;
+            capture.records.push(CaptureRecord { timestamp_micros, direction, data });
+        }
+
+        Ok(capture)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> NetworkResult<()> {
+        writer.write_all(&CAPTURE_MAGIC)?;
+        writer.write_all(&CAPTURE_VERSION.to_le_bytes())?;
+        writer.write_all(&self.magic.to_bytes())?;
+
+        for record in &self.records {
+            writer.write_all(&record.timestamp_micros.to_le_bytes())?;
+            writer.write_all(&[match record.direction {
+                Direction::Inbound => 0,
+                Direction::Outbound => 1,
+            }])?;
+            writer.write_all(&(record.data.len() as u32).to_le_bytes())?;
+            writer.write_all(&record.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Actions produced by one inbound record
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayStep {
+    pub record: usize,
+    pub timestamp_micros: u64,
+    pub actions: Vec<ProtocolAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    pub steps: Vec<ReplayStep>,
+    pub final_state: HandshakeState,
+    pub misbehavior: u32,
+    pub violations: BTreeMap<ProtocolViolation, u64>,
+}
+
+impl ReplayReport {
+    pub fn actions(&self) -> impl Iterator<Item = &ProtocolAction> {
+        self.steps.iter().flat_map(|step| step.actions.iter())
+    }
+
+    /// Reason for the first disconnect, if the peer was dropped
+    pub fn disconnect_reason(&self) -> Option<&str> {
+        self.actions().find_map(|action| match action {
+            ProtocolAction::Disconnect { reason } => Some(reason.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Run the inbound side of a capture through a fresh protocol state machine.
+/// Outbound records are skipped since replies are regenerated by the replay.
+pub fn replay(capture: &Capture, rate_limits: &PeerRateLimitConfig) -> ReplayReport {
+    replay_with_strictness(capture, rate_limits, ProtocolStrictness::default())
+}
+
+/// [`replay`] under the given strictness, e.g. to check how far traffic
+/// recorded from another implementation gets before strict mode drops it
+pub fn replay_with_strictness(
+    capture: &Capture,
+    rate_limits: &PeerRateLimitConfig,
+    strictness: ProtocolStrictness,
+) -> ReplayReport {
+    let start = Instant::now();
+    let mut protocol = PeerProtocol::new(capture.magic, rate_limits, start).with_strictness(strictness);
+    let mut steps = Vec::new();
+
+    for (index, record) in capture.records.iter().enumerate() {
+        if record.direction != Direction::Inbound {
+            continue;
+        }
+        let now = start + Duration::from_micros(record.timestamp_micros);
+        let actions = protocol.receive(&record.data, now);
+        if !actions.is_empty() {
+            steps.push(ReplayStep {
+                record: index,
+                timestamp_micros: record.timestamp_micros,
+                actions,
+            });
+        }
+    }
+
+    ReplayReport {
+        steps,
+        final_state: protocol.state(),
+        misbehavior: protocol.misbehavior(),
+        violations: protocol.violations().clone(),
+    }
+}
+
+/// Minimal `version` message for building handshake sequences
+pub fn version_message(start_height: i32) -> VersionMessage {
+    let unspecified = Address::new(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)), ServiceFlags::NONE);
+    VersionMessage::new(
+        ServiceFlags::NETWORK,
+        0,
+        unspecified.clone(),
+        unspecified,
+        0,
+        "/bitknotsrs-replay/".to_string(),
+        start_height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(magic: Magic) -> Capture {
+        let mut capture = Capture::new(magic);
+        capture.records.push(CaptureRecord::message(0, magic, NetworkMessage::Version(version_message(0))));
+        capture.records.push(CaptureRecord::message(1_000, magic, NetworkMessage::Verack));
+        capture
+    }
+
+    #[test]
+    fn test_capture_round_trip() {
+        let mut capture = handshake(Magic::REGTEST);
+        capture.records.push(CaptureRecord {
+            timestamp_micros: 2_000,
+            direction: Direction::Outbound,
+            data: vec![1, 2, 3],
+        });
+
+        let mut bytes = Vec::new();
+        capture.write_to(&mut bytes).unwrap();
+        assert_eq!(Capture::read_from(&mut bytes.as_slice()).unwrap(), capture);
+
+        // A record cut short is reported rather than silently dropped
+        bytes.truncate(bytes.len() - 1);
+        assert!(Capture::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_addr_flood_replays_deterministically() {
+        let magic = Magic::REGTEST;
+        let mut capture = handshake(magic);
+        for i in 0..200u64 {
+            capture.records.push(CaptureRecord::message(2_000 + i * 10, magic, NetworkMessage::Addr(Vec::new())));
+        }
+
+        let first = replay(&capture, &PeerRateLimitConfig::default());
+        let second = replay(&capture, &PeerRateLimitConfig::default());
+        assert_eq!(first, second);
+        assert_eq!(first.final_state, HandshakeState::Disconnected);
+        assert_eq!(first.disconnect_reason(), Some("rate limit exceeded"));
+    }
+
+    #[test]
+    fn test_strict_replay_stops_at_first_violation() {
+        let magic = Magic::REGTEST;
+        let mut capture = Capture::new(magic);
+        capture.records.push(CaptureRecord::message(0, magic, NetworkMessage::Version(version_message(0))));
+        // Feature negotiation is allowed before verack, other messages are not
+        capture.records.push(CaptureRecord::message(100, magic, NetworkMessage::SendAddrV2));
+        capture.records.push(CaptureRecord::message(200, magic, NetworkMessage::SendHeaders));
+        capture.records.push(CaptureRecord::message(300, magic, NetworkMessage::Verack));
+
+        let compat = replay(&capture, &PeerRateLimitConfig::default());
+        assert_eq!(compat.final_state, HandshakeState::Established);
+        assert_eq!(compat.violations.get(&ProtocolViolation::BeforeVerack), Some(&1));
+
+        let strict = replay_with_strictness(&capture, &PeerRateLimitConfig::default(), ProtocolStrictness::Strict);
+        assert_eq!(strict.final_state, HandshakeState::Disconnected);
+        assert_eq!(strict.disconnect_reason(), Some("strict protocol: sendheaders received before verack"));
+        assert_eq!(strict.violations, compat.violations);
+    }
+
+    #[test]
+    fn test_garbage_payload_is_penalized() {
+        let magic = Magic::REGTEST;
+        let mut capture = handshake(magic);
+        // Valid header claiming a 4 byte payload with a bogus checksum
+        let mut data = codec::encode(magic, NetworkMessage::Ping(0));
+        data[20..24].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        capture.records.push(CaptureRecord::inbound(5_000, data));
+
+        let report = replay(&capture, &PeerRateLimitConfig::default());
+        assert_eq!(report.final_state, HandshakeState::Established);
+        assert_eq!(report.misbehavior, 10);
+    }
+}
@@ -0,0 +1,239 @@
+//! Eclipse and partition heuristics
+//!
+//! A node whose every connection is controlled by one party can be fed a chain
+//! with far less work than the real one, or no new blocks at all. Nothing in
+//! the blocks themselves gives that away, so the monitor compares how fast
+//! chainwork grows with what the tip's difficulty predicts, and checks whether
+//! all peers sit in one network group.
+
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{NetType, PartitionConfig};
+
+/// One reason to suspect we are eclipsed or partitioned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PartitionSignal {
+    /// No new tip for several expected block intervals
+    StaleTip {
+        seconds_since_tip: u64,
+        expected_interval_secs: u64,
+    },
+    /// Chainwork over the window, in difficulty-1 units, fell well short of the tip's difficulty
+    LowChainWork {
+        observed_work: f64,
+        expected_work: f64,
+        window_secs: u64,
+    },
+    /// Every routable peer shares one network group
+    SingleNetgroup {
+        netgroup: String,
+        peer_count: usize,
+    },
+}
+
+impl PartitionSignal {
+    /// Human-readable form for `getnetworkinfo` warnings and logs
+    pub fn describe(&self) -> String {
+        match self {
+            PartitionSignal::StaleTip { seconds_since_tip, expected_interval_secs } => format!(
+                "No new block for {} minutes (one expected every {}); the node may be partitioned",
+                seconds_since_tip / 60,
+                expected_interval_secs / 60,
+            ),
+            PartitionSignal::LowChainWork { observed_work, expected_work, window_secs } => format!(
+                "Chainwork grew by {:.0}% of the expected amount over the last {} minutes; peers may be withholding blocks",
+                observed_work / expected_work * 100.0,
+                window_secs / 60,
+            ),
+            PartitionSignal::SingleNetgroup { netgroup, peer_count } => format!(
+                "All {} peers are in network group {}; the node may be eclipsed",
+                peer_count, netgroup,
+            ),
+        }
+    }
+}
+
+/// Tracks tip arrivals and evaluates the partition heuristics on demand
+pub struct PartitionMonitor {
+    config: PartitionConfig,
+    /// Expected time between blocks; `None` where blocks are mined on demand
+    target_spacing: Option<Duration>,
+    started: Instant,
+    last_tip: Option<Instant>,
+    /// Difficulty of the latest tip, which sets the expected work rate
+    tip_difficulty: Option<f64>,
+    /// Arrival time and difficulty of the tips seen inside the work window, oldest first
+    recent: VecDeque<(Instant, f64)>,
+}
+
+impl PartitionMonitor {
+    pub fn new(config: &PartitionConfig, network: bitcoin::Network, now: Instant) -> Self {
+        let params = bitcoin::consensus::Params::new(network);
+        let target_spacing = (!params.no_pow_retargeting)
+            .then_some(Duration::from_secs(params.pow_target_spacing));
+        Self {
+            config: config.clone(),
+            target_spacing,
+            started: now,
+            last_tip: None,
+            tip_difficulty: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Record a block joining the active chain
+    pub fn tip_advanced(&mut self, now: Instant, difficulty: f64) {
+        self.last_tip = Some(now);
+        self.tip_difficulty = Some(difficulty);
+        self.recent.push_back((now, difficulty));
+    }
+
+    /// Evaluate every heuristic against the current state and peer set
+    pub fn check<'a>(&mut self, now: Instant, peer_hosts: impl IntoIterator<Item = &'a str>) -> Vec<PartitionSignal> {
+        let mut signals = Vec::new();
+        if let Some(spacing) = self.target_spacing {
+            signals.extend(self.stale_tip(now, spacing));
+            signals.extend(self.low_chain_work(now, spacing));
+        }
+        signals.extend(single_netgroup(peer_hosts));
+        signals
+    }
+
+    fn stale_tip(&self, now: Instant, spacing: Duration) -> Option<PartitionSignal> {
+        let since_tip = now.saturating_duration_since(self.last_tip.unwrap_or(self.started));
+        let stale = since_tip.as_secs_f64() > spacing.as_secs_f64() * self.config.stale_tip_intervals;
+        stale.then_some(PartitionSignal::StaleTip {
+            seconds_since_tip: since_tip.as_secs(),
+            expected_interval_secs: spacing.as_secs(),
+        })
+    }
+
+    fn low_chain_work(&mut self, now: Instant, spacing: Duration) -> Option<PartitionSignal> {
+        let window = spacing.mul_f64(self.config.work_window_intervals);
+        while self.recent.front().is_some_and(|(at, _)| now.saturating_duration_since(*at) > window) {
+            self.recent.pop_front();
+        }
+        // Judge only once we have watched a full window
+        if now.saturating_duration_since(self.started) < window {
+            return None;
+        }
+        let tip_difficulty = self.tip_difficulty?;
+
+        let observed_work: f64 = self.recent.iter().map(|(_, difficulty)| difficulty).sum();
+        let expected_work = tip_difficulty * self.config.work_window_intervals;
+        (observed_work < expected_work * self.config.min_work_ratio).then_some(PartitionSignal::LowChainWork {
+            observed_work,
+            expected_work,
+            window_secs: window.as_secs(),
+        })
+    }
+}
+
+/// Group that an address shares with hosts likely run by the same operator:
+/// the /16 for IPv4, the /32 for IPv6 and the first address character for
+/// overlay networks. `None` for loopback and private addresses, which say
+/// nothing about eclipsing.
+pub fn netgroup(host: &str) -> Option<String> {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            if ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() {
+                return None;
+            }
+            let [a, b, _, _] = ip.octets();
+            Some(format!("{}.{}", a, b))
+        }
+        Ok(IpAddr::V6(ip)) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return netgroup(&v4.to_string());
+            }
+            if ip.is_loopback() || ip.is_unspecified() || is_local_v6(&ip) {
+                return None;
+            }
+            let segments = ip.segments();
+            Some(format!("{:x}:{:x}", segments[0], segments[1]))
+        }
+        Err(_) => match NetType::classify(host) {
+            net @ (NetType::Onion | NetType::I2p | NetType::Cjdns) => {
+                Some(format!("{}:{}", net.as_str(), host.chars().next().unwrap_or('_')))
+            }
+            // Unresolved hostname: all we can group by is the name itself
+            _ => Some(host.to_ascii_lowercase()),
+        },
+    }
+}
+
+/// Unique local (fc00::/7) and link-local (fe80::/10) addresses
+fn is_local_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+}
+
+fn single_netgroup<'a>(peer_hosts: impl IntoIterator<Item = &'a str>) -> Option<PartitionSignal> {
+    let groups: Vec<String> = peer_hosts.into_iter().filter_map(netgroup).collect();
+    let first = groups.first()?;
+    (groups.len() >= 2 && groups.iter().all(|group| group == first)).then(|| PartitionSignal::SingleNetgroup {
+        netgroup: first.clone(),
+        peer_count: groups.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACING: Duration = Duration::from_secs(600);
+
+    #[test]
+    fn test_netgroups() {
+        assert_eq!(netgroup("203.0.113.5").as_deref(), Some("203.0"));
+        assert_eq!(netgroup("2001:db8:1::5").as_deref(), Some("2001:db8"));
+        assert_eq!(netgroup("::ffff:198.51.100.7").as_deref(), Some("198.51"));
+        assert_eq!(netgroup("127.0.0.1"), None);
+        assert_eq!(netgroup("192.168.1.10"), None);
+        assert_eq!(netgroup("fd00::1"), None);
+        assert!(netgroup("abcdefghijklmnopqrstuvwxyz234567abcdefghijklmnopqrstuvwx.onion").unwrap().starts_with("onion:a"));
+
+        assert_eq!(single_netgroup(["203.0.113.5", "203.0.7.9"]), Some(PartitionSignal::SingleNetgroup {
+            netgroup: "203.0".to_string(),
+            peer_count: 2,
+        }));
+        assert_eq!(single_netgroup(["203.0.113.5", "198.51.100.7"]), None);
+        // One peer, or only local peers, is not evidence of an eclipse
+        assert_eq!(single_netgroup(["203.0.113.5", "127.0.0.1"]), None);
+    }
+
+    #[test]
+    fn test_stale_tip_and_low_work() {
+        let start = Instant::now();
+        let mut monitor = PartitionMonitor::new(&PartitionConfig::default(), bitcoin::Network::Bitcoin, start);
+        assert!(monitor.check(start + SPACING * 5, []).is_empty());
+
+        let signals = monitor.check(start + SPACING * 7, []);
+        assert!(matches!(signals[..], [PartitionSignal::StaleTip { seconds_since_tip: 4200, .. }]));
+
+        // A steady stream of blocks at the tip's difficulty clears both heuristics
+        for i in 0..12 {
+            monitor.tip_advanced(start + SPACING * (7 + i), 1000.0);
+        }
+        assert!(monitor.check(start + SPACING * 19, []).is_empty());
+
+        // Blocks at a tenth of the expected rate: fresh enough, but far too little work
+        for i in 0..2 {
+            monitor.tip_advanced(start + SPACING * (24 + i * 5), 1000.0);
+        }
+        let signals = monitor.check(start + SPACING * 31, []);
+        assert!(matches!(signals[..], [PartitionSignal::LowChainWork { .. }]), "{:?}", signals);
+    }
+
+    #[test]
+    fn test_regtest_skips_chain_heuristics() {
+        let start = Instant::now();
+        let mut monitor = PartitionMonitor::new(&PartitionConfig::default(), bitcoin::Network::Regtest, start);
+        assert!(monitor.check(start + SPACING * 100, []).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Network composition as seen from this node: peers grouped by user agent,
+//! advertised service bits and protocol version.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// User agents are chosen by the peer, so anything past this length is cut off
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// Service bits with their `getnetworkinfo` names
+pub const SERVICE_FLAGS: [(u64, &str); 7] = [
+    (1 << 0, "NETWORK"),
+    (1 << 1, "GETUTXO"),
+    (1 << 2, "BLOOM"),
+    (1 << 3, "WITNESS"),
+    (1 << 6, "COMPACT_FILTERS"),
+    (1 << 10, "NETWORK_LIMITED"),
+    (1 << 11, "P2P_V2"),
+];
+
+/// What a peer announced in its `version` message
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeerVersion {
+    pub user_agent: Option<String>,
+    pub services: Option<u64>,
+    pub version: Option<u32>,
+}
+
+/// Peer counts per user agent, service bit and protocol version
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeerBreakdown {
+    pub total: u64,
+    pub user_agents: BTreeMap<String, u64>,
+    /// Peers advertising each service bit; a peer is counted under every bit it sets
+    pub services: BTreeMap<String, u64>,
+    pub protocol_versions: BTreeMap<String, u64>,
+}
+
+impl PeerBreakdown {
+    pub fn from_peers<'a>(peers: impl IntoIterator<Item = &'a PeerVersion>) -> Self {
+        let mut breakdown = Self::default();
+        for peer in peers {
+            breakdown.total += 1;
+            *breakdown.user_agents.entry(user_agent_label(peer.user_agent.as_deref())).or_default() += 1;
+            for name in service_names(peer.services) {
+                *breakdown.services.entry(name).or_default() += 1;
+            }
+            let version = peer.version.map(|v| v.to_string()).unwrap_or_else(|| "unknown".to_string());
+            *breakdown.protocol_versions.entry(version).or_default() += 1;
+        }
+        breakdown
+    }
+
+    /// The same counts with user agents collapsed to their client family,
+    /// bounding label cardinality for Prometheus
+    pub fn by_family(&self) -> Self {
+        let mut user_agents = BTreeMap::new();
+        for (user_agent, count) in &self.user_agents {
+            *user_agents.entry(user_agent_family(user_agent)).or_default() += count;
+        }
+        Self { user_agents, ..self.clone() }
+    }
+}
+
+fn user_agent_label(user_agent: Option<&str>) -> String {
+    match user_agent.map(str::trim) {
+        None | Some("") => "unknown".to_string(),
+        Some(user_agent) => user_agent.chars().take(MAX_USER_AGENT_LEN).collect(),
+    }
+}
+
+/// Client name and major.minor version of the first BIP 14 component, so
+/// `/Satoshi:27.1.0(comment)/Knots:20240801/` becomes `Satoshi:27.1`
+pub fn user_agent_family(user_agent: &str) -> String {
+    let Some(component) = user_agent.split('/').find(|part| !part.is_empty()) else {
+        return "unknown".to_string();
+    };
+    let component = component.split('(').next().unwrap_or(component);
+    let (name, version) = component.split_once(':').unwrap_or((component, ""));
+    let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_').take(32).collect();
+    if name.is_empty() {
+        return "other".to_string();
+    }
+
+    let version: Vec<&str> = version.split('.').filter(|part| part.chars().all(|c| c.is_ascii_digit()) && !part.is_empty()).take(2).collect();
+    if version.is_empty() {
+        name
+    } else {
+        format!("{}:{}", name, version.join("."))
+    }
+}
+
+fn service_names(services: Option<u64>) -> Vec<String> {
+    let Some(services) = services else {
+        return vec!["unknown".to_string()];
+    };
+    if services == 0 {
+        return vec!["NONE".to_string()];
+    }
+
+    let mut names: Vec<String> = SERVICE_FLAGS.iter()
+        .filter(|(bit, _)| services & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let known: u64 = SERVICE_FLAGS.iter().map(|(bit, _)| bit).sum();
+    if services & !known != 0 {
+        names.push("OTHER".to_string());
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_family() {
+        assert_eq!(user_agent_family("/Satoshi:27.1.0/"), "Satoshi:27.1");
+        assert_eq!(user_agent_family("/Satoshi:27.1.0(comment)/Knots:20240801/"), "Satoshi:27.1");
+        assert_eq!(user_agent_family("/btcwire:0.5.0/btcd:0.24.0/"), "btcwire:0.5");
+        assert_eq!(user_agent_family("/bitknotsrs/"), "bitknotsrs");
+        assert_eq!(user_agent_family("/\u{1f600}:1.0/"), "other");
+        assert_eq!(user_agent_family(""), "unknown");
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let peers = vec![
+            PeerVersion {
+                user_agent: Some("/Satoshi:27.0.0/".to_string()),
+                services: Some((1 << 0) | (1 << 3) | (1 << 10)),
+                version: Some(70016),
+            },
+            PeerVersion {
+                user_agent: Some("/Satoshi:27.0.1/".to_string()),
+                services: Some((1 << 3) | (1 << 24)),
+                version: Some(70016),
+            },
+            PeerVersion::default(),
+        ];
+        let breakdown = PeerBreakdown::from_peers(&peers);
+        assert_eq!(breakdown.total, 3);
+        assert_eq!(breakdown.user_agents.len(), 3);
+        assert_eq!(breakdown.services["WITNESS"], 2);
+        assert_eq!(breakdown.services["NETWORK_LIMITED"], 1);
+        assert_eq!(breakdown.services["OTHER"], 1);
+        assert_eq!(breakdown.services["unknown"], 1);
+        assert_eq!(breakdown.protocol_versions["70016"], 2);
+
+        let families = breakdown.by_family();
+        assert_eq!(families.user_agents["Satoshi:27.0"], 2);
+        assert_eq!(families.user_agents["unknown"], 1);
+    }
+}
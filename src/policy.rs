@@ -0,0 +1,465 @@
+//! Mempool standardness policy
+//!
+//! A [`PolicyProfile`] picks the defaults for every rule and the operator can
+//! override single fields under `[mempool]`. The resolved [`MempoolPolicy`]
+//! only decides relay and mempool acceptance. [`check_consensus_sanity`]
+//! holds the context-free consensus checks that apply to every lane.
+//! [`BroadcastLimits`] guard the broadcast endpoints against fat-fingered
+//! transactions before they reach the mempool. [`ScriptFilter`] is the
+//! operator's block or allow list of scripts for the relay lane.
+
+use std::collections::HashSet;
+
+use bitcoin::{Amount, Script, ScriptBuf, Transaction, TxOut, Weight};
+
+use crate::amount::{FeeRate, Satoshi};
+use crate::config::{Config, MempoolConfig, PolicyProfile, RbfPolicy, ScriptFilterMode};
+use crate::error::{ConfigResult, PolicyError};
+
+/// Dust relay fee the `dust_value` helper in rust-bitcoin is computed at, in sat/kvB
+const BASE_DUST_RELAY_FEE: u64 = 3000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MempoolPolicy {
+    pub profile: PolicyProfile,
+    pub datacarrier: bool,
+    pub max_datacarrier_bytes: usize,
+    pub dust_relay_fee: u64,
+    pub permit_bare_multisig: bool,
+    pub rbf: RbfPolicy,
+    pub max_standard_tx_weight: u64,
+    pub chain_limits: ChainLimits,
+}
+
+/// Limits on chains of unconfirmed transactions, Core's defaults of 25
+/// transactions and 101kvB in each direction unless overridden
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainLimits {
+    pub ancestor_count: usize,
+    /// Virtual bytes
+    pub ancestor_vsize: u64,
+    pub descendant_count: usize,
+    /// Virtual bytes
+    pub descendant_vsize: u64,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self { ancestor_count: 25, ancestor_vsize: 101_000, descendant_count: 25, descendant_vsize: 101_000 }
+    }
+}
+
+impl MempoolPolicy {
+    pub fn for_profile(profile: PolicyProfile) -> Self {
+        match profile {
+            PolicyProfile::CoreDefault => Self {
+                profile,
+                datacarrier: true,
+                max_datacarrier_bytes: 83,
+                dust_relay_fee: 3000,
+                permit_bare_multisig: true,
+                rbf: RbfPolicy::Full,
+                max_standard_tx_weight: 400_000,
+                chain_limits: ChainLimits::default(),
+            },
+            PolicyProfile::KnotsStrict => Self {
+                profile,
+                datacarrier: true,
+                max_datacarrier_bytes: 42,
+                dust_relay_fee: 3000,
+                permit_bare_multisig: false,
+                rbf: RbfPolicy::Full,
+                max_standard_tx_weight: 400_000,
+                chain_limits: ChainLimits::default(),
+            },
+            PolicyProfile::Permissive => Self {
+                profile,
+                datacarrier: true,
+                max_datacarrier_bytes: 100_000,
+                dust_relay_fee: 0,
+                permit_bare_multisig: true,
+                rbf: RbfPolicy::Full,
+                max_standard_tx_weight: Weight::MAX_BLOCK.to_wu(),
+                chain_limits: ChainLimits::default(),
+            },
+        }
+    }
+
+    /// Profile defaults with any configured overrides applied
+    pub fn from_config(config: &MempoolConfig) -> Self {
+        let defaults = Self::for_profile(config.policy_profile);
+        Self {
+            profile: config.policy_profile,
+            datacarrier: config.datacarrier.unwrap_or(defaults.datacarrier),
+            max_datacarrier_bytes: config.max_datacarrier_bytes.unwrap_or(defaults.max_datacarrier_bytes),
+            dust_relay_fee: config.dust_relay_fee.unwrap_or(defaults.dust_relay_fee),
+            permit_bare_multisig: config.permit_bare_multisig.unwrap_or(defaults.permit_bare_multisig),
+            rbf: config.rbf.unwrap_or(defaults.rbf),
+            max_standard_tx_weight: config.max_standard_tx_weight.unwrap_or(defaults.max_standard_tx_weight),
+            chain_limits: ChainLimits {
+                ancestor_count: config.limit_ancestor_count.unwrap_or(defaults.chain_limits.ancestor_count),
+                ancestor_vsize: config.limit_ancestor_size.map_or(defaults.chain_limits.ancestor_vsize, |kvb| kvb * 1000),
+                descendant_count: config.limit_descendant_count.unwrap_or(defaults.chain_limits.descendant_count),
+                descendant_vsize: config
+                    .limit_descendant_size
+                    .map_or(defaults.chain_limits.descendant_vsize, |kvb| kvb * 1000),
+            },
+        }
+    }
+
+    /// Smallest relayable value for an output, following Core's GetDustThreshold
+    pub fn dust_threshold(&self, script_pubkey: &Script) -> u64 {
+        script_pubkey.dust_value().to_sat() * self.dust_relay_fee / BASE_DUST_RELAY_FEE
+    }
+
+    pub fn check_standard(&self, tx: &Transaction) -> Result<(), PolicyError> {
+        let weight = tx.weight().to_wu();
+        if weight > self.max_standard_tx_weight {
+            return Err(PolicyError::TxTooLarge { weight, max: self.max_standard_tx_weight });
+        }
+
+        let mut datacarrier_bytes = 0;
+        for (vout, output) in tx.output.iter().enumerate() {
+            let script = output.script_pubkey.as_script();
+            if script.is_op_return() {
+                if !self.datacarrier {
+                    return Err(PolicyError::DatacarrierDisabled);
+                }
+                datacarrier_bytes += script.len();
+                continue;
+            }
+            if script.is_multisig() && !self.permit_bare_multisig {
+                return Err(PolicyError::BareMultisig { vout });
+            }
+
+            let threshold = self.dust_threshold(script);
+            if output.value.to_sat() < threshold {
+                return Err(PolicyError::Dust { vout, value: output.value.to_sat(), threshold });
+            }
+        }
+
+        if datacarrier_bytes > self.max_datacarrier_bytes {
+            return Err(PolicyError::DatacarrierTooLarge {
+                size: datacarrier_bytes,
+                max: self.max_datacarrier_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether a mempool transaction may be evicted by a conflicting replacement
+    pub fn allows_replacement(&self, original: &Transaction) -> bool {
+        match self.rbf {
+            RbfPolicy::Full => true,
+            RbfPolicy::OptIn => original.is_explicitly_rbf(),
+            RbfPolicy::Disabled => false,
+        }
+    }
+}
+
+/// Caps `sendrawtransaction` applies on behalf of the caller, Core's defaults
+/// of a 0.10 BTC/kvB fee rate and no value in unspendable outputs unless the
+/// request raises them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BroadcastLimits {
+    /// Zero accepts any fee
+    pub max_fee_rate: FeeRate,
+    /// Most sats an unspendable output may carry
+    pub max_burn_amount: u64,
+}
+
+impl Default for BroadcastLimits {
+    fn default() -> Self {
+        Self { max_fee_rate: FeeRate::from_sat_per_kvb(10_000_000), max_burn_amount: 0 }
+    }
+}
+
+impl BroadcastLimits {
+    /// Limits from the `maxfeerate` (BTC/kvB) and `maxburnamount` (BTC) arguments
+    pub fn from_btc(max_fee_rate: Option<f64>, max_burn_amount: Option<f64>) -> Result<Self, String> {
+        let defaults = Self::default();
+        let max_fee_rate = match max_fee_rate {
+            None => defaults.max_fee_rate,
+            Some(rate) => {
+                let rate = FeeRate::from_btc_per_kvb(rate).ok_or_else(|| "maxfeerate is out of range".to_string())?;
+                if rate.to_sat_per_kvb() >= Amount::ONE_BTC.to_sat() {
+                    return Err("Fee rates larger than or equal to 1BTC/kvB are not accepted".to_string());
+                }
+                rate
+            }
+        };
+        let max_burn_amount = match max_burn_amount {
+            None => defaults.max_burn_amount,
+            Some(amount) => Amount::from_btc(amount).map_err(|_| "maxburnamount is out of range".to_string())?.to_sat(),
+        };
+        Ok(Self { max_fee_rate, max_burn_amount })
+    }
+
+    /// Outputs nobody can spend, OP_RETURN data included, may hold at most `max_burn_amount`
+    pub fn check_burn(&self, tx: &Transaction) -> Result<(), PolicyError> {
+        for (vout, output) in tx.output.iter().enumerate() {
+            let script = output.script_pubkey.as_script();
+            let unspendable = script.is_provably_unspendable() || script.len() > MAX_SCRIPT_SIZE;
+            if unspendable && output.value.to_sat() > self.max_burn_amount {
+                return Err(PolicyError::MaxBurnExceeded { vout, value: output.value.to_sat(), max: self.max_burn_amount });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_fee(&self, tx: &Transaction, fee: Satoshi) -> Result<(), PolicyError> {
+        if self.max_fee_rate == FeeRate::ZERO {
+            return Ok(());
+        }
+        let max = self.max_fee_rate.fee_for(tx.vsize() as u64);
+        if fee > max {
+            return Err(PolicyError::MaxFeeExceeded { fee: fee.to_sat(), max: max.to_sat() });
+        }
+        Ok(())
+    }
+}
+
+/// Relay hook refusing transactions by the scripts they pay to or spend from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptFilter {
+    pub mode: ScriptFilterMode,
+    scripts: HashSet<ScriptBuf>,
+}
+
+impl ScriptFilter {
+    pub fn new(config: &Config) -> ConfigResult<Self> {
+        let filter = &config.mempool.script_filter;
+        let scripts = match filter.mode {
+            ScriptFilterMode::Off => HashSet::new(),
+            _ => filter.script_pubkeys(config.network.to_bitcoin_network())?,
+        };
+        Ok(Self { mode: filter.mode, scripts })
+    }
+
+    /// Listed scriptPubKeys, xpub derivations included
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Check the outputs `tx` spends, in input order, and its own outputs.
+    /// OP_RETURN outputs carry data rather than pay anyone, so they pass.
+    pub fn check(&self, tx: &Transaction, spent_outputs: &[TxOut]) -> Result<(), PolicyError> {
+        if self.mode == ScriptFilterMode::Off {
+            return Ok(());
+        }
+        let touched = spent_outputs.iter()
+            .chain(&tx.output)
+            .map(|output| output.script_pubkey.as_script())
+            .filter(|script| !script.is_op_return());
+        for script in touched {
+            let listed = self.scripts.contains(script);
+            let refused = match self.mode {
+                ScriptFilterMode::Off => false,
+                ScriptFilterMode::Blocklist => listed,
+                ScriptFilterMode::Allowlist => !listed,
+            };
+            if refused {
+                return Err(PolicyError::ScriptFiltered { mode: self.mode.as_str(), script: script.to_hex_string() });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scripts past this size can never be executed
+const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Context-free consensus checks, Core's CheckTransaction.
+/// Script and input validation happen against the UTXO set elsewhere.
+pub fn check_consensus_sanity(tx: &Transaction) -> Result<(), PolicyError> {
+    if tx.input.is_empty() {
+        return Err(PolicyError::Consensus("bad-txns-vin-empty"));
+    }
+    if tx.output.is_empty() {
+        return Err(PolicyError::Consensus("bad-txns-vout-empty"));
+    }
+    if tx.weight() > Weight::MAX_BLOCK {
+        return Err(PolicyError::Consensus("bad-txns-oversize"));
+    }
+    if tx.is_coinbase() {
+        return Err(PolicyError::Consensus("coinbase"));
+    }
+
+    let mut total = Amount::ZERO;
+    for output in &tx.output {
+        total = match total.checked_add(output.value) {
+            Some(total) if output.value <= Amount::MAX_MONEY && total <= Amount::MAX_MONEY => total,
+            _ => return Err(PolicyError::Consensus("bad-txns-txouttotal-toolarge")),
+        };
+    }
+
+    let mut seen = HashSet::new();
+    if !tx.input.iter().all(|input| seen.insert(input.previous_output)) {
+        return Err(PolicyError::Consensus("bad-txns-inputs-duplicate"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::transaction::Version;
+    use bitcoin::{OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+
+    fn tx_with_outputs(outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: outputs,
+        }
+    }
+
+    fn op_return(len: usize) -> TxOut {
+        let data = PushBytesBuf::try_from(vec![0u8; len]).unwrap();
+        TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::new_op_return(&data) }
+    }
+
+    fn p2wpkh(value: u64) -> TxOut {
+        let mut script = vec![0x00, 0x14];
+        script.extend([0u8; 20]);
+        TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::from_bytes(script) }
+    }
+
+    #[test]
+    fn test_broadcast_limits() {
+        let burn = TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::new_op_return([1, 2, 3]) };
+        let payment = p2wpkh(50_000);
+        let tx = tx_with_outputs(vec![payment.clone(), burn]);
+
+        let limits = BroadcastLimits::default();
+        assert_eq!(limits.check_burn(&tx), Err(PolicyError::MaxBurnExceeded { vout: 1, value: 1000, max: 0 }));
+        assert!(limits.check_burn(&tx_with_outputs(vec![payment])).is_ok());
+        assert!(BroadcastLimits::from_btc(None, Some(0.00001)).unwrap().check_burn(&tx).is_ok());
+
+        // 0.10 BTC/kvB is 10,000 sat/vB
+        let max = 10_000 * tx.vsize() as u64;
+        assert!(limits.check_fee(&tx, Satoshi(max)).is_ok());
+        assert_eq!(limits.check_fee(&tx, Satoshi(max + 1)), Err(PolicyError::MaxFeeExceeded { fee: max + 1, max }));
+        assert!(BroadcastLimits::from_btc(Some(0.0), None).unwrap().check_fee(&tx, Amount::MAX_MONEY.into()).is_ok());
+
+        assert_eq!(BroadcastLimits::from_btc(Some(0.0001), None).unwrap().max_fee_rate, FeeRate::from_sat_per_kvb(10_000));
+        assert!(BroadcastLimits::from_btc(Some(1.0), None).is_err());
+        assert!(BroadcastLimits::from_btc(Some(-0.1), None).is_err());
+        assert!(BroadcastLimits::from_btc(None, Some(-1.0)).is_err());
+    }
+
+    #[test]
+    fn test_script_filter() {
+        let mut config = Config::default_regtest();
+        let listed = p2wpkh(50_000);
+        let mut other = p2wpkh(50_000);
+        other.script_pubkey = ScriptBuf::from_bytes([[0x00, 0x14].as_slice(), &[1; 20]].concat());
+        config.mempool.script_filter.scripts = vec![listed.script_pubkey.to_hex_string()];
+        let data = TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::new_op_return([1]) };
+        let paying_listed = tx_with_outputs(vec![listed.clone(), data]);
+        let paying_other = tx_with_outputs(vec![other.clone()]);
+
+        // Lists only apply once a mode is chosen
+        let off = ScriptFilter::new(&config).unwrap();
+        assert!(off.is_empty() && off.check(&paying_listed, &[]).is_ok());
+
+        config.mempool.script_filter.mode = ScriptFilterMode::Blocklist;
+        let blocklist = ScriptFilter::new(&config).unwrap();
+        assert!(matches!(blocklist.check(&paying_listed, &[]), Err(PolicyError::ScriptFiltered { mode: "blocklist", .. })));
+        assert!(blocklist.check(&paying_other, &[]).is_ok());
+        // Spending from a listed script counts too
+        assert!(blocklist.check(&paying_other, std::slice::from_ref(&listed)).is_err());
+
+        config.mempool.script_filter.mode = ScriptFilterMode::Allowlist;
+        let allowlist = ScriptFilter::new(&config).unwrap();
+        assert!(allowlist.check(&paying_listed, std::slice::from_ref(&listed)).is_ok());
+        assert!(allowlist.check(&paying_other, &[listed]).is_err());
+
+        // xpubs list both chains and address kinds up to the lookahead
+        let xpub = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+        config.mempool.script_filter.scripts.clear();
+        config.mempool.script_filter.xpubs = vec![xpub.to_string()];
+        config.mempool.script_filter.xpub_lookahead = 3;
+        assert_eq!(ScriptFilter::new(&config).unwrap().len(), 2 * 3 * 2);
+
+        config.mempool.script_filter.addresses = vec!["not-an-address".to_string()];
+        assert!(ScriptFilter::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_consensus_sanity() {
+        let mut tx = tx_with_outputs(vec![p2wpkh(1)]);
+        tx.input[0].previous_output.vout = 0;
+        assert!(check_consensus_sanity(&tx).is_ok());
+
+        tx.input.push(tx.input[0].clone());
+        assert_eq!(check_consensus_sanity(&tx), Err(PolicyError::Consensus("bad-txns-inputs-duplicate")));
+
+        let coinbase = tx_with_outputs(vec![p2wpkh(1)]);
+        assert_eq!(check_consensus_sanity(&coinbase), Err(PolicyError::Consensus("coinbase")));
+    }
+
+    #[test]
+    fn test_overrides_apply_on_top_of_profile() {
+        let config = MempoolConfig {
+            policy_profile: PolicyProfile::KnotsStrict,
+            permit_bare_multisig: Some(true),
+            ..Default::default()
+        };
+        let policy = MempoolPolicy::from_config(&config);
+
+        assert_eq!(policy.max_datacarrier_bytes, 42);
+        assert!(policy.permit_bare_multisig);
+        assert_eq!(MempoolPolicy::from_config(&MempoolConfig::default()),
+                   MempoolPolicy::for_profile(PolicyProfile::CoreDefault));
+    }
+
+    #[test]
+    fn test_datacarrier_limits_differ_by_profile() {
+        let tx = tx_with_outputs(vec![op_return(60), p2wpkh(10_000)]);
+
+        assert!(MempoolPolicy::for_profile(PolicyProfile::CoreDefault).check_standard(&tx).is_ok());
+        assert_eq!(
+            MempoolPolicy::for_profile(PolicyProfile::KnotsStrict).check_standard(&tx),
+            Err(PolicyError::DatacarrierTooLarge { size: 62, max: 42 })
+        );
+
+        let config = MempoolConfig { datacarrier: Some(false), ..Default::default() };
+        assert_eq!(MempoolPolicy::from_config(&config).check_standard(&tx), Err(PolicyError::DatacarrierDisabled));
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        let core = MempoolPolicy::for_profile(PolicyProfile::CoreDefault);
+        // 294 sat is Core's well known P2WPKH dust limit at 3 sat/vB
+        assert_eq!(core.dust_threshold(&p2wpkh(0).script_pubkey), 294);
+        assert!(matches!(core.check_standard(&tx_with_outputs(vec![p2wpkh(293)])), Err(PolicyError::Dust { .. })));
+
+        let permissive = MempoolPolicy::for_profile(PolicyProfile::Permissive);
+        assert!(permissive.check_standard(&tx_with_outputs(vec![p2wpkh(1)])).is_ok());
+    }
+
+    #[test]
+    fn test_rbf_rules() {
+        let final_tx = tx_with_outputs(vec![p2wpkh(10_000)]);
+        let mut signalling = final_tx.clone();
+        signalling.input[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+
+        let opt_in = MempoolPolicy::from_config(&MempoolConfig { rbf: Some(RbfPolicy::OptIn), ..Default::default() });
+        assert!(opt_in.allows_replacement(&signalling));
+        assert!(!opt_in.allows_replacement(&final_tx));
+        assert!(MempoolPolicy::for_profile(PolicyProfile::CoreDefault).allows_replacement(&final_tx));
+    }
+}
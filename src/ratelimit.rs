@@ -0,0 +1,186 @@
+//! Per-peer P2P message rate limiting
+//!
+//! Each peer gets token buckets for message counts (split by message class)
+//! and for bytes received. Exceeding a bucket throttles the message and adds
+//! misbehavior score; peers crossing the threshold are disconnected.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::config::{MessageRateLimit, PeerRateLimitConfig};
+
+/// Refilling token bucket allowing `rate` units per second with bursts up to `burst`
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: f64, now: Instant) -> Self {
+        Self {
+            capacity: burst,
+            tokens: burst,
+            refill_per_sec: rate,
+            last_refill: now,
+        }
+    }
+
+    /// Take `amount` tokens if available
+    pub fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Message groups limited separately, since address and inventory floods are the cheap attacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageClass {
+    Addr,
+    Inv,
+    Headers,
+    Other,
+}
+
+impl MessageClass {
+    pub fn from_command(command: &str) -> Self {
+        match command {
+            "addr" | "addrv2" => MessageClass::Addr,
+            "inv" | "getdata" | "notfound" => MessageClass::Inv,
+            "headers" | "getheaders" => MessageClass::Headers,
+            _ => MessageClass::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageClass::Addr => "addr",
+            MessageClass::Inv => "inv",
+            MessageClass::Headers => "headers",
+            MessageClass::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitVerdict {
+    Accept,
+    /// Drop the message but keep the peer
+    Throttle { misbehavior: u32 },
+    /// Misbehavior crossed the threshold
+    Disconnect { misbehavior: u32 },
+}
+
+/// Rate limiting state for a single peer connection
+#[derive(Debug, Clone)]
+pub struct PeerRateLimiter {
+    messages: HashMap<MessageClass, TokenBucket>,
+    bytes: TokenBucket,
+    misbehavior: u32,
+    score_per_violation: u32,
+    disconnect_threshold: u32,
+}
+
+impl PeerRateLimiter {
+    pub fn new(config: &PeerRateLimitConfig, now: Instant) -> Self {
+        let bucket = |limit: &MessageRateLimit| TokenBucket::new(limit.per_second, limit.burst, now);
+        let messages = HashMap::from([
+            (MessageClass::Addr, bucket(&config.addr)),
+            (MessageClass::Inv, bucket(&config.inv)),
+            (MessageClass::Headers, bucket(&config.headers)),
+            (MessageClass::Other, bucket(&config.other)),
+        ]);
+
+        Self {
+            messages,
+            bytes: TokenBucket::new(config.bytes_per_second, config.bytes_burst, now),
+            misbehavior: 0,
+            score_per_violation: config.misbehavior_per_violation,
+            disconnect_threshold: config.disconnect_threshold,
+        }
+    }
+
+    /// Account for a received message and decide what to do with it
+    pub fn check(&mut self, command: &str, bytes: usize, now: Instant) -> RateLimitVerdict {
+        let class = MessageClass::from_command(command);
+        let message_ok = self.messages
+            .get_mut(&class)
+            .map(|bucket| bucket.try_take(1.0, now))
+            .unwrap_or(true);
+        let bytes_ok = self.bytes.try_take(bytes as f64, now);
+
+        if message_ok && bytes_ok {
+            return RateLimitVerdict::Accept;
+        }
+
+        self.misbehavior = self.misbehavior.saturating_add(self.score_per_violation);
+        if self.misbehavior >= self.disconnect_threshold {
+            RateLimitVerdict::Disconnect { misbehavior: self.misbehavior }
+        } else {
+            RateLimitVerdict::Throttle { misbehavior: self.misbehavior }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1.0, 2.0, start);
+
+        assert!(bucket.try_take(1.0, start));
+        assert!(bucket.try_take(1.0, start));
+        assert!(!bucket.try_take(1.0, start));
+        assert!(bucket.try_take(1.0, start + Duration::from_secs(1)));
+        // Refill never exceeds the burst size
+        assert!(bucket.try_take(2.0, start + Duration::from_secs(60)));
+        assert!(!bucket.try_take(1.0, start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_flood_leads_to_disconnect() {
+        let config = PeerRateLimitConfig {
+            addr: MessageRateLimit { per_second: 0.0, burst: 1.0 },
+            misbehavior_per_violation: 50,
+            disconnect_threshold: 100,
+            ..Default::default()
+        };
+
+        let now = Instant::now();
+        let mut limiter = PeerRateLimiter::new(&config, now);
+
+        assert_eq!(limiter.check("addr", 100, now), RateLimitVerdict::Accept);
+        assert_eq!(limiter.check("addr", 100, now), RateLimitVerdict::Throttle { misbehavior: 50 });
+        // Other message classes have their own buckets
+        assert_eq!(limiter.check("ping", 8, now), RateLimitVerdict::Accept);
+        assert_eq!(limiter.check("addrv2", 100, now), RateLimitVerdict::Disconnect { misbehavior: 100 });
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let config = PeerRateLimitConfig {
+            bytes_per_second: 0.0,
+            bytes_burst: 1000.0,
+            ..Default::default()
+        };
+
+        let now = Instant::now();
+        let mut limiter = PeerRateLimiter::new(&config, now);
+        assert_eq!(limiter.check("block", 900, now), RateLimitVerdict::Accept);
+        assert!(matches!(limiter.check("block", 900, now), RateLimitVerdict::Throttle { .. }));
+    }
+}
@@ -0,0 +1,181 @@
+//! RPC authentication and method allowlisting
+//!
+//! Requests need HTTP basic auth with `rpc.user` / `rpc.password`. Without a
+//! configured password the server writes a `.cookie` file to the datadir, as
+//! bitcoind does, holding `__cookie__:<random hex>`; local clients read it to
+//! authenticate and it is removed again on shutdown. A non-empty
+//! `rpc.allowed_methods` limits authenticated callers to those methods.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bitcoin::base64::engine::general_purpose::STANDARD;
+use bitcoin::base64::Engine;
+use bitcoin::hex::DisplayHex;
+use jsonrpc_http_server::hyper;
+use serde_json::Value;
+
+use crate::config::Config;
+
+pub const COOKIE_USER: &str = "__cookie__";
+const COOKIE_FILE: &str = ".cookie";
+
+pub fn cookie_path(datadir: &Path) -> PathBuf {
+    datadir.join(COOKIE_FILE)
+}
+
+/// The user and password in a cookie file written by a running node
+pub fn read_cookie(datadir: &Path) -> std::io::Result<(String, String)> {
+    let cookie = std::fs::read_to_string(cookie_path(datadir))?;
+    let (user, password) = cookie.trim().split_once(':').ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "cookie file is not user:password")
+    })?;
+    Ok((user.to_string(), password.to_string()))
+}
+
+/// Write a fresh cookie, readable by the node's user only, and return its password
+fn write_cookie(path: &Path) -> std::io::Result<String> {
+    let password = crate::identity::random_bytes().to_lower_hex_string();
+
+    // Written aside and renamed so a client never reads half a cookie
+    let temp = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temp)?;
+    write!(file, "{}:{}", COOKIE_USER, password)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(password)
+}
+
+/// Compare without returning at the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+pub struct RpcAuth {
+    /// `user:password` as it appears in the decoded Authorization header
+    credentials: String,
+    /// Set when the credentials come from a cookie this server wrote
+    cookie: Option<PathBuf>,
+    /// Every method is callable when `None`
+    allowed_methods: Option<HashSet<String>>,
+}
+
+impl RpcAuth {
+    /// Credentials from the config, or a new cookie file when no password is set
+    pub fn new(config: &Config) -> std::io::Result<Self> {
+        let (credentials, cookie) = match (&config.rpc.user, &config.rpc.password) {
+            (Some(user), Some(password)) => (format!("{}:{}", user, password), None),
+            _ => {
+                let path = cookie_path(&config.datadir);
+                let password = write_cookie(&path)?;
+                (format!("{}:{}", COOKIE_USER, password), Some(path))
+            }
+        };
+        let allowed_methods = (!config.rpc.allowed_methods.is_empty())
+            .then(|| config.rpc.allowed_methods.iter().cloned().collect());
+        Ok(Self { credentials, cookie, allowed_methods })
+    }
+
+    pub fn cookie(&self) -> Option<&Path> {
+        self.cookie.as_deref()
+    }
+
+    /// Whether an Authorization header carries the expected basic credentials
+    pub fn authorized(&self, header: Option<&hyper::header::HeaderValue>) -> bool {
+        self.authorized_basic(header.and_then(|value| value.to_str().ok()))
+    }
+
+    /// [`Self::authorized`] for a header value taken from another HTTP stack
+    pub fn authorized_basic(&self, header: Option<&str>) -> bool {
+        let decoded = header
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok());
+        decoded.is_some_and(|decoded| constant_time_eq(&decoded, self.credentials.as_bytes()))
+    }
+
+    /// The first method in a request or batch that `allowed_methods` leaves out.
+    /// Bodies that are not JSON are left for the handler to reject.
+    pub fn refused_method(&self, body: &[u8]) -> Option<String> {
+        let allowed = self.allowed_methods.as_ref()?;
+        let request: Value = serde_json::from_slice(body).ok()?;
+        let calls = match &request {
+            Value::Array(calls) => calls.iter().collect(),
+            call => vec![call],
+        };
+        calls.into_iter()
+            .filter_map(|call| call.get("method").and_then(Value::as_str))
+            .find(|method| !allowed.contains(*method))
+            .map(str::to_string)
+    }
+
+    /// Remove the cookie this server wrote, if any
+    pub fn remove_cookie(&self) {
+        if let Some(path) = &self.cookie {
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!("Could not remove RPC cookie {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> hyper::header::HeaderValue {
+        format!("Basic {}", STANDARD.encode(credentials)).parse().unwrap()
+    }
+
+    #[test]
+    fn test_configured_credentials_and_allowed_methods() {
+        let mut config = Config::default_regtest();
+        config.rpc.allowed_methods = vec!["getblockcount".to_string(), "help".to_string()];
+        let auth = RpcAuth::new(&config).unwrap();
+        assert!(auth.cookie().is_none());
+
+        assert!(auth.authorized(Some(&basic("user:pass"))));
+        assert!(!auth.authorized(Some(&basic("user:wrong"))));
+        assert!(!auth.authorized(Some(&"Bearer user:pass".parse().unwrap())));
+        assert!(!auth.authorized(None));
+
+        assert_eq!(auth.refused_method(br#"{"method":"getblockcount","params":[]}"#), None);
+        let batch = br#"[{"method":"help"},{"method":"sendrawtransaction","params":["00"]}]"#;
+        assert_eq!(auth.refused_method(batch).as_deref(), Some("sendrawtransaction"));
+        assert_eq!(auth.refused_method(b"not json"), None);
+
+        config.rpc.allowed_methods.clear();
+        assert_eq!(RpcAuth::new(&config).unwrap().refused_method(batch), None);
+    }
+
+    #[test]
+    fn test_cookie_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default_regtest();
+        config.datadir = dir.path().to_path_buf();
+        config.rpc.password = None;
+
+        let auth = RpcAuth::new(&config).unwrap();
+        let (user, password) = read_cookie(dir.path()).unwrap();
+        assert_eq!(user, COOKIE_USER);
+        assert_eq!(password.len(), 64);
+        assert!(auth.authorized(Some(&basic(&format!("{}:{}", user, password)))));
+        assert!(!auth.authorized(Some(&basic("user:pass"))));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(cookie_path(dir.path())).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A restart replaces the cookie
+        let restarted = RpcAuth::new(&config).unwrap();
+        assert_ne!(read_cookie(dir.path()).unwrap().1, password);
+        restarted.remove_cookie();
+        assert!(!cookie_path(dir.path()).exists());
+    }
+}
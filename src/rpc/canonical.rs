@@ -0,0 +1,232 @@
+//! Bitcoin Core's text layout for `getblock` and `getrawtransaction`
+//!
+//! serde_json writes object keys sorted and amounts as the shortest float
+//! that round-trips (`50.0`, `1e-8`). Both parse to the same values, but
+//! clients that diff against bitcoind or scan the text by hand expect
+//! Core's layout. With `rpc.json_format = "core"` responses to those two
+//! methods are rewritten with the keys in the order Core writes them and
+//! amounts as 8-decimal numbers (`50.00000000`); `"sats"` keeps the order
+//! and writes amounts as integer satoshis. Hex is lowercase either way, as
+//! in Core. Other methods and the default `"native"` format are untouched.
+
+use serde_json::{Map, Value};
+
+use crate::config::RpcJsonFormat;
+
+/// What an object in the response is, which decides its key order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    /// A response to a method that is passed through
+    Response,
+    BlockResponse,
+    TxResponse,
+    Block,
+    Tx,
+    Input,
+    Output,
+    Script,
+    Other,
+}
+
+const RESPONSE_KEYS: &[&str] = &["jsonrpc", "result", "error", "id"];
+
+/// `blockheaderToJSON` followed by `blockToJSON`
+const BLOCK_KEYS: &[&str] = &[
+    "hash", "confirmations", "height", "version", "versionHex", "merkleroot", "time", "mediantime",
+    "nonce", "bits", "difficulty", "chainwork", "nTx", "previousblockhash", "nextblockhash",
+    "strippedsize", "size", "weight", "tx",
+];
+
+/// `TxToUniv`, then the block fields `getrawtransaction` adds for confirmed transactions
+const TX_KEYS: &[&str] = &[
+    "txid", "hash", "version", "size", "vsize", "weight", "locktime", "vin", "vout", "fee", "hex",
+    "blockhash", "confirmations", "time", "blocktime",
+];
+
+const INPUT_KEYS: &[&str] = &["coinbase", "txid", "vout", "scriptSig", "txinwitness", "sequence"];
+
+const OUTPUT_KEYS: &[&str] = &["value", "n", "scriptPubKey"];
+
+const SCRIPT_KEYS: &[&str] = &["asm", "desc", "hex", "address", "type"];
+
+impl Shape {
+    fn keys(self) -> &'static [&'static str] {
+        match self {
+            Shape::Response | Shape::BlockResponse | Shape::TxResponse => RESPONSE_KEYS,
+            Shape::Block => BLOCK_KEYS,
+            Shape::Tx => TX_KEYS,
+            Shape::Input => INPUT_KEYS,
+            Shape::Output => OUTPUT_KEYS,
+            Shape::Script => SCRIPT_KEYS,
+            Shape::Other => &[],
+        }
+    }
+
+    /// The shape of the value under `key`, or of its elements when it is an array
+    fn child(self, key: &str) -> Shape {
+        match (self, key) {
+            (Shape::BlockResponse, "result") => Shape::Block,
+            (Shape::TxResponse, "result") => Shape::Tx,
+            (Shape::Block, "tx") => Shape::Tx,
+            (Shape::Tx, "vin") => Shape::Input,
+            (Shape::Tx, "vout") => Shape::Output,
+            (Shape::Input, "scriptSig") | (Shape::Output, "scriptPubKey") => Shape::Script,
+            _ => Shape::Other,
+        }
+    }
+
+    fn is_amount(self, key: &str) -> bool {
+        matches!((self, key), (Shape::Output, "value") | (Shape::Tx, "fee"))
+    }
+}
+
+fn response_shape(method: &str) -> Option<Shape> {
+    match method {
+        "getblock" => Some(Shape::BlockResponse),
+        "getrawtransaction" => Some(Shape::TxResponse),
+        _ => None,
+    }
+}
+
+/// Rewrite the handler's `response` to `request` in `format`, leaving it as
+/// it is when the format is native or no call in the request is rewritten
+pub fn rewrite_response(format: RpcJsonFormat, request: &str, response: String) -> String {
+    if format == RpcJsonFormat::Native {
+        return response;
+    }
+    let Ok(request) = serde_json::from_str::<Value>(request) else {
+        return response;
+    };
+    let calls = match &request {
+        Value::Array(calls) => calls.iter().collect(),
+        call => vec![call],
+    };
+    let shapes: Vec<(&Value, Shape)> = calls.into_iter()
+        .filter_map(|call| Some((call.get("id")?, response_shape(call.get("method")?.as_str()?)?)))
+        .collect();
+    if shapes.is_empty() {
+        return response;
+    }
+    let Ok(outputs) = serde_json::from_str::<Value>(&response) else {
+        return response;
+    };
+
+    let shape_of = |output: &Value| {
+        let id = output.get("id");
+        shapes.iter().find(|(known, _)| Some(*known) == id).map_or(Shape::Response, |(_, shape)| *shape)
+    };
+    let mut text = String::with_capacity(response.len());
+    match &outputs {
+        Value::Array(outputs) => {
+            text.push('[');
+            for (index, output) in outputs.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                write(output, shape_of(output), format, &mut text);
+            }
+            text.push(']');
+        }
+        output => write(output, shape_of(output), format, &mut text),
+    }
+    text
+}
+
+fn write(value: &Value, shape: Shape, format: RpcJsonFormat, text: &mut String) {
+    match value {
+        Value::Object(fields) => write_object(fields, shape, format, text),
+        Value::Array(items) => {
+            text.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    text.push(',');
+                }
+                write(item, shape, format, text);
+            }
+            text.push(']');
+        }
+        scalar => text.push_str(&scalar.to_string()),
+    }
+}
+
+fn write_object(fields: &Map<String, Value>, shape: Shape, format: RpcJsonFormat, text: &mut String) {
+    let order = shape.keys();
+    // Stable, so keys Core does not write stay sorted after the ones it does
+    let mut keys: Vec<&String> = fields.keys().collect();
+    keys.sort_by_key(|key| order.iter().position(|known| *known == key.as_str()).unwrap_or(order.len()));
+
+    text.push('{');
+    for (index, key) in keys.into_iter().enumerate() {
+        if index > 0 {
+            text.push(',');
+        }
+        text.push_str(&Value::String(key.clone()).to_string());
+        text.push(':');
+        let value = &fields[key];
+        match value.as_f64().and_then(|btc| bitcoin::Amount::from_btc(btc).ok()) {
+            Some(amount) if shape.is_amount(key) => text.push_str(&format_amount(amount, format)),
+            _ => write(value, shape.child(key), format, text),
+        }
+    }
+    text.push('}');
+}
+
+/// `50.00000000` as Core's `ValueFromAmount` writes it, or `5000000000` in sats
+fn format_amount(amount: bitcoin::Amount, format: RpcJsonFormat) -> String {
+    let sats = amount.to_sat();
+    match format {
+        RpcJsonFormat::Sats => sats.to_string(),
+        _ => format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn coinbase_tx() -> Value {
+        let block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+        super::super::tx_json(&block.txdata[0], bitcoin::Network::Regtest)
+    }
+
+    #[test]
+    fn test_getrawtransaction_in_core_layout() {
+        let request = r#"{"jsonrpc":"1.0","id":"curltest","method":"getrawtransaction","params":["4a5e",true]}"#;
+        let response = json!({ "result": coinbase_tx(), "error": null, "id": "curltest" }).to_string();
+
+        let core = rewrite_response(RpcJsonFormat::Core, request, response.clone());
+        assert!(core.starts_with(r#"{"result":{"txid":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","hash":"#));
+        assert!(core.contains(r#""locktime":0,"vin":[{"coinbase":"04ffff001d"#));
+        assert!(core.contains(r#""vout":[{"value":50.00000000,"n":0,"scriptPubKey":{"asm":"#));
+        assert!(core.ends_with(r#""error":null,"id":"curltest"}"#));
+        // Same values, only the text differs
+        assert_eq!(serde_json::from_str::<Value>(&core).unwrap(), serde_json::from_str::<Value>(&response).unwrap());
+
+        let sats = rewrite_response(RpcJsonFormat::Sats, request, response.clone());
+        assert!(sats.contains(r#"{"value":5000000000,"n":0,"#));
+
+        assert_eq!(rewrite_response(RpcJsonFormat::Native, request, response.clone()), response);
+    }
+
+    #[test]
+    fn test_only_block_and_transaction_results_are_rewritten() {
+        let request = r#"[{"jsonrpc":"2.0","id":1,"method":"getblock","params":["00",2]},{"jsonrpc":"2.0","id":2,"method":"gettxout","params":["00",0]}]"#;
+        let block = json!({ "tx": [coinbase_tx()], "hash": "0f9188f1", "height": 0, "confirmations": 1, "nTx": 1 });
+        let txout = json!({ "value": 0.5, "bestblock": "0f9188f1" });
+        let response = json!([
+            { "jsonrpc": "2.0", "id": 1, "result": block },
+            { "jsonrpc": "2.0", "id": 2, "result": txout },
+        ]).to_string();
+
+        let core = rewrite_response(RpcJsonFormat::Core, request, response);
+        assert!(core.starts_with(r#"[{"jsonrpc":"2.0","result":{"hash":"0f9188f1","confirmations":1,"height":0,"nTx":1,"tx":[{"txid":"#));
+        assert!(core.contains(r#""value":50.00000000"#));
+        assert!(core.ends_with(r#"{"jsonrpc":"2.0","result":{"bestblock":"0f9188f1","value":0.5},"id":2}]"#));
+
+        // Nothing to rewrite
+        let request = r#"{"jsonrpc":"2.0","id":1,"method":"gettxout","params":["00",0]}"#;
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": txout }).to_string();
+        assert_eq!(rewrite_response(RpcJsonFormat::Core, request, response.clone()), response);
+    }
+}
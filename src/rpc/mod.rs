@@ -0,0 +1,1947 @@
+pub mod auth;
+pub mod canonical;
+pub mod registry;
+pub mod ws;
+
+use jsonrpc_core::{IoHandler, Params, Result as RpcResult, Value};
+use jsonrpc_http_server::{hyper, RequestMiddlewareAction, ServerBuilder, Server};
+use bitcoin::hex::DisplayHex;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{info, warn, error, Instrument};
+
+use actix::Addr;
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::{ExpireMempool, ReloadMempoolPolicy, GetMempoolEntry, GetStaleBlocks, GetUtxoSetInfo};
+use crate::actors::{GetMempoolAncestors, GetMempoolDescendants};
+use crate::actors::{AcceptReorg, WaitForHeight};
+use crate::actors::{EstimateSmartFee, GetFromMempool, GetMempoolSpends};
+use crate::actors::{BlockHeaderInfo, ChainTip, GetBlock, GetBlockHash, GetBlockHeaderInfo, GetBlockLocations, GetChainTip};
+use crate::actors::{GetBestHeader, GetChainstates, GetNetworkWarnings, GetPeers, GetTransaction, GetUtxos};
+use crate::actors::{GetSilentPaymentTweaks, ScanSilentPayments};
+#[cfg(feature = "wallet")]
+use crate::actors::{CreateWallet, FundPsbt, GetNewAddress, ListUnspent, ListWallets, SetTxNote, SetWalletFlag};
+#[cfg(feature = "wallet")]
+use crate::actors::{CreateProposal, GetProposal, ListProposals, SubmitProposalSignatures};
+#[cfg(feature = "wallet")]
+use crate::actors::{LeaseOutput, ListLeases, ReleaseOutput};
+#[cfg(feature = "miner")]
+use crate::actors::SubmitBlock;
+use crate::actors::chain::ChainActor;
+use crate::actors::mempool::MempoolActor;
+use crate::actors::network::NetworkActor;
+use crate::actors::storage::{StorageActor, MAX_BLOCK_LOCATIONS};
+#[cfg(feature = "wallet")]
+use crate::actors::wallet::WalletActor;
+use crate::broadcast::Broadcaster;
+use auth::RpcAuth;
+use crate::config::{Config, RpcConfig, RpcJsonFormat};
+use crate::error::{BroadcastError, RpcError, NodeResult};
+use crate::policy::BroadcastLimits;
+use crate::settings::Settings;
+use crate::events::EventManager;
+
+pub struct RpcServer {
+    server: Server,
+    auth: Arc<RpcAuth>,
+}
+
+impl RpcServer {
+    /// Stop accepting requests and wait for the server threads to finish
+    pub async fn close(self) {
+        let server = self.server;
+        // Closing drops the server's own runtimes and then blocks on its threads
+        if let Err(e) = tokio::task::spawn_blocking(move || server.close()).await {
+            warn!("RPC server did not shut down cleanly: {}", e);
+        }
+        self.auth.remove_cookie();
+    }
+}
+
+/// Actors the RPC methods call into
+#[derive(Clone)]
+pub struct RpcActors {
+    pub storage: Addr<StorageActor>,
+    pub mempool: Addr<MempoolActor>,
+    pub network: Addr<NetworkActor>,
+    pub chain: Addr<ChainActor>,
+    #[cfg(feature = "wallet")]
+    pub wallet: Addr<WalletActor>,
+}
+
+/// The method handler and credentials, shared by the HTTP server and the
+/// WebSocket bridge on the API server
+#[derive(Clone)]
+pub struct RpcService {
+    pub io: IoHandler,
+    pub auth: Arc<RpcAuth>,
+    pub json_format: RpcJsonFormat,
+}
+
+impl RpcService {
+    pub fn new(config: &Config, settings: Settings, event_manager: EventManager, actors: RpcActors) -> NodeResult<Self> {
+        let io = build_handler(config, settings, event_manager, actors);
+        let auth = Arc::new(RpcAuth::new(config)?);
+        if let Some(cookie) = auth.cookie() {
+            info!("No rpc.password set, wrote RPC cookie to {}", cookie.display());
+        }
+        Ok(Self { io, auth, json_format: config.rpc.json_format })
+    }
+}
+
+pub async fn start_server(config: &Config, service: RpcService) -> NodeResult<RpcServer> {
+    let RpcService { io, auth, json_format } = service;
+    let addr: SocketAddr = format!("{}:{}", config.rpc.host, config.rpc.port)
+        .parse()
+        .map_err(|e| RpcError::Internal(format!("Invalid RPC address: {}", e)))?;
+
+    let legacy_io = io.clone();
+    let middleware_auth = auth.clone();
+    let openrpc = registry::openrpc_document(matches!(config.network, crate::config::Network::Regtest)).to_string();
+    let server = ServerBuilder::new(io)
+        .request_middleware(move |request: hyper::Request<hyper::Body>| {
+            if !middleware_auth.authorized(request.headers().get(hyper::header::AUTHORIZATION)) {
+                return unauthorized_response();
+            }
+            if request.method() == hyper::Method::GET && request.uri().path() == OPENRPC_PATH {
+                return openrpc_response(&openrpc).into();
+            }
+            legacy_request(&legacy_io, &middleware_auth, json_format, request)
+        })
+        .start_http(&addr)
+        .map_err(|e| RpcError::Internal(format!("Failed to start RPC server: {}", e)))?;
+
+    info!("RPC server started on {}", addr);
+
+    Ok(RpcServer { server, auth })
+}
+
+/// Every RPC method for `config`'s network and this build's features, without the HTTP server in front
+pub fn build_handler(config: &Config, settings: Settings, event_manager: EventManager, actors: RpcActors) -> IoHandler {
+    let RpcActors {
+        storage: storage_actor,
+        mempool: mempool_actor,
+        network: network_actor,
+        chain: chain_actor,
+        #[cfg(feature = "wallet")]
+        wallet: wallet_actor,
+    } = actors;
+    // Requests without a "jsonrpc" member are answered in the 1.0 format bitcoind uses
+    let mut io = IoHandler::with_compatibility(jsonrpc_core::Compatibility::Both);
+
+    // Register RPC methods
+    register_blockchain_methods(&mut io, config, storage_actor.clone());
+    register_chain_history_methods(&mut io, storage_actor.clone(), chain_actor.clone());
+    register_mempool_methods(&mut io, mempool_actor.clone());
+    register_silent_payment_methods(&mut io, config, storage_actor.clone());
+    #[cfg(feature = "wallet")]
+    {
+        register_multisig_methods(&mut io, config, wallet_actor.clone());
+        register_wallet_methods(&mut io, config, wallet_actor);
+    }
+    #[cfg(feature = "miner")]
+    register_mining_methods(&mut io, config, storage_actor.clone(), mempool_actor.clone(), chain_actor.clone());
+    let broadcaster = Broadcaster::new(
+        config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        network_actor.clone(),
+        event_manager,
+    );
+    register_network_methods(&mut io, config, network_actor);
+    register_transaction_methods(&mut io, config, settings.clone(), storage_actor.clone(), mempool_actor.clone(), broadcaster);
+    #[cfg(feature = "zmq")]
+    register_zmq_methods(&mut io, config);
+    register_psbt_methods(&mut io);
+    register_payment_uri_methods(&mut io, config);
+    register_utility_methods(&mut io, config);
+    register_settings_methods(&mut io, settings, mempool_actor.clone());
+    if matches!(config.network, crate::config::Network::Regtest) {
+        #[cfg(feature = "miner")]
+        register_generate_methods(&mut io, config, storage_actor, mempool_actor.clone(), chain_actor);
+        register_regtest_methods(&mut io, mempool_actor);
+    }
+    debug_assert!(
+        io.iter().all(|(name, _)| registry::lookup(name).is_some()),
+        "every RPC method needs a registry entry",
+    );
+    io
+}
+
+/// Where the OpenRPC document is served to plain GETs
+const OPENRPC_PATH: &str = "/openrpc.json";
+
+fn openrpc_response(document: &str) -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(hyper::Body::from(document.to_string()))
+        .expect("static response parts are valid")
+}
+
+/// Failed attempts wait this long before the 401, as in bitcoind, to slow down guessing
+const AUTH_FAILURE_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+fn unauthorized_response() -> RequestMiddlewareAction {
+    warn!("Rejected RPC request with missing or incorrect credentials");
+    RequestMiddlewareAction::Respond {
+        should_validate_hosts: true,
+        response: Box::pin(async {
+            tokio::time::sleep(AUTH_FAILURE_DELAY).await;
+            Ok(hyper::Response::builder()
+                .status(hyper::StatusCode::UNAUTHORIZED)
+                .header(hyper::header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\"")
+                .body(hyper::Body::empty())
+                .expect("static response parts are valid"))
+        }),
+    }
+}
+
+/// Answer POSTs here rather than in the HTTP server, so that requests from
+/// btcd's rpcclient (LND) and other Core clients, which send `"jsonrpc": "1.0"`,
+/// reach the handler as the JSON-RPC 1.0 requests they are instead of failing
+/// the 2.0 version check. Methods outside `rpc.allowed_methods` get a 403.
+/// Responses are written in `rpc.json_format`.
+fn legacy_request(
+    io: &IoHandler,
+    auth: &Arc<RpcAuth>,
+    json_format: RpcJsonFormat,
+    request: hyper::Request<hyper::Body>,
+) -> RequestMiddlewareAction {
+    if request.method() != hyper::Method::POST {
+        return RequestMiddlewareAction::Proceed { should_continue_on_invalid_cors: false, request };
+    }
+
+    let io = io.clone();
+    let auth = auth.clone();
+    let span = tracing::debug_span!("rpc_request", method = tracing::field::Empty);
+    let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+    crate::logging::set_remote_parent(&span, header("traceparent"), header("tracestate"));
+    RequestMiddlewareAction::Respond {
+        should_validate_hosts: true,
+        response: Box::pin(async move {
+            let body = hyper::body::to_bytes(request.into_body()).await?;
+            tracing::Span::current().record("method", request_methods(&body).as_str());
+            if let Some(method) = auth.refused_method(&body) {
+                warn!("Refused RPC call to {}, which is not in rpc.allowed_methods", method);
+                return Ok(hyper::Response::builder()
+                    .status(hyper::StatusCode::FORBIDDEN)
+                    .body(hyper::Body::empty())
+                    .expect("static response parts are valid"));
+            }
+            let body = strip_legacy_version(&body).unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+            let response = io.handle_request(&body).await.unwrap_or_default();
+            let response = canonical::rewrite_response(json_format, &body, response);
+            Ok(hyper::Response::builder()
+                .header(hyper::header::CONTENT_TYPE, "application/json")
+                .body(hyper::Body::from(response))
+                .expect("static response parts are valid"))
+        }.instrument(span)),
+    }
+}
+
+/// The method a request calls, or those of a batch joined with commas, for its span
+fn request_methods(body: &[u8]) -> String {
+    let Ok(request) = serde_json::from_slice::<Value>(body) else {
+        return String::new();
+    };
+    let calls = match &request {
+        Value::Array(calls) => calls.iter().collect(),
+        call => vec![call],
+    };
+    calls.iter()
+        .filter_map(|call| call.get("method").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Drop `"jsonrpc": "1.0"` from a request or batch, `None` if there is nothing to change
+fn strip_legacy_version(body: &[u8]) -> Option<String> {
+    let mut request: Value = serde_json::from_slice(body).ok()?;
+    let calls = match &mut request {
+        Value::Array(calls) => calls.iter_mut().collect(),
+        call => vec![call],
+    };
+
+    let mut changed = false;
+    for call in calls {
+        if let Some(call) = call.as_object_mut() {
+            if call.get("jsonrpc").and_then(Value::as_str) == Some("1.0") {
+                call.remove("jsonrpc");
+                changed = true;
+            }
+        }
+    }
+    changed.then(|| request.to_string())
+}
+
+fn register_blockchain_methods(io: &mut IoHandler, config: &Config, storage_actor: Addr<StorageActor>) {
+    let network = config.network.to_bitcoin_network();
+    let chain = network.to_core_arg();
+
+    // getblockchaininfo
+    let actor = storage_actor.clone();
+    io.add_method("getblockchaininfo", move |_params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let tip = active_tip(&storage_actor).await?;
+            let info = header_info(&storage_actor, tip.hash).await?;
+            let headers = best_header_height(&storage_actor, &tip).await?;
+            let now = crate::clock::now_utc().timestamp();
+            Ok(json!({
+                "chain": chain,
+                "blocks": tip.height,
+                "headers": headers,
+                "bestblockhash": tip.hash.to_string(),
+                "difficulty": info.header.difficulty_float(),
+                "mediantime": info.median_time,
+                "verificationprogress": crate::ibd::verification_progress(network, info.header.time, now),
+                "initialblockdownload": crate::ibd::is_initial_block_download(info.header.time, now),
+                "chainwork": chain_work_hex(&info),
+                "size_on_disk": 0,
+                "pruned": false
+            }))
+        }
+    });
+
+    // getchainstates: the active chainstate, preceded after a node snapshot
+    // restore by the background one validating the blocks behind it
+    let actor = storage_actor.clone();
+    let coins_db_cache_bytes = config.storage.cache_size;
+    io.add_method("getchainstates", move |_params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let tip = active_tip(&storage_actor).await?;
+            let headers = best_header_height(&storage_actor, &tip).await?;
+            let chainstates = storage_actor.send_traced(GetChainstates).await
+                .map_err(internal_error)?
+                .map_err(internal_error)?;
+            let now = crate::clock::now_utc().timestamp();
+            let mut results = Vec::with_capacity(chainstates.len());
+            for chainstate in chainstates {
+                let info = header_info(&storage_actor, chainstate.tip.hash).await?;
+                let mut result = json!({
+                    "blocks": chainstate.tip.height,
+                    "bestblockhash": chainstate.tip.hash.to_string(),
+                    "difficulty": info.header.difficulty_float(),
+                    "verificationprogress": crate::ibd::verification_progress(network, info.header.time, now),
+                    "coins_db_cache_bytes": coins_db_cache_bytes,
+                    "coins_tip_cache_bytes": chainstate.coins_tip_cache_bytes,
+                    "validated": chainstate.validated
+                });
+                if let Some(snapshot_blockhash) = chainstate.snapshot_blockhash {
+                    result["snapshot_blockhash"] = json!(snapshot_blockhash.to_string());
+                }
+                results.push(result);
+            }
+            Ok(json!({
+                "headers": headers,
+                "chainstates": results
+            }))
+        }
+    });
+
+    // getbestblockhash
+    let actor = storage_actor.clone();
+    io.add_method("getbestblockhash", move |_params: Params| {
+        let storage_actor = actor.clone();
+        async move { Ok(json!(active_tip(&storage_actor).await?.hash.to_string())) }
+    });
+
+    // getblock: verbosity 0 is the serialized block, 2 expands transactions
+    let actor = storage_actor.clone();
+    io.add_method("getblock", move |params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let (hash, verbosity) = parse_params::<(String, Option<u8>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let hash = parse_block_hash(&hash)?;
+
+            let block = storage_actor.send_traced(GetBlock { hash })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"))?;
+
+            let verbosity = verbosity.unwrap_or(1);
+            if verbosity == 0 {
+                return Ok(json!(bitcoin::consensus::serialize(&block).to_lower_hex_string()));
+            }
+
+            let info = header_info(&storage_actor, hash).await?;
+            let mut result = header_json(&info);
+            result["nTx"] = json!(block.txdata.len());
+            result["size"] = json!(block.total_size());
+            // weight = 3 * stripped size + total size
+            result["strippedsize"] = json!((block.weight().to_wu() as usize - block.total_size()) / 3);
+            result["weight"] = json!(block.weight().to_wu());
+            result["tx"] = if verbosity >= 2 {
+                block.txdata.iter().map(|tx| tx_json(tx, network)).collect()
+            } else {
+                block.txdata.iter().map(|tx| json!(tx.txid().to_string())).collect()
+            };
+            Ok(result)
+        }
+    });
+
+    // getblockheader: verbose JSON, or the 80 byte header as hex
+    let actor = storage_actor.clone();
+    io.add_method("getblockheader", move |params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let (hash, verbose) = parse_params::<(String, Option<bool>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let hash = parse_block_hash(&hash)?;
+
+            let info = header_info(&storage_actor, hash).await?;
+            if !verbose.unwrap_or(true) {
+                return Ok(json!(bitcoin::consensus::serialize(&info.header).to_lower_hex_string()));
+            }
+
+            let mut result = header_json(&info);
+            if let Some(block) = storage_actor.send_traced(GetBlock { hash }).await.map_err(internal_error)?.map_err(internal_error)? {
+                result["nTx"] = json!(block.txdata.len());
+            }
+            Ok(result)
+        }
+    });
+
+    // getblocklocations: where blocks are stored, for tools that read them directly
+    let actor = storage_actor.clone();
+    io.add_method("getblocklocations", move |params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let (hash, nblocks) = parse_params::<(String, Option<u64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let hash = parse_block_hash(&hash)?;
+            let count = nblocks.unwrap_or(1);
+            if count > MAX_BLOCK_LOCATIONS {
+                return Err(rpc_error(RPC_INVALID_PARAMETER, format!("nblocks must be at most {}", MAX_BLOCK_LOCATIONS)));
+            }
+
+            let locations = storage_actor.send_traced(GetBlockLocations { hash, count })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"))?;
+            Ok(json!(locations))
+        }
+    });
+
+    // getblockcount
+    let actor = storage_actor.clone();
+    io.add_method("getblockcount", move |_params: Params| {
+        let storage_actor = actor.clone();
+        async move { Ok(json!(active_tip(&storage_actor).await?.height)) }
+    });
+
+    // getblockhash
+    io.add_method("getblockhash", move |params: Params| {
+        let storage_actor = storage_actor.clone();
+        async move {
+            let (height,) = parse_params::<(u64,)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+
+            let hash = storage_actor.send_traced(GetBlockHash { height })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Block height out of range"))?;
+            Ok(json!(hash.to_string()))
+        }
+    });
+}
+
+/// Core's RPC_INVALID_PARAMETER, which chain backends match on
+const RPC_INVALID_PARAMETER: i64 = -8;
+/// Core's RPC_INVALID_ADDRESS_OR_KEY, used for unknown blocks and transactions
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// Core's RPC_DESERIALIZATION_ERROR
+const RPC_DESERIALIZATION_ERROR: i64 = -22;
+/// Core's RPC_VERIFY_ERROR
+const RPC_VERIFY_ERROR: i64 = -25;
+/// Core's RPC_VERIFY_REJECTED
+const RPC_VERIFY_REJECTED: i64 = -26;
+/// Core's RPC_VERIFY_ALREADY_IN_CHAIN
+const RPC_VERIFY_ALREADY_IN_CHAIN: i64 = -27;
+
+/// An error with the numeric code bitcoind would return, for clients that branch on it
+fn rpc_error(code: i64, message: impl ToString) -> jsonrpc_core::Error {
+    jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(code),
+        message: message.to_string(),
+        data: None,
+    }
+}
+
+/// Most positional arguments a method takes
+const MAX_RPC_PARAMS: usize = 8;
+
+/// Parse positional params, treating omitted trailing arguments as null the
+/// way bitcoind does, so `getblock <hash>` fills a `(String, Option<u8>)`
+fn parse_params<D: serde::de::DeserializeOwned>(params: Params) -> RpcResult<D> {
+    let mut values = match params {
+        Params::Array(values) => values,
+        Params::None => Vec::new(),
+        params => return params.parse(),
+    };
+    loop {
+        match Params::Array(values.clone()).parse() {
+            Err(_) if values.len() < MAX_RPC_PARAMS => values.push(Value::Null),
+            parsed => return parsed,
+        }
+    }
+}
+
+fn parse_block_hash(hash: &str) -> RpcResult<bitcoin::BlockHash> {
+    hash.parse().map_err(|_| rpc_error(RPC_INVALID_PARAMETER, "blockhash must be a 64 character hex string"))
+}
+
+async fn active_tip(storage_actor: &Addr<StorageActor>) -> RpcResult<ChainTip> {
+    storage_actor.send_traced(GetChainTip)
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?
+        .ok_or_else(|| internal_error("No chain tip yet"))
+}
+
+/// Height of the best header chain, which the active chain may trail
+async fn best_header_height(storage_actor: &Addr<StorageActor>, tip: &ChainTip) -> RpcResult<u64> {
+    let best_header = storage_actor.send_traced(GetBestHeader)
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    Ok(best_header.map_or(tip.height, |header| header.height.max(tip.height)))
+}
+
+async fn header_info(storage_actor: &Addr<StorageActor>, hash: bitcoin::BlockHash) -> RpcResult<BlockHeaderInfo> {
+    storage_actor.send_traced(GetBlockHeaderInfo { hash })
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?
+        .ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"))
+}
+
+pub fn chain_work_hex(info: &BlockHeaderInfo) -> String {
+    info.chain_work.to_be_bytes().to_lower_hex_string()
+}
+
+/// The header fields shared by `getblockheader` and `getblock`
+fn header_json(info: &BlockHeaderInfo) -> Value {
+    let header = &info.header;
+    let mut result = json!({
+        "hash": header.block_hash().to_string(),
+        "confirmations": info.confirmations,
+        "height": info.height,
+        "version": header.version.to_consensus(),
+        "versionHex": format!("{:08x}", header.version.to_consensus()),
+        "merkleroot": header.merkle_root.to_string(),
+        "time": header.time,
+        "mediantime": info.median_time,
+        "nonce": header.nonce,
+        "bits": format!("{:08x}", header.bits.to_consensus()),
+        "difficulty": header.difficulty_float(),
+        "chainwork": chain_work_hex(info),
+    });
+    if info.height > 0 {
+        result["previousblockhash"] = json!(header.prev_blockhash.to_string());
+    }
+    if let Some(next) = info.next_hash {
+        result["nextblockhash"] = json!(next.to_string());
+    }
+    result
+}
+
+/// Core's name for the script template of an output
+fn script_type(script: &bitcoin::Script) -> &'static str {
+    if script.is_p2pkh() {
+        "pubkeyhash"
+    } else if script.is_p2sh() {
+        "scripthash"
+    } else if script.is_p2wpkh() {
+        "witness_v0_keyhash"
+    } else if script.is_p2wsh() {
+        "witness_v0_scripthash"
+    } else if script.is_p2tr() {
+        "witness_v1_taproot"
+    } else if script.is_p2pk() {
+        "pubkey"
+    } else if script.is_op_return() {
+        "nulldata"
+    } else if script.is_witness_program() {
+        "witness_unknown"
+    } else {
+        "nonstandard"
+    }
+}
+
+fn script_pubkey_json(script: &bitcoin::Script, network: bitcoin::Network) -> Value {
+    let mut result = json!({
+        "asm": script.to_asm_string(),
+        "hex": script.to_hex_string(),
+        "type": script_type(script),
+    });
+    if let Ok(address) = bitcoin::Address::from_script(script, network) {
+        result["address"] = json!(address.to_string());
+    }
+    result
+}
+
+/// Decoded transaction as `getrawtransaction` and `getblock` verbosity 2 report it
+pub fn tx_json(tx: &bitcoin::Transaction, network: bitcoin::Network) -> Value {
+    let vin: Vec<Value> = tx.input.iter().map(|input| {
+        let mut entry = if tx.is_coinbase() {
+            json!({ "coinbase": input.script_sig.to_hex_string() })
+        } else {
+            json!({
+                "txid": input.previous_output.txid.to_string(),
+                "vout": input.previous_output.vout,
+                "scriptSig": {
+                    "asm": input.script_sig.to_asm_string(),
+                    "hex": input.script_sig.to_hex_string(),
+                },
+            })
+        };
+        if !input.witness.is_empty() {
+            entry["txinwitness"] = input.witness.iter().map(|item| json!(item.to_lower_hex_string())).collect();
+        }
+        entry["sequence"] = json!(input.sequence.0);
+        entry
+    }).collect();
+
+    let vout: Vec<Value> = tx.output.iter().enumerate().map(|(n, output)| json!({
+        "value": output.value.to_btc(),
+        "n": n,
+        "scriptPubKey": script_pubkey_json(&output.script_pubkey, network),
+    })).collect();
+
+    json!({
+        "txid": tx.txid().to_string(),
+        "hash": tx.wtxid().to_string(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "hex": bitcoin::consensus::serialize(tx).to_lower_hex_string(),
+    })
+}
+
+fn register_chain_history_methods(io: &mut IoHandler, storage_actor: Addr<StorageActor>, chain_actor: Addr<ChainActor>) {
+    // gettxoutsetinfo: full UTXO set scan; hash_type is "muhash" (the default
+    // here) or "none", and commitment_matches compares against the MuHash
+    // kept up to date block by block
+    let actor = storage_actor.clone();
+    io.add_method("gettxoutsetinfo", move |params: Params| {
+        let storage_actor = actor.clone();
+        async move {
+            let hash_type = match params {
+                Params::None => None,
+                params => parse_params::<(Option<String>,)>(params)
+                    .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?
+                    .0,
+            };
+            let with_hash = match hash_type.as_deref() {
+                None | Some("muhash") => true,
+                Some("none") => false,
+                Some(other) => return Err(rpc_error(RPC_INVALID_PARAMETER, format!("{} hash type is not supported, use muhash or none", other))),
+            };
+
+            let info = storage_actor.send_traced(GetUtxoSetInfo)
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?;
+            let mut result = json!({
+                "height": info.height,
+                "bestblock": info.best_block,
+                "txouts": info.txouts,
+                "total_amount": info.total_amount as f64 / 100_000_000.0,
+                "commitment_matches": info.commitment_matches,
+            });
+            if with_hash {
+                result["muhash"] = json!(info.muhash);
+            }
+            Ok(result)
+        }
+    });
+
+    // getstaleblocks: archived side chain and reorged-out blocks, highest first
+    io.add_method("getstaleblocks", move |params: Params| {
+        let storage_actor = storage_actor.clone();
+        async move {
+            let limit = match params {
+                Params::None => None,
+                params => parse_params::<(Option<usize>,)>(params)
+                    .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?
+                    .0,
+            };
+
+            let blocks = storage_actor.send_traced(GetStaleBlocks { limit: limit.unwrap_or(50) })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?;
+            Ok(json!(blocks))
+        }
+    });
+
+    // waitforblockheight: as in Core, a timeout of 0 (the default) waits without limit
+    let actor = chain_actor.clone();
+    io.add_method("waitforblockheight", move |params: Params| {
+        let chain_actor = actor.clone();
+        async move {
+            let (height, timeout_ms) = parse_params::<(u64, Option<u64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let timeout = timeout_ms.filter(|ms| *ms > 0).map(std::time::Duration::from_millis);
+
+            let tip = chain_actor.send_traced(WaitForHeight { height, timeout })
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(|| internal_error("Chain tip not loaded"))?;
+            Ok(json!({ "hash": tip.hash.to_string(), "height": tip.height }))
+        }
+    });
+
+    // acceptreorg: follow a reorg held back by validation.max_reorg_depth
+    io.add_method("acceptreorg", move |params: Params| {
+        let chain_actor = chain_actor.clone();
+        async move {
+            let (hash,) = parse_params::<(String,)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let hash: bitcoin::BlockHash = hash.parse()
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid block hash"))?;
+
+            let record = chain_actor.send_traced(AcceptReorg { hash })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "No reorg to this block is held"))?;
+            Ok(json!(record))
+        }
+    });
+}
+
+fn internal_error(message: impl ToString) -> jsonrpc_core::Error {
+    let mut error = jsonrpc_core::Error::internal_error();
+    error.message = message.to_string();
+    error
+}
+
+/// The single `"txid"` argument of the mempool entry methods
+fn parse_txid_param(params: Params) -> RpcResult<bitcoin::Txid> {
+    let (txid,) = parse_params::<(String,)>(params)
+        .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+    txid.parse().map_err(|_| jsonrpc_core::Error::invalid_params("Invalid txid"))
+}
+
+fn register_mempool_methods(io: &mut IoHandler, mempool_actor: Addr<MempoolActor>) {
+    // estimatesmartfee: BTC/kvB needed to confirm within conf_target blocks;
+    // the estimate_mode argument is accepted for compatibility and ignored
+    let actor = mempool_actor.clone();
+    io.add_method("estimatesmartfee", move |params: Params| {
+        let mempool_actor = actor.clone();
+        async move {
+            let (conf_target, _estimate_mode) = parse_params::<(u32, Option<String>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            if !(1..=1008).contains(&conf_target) {
+                return Err(rpc_error(RPC_INVALID_PARAMETER, "Invalid conf_target, must be between 1 and 1008"));
+            }
+
+            let fee_rate = mempool_actor.send_traced(EstimateSmartFee { conf_target })
+                .await
+                .map_err(internal_error)?;
+            Ok(json!({
+                "feerate": fee_rate.to_btc_per_kvb(),
+                "blocks": conf_target,
+            }))
+        }
+    });
+
+    // getmempoolancestors / getmempooldescendants: txids only, no verbose form
+    let ancestors_mempool = mempool_actor.clone();
+    io.add_method("getmempoolancestors", move |params: Params| {
+        let mempool_actor = ancestors_mempool.clone();
+        async move {
+            let txid = parse_txid_param(params)?;
+            let ancestors = mempool_actor.send_traced(GetMempoolAncestors { txid })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("Transaction not in mempool"))?;
+            Ok(json!(ancestors.iter().map(|txid| txid.to_string()).collect::<Vec<_>>()))
+        }
+    });
+
+    let descendants_mempool = mempool_actor.clone();
+    io.add_method("getmempooldescendants", move |params: Params| {
+        let mempool_actor = descendants_mempool.clone();
+        async move {
+            let txid = parse_txid_param(params)?;
+            let descendants = mempool_actor.send_traced(GetMempoolDescendants { txid })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("Transaction not in mempool"))?;
+            Ok(json!(descendants.iter().map(|txid| txid.to_string()).collect::<Vec<_>>()))
+        }
+    });
+
+    // getmempoolentry: includes the miner lane priority tag
+    io.add_method("getmempoolentry", move |params: Params| {
+        let mempool_actor = mempool_actor.clone();
+        async move {
+            let txid = parse_txid_param(params)?;
+
+            let entry = mempool_actor.send_traced(GetMempoolEntry { txid })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("Transaction not in mempool"))?;
+
+            Ok(json!({
+                "vsize": entry.vsize,
+                "weight": entry.weight,
+                "time": entry.time,
+                "fees": {
+                    "base": entry.fee.to_btc(),
+                },
+                "depends": entry.depends,
+                "spentby": entry.spent_by,
+                "ancestorcount": entry.ancestor_count,
+                "ancestorsize": entry.ancestor_size,
+                "descendantcount": entry.descendant_count,
+                "descendantsize": entry.descendant_size,
+                "priority": entry.priority,
+            }))
+        }
+    });
+}
+
+#[cfg(feature = "miner")]
+fn register_mining_methods(
+    io: &mut IoHandler,
+    config: &Config,
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    chain_actor: Addr<ChainActor>,
+) {
+    let network = config.network.to_bitcoin_network();
+
+    // submitblock: null once connected, otherwise Core's BIP22 reason
+    io.add_method("submitblock", move |params: Params| {
+        let chain_actor = chain_actor.clone();
+        async move {
+            use bitcoin::hex::FromHex;
+
+            // The second parameter is ignored, as in Core
+            let (hexdata, _dummy) = parse_params::<(String, Option<Value>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let block: bitcoin::Block = Vec::<u8>::from_hex(&hexdata).ok()
+                .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+                .ok_or_else(|| rpc_error(RPC_DESERIALIZATION_ERROR, "Block decode failed"))?;
+            if !block.txdata.first().is_some_and(|tx| tx.is_coinbase()) {
+                return Err(rpc_error(RPC_DESERIALIZATION_ERROR, "Block does not start with a coinbase"));
+            }
+
+            let submission = chain_actor.send_traced(SubmitBlock { block }).await
+                .map_err(internal_error)?
+                .map_err(internal_error)?;
+            Ok(json!(submission.reason()))
+        }
+    });
+
+    // getblocktemplate: BIP22 template, miner lane transactions first
+    io.add_method("getblocktemplate", move |_params: Params| {
+        let storage_actor = storage_actor.clone();
+        let mempool_actor = mempool_actor.clone();
+        async move {
+            let template = crate::mining::fetch_template(&storage_actor, &mempool_actor, network)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(|| internal_error("No chain tip yet"))?;
+            Ok(template.to_json())
+        }
+    });
+}
+
+/// Nonces `generatetoaddress` tries across all its blocks, as in Core
+#[cfg(feature = "miner")]
+const DEFAULT_MAX_TRIES: u64 = 1_000_000;
+
+/// Hand a generated block to the chain actor, which must connect it
+#[cfg(feature = "miner")]
+async fn submit_generated(chain_actor: &Addr<ChainActor>, block: bitcoin::Block) -> RpcResult<bitcoin::BlockHash> {
+    let hash = block.block_hash();
+    let submission = chain_actor.send_traced(SubmitBlock { block }).await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    match submission.reason() {
+        None => Ok(hash),
+        Some(reason) => Err(rpc_error(RPC_VERIFY_ERROR, format!("Block {} not accepted: {}", hash, reason))),
+    }
+}
+
+/// Regtest block production without an external miner
+#[cfg(feature = "miner")]
+fn register_generate_methods(
+    io: &mut IoHandler,
+    config: &Config,
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    chain_actor: Addr<ChainActor>,
+) {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use crate::actors::mempool::TemplateTransaction;
+
+    let network = config.network.to_bitcoin_network();
+    let message = config.mining.coinbase_message.as_bytes().to_vec();
+    let coinbase_spec = move |address: &str| -> RpcResult<crate::mining::CoinbaseSpec> {
+        let address = address.parse::<bitcoin::Address<_>>().ok()
+            .and_then(|address| address.require_network(network).ok())
+            .ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Error: Invalid address"))?;
+        Ok(crate::mining::CoinbaseSpec {
+            payouts: vec![(address.script_pubkey(), 1.0)],
+            message: message.clone(),
+            extra_nonce_size: 8,
+        })
+    };
+    // Keeps coinbases unique when blocks are mined again on the same parent
+    let extra_nonce = Arc::new(AtomicU64::new(0));
+
+    // generatetoaddress: mine blocks with the mempool's transactions, coinbase to the address
+    let generate_storage_actor = storage_actor.clone();
+    let generate_mempool_actor = mempool_actor.clone();
+    let generate_chain_actor = chain_actor.clone();
+    let generate_spec = coinbase_spec.clone();
+    let generate_extra_nonce = extra_nonce.clone();
+    io.add_method("generatetoaddress", move |params: Params| {
+        let storage_actor = generate_storage_actor.clone();
+        let mempool_actor = generate_mempool_actor.clone();
+        let chain_actor = generate_chain_actor.clone();
+        let coinbase_spec = generate_spec.clone();
+        let extra_nonce = generate_extra_nonce.clone();
+        async move {
+            let (blocks, address, max_tries) = parse_params::<(u64, String, Option<u64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let spec = coinbase_spec(&address)?;
+            let mut max_tries = max_tries.unwrap_or(DEFAULT_MAX_TRIES);
+
+            let mut hashes = Vec::new();
+            while (hashes.len() as u64) < blocks && max_tries > 0 {
+                let template = crate::mining::fetch_template(&storage_actor, &mempool_actor, network)
+                    .await
+                    .map_err(internal_error)?
+                    .ok_or_else(|| internal_error("No chain tip yet"))?;
+                let nonce = extra_nonce.fetch_add(1, Ordering::Relaxed);
+                // Like Core, running out of tries ends the call with the blocks found so far
+                let Some(block) = template.solve(&spec, &nonce.to_le_bytes(), template.cur_time, max_tries) else {
+                    break;
+                };
+                max_tries -= u64::from(block.header.nonce) + 1;
+                hashes.push(submit_generated(&chain_actor, block).await?.to_string());
+            }
+            info!("Generated {} blocks to {}", hashes.len(), address);
+            Ok(json!(hashes))
+        }
+    });
+
+    // generateblock: mine one block with exactly the given transactions, by mempool txid or raw hex
+    io.add_method("generateblock", move |params: Params| {
+        let storage_actor = storage_actor.clone();
+        let mempool_actor = mempool_actor.clone();
+        let chain_actor = chain_actor.clone();
+        let coinbase_spec = coinbase_spec.clone();
+        let extra_nonce = extra_nonce.clone();
+        async move {
+            use bitcoin::hex::FromHex;
+
+            let (output, transactions, submit) = parse_params::<(String, Vec<String>, Option<bool>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let spec = coinbase_spec(&output)?;
+
+            let mut selected = Vec::with_capacity(transactions.len());
+            for entry in &transactions {
+                let selection = if let Ok(txid) = entry.parse::<bitcoin::Txid>() {
+                    let not_found = || rpc_error(RPC_INVALID_ADDRESS_OR_KEY, format!("Transaction {} not in mempool.", txid));
+                    let tx = mempool_actor.send_traced(GetFromMempool { txid }).await
+                        .map_err(internal_error)?
+                        .map_err(internal_error)?
+                        .ok_or_else(not_found)?;
+                    let info = mempool_actor.send_traced(GetMempoolEntry { txid }).await
+                        .map_err(internal_error)?
+                        .map_err(internal_error)?
+                        .ok_or_else(not_found)?;
+                    TemplateTransaction { tx, fee: info.fee, weight: info.weight, priority: info.priority }
+                } else {
+                    // Fees of transactions from outside the mempool are not looked up and left unclaimed
+                    let tx: bitcoin::Transaction = Vec::<u8>::from_hex(entry).ok()
+                        .and_then(|bytes| bitcoin::consensus::deserialize(&bytes).ok())
+                        .ok_or_else(|| rpc_error(RPC_DESERIALIZATION_ERROR, format!("Transaction decode failed for {}", entry)))?;
+                    TemplateTransaction { weight: tx.weight().to_wu(), tx, fee: crate::amount::Satoshi::ZERO, priority: false }
+                };
+                selected.push(selection);
+            }
+
+            let template = crate::mining::template_on_tip(&storage_actor, selected, network)
+                .await
+                .map_err(internal_error)?
+                .ok_or_else(|| internal_error("No chain tip yet"))?;
+            let nonce = extra_nonce.fetch_add(1, Ordering::Relaxed);
+            let block = template.solve(&spec, &nonce.to_le_bytes(), template.cur_time, DEFAULT_MAX_TRIES)
+                .ok_or_else(|| internal_error("Failed to make block"))?;
+            if submit.unwrap_or(true) {
+                let hash = submit_generated(&chain_actor, block).await?;
+                return Ok(json!({ "hash": hash.to_string() }));
+            }
+            Ok(json!({
+                "hash": block.block_hash().to_string(),
+                "hex": bitcoin::consensus::serialize(&block).to_lower_hex_string(),
+            }))
+        }
+    });
+}
+
+fn register_network_methods(io: &mut IoHandler, config: &Config, network_actor: Addr<NetworkActor>) {
+    let networks = json!(config.network_reachability());
+    // What our `version` message advertises; no bit is claimed that the node does not serve
+    let services = crate::network::NetworkConstants::for_network(&config.network).services;
+    let service_names: Vec<&str> = crate::peerstats::SERVICE_FLAGS.iter()
+        .filter(|(bit, _)| services & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    // getnetworkinfo
+    let info_actor = network_actor.clone();
+    io.add_method("getnetworkinfo", move |_params: Params| {
+        let networks = networks.clone();
+        let service_names = service_names.clone();
+        let network_actor = info_actor.clone();
+        async move {
+            let warnings = network_actor.send(GetNetworkWarnings).await.map_err(internal_error)?;
+            let peers = network_actor.send(GetPeers).await.map_err(internal_error)?.map_err(internal_error)?;
+            let inbound = peers.iter().filter(|peer| peer.inbound).count();
+            Ok(json!({
+                "version": 250000,
+                "subversion": "/BitKnotsRS:0.1.0/",
+                "protocolversion": 70016,
+                "localservices": format!("{:016x}", services),
+                "localservicesnames": service_names,
+                "localrelay": true,
+                "timeoffset": 0,
+                "connections": peers.len(),
+                "connections_in": inbound,
+                "connections_out": peers.len() - inbound,
+                "networkactive": true,
+                "networks": networks,
+                "relayfee": 0.00001000,
+                "incrementalfee": 0.00001000,
+                "localaddresses": [],
+                "warnings": warnings.join("; ")
+            }))
+        }
+    });
+
+    // getpeerinfo
+    let peers_actor = network_actor.clone();
+    io.add_method("getpeerinfo", move |_params: Params| {
+        let network_actor = peers_actor.clone();
+        async move {
+            let peers = network_actor.send(GetPeers).await.map_err(internal_error)?.map_err(internal_error)?;
+            let peers: Vec<Value> = peers.iter().map(|peer| json!({
+                "id": peer.id,
+                "addr": peer.address,
+                "subver": peer.user_agent.clone().unwrap_or_default(),
+                "conntime": peer.connected_at.timestamp(),
+                "bytessent": peer.bytes_sent,
+                "bytesrecv": peer.bytes_received,
+                "inbound": peer.inbound,
+            })).collect();
+            Ok(json!(peers))
+        }
+    });
+
+    // getconnectioncount
+    io.add_method("getconnectioncount", move |_params: Params| {
+        let network_actor = network_actor.clone();
+        async move {
+            let peers = network_actor.send(GetPeers).await.map_err(internal_error)?.map_err(internal_error)?;
+            Ok(json!(peers.len()))
+        }
+    });
+}
+
+fn register_transaction_methods(
+    io: &mut IoHandler,
+    config: &Config,
+    settings: Settings,
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    broadcaster: Broadcaster,
+) {
+    let network = config.network.to_bitcoin_network();
+
+    // getrawtransaction: mempool first, then confirmed transactions
+    let storage = storage_actor.clone();
+    let mempool = mempool_actor.clone();
+    io.add_method("getrawtransaction", move |params: Params| {
+        let storage_actor = storage.clone();
+        let mempool_actor = mempool.clone();
+        async move {
+            let (txid, verbose) = parse_params::<(String, Option<Value>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let txid: bitcoin::Txid = txid.parse()
+                .map_err(|_| rpc_error(RPC_INVALID_PARAMETER, "txid must be a 64 character hex string"))?;
+            // Core accepts both a boolean and a 0/1 verbosity
+            let verbose = match verbose {
+                Some(Value::Bool(verbose)) => verbose,
+                Some(Value::Number(verbosity)) => verbosity.as_u64().unwrap_or(0) > 0,
+                _ => false,
+            };
+
+            let tx = match mempool_actor.send_traced(GetFromMempool { txid }).await.map_err(internal_error)?.map_err(internal_error)? {
+                Some(tx) => Some(tx),
+                None => storage_actor.send_traced(GetTransaction { txid }).await.map_err(internal_error)?.map_err(internal_error)?,
+            };
+            let tx = tx.ok_or_else(|| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "No such mempool or blockchain transaction"))?;
+
+            if verbose {
+                Ok(tx_json(&tx, network))
+            } else {
+                Ok(json!(bitcoin::consensus::serialize(&tx).to_lower_hex_string()))
+            }
+        }
+    });
+
+    // gettxout: null when spent, including by a mempool transaction
+    let storage = storage_actor.clone();
+    let mempool = mempool_actor.clone();
+    io.add_method("gettxout", move |params: Params| {
+        let storage_actor = storage.clone();
+        let mempool_actor = mempool.clone();
+        async move {
+            let (txid, vout, include_mempool) = parse_params::<(String, u32, Option<bool>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let txid: bitcoin::Txid = txid.parse()
+                .map_err(|_| rpc_error(RPC_INVALID_PARAMETER, "txid must be a 64 character hex string"))?;
+            let outpoint = bitcoin::OutPoint { txid, vout };
+            let include_mempool = include_mempool.unwrap_or(true);
+
+            if include_mempool {
+                let spent = mempool_actor.send_traced(GetMempoolSpends { outpoints: vec![outpoint] })
+                    .await
+                    .map_err(internal_error)?;
+                if !spent.is_empty() {
+                    return Ok(Value::Null);
+                }
+            }
+
+            let tip = active_tip(&storage_actor).await?;
+            let utxo = storage_actor.send_traced(GetUtxos { outpoints: vec![outpoint] })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .pop()
+                .flatten();
+            let (output, confirmations, coinbase) = match utxo {
+                Some(entry) => (entry.output, tip.height.saturating_sub(entry.height) + 1, entry.is_coinbase),
+                None if include_mempool => {
+                    let parent = mempool_actor.send_traced(GetFromMempool { txid })
+                        .await
+                        .map_err(internal_error)?
+                        .map_err(internal_error)?;
+                    match parent.and_then(|tx| tx.output.get(vout as usize).cloned()) {
+                        Some(output) => (output, 0, false),
+                        None => return Ok(Value::Null),
+                    }
+                }
+                None => return Ok(Value::Null),
+            };
+
+            Ok(json!({
+                "bestblock": tip.hash.to_string(),
+                "confirmations": confirmations,
+                "value": output.value.to_btc(),
+                "scriptPubKey": script_pubkey_json(&output.script_pubkey, network),
+                "coinbase": coinbase,
+            }))
+        }
+    });
+
+    // sendrawtransaction: mempool acceptance, then relay to peers
+    io.add_method("sendrawtransaction", move |params: Params| {
+        let broadcaster = broadcaster.clone();
+        async move {
+            let (hex, max_fee_rate, max_burn_amount) = parse_params::<(String, Option<f64>, Option<f64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let limits = BroadcastLimits::from_btc(max_fee_rate, max_burn_amount)
+                .map_err(|e| rpc_error(RPC_INVALID_PARAMETER, e))?;
+            let txid = broadcaster.submit(&hex, limits).await.map_err(|e| {
+                let code = match &e {
+                    BroadcastError::Decode => RPC_DESERIALIZATION_ERROR,
+                    BroadcastError::Limit(_) | BroadcastError::MissingInputs => RPC_VERIFY_ERROR,
+                    BroadcastError::InBelowOut | BroadcastError::Rejected(_) => RPC_VERIFY_REJECTED,
+                    BroadcastError::AlreadyInChain => RPC_VERIFY_ALREADY_IN_CHAIN,
+                    BroadcastError::Unavailable(message) => return internal_error(message),
+                };
+                rpc_error(code, e)
+            })?;
+            Ok(json!(txid.to_string()))
+        }
+    });
+
+    // getmempoolinfo, with the script filter in force so the relay stance can be audited
+    io.add_method("getmempoolinfo", move |_params: Params| {
+        let filter = settings.config().mempool.script_filter;
+        let script_filter = json!({
+            "mode": filter.mode.as_str(),
+            "addresses": filter.addresses,
+            "scripts": filter.scripts,
+            "xpubs": filter.xpubs,
+            "xpub_lookahead": filter.xpub_lookahead,
+        });
+        async move {
+            Ok(json!({
+                "loaded": true,
+                "size": 0,
+                "bytes": 0,
+                "usage": 0,
+                "maxmempool": 300000000,
+                "mempoolminfee": 0.00001000,
+                "minrelaytxfee": 0.00001000,
+                "unbroadcastcount": 0,
+                "scriptfilter": script_filter
+            }))
+        }
+    });
+
+    // getrawmempool
+    io.add_method("getrawmempool", |params: Params| async {
+        let verbose = if let Ok((verbose,)) = parse_params::<(bool,)>(params) {
+            verbose
+        } else {
+            false
+        };
+
+        if verbose {
+            // TODO: Get actual mempool data with details
+            Ok(json!({}))
+        } else {
+            // TODO: Get actual mempool transaction IDs
+            Ok(json!([]))
+        }
+    });
+}
+
+#[cfg(feature = "zmq")]
+fn register_zmq_methods(io: &mut IoHandler, config: &Config) {
+    // getzmqnotifications: the sockets `[network_config.zmq]` binds, which the
+    // ZMQ notifier publishes on; the settings are fixed at startup
+    let notifications = zmq_notifications(config);
+    io.add_sync_method("getzmqnotifications", move |_params: Params| Ok(notifications.clone()));
+}
+
+/// One `getzmqnotifications` entry per published topic, as Core lists them
+#[cfg(feature = "zmq")]
+fn zmq_notifications(config: &Config) -> Value {
+    let notifications: Vec<Value> = crate::actors::zmq::endpoints(config)
+        .into_iter()
+        .flat_map(|endpoint| {
+            let address = endpoint.address;
+            let hwm = endpoint.high_water_mark;
+            endpoint.topics.into_iter().map(move |topic| json!({
+                "type": format!("pub{}", topic),
+                "address": address,
+                "hwm": hwm,
+            }))
+        })
+        .collect();
+    json!(notifications)
+}
+
+fn register_psbt_methods(io: &mut IoHandler) {
+    // encodepsbtur: PSBT as ur:crypto-psbt parts for animated QR signers
+    io.add_method("encodepsbtur", |params: Params| async {
+        let (psbt, max_fragment_len, part_count) = parse_params::<(String, Option<usize>, Option<usize>)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+        let psbt: bitcoin::psbt::Psbt = psbt.parse()
+            .map_err(|e| jsonrpc_core::Error::invalid_params(format!("TX decode failed {}", e)))?;
+        let max_fragment_len = max_fragment_len.unwrap_or(crate::ur::DEFAULT_MAX_FRAGMENT_LEN);
+        if max_fragment_len < 10 {
+            return Err(jsonrpc_core::Error::invalid_params("max_fragment_len must be at least 10"));
+        }
+
+        let parts = crate::ur::encode_psbt(&psbt, max_fragment_len, part_count);
+        Ok(json!({
+            "type": crate::ur::PSBT_UR_TYPE,
+            "parts": parts,
+        }))
+    });
+
+    // decodepsbtur: reassemble scanned parts, in any order, back into a base64 PSBT
+    io.add_method("decodepsbtur", |params: Params| async {
+        let (parts,) = parse_params::<(Vec<String>,)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+
+        let mut decoder = crate::ur::UrDecoder::new();
+        for part in &parts {
+            decoder.receive(part)
+                .map_err(|e| jsonrpc_core::Error::invalid_params(e.to_string()))?;
+        }
+        let psbt = crate::ur::decode_psbt(&decoder)
+            .map_err(|e| jsonrpc_core::Error::invalid_params(e.to_string()))?;
+
+        let (received, expected) = decoder.progress();
+        Ok(json!({
+            "complete": psbt.is_some(),
+            "psbt": psbt.map(|psbt| psbt.to_string()),
+            "received": received,
+            "expected": expected,
+        }))
+    });
+}
+
+fn register_payment_uri_methods(io: &mut IoHandler, config: &Config) {
+    let network = config.network.to_bitcoin_network();
+
+    // getpaymenturi: BIP21 URI for an address on this node's network
+    io.add_method("getpaymenturi", move |params: Params| async move {
+        let (address, amount, label, message) = parse_params::<(String, Option<f64>, Option<String>, Option<String>)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+        let address = address.parse::<bitcoin::Address<_>>()
+            .map_err(|e| jsonrpc_core::Error::invalid_params(format!("Invalid address: {}", e)))?
+            .require_network(network)
+            .map_err(|_| jsonrpc_core::Error::invalid_params(format!("Address is not valid for {}", network)))?;
+
+        let mut payment = crate::bip21::PaymentUri::new(address);
+        if let Some(amount) = amount {
+            let amount = bitcoin::Amount::from_btc(amount)
+                .ok()
+                .filter(|amount| *amount > bitcoin::Amount::ZERO)
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("Invalid amount"))?;
+            payment.amount = Some(amount);
+        }
+        payment.label = label.filter(|label| !label.is_empty());
+        payment.message = message.filter(|message| !message.is_empty());
+        Ok(json!(payment.to_string()))
+    });
+
+    // parsepaymenturi: split a BIP21 URI, rejecting addresses from other networks
+    io.add_method("parsepaymenturi", move |params: Params| async move {
+        let (uri,) = parse_params::<(String,)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+        let payment = crate::bip21::PaymentUri::parse(&uri, network)
+            .map_err(|e| jsonrpc_core::Error::invalid_params(e.to_string()))?;
+
+        Ok(json!({
+            "address": payment.address.to_string(),
+            "amount": payment.amount.map(|amount| amount.to_btc()),
+            "label": payment.label,
+            "message": payment.message,
+            "params": payment.extras,
+        }))
+    });
+}
+
+fn register_silent_payment_methods(io: &mut IoHandler, config: &Config, storage_actor: Addr<StorageActor>) {
+    let network = config.network.to_bitcoin_network();
+    let index_enabled = config.storage.silent_payment_index;
+    let index_disabled = || internal_error("Silent payment index is disabled (set storage.silent_payment_index)");
+    let parse_key = |key: &str| key.parse::<bitcoin::secp256k1::PublicKey>()
+        .map_err(|e| jsonrpc_core::Error::invalid_params(format!("Invalid public key: {}", e)));
+
+    // getsilentpaymentaddress: BIP 352 address for a scan and spend public key pair
+    io.add_method("getsilentpaymentaddress", move |params: Params| async move {
+        let (scan, spend) = parse_params::<(String, String)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+        let address = crate::silentpayments::SilentPaymentAddress::new(network, parse_key(&scan)?, parse_key(&spend)?);
+        Ok(json!({
+            "address": address.to_string(),
+            "scan_pubkey": address.scan.to_string(),
+            "spend_pubkey": address.spend.to_string(),
+        }))
+    });
+
+    // getsilentpaymenttweaks: per-transaction tweaks of one block, for light client scanning
+    let tweaks_actor = storage_actor.clone();
+    io.add_method("getsilentpaymenttweaks", move |params: Params| {
+        let storage_actor = tweaks_actor.clone();
+        async move {
+            if !index_enabled {
+                return Err(index_disabled());
+            }
+            let (height,) = parse_params::<(u64,)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let tweaks = storage_actor.send_traced(GetSilentPaymentTweaks { height })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?
+                .ok_or_else(|| jsonrpc_core::Error::invalid_params("Block not indexed"))?;
+
+            let tweaks: Vec<Value> = tweaks.into_iter()
+                .map(|(txid, tweak)| json!({ "txid": txid.to_string(), "tweak": tweak.to_string() }))
+                .collect();
+            Ok(json!(tweaks))
+        }
+    });
+
+    // scansilentpayments: outputs paying a scan key in indexed blocks; the spend secret never leaves the signer
+    io.add_method("scansilentpayments", move |params: Params| {
+        let storage_actor = storage_actor.clone();
+        async move {
+            if !index_enabled {
+                return Err(index_disabled());
+            }
+            let (scan_secret, address, start_height, end_height) = parse_params::<(String, String, u64, Option<u64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let scan_secret = scan_secret.parse()
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid scan private key"))?;
+            let keys = crate::silentpayments::SilentPaymentAddress::parse(&address, network)
+                .and_then(|address| crate::silentpayments::ScanKeys::for_address(scan_secret, &address))
+                .map_err(|e| jsonrpc_core::Error::invalid_params(e.to_string()))?;
+
+            let matches = storage_actor.send_traced(ScanSilentPayments { keys, start_height, end_height })
+                .await
+                .map_err(internal_error)?
+                .map_err(internal_error)?;
+            Ok(json!(matches))
+        }
+    });
+}
+
+#[cfg(feature = "wallet")]
+fn wallet_error(error: crate::error::WalletError) -> jsonrpc_core::Error {
+    use crate::error::WalletError;
+    match error {
+        WalletError::Io(_) | WalletError::Storage(_) | WalletError::Mailbox(_) | WalletError::Serialization(_) => internal_error(error),
+        error => jsonrpc_core::Error::invalid_params(error.to_string()),
+    }
+}
+
+#[cfg(feature = "wallet")]
+fn parse_wallet_outputs(outputs: &Value, network: bitcoin::Network) -> RpcResult<Vec<bitcoin::TxOut>> {
+    use bitcoin::hex::FromHex;
+
+    let invalid = |message: String| jsonrpc_core::Error::invalid_params(message);
+    // Outputs are either {"address": amount, ...} or [{"address": amount}, {"data": "hex"}, ...]
+    let entries: Vec<(&String, &Value)> = match outputs {
+        Value::Object(map) => map.iter().collect(),
+        Value::Array(items) => items.iter()
+            .map(|item| item.as_object().ok_or_else(|| invalid("Outputs must be objects".to_string())))
+            .collect::<RpcResult<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|map| map.iter())
+            .collect(),
+        _ => return Err(invalid("Outputs must be an object or an array".to_string())),
+    };
+
+    entries.into_iter().map(|(key, value)| {
+        if key == "data" {
+            let data = value.as_str()
+                .and_then(|hex| Vec::<u8>::from_hex(hex).ok())
+                .and_then(|bytes| bitcoin::script::PushBytesBuf::try_from(bytes).ok())
+                .ok_or_else(|| invalid("Invalid data output".to_string()))?;
+            return Ok(bitcoin::TxOut { value: bitcoin::Amount::ZERO, script_pubkey: bitcoin::ScriptBuf::new_op_return(&data) });
+        }
+        let address = key.parse::<bitcoin::Address<bitcoin::address::NetworkUnchecked>>()
+            .map_err(|e| invalid(format!("Invalid address {}: {}", key, e)))?
+            .require_network(network)
+            .map_err(|_| invalid(format!("Address {} is not valid for {}", key, network)))?;
+        let value = value.as_f64()
+            .and_then(|btc| bitcoin::Amount::from_btc(btc).ok())
+            .ok_or_else(|| invalid(format!("Invalid amount for {}", key)))?;
+        Ok(bitcoin::TxOut { value, script_pubkey: address.script_pubkey() })
+    }).collect()
+}
+
+#[cfg(feature = "wallet")]
+/// Coin control from `walletcreatefundedpsbt` inputs and options, named as in Bitcoin Core
+fn parse_coin_control(inputs: &Value, options: Option<&Value>, network: bitcoin::Network) -> RpcResult<crate::wallet::CoinControl> {
+    let invalid = |message: &str| jsonrpc_core::Error::invalid_params(message.to_string());
+    let mut control = crate::wallet::CoinControl::default();
+
+    for input in inputs.as_array().ok_or_else(|| invalid("Inputs must be an array"))? {
+        let txid = input["txid"].as_str()
+            .and_then(|txid| txid.parse().ok())
+            .ok_or_else(|| invalid("Invalid input txid"))?;
+        let vout = input["vout"].as_u64()
+            .and_then(|vout| u32::try_from(vout).ok())
+            .ok_or_else(|| invalid("Invalid input vout"))?;
+        control.inputs.push(bitcoin::OutPoint { txid, vout });
+    }
+
+    let Some(options) = options else {
+        return Ok(control);
+    };
+    let options = options.as_object().ok_or_else(|| invalid("Options must be an object"))?;
+    for (key, value) in options {
+        match key.as_str() {
+            "add_inputs" => control.add_inputs = Some(value.as_bool().ok_or_else(|| invalid("add_inputs must be a boolean"))?),
+            "minconf" => {
+                control.min_conf = value.as_u64()
+                    .and_then(|conf| u32::try_from(conf).ok())
+                    .ok_or_else(|| invalid("minconf must be a non-negative integer"))?;
+            }
+            "avoid_reuse" => control.avoid_reuse = Some(value.as_bool().ok_or_else(|| invalid("avoid_reuse must be a boolean"))?),
+            "changeAddress" => {
+                let address = value.as_str()
+                    .and_then(|address| address.parse::<bitcoin::Address<bitcoin::address::NetworkUnchecked>>().ok())
+                    .and_then(|address| address.require_network(network).ok())
+                    .ok_or_else(|| invalid("Invalid changeAddress"))?;
+                control.change_address = Some(address);
+            }
+            "change_type" => {
+                let kind = value.as_str().ok_or_else(|| invalid("change_type must be a string"))?;
+                control.change_type = Some(kind.parse().map_err(wallet_error)?);
+            }
+            "fee_rate" => control.fee_rate = value.as_f64().and_then(crate::amount::FeeRate::from_sat_per_vb)
+                .ok_or_else(|| invalid("fee_rate must be a non-negative number in sat/vB"))?,
+            other => return Err(jsonrpc_core::Error::invalid_params(format!("Unknown option: {}", other))),
+        }
+    }
+    Ok(control)
+}
+
+#[cfg(feature = "wallet")]
+/// Watch-only wallets; PSBTs come back unsigned for an external signer
+fn register_wallet_methods(io: &mut IoHandler, config: &Config, wallet_actor: Addr<WalletActor>) {
+    let network = config.network.to_bitcoin_network();
+
+    // createwallet: import an account xpub, optionally with its [fingerprint/path] origin
+    let create_actor = wallet_actor.clone();
+    io.add_method("createwallet", move |params: Params| {
+        let wallet_actor = create_actor.clone();
+        async move {
+            let (name, account_key, avoid_reuse) = parse_params::<(String, String, Option<bool>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let avoid_reuse = avoid_reuse.unwrap_or(false);
+            wallet_actor.send(CreateWallet { name: name.clone(), account_key, avoid_reuse })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!({ "name": name }))
+        }
+    });
+
+    // listwallets
+    let list_actor = wallet_actor.clone();
+    io.add_method("listwallets", move |_params: Params| {
+        let wallet_actor = list_actor.clone();
+        async move {
+            let wallets = wallet_actor.send(ListWallets).await.map_err(internal_error)?;
+            Ok(json!(wallets))
+        }
+    });
+
+    // setwalletflag: only avoid_reuse for now, as in Core
+    let flag_actor = wallet_actor.clone();
+    io.add_method("setwalletflag", move |params: Params| {
+        let wallet_actor = flag_actor.clone();
+        async move {
+            let (wallet, flag, value) = parse_params::<(String, String, Option<bool>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let value = value.unwrap_or(true);
+            wallet_actor.send(SetWalletFlag { wallet, flag: flag.clone(), value })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!({ "flag_name": flag, "flag_state": value, "warnings": "" }))
+        }
+    });
+
+    // settxnote: bookkeeping note shown in the history CSV export
+    let note_actor = wallet_actor.clone();
+    io.add_method("settxnote", move |params: Params| {
+        let wallet_actor = note_actor.clone();
+        async move {
+            let (wallet, txid, note) = parse_params::<(String, String, Option<String>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let txid = txid.parse()
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid txid"))?;
+            wallet_actor.send(SetTxNote { wallet, txid, note })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(Value::Null)
+        }
+    });
+
+    // getnewaddress / getrawchangeaddress: next unused address on the receive or change chain
+    let address_actor = wallet_actor.clone();
+    io.add_method("getnewaddress", move |params: Params| {
+        let wallet_actor = address_actor.clone();
+        async move {
+            let (wallet, label, kind) = parse_params::<(String, Option<String>, Option<String>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let kind = kind.map(|kind| kind.parse()).transpose().map_err(wallet_error)?;
+            let label = label.filter(|label| !label.is_empty());
+            let address = wallet_actor.send(GetNewAddress { wallet, change: false, kind, label })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!(address))
+        }
+    });
+
+    let change_actor = wallet_actor.clone();
+    io.add_method("getrawchangeaddress", move |params: Params| {
+        let wallet_actor = change_actor.clone();
+        async move {
+            let (wallet, kind) = parse_params::<(String, Option<String>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let kind = kind.map(|kind| kind.parse()).transpose().map_err(wallet_error)?;
+            let address = wallet_actor.send(GetNewAddress { wallet, change: true, kind, label: None })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!(address))
+        }
+    });
+
+    // listunspent: wallet coins not already spent by a mempool transaction
+    let unspent_actor = wallet_actor.clone();
+    io.add_method("listunspent", move |params: Params| {
+        let wallet_actor = unspent_actor.clone();
+        async move {
+            let (wallet, min_conf, max_conf) = parse_params::<(String, Option<u32>, Option<u32>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let unspent = wallet_actor.send(ListUnspent {
+                wallet,
+                min_conf: min_conf.unwrap_or(1),
+                max_conf: max_conf.unwrap_or(9_999_999),
+            })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!(unspent))
+        }
+    });
+
+    // leaseoutput / releaseoutput / listleases: reservations for chain backends such as lnd
+    let lease_actor = wallet_actor.clone();
+    io.add_method("leaseoutput", move |params: Params| {
+        let wallet_actor = lease_actor.clone();
+        async move {
+            let (id, txid, vout, duration_secs) = parse_params::<(String, String, u32, Option<u64>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let txid = txid.parse()
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid txid"))?;
+            let expiration = wallet_actor.send(LeaseOutput {
+                id,
+                outpoint: bitcoin::OutPoint { txid, vout },
+                duration_secs: duration_secs.unwrap_or(crate::wallet::DEFAULT_LEASE_SECS),
+            })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!({ "expiration": expiration }))
+        }
+    });
+
+    let release_actor = wallet_actor.clone();
+    io.add_method("releaseoutput", move |params: Params| {
+        let wallet_actor = release_actor.clone();
+        async move {
+            let (id, txid, vout) = parse_params::<(String, String, u32)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let txid = txid.parse()
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid txid"))?;
+            wallet_actor.send(ReleaseOutput { id, outpoint: bitcoin::OutPoint { txid, vout } })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(Value::Null)
+        }
+    });
+
+    let leases_actor = wallet_actor.clone();
+    io.add_method("listleases", move |_params: Params| {
+        let wallet_actor = leases_actor.clone();
+        async move {
+            let leases = wallet_actor.send(ListLeases).await.map_err(internal_error)?;
+            Ok(json!(leases))
+        }
+    });
+
+    // walletcreatefundedpsbt: coin-controlled funding, change always last
+    io.add_method("walletcreatefundedpsbt", move |params: Params| {
+        let wallet_actor = wallet_actor.clone();
+        async move {
+            let (wallet, inputs, outputs, lock_time, options) = parse_params::<(String, Value, Value, Option<u32>, Option<Value>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let control = parse_coin_control(&inputs, options.as_ref(), network)?;
+            let outputs = parse_wallet_outputs(&outputs, network)?;
+            let lock_time = bitcoin::absolute::LockTime::from_consensus(lock_time.unwrap_or(0));
+
+            let funded = wallet_actor.send(FundPsbt { wallet, outputs, lock_time, control })
+                .await
+                .map_err(internal_error)?
+                .map_err(wallet_error)?;
+            Ok(json!({
+                "psbt": funded.psbt.to_string(),
+                "fee": funded.fee.to_btc(),
+                "changepos": funded.change_position.map(|position| position as i64).unwrap_or(-1),
+            }))
+        }
+    });
+}
+
+#[cfg(feature = "wallet")]
+fn multisig_error(error: crate::error::MultisigError) -> jsonrpc_core::Error {
+    use crate::error::MultisigError;
+    match error {
+        MultisigError::Io(_) | MultisigError::Serialization(_) => internal_error(error),
+        error => jsonrpc_core::Error::invalid_params(error.to_string()),
+    }
+}
+
+#[cfg(feature = "wallet")]
+/// Coordination backend for multisig: descriptors and signature collection
+fn register_multisig_methods(io: &mut IoHandler, config: &Config, wallet_actor: Addr<WalletActor>) {
+    let network = config.network.to_bitcoin_network();
+
+    // createmultisigdescriptor: wsh(sortedmulti) receive and change descriptors from cosigner xpubs
+    io.add_method("createmultisigdescriptor", move |params: Params| async move {
+        let (threshold, keys) = parse_params::<(usize, Vec<String>)>(params)
+            .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+        let cosigners = keys.iter()
+            .map(|key| key.parse())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(wallet_error)?;
+        let descriptor = crate::multisig::MultisigDescriptor::new(threshold, cosigners).map_err(multisig_error)?;
+        Ok(json!({
+            "descriptor": descriptor.to_string_for_chain(0).map_err(multisig_error)?,
+            "change_descriptor": descriptor.to_string_for_chain(1).map_err(multisig_error)?,
+            "address": descriptor.address(0, 0, network).map_err(multisig_error)?.to_string(),
+        }))
+    });
+
+    // createmultisigproposal: the proposal ID is the unsigned txid
+    let create_actor = wallet_actor.clone();
+    io.add_method("createmultisigproposal", move |params: Params| {
+        let wallet_actor = create_actor.clone();
+        async move {
+            let (psbt, description) = parse_params::<(String, Option<String>)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let status = wallet_actor.send(CreateProposal { psbt, description })
+                .await
+                .map_err(internal_error)?
+                .map_err(multisig_error)?;
+            Ok(json!(status))
+        }
+    });
+
+    // submitmultisigsignatures: verify and merge a cosigner's partially signed copy
+    let submit_actor = wallet_actor.clone();
+    io.add_method("submitmultisigsignatures", move |params: Params| {
+        let wallet_actor = submit_actor.clone();
+        async move {
+            let (proposal_id, psbt) = parse_params::<(String, String)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let status = wallet_actor.send(SubmitProposalSignatures { proposal_id, psbt })
+                .await
+                .map_err(internal_error)?
+                .map_err(multisig_error)?;
+            Ok(json!(status))
+        }
+    });
+
+    let get_actor = wallet_actor.clone();
+    io.add_method("getmultisigproposal", move |params: Params| {
+        let wallet_actor = get_actor.clone();
+        async move {
+            let (proposal_id,) = parse_params::<(String,)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            let status = wallet_actor.send(GetProposal { proposal_id })
+                .await
+                .map_err(internal_error)?
+                .map_err(multisig_error)?;
+            Ok(json!(status))
+        }
+    });
+
+    io.add_method("listmultisigproposals", move |_params: Params| {
+        let wallet_actor = wallet_actor.clone();
+        async move {
+            let proposals = wallet_actor.send(ListProposals)
+                .await
+                .map_err(internal_error)?
+                .map_err(multisig_error)?;
+            Ok(json!(proposals))
+        }
+    });
+}
+
+/// Test-only methods, never registered outside regtest
+fn register_regtest_methods(io: &mut IoHandler, mempool_actor: Addr<MempoolActor>) {
+    // setmocktime: overrides the clock for block time checks, mempool expiry and peer bans
+    io.add_method("setmocktime", move |params: Params| {
+        let mempool_actor = mempool_actor.clone();
+        async move {
+            let (timestamp,) = parse_params::<(i64,)>(params)
+                .map_err(|_| jsonrpc_core::Error::invalid_params("Invalid parameters"))?;
+            if timestamp < 0 {
+                return Err(jsonrpc_core::Error::invalid_params(format!("Mocktime cannot be negative: {}.", timestamp)));
+            }
+
+            crate::clock::set_mock_time(timestamp);
+            info!("Mock time set to {}", timestamp);
+            // Apply the new time to the mempool right away rather than on the next interval
+            mempool_actor.send_traced(ExpireMempool).await.map_err(internal_error)?;
+            Ok(Value::Null)
+        }
+    });
+}
+
+/// A setting as `getconfig` and `setconfig` report it
+fn setting_json(settings: &Settings, key: &str) -> RpcResult<Value> {
+    let setting = crate::settings::lookup(key).map_err(|e| rpc_error(-8, e))?;
+    let (value, overridden) = settings.get(key).map_err(|e| rpc_error(-8, e))?;
+    Ok(json!({
+        "key": setting.key,
+        "value": value,
+        "overridden": overridden,
+        "restart_required": !setting.live,
+    }))
+}
+
+fn register_settings_methods(io: &mut IoHandler, settings: Settings, mempool_actor: Addr<MempoolActor>) {
+    // getconfig: one setting, or every one that setconfig accepts
+    let getconfig_settings = settings.clone();
+    io.add_method("getconfig", move |params: Params| {
+        let settings = getconfig_settings.clone();
+        async move {
+            let (key,) = parse_params::<(Option<String>,)>(params)?;
+            match key {
+                Some(key) => setting_json(&settings, &key),
+                None => crate::settings::SETTABLE.iter()
+                    .map(|setting| setting_json(&settings, setting.key))
+                    .collect::<RpcResult<Vec<_>>>()
+                    .map(Value::from),
+            }
+        }
+    });
+
+    // setconfig: persist an override to settings.json, null or no value drops it
+    io.add_method("setconfig", move |params: Params| {
+        let settings = settings.clone();
+        let mempool_actor = mempool_actor.clone();
+        async move {
+            let (key, value) = parse_params::<(String, Option<Value>)>(params)?;
+            let setting = crate::settings::lookup(&key).map_err(|e| rpc_error(-8, e))?;
+            let config = settings.set(&key, value).map_err(|e| rpc_error(-8, e))?;
+            info!("setconfig {} written to {}", key, settings.path().display());
+            if setting.live {
+                mempool_actor.send_traced(ReloadMempoolPolicy { config })
+                    .await
+                    .map_err(internal_error)?;
+            }
+            setting_json(&settings, &key)
+        }
+    });
+}
+
+fn register_utility_methods(io: &mut IoHandler, config: &Config) {
+    let regtest = matches!(config.network, crate::config::Network::Regtest);
+
+    // help: generated from the method registry
+    io.add_method("help", move |params: Params| async move {
+        let command = if let Ok((cmd,)) = parse_params::<(String,)>(params) {
+            Some(cmd)
+        } else {
+            None
+        };
+
+        match command.as_deref() {
+            None => Ok(json!(registry::help_listing(regtest))),
+            Some(name) => match registry::lookup(name) {
+                Some(spec) if regtest || !spec.regtest_only => Ok(json!(spec.help())),
+                _ => Ok(json!("Unknown command. Use 'help' to list available commands.")),
+            },
+        }
+    });
+
+    // rpc.discover: the OpenRPC service discovery method
+    let document = registry::openrpc_document(regtest);
+    io.add_method("rpc.discover", move |_params: Params| {
+        let document = document.clone();
+        async move { Ok(document) }
+    });
+
+    // uptime
+    io.add_method("uptime", |_params: Params| async {
+        // TODO: Calculate actual uptime
+        Ok(json!(0))
+    });
+
+    // getversion (non-standard but useful)
+    io.add_method("getversion", |_params: Params| async {
+        Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "name": "BitKnotsRS",
+            "description": "A knots-inspired Bitcoin node implementation in Rust"
+        }))
+    });
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_params_fills_omitted_arguments() {
+        let params = Params::Array(vec![json!("hash")]);
+        let (hash, verbosity) = parse_params::<(String, Option<u8>)>(params).unwrap();
+        assert_eq!(hash, "hash");
+        assert_eq!(verbosity, None);
+
+        let (target, mode) = parse_params::<(u32, Option<String>)>(Params::Array(vec![json!(6), json!("ECONOMICAL")])).unwrap();
+        assert_eq!((target, mode.as_deref()), (6, Some("ECONOMICAL")));
+
+        assert!(parse_params::<(String, u32)>(Params::Array(vec![json!("txid")])).is_err());
+        assert!(parse_params::<(u32,)>(Params::Array(vec![json!(1), json!(2)])).is_err());
+    }
+
+    #[test]
+    fn test_strip_legacy_version() {
+        let body = br#"{"jsonrpc":"1.0","id":1,"method":"getblockcount","params":[]}"#;
+        let stripped: Value = serde_json::from_str(&strip_legacy_version(body).unwrap()).unwrap();
+        assert!(stripped.get("jsonrpc").is_none());
+        assert_eq!(stripped["method"], "getblockcount");
+
+        let batch = br#"[{"jsonrpc":"1.0","id":1,"method":"a"},{"jsonrpc":"2.0","id":2,"method":"b"}]"#;
+        let stripped: Value = serde_json::from_str(&strip_legacy_version(batch).unwrap()).unwrap();
+        assert!(stripped[0].get("jsonrpc").is_none());
+        assert_eq!(stripped[1]["jsonrpc"], "2.0");
+
+        assert!(strip_legacy_version(br#"{"jsonrpc":"2.0","id":1,"method":"a"}"#).is_none());
+        assert!(strip_legacy_version(b"not json").is_none());
+
+        // The 1.0 form is then served with a Core style response
+        let mut io = IoHandler::with_compatibility(jsonrpc_core::Compatibility::Both);
+        io.add_sync_method("getblockcount", |_params: Params| Ok(json!(101)));
+        let response: Value = serde_json::from_str(&io.handle_request_sync(
+            &strip_legacy_version(body).unwrap(),
+        ).unwrap()).unwrap();
+        assert_eq!(response["result"], 101);
+        assert_eq!(response["id"], 1);
+    }
+
+    #[cfg(feature = "zmq")]
+    #[test]
+    fn test_zmq_notifications_list_configured_sockets() {
+        let mut config = Config::default_regtest();
+        config.network_config.zmq.enabled = false;
+        assert_eq!(zmq_notifications(&config), json!([]));
+
+        config.network_config.zmq.enabled = true;
+        config.network_config.zmq.pub_port = Some(28332);
+        config.network_config.zmq.topics = vec!["hashblock".to_string(), "rawtx".to_string(), "sequence".to_string()];
+        let notifications = zmq_notifications(&config);
+        let address = format!("tcp://{}:28332", config.rpc.host);
+        assert_eq!(notifications, json!([
+            { "type": "pubhashblock", "address": address, "hwm": 1000 },
+            { "type": "pubrawtx", "address": address, "hwm": 1000 },
+        ]));
+    }
+}
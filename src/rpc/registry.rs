@@ -0,0 +1,625 @@
+//! Declarations of every RPC method
+//!
+//! Each method lists its parameters and result with a small schema, which is
+//! all `help` and the `rpc.allowed_methods` check need, and maps directly to
+//! JSON Schema for the OpenRPC document. Handlers are still
+//! registered by hand in `register_*_methods`; `build_handler` checks every
+//! registered name has an entry here.
+
+use serde_json::{json, Value};
+
+/// Shape of a parameter or result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    String,
+    /// Hex encoded bytes, hashes and txids included
+    Hex,
+    Integer,
+    Number,
+    Boolean,
+    Object,
+    Null,
+    Array(&'static Schema),
+    OneOf(&'static [Schema]),
+}
+
+impl Schema {
+    pub fn to_json_schema(self) -> Value {
+        match self {
+            Schema::String => json!({ "type": "string" }),
+            Schema::Hex => json!({ "type": "string", "pattern": "^[0-9a-fA-F]*$" }),
+            Schema::Integer => json!({ "type": "integer" }),
+            Schema::Number => json!({ "type": "number" }),
+            Schema::Boolean => json!({ "type": "boolean" }),
+            Schema::Object => json!({ "type": "object" }),
+            Schema::Null => json!({ "type": "null" }),
+            Schema::Array(items) => json!({ "type": "array", "items": items.to_json_schema() }),
+            Schema::OneOf(schemas) => json!({ "oneOf": schemas.iter().map(|schema| schema.to_json_schema()).collect::<Vec<_>>() }),
+        }
+    }
+
+    fn is_string(self) -> bool {
+        matches!(self, Schema::String | Schema::Hex)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Param {
+    pub name: &'static str,
+    pub schema: Schema,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+const fn required(name: &'static str, schema: Schema, description: &'static str) -> Param {
+    Param { name, schema, required: true, description }
+}
+
+const fn optional(name: &'static str, schema: Schema, description: &'static str) -> Param {
+    Param { name, schema, required: false, description }
+}
+
+impl Param {
+    /// How the parameter appears in a `help` synopsis: strings quoted, arrays
+    /// by their element
+    fn synopsis(&self) -> String {
+        match self.schema {
+            Schema::Array(item) if item.is_string() => format!("[\"{}\",...]", self.name),
+            Schema::Array(Schema::Object) => "[{...},...]".to_string(),
+            schema if schema.is_string() => format!("\"{}\"", self.name),
+            _ => self.name.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodSpec {
+    pub name: &'static str,
+    /// Group the method is listed under, as in Core's help
+    pub category: &'static str,
+    pub description: &'static str,
+    pub params: &'static [Param],
+    pub result: Schema,
+    pub result_description: &'static str,
+    /// Only registered on regtest
+    pub regtest_only: bool,
+    /// False when the cargo feature the method belongs to is off
+    pub compiled: bool,
+}
+
+impl MethodSpec {
+    /// `name "arg" ( optional ... )`, with the optional arguments grouped
+    pub fn synopsis(&self) -> String {
+        let mut synopsis = self.name.to_string();
+        let (required, optional): (Vec<&Param>, Vec<&Param>) = self.params.iter().partition(|param| param.required);
+        for param in required {
+            synopsis.push(' ');
+            synopsis.push_str(&param.synopsis());
+        }
+        if !optional.is_empty() {
+            let optional: Vec<String> = optional.iter().map(|param| param.synopsis()).collect();
+            synopsis.push_str(&format!(" ( {} )", optional.join(" ")));
+        }
+        synopsis
+    }
+
+    /// The `help <method>` text
+    pub fn help(&self) -> String {
+        let mut help = format!("{}\n\n{}", self.synopsis(), self.description);
+        if !self.params.is_empty() {
+            help.push_str("\n\nArguments:");
+            for (position, param) in self.params.iter().enumerate() {
+                let requirement = if param.required { "required" } else { "optional" };
+                help.push_str(&format!("\n{}. {} ({}) {}", position + 1, param.name, requirement, param.description));
+            }
+        }
+        help.push_str(&format!("\n\nResult:\n{}", self.result_description));
+        help
+    }
+
+    /// The method's OpenRPC Method Object
+    pub fn to_openrpc(self) -> Value {
+        let params: Vec<Value> = self.params.iter().map(|param| json!({
+            "name": param.name,
+            "description": param.description,
+            "required": param.required,
+            "schema": param.schema.to_json_schema(),
+        })).collect();
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "tags": [{ "name": self.category }],
+            "paramStructure": "by-position",
+            "params": params,
+            "result": {
+                "name": "result",
+                "description": self.result_description,
+                "schema": self.result.to_json_schema(),
+            },
+        })
+    }
+}
+
+const fn method(
+    name: &'static str,
+    category: &'static str,
+    description: &'static str,
+    params: &'static [Param],
+    result: Schema,
+    result_description: &'static str,
+) -> MethodSpec {
+    MethodSpec { name, category, description, params, result, result_description, regtest_only: false, compiled: true }
+}
+
+const fn regtest(spec: MethodSpec) -> MethodSpec {
+    MethodSpec { regtest_only: true, ..spec }
+}
+
+const fn wallet(spec: MethodSpec) -> MethodSpec {
+    MethodSpec { compiled: cfg!(feature = "wallet"), ..spec }
+}
+
+const fn miner(spec: MethodSpec) -> MethodSpec {
+    MethodSpec { compiled: cfg!(feature = "miner"), ..spec }
+}
+
+const fn zmq(spec: MethodSpec) -> MethodSpec {
+    MethodSpec { compiled: cfg!(feature = "zmq"), ..spec }
+}
+
+const OPENRPC_VERSION: &str = "1.2.6";
+
+const HEX_OR_OBJECT: Schema = Schema::OneOf(&[Schema::Hex, Schema::Object]);
+const OBJECT_OR_NULL: Schema = Schema::OneOf(&[Schema::Object, Schema::Null]);
+
+pub static METHODS: &[MethodSpec] = &[
+    // Blockchain
+    method("getblockchaininfo", "blockchain",
+        "Returns an object containing various state info regarding blockchain processing.",
+        &[], Schema::Object, "Chain name, tip height and hash, difficulty, median time, chain work and sync progress."),
+    method("getchainstates", "blockchain",
+        "Return information about chainstates. After a node snapshot restore, a background chainstate validates the restored blocks until the snapshot is marked validated.",
+        &[], Schema::Object, "Best header height and each chainstate's tip, difficulty, progress, cache sizes and validated flag."),
+    method("getbestblockhash", "blockchain",
+        "Returns the hash of the best (tip) block in the most-work fully-validated chain.",
+        &[], Schema::Hex, "The block hash."),
+    method("getblock", "blockchain",
+        "If verbosity is 0, returns a string that is serialized, hex-encoded data for block 'hash'. If verbosity is 1, returns an Object with information about block 'hash'. If verbosity is 2, the Object includes every transaction decoded.",
+        &[
+            required("blockhash", Schema::Hex, "The block hash"),
+            optional("verbosity", Schema::Integer, "0 for hex-encoded data, 1 for a JSON object, 2 for a JSON object with transaction data (default 1)"),
+        ],
+        HEX_OR_OBJECT, "The serialized block, or its header fields with size, weight and transactions."),
+    method("getblockcount", "blockchain",
+        "Returns the height of the most-work fully-validated chain.",
+        &[], Schema::Integer, "The current block count."),
+    method("getblockhash", "blockchain",
+        "Returns hash of block in best-block-chain at height provided.",
+        &[required("height", Schema::Integer, "The height index")],
+        Schema::Hex, "The block hash."),
+    method("getblocklocations", "blockchain",
+        "Returns where blocks are stored, so external tools can read their serialized bytes directly. Blocks are RocksDB values; file and offset are null until blocks are kept in flat files.",
+        &[
+            required("blockhash", Schema::Hex, "The first block, on the active chain"),
+            optional("nblocks", Schema::Integer, "How many active chain blocks from it to cover, at most 1000 (default 1)"),
+        ],
+        Schema::Array(&Schema::Object), "Each block's hash, height, store, column family, hex key, file, offset and length in bytes."),
+    method("waitforblockheight", "blockchain",
+        "Waits for the active chain to reach a height, or for the timeout to run out, and returns the tip at that point.",
+        &[
+            required("height", Schema::Integer, "Block height to wait for"),
+            optional("timeout", Schema::Integer, "Time in milliseconds to wait for a response. 0 indicates no timeout (default 0)"),
+        ],
+        Schema::Object, "The hash and height of the current tip."),
+    method("getblockheader", "blockchain",
+        "If verbose is false, returns a string that is serialized, hex-encoded data for blockheader 'hash'. If verbose is true, returns an Object with information about blockheader 'hash'.",
+        &[
+            required("blockhash", Schema::Hex, "The block hash"),
+            optional("verbose", Schema::Boolean, "true for a JSON object, false for the hex-encoded data (default true)"),
+        ],
+        HEX_OR_OBJECT, "The serialized header, or its fields with height, confirmations and chain work."),
+    method("gettxoutsetinfo", "blockchain",
+        "Returns statistics about the unspent transaction output set. Scans the whole set, so this call may take some time.",
+        &[optional("hash_type", Schema::String, "muhash or none (default muhash)")],
+        Schema::Object, "Height, best block, output count, total amount and, for muhash, the set's MuHash and whether it matches the one kept block by block."),
+    method("getstaleblocks", "blockchain",
+        "Returns archived side chain and reorged-out blocks, highest first.",
+        &[optional("count", Schema::Integer, "How many blocks to return (default 50)")],
+        Schema::Array(&Schema::Object), "The stale blocks with their height, hash and why they left the active chain."),
+    method("acceptreorg", "blockchain",
+        "Follows a reorg that was held back for being deeper than validation.max_reorg_depth.",
+        &[required("blockhash", Schema::Hex, "The side chain tip the reorg leads to")],
+        Schema::Object, "The reorg record with the old and new tips, fork height and the blocks disconnected and connected."),
+    method("gettxout", "blockchain",
+        "Returns details about an unspent transaction output, or null if it is spent or unknown.",
+        &[
+            required("txid", Schema::Hex, "The transaction id"),
+            required("n", Schema::Integer, "The output number"),
+            optional("include_mempool", Schema::Boolean, "Whether to include the mempool (default true)"),
+        ],
+        OBJECT_OR_NULL, "The best block hash, confirmations, value, scriptPubKey and whether it is a coinbase output."),
+
+    // Mempool
+    method("estimatesmartfee", "util",
+        "Estimates the fee per kilobyte needed for a transaction to begin confirmation within conf_target blocks, from the current mempool.",
+        &[
+            required("conf_target", Schema::Integer, "Confirmation target in blocks (1 - 1008)"),
+            optional("estimate_mode", Schema::String, "Accepted for compatibility and ignored"),
+        ],
+        Schema::Object, "feerate in BTC/kvB and the blocks it was estimated for."),
+    method("getmempoolinfo", "blockchain",
+        "Returns details on the active state of the TX memory pool.",
+        &[], Schema::Object, "Size, usage and fee settings of the mempool, and the configured script filter."),
+    method("getrawmempool", "blockchain",
+        "Returns all transaction ids in memory pool as a json array of string transaction ids.",
+        &[optional("verbose", Schema::Boolean, "true for an object keyed by txid, false for an array of txids (default false)")],
+        Schema::OneOf(&[Schema::Array(&Schema::Hex), Schema::Object]), "The transaction ids, or their entries keyed by txid."),
+    method("getmempoolentry", "blockchain",
+        "Returns mempool data for given transaction, including whether it came through the miner lane.",
+        &[required("txid", Schema::Hex, "The transaction id, which must be in the mempool")],
+        Schema::Object, "Size, fees, time, in-mempool parents and children, ancestor and descendant totals and the priority tag."),
+    method("getmempoolancestors", "blockchain",
+        "If txid is in the mempool, returns all in-mempool ancestors.",
+        &[required("txid", Schema::Hex, "The transaction id, which must be in the mempool")],
+        Schema::Array(&Schema::Hex), "The ancestor transaction ids."),
+    method("getmempooldescendants", "blockchain",
+        "If txid is in the mempool, returns all in-mempool descendants.",
+        &[required("txid", Schema::Hex, "The transaction id, which must be in the mempool")],
+        Schema::Array(&Schema::Hex), "The descendant transaction ids."),
+
+    // Mining
+    miner(method("getblocktemplate", "mining",
+        "Returns data needed to construct a block to work on.",
+        &[optional("template_request", Schema::Object, "Accepted for compatibility and ignored")],
+        Schema::Object, "A BIP22 block template, miner lane transactions first.")),
+    miner(method("submitblock", "mining",
+        "Attempts to submit a new block to the network.",
+        &[required("hexdata", Schema::Hex, "The hex-encoded block data to submit"),
+          optional("dummy", Schema::String, "Ignored, for compatibility")],
+        Schema::OneOf(&[Schema::Null, Schema::String]), "Nothing when accepted, otherwise a BIP22 rejection reason such as duplicate or inconclusive.")),
+
+    // Generating
+    regtest(miner(method("generatetoaddress", "generating",
+        "Mine blocks immediately to a specified address, with the mempool's transactions (-regtest only).",
+        &[
+            required("nblocks", Schema::Integer, "How many blocks are generated"),
+            required("address", Schema::String, "The address to send the newly generated bitcoin to"),
+            optional("maxtries", Schema::Integer, "How many nonces to try across all blocks (default 1000000)"),
+        ],
+        Schema::Array(&Schema::Hex), "Hashes of the blocks generated.")),
+    regtest(miner(method("generateblock", "generating",
+        "Mine a block with exactly the given transactions to a specified address (-regtest only). Mempool transactions are given by txid, others as raw hex.",
+        &[
+            required("output", Schema::String, "The address to send the newly generated bitcoin to"),
+            required("transactions", Schema::Array(&Schema::String), "Txids of mempool transactions or raw transactions, in block order"),
+            optional("submit", Schema::Boolean, "Submit the block, or only return it as hex (default true)"),
+        ],
+        Schema::Object, "The block hash, and with submit false the block hex.")),
+
+    // Network
+    method("getnetworkinfo", "network",
+        "Returns an object containing various state info regarding P2P networking.",
+        &[], Schema::Object, "Version, services, connection counts, reachable networks, relay fees and warnings."),
+    method("getpeerinfo", "network",
+        "Returns data about each connected network node as a json array of objects.",
+        &[], Schema::Array(&Schema::Object), "One object per peer."),
+    method("getconnectioncount", "network",
+        "Returns the number of connections to other nodes.",
+        &[], Schema::Integer, "The connection count."),
+
+    // Raw transactions
+    method("getrawtransaction", "rawtransactions",
+        "Return the raw transaction data, from the mempool or the blockchain.",
+        &[
+            required("txid", Schema::Hex, "The transaction id"),
+            optional("verbose", Schema::OneOf(&[Schema::Boolean, Schema::Integer]), "true or 1 for a JSON object, false or 0 for the hex-encoded data (default false)"),
+        ],
+        HEX_OR_OBJECT, "The serialized transaction, or the decoded transaction."),
+    method("sendrawtransaction", "rawtransactions",
+        "Submit a raw transaction (serialized, hex-encoded) to local node and network.",
+        &[
+            required("hexstring", Schema::Hex, "The hex string of the raw transaction"),
+            optional("maxfeerate", Schema::Number, "Reject transactions whose fee rate is higher than this, in BTC/kvB and below 1. 0 accepts any fee rate (default 0.10)"),
+            optional("maxburnamount", Schema::Number, "Reject transactions with unspendable outputs, OP_RETURN data included, worth more than this in BTC (default 0)"),
+        ],
+        Schema::Hex, "The transaction id."),
+
+    // ZMQ
+    zmq(method("getzmqnotifications", "zmq",
+        "Returns information about the active ZeroMQ notifications.",
+        &[], Schema::Array(&Schema::Object), "type, address and hwm of each notification.")),
+
+    // PSBT
+    method("encodepsbtur", "rawtransactions",
+        "Encode a base64 PSBT as ur:crypto-psbt parts for animated QR codes. Parts past the fragment count are fountain codes.",
+        &[
+            required("psbt", Schema::String, "The base64 PSBT"),
+            optional("max_fragment_len", Schema::Integer, "Largest fragment in bytes, at least 10"),
+            optional("part_count", Schema::Integer, "How many parts to return (default the fragment count)"),
+        ],
+        Schema::Object, "The UR type and the parts."),
+    method("decodepsbtur", "rawtransactions",
+        "Reassemble scanned ur:crypto-psbt parts, in any order, into a base64 PSBT.",
+        &[required("part", Schema::Array(&Schema::String), "The scanned parts")],
+        Schema::Object, "Whether the PSBT is complete, the PSBT once it is, and the fragments received and expected."),
+
+    // Payment URIs
+    method("getpaymenturi", "util",
+        "Returns a BIP21 bitcoin: URI for the address, which must belong to this node's network.",
+        &[
+            required("address", Schema::String, "The address to pay"),
+            optional("amount", Schema::Number, "Amount in BTC"),
+            optional("label", Schema::String, "Label for the recipient"),
+            optional("message", Schema::String, "Message describing the payment"),
+        ],
+        Schema::String, "The URI."),
+    method("parsepaymenturi", "util",
+        "Parses a BIP21 bitcoin: URI, validating the address against this node's network.",
+        &[required("uri", Schema::String, "The bitcoin: URI")],
+        Schema::Object, "The address, amount, label, message and any other parameters."),
+
+    // Silent payments
+    method("getsilentpaymentaddress", "util",
+        "Returns the BIP 352 silent payment address for a scan and spend key pair.",
+        &[
+            required("scan_pubkey", Schema::Hex, "Compressed scan public key"),
+            required("spend_pubkey", Schema::Hex, "Compressed spend public key"),
+        ],
+        Schema::Object, "The address and both public keys."),
+    method("getsilentpaymenttweaks", "blockchain",
+        "Returns the silent payment tweak of each eligible transaction in the block. Requires the silent payment index.",
+        &[required("height", Schema::Integer, "The block height")],
+        Schema::Array(&Schema::Object), "txid and tweak of each eligible transaction."),
+    method("scansilentpayments", "blockchain",
+        "Finds silent payments to the given keys in indexed blocks. Requires the silent payment index.",
+        &[
+            required("scan_privkey", Schema::Hex, "The scan secret key"),
+            required("address", Schema::String, "The silent payment address, for its spend key"),
+            required("start_height", Schema::Integer, "First block to scan"),
+            optional("end_height", Schema::Integer, "Last block to scan (default the tip)"),
+        ],
+        Schema::Array(&Schema::Object), "The outputs found."),
+
+    // Wallet
+    wallet(method("createwallet", "wallet",
+        "Creates a watch-only wallet from an account xpub, optionally prefixed with its [fingerprint/path] origin.",
+        &[
+            required("wallet_name", Schema::String, "Name of the new wallet"),
+            required("account_key", Schema::String, "The account xpub or tpub"),
+            optional("avoid_reuse", Schema::Boolean, "Keep coins sent to already used addresses out of coin selection (default false)"),
+        ],
+        Schema::Object, "The wallet name.")),
+    wallet(method("listwallets", "wallet",
+        "Returns the names of the loaded wallets.",
+        &[], Schema::Array(&Schema::String), "The wallet names.")),
+    wallet(method("setwalletflag", "wallet",
+        "Change the state of the given wallet flag. Available flags: avoid_reuse (keep coins sent to already used addresses out of coin selection).",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            required("flag", Schema::String, "The flag to change"),
+            optional("value", Schema::Boolean, "The new state (default true)"),
+        ],
+        Schema::Object, "The flag and its new state.")),
+    wallet(method("settxnote", "wallet",
+        "Sets the bookkeeping note of a transaction. An empty or missing note removes it.",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            required("txid", Schema::Hex, "The transaction id"),
+            optional("note", Schema::String, "The note"),
+        ],
+        Schema::Null, "Nothing.")),
+    wallet(method("getnewaddress", "wallet",
+        "Returns a new receiving address. address_type is bech32 or bech32m.",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            optional("label", Schema::String, "Label for the address"),
+            optional("address_type", Schema::String, "bech32 or bech32m (default the wallet's)"),
+        ],
+        Schema::String, "The address.")),
+    wallet(method("getrawchangeaddress", "wallet",
+        "Returns a new change address.",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            optional("address_type", Schema::String, "bech32 or bech32m (default the wallet's)"),
+        ],
+        Schema::String, "The address.")),
+    wallet(method("listunspent", "wallet",
+        "Returns the wallet's unspent outputs, excluding those spent by mempool transactions.",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            optional("minconf", Schema::Integer, "Fewest confirmations (default 1)"),
+            optional("maxconf", Schema::Integer, "Most confirmations (default 9999999)"),
+        ],
+        Schema::Array(&Schema::Object), "One object per output.")),
+    wallet(method("leaseoutput", "wallet",
+        "Reserves an output under a 32-byte hex lock ID so no wallet spends it until the lease expires (default 600 seconds). The same ID may extend its lease.",
+        &[
+            required("id", Schema::Hex, "The 32-byte lock ID"),
+            required("txid", Schema::Hex, "The transaction id"),
+            required("vout", Schema::Integer, "The output number"),
+            optional("expiration_seconds", Schema::Integer, "Lease duration (default 600)"),
+        ],
+        Schema::Object, "The lease and when it expires.")),
+    wallet(method("releaseoutput", "wallet",
+        "Releases an output leased under the given ID.",
+        &[
+            required("id", Schema::Hex, "The 32-byte lock ID"),
+            required("txid", Schema::Hex, "The transaction id"),
+            required("vout", Schema::Integer, "The output number"),
+        ],
+        Schema::Object, "Whether a lease was released.")),
+    wallet(method("listleases", "wallet",
+        "Returns every active lease with its ID and expiration time.",
+        &[], Schema::Array(&Schema::Object), "One object per lease.")),
+    wallet(method("walletcreatefundedpsbt", "wallet",
+        "Creates an unsigned PSBT funded by the wallet. Options: add_inputs, minconf, avoid_reuse, changeAddress, change_type, fee_rate (sat/vB).",
+        &[
+            required("wallet_name", Schema::String, "The wallet"),
+            required("inputs", Schema::Array(&Schema::Object), "Inputs to spend, as {\"txid\":\"hex\",\"vout\":n}"),
+            required("outputs", Schema::OneOf(&[Schema::Object, Schema::Array(&Schema::Object)]), "Address to amount in BTC, as one object or a list of them"),
+            optional("locktime", Schema::Integer, "Raw locktime (default 0)"),
+            optional("options", Schema::Object, "Coin control options"),
+        ],
+        Schema::Object, "The base64 PSBT, its fee and the change output position, -1 without one.")),
+
+    // Multisig
+    wallet(method("createmultisigdescriptor", "wallet",
+        "Returns wsh(sortedmulti) receive and change descriptors for the cosigner xpubs, and the first receive address.",
+        &[
+            required("threshold", Schema::Integer, "Signatures required"),
+            required("account_key", Schema::Array(&Schema::String), "The cosigner account keys"),
+        ],
+        Schema::Object, "The receive and change descriptors and the first receive address.")),
+    wallet(method("createmultisigproposal", "wallet",
+        "Starts collecting signatures for a multisig PSBT. The proposal ID is the unsigned txid.",
+        &[
+            required("psbt", Schema::String, "The base64 PSBT"),
+            optional("description", Schema::String, "What the payment is for"),
+        ],
+        Schema::Object, "The proposal status.")),
+    wallet(method("submitmultisigsignatures", "wallet",
+        "Verifies a cosigner's partial signatures and merges them into the proposal.",
+        &[
+            required("proposal_id", Schema::Hex, "The proposal ID"),
+            required("psbt", Schema::String, "The cosigner's base64 PSBT"),
+        ],
+        Schema::Object, "The proposal status.")),
+    wallet(method("getmultisigproposal", "wallet",
+        "Returns the merged PSBT and signatures collected per input.",
+        &[required("proposal_id", Schema::Hex, "The proposal ID")],
+        Schema::Object, "The proposal status.")),
+    wallet(method("listmultisigproposals", "wallet",
+        "Returns every multisig proposal.",
+        &[], Schema::Array(&Schema::Object), "One status per proposal.")),
+
+    // Control
+    method("help", "control",
+        "List all commands, or get help for a specified command.",
+        &[optional("command", Schema::String, "The command to get help on")],
+        Schema::String, "The help text."),
+    method("rpc.discover", "control",
+        "Returns the OpenRPC document describing every method this node serves, also served at GET /openrpc.json.",
+        &[], Schema::Object, "The OpenRPC document."),
+    method("uptime", "control",
+        "Returns the total uptime of the server.",
+        &[], Schema::Integer, "Seconds since the server started."),
+    method("getversion", "control",
+        "Returns the node's name and version.",
+        &[], Schema::Object, "version, name and description."),
+    method("getconfig", "control",
+        "Returns the settings setconfig can change, or one of them, with their current values and whether settings.json overrides them.",
+        &[optional("key", Schema::String, "A dotted config key such as mempool.rbf")],
+        Schema::OneOf(&[Schema::Array(&Schema::Object), Schema::Object]),
+        "key, value, overridden and restart_required for each setting, or the one asked for."),
+    method("setconfig", "control",
+        "Override a config file setting in <datadir>/settings.json, which is applied at every startup. Mempool settings also take effect immediately.",
+        &[
+            required("key", Schema::String, "A dotted config key such as mempool.rbf, see getconfig"),
+            optional("value", Schema::OneOf(&[Schema::String, Schema::Number, Schema::Boolean, Schema::Array(&Schema::String)]),
+                "The new value, in the config file's format; leave out or pass null to drop the override"),
+        ],
+        Schema::Object, "The setting as getconfig reports it."),
+    regtest(method("setmocktime", "control",
+        "Set the local time to given timestamp (-regtest only). Pass 0 to go back to using the system time.",
+        &[required("timestamp", Schema::Integer, "Unix seconds, or 0 for the system time")],
+        Schema::Null, "Nothing.")),
+];
+
+/// A method compiled into this build
+pub fn lookup(name: &str) -> Option<&'static MethodSpec> {
+    METHODS.iter().find(|spec| spec.name == name && spec.compiled)
+}
+
+/// Methods registered on a node, regtest ones only when `regtest`
+pub fn available(regtest: bool) -> impl Iterator<Item = &'static MethodSpec> {
+    METHODS.iter().filter(move |spec| spec.compiled && (regtest || !spec.regtest_only))
+}
+
+/// The `help` text without a command: one method name per line
+pub fn help_listing(regtest: bool) -> String {
+    let mut listing = "Available commands:".to_string();
+    for spec in available(regtest) {
+        listing.push('\n');
+        listing.push_str(spec.name);
+    }
+    listing
+}
+
+/// OpenRPC document for the methods a node registers, regtest ones only when `regtest`
+pub fn openrpc_document(regtest: bool) -> Value {
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "BitKnotsRS JSON-RPC",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "methods": available(regtest).map(|spec| spec.to_openrpc()).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_synopsis_and_help() {
+        assert_eq!(lookup("getblockcount").unwrap().synopsis(), "getblockcount");
+        assert_eq!(lookup("getblock").unwrap().synopsis(), "getblock \"blockhash\" ( verbosity )");
+        assert_eq!(lookup("gettxout").unwrap().synopsis(), "gettxout \"txid\" n ( include_mempool )");
+        assert_eq!(lookup("decodepsbtur").unwrap().synopsis(), "decodepsbtur [\"part\",...]");
+        if cfg!(feature = "wallet") {
+            assert_eq!(
+                lookup("walletcreatefundedpsbt").unwrap().synopsis(),
+                "walletcreatefundedpsbt \"wallet_name\" [{...},...] outputs ( locktime options )"
+            );
+        }
+
+        let help = lookup("getblockhash").unwrap().help();
+        assert!(help.starts_with("getblockhash height\n\nReturns hash of block"));
+        assert!(help.contains("\n1. height (required) The height index"));
+        assert!(help.ends_with("Result:\nThe block hash."));
+    }
+
+    #[test]
+    fn test_registry_is_consistent() {
+        let mut names = HashSet::new();
+        for spec in METHODS {
+            assert!(names.insert(spec.name), "{} is declared twice", spec.name);
+            assert!(!spec.description.is_empty() && !spec.result_description.is_empty(), "{}", spec.name);
+            // Optional positional arguments can only trail the required ones
+            let first_optional = spec.params.iter().position(|param| !param.required).unwrap_or(spec.params.len());
+            assert!(spec.params[first_optional..].iter().all(|param| !param.required), "{}", spec.name);
+        }
+
+        assert!(available(false).all(|spec| !spec.regtest_only));
+        assert!(help_listing(true).lines().any(|line| line == "setmocktime"));
+        assert!(!help_listing(false).lines().any(|line| line == "setmocktime"));
+        // Methods behind a cargo feature disappear with it
+        assert_eq!(lookup("getblocktemplate").is_some(), cfg!(feature = "miner"));
+        assert_eq!(help_listing(true).lines().any(|line| line == "listwallets"), cfg!(feature = "wallet"));
+    }
+
+    #[test]
+    fn test_json_schema() {
+        assert_eq!(Schema::Array(&Schema::Hex).to_json_schema(), json!({
+            "type": "array",
+            "items": { "type": "string", "pattern": "^[0-9a-fA-F]*$" },
+        }));
+        assert_eq!(OBJECT_OR_NULL.to_json_schema(), json!({ "oneOf": [{ "type": "object" }, { "type": "null" }] }));
+
+        let document = openrpc_document(false);
+        assert_eq!(document["openrpc"], OPENRPC_VERSION);
+        let methods = document["methods"].as_array().unwrap();
+        assert_eq!(methods.len(), available(false).count());
+        let gettxout = methods.iter().find(|method| method["name"] == "gettxout").unwrap();
+        assert_eq!(gettxout["params"][1], json!({
+            "name": "n",
+            "description": "The output number",
+            "required": true,
+            "schema": { "type": "integer" },
+        }));
+        assert_eq!(gettxout["result"]["schema"], OBJECT_OR_NULL.to_json_schema());
+        assert!(methods.iter().all(|method| method["name"] != "setmocktime"));
+    }
+}
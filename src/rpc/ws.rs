@@ -0,0 +1,291 @@
+//! JSON-RPC over WebSocket
+//!
+//! `GET /api/v1/rpc/ws` upgrades to a WebSocket that takes the same requests
+//! as the HTTP RPC server, one JSON-RPC request or batch per text frame, with
+//! the same credentials and `rpc.allowed_methods`. Clients that keep the
+//! connection open can also subscribe instead of polling:
+//!
+//! - `subscribe_newblock` notifies `newblock` with the hash and height of each new tip
+//! - `subscribe_newtx` notifies `newtx` with the txid and fee rate of each transaction the mempool accepts
+//! - `unsubscribe` takes the subscription ID either call returned
+//!
+//! Notifications are JSON-RPC 2.0 requests without an ID, carrying
+//! `{"subscription": <id>, "result": ...}` as their params.
+
+use actix::prelude::*;
+use actix_web_actors::ws;
+use jsonrpc_core::IoHandler;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, WatchStream};
+use tokio_stream::StreamExt;
+use tracing::{debug, warn};
+
+use super::auth::RpcAuth;
+use crate::actors::chain::ChainTipFeed;
+use crate::actors::mempool::{MempoolDelta, MempoolDeltaFeed, SequencedMempoolDelta};
+use crate::actors::ChainTip;
+use crate::config::RpcJsonFormat;
+
+/// How often heartbeat pings are sent to clients
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a client may stay silent before the session is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// JSON-RPC "invalid request"
+const INVALID_REQUEST: i64 = -32600;
+/// JSON-RPC "invalid params"
+const INVALID_PARAMS: i64 = -32602;
+/// JSON-RPC "method not found", also used for methods left out of `rpc.allowed_methods`
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topic {
+    NewBlock,
+    NewTx,
+}
+
+impl Topic {
+    fn notification(&self) -> &'static str {
+        match self {
+            Topic::NewBlock => "newblock",
+            Topic::NewTx => "newtx",
+        }
+    }
+}
+
+/// A request the session answers itself rather than passing to the handler
+#[derive(Debug, PartialEq)]
+enum SessionCall {
+    Subscribe(Topic),
+    Unsubscribe(Option<u64>),
+}
+
+/// The subscription call in a single (non-batch) request, with its ID
+fn session_call(request: &Value) -> Option<(Value, SessionCall)> {
+    let call = match request.get("method")?.as_str()? {
+        "subscribe_newblock" => SessionCall::Subscribe(Topic::NewBlock),
+        "subscribe_newtx" => SessionCall::Subscribe(Topic::NewTx),
+        "unsubscribe" => SessionCall::Unsubscribe(request.get("params").and_then(|params| params.get(0)).and_then(Value::as_u64)),
+        _ => return None,
+    };
+    Some((request.get("id").cloned().unwrap_or(Value::Null), call))
+}
+
+fn result_response(id: Value, result: Value) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string()
+}
+
+fn error_response(id: Value, code: i64, message: String) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }).to_string()
+}
+
+pub struct RpcWebSocketSession {
+    io: IoHandler,
+    auth: Arc<RpcAuth>,
+    json_format: RpcJsonFormat,
+    tip_feed: ChainTipFeed,
+    mempool_deltas: MempoolDeltaFeed,
+    /// Open subscriptions by ID, with the stream feeding each
+    subscriptions: HashMap<u64, (Topic, SpawnHandle)>,
+    next_subscription: u64,
+    last_heartbeat: Instant,
+}
+
+impl RpcWebSocketSession {
+    pub fn new(
+        io: IoHandler,
+        auth: Arc<RpcAuth>,
+        json_format: RpcJsonFormat,
+        tip_feed: ChainTipFeed,
+        mempool_deltas: MempoolDeltaFeed,
+    ) -> Self {
+        Self {
+            io,
+            auth,
+            json_format,
+            tip_feed,
+            mempool_deltas,
+            subscriptions: HashMap::new(),
+            next_subscription: 1,
+            last_heartbeat: Instant::now(),
+        }
+    }
+
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.last_heartbeat) > CLIENT_TIMEOUT {
+                debug!("RPC WebSocket client timed out");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn handle_text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let request: Option<Value> = serde_json::from_str(text).ok();
+        if let Some((id, call)) = request.as_ref().and_then(session_call) {
+            let response = self.handle_session_call(id, call, ctx);
+            ctx.text(response);
+            return;
+        }
+
+        if let Some(method) = self.auth.refused_method(text.as_bytes()) {
+            warn!("Refused RPC call to {}, which is not in rpc.allowed_methods", method);
+            let id = request.and_then(|request| request.get("id").cloned()).unwrap_or(Value::Null);
+            ctx.text(error_response(id, METHOD_NOT_FOUND, format!("{} is not in rpc.allowed_methods", method)));
+            return;
+        }
+        let body = super::strip_legacy_version(text.as_bytes()).unwrap_or_else(|| text.to_string());
+        let io = self.io.clone();
+        let json_format = self.json_format;
+        async move {
+            let response = io.handle_request(&body).await?;
+            Some(super::canonical::rewrite_response(json_format, &body, response))
+        }
+            .into_actor(self)
+            .map(|response, _act, ctx| {
+                // Notifications get no response
+                if let Some(response) = response {
+                    ctx.text(response);
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn handle_session_call(&mut self, id: Value, call: SessionCall, ctx: &mut ws::WebsocketContext<Self>) -> String {
+        match call {
+            SessionCall::Subscribe(topic) => {
+                let subscription = self.next_subscription;
+                self.next_subscription += 1;
+                let handle = match topic {
+                    Topic::NewBlock => {
+                        let stream = WatchStream::from_changes(self.tip_feed.subscribe())
+                            .filter_map(|tip| tip)
+                            .map(move |tip| (subscription, tip));
+                        ctx.add_stream(stream)
+                    }
+                    Topic::NewTx => {
+                        let stream = BroadcastStream::new(self.mempool_deltas.subscribe())
+                            .map(move |delta| (subscription, delta));
+                        ctx.add_stream(stream)
+                    }
+                };
+                self.subscriptions.insert(subscription, (topic, handle));
+                result_response(id, json!(subscription))
+            }
+            SessionCall::Unsubscribe(subscription) => {
+                match subscription.and_then(|subscription| self.subscriptions.remove(&subscription)) {
+                    Some((_, handle)) => {
+                        ctx.cancel_future(handle);
+                        result_response(id, json!(true))
+                    }
+                    None => error_response(id, INVALID_PARAMS, "No such subscription".to_string()),
+                }
+            }
+        }
+    }
+
+    fn notify(&self, subscription: u64, result: Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some((topic, _)) = self.subscriptions.get(&subscription) else {
+            return;
+        };
+        ctx.text(json!({
+            "jsonrpc": "2.0",
+            "method": topic.notification(),
+            "params": { "subscription": subscription, "result": result },
+        }).to_string());
+    }
+}
+
+impl Actor for RpcWebSocketSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.start_heartbeat(ctx);
+    }
+}
+
+impl StreamHandler<(u64, ChainTip)> for RpcWebSocketSession {
+    fn handle(&mut self, (subscription, tip): (u64, ChainTip), ctx: &mut Self::Context) {
+        self.notify(subscription, json!({ "hash": tip.hash.to_string(), "height": tip.height }), ctx);
+    }
+
+    // Keep the session when a feed ends, it just stops notifying
+    fn finished(&mut self, _ctx: &mut Self::Context) {}
+}
+
+impl StreamHandler<(u64, Result<SequencedMempoolDelta, BroadcastStreamRecvError>)> for RpcWebSocketSession {
+    fn handle(
+        &mut self,
+        (subscription, delta): (u64, Result<SequencedMempoolDelta, BroadcastStreamRecvError>),
+        ctx: &mut Self::Context,
+    ) {
+        match delta {
+            Ok(SequencedMempoolDelta { delta: MempoolDelta::Added { txid, fee_rate }, .. }) => {
+                self.notify(subscription, json!({ "txid": txid, "fee_rate": fee_rate }), ctx);
+            }
+            Ok(_) => {}
+            // Missed transactions cannot be recovered from the feed, so say how many
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                warn!("RPC WebSocket newtx subscriber lagged behind by {} mempool deltas", missed);
+                self.notify(subscription, json!({ "missed": missed }), ctx);
+            }
+        }
+    }
+
+    fn finished(&mut self, _ctx: &mut Self::Context) {}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for RpcWebSocketSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.last_heartbeat = Instant::now();
+        match msg {
+            Ok(ws::Message::Text(text)) => self.handle_text(&text, ctx),
+            Ok(ws::Message::Ping(payload)) => ctx.pong(&payload),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(ws::Message::Binary(_)) => {
+                ctx.text(error_response(Value::Null, INVALID_REQUEST, "Requests must be sent as text frames".to_string()));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("RPC WebSocket protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_calls() {
+        let call = |request: Value| session_call(&request);
+        assert_eq!(
+            call(json!({"jsonrpc": "2.0", "id": 1, "method": "subscribe_newblock"})),
+            Some((json!(1), SessionCall::Subscribe(Topic::NewBlock))),
+        );
+        assert_eq!(
+            call(json!({"id": "a", "method": "subscribe_newtx", "params": []})),
+            Some((json!("a"), SessionCall::Subscribe(Topic::NewTx))),
+        );
+        assert_eq!(
+            call(json!({"id": 2, "method": "unsubscribe", "params": [3]})),
+            Some((json!(2), SessionCall::Unsubscribe(Some(3)))),
+        );
+        assert_eq!(call(json!({"id": 2, "method": "unsubscribe"})), Some((json!(2), SessionCall::Unsubscribe(None))));
+
+        // Everything else goes to the RPC handler
+        assert_eq!(call(json!({"id": 1, "method": "getblockcount"})), None);
+        assert_eq!(call(json!([{"id": 1, "method": "subscribe_newblock"}])), None);
+    }
+}
@@ -0,0 +1,518 @@
+//! Deterministic regtest scenarios
+//!
+//! A scenario is a TOML script of steps that the runner drives through the
+//! in-process chain, mempool and storage actors. Wallet keys are derived from
+//! wallet names and every block is timestamped one second after its parent, so
+//! the same script run against an empty datadir always produces the same chain.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use actix::Addr;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{All, Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, Block, BlockHash, Network, OutPoint, PublicKey, ScriptBuf, Sequence};
+use bitcoin::{Transaction, TxIn, TxOut, Txid, Weight, Witness};
+use serde::{Deserialize, Serialize};
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::amount::{FeeRate, Satoshi};
+use crate::actors::chain::ChainActor;
+use crate::actors::mempool::{MempoolActor, TemplateTransaction};
+use crate::actors::storage::StorageActor;
+use crate::actors::{AddToMempool, GetBlock, GetBlockTemplateTransactions, GetChainInfo, GetChainTip};
+use crate::actors::{GetHeaders, GetMempoolEntry, InvalidateBlock, ScanUtxos, StoreBlock, ChainTip};
+use crate::error::{ScenarioError, ScenarioResult};
+use crate::mining::{BlockTemplate, CoinbaseSpec, COINBASE_RESERVED_WEIGHT};
+
+/// Confirmations before a coinbase output can be spent
+const COINBASE_MATURITY: u64 = 100;
+
+const COINBASE_MESSAGE: &[u8] = b"/bitknotsrs-scenario/";
+
+/// Virtual size estimates for P2WPKH spends, used to size fees up front
+const TX_OVERHEAD_VBYTES: u64 = 11;
+const P2WPKH_INPUT_VBYTES: u64 = 68;
+const P2WPKH_OUTPUT_VBYTES: u64 = 31;
+
+fn default_wallet() -> String {
+    "miner".to_string()
+}
+
+fn default_fee_rate() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub steps: Vec<ScenarioStep>,
+}
+
+impl Scenario {
+    pub fn parse(script: &str) -> ScenarioResult<Self> {
+        Ok(toml::from_str(script)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScenarioStep {
+    /// Mine blocks on the tip with the mempool contents, paying the coinbase to `to`
+    Mine {
+        blocks: u64,
+        #[serde(default = "default_wallet")]
+        to: String,
+    },
+    /// Pay `amount` satoshis from one wallet to another through the mempool
+    Send {
+        from: String,
+        to: String,
+        amount: u64,
+        /// sat/vB
+        #[serde(default = "default_fee_rate")]
+        fee_rate: u64,
+    },
+    /// Invalidate the active block at `height` and everything above it
+    Invalidate { height: u64 },
+    /// Replace the top `depth` blocks with a longer empty fork paying to `to`
+    Reorg {
+        depth: u64,
+        #[serde(default = "default_wallet")]
+        to: String,
+    },
+}
+
+impl ScenarioStep {
+    fn action(&self) -> &'static str {
+        match self {
+            ScenarioStep::Mine { .. } => "mine",
+            ScenarioStep::Send { .. } => "send",
+            ScenarioStep::Invalidate { .. } => "invalidate",
+            ScenarioStep::Reorg { .. } => "reorg",
+        }
+    }
+}
+
+/// P2WPKH wallet whose key is derived from its name
+pub struct ScenarioWallet {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    pub address: Address,
+}
+
+impl ScenarioWallet {
+    pub fn new(secp: &Secp256k1<All>, name: &str, network: Network) -> Self {
+        let seed = sha256::Hash::hash(format!("bitknotsrs-scenario/{}", name).as_bytes());
+        let secret_key = SecretKey::from_slice(seed.as_byte_array()).expect("hash is a valid secret key");
+        let public_key = PublicKey::new(secret_key.public_key(secp));
+        let address = Address::p2wpkh(&public_key, network).expect("key is compressed");
+        Self { secret_key, public_key, address }
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        self.address.script_pubkey()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioReport {
+    pub tip_height: u64,
+    pub tip_hash: String,
+    pub steps: Vec<StepReport>,
+    pub wallets: BTreeMap<String, WalletReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepReport {
+    pub step: usize,
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disconnected: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletReport {
+    pub address: String,
+    /// Confirmed balance in satoshis, including immature coinbases
+    pub balance: u64,
+}
+
+pub struct ScenarioRunner {
+    storage_actor: Addr<StorageActor>,
+    mempool_actor: Addr<MempoolActor>,
+    chain_actor: Addr<ChainActor>,
+    network: Network,
+    secp: Secp256k1<All>,
+    wallets: BTreeMap<String, ScenarioWallet>,
+    /// Wallet coins spent by transactions still waiting in the mempool
+    pending_spends: HashMap<OutPoint, Txid>,
+    /// Keeps coinbases unique when several blocks are mined on the same parent
+    extra_nonce: u64,
+}
+
+impl ScenarioRunner {
+    pub fn new(
+        network: Network,
+        storage_actor: Addr<StorageActor>,
+        mempool_actor: Addr<MempoolActor>,
+        chain_actor: Addr<ChainActor>,
+    ) -> ScenarioResult<Self> {
+        if network != Network::Regtest {
+            return Err(ScenarioError::NotRegtest);
+        }
+        Ok(Self {
+            storage_actor,
+            mempool_actor,
+            chain_actor,
+            network,
+            secp: Secp256k1::new(),
+            wallets: BTreeMap::new(),
+            pending_spends: HashMap::new(),
+            extra_nonce: 0,
+        })
+    }
+
+    pub async fn run(&mut self, scenario: &Scenario) -> ScenarioResult<ScenarioReport> {
+        // Queued until the chain actor has loaded its tip or connected genesis
+        self.chain_actor.send_traced(GetChainInfo).await??;
+
+        let mut steps = Vec::with_capacity(scenario.steps.len());
+        for (index, step) in scenario.steps.iter().enumerate() {
+            let number = index + 1;
+            let mut report = StepReport {
+                step: number,
+                action: step.action(),
+                blocks: Vec::new(),
+                txid: None,
+                disconnected: Vec::new(),
+            };
+            match step {
+                ScenarioStep::Mine { blocks, to } => {
+                    for _ in 0..*blocks {
+                        report.blocks.push(self.mine_on_tip(number, to).await?.to_string());
+                    }
+                }
+                ScenarioStep::Send { from, to, amount, fee_rate } => {
+                    report.txid = Some(self.send(number, from, to, *amount, *fee_rate).await?.to_string());
+                }
+                ScenarioStep::Invalidate { height } => {
+                    let hash = self.active_hash(number, *height).await?;
+                    let disconnected = self.chain_actor.send_traced(InvalidateBlock { hash }).await??
+                        .ok_or_else(|| step_error(number, format!("block {} is not on the active chain", hash)))?;
+                    report.disconnected = disconnected.iter().map(ToString::to_string).collect();
+                }
+                ScenarioStep::Reorg { depth, to } => {
+                    let (blocks, disconnected) = self.reorg(number, *depth, to).await?;
+                    report.blocks = blocks.iter().map(ToString::to_string).collect();
+                    report.disconnected = disconnected.iter().map(ToString::to_string).collect();
+                }
+            }
+            steps.push(report);
+        }
+
+        let tip = self.tip(scenario.steps.len()).await?;
+        let mut wallets = BTreeMap::new();
+        for (name, wallet) in &self.wallets {
+            let coins = self.storage_actor.send_traced(ScanUtxos { script_pubkeys: HashSet::from([wallet.script_pubkey()]) }).await??;
+            wallets.insert(name.clone(), WalletReport {
+                address: wallet.address.to_string(),
+                balance: coins.iter().map(|(_, coin)| coin.output.value.to_sat()).sum(),
+            });
+        }
+
+        Ok(ScenarioReport {
+            tip_height: tip.height,
+            tip_hash: tip.hash.to_string(),
+            steps,
+            wallets,
+        })
+    }
+
+    fn wallet(&mut self, name: &str) -> &ScenarioWallet {
+        let (secp, network) = (&self.secp, self.network);
+        self.wallets
+            .entry(name.to_string())
+            .or_insert_with(|| ScenarioWallet::new(secp, name, network))
+    }
+
+    async fn tip(&self, step: usize) -> ScenarioResult<ChainTip> {
+        self.storage_actor.send_traced(GetChainTip).await??
+            .ok_or_else(|| step_error(step, "no chain tip"))
+    }
+
+    async fn block(&self, step: usize, hash: BlockHash) -> ScenarioResult<Block> {
+        self.storage_actor.send_traced(GetBlock { hash }).await??
+            .ok_or_else(|| step_error(step, format!("block {} not found", hash)))
+    }
+
+    async fn active_hash(&self, step: usize, height: u64) -> ScenarioResult<BlockHash> {
+        let export = self.storage_actor.send_traced(GetHeaders { start_height: height, count: 1, interval: 1 }).await??;
+        export.headers
+            .first()
+            .and_then(|record| record.hash.parse().ok())
+            .ok_or_else(|| step_error(step, format!("no active block at height {}", height)))
+    }
+
+    /// Solved block on `parent` with a coinbase paying to `payout`
+    fn build_block(&mut self, parent: &Block, parent_height: u64, transactions: Vec<TemplateTransaction>, payout: ScriptBuf) -> Block {
+        let template = BlockTemplate::new(parent, parent_height, transactions, self.network, parent.header.time + 1);
+        let spec = CoinbaseSpec {
+            payouts: vec![(payout, 1.0)],
+            message: COINBASE_MESSAGE.to_vec(),
+            extra_nonce_size: 8,
+        };
+        let extra_nonce = self.extra_nonce.to_le_bytes();
+        self.extra_nonce += 1;
+
+        // Regtest targets are met within a handful of attempts
+        template.solve(&spec, &extra_nonce, template.min_time, u64::MAX)
+            .expect("regtest target not met")
+    }
+
+    async fn submit_block(&self, block: Block) -> ScenarioResult<BlockHash> {
+        let hash = block.block_hash();
+        self.chain_actor.send_traced(StoreBlock { block }).await??;
+        Ok(hash)
+    }
+
+    async fn mine_on_tip(&mut self, step: usize, to: &str) -> ScenarioResult<BlockHash> {
+        let payout = self.wallet(to).script_pubkey();
+        let tip = self.tip(step).await?;
+        let parent = self.block(step, tip.hash).await?;
+        let max_weight = Weight::MAX_BLOCK.to_wu() - COINBASE_RESERVED_WEIGHT;
+        let transactions = self.mempool_actor.send_traced(GetBlockTemplateTransactions { max_weight }).await??;
+
+        let block = self.build_block(&parent, tip.height, transactions, payout);
+        let hash = self.submit_block(block).await?;
+        if self.tip(step).await?.hash != hash {
+            return Err(step_error(step, format!("block {} was not connected", hash)));
+        }
+        Ok(hash)
+    }
+
+    async fn reorg(&mut self, step: usize, depth: u64, to: &str) -> ScenarioResult<(Vec<BlockHash>, Vec<BlockHash>)> {
+        let payout = self.wallet(to).script_pubkey();
+        let tip = self.tip(step).await?;
+        if depth == 0 || depth > tip.height {
+            return Err(step_error(step, format!("cannot reorg {} blocks at height {}", depth, tip.height)));
+        }
+        let fork_height = tip.height - depth;
+        let mut disconnected = Vec::new();
+        for height in (fork_height + 1..=tip.height).rev() {
+            disconnected.push(self.active_hash(step, height).await?);
+        }
+
+        let mut parent = self.block(step, self.active_hash(step, fork_height).await?).await?;
+        let mut blocks = Vec::new();
+        for height in fork_height..=tip.height {
+            let block = self.build_block(&parent, height, Vec::new(), payout.clone());
+            blocks.push(self.submit_block(block.clone()).await?);
+            parent = block;
+        }
+
+        if blocks.last() != Some(&self.tip(step).await?.hash) {
+            return Err(step_error(step, "fork did not become the active chain"));
+        }
+        Ok((blocks, disconnected))
+    }
+
+    async fn send(&mut self, step: usize, from: &str, to: &str, amount: u64, fee_rate: u64) -> ScenarioResult<Txid> {
+        let destination = self.wallet(to).script_pubkey();
+        let change = self.wallet(from).script_pubkey();
+        let tip = self.tip(step).await?;
+
+        // Coins stay reserved only while their spending transaction is in the mempool
+        let mut still_pending = HashMap::new();
+        for (outpoint, txid) in std::mem::take(&mut self.pending_spends) {
+            if self.mempool_actor.send_traced(GetMempoolEntry { txid }).await??.is_some() {
+                still_pending.insert(outpoint, txid);
+            }
+        }
+        self.pending_spends = still_pending;
+
+        let coins: Vec<(OutPoint, TxOut)> = self.storage_actor.send_traced(ScanUtxos { script_pubkeys: HashSet::from([change.clone()]) }).await??
+            .into_iter()
+            .filter(|(outpoint, coin)| !self.pending_spends.contains_key(outpoint)
+                && (!coin.is_coinbase || tip.height + 1 >= coin.height + COINBASE_MATURITY))
+            .map(|(outpoint, coin)| (outpoint, coin.output))
+            .collect();
+
+        let fee_for = |inputs: usize| {
+            fee_rate * (TX_OVERHEAD_VBYTES + P2WPKH_INPUT_VBYTES * inputs as u64 + P2WPKH_OUTPUT_VBYTES * 2)
+        };
+        let mut selected = Vec::new();
+        let mut total = 0;
+        for (outpoint, output) in &coins {
+            if total >= amount + fee_for(selected.len()) {
+                break;
+            }
+            total += output.value.to_sat();
+            selected.push((*outpoint, output.clone()));
+        }
+        let fee = fee_for(selected.len());
+        if total < amount + fee {
+            return Err(ScenarioError::InsufficientFunds {
+                step,
+                wallet: from.to_string(),
+                available: coins.iter().map(|(_, output)| output.value.to_sat()).sum(),
+                needed: amount + fee,
+            });
+        }
+
+        let mut output = vec![TxOut { value: Amount::from_sat(amount), script_pubkey: destination }];
+        // Change below the dust limit is left to the miner
+        let change_value = total - amount - fee;
+        if change_value >= change.dust_value().to_sat() {
+            output.push(TxOut { value: Amount::from_sat(change_value), script_pubkey: change });
+        }
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: selected.iter().map(|(outpoint, _)| TxIn {
+                previous_output: *outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }).collect(),
+            output,
+        };
+        self.sign(from, &mut tx, &selected);
+        let spent_outputs = selected.iter().map(|(_, output)| output.clone()).collect();
+
+        let txid = tx.txid();
+        let fee = total - tx.output.iter().map(|output| output.value.to_sat()).sum::<u64>();
+        let fee = Satoshi(fee);
+        let fee_rate = FeeRate::from_fee(fee, tx.vsize() as u64);
+        self.mempool_actor.send_traced(AddToMempool { tx, fee, fee_rate, spent_outputs }).await??;
+        if self.mempool_actor.send_traced(GetMempoolEntry { txid }).await??.is_none() {
+            return Err(step_error(step, format!("transaction {} was rejected by the mempool", txid)));
+        }
+        for (outpoint, _) in selected {
+            self.pending_spends.insert(outpoint, txid);
+        }
+        Ok(txid)
+    }
+
+    fn sign(&mut self, name: &str, tx: &mut Transaction, prevouts: &[(OutPoint, TxOut)]) {
+        let wallet = self.wallet(name);
+        let (secret_key, public_key) = (wallet.secret_key, wallet.public_key);
+
+        let mut cache = SighashCache::new(&*tx);
+        let witnesses: Vec<Witness> = prevouts
+            .iter()
+            .enumerate()
+            .map(|(index, (_, prevout))| {
+                let sighash = cache
+                    .p2wpkh_signature_hash(index, &prevout.script_pubkey, prevout.value, EcdsaSighashType::All)
+                    .expect("input index and script are valid");
+                let signature = bitcoin::ecdsa::Signature {
+                    sig: self.secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret_key),
+                    hash_ty: EcdsaSighashType::All,
+                };
+                Witness::p2wpkh(&signature, &public_key.inner)
+            })
+            .collect();
+        for (input, witness) in tx.input.iter_mut().zip(witnesses) {
+            input.witness = witness;
+        }
+    }
+}
+
+fn step_error(step: usize, reason: impl ToString) -> ScenarioError {
+    ScenarioError::Step { step, reason: reason.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Actor;
+    use crate::actors::mempool::{MempoolDeltaFeed, MEMPOOL_DELTA_BUFFER};
+    use crate::config::Config;
+    use crate::events::EventManager;
+
+    const SCRIPT: &str = r#"
+        [[steps]]
+        action = "mine"
+        blocks = 101
+        to = "alice"
+
+        [[steps]]
+        action = "send"
+        from = "alice"
+        to = "bob"
+        amount = 1000000000
+        fee_rate = 2
+
+        [[steps]]
+        action = "mine"
+        blocks = 1
+
+        [[steps]]
+        action = "reorg"
+        depth = 2
+
+        [[steps]]
+        action = "invalidate"
+        height = 103
+    "#;
+
+    async fn run_scenario(scenario: &Scenario) -> ScenarioReport {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        let event_manager = EventManager::with_publishers(Vec::new());
+
+        let storage_actor = StorageActor::new(&config).start();
+        let mempool_actor = MempoolActor::new(
+            &config,
+            storage_actor.clone(),
+            MempoolDeltaFeed::new(MEMPOOL_DELTA_BUFFER),
+            Vec::new(),
+            event_manager.clone(),
+        ).start();
+        let chain_actor = ChainActor::new(&config, storage_actor.clone(), mempool_actor.clone(), Vec::new(), event_manager).start();
+
+        let mut runner = ScenarioRunner::new(Network::Regtest, storage_actor, mempool_actor, chain_actor).unwrap();
+        runner.run(scenario).await.unwrap()
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let scenario = Scenario::parse(SCRIPT).unwrap();
+        assert_eq!(scenario.steps.len(), 5);
+        assert_eq!(scenario.steps[1], ScenarioStep::Send {
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            amount: 1_000_000_000,
+            fee_rate: 2,
+        });
+        assert_eq!(scenario.steps[2], ScenarioStep::Mine { blocks: 1, to: "miner".to_string() });
+        assert!(Scenario::parse("[[steps]]\naction = \"explode\"").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_scenario_is_reproducible() {
+        let scenario = Scenario::parse(SCRIPT).unwrap();
+        let report = run_scenario(&scenario).await;
+
+        // The send confirmed at 102, then the empty fork from 100 reorged it out
+        assert_eq!(report.steps[3].disconnected.len(), 2);
+        assert_eq!(report.steps[3].blocks.len(), 3);
+        assert_eq!(report.steps[4].disconnected.len(), 1);
+        assert_eq!(report.tip_height, 102);
+        assert_eq!(report.wallets["alice"].balance, 100 * 50 * 100_000_000);
+        assert_eq!(report.wallets["bob"].balance, 0);
+        assert_eq!(report.wallets["miner"].balance, 2 * 50 * 100_000_000);
+
+        let again = run_scenario(&scenario).await;
+        assert_eq!(again.tip_hash, report.tip_hash);
+        assert_eq!(again.steps[1].txid, report.steps[1].txid);
+    }
+}
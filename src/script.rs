@@ -0,0 +1,305 @@
+//! Input script and witness verification
+//!
+//! Every input is run through libbitcoinconsensus, the script interpreter
+//! extracted from Bitcoin Core, against the output it spends. Blocks are held
+//! to the soft fork rules active at their height; mempool transactions to all
+//! of them. The bundled library predates taproot, so in blocks witness
+//! version 1 spends are treated as an upgradable witness version and pass
+//! unchecked, with a warning naming the block, while the mempool refuses them
+//! as non-standard rather than relay spends it never verified.
+//!
+//! Ancestors of a configured assume-valid block skip the check, the way
+//! Core's `-assumevalid` does. A block counts as an ancestor only when the
+//! best header chain holds the assume-valid block at its height and this
+//! block at its own, so a block on any other chain is always checked. Peers'
+//! headers carry the best header chain ahead of the blocks, so a syncing node
+//! skips the blocks below the assume-valid one before it arrives; if a
+//! different block connects at that height, checks resume for good.
+
+use bitcoin::bitcoinconsensus::{self, VERIFY_ALL, VERIFY_CHECKLOCKTIMEVERIFY, VERIFY_CHECKSEQUENCEVERIFY};
+use bitcoin::bitcoinconsensus::{VERIFY_DERSIG, VERIFY_NONE, VERIFY_NULLDUMMY, VERIFY_P2SH, VERIFY_WITNESS};
+use bitcoin::{BlockHash, Network, Transaction, TxOut, WitnessVersion};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::{ConfigError, ConfigResult, PolicyError};
+
+/// Rules relayed transactions are checked under
+pub const MEMPOOL_FLAGS: u32 = VERIFY_ALL;
+
+/// Activation heights of the soft forks that added script rules
+struct Deployments {
+    /// Historic block that breaks P2SH and is accepted without it
+    bip16_exception: Option<&'static str>,
+    bip66: u64,
+    bip65: u64,
+    csv: u64,
+    segwit: u64,
+}
+
+fn deployments(network: Network) -> Deployments {
+    match network {
+        Network::Bitcoin => Deployments {
+            bip16_exception: Some("00000000000002dc756eebf4f49723ed8d30cc28a5f108eb94b1ba88ac4f9c22"),
+            bip66: 363_725,
+            bip65: 388_381,
+            csv: 419_328,
+            segwit: 481_824,
+        },
+        Network::Testnet => Deployments {
+            bip16_exception: Some("00000000dd30457c001f4095d208cc1296b0eed002427aa599874af7a432b105"),
+            bip66: 330_776,
+            bip65: 581_885,
+            csv: 770_112,
+            segwit: 834_624,
+        },
+        // Signet and regtest enforce everything from the first block
+        _ => Deployments { bip16_exception: None, bip66: 1, bip65: 1, csv: 1, segwit: 1 },
+    }
+}
+
+/// Verification flags for the block `hash` at `height`. P2SH and witness
+/// rules apply to every block, as in Core, since no historic block but the
+/// exception breaks them.
+pub fn block_flags(network: Network, height: u64, hash: &BlockHash) -> u32 {
+    let deployments = deployments(network);
+    if deployments.bip16_exception.is_some_and(|exception| hash.to_string() == exception) {
+        return VERIFY_NONE;
+    }
+
+    let mut flags = VERIFY_P2SH | VERIFY_WITNESS;
+    for (height_active, flag) in [
+        (deployments.bip66, VERIFY_DERSIG),
+        (deployments.bip65, VERIFY_CHECKLOCKTIMEVERIFY),
+        (deployments.csv, VERIFY_CHECKSEQUENCEVERIFY),
+        (deployments.segwit, VERIFY_NULLDUMMY),
+    ] {
+        if height >= height_active {
+            flags |= flag;
+        }
+    }
+    flags
+}
+
+/// Check every input of `tx` against the output it spends, given in input order
+pub fn verify_transaction(tx: &Transaction, spent_outputs: &[TxOut], flags: u32) -> Result<(), PolicyError> {
+    if spent_outputs.len() != tx.input.len() {
+        return Err(PolicyError::Consensus("bad-txns-inputs-missingorspent"));
+    }
+
+    let serialized = bitcoin::consensus::serialize(tx);
+    for (input, spent) in spent_outputs.iter().enumerate() {
+        bitcoinconsensus::verify_with_flags(spent.script_pubkey.as_bytes(), spent.value.to_sat(), &serialized, input, flags)
+            .map_err(|e| PolicyError::ScriptVerification {
+                input,
+                // The library leaves the error unset when the script itself fails
+                reason: match e {
+                    bitcoinconsensus::Error::ERR_SCRIPT => "script evaluation failed".to_string(),
+                    other => other.to_string(),
+                },
+            })?;
+    }
+    Ok(())
+}
+
+/// Whether spending `spent` is beyond the library: any witness version past 0
+pub fn is_unverifiable(spent: &TxOut) -> bool {
+    spent.script_pubkey.witness_version().is_some_and(|version| version != WitnessVersion::V0)
+}
+
+/// [`verify_transaction`] under [`MEMPOOL_FLAGS`], first refusing spends of
+/// witness versions the library cannot check, as Core refuses versions
+/// reserved for upgrades
+pub fn verify_mempool_transaction(tx: &Transaction, spent_outputs: &[TxOut]) -> Result<(), PolicyError> {
+    if let Some(input) = spent_outputs.iter().position(is_unverifiable) {
+        return Err(PolicyError::UnverifiableWitness { input });
+    }
+    verify_transaction(tx, spent_outputs, MEMPOOL_FLAGS)
+}
+
+/// Decides which connected blocks get their scripts checked
+#[derive(Debug, Clone)]
+pub struct ScriptChecks {
+    network: Network,
+    /// Height and hash of the assume-valid block, until another block connects there
+    assume_valid: Option<(u64, BlockHash)>,
+}
+
+impl ScriptChecks {
+    pub fn new(config: &Config) -> ConfigResult<Self> {
+        let validation = &config.validation;
+        let assume_valid = match (&validation.assume_valid_block, validation.assume_valid_height) {
+            (None, None) => None,
+            (Some(hash), Some(height)) => {
+                let hash = hash.parse().map_err(|e| ConfigError::InvalidValue {
+                    field: "validation.assume_valid_block".to_string(),
+                    value: format!("{}: {}", hash, e),
+                })?;
+                Some((height, hash))
+            }
+            (Some(_), None) => return Err(ConfigError::MissingField("validation.assume_valid_height".to_string())),
+            (None, Some(_)) => return Err(ConfigError::MissingField("validation.assume_valid_block".to_string())),
+        };
+        Ok(Self { network: config.network.to_bitcoin_network(), assume_valid })
+    }
+
+    /// Checks for every block, for when the configuration is unusable
+    pub fn all(network: Network) -> Self {
+        Self { network, assume_valid: None }
+    }
+
+    pub fn assume_valid_height(&self) -> Option<u64> {
+        self.assume_valid.map(|(height, _)| height)
+    }
+
+    /// Note the block on the active chain at `height`; checks resume for
+    /// good once a block other than the assume-valid one is seen at its height
+    pub fn observe(&mut self, height: u64, hash: &BlockHash) {
+        match self.assume_valid {
+            Some((assumed_height, assumed_hash)) if assumed_height == height && assumed_hash != *hash => {
+                warn!("Block {} at height {} is not the assume-valid block {}, verifying all scripts from here on",
+                      hash, height, assumed_hash);
+                self.assume_valid = None;
+            }
+            Some((assumed_height, _)) if assumed_height == height => {
+                info!("Reached the assume-valid block {}, verifying scripts of later blocks", hash);
+            }
+            _ => {}
+        }
+    }
+
+    /// Flags to verify the block with, `None` when its scripts are assumed
+    /// valid. `header_at` gives the hash the best header chain holds at a height.
+    pub fn flags_for(&mut self, height: u64, hash: &BlockHash, header_at: impl Fn(u64) -> Option<BlockHash>) -> Option<u32> {
+        self.observe(height, hash);
+        match self.assume_valid {
+            Some((assumed_height, assumed_hash))
+                if height <= assumed_height
+                    && header_at(assumed_height) == Some(assumed_hash)
+                    && header_at(height) == Some(*hash) => None,
+            _ => Some(block_flags(self.network, height, hash)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::{Amount, OutPoint, PublicKey, ScriptBuf, Sequence, TxIn, Witness};
+
+    /// A P2WPKH coin and a transaction spending it, signed with `key`
+    fn signed_spend(key: u8) -> (Transaction, TxOut) {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[key; 32]).unwrap();
+        let public_key = PublicKey::new(secret_key.public_key(&secp));
+        let spent = TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash().unwrap()),
+        };
+        let mut tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(bitcoin::Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: Amount::from_sat(49_000), script_pubkey: spent.script_pubkey.clone() }],
+        };
+        let sighash = SighashCache::new(&tx)
+            .p2wpkh_signature_hash(0, &spent.script_pubkey, spent.value, EcdsaSighashType::All)
+            .unwrap();
+        let signature = bitcoin::ecdsa::Signature {
+            sig: secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &secret_key),
+            hash_ty: EcdsaSighashType::All,
+        };
+        tx.input[0].witness = Witness::p2wpkh(&signature, &public_key.inner);
+        (tx, spent)
+    }
+
+    #[test]
+    fn test_signatures_are_checked() {
+        let (tx, spent) = signed_spend(7);
+        verify_transaction(&tx, std::slice::from_ref(&spent), MEMPOOL_FLAGS).unwrap();
+
+        // Signed for a different amount, which the witness sighash commits to
+        let mut wrong_amount = spent.clone();
+        wrong_amount.value = Amount::from_sat(60_000);
+        assert!(matches!(
+            verify_transaction(&tx, &[wrong_amount], MEMPOOL_FLAGS),
+            Err(PolicyError::ScriptVerification { input: 0, .. })
+        ));
+
+        // Someone else's signature
+        let (mut forged, _) = signed_spend(8);
+        forged.input[0].witness = signed_spend(9).0.input[0].witness.clone();
+        assert!(verify_transaction(&forged, &[spent], MEMPOOL_FLAGS).is_err());
+
+        assert!(verify_transaction(&tx, &[], MEMPOOL_FLAGS).is_err());
+    }
+
+    #[test]
+    fn test_mempool_refuses_taproot_spends() {
+        let (tx, spent) = signed_spend(7);
+        verify_mempool_transaction(&tx, std::slice::from_ref(&spent)).unwrap();
+
+        // Would pass the library unchecked as an upgradable witness version
+        let secp = Secp256k1::new();
+        let (internal_key, _) = SecretKey::from_slice(&[7; 32]).unwrap().x_only_public_key(&secp);
+        let taproot = TxOut { value: spent.value, script_pubkey: ScriptBuf::new_p2tr(&secp, internal_key, None) };
+        verify_transaction(&tx, std::slice::from_ref(&taproot), MEMPOOL_FLAGS).unwrap();
+        assert!(matches!(
+            verify_mempool_transaction(&tx, &[taproot]),
+            Err(PolicyError::UnverifiableWitness { input: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_block_flags_follow_deployments() {
+        let hash = BlockHash::all_zeros();
+        assert_eq!(block_flags(Network::Regtest, 1, &hash), VERIFY_ALL);
+        assert_eq!(block_flags(Network::Bitcoin, 200_000, &hash), VERIFY_P2SH | VERIFY_WITNESS);
+        assert_eq!(block_flags(Network::Bitcoin, 400_000, &hash) & VERIFY_CHECKSEQUENCEVERIFY, 0);
+        assert_eq!(block_flags(Network::Bitcoin, 481_824, &hash), VERIFY_ALL);
+
+        let exception = "00000000000002dc756eebf4f49723ed8d30cc28a5f108eb94b1ba88ac4f9c22".parse().unwrap();
+        assert_eq!(block_flags(Network::Bitcoin, 170_060, &exception), VERIFY_NONE);
+    }
+
+    #[test]
+    fn test_assume_valid() {
+        let mut config = Config::test_config();
+        let assumed = BlockHash::from_byte_array([5; 32]);
+        config.validation.assume_valid_block = Some(assumed.to_string());
+        assert!(ScriptChecks::new(&config).is_err());
+        config.validation.assume_valid_height = Some(10);
+
+        let ancestor = BlockHash::from_byte_array([3; 32]);
+        let other = BlockHash::from_byte_array([6; 32]);
+        let header_index = |height: u64| match height {
+            3 => Some(ancestor),
+            10 => Some(assumed),
+            _ => None,
+        };
+        let mut checks = ScriptChecks::new(&config).unwrap();
+        assert_eq!(checks.flags_for(3, &ancestor, header_index), None);
+        assert_eq!(checks.flags_for(10, &assumed, header_index), None);
+        assert!(checks.flags_for(11, &other, header_index).is_some());
+
+        // Below the assume-valid height but not on its header chain
+        assert!(checks.flags_for(3, &other, header_index).is_some());
+        assert!(checks.flags_for(5, &other, header_index).is_some());
+        // Nothing is assumed before the assume-valid block is on the best header chain
+        assert!(checks.flags_for(3, &ancestor, |height: u64| (height == 3).then_some(ancestor)).is_some());
+
+        // A different block at the assume-valid height turns checks back on
+        let mut checks = ScriptChecks::new(&config).unwrap();
+        assert!(checks.flags_for(10, &other, header_index).is_some());
+        assert!(checks.flags_for(3, &ancestor, header_index).is_some());
+        assert_eq!(checks.assume_valid_height(), None);
+    }
+}
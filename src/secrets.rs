@@ -0,0 +1,294 @@
+//! Credentials kept out of the config file
+//!
+//! Any `password`, `token` or `secret` key in the config may hold a table
+//! naming where the value lives instead of the value itself:
+//!
+//! - `{ file = "/run/secrets/rpc_password" }` reads the file, trailing newline dropped
+//! - `{ env = "BITKNOTS_RPC_PASSWORD" }` reads an environment variable
+//! - `{ vault = "secret/data/bitknotsrs#rpc_password" }` reads a key from a
+//!   Vault KV v2 secret, using `VAULT_ADDR` and `VAULT_TOKEN`
+//! - `{ k8s_secret = "namespace/name/key" }` reads a key from a Kubernetes
+//!   Secret with the pod's service account
+//!
+//! References are resolved once, when the config is loaded, so the rest of
+//! the node only ever sees plain values. [`redact`] masks the same keys
+//! wherever the config is printed.
+
+#[cfg(feature = "http-client")]
+use std::path::Path;
+
+#[cfg(feature = "http-client")]
+use bitcoin::base64::engine::general_purpose::STANDARD;
+#[cfg(feature = "http-client")]
+use bitcoin::base64::Engine;
+use serde_json::Value;
+
+use crate::error::{ConfigError, ConfigResult};
+
+/// Config keys whose values are credentials
+pub const SECRET_KEYS: &[&str] = &["password", "token", "secret"];
+
+/// What a redacted value is printed as
+pub const REDACTED: &str = "<redacted>";
+
+#[cfg(feature = "http-client")]
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Where a credential is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretRef {
+    File(String),
+    Env(String),
+    /// KV v2 path and key, e.g. `secret/data/bitknotsrs` and `rpc_password`
+    Vault { path: String, key: String },
+    Kubernetes { namespace: String, name: String, key: String },
+}
+
+impl SecretRef {
+    /// The reference a config table holds, if it is one
+    fn parse(table: &toml::map::Map<String, toml::Value>, field: &str) -> ConfigResult<Option<Self>> {
+        let mut entries = table.iter();
+        let (Some((kind, toml::Value::String(target))), None) = (entries.next(), entries.next()) else {
+            return Ok(None);
+        };
+        let invalid = |value: &str| ConfigError::InvalidValue { field: field.to_string(), value: value.to_string() };
+        let reference = match kind.as_str() {
+            "file" => SecretRef::File(target.clone()),
+            "env" => SecretRef::Env(target.clone()),
+            "vault" => {
+                let (path, key) = target.rsplit_once('#')
+                    .ok_or_else(|| invalid("vault reference must be <path>#<key>"))?;
+                SecretRef::Vault { path: path.trim_matches('/').to_string(), key: key.to_string() }
+            }
+            "k8s_secret" => {
+                let parts: Vec<&str> = target.split('/').collect();
+                let [namespace, name, key] = parts[..] else {
+                    return Err(invalid("k8s_secret reference must be <namespace>/<name>/<key>"));
+                };
+                SecretRef::Kubernetes { namespace: namespace.to_string(), name: name.to_string(), key: key.to_string() }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(reference))
+    }
+
+    fn is_remote(&self) -> bool {
+        matches!(self, SecretRef::Vault { .. } | SecretRef::Kubernetes { .. })
+    }
+
+    /// Read files and environment variables; remote references are left to [`Self::fetch`]
+    fn read_local(&self) -> Result<String, String> {
+        match self {
+            SecretRef::File(path) => std::fs::read_to_string(path)
+                .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|e| format!("cannot read {}: {}", path, e)),
+            SecretRef::Env(name) => std::env::var(name).map_err(|e| format!("{}: {}", name, e)),
+            _ => Err("remote references are fetched, not read".to_string()),
+        }
+    }
+
+    #[cfg(feature = "http-client")]
+    async fn fetch(&self, client: &reqwest::Client) -> Result<String, String> {
+        match self {
+            SecretRef::Vault { path, key } => {
+                let addr = std::env::var("VAULT_ADDR").map_err(|_| "VAULT_ADDR is not set".to_string())?;
+                let token = std::env::var("VAULT_TOKEN").map_err(|_| "VAULT_TOKEN is not set".to_string())?;
+                let url = format!("{}/v1/{}", addr.trim_end_matches('/'), path);
+                let response: Value = get_json(client.get(&url).header("X-Vault-Token", token)).await?;
+                response.pointer("/data/data").and_then(|data| data.get(key)).and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("{} has no key {}", path, key))
+            }
+            SecretRef::Kubernetes { namespace, name, key } => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST")
+                    .map_err(|_| "not running in a Kubernetes pod".to_string())?;
+                let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+                let token = std::fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("token"))
+                    .map_err(|e| format!("cannot read the service account token: {}", e))?;
+                let url = format!("https://{}:{}/api/v1/namespaces/{}/secrets/{}", host, port, namespace, name);
+                let response: Value = get_json(client.get(&url).bearer_auth(token.trim())).await?;
+                let encoded = response.pointer("/data").and_then(|data| data.get(key)).and_then(Value::as_str)
+                    .ok_or_else(|| format!("secret {}/{} has no key {}", namespace, name, key))?;
+                let bytes = STANDARD.decode(encoded).map_err(|e| format!("secret {}/{}: {}", namespace, name, e))?;
+                String::from_utf8(bytes).map_err(|_| format!("secret {}/{} key {} is not UTF-8", namespace, name, key))
+            }
+            local => local.read_local(),
+        }
+    }
+}
+
+#[cfg(feature = "http-client")]
+async fn get_json(request: reqwest::RequestBuilder) -> Result<Value, String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("request failed with status {}", status));
+    }
+    response.json().await.map_err(|e| e.to_string())
+}
+
+/// Every secret reference in `tree`, with its dotted key and a handle to its slot
+fn collect<'a>(tree: &'a mut toml::Value, key: String, found: &mut Vec<(String, SecretRef, &'a mut toml::Value)>) -> ConfigResult<()> {
+    match tree {
+        toml::Value::Table(table) => {
+            for (name, value) in table.iter_mut() {
+                let field = if key.is_empty() { name.clone() } else { format!("{}.{}", key, name) };
+                if SECRET_KEYS.contains(&name.as_str()) {
+                    if let toml::Value::Table(reference) = &*value {
+                        if let Some(reference) = SecretRef::parse(reference, &field)? {
+                            found.push((field, reference, value));
+                            continue;
+                        }
+                    }
+                }
+                collect(value, field, found)?;
+            }
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                collect(item, format!("{}[{}]", key, index), found)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace every secret reference in a parsed config file with the value it names
+pub fn resolve(tree: &mut toml::Value) -> ConfigResult<()> {
+    let mut found = Vec::new();
+    collect(tree, String::new(), &mut found)?;
+    if found.is_empty() {
+        return Ok(());
+    }
+
+    let remote = found.iter().any(|(_, reference, _)| reference.is_remote());
+    let values: Vec<String> = if remote {
+        fetch_all(&found)?
+    } else {
+        found.iter()
+            .map(|(field, reference, _)| reference.read_local().map_err(|e| failed(field, e)))
+            .collect::<ConfigResult<_>>()?
+    };
+
+    for ((_, _, slot), value) in found.into_iter().zip(values) {
+        *slot = toml::Value::String(value);
+    }
+    Ok(())
+}
+
+fn failed(field: &str, e: String) -> ConfigError {
+    ConfigError::InvalidValue { field: field.to_string(), value: e }
+}
+
+/// Every reference's value, fetching the Vault and Kubernetes ones
+#[cfg(feature = "http-client")]
+fn fetch_all(found: &[(String, SecretRef, &mut toml::Value)]) -> ConfigResult<Vec<String>> {
+    // Loading happens before the node's runtime starts, so fetch on one of our own
+    let mut client = reqwest::Client::builder().timeout(std::time::Duration::from_secs(10));
+    if let Ok(ca) = std::fs::read(Path::new(SERVICE_ACCOUNT_DIR).join("ca.crt")) {
+        if let Ok(certificate) = reqwest::Certificate::from_pem(&ca) {
+            client = client.add_root_certificate(certificate);
+        }
+    }
+    let client = client.build().map_err(|e| failed("secrets", e.to_string()))?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| failed("secrets", e.to_string()))?;
+    runtime.block_on(async {
+        let mut values = Vec::new();
+        for (field, reference, _) in found {
+            values.push(reference.fetch(&client).await.map_err(|e| failed(field, e))?);
+        }
+        Ok::<_, ConfigError>(values)
+    })
+}
+
+#[cfg(not(feature = "http-client"))]
+fn fetch_all(found: &[(String, SecretRef, &mut toml::Value)]) -> ConfigResult<Vec<String>> {
+    let (field, _, _) = found.iter()
+        .find(|(_, reference, _)| reference.is_remote())
+        .expect("only called with a remote reference");
+    Err(failed(field, "Vault and Kubernetes secrets need the http-client feature".to_string()))
+}
+
+/// Mask every credential in a serialized config
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&name.as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolves_file_and_env_references() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let password = dir.path().join("rpc_password");
+        std::fs::write(&password, "hunter2\n").unwrap();
+        std::env::set_var("BITKNOTS_TEST_WEBHOOK_SECRET", "s3cret");
+
+        let mut tree: toml::Value = toml::from_str(&format!(r#"
+            [rpc]
+            password = {{ file = "{}" }}
+
+            [events.webhook]
+            endpoints = ["http://a", {{ url = "http://b", secret = {{ env = "BITKNOTS_TEST_WEBHOOK_SECRET" }} }}]
+
+            [logging]
+            file = "node.log"
+        "#, password.display())).unwrap();
+        resolve(&mut tree).unwrap();
+
+        assert_eq!(tree["rpc"]["password"].as_str(), Some("hunter2"));
+        assert_eq!(tree["events"]["webhook"]["endpoints"][1]["secret"].as_str(), Some("s3cret"));
+        // Only credential keys are references
+        assert_eq!(tree["logging"]["file"].as_str(), Some("node.log"));
+
+        let mut missing: toml::Value = toml::from_str("[rpc]\npassword = { env = \"BITKNOTS_TEST_UNSET\" }").unwrap();
+        let error = resolve(&mut missing).unwrap_err().to_string();
+        assert!(error.contains("rpc.password"));
+    }
+
+    #[test]
+    fn test_remote_references_are_parsed() {
+        let table = |toml: &str| toml::from_str::<toml::map::Map<String, toml::Value>>(toml).unwrap();
+        assert_eq!(
+            SecretRef::parse(&table("vault = \"/secret/data/node#rpc_password\""), "rpc.password").unwrap(),
+            Some(SecretRef::Vault { path: "secret/data/node".to_string(), key: "rpc_password".to_string() }),
+        );
+        assert_eq!(
+            SecretRef::parse(&table("k8s_secret = \"bitcoin/node/token\""), "storage.read_api.token").unwrap(),
+            Some(SecretRef::Kubernetes { namespace: "bitcoin".to_string(), name: "node".to_string(), key: "token".to_string() }),
+        );
+        assert!(SecretRef::parse(&table("vault = \"secret/data/node\""), "rpc.password").is_err());
+        assert_eq!(SecretRef::parse(&table("url = \"http://b\""), "secret").unwrap(), None);
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut config = json!({
+            "rpc": { "user": "alice", "password": "hunter2" },
+            "storage": { "read_api": { "token": null } },
+            "events": { "webhook": { "endpoints": ["http://a", { "url": "http://b", "secret": "s3cret" }] } },
+        });
+        redact(&mut config);
+        assert_eq!(config["rpc"], json!({ "user": "alice", "password": REDACTED }));
+        assert_eq!(config["storage"]["read_api"]["token"], Value::Null);
+        assert_eq!(config["events"]["webhook"]["endpoints"][1]["secret"], json!(REDACTED));
+    }
+}
@@ -0,0 +1,106 @@
+//! Bootstrap peer discovery: DNS seeds, their cached answers, and the fixed
+//! seed lists used when DNS is unavailable.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Network;
+
+/// How long one DNS seed gets to answer before it is skipped
+pub const DNS_SEED_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAINNET_DNS_SEEDS: &[&str] = &[
+    "seed.bitcoin.sipa.be",
+    "dnsseed.bluematt.me",
+    "dnsseed.bitcoin.dashjr.org",
+    "seed.bitcoinstats.com",
+    "seed.bitcoin.jonasschnelli.ch",
+    "seed.btc.petertodd.org",
+    "seed.bitcoin.sprovoost.nl",
+    "dnsseed.emzy.de",
+];
+
+const TESTNET_DNS_SEEDS: &[&str] = &[
+    "testnet-seed.bitcoin.jonasschnelli.ch",
+    "seed.tbtc.petertodd.org",
+    "seed.testnet.bitcoin.sprovoost.nl",
+    "testnet-seed.bluematt.me",
+];
+
+const MAINNET_FIXED_SEEDS: &str = include_str!("seeds/nodes_main.txt");
+const TESTNET_FIXED_SEEDS: &str = include_str!("seeds/nodes_test.txt");
+
+pub fn dns_seeds(network: &Network) -> &'static [&'static str] {
+    match network {
+        Network::Mainnet => MAINNET_DNS_SEEDS,
+        Network::Testnet => TESTNET_DNS_SEEDS,
+        Network::Regtest => &[],
+    }
+}
+
+/// Embedded `host:port` peers to fall back on when no DNS seed answers
+pub fn fixed_seeds(network: &Network) -> Vec<String> {
+    match network {
+        Network::Mainnet => parse_seed_list(MAINNET_FIXED_SEEDS),
+        Network::Testnet => parse_seed_list(TESTNET_FIXED_SEEDS),
+        Network::Regtest => Vec::new(),
+    }
+}
+
+/// One address per line; blank lines and `#` comments are skipped
+fn parse_seed_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A DNS seed's last answer, kept in `CF_PEERS` so restarts and DNS outages
+/// can reuse it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsSeedRecord {
+    pub addresses: Vec<String>,
+    pub resolved_at: DateTime<Utc>,
+    pub ttl_secs: u64,
+}
+
+impl DnsSeedRecord {
+    pub fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        now < self.resolved_at + chrono::Duration::seconds(self.ttl_secs as i64)
+    }
+}
+
+/// Resolve a seed hostname to `ip:port` peer addresses
+pub async fn resolve_seed(seed: &str, port: u16) -> std::io::Result<Vec<String>> {
+    let lookup = tokio::net::lookup_host((seed, port));
+    let addresses = tokio::time::timeout(DNS_SEED_TIMEOUT, lookup)
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "DNS seed timed out"))??;
+    Ok(addresses.map(|address| address.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_lists() {
+        assert!(!dns_seeds(&Network::Mainnet).is_empty());
+        assert!(dns_seeds(&Network::Regtest).is_empty());
+        assert!(fixed_seeds(&Network::Regtest).is_empty());
+
+        let parsed = parse_seed_list("# header\n203.0.113.5:8333\n\n[2001:db8::1]:8333  # comment\n");
+        assert_eq!(parsed, vec!["203.0.113.5:8333".to_string(), "[2001:db8::1]:8333".to_string()]);
+    }
+
+    #[test]
+    fn test_record_freshness() {
+        let resolved_at = Utc::now();
+        let record = DnsSeedRecord { addresses: vec![], resolved_at, ttl_secs: 60 };
+        assert!(record.is_fresh(resolved_at + chrono::Duration::seconds(59)));
+        assert!(!record.is_fresh(resolved_at + chrono::Duration::seconds(60)));
+    }
+}
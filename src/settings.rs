@@ -0,0 +1,224 @@
+//! Persistent settings overlay
+//!
+//! `setconfig` writes overrides for a fixed set of config keys to
+//! `<datadir>/settings.json`, and the node applies them on top of its config
+//! file at every startup, so operational adjustments survive restarts without
+//! editing files on disk. Mempool policy keys also take effect immediately;
+//! the rest wait for the next start.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::error::{ConfigError, ConfigResult};
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// A config key that may be overridden, in its dotted TOML form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setting {
+    pub key: &'static str,
+    /// Pushed to the running mempool instead of waiting for a restart
+    pub live: bool,
+}
+
+const fn live(key: &'static str) -> Setting {
+    Setting { key, live: true }
+}
+
+const fn on_restart(key: &'static str) -> Setting {
+    Setting { key, live: false }
+}
+
+pub static SETTABLE: &[Setting] = &[
+    live("mempool.policy_profile"),
+    live("mempool.datacarrier"),
+    live("mempool.max_datacarrier_bytes"),
+    live("mempool.dust_relay_fee"),
+    live("mempool.permit_bare_multisig"),
+    live("mempool.rbf"),
+    live("mempool.max_standard_tx_weight"),
+    live("mempool.expiry_hours"),
+    live("mempool.max_mempool"),
+    live("mempool.limit_ancestor_count"),
+    live("mempool.limit_ancestor_size"),
+    live("mempool.limit_descendant_count"),
+    live("mempool.limit_descendant_size"),
+    live("mempool.script_filter.mode"),
+    live("mempool.script_filter.addresses"),
+    live("mempool.script_filter.scripts"),
+    live("mempool.script_filter.xpubs"),
+    on_restart("network_config.max_peers"),
+    on_restart("network_config.custom_peers"),
+    on_restart("network_config.enable_peer_exchange"),
+    on_restart("network_config.protocol_strictness"),
+    on_restart("network_config.peer_rate_limits.misbehavior_per_violation"),
+    on_restart("network_config.peer_rate_limits.disconnect_threshold"),
+    on_restart("logging.level"),
+];
+
+pub fn lookup(key: &str) -> ConfigResult<Setting> {
+    SETTABLE.iter().copied().find(|setting| setting.key == key).ok_or_else(|| ConfigError::InvalidValue {
+        field: key.to_string(),
+        value: "not a setting that can be changed at runtime".to_string(),
+    })
+}
+
+/// The JSON pointer a dotted key names in the serialized config
+fn pointer(key: &str) -> String {
+    format!("/{}", key.replace('.', "/"))
+}
+
+/// `base` with `overrides` applied, validated as a whole
+fn overlay(base: &Config, overrides: &BTreeMap<String, Value>) -> ConfigResult<Config> {
+    let invalid = |field: &str, e: serde_json::Error| ConfigError::InvalidValue {
+        field: field.to_string(),
+        value: e.to_string(),
+    };
+    let mut tree = serde_json::to_value(base).map_err(|e| invalid(SETTINGS_FILE, e))?;
+    for (key, value) in overrides {
+        lookup(key)?;
+        let slot = tree.pointer_mut(&pointer(key)).ok_or_else(|| ConfigError::InvalidValue {
+            field: key.clone(),
+            value: "missing from the config".to_string(),
+        })?;
+        *slot = value.clone();
+        // Checked one key at a time so a bad value names its key
+        serde_json::from_value::<Config>(tree.clone()).map_err(|e| invalid(key, e))?;
+    }
+    let config: Config = serde_json::from_value(tree).map_err(|e| invalid(SETTINGS_FILE, e))?;
+    config.validate()?;
+    Ok(config)
+}
+
+struct State {
+    /// The config file and command line, before any overrides
+    base: Config,
+    overrides: BTreeMap<String, Value>,
+    current: Config,
+}
+
+/// Shared handle to the overlay and the config it produces
+#[derive(Clone)]
+pub struct Settings {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+impl Settings {
+    /// Read `<datadir>/settings.json`, if there is one, over `base`
+    pub fn load(base: Config) -> ConfigResult<Self> {
+        let path = base.datadir.join(SETTINGS_FILE);
+        let overrides: BTreeMap<String, Value> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| ConfigError::InvalidValue {
+                field: path.display().to_string(),
+                value: e.to_string(),
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(ConfigError::InvalidValue {
+                field: path.display().to_string(),
+                value: format!("Cannot read: {}", e),
+            }),
+        };
+        let current = overlay(&base, &overrides)?;
+        Ok(Self { path, state: Arc::new(Mutex::new(State { base, overrides, current })) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The config with every override applied
+    pub fn config(&self) -> Config {
+        self.state.lock().expect("settings lock poisoned").current.clone()
+    }
+
+    pub fn overrides(&self) -> BTreeMap<String, Value> {
+        self.state.lock().expect("settings lock poisoned").overrides.clone()
+    }
+
+    /// The value `key` has now and whether settings.json overrides it
+    pub fn get(&self, key: &str) -> ConfigResult<(Value, bool)> {
+        lookup(key)?;
+        let state = self.state.lock().expect("settings lock poisoned");
+        let tree = serde_json::to_value(&state.current).map_err(|e| ConfigError::InvalidValue {
+            field: key.to_string(),
+            value: e.to_string(),
+        })?;
+        let value = tree.pointer(&pointer(key)).cloned().unwrap_or(Value::Null);
+        Ok((value, state.overrides.contains_key(key)))
+    }
+
+    /// Override `key`, or drop its override with `None`, and persist the
+    /// overlay. Nothing changes if the result does not validate.
+    pub fn set(&self, key: &str, value: Option<Value>) -> ConfigResult<Config> {
+        lookup(key)?;
+        let mut state = self.state.lock().expect("settings lock poisoned");
+        let mut overrides = state.overrides.clone();
+        match value {
+            Some(value) => overrides.insert(key.to_string(), value),
+            None => overrides.remove(key),
+        };
+        let current = overlay(&state.base, &overrides)?;
+        self.save(&overrides)?;
+        state.overrides = overrides;
+        state.current = current.clone();
+        Ok(current)
+    }
+
+    /// Written aside and renamed so a crash never leaves half a file
+    fn save(&self, overrides: &BTreeMap<String, Value>) -> ConfigResult<()> {
+        let write_failed = |e: std::io::Error| ConfigError::InvalidValue {
+            field: self.path.display().to_string(),
+            value: format!("Cannot write: {}", e),
+        };
+        let json = serde_json::to_string_pretty(overrides).expect("JSON values always serialize");
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, json + "\n").map_err(write_failed)?;
+        std::fs::rename(&temp, &self.path).map_err(write_failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn base(datadir: &Path) -> Config {
+        let mut config = Config::default_regtest();
+        config.datadir = datadir.to_path_buf();
+        config
+    }
+
+    #[test]
+    fn test_overrides_persist_and_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings::load(base(dir.path())).unwrap();
+        assert_eq!(settings.get("mempool.rbf").unwrap(), (Value::Null, false));
+
+        let config = settings.set("mempool.rbf", Some(json!("opt_in"))).unwrap();
+        assert_eq!(config.mempool.rbf, Some(crate::config::RbfPolicy::OptIn));
+        settings.set("network_config.max_peers", Some(json!(12))).unwrap();
+
+        // Bad keys and values leave the overlay as it was
+        assert!(settings.set("rpc.password", Some(json!("x"))).is_err());
+        assert!(settings.set("mempool.rbf", Some(json!("sometimes"))).is_err());
+        assert!(settings.set("mempool.script_filter.addresses", Some(json!(["not-an-address"]))).is_err());
+        assert_eq!(settings.get("mempool.rbf").unwrap(), (json!("opt_in"), true));
+
+        // A restart applies the overlay on top of the unchanged config file
+        let restarted = Settings::load(base(dir.path())).unwrap();
+        assert_eq!(restarted.config().network_config.max_peers, 12);
+        assert_eq!(restarted.overrides().len(), 2);
+
+        restarted.set("network_config.max_peers", None).unwrap();
+        assert_eq!(restarted.get("network_config.max_peers").unwrap(), (json!(base(dir.path()).network_config.max_peers), false));
+        assert_eq!(Settings::load(base(dir.path())).unwrap().overrides().len(), 1);
+
+        std::fs::write(dir.path().join(SETTINGS_FILE), r#"{"storage.cache_size": 1}"#).unwrap();
+        assert!(Settings::load(base(dir.path())).is_err());
+    }
+}
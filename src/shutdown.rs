@@ -0,0 +1,237 @@
+//! Ordered node shutdown
+//!
+//! SIGINT and SIGTERM start it; the node handles both itself rather than
+//! leaving them to the API server, publishes `NodeStopping` and then runs
+//! the stages below. A second signal while they run exits at once.
+//!
+//! Components stop front to back: the API and RPC servers first so no new
+//! work arrives, then the miner and the network, the mempool and chain they
+//! feed, storage once nothing writes to it any more, and finally the event
+//! deliveries everything above may have queued. Each stage gets its own drain
+//! timeout; a stage that overruns it is aborted and shutdown moves on, so one
+//! slow webhook or a WebSocket client that never closes cannot hang the node.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use tracing::{info, warn};
+
+use crate::actors::Shutdown;
+use crate::config::ShutdownConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Api,
+    Rpc,
+    Miner,
+    Network,
+    Mempool,
+    Chain,
+    Storage,
+    Events,
+}
+
+pub const SHUTDOWN_ORDER: [Component; 8] = [
+    Component::Api,
+    Component::Rpc,
+    Component::Miner,
+    Component::Network,
+    Component::Mempool,
+    Component::Chain,
+    Component::Storage,
+    Component::Events,
+];
+
+impl Component {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Api => "api",
+            Component::Rpc => "rpc",
+            Component::Miner => "miner",
+            Component::Network => "network",
+            Component::Mempool => "mempool",
+            Component::Chain => "chain",
+            Component::Storage => "storage",
+            Component::Events => "events",
+        }
+    }
+
+    pub fn drain_timeout(&self, config: &ShutdownConfig) -> Duration {
+        let secs = match self {
+            Component::Api => config.api_secs,
+            Component::Rpc => config.rpc_secs,
+            Component::Miner => config.miner_secs,
+            Component::Network => config.network_secs,
+            Component::Mempool => config.mempool_secs,
+            Component::Chain => config.chain_secs,
+            Component::Storage => config.storage_secs,
+            Component::Events => config.events_secs,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageOutcome {
+    Drained(Duration),
+    /// Ran past its drain timeout and was abandoned
+    Aborted,
+    /// Not running on this node
+    Skipped,
+}
+
+type Drain = Pin<Box<dyn Future<Output = ()>>>;
+
+struct Stage {
+    drain: Option<Drain>,
+    abort: Option<Box<dyn FnOnce()>>,
+}
+
+pub struct ShutdownPlan {
+    config: ShutdownConfig,
+    stages: Vec<(Component, Stage)>,
+}
+
+impl ShutdownPlan {
+    pub fn new(config: &ShutdownConfig) -> Self {
+        Self {
+            config: config.clone(),
+            stages: SHUTDOWN_ORDER.iter().map(|&component| (component, Stage { drain: None, abort: None })).collect(),
+        }
+    }
+
+    /// Future that resolves once `component` has stopped cleanly
+    pub fn add(&mut self, component: Component, drain: impl Future<Output = ()> + 'static) -> &mut Self {
+        self.stage(component).drain = Some(Box::pin(drain));
+        self
+    }
+
+    /// Forced stop for `component` when its drain times out
+    pub fn on_abort(&mut self, component: Component, abort: impl FnOnce() + 'static) -> &mut Self {
+        self.stage(component).abort = Some(Box::new(abort));
+        self
+    }
+
+    fn stage(&mut self, component: Component) -> &mut Stage {
+        let (_, stage) = self.stages.iter_mut().find(|(c, _)| *c == component).expect("every component has a stage");
+        stage
+    }
+
+    /// Stop every component in order, returning how each one went
+    pub async fn run(self) -> Vec<(Component, StageOutcome)> {
+        let mut outcomes = Vec::with_capacity(self.stages.len());
+        for (component, stage) in self.stages {
+            let Some(drain) = stage.drain else {
+                outcomes.push((component, StageOutcome::Skipped));
+                continue;
+            };
+
+            let timeout = component.drain_timeout(&self.config);
+            let started = Instant::now();
+            let outcome = match tokio::time::timeout(timeout, drain).await {
+                Ok(()) => {
+                    info!("Stopped {} in {:?}", component.as_str(), started.elapsed());
+                    StageOutcome::Drained(started.elapsed())
+                }
+                Err(_) => {
+                    warn!("{} did not stop within {:?}, aborting it", component.as_str(), timeout);
+                    if let Some(abort) = stage.abort {
+                        abort();
+                    }
+                    StageOutcome::Aborted
+                }
+            };
+            outcomes.push((component, outcome));
+        }
+        outcomes
+    }
+}
+
+/// Resolves with the name of the first SIGINT or SIGTERM the process receives
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                return tokio::select! {
+                    _ = tokio::signal::ctrl_c() => "SIGINT",
+                    _ = terminate.recv() => "SIGTERM",
+                };
+            }
+            Err(e) => warn!("Cannot listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Cannot listen for SIGINT: {}", e);
+        return std::future::pending().await;
+    }
+    "SIGINT"
+}
+
+/// Ask an actor to shut down; resolves once it has worked through the
+/// messages queued ahead of the request
+pub async fn stop_actor<A>(addr: Addr<A>)
+where
+    A: Actor<Context = Context<A>> + Handler<Shutdown>,
+{
+    // A mailbox error means the actor is already gone
+    let _ = addr.send(Shutdown).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    fn quick_config() -> ShutdownConfig {
+        ShutdownConfig {
+            api_secs: 1,
+            rpc_secs: 1,
+            miner_secs: 1,
+            network_secs: 1,
+            mempool_secs: 1,
+            chain_secs: 1,
+            storage_secs: 1,
+            events_secs: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stages_run_in_order() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let mut plan = ShutdownPlan::new(&quick_config());
+        // Added out of order on purpose
+        for component in [Component::Storage, Component::Api, Component::Chain, Component::Network] {
+            let order = order.clone();
+            plan.add(component, async move { order.borrow_mut().push(component) });
+        }
+
+        let outcomes = plan.run().await;
+        assert_eq!(*order.borrow(), vec![Component::Api, Component::Network, Component::Chain, Component::Storage]);
+        assert_eq!(outcomes.iter().map(|(c, _)| *c).collect::<Vec<_>>(), SHUTDOWN_ORDER);
+        assert_eq!(outcomes[1].1, StageOutcome::Skipped);
+        assert!(matches!(outcomes[0].1, StageOutcome::Drained(_)));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stage_is_aborted() {
+        let aborted = Rc::new(Cell::new(false));
+        let storage_stopped = Rc::new(Cell::new(false));
+        let mut plan = ShutdownPlan::new(&quick_config());
+
+        let flag = aborted.clone();
+        plan.add(Component::Events, std::future::pending())
+            .on_abort(Component::Events, move || flag.set(true));
+        let flag = storage_stopped.clone();
+        plan.add(Component::Storage, async move { flag.set(true) });
+
+        let outcomes = plan.run().await;
+        assert!(aborted.get());
+        assert!(storage_stopped.get());
+        assert_eq!(outcomes.last(), Some(&(Component::Events, StageOutcome::Aborted)));
+    }
+}
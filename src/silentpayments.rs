@@ -0,0 +1,353 @@
+//! Silent payments (BIP 352) receiving
+//!
+//! A silent payment address carries a scan key and a spend key. Every payment
+//! lands on a fresh taproot output derived from the sender's input keys, so
+//! receiving never reuses an address. Finding those outputs needs one tweak
+//! per transaction, `input_hash·A`, which the optional block index stores so
+//! scans and light clients never have to look up prevouts again.
+
+use std::fmt;
+
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::primitives::encode::Encoder;
+use bitcoin::bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+use bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{Parity, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::script::Instruction;
+use bitcoin::{Network, Transaction, TxIn, TxOut, Txid, WitnessVersion};
+
+use crate::error::{SilentPaymentError, SilentPaymentResult};
+
+/// Taproot internal key with no known discrete log, which marks a script-path-only output
+const NUMS_H: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Serialized size of one index record: txid followed by the compressed tweak point
+const TWEAK_RECORD_LEN: usize = 32 + 33;
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for part in data {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn address_hrp(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "sp",
+        _ => "tsp",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentAddress {
+    pub network: Network,
+    pub scan: PublicKey,
+    pub spend: PublicKey,
+}
+
+impl SilentPaymentAddress {
+    pub fn new(network: Network, scan: PublicKey, spend: PublicKey) -> Self {
+        Self { network, scan, spend }
+    }
+
+    /// Parse an address, which must be for `network`
+    pub fn parse(address: &str, network: Network) -> SilentPaymentResult<Self> {
+        let checked = CheckedHrpstring::new::<Bech32m>(address)
+            .map_err(|e| SilentPaymentError::Address(e.to_string()))?;
+        if checked.hrp().to_lowercase() != address_hrp(network) {
+            return Err(SilentPaymentError::WrongNetwork(network.to_string()));
+        }
+
+        // The version is a single character ahead of the key payload, so convert by hand
+        let lower = address.to_ascii_lowercase();
+        let data = &lower[lower.rfind('1').expect("checked separator") + 1..lower.len() - 6];
+        let mut fes = data.chars().map(|c| Fe32::from_char(c).expect("checked characters"));
+        let version = fes.next().ok_or_else(|| SilentPaymentError::Address("missing version".to_string()))?;
+        if version != Fe32::Q {
+            return Err(SilentPaymentError::Address(format!("unsupported version {}", version.to_u8())));
+        }
+        let payload: Vec<u8> = fes.fes_to_bytes().collect();
+        if payload.len() != 66 {
+            return Err(SilentPaymentError::Address(format!("expected 66 key bytes, found {}", payload.len())));
+        }
+
+        let key = |bytes: &[u8]| PublicKey::from_slice(bytes).map_err(|e| SilentPaymentError::Key(e.to_string()));
+        Ok(Self::new(network, key(&payload[..33])?, key(&payload[33..])?))
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = Hrp::parse(address_hrp(self.network)).expect("static hrp");
+        let mut payload = self.scan.serialize().to_vec();
+        payload.extend(self.spend.serialize());
+        let chars = Encoder::<_, Bech32m>::new(payload.into_iter().bytes_to_fes(), &hrp)
+            .with_witness_version(Fe32::Q)
+            .chars();
+        for c in chars {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a scanner needs: the scan secret and the public spend key, never the spend secret
+#[derive(Debug, Clone, Copy)]
+pub struct ScanKeys {
+    pub scan_secret: SecretKey,
+    pub spend: PublicKey,
+}
+
+impl ScanKeys {
+    /// Scan keys for `address`, checking the scan secret belongs to it
+    pub fn for_address(scan_secret: SecretKey, address: &SilentPaymentAddress) -> SilentPaymentResult<Self> {
+        if scan_secret.public_key(&Secp256k1::signing_only()) != address.scan {
+            return Err(SilentPaymentError::Key("scan key does not belong to the address".to_string()));
+        }
+        Ok(Self { scan_secret, spend: address.spend })
+    }
+}
+
+/// Public key an input contributes to the shared secret, if its spend type is eligible
+fn input_public_key(input: &TxIn, prevout: &TxOut) -> Option<PublicKey> {
+    let script = &prevout.script_pubkey;
+    let witness: Vec<&[u8]> = input.witness.iter().collect();
+
+    if script.is_p2tr() {
+        let mut stack = witness.as_slice();
+        if stack.len() > 1 && stack.last().is_some_and(|item| item.first() == Some(&0x50)) {
+            stack = &stack[..stack.len() - 1];
+        }
+        // Script path spends through the NUMS point have no usable key
+        if stack.len() > 1 && stack.last().is_some_and(|control| control.get(1..33) == Some(&NUMS_H[..])) {
+            return None;
+        }
+        let key = XOnlyPublicKey::from_slice(&script.as_bytes()[2..34]).ok()?;
+        return Some(key.public_key(Parity::Even));
+    }
+
+    if script.is_p2wpkh() {
+        return compressed_key(witness.get(1)?);
+    }
+
+    if script.is_p2sh() {
+        let redeem: Vec<_> = input.script_sig.instructions().collect();
+        return match redeem.as_slice() {
+            [Ok(Instruction::PushBytes(push))] if push.len() == 22 && push.as_bytes()[..2] == [0x00, 0x14] => {
+                compressed_key(witness.get(1)?)
+            }
+            _ => None,
+        };
+    }
+
+    if script.is_p2pkh() {
+        let pubkey_hash = &script.as_bytes()[3..23];
+        let pushes: Vec<_> = input.script_sig.instructions().filter_map(Result::ok).collect();
+        return pushes.iter().rev().find_map(|instruction| match instruction {
+            Instruction::PushBytes(push)
+                if push.len() == 33 && hash160::Hash::hash(push.as_bytes()).as_byte_array()[..] == pubkey_hash[..] =>
+            {
+                compressed_key(push.as_bytes())
+            }
+            _ => None,
+        });
+    }
+
+    None
+}
+
+fn compressed_key(bytes: &[u8]) -> Option<PublicKey> {
+    if bytes.len() != 33 {
+        return None;
+    }
+    PublicKey::from_slice(bytes).ok()
+}
+
+/// `input_hash·A` for a transaction that could pay a silent payment address.
+/// `prevouts` lines up with `tx.input`.
+pub fn transaction_tweak(tx: &Transaction, prevouts: &[TxOut]) -> Option<PublicKey> {
+    if tx.is_coinbase() || prevouts.len() != tx.input.len() || !tx.output.iter().any(|output| output.script_pubkey.is_p2tr()) {
+        return None;
+    }
+    // Spends of future segwit versions rule out the whole transaction
+    if prevouts.iter().any(|prevout| {
+        prevout.script_pubkey.witness_version().is_some_and(|version| version > WitnessVersion::V1)
+    }) {
+        return None;
+    }
+
+    let keys: Vec<PublicKey> = tx.input.iter()
+        .zip(prevouts)
+        .filter_map(|(input, prevout)| input_public_key(input, prevout))
+        .collect();
+    let key_refs: Vec<&PublicKey> = keys.iter().collect();
+    let input_sum = PublicKey::combine_keys(&key_refs).ok()?;
+
+    let smallest_outpoint = tx.input.iter()
+        .map(|input| bitcoin::consensus::serialize(&input.previous_output))
+        .min()?;
+    let input_hash = tagged_hash("BIP0352/Inputs", &[&smallest_outpoint, &input_sum.serialize()]);
+    let input_hash = Scalar::from_be_bytes(input_hash).ok()?;
+    input_sum.mul_tweak(&Secp256k1::verification_only(), &input_hash).ok()
+}
+
+/// Output paying the scan keys' owner, with the tweak added to the spend key to spend it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedOutput {
+    pub vout: u32,
+    pub tweak: [u8; 32],
+}
+
+/// Taproot outputs of `tx` that pay `keys`, given the transaction's tweak
+pub fn scan_outputs(keys: &ScanKeys, tweak: &PublicKey, tx: &Transaction) -> Vec<DetectedOutput> {
+    let secp = Secp256k1::verification_only();
+    let Ok(shared_secret) = tweak.mul_tweak(&secp, &Scalar::from(keys.scan_secret)) else {
+        return Vec::new();
+    };
+    let shared_secret = shared_secret.serialize();
+
+    let mut remaining: Vec<(u32, XOnlyPublicKey)> = tx.output.iter()
+        .enumerate()
+        .filter(|(_, output)| output.script_pubkey.is_p2tr())
+        .filter_map(|(vout, output)| {
+            XOnlyPublicKey::from_slice(&output.script_pubkey.as_bytes()[2..34]).ok().map(|key| (vout as u32, key))
+        })
+        .collect();
+
+    // Outputs for one recipient use k = 0, 1, ... so stop at the first k with no match
+    let mut detected = Vec::new();
+    for k in 0u32.. {
+        let t_k = tagged_hash("BIP0352/SharedSecret", &[&shared_secret, &k.to_be_bytes()]);
+        let Ok(scalar) = Scalar::from_be_bytes(t_k) else {
+            break;
+        };
+        let Ok(candidate) = keys.spend.add_exp_tweak(&secp, &scalar) else {
+            break;
+        };
+        let candidate = candidate.x_only_public_key().0;
+        let Some(position) = remaining.iter().position(|(_, key)| *key == candidate) else {
+            break;
+        };
+        let (vout, _) = remaining.remove(position);
+        detected.push(DetectedOutput { vout, tweak: t_k });
+    }
+    detected
+}
+
+pub fn encode_tweaks(tweaks: &[(Txid, PublicKey)]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(tweaks.len() * TWEAK_RECORD_LEN);
+    for (txid, tweak) in tweaks {
+        data.extend(txid.to_byte_array());
+        data.extend(tweak.serialize());
+    }
+    data
+}
+
+pub fn decode_tweaks(data: &[u8]) -> SilentPaymentResult<Vec<(Txid, PublicKey)>> {
+    if !data.len().is_multiple_of(TWEAK_RECORD_LEN) {
+        return Err(SilentPaymentError::Index(format!("record length {} is not a multiple of {}", data.len(), TWEAK_RECORD_LEN)));
+    }
+    data.chunks(TWEAK_RECORD_LEN)
+        .map(|record| {
+            let txid = Txid::from_slice(&record[..32]).map_err(|e| SilentPaymentError::Index(e.to_string()))?;
+            let tweak = PublicKey::from_slice(&record[32..]).map_err(|e| SilentPaymentError::Index(e.to_string()))?;
+            Ok((txid, tweak))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, Witness};
+
+    fn secret(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    /// Sender side of BIP 352 for a single P2WPKH input
+    fn payment_to(address: &SilentPaymentAddress, sender: SecretKey, outpoint: OutPoint) -> (Transaction, TxOut) {
+        let secp = Secp256k1::new();
+        let sender_public = sender.public_key(&secp);
+        let prevout = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::PublicKey::new(sender_public).wpubkey_hash().unwrap()),
+        };
+
+        let input_hash = tagged_hash("BIP0352/Inputs", &[&bitcoin::consensus::serialize(&outpoint), &sender_public.serialize()]);
+        let shared_secret = address.scan
+            .mul_tweak(&secp, &Scalar::from(sender.mul_tweak(&Scalar::from_be_bytes(input_hash).unwrap()).unwrap()))
+            .unwrap();
+        let t_0 = tagged_hash("BIP0352/SharedSecret", &[&shared_secret.serialize(), &0u32.to_be_bytes()]);
+        let output_key = address.spend.add_exp_tweak(&secp, &Scalar::from_be_bytes(t_0).unwrap()).unwrap();
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![0x30; 71], sender_public.serialize().to_vec()]),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::PublicKey::new(sender_public).wpubkey_hash().unwrap()),
+                },
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(
+                        bitcoin::key::TweakedPublicKey::dangerous_assume_tweaked(output_key.x_only_public_key().0),
+                    ),
+                },
+            ],
+        };
+        (tx, prevout)
+    }
+
+    #[test]
+    fn test_address_roundtrip() {
+        let secp = Secp256k1::new();
+        let address = SilentPaymentAddress::new(Network::Regtest, secret(1).public_key(&secp), secret(2).public_key(&secp));
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("tsp1q"));
+        assert_eq!(SilentPaymentAddress::parse(&encoded, Network::Regtest).unwrap(), address);
+        assert_eq!(SilentPaymentAddress::parse(&encoded.to_uppercase(), Network::Signet).unwrap().scan, address.scan);
+        assert!(matches!(SilentPaymentAddress::parse(&encoded, Network::Bitcoin), Err(SilentPaymentError::WrongNetwork(_))));
+    }
+
+    #[test]
+    fn test_scan_detects_payment() {
+        let secp = Secp256k1::new();
+        let address = SilentPaymentAddress::new(Network::Regtest, secret(1).public_key(&secp), secret(2).public_key(&secp));
+        let keys = ScanKeys::for_address(secret(1), &address).unwrap();
+        assert!(ScanKeys::for_address(secret(4), &address).is_err());
+        let outpoint = OutPoint { txid: Txid::from_byte_array([9; 32]), vout: 1 };
+        let (tx, prevout) = payment_to(&address, secret(3), outpoint);
+
+        let tweak = transaction_tweak(&tx, std::slice::from_ref(&prevout)).unwrap();
+        let detected = scan_outputs(&keys, &tweak, &tx);
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].vout, 1);
+
+        // Someone else's scan key finds nothing
+        let other = ScanKeys { scan_secret: secret(4), spend: keys.spend };
+        assert!(scan_outputs(&other, &tweak, &tx).is_empty());
+
+        let records = vec![(tx.txid(), tweak)];
+        assert_eq!(decode_tweaks(&encode_tweaks(&records)).unwrap(), records);
+
+        // Without a taproot output there is nothing to scan for
+        let mut no_taproot = tx.clone();
+        no_taproot.output.pop();
+        assert_eq!(transaction_tweak(&no_taproot, &[prevout]), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Regtest node snapshots for test fixtures
+//!
+//! A snapshot is a single JSON archive holding every storage column family
+//! (chain, UTXO set, indexes and anything else persisted) plus the mempool.
+//! Restoring one replaces the running node's state in place, so a test suite
+//! can boot a pre-mined chain instead of mining it on every run.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use actix::Addr;
+use bitcoin::hex::{DisplayHex, FromHex};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::chain::ChainActor;
+use crate::actors::mempool::{MempoolActor, MempoolExportEntry};
+use crate::actors::storage::StorageActor;
+use crate::actors::{ExportMempool, ExportStorage, GetChainTip, ImportMempool, ImportStorage, ReloadChainTip};
+use crate::error::{SnapshotError, SnapshotResult};
+use crate::storage::ColumnFamilyDump;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub version: u32,
+    pub network: String,
+    pub created_at: DateTime<Utc>,
+    pub tip_height: Option<u64>,
+    pub tip_hash: Option<String>,
+    /// Hex encoded key/value pairs per column family
+    pub column_families: BTreeMap<String, Vec<(String, String)>>,
+    pub mempool: Vec<MempoolExportEntry>,
+}
+
+impl NodeSnapshot {
+    fn storage_dump(&self) -> SnapshotResult<ColumnFamilyDump> {
+        let decode = |hex: &str| Vec::<u8>::from_hex(hex).map_err(|e| SnapshotError::Invalid(e.to_string()));
+        self.column_families
+            .iter()
+            .map(|(name, records)| {
+                let records = records
+                    .iter()
+                    .map(|(key, value)| Ok((decode(key)?, decode(value)?)))
+                    .collect::<SnapshotResult<Vec<_>>>()?;
+                Ok((name.clone(), records))
+            })
+            .collect()
+    }
+
+    pub fn summary(&self, name: &str) -> SnapshotSummary {
+        SnapshotSummary {
+            name: name.to_string(),
+            created_at: self.created_at,
+            tip_height: self.tip_height,
+            tip_hash: self.tip_hash.clone(),
+            mempool_size: self.mempool.len(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> SnapshotResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_vec(self).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+        // Write then rename so a crash never leaves a truncated fixture behind
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, data)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> SnapshotResult<Self> {
+        let data = std::fs::read(path)?;
+        let snapshot: Self = serde_json::from_slice(&data).map_err(|e| SnapshotError::Invalid(e.to_string()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotSummary {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub tip_height: Option<u64>,
+    pub tip_hash: Option<String>,
+    pub mempool_size: usize,
+}
+
+/// Archive path for a snapshot name, which may only use letters, digits, `-` and `_`
+pub fn snapshot_path(datadir: &Path, name: &str) -> SnapshotResult<PathBuf> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(SnapshotError::InvalidName(name.to_string()));
+    }
+    Ok(datadir.join("snapshots").join(format!("{}.json", name)))
+}
+
+pub async fn take_snapshot(
+    storage_actor: &Addr<StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    network: &str,
+) -> SnapshotResult<NodeSnapshot> {
+    let tip = storage_actor.send_traced(GetChainTip).await??;
+    let dump = storage_actor.send_traced(ExportStorage).await??;
+    let mempool = mempool_actor.send_traced(ExportMempool).await?;
+
+    let column_families = dump
+        .into_iter()
+        .map(|(name, records)| {
+            let records = records
+                .iter()
+                .map(|(key, value)| (key.to_lower_hex_string(), value.to_lower_hex_string()))
+                .collect();
+            (name, records)
+        })
+        .collect();
+
+    Ok(NodeSnapshot {
+        version: SNAPSHOT_VERSION,
+        network: network.to_string(),
+        created_at: Utc::now(),
+        tip_height: tip.map(|tip| tip.height),
+        tip_hash: tip.map(|tip| tip.hash.to_string()),
+        column_families,
+        mempool,
+    })
+}
+
+/// Replace storage and mempool with the snapshot, then point the chain actor at the restored tip
+pub async fn restore_snapshot(
+    snapshot: NodeSnapshot,
+    storage_actor: &Addr<StorageActor>,
+    mempool_actor: &Addr<MempoolActor>,
+    chain_actor: &Addr<ChainActor>,
+    network: &str,
+) -> SnapshotResult<()> {
+    if snapshot.network != network {
+        return Err(SnapshotError::NetworkMismatch {
+            expected: network.to_string(),
+            found: snapshot.network,
+        });
+    }
+
+    let dump = snapshot.storage_dump()?;
+    storage_actor.send_traced(ImportStorage { dump }).await??;
+    chain_actor.send_traced(ReloadChainTip).await??;
+    mempool_actor.send_traced(ImportMempool { entries: snapshot.mempool }).await??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_names() {
+        let datadir = Path::new("/data");
+        assert_eq!(snapshot_path(datadir, "mined-101_blocks").unwrap(), PathBuf::from("/data/snapshots/mined-101_blocks.json"));
+        assert!(matches!(snapshot_path(datadir, "../config"), Err(SnapshotError::InvalidName(_))));
+        assert!(snapshot_path(datadir, "").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_file_roundtrip() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = snapshot_path(temp_dir.path(), "fixture").unwrap();
+        let mut column_families = BTreeMap::new();
+        column_families.insert("blocks".to_string(), vec![("0102".to_string(), "ff".to_string())]);
+        let snapshot = NodeSnapshot {
+            version: SNAPSHOT_VERSION,
+            network: "regtest".to_string(),
+            created_at: Utc::now(),
+            tip_height: Some(0),
+            tip_hash: None,
+            column_families,
+            mempool: Vec::new(),
+        };
+
+        snapshot.save(&path).unwrap();
+        let loaded = NodeSnapshot::load(&path).unwrap();
+        assert_eq!(loaded.storage_dump().unwrap()["blocks"], vec![(vec![1, 2], vec![0xff])]);
+        assert_eq!(loaded.summary("fixture").tip_height, Some(0));
+
+        let mut future = snapshot;
+        future.version = SNAPSHOT_VERSION + 1;
+        future.save(&path).unwrap();
+        assert!(matches!(NodeSnapshot::load(&path), Err(SnapshotError::UnsupportedVersion(_))));
+    }
+}
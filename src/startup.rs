@@ -0,0 +1,201 @@
+//! Startup progress
+//!
+//! Opening RocksDB (which replays its write-ahead log), rolling an
+//! interrupted block connection forward and rebuilding the UTXO set
+//! commitment can each take minutes on a large datadir. The stages report
+//! here, progress is logged in steps of ten percent, and `StartupServer`
+//! answers `GET /startup` on the API address until the API server takes the
+//! port over, so a slow start can be told apart from a hung one.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupStage {
+    OpeningDatabase,
+    RecoveringBlock,
+    BuildingUtxoCommitment,
+    StartingActors,
+    Ready,
+}
+
+impl StartupStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StartupStage::OpeningDatabase => "opening_database",
+            StartupStage::RecoveringBlock => "recovering_block",
+            StartupStage::BuildingUtxoCommitment => "building_utxo_commitment",
+            StartupStage::StartingActors => "starting_actors",
+            StartupStage::Ready => "ready",
+        }
+    }
+}
+
+struct Progress {
+    stage: StartupStage,
+    started: Instant,
+    /// Share of the stage done, for stages that can tell
+    percent: Option<u8>,
+}
+
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+/// Record and log the start of a stage
+pub fn enter(stage: StartupStage) {
+    info!(stage = stage.as_str(), "Startup stage {}", stage.as_str());
+    *PROGRESS.lock().unwrap() = Some(Progress { stage, started: Instant::now(), percent: None });
+}
+
+/// Record `done` out of `total` in the current stage, logging each ten percent step
+pub fn progress(done: u64, total: u64) {
+    let percent = (done.saturating_mul(100) / total.max(1)).min(100) as u8;
+    let mut progress = PROGRESS.lock().unwrap();
+    let Some(progress) = progress.as_mut() else {
+        return;
+    };
+    if crosses_step(progress.percent, percent) {
+        info!(stage = progress.stage.as_str(), percent, "Startup stage {} at {}%", progress.stage.as_str(), percent);
+    }
+    progress.percent = Some(percent);
+}
+
+/// Whether `percent` reaches a ten percent step that `last` did not
+fn crosses_step(last: Option<u8>, percent: u8) -> bool {
+    last.is_none_or(|last| percent / 10 > last / 10)
+}
+
+/// What `/startup` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupStatus {
+    pub stage: StartupStage,
+    pub percent: Option<u8>,
+    pub stage_seconds: u64,
+    pub ready: bool,
+}
+
+pub fn status() -> StartupStatus {
+    match PROGRESS.lock().unwrap().as_ref() {
+        Some(progress) => StartupStatus {
+            stage: progress.stage,
+            percent: progress.percent,
+            stage_seconds: progress.started.elapsed().as_secs(),
+            ready: progress.stage == StartupStage::Ready,
+        },
+        // Library users that never report stages are ready as soon as they ask
+        None => StartupStatus { stage: StartupStage::Ready, percent: None, stage_seconds: 0, ready: true },
+    }
+}
+
+/// `/startup` on a plain thread, which keeps answering while initialization
+/// blocks the runtime
+pub struct StartupServer {
+    addr: SocketAddr,
+    stopping: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl StartupServer {
+    pub fn start(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let mut addr = listener.local_addr()?;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+        }
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread_stopping = stopping.clone();
+        let thread = std::thread::Builder::new()
+            .name("startup-http".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Err(e) = stream.and_then(respond) {
+                        warn!("Startup status request failed: {}", e);
+                    }
+                }
+            })?;
+        Ok(Self { addr, stopping, thread })
+    }
+
+    /// Close the listener so the API server can bind its address
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // Wakes the accept loop up to see the flag
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1));
+        let _ = self.thread.join();
+    }
+}
+
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (status_line, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/startup")) => {
+            let status = status();
+            let status_line = if status.ready { "200 OK" } else { "503 Service Unavailable" };
+            (status_line, serde_json::to_string(&status).unwrap_or_default())
+        }
+        _ => ("404 Not Found", r#"{"error":"Starting up, only /startup is served"}"#.to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body,
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_progress_is_logged_in_steps() {
+        assert!(crosses_step(None, 0));
+        assert!(!crosses_step(Some(12), 19));
+        assert!(crosses_step(Some(19), 20));
+        assert!(crosses_step(Some(35), 100));
+    }
+
+    #[test]
+    fn test_startup_server() {
+        let server = StartupServer::start("127.0.0.1:0").unwrap();
+        let addr = server.addr;
+
+        // Storage tests report stages too, so only the shape is checked
+        let response = get(addr, "/startup");
+        let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert!(body["stage"].is_string());
+        let ready = body["ready"].as_bool().unwrap();
+        assert_eq!(response.starts_with("HTTP/1.1 200"), ready);
+        assert_eq!(response.starts_with("HTTP/1.1 503"), !ready);
+        assert!(get(addr, "/api/v1/info").starts_with("HTTP/1.1 404"));
+
+        // The address is free again once stopped
+        server.stop();
+        TcpListener::bind(addr).unwrap();
+    }
+}
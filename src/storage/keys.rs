@@ -0,0 +1,371 @@
+//! Key schema for the storage column families
+//!
+//! Every key the node writes is built by one of these types, so each column
+//! family has a single canonical encoding instead of byte slices assembled at
+//! the call site. Integers are big-endian so RocksDB's byte order is numeric
+//! order, and hashes are stored in their internal byte order.
+
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, OutPoint, Txid};
+
+use crate::error::{StorageError, StorageResult};
+
+/// A key with one canonical byte encoding
+pub trait StorageKey: Sized {
+    fn encode(&self) -> Vec<u8>;
+    fn decode(bytes: &[u8]) -> StorageResult<Self>;
+}
+
+fn fixed<const N: usize>(bytes: &[u8], what: &str) -> StorageResult<[u8; N]> {
+    bytes.try_into().map_err(|_| StorageError::Serialization(format!("{} key has {} bytes, expected {}", what, bytes.len(), N)))
+}
+
+/// Block hash; keys `CF_BLOCKS`, `CF_BLOCK_INDEX`, `CF_BEST_HEADER_INDEX` and `CF_STALE_BLOCKS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockKey(pub BlockHash);
+
+impl StorageKey for BlockKey {
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_byte_array().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        Ok(Self(BlockHash::from_byte_array(fixed(bytes, "Block")?)))
+    }
+}
+
+/// Transaction id; keys `CF_TRANSACTIONS`, `CF_TX_INDEX` and `CF_MEMPOOL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TxKey(pub Txid);
+
+impl StorageKey for TxKey {
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_byte_array().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        Ok(Self(Txid::from_byte_array(fixed(bytes, "Transaction")?)))
+    }
+}
+
+/// Outpoint in `CF_UTXOS`: the txid followed by the big-endian output index,
+/// so all outputs of a transaction are adjacent and in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UtxoKey {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl UtxoKey {
+    pub const SIZE: usize = 36;
+
+    pub fn new(txid: Txid, vout: u32) -> Self {
+        Self { txid, vout }
+    }
+
+    /// Prefix shared by every output of `txid`
+    pub fn txid_prefix(txid: &Txid) -> [u8; 32] {
+        txid.to_byte_array()
+    }
+
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+}
+
+impl From<OutPoint> for UtxoKey {
+    fn from(outpoint: OutPoint) -> Self {
+        Self::new(outpoint.txid, outpoint.vout)
+    }
+}
+
+impl StorageKey for UtxoKey {
+    fn encode(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(Self::SIZE);
+        key.extend_from_slice(&Self::txid_prefix(&self.txid));
+        key.extend_from_slice(&self.vout.to_be_bytes());
+        key
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        let bytes: [u8; Self::SIZE] = fixed(bytes, "Outpoint")?;
+        let txid = Txid::from_byte_array(bytes[..32].try_into().expect("32 bytes"));
+        Ok(Self::new(txid, u32::from_be_bytes(bytes[32..].try_into().expect("4 bytes"))))
+    }
+}
+
+/// Big-endian height or sequence number, so keys sort numerically; keys
+/// `CF_HEADERS`, `CF_BEST_HEADERS`, `CF_UTXO_DELTAS`, `CF_SILENT_PAYMENT_TWEAKS`,
+/// `CF_REORGS`, `CF_PEER_EVENTS` and `CF_EVENTS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeightKey(pub u64);
+
+impl StorageKey for HeightKey {
+    fn encode(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        Ok(Self(u64::from_be_bytes(fixed(bytes, "Height")?)))
+    }
+}
+
+/// `CF_STATS` record: a type prefix followed by a big-endian id, the height,
+/// day index or Unix time. Block fee rates are keyed by block time with the
+/// height after it, so a time range is one contiguous scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatsKey {
+    Block(u64),
+    Day(u32),
+    BlockFeeRates { time: u32, height: u32 },
+    MempoolFee(u64),
+}
+
+impl StatsKey {
+    const BLOCK_PREFIX: u8 = b'b';
+    const DAY_PREFIX: u8 = b'd';
+    const BLOCK_FEE_RATES_PREFIX: u8 = b'f';
+    const MEMPOOL_FEE_PREFIX: u8 = b'm';
+
+    /// Prefix shared by every record of the same type as `self`
+    pub fn prefix(&self) -> u8 {
+        match self {
+            StatsKey::Block(_) => Self::BLOCK_PREFIX,
+            StatsKey::Day(_) => Self::DAY_PREFIX,
+            StatsKey::BlockFeeRates { .. } => Self::BLOCK_FEE_RATES_PREFIX,
+            StatsKey::MempoolFee(_) => Self::MEMPOOL_FEE_PREFIX,
+        }
+    }
+}
+
+impl StorageKey for StatsKey {
+    fn encode(&self) -> Vec<u8> {
+        let id = match *self {
+            StatsKey::Block(height) => height,
+            StatsKey::Day(day) => day as u64,
+            StatsKey::BlockFeeRates { time, height } => ((time as u64) << 32) | height as u64,
+            StatsKey::MempoolFee(time) => time,
+        };
+        let mut key = Vec::with_capacity(9);
+        key.push(self.prefix());
+        key.extend_from_slice(&id.to_be_bytes());
+        key
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        let bytes: [u8; 9] = fixed(bytes, "Stats")?;
+        let id = u64::from_be_bytes(bytes[1..].try_into().expect("8 bytes"));
+        match bytes[0] {
+            Self::BLOCK_PREFIX => Ok(StatsKey::Block(id)),
+            Self::DAY_PREFIX => u32::try_from(id)
+                .map(StatsKey::Day)
+                .map_err(|_| StorageError::Serialization(format!("Stats day index {} out of range", id))),
+            Self::BLOCK_FEE_RATES_PREFIX => Ok(StatsKey::BlockFeeRates { time: (id >> 32) as u32, height: id as u32 }),
+            Self::MEMPOOL_FEE_PREFIX => Ok(StatsKey::MempoolFee(id)),
+            prefix => Err(StorageError::Serialization(format!("Unknown stats key prefix {:#04x}", prefix))),
+        }
+    }
+}
+
+/// `CF_PEERS` record: a peer id, or a cached DNS seed answer under
+/// `dnsseed/`; peer ids never contain a `/`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerKey {
+    Peer(String),
+    DnsSeed(String),
+}
+
+impl PeerKey {
+    pub const DNS_SEED_PREFIX: &'static [u8] = b"dnsseed/";
+}
+
+impl StorageKey for PeerKey {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            PeerKey::Peer(id) => id.as_bytes().to_vec(),
+            PeerKey::DnsSeed(seed) => [Self::DNS_SEED_PREFIX, seed.as_bytes()].concat(),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        let text = |bytes: &[u8]| {
+            String::from_utf8(bytes.to_vec()).map_err(|e| StorageError::Serialization(format!("Peer key is not UTF-8: {}", e)))
+        };
+        match bytes.strip_prefix(Self::DNS_SEED_PREFIX) {
+            Some(seed) => Ok(PeerKey::DnsSeed(text(seed)?)),
+            None => Ok(PeerKey::Peer(text(bytes)?)),
+        }
+    }
+}
+
+/// Whether an address index entry records a coin paid to the script or one it spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressEntryKind {
+    Funding,
+    Spending,
+}
+
+/// `CF_ADDRESS_INDEX` entry: the script hash, the big-endian height, the
+/// txid, the kind and the output index (funding) or input index (spending),
+/// so one script's history is contiguous and in chain order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AddressKey {
+    pub script_hash: [u8; 32],
+    pub height: u64,
+    pub txid: Txid,
+    pub kind: AddressEntryKind,
+    pub index: u32,
+}
+
+impl AddressKey {
+    pub const SIZE: usize = 77;
+    const FUNDING: u8 = b'f';
+    const SPENDING: u8 = b's';
+
+    /// SHA256 of the script, as Electrum servers key their history
+    pub fn script_hash(script_pubkey: &bitcoin::Script) -> [u8; 32] {
+        bitcoin::hashes::sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array()
+    }
+
+    /// The last possible key of `script_hash` at `height`, where a newest
+    /// first scan of that height and below starts
+    pub fn height_end(script_hash: &[u8; 32], height: u64) -> Vec<u8> {
+        let mut key = Vec::with_capacity(Self::SIZE);
+        key.extend_from_slice(script_hash);
+        key.extend_from_slice(&height.to_be_bytes());
+        key.resize(Self::SIZE, 0xff);
+        key
+    }
+}
+
+impl StorageKey for AddressKey {
+    fn encode(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(Self::SIZE);
+        key.extend_from_slice(&self.script_hash);
+        key.extend_from_slice(&self.height.to_be_bytes());
+        key.extend_from_slice(&self.txid.to_byte_array());
+        key.push(match self.kind {
+            AddressEntryKind::Funding => Self::FUNDING,
+            AddressEntryKind::Spending => Self::SPENDING,
+        });
+        key.extend_from_slice(&self.index.to_be_bytes());
+        key
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        let bytes: [u8; Self::SIZE] = fixed(bytes, "Address")?;
+        let kind = match bytes[72] {
+            Self::FUNDING => AddressEntryKind::Funding,
+            Self::SPENDING => AddressEntryKind::Spending,
+            kind => return Err(StorageError::Serialization(format!("Unknown address entry kind {:#04x}", kind))),
+        };
+        Ok(Self {
+            script_hash: bytes[..32].try_into().expect("32 bytes"),
+            height: u64::from_be_bytes(bytes[32..40].try_into().expect("8 bytes")),
+            txid: Txid::from_byte_array(bytes[40..72].try_into().expect("32 bytes")),
+            kind,
+            index: u32::from_be_bytes(bytes[73..].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+/// Named `CF_CHAIN_STATE` records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChainStateKey {
+    Tip,
+    /// The UTXO delta of a block whose connection has started but not
+    /// finished, left by versions before block connections were one atomic write
+    ConnectIntent,
+    UtxoCommitment,
+    /// Blocks connected before the address index was enabled: the next height
+    /// to index and the first one indexed as it connected, both big-endian
+    AddressIndexBackfill,
+    /// Tip a node snapshot was restored at; the restored blocks were
+    /// validated by the node that took the snapshot, not by this one
+    SnapshotBase,
+}
+
+impl ChainStateKey {
+    pub const ALL: [ChainStateKey; 5] = [
+        ChainStateKey::Tip,
+        ChainStateKey::ConnectIntent,
+        ChainStateKey::UtxoCommitment,
+        ChainStateKey::AddressIndexBackfill,
+        ChainStateKey::SnapshotBase,
+    ];
+
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            ChainStateKey::Tip => b"tip",
+            ChainStateKey::ConnectIntent => b"connect_intent",
+            ChainStateKey::UtxoCommitment => b"utxo_commitment",
+            ChainStateKey::AddressIndexBackfill => b"address_index_backfill",
+            ChainStateKey::SnapshotBase => b"snapshot_base",
+        }
+    }
+}
+
+impl StorageKey for ChainStateKey {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> StorageResult<Self> {
+        Self::ALL.into_iter().find(|key| key.as_bytes() == bytes).ok_or_else(|| {
+            StorageError::Serialization(format!("Unknown chain state key {}", String::from_utf8_lossy(bytes)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<K: StorageKey + PartialEq + std::fmt::Debug>(key: K) {
+        assert_eq!(K::decode(&key.encode()).unwrap(), key);
+    }
+
+    #[test]
+    fn test_keys_round_trip() {
+        let txid = Txid::from_byte_array([7u8; 32]);
+        round_trip(BlockKey(BlockHash::from_byte_array([3u8; 32])));
+        round_trip(TxKey(txid));
+        round_trip(UtxoKey::new(txid, 513));
+        round_trip(HeightKey(840_000));
+        round_trip(StatsKey::Block(12));
+        round_trip(StatsKey::Day(19_800));
+        round_trip(StatsKey::BlockFeeRates { time: 1_700_000_000, height: 820_000 });
+        round_trip(StatsKey::MempoolFee(1_700_000_600));
+        round_trip(PeerKey::Peer("203.0.113.5:8333".to_string()));
+        round_trip(PeerKey::DnsSeed("seed.example.org".to_string()));
+        round_trip(AddressKey { script_hash: [5u8; 32], height: 100, txid, kind: AddressEntryKind::Spending, index: 2 });
+        for key in ChainStateKey::ALL {
+            round_trip(key);
+        }
+
+        assert!(BlockKey::decode(&[0u8; 31]).is_err());
+        assert!(UtxoKey::decode(&[0u8; 32]).is_err());
+        assert!(StatsKey::decode(&[b'x'; 9]).is_err());
+        assert!(ChainStateKey::decode(b"nope").is_err());
+    }
+
+    #[test]
+    fn test_encodings_sort_and_group() {
+        let txid = Txid::from_byte_array([9u8; 32]);
+        let low = UtxoKey::new(txid, 1).encode();
+        let high = UtxoKey::new(txid, 256).encode();
+        assert!(low < high);
+        assert!(low.starts_with(&UtxoKey::txid_prefix(&txid)));
+
+        assert_ne!(StatsKey::Block(5).encode(), StatsKey::Day(5).encode());
+        assert_eq!(StatsKey::Day(5).encode()[0], StatsKey::Day(0).prefix());
+        assert!(PeerKey::DnsSeed("a".to_string()).encode().starts_with(PeerKey::DNS_SEED_PREFIX));
+
+        let script_hash = [4u8; 32];
+        let funding = AddressKey { script_hash, height: 7, txid, kind: AddressEntryKind::Funding, index: 300 };
+        let later = AddressKey { height: 8, index: 0, ..funding }.encode();
+        assert!(funding.encode() < later);
+        assert!(funding.encode() <= AddressKey::height_end(&script_hash, 7));
+        assert!(later > AddressKey::height_end(&script_hash, 7));
+    }
+}
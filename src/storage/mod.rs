@@ -0,0 +1,1682 @@
+use rocksdb::{DB, Direction, IteratorMode, Options, ColumnFamily, ColumnFamilyDescriptor, WriteBatch};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{info, error};
+
+use crate::config::StorageConfig;
+use crate::error::{StorageError, StorageResult};
+use crate::muhash::{MuHash3072, MUHASH_STATE_SIZE};
+
+pub mod keys;
+pub mod remote;
+
+use keys::{AddressKey, BlockKey, ChainStateKey, HeightKey, PeerKey, StatsKey, StorageKey, TxKey, UtxoKey};
+
+pub struct Storage {
+    backend: Backend,
+    /// Set on a `write_batch` view, whose writes collect here until `commit`
+    batch: Option<Arc<Mutex<WriteBatch>>>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Local(Arc<DB>),
+    /// An API replica reading through to a full node
+    Remote(Arc<remote::RemoteStorage>),
+}
+
+// Column families for different data types
+pub const CF_BLOCKS: &str = "blocks";
+pub const CF_TRANSACTIONS: &str = "transactions";
+pub const CF_UTXOS: &str = "utxos";
+pub const CF_CHAIN_STATE: &str = "chain_state";
+pub const CF_MEMPOOL: &str = "mempool";
+pub const CF_PEERS: &str = "peers";
+pub const CF_UTXO_DELTAS: &str = "utxo_deltas";
+pub const CF_TX_INDEX: &str = "tx_index";
+pub const CF_HEADERS: &str = "headers";
+pub const CF_PEER_EVENTS: &str = "peer_events";
+pub const CF_STATS: &str = "stats";
+pub const CF_BLOCK_INDEX: &str = "block_index";
+pub const CF_STALE_BLOCKS: &str = "stale_blocks";
+pub const CF_REORGS: &str = "reorgs";
+pub const CF_SILENT_PAYMENT_TWEAKS: &str = "silent_payment_tweaks";
+pub const CF_ADDRESS_INDEX: &str = "address_index";
+pub const CF_EVENTS: &str = "events";
+pub const CF_BEST_HEADERS: &str = "best_headers";
+pub const CF_BEST_HEADER_INDEX: &str = "best_header_index";
+
+pub const ALL_COLUMN_FAMILIES: &[&str] = &[
+    CF_BLOCKS,
+    CF_TRANSACTIONS,
+    CF_UTXOS,
+    CF_CHAIN_STATE,
+    CF_MEMPOOL,
+    CF_PEERS,
+    CF_UTXO_DELTAS,
+    CF_TX_INDEX,
+    CF_HEADERS,
+    CF_PEER_EVENTS,
+    CF_STATS,
+    CF_BLOCK_INDEX,
+    CF_STALE_BLOCKS,
+    CF_REORGS,
+    CF_SILENT_PAYMENT_TWEAKS,
+    CF_ADDRESS_INDEX,
+    CF_EVENTS,
+    CF_BEST_HEADERS,
+    CF_BEST_HEADER_INDEX,
+];
+
+/// Address index entries read per batch while walking a script's history
+const ADDRESS_SCAN_BATCH: usize = 1000;
+
+/// Pairs read per page by `StorageIter`
+const ITER_PAGE: usize = 1000;
+
+/// Pairs of a column family read a page at a time, from RocksDB or through
+/// to the full node behind a replica. Each page is read separately, so a
+/// long iteration sees writes made while it runs.
+pub struct StorageIter<'a> {
+    storage: &'a Storage,
+    cf_name: &'a str,
+    prefix: Vec<u8>,
+    /// Last key returned, where the next page starts
+    last: Option<Vec<u8>>,
+    page: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    exhausted: bool,
+}
+
+impl StorageIter<'_> {
+    fn read_page(&mut self) -> StorageResult<()> {
+        let from = self.last.clone().unwrap_or_else(|| self.prefix.clone());
+        // A page after the first starts at the key already returned
+        let limit = ITER_PAGE + self.last.is_some() as usize;
+        let (mut items, mut read, mut past_prefix) = (Vec::with_capacity(limit), 0, false);
+        self.storage.scan(self.cf_name, Some(&from), false, limit, |key, value| {
+            read += 1;
+            if !key.starts_with(&self.prefix) {
+                past_prefix = true;
+            } else if !past_prefix && self.last.as_deref() != Some(key) {
+                items.push((key.to_vec(), value.to_vec()));
+            }
+            Ok(())
+        })?;
+
+        self.exhausted = read < limit || past_prefix;
+        if let Some((key, _)) = items.last() {
+            self.last = Some(key.clone());
+        }
+        self.page = items.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for StorageIter<'_> {
+    type Item = StorageResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.page.next() {
+                return Some(Ok(item));
+            }
+            if self.exhausted {
+                return None;
+            }
+            if let Err(e) = self.read_page() {
+                self.exhausted = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// A backup in the backup directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub id: u32,
+    /// Unix time the backup was taken
+    pub timestamp: i64,
+    pub size: u64,
+}
+
+impl From<rocksdb::backup::BackupEngineInfo> for BackupInfo {
+    fn from(info: rocksdb::backup::BackupEngineInfo) -> Self {
+        Self { id: info.backup_id, timestamp: info.timestamp, size: info.size }
+    }
+}
+
+fn open_backup_engine(backup_path: &Path) -> StorageResult<rocksdb::backup::BackupEngine> {
+    let options = rocksdb::backup::BackupEngineOptions::new(backup_path)?;
+    Ok(rocksdb::backup::BackupEngine::open(&options, &rocksdb::Env::new()?)?)
+}
+
+/// Raw key/value pairs per column family, as exported for node snapshots
+pub type ColumnFamilyDump = BTreeMap<String, Vec<(Vec<u8>, Vec<u8>)>>;
+
+/// Unspent output as stored in the UTXO column family
+#[derive(Debug, Clone, PartialEq)]
+pub struct UtxoEntry {
+    pub height: u64,
+    pub is_coinbase: bool,
+    pub output: bitcoin::TxOut,
+}
+
+impl UtxoEntry {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.push(self.is_coinbase as u8);
+        data.extend_from_slice(&bitcoin::consensus::serialize(&self.output));
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> StorageResult<Self> {
+        if data.len() < 9 {
+            return Err(StorageError::Serialization(format!("UTXO entry too short: {} bytes", data.len())));
+        }
+        let mut height = [0u8; 8];
+        height.copy_from_slice(&data[..8]);
+        let output = bitcoin::consensus::deserialize(&data[9..])
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+
+        Ok(Self {
+            height: u64::from_be_bytes(height),
+            is_coinbase: data[8] != 0,
+            output,
+        })
+    }
+}
+
+/// Active chain header at a height, with the cumulative work up to and including it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderEntry {
+    pub header: bitcoin::block::Header,
+    pub chain_work: bitcoin::Work,
+}
+
+impl HeaderEntry {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = bitcoin::consensus::serialize(&self.header);
+        data.extend_from_slice(&self.chain_work.to_be_bytes());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> StorageResult<Self> {
+        if data.len() != 112 {
+            return Err(StorageError::Serialization(format!("Invalid header entry: {} bytes", data.len())));
+        }
+        let header = bitcoin::consensus::deserialize(&data[..80])
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let mut work = [0u8; 32];
+        work.copy_from_slice(&data[80..]);
+
+        Ok(Self {
+            header,
+            chain_work: bitcoin::Work::from_be_bytes(work),
+        })
+    }
+}
+
+/// MuHash of the UTXO set as of `block_hash`; the all-zero hash stands for
+/// the empty set before genesis
+#[derive(Clone, PartialEq, Eq)]
+pub struct UtxoCommitment {
+    pub block_hash: bitcoin::BlockHash,
+    pub muhash: MuHash3072,
+}
+
+impl UtxoCommitment {
+    pub fn encode(&self) -> Vec<u8> {
+        use bitcoin::hashes::Hash;
+
+        let mut data = self.block_hash.to_byte_array().to_vec();
+        data.extend(self.muhash.to_bytes());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> StorageResult<Self> {
+        use bitcoin::hashes::Hash;
+
+        if data.len() != 32 + MUHASH_STATE_SIZE {
+            return Err(StorageError::Serialization(format!("Invalid UTXO commitment: {} bytes", data.len())));
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&data[..32]);
+        let muhash = MuHash3072::from_bytes(&data[32..])
+            .ok_or_else(|| StorageError::Serialization("Invalid MuHash state".to_string()))?;
+
+        Ok(Self {
+            block_hash: bitcoin::BlockHash::from_byte_array(hash),
+            muhash,
+        })
+    }
+}
+
+/// Address index value: the amount, and for a spend the outpoint it consumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressEntry {
+    pub value: u64,
+    pub prevout: Option<bitcoin::OutPoint>,
+}
+
+impl AddressEntry {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.value.to_be_bytes().to_vec();
+        if let Some(prevout) = self.prevout {
+            data.extend(UtxoKey::from(prevout).encode());
+        }
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> StorageResult<Self> {
+        let prevout = match data.len() {
+            8 => None,
+            44 => Some(UtxoKey::decode(&data[8..])?.outpoint()),
+            len => return Err(StorageError::Serialization(format!("Invalid address entry: {} bytes", len))),
+        };
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&data[..8]);
+        Ok(Self { value: u64::from_be_bytes(value), prevout })
+    }
+}
+
+/// Where a confirmed transaction lives, as stored in the transaction index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block_hash: bitcoin::BlockHash,
+    pub height: u64,
+    pub position: u32,
+}
+
+impl TxLocation {
+    pub fn encode(&self) -> Vec<u8> {
+        use bitcoin::hashes::Hash;
+
+        let mut data = Vec::with_capacity(44);
+        data.extend_from_slice(&self.block_hash.to_byte_array());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&self.position.to_be_bytes());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> StorageResult<Self> {
+        use bitcoin::hashes::Hash;
+
+        if data.len() != 44 {
+            return Err(StorageError::Serialization(format!("Invalid tx location: {} bytes", data.len())));
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&data[..32]);
+        let mut height = [0u8; 8];
+        height.copy_from_slice(&data[32..40]);
+        let mut position = [0u8; 4];
+        position.copy_from_slice(&data[40..]);
+
+        Ok(Self {
+            block_hash: bitcoin::BlockHash::from_byte_array(hash),
+            height: u64::from_be_bytes(height),
+            position: u32::from_be_bytes(position),
+        })
+    }
+}
+
+impl Storage {
+    pub fn new(config: &StorageConfig) -> StorageResult<Self> {
+        if config.remote.enabled {
+            let remote = remote::RemoteStorage::new(&config.remote)?;
+            return Ok(Self { backend: Backend::Remote(Arc::new(remote)), batch: None });
+        }
+        let path = &config.rocks_db_path;
+
+        // Create directory if it doesn't exist
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| StorageError::DatabaseNotFound {
+                    path: format!("Failed to create directory {}: {}", parent.display(), e)
+                })?;
+        }
+
+        // Configure RocksDB options
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        opts.set_max_open_files(config.max_open_files);
+
+        // Set cache size
+        let cache = rocksdb::Cache::new_lru_cache(config.cache_size);
+        let mut block_opts = rocksdb::BlockBasedOptions::default();
+        block_opts.set_block_cache(&cache);
+        opts.set_block_based_table_factory(&block_opts);
+
+        // Set compression
+        match config.compression {
+            crate::config::CompressionType::None => opts.set_compression_type(rocksdb::DBCompressionType::None),
+            crate::config::CompressionType::Snappy => opts.set_compression_type(rocksdb::DBCompressionType::Snappy),
+            crate::config::CompressionType::Lz4 => opts.set_compression_type(rocksdb::DBCompressionType::Lz4),
+            crate::config::CompressionType::Zstd => opts.set_compression_type(rocksdb::DBCompressionType::Zstd),
+        }
+
+        // Throttle flush and compaction writes, manual compaction included
+        let rate_limit = config.compaction.rate_limit_bytes_per_sec;
+        if rate_limit > 0 {
+            opts.set_ratelimiter(rate_limit as i64, 100_000, 10);
+        }
+
+        // Define column families
+        let cfs: Vec<_> = ALL_COLUMN_FAMILIES
+            .iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, Options::default()))
+            .collect();
+
+        // Open database
+        let db = DB::open_cf_descriptors(&opts, path, cfs)
+            .map_err(|e| StorageError::RocksDb(e))?;
+
+        info!("Storage initialized at {:?}", path);
+        info!("Cache size: {} MB", config.cache_size / 1024 / 1024);
+        info!("Compression: {:?}", config.compression);
+
+        Ok(Self {
+            backend: Backend::Local(Arc::new(db)),
+            batch: None,
+        })
+    }
+
+    /// Whether reads go to a remote node and writes are refused
+    pub fn is_remote(&self) -> bool {
+        matches!(self.backend, Backend::Remote(_))
+    }
+
+    /// The local database, for anything that writes or needs RocksDB itself
+    fn db(&self) -> StorageResult<&DB> {
+        match &self.backend {
+            Backend::Local(db) => Ok(db),
+            Backend::Remote(remote) => Err(StorageError::ReadOnly { url: remote.url().to_string() }),
+        }
+    }
+
+    /// Visit up to `limit` pairs of a column family in key order, or in
+    /// reverse, from `from` inclusive or from the first or last key
+    fn scan(
+        &self,
+        cf_name: &str,
+        from: Option<&[u8]>,
+        reverse: bool,
+        limit: usize,
+        mut visit: impl FnMut(&[u8], &[u8]) -> StorageResult<()>,
+    ) -> StorageResult<()> {
+        let db = match &self.backend {
+            Backend::Local(db) => db,
+            Backend::Remote(remote) => return remote.scan(cf_name, from, reverse, limit, visit),
+        };
+        let cf = Self::cf(db, cf_name)?;
+        let direction = if reverse { Direction::Reverse } else { Direction::Forward };
+        let mode = match (from, reverse) {
+            (Some(from), _) => IteratorMode::From(from, direction),
+            (None, false) => IteratorMode::Start,
+            (None, true) => IteratorMode::End,
+        };
+        for item in db.iterator_cf(cf, mode).take(limit) {
+            let (key, value) = item.map_err(StorageError::RocksDb)?;
+            visit(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Every pair of a column family in key order
+    pub fn iter_cf<'a>(&'a self, cf_name: &'a str) -> StorageIter<'a> {
+        self.iter_prefix(cf_name, &[])
+    }
+
+    /// The pairs of a column family whose keys start with `prefix`, in key order
+    pub fn iter_prefix<'a>(&'a self, cf_name: &'a str, prefix: &[u8]) -> StorageIter<'a> {
+        StorageIter {
+            storage: self,
+            cf_name,
+            prefix: prefix.to_vec(),
+            last: None,
+            page: Vec::new().into_iter(),
+            exhausted: false,
+        }
+    }
+
+    /// Number of keys in a column family. Reads every key, so it takes as
+    /// long as a full scan.
+    pub fn count_cf(&self, cf_name: &str) -> StorageResult<u64> {
+        let mut count = 0;
+        self.scan(cf_name, None, false, usize::MAX, |_, _| {
+            count += 1;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Answer a replica's read from the local database
+    pub fn serve_read(&self, request: &remote::StorageRead) -> StorageResult<remote::StorageReadResponse> {
+        use remote::{decode_hex, encode_hex, StorageRead, StorageReadResponse};
+        // A replica forwards nothing; only local data is served
+        self.db()?;
+        match request {
+            StorageRead::Get { cf, key } => {
+                Ok(StorageReadResponse::Value(self.get(cf, &decode_hex(key)?)?.as_deref().map(encode_hex)))
+            }
+            StorageRead::Scan { cf, from, reverse, limit } => {
+                let from = from.as_deref().map(decode_hex).transpose()?;
+                let mut items = Vec::new();
+                self.scan(cf, from.as_deref(), *reverse, (*limit).min(remote::MAX_SCAN_PAGE), |key, value| {
+                    items.push((encode_hex(key), encode_hex(value)));
+                    Ok(())
+                })?;
+                Ok(StorageReadResponse::Items(items))
+            }
+            StorageRead::Size => Ok(StorageReadResponse::Size(self.get_database_size()?)),
+        }
+    }
+
+    /// A view whose writes, across every column family, are collected and
+    /// applied in one atomic write by `commit`. Reads through the view see
+    /// only what is already committed.
+    pub fn write_batch(&self) -> StorageResult<Storage> {
+        self.db()?;
+        Ok(Self {
+            backend: self.backend.clone(),
+            batch: Some(Arc::new(Mutex::new(WriteBatch::default()))),
+        })
+    }
+
+    /// Apply the writes collected by a `write_batch` view; nothing to do on
+    /// any other storage, which writes as it goes
+    pub fn commit(&self) -> StorageResult<()> {
+        let Some(batch) = &self.batch else {
+            return Ok(());
+        };
+        let batch = std::mem::take(&mut *batch.lock().unwrap());
+        let _span = tracing::debug_span!("storage_write", operations = batch.len(), bytes = batch.size_in_bytes()).entered();
+        self.db()?.write(batch).map_err(StorageError::RocksDb)
+    }
+
+    // Generic key-value operations
+    pub fn put(&self, cf_name: &str, key: &[u8], value: &[u8]) -> StorageResult<()> {
+        let db = self.db()?;
+        let cf = Self::cf(db, cf_name)?;
+        match &self.batch {
+            Some(batch) => batch.lock().unwrap().put_cf(cf, key, value),
+            None => db.put_cf(cf, key, value).map_err(|e| StorageError::RocksDb(e))?,
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, cf_name: &str, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        match &self.backend {
+            Backend::Local(db) => db.get_cf(Self::cf(db, cf_name)?, key)
+                .map_err(|e| StorageError::RocksDb(e)),
+            Backend::Remote(remote) => remote.get(cf_name, key),
+        }
+    }
+
+    pub fn delete(&self, cf_name: &str, key: &[u8]) -> StorageResult<()> {
+        let db = self.db()?;
+        let cf = Self::cf(db, cf_name)?;
+        match &self.batch {
+            Some(batch) => batch.lock().unwrap().delete_cf(cf, key),
+            None => db.delete_cf(cf, key).map_err(|e| StorageError::RocksDb(e))?,
+        }
+        Ok(())
+    }
+
+    pub fn exists(&self, cf_name: &str, key: &[u8]) -> StorageResult<bool> {
+        Ok(self.get(cf_name, key)?.is_some())
+    }
+
+    /// Write every column family's memtable to disk
+    pub fn flush(&self) -> StorageResult<()> {
+        let db = self.db()?;
+        for cf_name in ALL_COLUMN_FAMILIES {
+            db.flush_cf(Self::cf(db, cf_name)?)
+                .map_err(|e| StorageError::RocksDb(e))?;
+        }
+        Ok(())
+    }
+
+    /// Flush memtables and the WAL to disk and wait for background flushes
+    /// and compactions to stop, so the database closes without recovery work
+    /// on the next open. Remote storage has nothing to close.
+    pub fn close(&self) -> StorageResult<()> {
+        let Backend::Local(db) = &self.backend else {
+            return Ok(());
+        };
+        self.flush()?;
+        db.flush_wal(true)?;
+        db.cancel_all_background_work(true);
+        Ok(())
+    }
+
+    // Block operations
+    pub fn store_block(&self, key: &BlockKey, block_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_BLOCKS, &key.encode(), block_data)
+    }
+
+    pub fn get_block(&self, key: &BlockKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_BLOCKS, &key.encode())
+    }
+
+    /// Serialized size of a stored block, without copying it out of RocksDB
+    pub fn get_block_size(&self, key: &BlockKey) -> StorageResult<Option<u64>> {
+        match &self.backend {
+            Backend::Local(db) => Ok(db.get_pinned_cf(Self::cf(db, CF_BLOCKS)?, key.encode())?
+                .map(|value| value.len() as u64)),
+            Backend::Remote(remote) => Ok(remote.get(CF_BLOCKS, &key.encode())?.map(|value| value.len() as u64)),
+        }
+    }
+
+    pub fn delete_block(&self, key: &BlockKey) -> StorageResult<()> {
+        self.delete(CF_BLOCKS, &key.encode())
+    }
+
+    // Transaction operations
+    pub fn store_transaction(&self, key: &TxKey, tx_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_TRANSACTIONS, &key.encode(), tx_data)
+    }
+
+    pub fn get_transaction(&self, key: &TxKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_TRANSACTIONS, &key.encode())
+    }
+
+    pub fn delete_transaction(&self, key: &TxKey) -> StorageResult<()> {
+        self.delete(CF_TRANSACTIONS, &key.encode())
+    }
+
+    // UTXO operations
+    pub fn store_utxo(&self, key: &UtxoKey, utxo_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_UTXOS, &key.encode(), utxo_data)
+    }
+
+    pub fn get_utxo(&self, key: &UtxoKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_UTXOS, &key.encode())
+    }
+
+    pub fn delete_utxo(&self, key: &UtxoKey) -> StorageResult<()> {
+        self.delete(CF_UTXOS, &key.encode())
+    }
+
+    /// Visit every coin in the UTXO set in key order
+    pub fn for_each_utxo(&self, mut visit: impl FnMut(bitcoin::OutPoint, UtxoEntry)) -> StorageResult<()> {
+        self.scan(CF_UTXOS, None, false, usize::MAX, |key, value| {
+            visit(UtxoKey::decode(key)?.outpoint(), UtxoEntry::decode(value)?);
+            Ok(())
+        })
+    }
+
+    /// Unspent outputs of one transaction, by output index
+    pub fn get_tx_utxos(&self, txid: &bitcoin::Txid) -> StorageResult<Vec<(u32, UtxoEntry)>> {
+        self.iter_prefix(CF_UTXOS, txid.as_byte_array())
+            .map(|item| {
+                let (key, value) = item?;
+                Ok((UtxoKey::decode(&key)?.vout, UtxoEntry::decode(&value)?))
+            })
+            .collect()
+    }
+
+    // Chain state operations
+    pub fn store_chain_state(&self, key: &ChainStateKey, value: &[u8]) -> StorageResult<()> {
+        self.put(CF_CHAIN_STATE, key.as_bytes(), value)
+    }
+
+    pub fn get_chain_state(&self, key: &ChainStateKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_CHAIN_STATE, key.as_bytes())
+    }
+
+    pub fn delete_chain_state(&self, key: &ChainStateKey) -> StorageResult<()> {
+        self.delete(CF_CHAIN_STATE, key.as_bytes())
+    }
+
+    // Mempool operations
+    pub fn store_mempool_tx(&self, key: &TxKey, tx_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_MEMPOOL, &key.encode(), tx_data)
+    }
+
+    pub fn get_mempool_tx(&self, key: &TxKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_MEMPOOL, &key.encode())
+    }
+
+    pub fn delete_mempool_tx(&self, key: &TxKey) -> StorageResult<()> {
+        self.delete(CF_MEMPOOL, &key.encode())
+    }
+
+    pub fn mempool_txids(&self) -> StorageResult<Vec<bitcoin::Txid>> {
+        self.iter_cf(CF_MEMPOOL)
+            .map(|item| Ok(TxKey::decode(&item?.0)?.0))
+            .collect()
+    }
+
+    // UTXO delta operations
+    pub fn store_utxo_delta(&self, height: u64, delta_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_UTXO_DELTAS, &HeightKey(height).encode(), delta_data)
+    }
+
+    pub fn get_utxo_delta(&self, height: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_UTXO_DELTAS, &HeightKey(height).encode())
+    }
+
+    pub fn delete_utxo_delta(&self, height: u64) -> StorageResult<()> {
+        self.delete(CF_UTXO_DELTAS, &HeightKey(height).encode())
+    }
+
+    // Header chain operations
+    pub fn store_header_entry(&self, height: u64, entry: &HeaderEntry) -> StorageResult<()> {
+        self.put(CF_HEADERS, &HeightKey(height).encode(), &entry.encode())
+    }
+
+    pub fn get_header_entry(&self, height: u64) -> StorageResult<Option<HeaderEntry>> {
+        match self.get(CF_HEADERS, &HeightKey(height).encode())? {
+            Some(data) => Ok(Some(HeaderEntry::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_header_entry(&self, height: u64) -> StorageResult<()> {
+        self.delete(CF_HEADERS, &HeightKey(height).encode())
+    }
+
+    // Best header chain: the headers with the most work known, which may
+    // run ahead of the active chain
+    pub fn store_best_header(&self, height: u64, entry: &HeaderEntry) -> StorageResult<()> {
+        self.put(CF_BEST_HEADERS, &HeightKey(height).encode(), &entry.encode())?;
+        // Entries left behind by replaced headers are caught by best_header_height
+        self.put(CF_BEST_HEADER_INDEX, &BlockKey(entry.header.block_hash()).encode(), &height.to_be_bytes())
+    }
+
+    pub fn get_best_header(&self, height: u64) -> StorageResult<Option<HeaderEntry>> {
+        match self.get(CF_BEST_HEADERS, &HeightKey(height).encode())? {
+            Some(data) => Ok(Some(HeaderEntry::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_best_header(&self, height: u64) -> StorageResult<()> {
+        self.delete(CF_BEST_HEADERS, &HeightKey(height).encode())
+    }
+
+    /// Height of a header on the best header chain
+    pub fn best_header_height(&self, hash: &bitcoin::BlockHash) -> StorageResult<Option<u64>> {
+        let Some(data) = self.get(CF_BEST_HEADER_INDEX, &BlockKey(*hash).encode())? else {
+            return Ok(None);
+        };
+        let height = Self::decode_be_u64(&data)?;
+        let current = self.get_best_header(height)?.is_some_and(|entry| entry.header.block_hash() == *hash);
+        Ok(current.then_some(height))
+    }
+
+    /// Height and entry of the best header chain's tip
+    pub fn best_header_tip(&self) -> StorageResult<Option<(u64, HeaderEntry)>> {
+        let mut tip = None;
+        self.scan(CF_BEST_HEADERS, None, true, 1, |key, value| {
+            tip = Some((HeightKey::decode(key)?.0, HeaderEntry::decode(value)?));
+            Ok(())
+        })?;
+        Ok(tip)
+    }
+
+    // Active chain block index: block hash to height
+    pub fn store_block_height(&self, key: &BlockKey, height: u64) -> StorageResult<()> {
+        self.put(CF_BLOCK_INDEX, &key.encode(), &height.to_be_bytes())
+    }
+
+    pub fn get_block_height(&self, key: &BlockKey) -> StorageResult<Option<u64>> {
+        match self.get(CF_BLOCK_INDEX, &key.encode())? {
+            Some(data) => Ok(Some(Self::decode_be_u64(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete_block_height(&self, key: &BlockKey) -> StorageResult<()> {
+        self.delete(CF_BLOCK_INDEX, &key.encode())
+    }
+
+    // Stale block archive, keyed by block hash
+    pub fn store_stale_block(&self, key: &BlockKey, record_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_STALE_BLOCKS, &key.encode(), record_data)
+    }
+
+    pub fn get_stale_block(&self, key: &BlockKey) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_STALE_BLOCKS, &key.encode())
+    }
+
+    pub fn delete_stale_block(&self, key: &BlockKey) -> StorageResult<()> {
+        self.delete(CF_STALE_BLOCKS, &key.encode())
+    }
+
+    pub fn get_stale_blocks(&self) -> StorageResult<Vec<Vec<u8>>> {
+        let mut records = Vec::new();
+        self.scan(CF_STALE_BLOCKS, None, false, usize::MAX, |_, value| {
+            records.push(value.to_vec());
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    // Reorg history, keyed by big-endian sequence number
+    pub fn store_reorg(&self, seq: u64, record_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_REORGS, &HeightKey(seq).encode(), record_data)
+    }
+
+    /// Up to `limit` reorg records, newest first
+    pub fn get_recent_reorgs(&self, limit: usize) -> StorageResult<Vec<(u64, Vec<u8>)>> {
+        let mut records = Vec::new();
+        self.scan(CF_REORGS, None, true, limit, |key, value| {
+            records.push((HeightKey::decode(key)?.0, value.to_vec()));
+            Ok(())
+        })?;
+        Ok(records)
+    }
+
+    // Silent payment tweaks, keyed by block height
+    pub fn store_silent_payment_tweaks(&self, height: u64, tweak_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_SILENT_PAYMENT_TWEAKS, &HeightKey(height).encode(), tweak_data)
+    }
+
+    pub fn get_silent_payment_tweaks(&self, height: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_SILENT_PAYMENT_TWEAKS, &HeightKey(height).encode())
+    }
+
+    pub fn delete_silent_payment_tweaks(&self, height: u64) -> StorageResult<()> {
+        self.delete(CF_SILENT_PAYMENT_TWEAKS, &HeightKey(height).encode())
+    }
+
+    pub fn store_tx_location(&self, key: &TxKey, location: &TxLocation) -> StorageResult<()> {
+        self.put(CF_TX_INDEX, &key.encode(), &location.encode())
+    }
+
+    pub fn delete_tx_location(&self, key: &TxKey) -> StorageResult<()> {
+        self.delete(CF_TX_INDEX, &key.encode())
+    }
+
+    pub fn get_tx_location(&self, key: &TxKey) -> StorageResult<Option<TxLocation>> {
+        match self.get(CF_TX_INDEX, &key.encode())? {
+            Some(data) => Ok(Some(TxLocation::decode(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Address index, keyed by script hash then height
+    pub fn store_address_entry(&self, key: &AddressKey, entry: &AddressEntry) -> StorageResult<()> {
+        self.put(CF_ADDRESS_INDEX, &key.encode(), &entry.encode())
+    }
+
+    pub fn delete_address_entry(&self, key: &AddressKey) -> StorageResult<()> {
+        self.delete(CF_ADDRESS_INDEX, &key.encode())
+    }
+
+    /// Visit the index entries of a script at `max_height` and below, newest
+    /// first, until `visit` returns false
+    pub fn for_each_address_entry(
+        &self,
+        script_hash: &[u8; 32],
+        max_height: u64,
+        mut visit: impl FnMut(AddressKey, AddressEntry) -> StorageResult<bool>,
+    ) -> StorageResult<()> {
+        let mut from = AddressKey::height_end(script_hash, max_height);
+        // A reverse scan includes its starting key, visited at the end of the previous batch
+        let mut visited = None;
+        loop {
+            let mut batch = Vec::new();
+            self.scan(CF_ADDRESS_INDEX, Some(&from), true, ADDRESS_SCAN_BATCH, |key, value| {
+                batch.push((key.to_vec(), value.to_vec()));
+                Ok(())
+            })?;
+            let exhausted = batch.len() < ADDRESS_SCAN_BATCH;
+            for (key, value) in batch {
+                if visited.as_ref() == Some(&key) {
+                    continue;
+                }
+                if !key.starts_with(script_hash) {
+                    return Ok(());
+                }
+                if !visit(AddressKey::decode(&key)?, AddressEntry::decode(&value)?)? {
+                    return Ok(());
+                }
+                visited = Some(key);
+            }
+            match &visited {
+                Some(last) if !exhausted => from = last.clone(),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // Peer operations
+    // Chain analytics operations
+    pub fn store_block_stats(&self, height: u64, stats_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_STATS, &StatsKey::Block(height).encode(), stats_data)
+    }
+
+    pub fn get_block_stats(&self, height: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_STATS, &StatsKey::Block(height).encode())
+    }
+
+    pub fn delete_block_stats(&self, height: u64) -> StorageResult<()> {
+        self.delete(CF_STATS, &StatsKey::Block(height).encode())
+    }
+
+    pub fn store_daily_stats(&self, day: u32, stats_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_STATS, &StatsKey::Day(day).encode(), stats_data)
+    }
+
+    pub fn get_daily_stats(&self, day: u32) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_STATS, &StatsKey::Day(day).encode())
+    }
+
+    pub fn store_block_fee_rates(&self, time: u32, height: u64, data: &[u8]) -> StorageResult<()> {
+        self.put(CF_STATS, &StatsKey::BlockFeeRates { time, height: height as u32 }.encode(), data)
+    }
+
+    pub fn delete_block_fee_rates(&self, time: u32, height: u64) -> StorageResult<()> {
+        self.delete(CF_STATS, &StatsKey::BlockFeeRates { time, height: height as u32 }.encode())
+    }
+
+    /// Block fee rates for blocks timestamped from `from` to `to` inclusive, oldest first
+    pub fn get_block_fee_rates(&self, from: u32, to: u32) -> StorageResult<Vec<Vec<u8>>> {
+        self.stats_range(
+            StatsKey::BlockFeeRates { time: from, height: 0 },
+            StatsKey::BlockFeeRates { time: to, height: u32::MAX },
+        )
+    }
+
+    pub fn store_mempool_fee(&self, time: u64, data: &[u8]) -> StorageResult<()> {
+        self.put(CF_STATS, &StatsKey::MempoolFee(time).encode(), data)
+    }
+
+    /// Mempool fee samples taken from `from` to `to` inclusive, oldest first
+    pub fn get_mempool_fees(&self, from: u64, to: u64) -> StorageResult<Vec<Vec<u8>>> {
+        self.stats_range(StatsKey::MempoolFee(from), StatsKey::MempoolFee(to))
+    }
+
+    /// Drop fee rate history older than `time`
+    pub fn prune_fee_history_before(&self, time: u64) -> StorageResult<()> {
+        let db = self.db()?;
+        let cf = Self::cf(db, CF_STATS)?;
+        let block_cutoff = u32::try_from(time).unwrap_or(u32::MAX);
+        db.delete_range_cf(
+            cf,
+            StatsKey::BlockFeeRates { time: 0, height: 0 }.encode(),
+            StatsKey::BlockFeeRates { time: block_cutoff, height: 0 }.encode(),
+        ).map_err(StorageError::RocksDb)?;
+        db.delete_range_cf(cf, StatsKey::MempoolFee(0).encode(), StatsKey::MempoolFee(time).encode())
+            .map_err(StorageError::RocksDb)
+    }
+
+    /// Values of the `CF_STATS` records from `start` to `end` inclusive, read a page at a time
+    fn stats_range(&self, start: StatsKey, end: StatsKey) -> StorageResult<Vec<Vec<u8>>> {
+        let (mut from, end) = (start.encode(), end.encode());
+        let mut values = Vec::new();
+        loop {
+            let (mut read, mut last, mut past_end) = (0, None, false);
+            self.scan(CF_STATS, Some(&from), false, ITER_PAGE, |key, value| {
+                read += 1;
+                if key <= end.as_slice() {
+                    values.push(value.to_vec());
+                    last = Some(key.to_vec());
+                } else {
+                    past_end = true;
+                }
+                Ok(())
+            })?;
+            match last {
+                Some(key) if read == ITER_PAGE && !past_end => {
+                    // The smallest key after the last one read
+                    from = [key.as_slice(), &[0]].concat();
+                }
+                _ => return Ok(values),
+            }
+        }
+    }
+
+    // Peer timeline operations, keyed by big-endian sequence number
+    pub fn store_peer_event(&self, seq: u64, event_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_PEER_EVENTS, &HeightKey(seq).encode(), event_data)
+    }
+
+    /// Up to `limit` events with sequence numbers of at least `from_seq`, oldest first
+    pub fn get_peer_events(&self, from_seq: u64, limit: usize) -> StorageResult<Vec<(u64, Vec<u8>)>> {
+        let start = HeightKey(from_seq).encode();
+        let mut events = Vec::new();
+        self.scan(CF_PEER_EVENTS, Some(&start), false, limit, |key, value| {
+            events.push((HeightKey::decode(key)?.0, value.to_vec()));
+            Ok(())
+        })?;
+        Ok(events)
+    }
+
+    pub fn last_peer_event_seq(&self) -> StorageResult<Option<u64>> {
+        let mut last = None;
+        self.scan(CF_PEER_EVENTS, None, true, 1, |key, _| {
+            last = Some(HeightKey::decode(key)?.0);
+            Ok(())
+        })?;
+        Ok(last)
+    }
+
+    /// Drop every event with a sequence number below `seq`
+    pub fn prune_peer_events_before(&self, seq: u64) -> StorageResult<()> {
+        let db = self.db()?;
+        db.delete_range_cf(Self::cf(db, CF_PEER_EVENTS)?, HeightKey(0).encode(), HeightKey(seq).encode())
+            .map_err(StorageError::RocksDb)
+    }
+
+    // Event journal operations, keyed by the event's big-endian sequence number
+    pub fn store_event(&self, seq: u64, event_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_EVENTS, &HeightKey(seq).encode(), event_data)
+    }
+
+    /// Up to `limit` journaled events with sequence numbers of at least `from_seq`, oldest first
+    pub fn get_events(&self, from_seq: u64, limit: usize) -> StorageResult<Vec<(u64, Vec<u8>)>> {
+        let start = HeightKey(from_seq).encode();
+        let mut events = Vec::new();
+        self.scan(CF_EVENTS, Some(&start), false, limit, |key, value| {
+            events.push((HeightKey::decode(key)?.0, value.to_vec()));
+            Ok(())
+        })?;
+        Ok(events)
+    }
+
+    pub fn last_event_seq(&self) -> StorageResult<Option<u64>> {
+        let mut last = None;
+        self.scan(CF_EVENTS, None, true, 1, |key, _| {
+            last = Some(HeightKey::decode(key)?.0);
+            Ok(())
+        })?;
+        Ok(last)
+    }
+
+    /// Drop every journaled event with a sequence number below `seq`
+    pub fn prune_events_before(&self, seq: u64) -> StorageResult<()> {
+        let db = self.db()?;
+        db.delete_range_cf(Self::cf(db, CF_EVENTS)?, HeightKey(0).encode(), HeightKey(seq).encode())
+            .map_err(StorageError::RocksDb)
+    }
+
+    fn decode_be_u64(data: &[u8]) -> StorageResult<u64> {
+        let bytes: [u8; 8] = data.try_into().map_err(|_| StorageError::Corruption {
+            component: format!("expected 8 byte integer, found {} bytes", data.len()),
+        })?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Cached answer of a DNS seed, kept in `CF_PEERS` next to peer records
+    pub fn store_dns_seed(&self, seed: &str, record: &[u8]) -> StorageResult<()> {
+        self.put(CF_PEERS, &PeerKey::DnsSeed(seed.to_string()).encode(), record)
+    }
+
+    pub fn get_dns_seed(&self, seed: &str) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_PEERS, &PeerKey::DnsSeed(seed.to_string()).encode())
+    }
+
+    pub fn store_peer_info(&self, peer_id: &str, peer_data: &[u8]) -> StorageResult<()> {
+        self.put(CF_PEERS, &PeerKey::Peer(peer_id.to_string()).encode(), peer_data)
+    }
+
+    pub fn get_peer_info(&self, peer_id: &str) -> StorageResult<Option<Vec<u8>>> {
+        self.get(CF_PEERS, &PeerKey::Peer(peer_id.to_string()).encode())
+    }
+
+    pub fn delete_peer_info(&self, peer_id: &str) -> StorageResult<()> {
+        self.delete(CF_PEERS, &PeerKey::Peer(peer_id.to_string()).encode())
+    }
+
+    // Utility methods
+    pub fn get_database_size(&self) -> StorageResult<u64> {
+        let db = match &self.backend {
+            Backend::Local(db) => db,
+            Backend::Remote(remote) => return remote.size(),
+        };
+        // Get approximate size of all column families
+        let mut total_size = 0u64;
+
+        for cf_name in ALL_COLUMN_FAMILIES {
+            if let Ok(cf) = Self::cf(db, cf_name) {
+                if let Ok(Some(size_str)) = db.property_value_cf(cf, "rocksdb.total-sst-files-size") {
+                    if let Ok(size) = size_str.parse::<u64>() {
+                        total_size += size;
+                    }
+                }
+            }
+        }
+
+        Ok(total_size)
+    }
+
+    /// RocksDB's estimate of the keys in a column family, `None` on a replica
+    pub fn estimate_num_keys(&self, cf_name: &str) -> StorageResult<Option<u64>> {
+        let db = match &self.backend {
+            Backend::Local(db) => db,
+            Backend::Remote(_) => return Ok(None),
+        };
+        db.property_int_value_cf(Self::cf(db, cf_name)?, "rocksdb.estimate-num-keys")
+            .map_err(StorageError::RocksDb)
+    }
+
+    /// Memory held by a column family's memtables, the writes not yet flushed
+    pub fn memtable_bytes(&self, cf_name: &str) -> StorageResult<Option<u64>> {
+        let db = match &self.backend {
+            Backend::Local(db) => db,
+            Backend::Remote(_) => return Ok(None),
+        };
+        db.property_int_value_cf(Self::cf(db, cf_name)?, "rocksdb.cur-size-all-mem-tables")
+            .map_err(StorageError::RocksDb)
+    }
+
+    pub fn compact(&self) -> StorageResult<()> {
+        for cf_name in ALL_COLUMN_FAMILIES {
+            self.compact_cf(cf_name)?;
+        }
+        info!("Database compaction completed");
+        Ok(())
+    }
+
+    /// Compact one column family end to end. Blocks until done, but lets
+    /// RocksDB's automatic compactions keep running alongside.
+    pub fn compact_cf(&self, cf_name: &str) -> StorageResult<()> {
+        let db = self.db()?;
+        let mut options = rocksdb::CompactOptions::default();
+        options.set_exclusive_manual_compaction(false);
+        db.compact_range_cf_opt(Self::cf(db, cf_name)?, None::<&[u8]>, None::<&[u8]>, &options);
+        Ok(())
+    }
+
+    /// Back the database up into `backup_path` and drop all but the newest
+    /// `keep` backups. Backups are incremental: SST files already in an
+    /// earlier backup are not copied again.
+    pub fn backup(&self, backup_path: &Path, keep: usize) -> StorageResult<BackupInfo> {
+        let db = self.db()?;
+        std::fs::create_dir_all(backup_path)
+            .map_err(|e| StorageError::DatabaseNotFound {
+                path: format!("Failed to create backup directory {}: {}", backup_path.display(), e)
+            })?;
+
+        let mut engine = open_backup_engine(backup_path)?;
+        // Flushing first puts the memtables in the backup rather than leaving them to the WAL
+        engine.create_new_backup_flush(db, true)?;
+        engine.purge_old_backups(keep.max(1))?;
+        let latest = engine.get_backup_info().into_iter()
+            .max_by_key(|info| info.backup_id)
+            .ok_or_else(|| StorageError::Corruption {
+                component: format!("backups in {}", backup_path.display()),
+            })?;
+        engine.verify_backup(latest.backup_id)?;
+
+        info!("Backup {} created at {:?}", latest.backup_id, backup_path);
+        Ok(BackupInfo::from(latest))
+    }
+
+    /// Backups in `backup_path`, oldest first
+    pub fn list_backups(backup_path: &Path) -> StorageResult<Vec<BackupInfo>> {
+        let engine = open_backup_engine(backup_path)?;
+        let mut backups: Vec<BackupInfo> = engine.get_backup_info().into_iter().map(BackupInfo::from).collect();
+        backups.sort_by_key(|backup| backup.id);
+        Ok(backups)
+    }
+
+    /// Replace the database at `config.rocks_db_path` with a backup, the newest
+    /// one unless `backup_id` is given. The database must not be open.
+    pub fn restore_from_backup(config: &StorageConfig, backup_id: Option<u32>) -> StorageResult<BackupInfo> {
+        let backup_path = config.backup_path();
+        let mut engine = open_backup_engine(&backup_path)?;
+        let backup = engine.get_backup_info().into_iter()
+            .filter(|info| backup_id.is_none_or(|id| info.backup_id == id))
+            .max_by_key(|info| info.backup_id)
+            .ok_or_else(|| StorageError::DatabaseNotFound {
+                path: match backup_id {
+                    Some(id) => format!("backup {} in {}", id, backup_path.display()),
+                    None => format!("any backup in {}", backup_path.display()),
+                },
+            })?;
+        engine.verify_backup(backup.backup_id)?;
+
+        let path = &config.rocks_db_path;
+        let options = rocksdb::backup::RestoreOptions::default();
+        engine.restore_from_backup(path, path, &options, backup.backup_id)?;
+        info!("Restored backup {} from {:?} into {:?}", backup.backup_id, backup_path, path);
+        Ok(BackupInfo::from(backup))
+    }
+
+    /// Every key/value pair in every column family
+    pub fn export_column_families(&self) -> StorageResult<ColumnFamilyDump> {
+        let mut dump = BTreeMap::new();
+        for cf_name in ALL_COLUMN_FAMILIES {
+            let mut records = Vec::new();
+            self.scan(cf_name, None, false, usize::MAX, |key, value| {
+                records.push((key.to_vec(), value.to_vec()));
+                Ok(())
+            })?;
+            dump.insert(cf_name.to_string(), records);
+        }
+        Ok(dump)
+    }
+
+    /// Replace the whole database contents with `dump` in a single write.
+    /// Column families missing from the dump end up empty.
+    pub fn import_column_families(&self, dump: &ColumnFamilyDump) -> StorageResult<()> {
+        if let Some(unknown) = dump.keys().find(|name| !ALL_COLUMN_FAMILIES.contains(&name.as_str())) {
+            return Err(StorageError::Corruption { component: format!("Column family '{}' not found", unknown) });
+        }
+
+        let db = self.db()?;
+        let mut batch = WriteBatch::default();
+        for cf_name in ALL_COLUMN_FAMILIES {
+            let cf = Self::cf(db, cf_name)?;
+            for item in db.iterator_cf(cf, IteratorMode::Start) {
+                let (key, _) = item.map_err(StorageError::RocksDb)?;
+                batch.delete_cf(&cf, key);
+            }
+            for (key, value) in dump.get(*cf_name).into_iter().flatten() {
+                batch.put_cf(&cf, key, value);
+            }
+        }
+        db.write(batch).map_err(StorageError::RocksDb)?;
+        info!("Imported {} column families", dump.len());
+        Ok(())
+    }
+
+    // Helper method to get column family handle
+    fn cf<'a>(db: &'a DB, cf_name: &str) -> StorageResult<&'a ColumnFamily> {
+        db.cf_handle(cf_name)
+            .ok_or_else(|| StorageError::Corruption {
+                component: format!("Column family '{}' not found", cf_name)
+            })
+    }
+}
+
+impl Clone for Storage {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            batch: self.batch.clone(),
+        }
+    }
+}
+
+// Storage statistics
+#[derive(Debug, Clone)]
+pub struct StorageStats {
+    pub total_size_bytes: u64,
+    pub block_count: u64,
+    pub transaction_count: u64,
+    pub utxo_count: u64,
+    pub mempool_count: u64,
+    pub peer_count: u64,
+}
+
+impl Storage {
+    /// Counts every column family involved, so this takes about as long as
+    /// a scan of the UTXO set
+    pub fn get_stats(&self) -> StorageResult<StorageStats> {
+        let mut peer_count = 0;
+        // Cached DNS seed answers share the column family with peers
+        self.scan(CF_PEERS, None, false, usize::MAX, |key, _| {
+            if !key.starts_with(PeerKey::DNS_SEED_PREFIX) {
+                peer_count += 1;
+            }
+            Ok(())
+        })?;
+
+        Ok(StorageStats {
+            total_size_bytes: self.get_database_size()?,
+            block_count: self.count_cf(CF_BLOCKS)?,
+            transaction_count: self.count_cf(CF_TRANSACTIONS)?,
+            utxo_count: self.count_cf(CF_UTXOS)?,
+            mempool_count: self.count_cf(CF_MEMPOOL)?,
+            peer_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use bitcoin::hashes::Hash;
+    use tempfile::TempDir;
+
+    fn block_key(byte: u8) -> BlockKey {
+        BlockKey(bitcoin::BlockHash::from_byte_array([byte; 32]))
+    }
+
+    fn tx_key(byte: u8) -> TxKey {
+        TxKey(bitcoin::Txid::from_byte_array([byte; 32]))
+    }
+
+    fn create_test_storage() -> (Storage, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+
+        let storage = Storage::new(&config.storage).unwrap();
+        (storage, temp_dir)
+    }
+
+    #[test]
+    fn test_storage_initialization() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Test basic operations
+        let key = b"test_key";
+        let value = b"test_value";
+
+        storage.put("blocks", key, value).unwrap();
+        let retrieved = storage.get("blocks", key).unwrap();
+
+        assert_eq!(retrieved, Some(value.to_vec()));
+    }
+
+    #[test]
+    fn test_write_batch_commits_atomically() {
+        let (storage, _temp_dir) = create_test_storage();
+        storage.store_block(&block_key(1), b"old").unwrap();
+
+        let batch = storage.write_batch().unwrap();
+        batch.store_block(&block_key(2), b"block").unwrap();
+        batch.store_transaction(&tx_key(3), b"tx").unwrap();
+        batch.delete_block(&block_key(1)).unwrap();
+        // Nothing is visible until the commit, through the view or otherwise
+        assert_eq!(batch.get_block(&block_key(2)).unwrap(), None);
+        assert_eq!(storage.get_block(&block_key(1)).unwrap(), Some(b"old".to_vec()));
+
+        batch.commit().unwrap();
+        assert_eq!(storage.get_block(&block_key(2)).unwrap(), Some(b"block".to_vec()));
+        assert_eq!(storage.get_transaction(&tx_key(3)).unwrap(), Some(b"tx".to_vec()));
+        assert_eq!(storage.get_block(&block_key(1)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_block_size() {
+        let (storage, _temp_dir) = create_test_storage();
+        storage.store_block(&block_key(1), &[0u8; 285]).unwrap();
+        assert_eq!(storage.get_block_size(&block_key(1)).unwrap(), Some(285));
+        assert_eq!(storage.get_block_size(&block_key(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::test_config();
+        config.storage.rocks_db_path = temp_dir.path().join("rocksdb");
+        assert_eq!(config.storage.backup_path(), temp_dir.path().join("backups"));
+
+        let storage = Storage::new(&config.storage).unwrap();
+        storage.store_block(&block_key(1), b"first").unwrap();
+        let first = storage.backup(&config.storage.backup_path(), 2).unwrap();
+        storage.store_block(&block_key(2), b"second").unwrap();
+        storage.backup(&config.storage.backup_path(), 2).unwrap();
+        storage.store_block(&block_key(3), b"third").unwrap();
+        let latest = storage.backup(&config.storage.backup_path(), 2).unwrap();
+
+        // Only the newest two are kept
+        let backups = Storage::list_backups(&config.storage.backup_path()).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups.last(), Some(&latest));
+        assert!(!backups.iter().any(|backup| backup.id == first.id));
+        drop(storage);
+
+        assert!(Storage::restore_from_backup(&config.storage, Some(first.id)).is_err());
+        let restored = Storage::restore_from_backup(&config.storage, Some(backups[0].id)).unwrap();
+        assert_eq!(restored, backups[0]);
+        let storage = Storage::new(&config.storage).unwrap();
+        assert_eq!(storage.get_block(&block_key(2)).unwrap(), Some(b"second".to_vec()));
+        assert_eq!(storage.get_block(&block_key(3)).unwrap(), None);
+        drop(storage);
+
+        Storage::restore_from_backup(&config.storage, None).unwrap();
+        let storage = Storage::new(&config.storage).unwrap();
+        assert_eq!(storage.get_block(&block_key(3)).unwrap(), Some(b"third".to_vec()));
+    }
+
+    #[test]
+    fn test_column_family_export_import() {
+        let (source, _source_dir) = create_test_storage();
+        let (target, _target_dir) = create_test_storage();
+        source.store_block(&block_key(1), b"block").unwrap();
+        source.store_chain_state(&ChainStateKey::Tip, b"tip").unwrap();
+        target.store_utxo(&UtxoKey::new(tx_key(9).0, 0), b"coin").unwrap();
+
+        let dump = source.export_column_families().unwrap();
+        target.import_column_families(&dump).unwrap();
+
+        assert_eq!(target.get_block(&block_key(1)).unwrap(), Some(b"block".to_vec()));
+        assert_eq!(target.get_chain_state(&ChainStateKey::Tip).unwrap(), Some(b"tip".to_vec()));
+        assert_eq!(target.get_utxo(&UtxoKey::new(tx_key(9).0, 0)).unwrap(), None);
+        assert_eq!(target.export_column_families().unwrap(), dump);
+    }
+
+    #[test]
+    fn test_fee_history_ranges_and_pruning() {
+        let (storage, _temp_dir) = create_test_storage();
+        // More samples than one page, next to block stats and block fee rates
+        for time in 0..(ITER_PAGE as u64 + 10) {
+            storage.store_mempool_fee(1_000 + time, &time.to_be_bytes()).unwrap();
+        }
+        storage.store_block_stats(5, b"stats").unwrap();
+        storage.store_block_fee_rates(1_500, 5, b"five").unwrap();
+        storage.store_block_fee_rates(1_400, 6, b"six").unwrap();
+
+        let samples = storage.get_mempool_fees(1_000, 1_000 + ITER_PAGE as u64 + 5).unwrap();
+        assert_eq!(samples.len(), ITER_PAGE + 6);
+        assert_eq!(samples.last().unwrap(), &(ITER_PAGE as u64 + 5).to_be_bytes());
+        // Ordered by time rather than height
+        assert_eq!(storage.get_block_fee_rates(0, u32::MAX).unwrap(), vec![b"six".to_vec(), b"five".to_vec()]);
+        assert_eq!(storage.get_block_fee_rates(1_450, 2_000).unwrap(), vec![b"five".to_vec()]);
+
+        storage.prune_fee_history_before(1_450).unwrap();
+        assert_eq!(storage.get_block_fee_rates(0, u32::MAX).unwrap(), vec![b"five".to_vec()]);
+        assert_eq!(storage.get_mempool_fees(0, u64::MAX).unwrap().len(), ITER_PAGE + 10 - 450);
+        assert_eq!(storage.get_block_stats(5).unwrap().as_deref(), Some(&b"stats"[..]));
+    }
+
+    #[test]
+    fn test_storage_block_operations() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Create a dummy block hash and data
+        let block_hash = block_key(0);
+        let block_data = b"dummy_block_data";
+
+        // Store block
+        storage.store_block(&block_hash, block_data).unwrap();
+
+        // Retrieve block
+        let retrieved = storage.get_block(&block_hash).unwrap();
+        assert_eq!(retrieved, Some(block_data.to_vec()));
+
+        // Test non-existent block
+        let non_existent_hash = block_key(1);
+        let not_found = storage.get_block(&non_existent_hash).unwrap();
+        assert_eq!(not_found, None);
+    }
+
+    #[test]
+    fn test_storage_transaction_operations() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Create a dummy transaction hash and data
+        let txid = tx_key(0);
+        let tx_data = b"dummy_transaction_data";
+
+        // Store transaction
+        storage.store_transaction(&txid, tx_data).unwrap();
+
+        // Retrieve transaction
+        let retrieved = storage.get_transaction(&txid).unwrap();
+        assert_eq!(retrieved, Some(tx_data.to_vec()));
+    }
+
+    #[test]
+    fn test_storage_utxo_operations() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Create a dummy outpoint and UTXO data
+        let outpoint = &UtxoKey::new(tx_key(0).0, 0);
+        let utxo_data = b"dummy_utxo_data";
+
+        // Store UTXO
+        storage.store_utxo(outpoint, utxo_data).unwrap();
+
+        // Retrieve UTXO
+        let retrieved = storage.get_utxo(outpoint).unwrap();
+        assert_eq!(retrieved, Some(utxo_data.to_vec()));
+
+        // Delete UTXO
+        storage.delete_utxo(outpoint).unwrap();
+        let deleted = storage.get_utxo(outpoint).unwrap();
+        assert_eq!(deleted, None);
+    }
+
+    #[test]
+    fn test_utxo_entry_roundtrip() {
+        use bitcoin::hashes::Hash;
+
+        let entry = UtxoEntry {
+            height: 840_000,
+            is_coinbase: true,
+            output: bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(312_500_000),
+                script_pubkey: bitcoin::ScriptBuf::from_bytes(vec![0x51]),
+            },
+        };
+
+        let decoded = UtxoEntry::decode(&entry.encode()).unwrap();
+        assert_eq!(decoded, entry);
+        assert!(UtxoEntry::decode(&[0u8; 4]).is_err());
+
+        let outpoint = bitcoin::OutPoint::new(bitcoin::Txid::from_byte_array([3u8; 32]), 258);
+        assert_eq!(UtxoKey::decode(&UtxoKey::from(outpoint).encode()).unwrap().outpoint(), outpoint);
+    }
+
+    #[test]
+    fn test_tx_location_roundtrip() {
+        use bitcoin::hashes::Hash;
+
+        let location = TxLocation {
+            block_hash: bitcoin::BlockHash::from_byte_array([7u8; 32]),
+            height: 123_456,
+            position: 42,
+        };
+
+        assert_eq!(TxLocation::decode(&location.encode()).unwrap(), location);
+        assert!(TxLocation::decode(&[0u8; 40]).is_err());
+    }
+
+    #[test]
+    fn test_header_entry_roundtrip() {
+        let header = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).header;
+        let entry = HeaderEntry {
+            header,
+            chain_work: header.work() + header.work(),
+        };
+
+        assert_eq!(HeaderEntry::decode(&entry.encode()).unwrap(), entry);
+        assert!(HeaderEntry::decode(&[0u8; 80]).is_err());
+    }
+
+    #[test]
+    fn test_best_header_tip_is_the_highest_entry() {
+        let (storage, _temp_dir) = create_test_storage();
+        assert_eq!(storage.best_header_tip().unwrap(), None);
+
+        let header = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).header;
+        let entry = HeaderEntry { header, chain_work: header.work() };
+        for height in [0, 255, 256] {
+            storage.store_best_header(height, &entry).unwrap();
+        }
+        assert_eq!(storage.best_header_tip().unwrap(), Some((256, entry)));
+        storage.delete_best_header(256).unwrap();
+        assert_eq!(storage.best_header_tip().unwrap().map(|(height, _)| height), Some(255));
+        assert_eq!(storage.get_best_header(0).unwrap(), Some(entry));
+        // The index keeps the height stored last, and trusts it only while that entry stands
+        assert_eq!(storage.best_header_height(&header.block_hash()).unwrap(), None);
+        storage.store_best_header(7, &entry).unwrap();
+        assert_eq!(storage.best_header_height(&header.block_hash()).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn test_height_keys_sort_numerically() {
+        assert!(HeightKey(255).encode() < HeightKey(256).encode());
+        assert!(HeightKey(1).encode() < HeightKey(1_000_000).encode());
+    }
+
+    #[test]
+    fn test_peer_event_log() {
+        let (storage, _temp_dir) = create_test_storage();
+        assert_eq!(storage.last_peer_event_seq().unwrap(), None);
+
+        for seq in [1u64, 2, 300] {
+            storage.store_peer_event(seq, format!("event {}", seq).as_bytes()).unwrap();
+        }
+        assert_eq!(storage.last_peer_event_seq().unwrap(), Some(300));
+
+        let events = storage.get_peer_events(2, 10).unwrap();
+        assert_eq!(events, vec![(2, b"event 2".to_vec()), (300, b"event 300".to_vec())]);
+
+        storage.prune_peer_events_before(300).unwrap();
+        let events = storage.get_peer_events(0, 10).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, 300);
+    }
+
+    #[test]
+    fn test_reorg_archive() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        storage.store_block_height(&block_key(7), 42).unwrap();
+        assert_eq!(storage.get_block_height(&block_key(7)).unwrap(), Some(42));
+        storage.delete_block_height(&block_key(7)).unwrap();
+        assert_eq!(storage.get_block_height(&block_key(7)).unwrap(), None);
+
+        storage.store_stale_block(&block_key(1), b"stale 1").unwrap();
+        storage.store_stale_block(&block_key(2), b"stale 2").unwrap();
+        assert_eq!(storage.get_stale_blocks().unwrap().len(), 2);
+        storage.delete_stale_block(&block_key(1)).unwrap();
+        assert_eq!(storage.get_stale_blocks().unwrap(), vec![b"stale 2".to_vec()]);
+
+        for seq in [1u64, 2, 3] {
+            storage.store_reorg(seq, format!("reorg {}", seq).as_bytes()).unwrap();
+        }
+        let reorgs = storage.get_recent_reorgs(2).unwrap();
+        assert_eq!(reorgs, vec![(3, b"reorg 3".to_vec()), (2, b"reorg 2".to_vec())]);
+    }
+
+    #[test]
+    fn test_iteration_pages_and_prefixes() {
+        let (storage, _temp_dir) = create_test_storage();
+        // More than a page, across two transactions
+        for vout in 0..1200 {
+            storage.store_utxo(&UtxoKey::new(tx_key(1).0, vout), b"a").unwrap();
+        }
+        for vout in 0..3 {
+            storage.store_utxo(&UtxoKey::new(tx_key(2).0, vout), b"b").unwrap();
+        }
+        storage.store_mempool_tx(&tx_key(5), b"tx").unwrap();
+        storage.store_mempool_tx(&tx_key(6), b"tx").unwrap();
+        storage.store_dns_seed("seed.example", b"record").unwrap();
+
+        let keys: Vec<Vec<u8>> = storage.iter_cf(CF_UTXOS).map(|item| item.unwrap().0).collect();
+        assert_eq!(keys.len(), 1203);
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(storage.count_cf(CF_UTXOS).unwrap(), 1203);
+
+        let prefixed: Vec<_> = storage.iter_prefix(CF_UTXOS, tx_key(2).0.as_byte_array()).map(Result::unwrap).collect();
+        assert_eq!(prefixed.len(), 3);
+        assert!(prefixed.iter().all(|(_, value)| value == b"b"));
+        assert_eq!(storage.iter_prefix(CF_UTXOS, &[9; 32]).count(), 0);
+        assert_eq!(storage.mempool_txids().unwrap(), vec![tx_key(5).0, tx_key(6).0]);
+
+        let stats = storage.get_stats().unwrap();
+        assert_eq!((stats.utxo_count, stats.mempool_count, stats.peer_count, stats.block_count), (1203, 2, 0, 0));
+    }
+
+    #[test]
+    fn test_storage_mempool_operations() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Create dummy mempool transaction
+        let txid = tx_key(1);
+        let tx_data = b"mempool_transaction_data";
+
+        // Store in mempool
+        storage.store_mempool_tx(&txid, tx_data).unwrap();
+
+        // Retrieve from mempool
+        let retrieved = storage.get_mempool_tx(&txid).unwrap();
+        assert_eq!(retrieved, Some(tx_data.to_vec()));
+
+        // Delete from mempool
+        storage.delete_mempool_tx(&txid).unwrap();
+        let deleted = storage.get_mempool_tx(&txid).unwrap();
+        assert_eq!(deleted, None);
+    }
+
+    #[test]
+    fn test_storage_exists() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        let key = b"test_exists_key";
+        let value = b"test_exists_value";
+
+        // Key should not exist initially
+        assert!(!storage.exists("blocks", key).unwrap());
+
+        // Store the key-value pair
+        storage.put("blocks", key, value).unwrap();
+
+        // Key should now exist
+        assert!(storage.exists("blocks", key).unwrap());
+    }
+
+    #[test]
+    fn test_storage_stats() {
+        let (storage, _temp_dir) = create_test_storage();
+
+        // Get storage statistics
+        let stats = storage.get_stats().unwrap();
+
+        // Should have zero counts initially
+        assert_eq!(stats.block_count, 0);
+        assert_eq!(stats.transaction_count, 0);
+        assert_eq!(stats.utxo_count, 0);
+        assert_eq!(stats.mempool_count, 0);
+        assert_eq!(stats.peer_count, 0);
+    }
+
+    #[cfg(feature = "http-client")]
+    #[test]
+    fn test_remote_storage_reads_through() {
+        use actix_web::{web, App, HttpResponse, HttpServer};
+
+        let (local, _temp_dir) = create_test_storage();
+        // More than a page, so scans have to continue
+        for seq in 0..(remote::MAX_SCAN_PAGE as u64 + 200) {
+            local.store_peer_event(seq, &seq.to_be_bytes()).unwrap();
+        }
+        for seq in 0..3 {
+            local.store_reorg(seq, &[seq as u8]).unwrap();
+        }
+        local.store_block(&block_key(1), b"block").unwrap();
+
+        let served = local.clone();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // Its own thread, as the replica blocks on each read
+        std::thread::spawn(move || {
+            actix_rt::System::new().block_on(async move {
+                HttpServer::new(move || {
+                    let served = served.clone();
+                    App::new().route("/api/v1/storage/read", web::post().to(move |request: web::Json<remote::StorageRead>| {
+                        let response = served.serve_read(&request).unwrap();
+                        async move { HttpResponse::Ok().json(response) }
+                    }))
+                })
+                .listen(listener)
+                .unwrap()
+                .run()
+                .await
+            })
+        });
+
+        let mut config = Config::test_config();
+        config.storage.remote.enabled = true;
+        config.storage.remote.url = format!("http://{}", address);
+        config.storage.remote.token = Some("token".to_string());
+        let replica = Storage::new(&config.storage).unwrap();
+        assert!(replica.is_remote());
+
+        assert_eq!(replica.get_block(&block_key(1)).unwrap(), Some(b"block".to_vec()));
+        assert_eq!(replica.get_block(&block_key(2)).unwrap(), None);
+        assert_eq!(replica.get_peer_events(5, 1100).unwrap(), local.get_peer_events(5, 1100).unwrap());
+        assert_eq!(replica.get_peer_events(5, 1100).unwrap().len(), 1100);
+        assert_eq!(replica.last_peer_event_seq().unwrap(), Some(remote::MAX_SCAN_PAGE as u64 + 199));
+        assert_eq!(replica.get_recent_reorgs(2).unwrap(), vec![(2, vec![2]), (1, vec![1])]);
+
+        assert!(matches!(replica.store_block(&block_key(3), b"x"), Err(StorageError::ReadOnly { .. })));
+        assert!(matches!(replica.serve_read(&remote::StorageRead::Size), Err(StorageError::ReadOnly { .. })));
+    }
+}
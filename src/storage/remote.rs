@@ -0,0 +1,194 @@
+//! Read-through storage for API replicas
+//!
+//! A replica keeps no RocksDB of its own. Its `Storage` sends each point
+//! read and column family scan to the validating node's
+//! `POST /api/v1/storage/read` endpoint, so any number of stateless API
+//! instances can sit behind a load balancer in front of one full node.
+//! Writes are refused. Keys and values travel as hex in JSON.
+
+use std::sync::mpsc;
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use serde::{Deserialize, Serialize};
+
+use crate::config::RemoteStorageConfig;
+use crate::error::{StorageError, StorageResult};
+
+/// Most items one scan request returns; longer scans page
+pub const MAX_SCAN_PAGE: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StorageRead {
+    Get { cf: String, key: String },
+    /// Up to `limit` pairs from `from` on, inclusive, or from either end
+    /// of the column family without it
+    Scan {
+        cf: String,
+        from: Option<String>,
+        #[serde(default)]
+        reverse: bool,
+        limit: usize,
+    },
+    Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageReadResponse {
+    Value(Option<String>),
+    Items(Vec<(String, String)>),
+    Size(u64),
+}
+
+pub(super) fn decode_hex(hex: &str) -> StorageResult<Vec<u8>> {
+    Vec::<u8>::from_hex(hex).map_err(|e| StorageError::Serialization(format!("bad hex in storage read: {}", e)))
+}
+
+pub(super) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.to_lower_hex_string()
+}
+
+type Reply = mpsc::Sender<StorageResult<StorageReadResponse>>;
+
+/// Client for a full node's storage read endpoint. Storage calls are
+/// synchronous, so requests go to a thread with its own runtime and the
+/// caller blocks on the answer, as it would on a local disk read.
+#[cfg_attr(not(feature = "http-client"), allow(dead_code))]
+pub struct RemoteStorage {
+    url: String,
+    requests: mpsc::Sender<(StorageRead, Reply)>,
+}
+
+#[cfg_attr(not(feature = "http-client"), allow(dead_code))]
+impl RemoteStorage {
+    #[cfg(not(feature = "http-client"))]
+    pub fn new(_config: &RemoteStorageConfig) -> StorageResult<Self> {
+        Err(StorageError::Remote("remote storage needs the http-client feature".to_string()))
+    }
+
+    #[cfg(feature = "http-client")]
+    pub fn new(config: &RemoteStorageConfig) -> StorageResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| StorageError::Remote(e.to_string()))?;
+        let url = format!("{}/api/v1/storage/read", config.url.trim_end_matches('/'));
+        let token = config.token.clone().unwrap_or_default();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| StorageError::Remote(e.to_string()))?;
+
+        let (requests, receiver) = mpsc::channel::<(StorageRead, Reply)>();
+        let endpoint = url.clone();
+        std::thread::Builder::new()
+            .name("remote-storage".to_string())
+            .spawn(move || {
+                while let Ok((request, reply)) = receiver.recv() {
+                    let response = runtime.block_on(Self::call(&client, &endpoint, &token, &request));
+                    // The caller may have given up
+                    let _ = reply.send(response);
+                }
+            })
+            .map_err(|e| StorageError::Remote(e.to_string()))?;
+
+        tracing::info!("Storage reads go to {}", url);
+        Ok(Self { url, requests })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[cfg(feature = "http-client")]
+    async fn call(client: &reqwest::Client, url: &str, token: &str, request: &StorageRead) -> StorageResult<StorageReadResponse> {
+        let remote = |e: reqwest::Error| StorageError::Remote(e.to_string());
+        let response = client.post(url).bearer_auth(token).json(request).send().await.map_err(remote)?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(StorageError::Remote(format!("{} answered {}: {}", url, status, body)));
+        }
+        response.json().await.map_err(remote)
+    }
+
+    fn request(&self, request: StorageRead) -> StorageResult<StorageReadResponse> {
+        let (reply, answer) = mpsc::channel();
+        self.requests
+            .send((request, reply))
+            .map_err(|_| StorageError::Remote("remote storage thread stopped".to_string()))?;
+        answer.recv().map_err(|_| StorageError::Remote("remote storage thread stopped".to_string()))?
+    }
+
+    pub fn get(&self, cf: &str, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        match self.request(StorageRead::Get { cf: cf.to_string(), key: encode_hex(key) })? {
+            StorageReadResponse::Value(value) => value.as_deref().map(decode_hex).transpose(),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn size(&self) -> StorageResult<u64> {
+        match self.request(StorageRead::Size)? {
+            StorageReadResponse::Size(size) => Ok(size),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Visit up to `limit` pairs from `from`, one page per request. Each page
+    /// after the first starts at the last key seen, which is skipped.
+    pub fn scan(
+        &self,
+        cf: &str,
+        from: Option<&[u8]>,
+        reverse: bool,
+        limit: usize,
+        mut visit: impl FnMut(&[u8], &[u8]) -> StorageResult<()>,
+    ) -> StorageResult<()> {
+        let mut from = from.map(encode_hex);
+        let mut skip = None;
+        let mut remaining = limit;
+        while remaining > 0 {
+            let page = (remaining + skip.is_some() as usize).min(MAX_SCAN_PAGE);
+            let request = StorageRead::Scan { cf: cf.to_string(), from: from.clone(), reverse, limit: page };
+            let items = match self.request(request)? {
+                StorageReadResponse::Items(items) => items,
+                other => return Err(unexpected(&other)),
+            };
+            let exhausted = items.len() < page;
+            for (key, value) in &items {
+                if skip.as_ref() == Some(key) || remaining == 0 {
+                    continue;
+                }
+                visit(&decode_hex(key)?, &decode_hex(value)?)?;
+                remaining -= 1;
+            }
+            match items.last() {
+                Some((last, _)) if !exhausted => {
+                    from = Some(last.clone());
+                    skip = Some(last.clone());
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unexpected(response: &StorageReadResponse) -> StorageError {
+    StorageError::Remote(format!("unexpected response {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() {
+        let request: StorageRead = serde_json::from_str(r#"{"op":"scan","cf":"reorgs","from":null,"limit":5}"#).unwrap();
+        assert_eq!(request, StorageRead::Scan { cf: "reorgs".to_string(), from: None, reverse: false, limit: 5 });
+        let response = StorageReadResponse::Items(vec![("00".to_string(), "ff".to_string())]);
+        assert_eq!(serde_json::to_string(&response).unwrap(), r#"{"items":[["00","ff"]]}"#);
+        assert_eq!(serde_json::to_string(&StorageReadResponse::Value(None)).unwrap(), r#"{"value":null}"#);
+    }
+}
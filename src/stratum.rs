@@ -0,0 +1,355 @@
+//! Stratum v1 job construction and share checking
+//!
+//! Jobs are cut from a [`BlockTemplate`]: the coinbase is split around the
+//! extranonce so each connection can roll its own extranonce2, and the merkle
+//! branch lets the miner rebuild the root without the full transaction list.
+//! Networking and session state live in the stratum actor.
+
+use std::collections::HashSet;
+
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::{Block, BlockHash, CompactTarget, Target, Transaction, TxMerkleNode};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::error::{ConfigError, ConfigResult, StratumError};
+use crate::mining::{BlockTemplate, CoinbaseSpec};
+
+/// Bytes of extranonce1 assigned to each connection
+pub const EXTRANONCE1_SIZE: usize = 4;
+
+/// Header version bits miners may roll under BIP310
+pub const VERSION_ROLLING_MASK: u32 = 0x1fff_e000;
+
+/// Coinbase for stratum jobs: `[mining]` payouts unless `stratum.payout_address` is set,
+/// with the extra nonce space sized for extranonce1 and extranonce2
+pub fn coinbase_spec(config: &Config) -> ConfigResult<CoinbaseSpec> {
+    let network = config.network.to_bitcoin_network();
+    let mut spec = CoinbaseSpec::from_config(&config.mining, network)?;
+    if let Some(script) = config.stratum.payout_script(network)? {
+        spec.payouts = vec![(script, 1.0)];
+    }
+    if spec.payouts.is_empty() {
+        return Err(ConfigError::MissingField("stratum.payout_address".to_string()));
+    }
+    spec.extra_nonce_size = EXTRANONCE1_SIZE + config.stratum.extranonce2_size;
+    Ok(spec)
+}
+
+/// extranonce1, extranonce2, ntime, nonce and version of a submitted share
+type ShareKey = (Vec<u8>, Vec<u8>, u32, u32, u32);
+
+/// What a valid submission amounts to
+#[derive(Debug, Clone)]
+pub enum ShareOutcome {
+    Share { difficulty: f64 },
+    /// The share also meets the network target
+    Block { block: Box<Block>, difficulty: f64 },
+}
+
+/// Parameters of a `mining.submit` request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submission {
+    pub worker: String,
+    pub job_id: String,
+    pub extranonce2: Vec<u8>,
+    pub ntime: u32,
+    pub nonce: u32,
+    pub version_bits: Option<u32>,
+}
+
+impl Submission {
+    pub fn from_params(params: &Value) -> Result<Self, StratumError> {
+        let field = |index: usize, name: &str| {
+            params.get(index)
+                .and_then(Value::as_str)
+                .ok_or_else(|| StratumError::Malformed(format!("missing {}", name)))
+        };
+        let hex_u32 = |index: usize, name: &str| {
+            field(index, name).and_then(|value| {
+                u32::from_str_radix(value, 16).map_err(|_| StratumError::Malformed(format!("invalid {}", name)))
+            })
+        };
+
+        Ok(Self {
+            worker: field(0, "worker")?.to_string(),
+            job_id: field(1, "job id")?.to_string(),
+            extranonce2: Vec::<u8>::from_hex(field(2, "extranonce2")?)
+                .map_err(|_| StratumError::Malformed("invalid extranonce2".to_string()))?,
+            ntime: hex_u32(3, "ntime")?,
+            nonce: hex_u32(4, "nonce")?,
+            version_bits: match params.get(5) {
+                Some(_) => Some(hex_u32(5, "version bits")?),
+                None => None,
+            },
+        })
+    }
+}
+
+/// One unit of work handed to miners with `mining.notify`
+#[derive(Debug, Clone)]
+pub struct StratumJob {
+    pub id: String,
+    pub template: BlockTemplate,
+    coinb1: Vec<u8>,
+    coinb2: Vec<u8>,
+    merkle_branch: Vec<sha256d::Hash>,
+    extranonce2_size: usize,
+    submitted: HashSet<ShareKey>,
+}
+
+impl StratumJob {
+    pub fn new(id: String, template: BlockTemplate, coinbase: &CoinbaseSpec) -> Self {
+        let extranonce_len = coinbase.extra_nonce_size;
+        let extranonce2_size = extranonce_len - EXTRANONCE1_SIZE;
+        let coinbase = template.coinbase_transaction(coinbase, &vec![0u8; extranonce_len]);
+        let serialized = bitcoin::consensus::serialize(&coinbase);
+
+        // version (4), input count (1), null prevout (36), scriptSig length, height push
+        let script_len = coinbase.input[0].script_sig.len();
+        let split = 4 + 1 + 36 + bitcoin::VarInt(script_len as u64).size() + template.coinbase_height_push().len();
+        let coinb1 = serialized[..split].to_vec();
+        let coinb2 = serialized[split + extranonce_len..].to_vec();
+
+        let txids = template.transactions.iter().map(|entry| entry.tx.txid().to_raw_hash()).collect();
+        Self {
+            id,
+            merkle_branch: merkle_branch(txids),
+            template,
+            coinb1,
+            coinb2,
+            extranonce2_size,
+            submitted: HashSet::new(),
+        }
+    }
+
+    pub fn previous_block_hash(&self) -> BlockHash {
+        self.template.previous_block_hash
+    }
+
+    pub fn notify_params(&self, clean_jobs: bool) -> Value {
+        json!([
+            self.id,
+            stratum_prev_hash(&self.template.previous_block_hash),
+            self.coinb1.to_lower_hex_string(),
+            self.coinb2.to_lower_hex_string(),
+            self.merkle_branch.iter().map(|hash| hash.to_byte_array().to_lower_hex_string()).collect::<Vec<_>>(),
+            format!("{:08x}", self.template.version as u32),
+            format!("{:08x}", self.template.bits.to_consensus()),
+            format!("{:08x}", self.template.cur_time),
+            clean_jobs,
+        ])
+    }
+
+    /// Rebuild the block a submission describes and grade it against the share and network targets
+    pub fn check_submission(
+        &mut self,
+        submission: &Submission,
+        extranonce1: &[u8],
+        version_mask: Option<u32>,
+        share_difficulty: f64,
+    ) -> Result<ShareOutcome, StratumError> {
+        if submission.extranonce2.len() != self.extranonce2_size {
+            return Err(StratumError::Malformed("incorrect extranonce2 size".to_string()));
+        }
+        if submission.ntime < self.template.min_time {
+            return Err(StratumError::Malformed("ntime out of range".to_string()));
+        }
+
+        let version = match (submission.version_bits, version_mask) {
+            (Some(bits), Some(mask)) => {
+                if bits & !mask != 0 {
+                    return Err(StratumError::Malformed("version bits outside the negotiated mask".to_string()));
+                }
+                (self.template.version as u32 & !mask) | bits
+            }
+            (Some(_), None) => return Err(StratumError::Malformed("version rolling not negotiated".to_string())),
+            (None, _) => self.template.version as u32,
+        };
+
+        let key = (
+            extranonce1.to_vec(),
+            submission.extranonce2.clone(),
+            submission.ntime,
+            submission.nonce,
+            version,
+        );
+        if self.submitted.contains(&key) {
+            return Err(StratumError::Duplicate);
+        }
+
+        let mut coinbase_bytes = self.coinb1.clone();
+        coinbase_bytes.extend_from_slice(extranonce1);
+        coinbase_bytes.extend_from_slice(&submission.extranonce2);
+        coinbase_bytes.extend_from_slice(&self.coinb2);
+        let coinbase: Transaction = bitcoin::consensus::deserialize(&coinbase_bytes)
+            .map_err(|e| StratumError::Malformed(format!("coinbase does not decode: {}", e)))?;
+
+        let merkle_root = self.merkle_branch
+            .iter()
+            .fold(coinbase.txid().to_raw_hash(), |hash, step| hash_pair(&hash, step));
+        let header = Header {
+            version: Version::from_consensus(version as i32),
+            prev_blockhash: self.template.previous_block_hash,
+            merkle_root: TxMerkleNode::from_raw_hash(merkle_root),
+            time: submission.ntime,
+            bits: self.template.bits,
+            nonce: submission.nonce,
+        };
+
+        let difficulty = hash_difficulty(&header.block_hash());
+        if difficulty < share_difficulty {
+            return Err(StratumError::LowDifficulty(difficulty));
+        }
+        self.submitted.insert(key);
+
+        if header.validate_pow(header.target()).is_ok() {
+            let block = self.template.assemble_block(header, coinbase);
+            return Ok(ShareOutcome::Block { block: Box::new(block), difficulty });
+        }
+        Ok(ShareOutcome::Share { difficulty })
+    }
+}
+
+/// Difficulty a block hash corresponds to, relative to the difficulty 1 target
+pub fn hash_difficulty(hash: &BlockHash) -> f64 {
+    Target::from_le_bytes(hash.to_byte_array()).difficulty_float()
+}
+
+/// Highest share difficulty that still meets `bits`, used to cap the share target on regtest
+pub fn network_difficulty(bits: CompactTarget) -> f64 {
+    Target::from_compact(bits).difficulty_float()
+}
+
+/// Sibling hashes on the path from the coinbase (always leftmost) to the merkle root
+fn merkle_branch(mut level: Vec<sha256d::Hash>) -> Vec<sha256d::Hash> {
+    let mut branch = Vec::new();
+    while !level.is_empty() {
+        branch.push(level[0]);
+        // Pair everything right of the coinbase path, duplicating an odd last hash
+        level = level[1..]
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    branch
+}
+
+fn hash_pair(left: &sha256d::Hash, right: &sha256d::Hash) -> sha256d::Hash {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    sha256d::Hash::from_engine(engine)
+}
+
+/// Previous hash in the 32-bit word swapped form `mining.notify` uses
+fn stratum_prev_hash(hash: &BlockHash) -> String {
+    hash.to_byte_array()
+        .chunks(4)
+        .flat_map(|word| word.iter().rev().copied())
+        .collect::<Vec<u8>>()
+        .to_lower_hex_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Network, ScriptBuf};
+
+    fn regtest_job() -> StratumJob {
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let template = BlockTemplate::new(&genesis, 0, Vec::new(), Network::Regtest, genesis.header.time + 1);
+        let coinbase = CoinbaseSpec {
+            payouts: vec![(ScriptBuf::new_op_return([7u8; 4]), 1.0)],
+            message: b"/bitknotsrs/".to_vec(),
+            extra_nonce_size: EXTRANONCE1_SIZE + 4,
+        };
+        StratumJob::new("1".to_string(), template, &coinbase)
+    }
+
+    #[test]
+    fn test_stratum_payout_overrides_mining_payouts() {
+        let mut config = Config::default_regtest();
+        assert!(coinbase_spec(&config).is_err());
+
+        config.mining.payouts = vec![
+            crate::config::CoinbasePayout { address: "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080".to_string(), weight: 60.0 },
+            crate::config::CoinbasePayout { address: "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080".to_string(), weight: 40.0 },
+        ];
+        let spec = coinbase_spec(&config).unwrap();
+        assert_eq!(spec.payouts.len(), 2);
+        assert_eq!(spec.extra_nonce_size, EXTRANONCE1_SIZE + config.stratum.extranonce2_size);
+
+        config.stratum.payout_address = Some("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080".to_string());
+        assert_eq!(coinbase_spec(&config).unwrap().payouts.len(), 1);
+    }
+
+    fn submission(nonce: u32, ntime: u32) -> Submission {
+        Submission {
+            worker: "rig".to_string(),
+            job_id: "1".to_string(),
+            extranonce2: vec![0, 0, 0, 1],
+            ntime,
+            nonce,
+            version_bits: None,
+        }
+    }
+
+    #[test]
+    fn test_merkle_branch_matches_block_root() {
+        let hashes: Vec<sha256d::Hash> = (1u8..=5).map(|i| sha256d::Hash::hash(&[i])).collect();
+        let coinbase = sha256d::Hash::hash(&[0]);
+
+        let root = merkle_branch(hashes.clone()).iter().fold(coinbase, |hash, step| hash_pair(&hash, step));
+        let expected = bitcoin::merkle_tree::calculate_root(std::iter::once(coinbase).chain(hashes)).unwrap();
+        assert_eq!(root, expected);
+    }
+
+    #[test]
+    fn test_regtest_submission_finds_block() {
+        let mut job = regtest_job();
+        let ntime = job.template.cur_time;
+
+        // Half of all hashes meet the regtest target, so a block turns up within a few nonces
+        let block = (0..64u32)
+            .find_map(|nonce| match job.check_submission(&submission(nonce, ntime), &[0, 0, 0, 1], None, 0.0) {
+                Ok(ShareOutcome::Block { block, .. }) => Some(block),
+                _ => None,
+            })
+            .expect("a regtest block within 64 nonces");
+
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+        assert_eq!(block.header.prev_blockhash, job.previous_block_hash());
+        assert_eq!(block.txdata[0].input[0].script_sig.as_bytes()[1..9], [0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_duplicate_and_malformed_submissions() {
+        let mut job = regtest_job();
+        let ntime = job.template.cur_time;
+
+        assert!(job.check_submission(&submission(1, ntime), &[0; 4], None, 0.0).is_ok());
+        assert_eq!(job.check_submission(&submission(1, ntime), &[0; 4], None, 0.0).unwrap_err(), StratumError::Duplicate);
+
+        let mut short = submission(2, ntime);
+        short.extranonce2.pop();
+        assert!(matches!(job.check_submission(&short, &[0; 4], None, 0.0), Err(StratumError::Malformed(_))));
+
+        let params = json!(["rig", "1", "00000001", format!("{:08x}", ntime), "0000002a"]);
+        assert_eq!(Submission::from_params(&params).unwrap().nonce, 42);
+    }
+
+    #[test]
+    fn test_notify_prev_hash_is_word_swapped() {
+        let job = regtest_job();
+        let params = job.notify_params(true);
+        let prev = params[1].as_str().unwrap();
+        let internal = job.previous_block_hash().to_byte_array().to_lower_hex_string();
+
+        assert_eq!(&prev[..8], format!("{}{}{}{}", &internal[6..8], &internal[4..6], &internal[2..4], &internal[..2]));
+        assert_eq!(params[8], true);
+    }
+}
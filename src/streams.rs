@@ -0,0 +1,397 @@
+//! WebSocket streaming sessions served by the REST API
+//!
+//! Sessions subscribe to broadcast feeds owned by the actors and push
+//! JSON messages to clients instead of requiring them to poll. Clients that
+//! cannot use WebSockets get the same events as Server-Sent Events from
+//! [`sse_events`].
+
+use actix::prelude::*;
+use actix_web_actors::ws;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, IntervalStream};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, warn};
+
+use crate::actors::instrument::InstrumentedAddr;
+use crate::actors::mempool::{MempoolActor, MempoolDeltaFeed, MempoolSnapshot, SequencedMempoolDelta};
+use crate::actors::GetMempoolSnapshot;
+use crate::events::{BitcoinEvent, EventManager, EventTopic};
+
+/// How often heartbeat pings are sent to clients
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a client may stay silent before the session is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often an idle SSE stream sends a comment so proxies keep it open
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// How long an SSE client waits before reconnecting, in milliseconds
+const SSE_RETRY_MS: u64 = 3000;
+
+/// Streams sequenced mempool deltas, optionally preceded by a full snapshot
+pub struct MempoolDeltaSession {
+    mempool_actor: Addr<MempoolActor>,
+    feed: MempoolDeltaFeed,
+    send_snapshot: bool,
+    /// Deltas received while the snapshot is still being fetched
+    pending: Option<Vec<SequencedMempoolDelta>>,
+    last_sequence: u64,
+    last_heartbeat: Instant,
+}
+
+impl MempoolDeltaSession {
+    pub fn new(mempool_actor: Addr<MempoolActor>, feed: MempoolDeltaFeed, send_snapshot: bool) -> Self {
+        Self {
+            mempool_actor,
+            feed,
+            send_snapshot,
+            pending: None,
+            last_sequence: 0,
+            last_heartbeat: Instant::now(),
+        }
+    }
+
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.last_heartbeat) > CLIENT_TIMEOUT {
+                debug!("Mempool stream client timed out");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn request_snapshot(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        self.pending = Some(Vec::new());
+        self.mempool_actor
+            .send_traced(GetMempoolSnapshot)
+            .into_actor(self)
+            .map(|result, act, ctx| match result {
+                Ok(Ok(snapshot)) => act.apply_snapshot(snapshot, ctx),
+                Ok(Err(e)) => {
+                    warn!("Failed to load mempool snapshot: {}", e);
+                    ctx.stop();
+                }
+                Err(e) => {
+                    warn!("Mempool actor unavailable for snapshot: {}", e);
+                    ctx.stop();
+                }
+            })
+            .spawn(ctx);
+    }
+
+    fn apply_snapshot(&mut self, snapshot: MempoolSnapshot, ctx: &mut ws::WebsocketContext<Self>) {
+        self.last_sequence = snapshot.sequence;
+        ctx.text(json!({
+            "type": "snapshot",
+            "sequence": snapshot.sequence,
+            "entries": snapshot.entries,
+        }).to_string());
+
+        for delta in self.pending.take().unwrap_or_default() {
+            self.send_delta(delta, ctx);
+        }
+    }
+
+    fn send_delta(&mut self, delta: SequencedMempoolDelta, ctx: &mut ws::WebsocketContext<Self>) {
+        // Deltas already reflected in the snapshot are skipped
+        if delta.sequence <= self.last_sequence {
+            return;
+        }
+        self.last_sequence = delta.sequence;
+        match serde_json::to_string(&delta) {
+            Ok(text) => ctx.text(text),
+            Err(e) => warn!("Failed to serialize mempool delta: {}", e),
+        }
+    }
+}
+
+impl Actor for MempoolDeltaSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Subscribe before taking the snapshot so no delta falls in between
+        ctx.add_stream(BroadcastStream::new(self.feed.subscribe()));
+        self.start_heartbeat(ctx);
+
+        if self.send_snapshot {
+            self.request_snapshot(ctx);
+        } else {
+            self.last_sequence = self.feed.current_sequence();
+            ctx.text(json!({
+                "type": "subscribed",
+                "sequence": self.last_sequence,
+            }).to_string());
+        }
+    }
+}
+
+impl StreamHandler<Result<SequencedMempoolDelta, BroadcastStreamRecvError>> for MempoolDeltaSession {
+    fn handle(&mut self, item: Result<SequencedMempoolDelta, BroadcastStreamRecvError>, ctx: &mut Self::Context) {
+        match item {
+            Ok(delta) => match self.pending.as_mut() {
+                Some(pending) => pending.push(delta),
+                None => self.send_delta(delta, ctx),
+            },
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                // The client can no longer mirror the mempool and has to resync from a snapshot
+                warn!("Mempool stream client lagged behind by {} deltas", missed);
+                ctx.text(json!({
+                    "type": "resync_required",
+                    "missed": missed,
+                }).to_string());
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Again,
+                    description: Some("subscriber lagged behind".to_string()),
+                }));
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for MempoolDeltaSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(payload)) => {
+                self.last_heartbeat = Instant::now();
+                ctx.pong(&payload);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.last_heartbeat = Instant::now();
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {
+                // The stream is server-push only; other client frames just count as liveness
+                self.last_heartbeat = Instant::now();
+            }
+            Err(e) => {
+                warn!("Mempool stream protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+/// A client frame on `/api/v1/ws` changing what it receives
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicCommand {
+    Subscribe(Vec<EventTopic>),
+    Unsubscribe(Vec<EventTopic>),
+}
+
+/// Streams published events to a client, filtered to the topics it subscribed to
+pub struct EventStreamSession {
+    events: EventManager,
+    topics: HashSet<EventTopic>,
+    /// Sequence of the last event sent, which a lagging client replays from
+    last_sequence: u64,
+    last_heartbeat: Instant,
+}
+
+impl EventStreamSession {
+    pub fn new(events: EventManager, topics: HashSet<EventTopic>) -> Self {
+        Self {
+            events,
+            topics,
+            last_sequence: 0,
+            last_heartbeat: Instant::now(),
+        }
+    }
+
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.last_heartbeat) > CLIENT_TIMEOUT {
+                debug!("Event stream client timed out");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    fn send_topics(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let mut topics: Vec<&str> = self.topics.iter().map(EventTopic::as_str).collect();
+        topics.sort_unstable();
+        ctx.text(json!({ "type": "subscribed", "topics": topics }).to_string());
+    }
+
+    fn handle_command(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::from_str::<TopicCommand>(text) {
+            Ok(TopicCommand::Subscribe(topics)) => self.topics.extend(topics),
+            Ok(TopicCommand::Unsubscribe(topics)) => topics.iter().for_each(|topic| {
+                self.topics.remove(topic);
+            }),
+            Err(e) => {
+                ctx.text(json!({ "type": "error", "error": e.to_string() }).to_string());
+                return;
+            }
+        }
+        self.send_topics(ctx);
+    }
+}
+
+impl Actor for EventStreamSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.add_stream(BroadcastStream::new(self.events.subscribe()));
+        self.start_heartbeat(ctx);
+        self.send_topics(ctx);
+    }
+}
+
+impl StreamHandler<Result<BitcoinEvent, BroadcastStreamRecvError>> for EventStreamSession {
+    fn handle(&mut self, item: Result<BitcoinEvent, BroadcastStreamRecvError>, ctx: &mut Self::Context) {
+        match item {
+            Ok(event) => {
+                let topic = event.event_type.topic();
+                if !self.topics.contains(&topic) {
+                    return;
+                }
+                self.last_sequence = self.last_sequence.max(event.sequence);
+                ctx.text(json!({ "type": "event", "topic": topic, "event": event }).to_string());
+            }
+            // Missed events stay in the journal, so the client can fetch them and carry on
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                warn!("Event stream client lagged behind by {} events", missed);
+                ctx.text(json!({
+                    "type": "lagged",
+                    "missed": missed,
+                    "since_seq": self.last_sequence,
+                }).to_string());
+            }
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for EventStreamSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        self.last_heartbeat = Instant::now();
+        match msg {
+            Ok(ws::Message::Text(text)) => self.handle_command(&text, ctx),
+            Ok(ws::Message::Ping(payload)) => ctx.pong(&payload),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Event stream protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
+
+/// One Server-Sent Event carrying `event`, with its journal sequence as the ID
+/// a reconnecting client resumes from
+pub fn sse_frame(event: &BitcoinEvent) -> String {
+    let data = serde_json::to_string(event).expect("events always serialize");
+    format!("id: {}\nevent: {}\ndata: {}\n\n", event.sequence, event.event_type.name(), data)
+}
+
+enum SseItem {
+    Event(BitcoinEvent),
+    KeepAlive,
+    /// The live feed overran this client
+    Lagged,
+}
+
+/// `replay`, the journaled events after `since_seq`, followed by live events
+/// on `topics`, as an SSE body. Live events the replay already covered are
+/// skipped. A client that falls behind the
+/// live feed has its stream ended, so it reconnects with `Last-Event-ID` and
+/// catches up from the journal.
+pub fn sse_events(
+    live: tokio::sync::broadcast::Receiver<BitcoinEvent>,
+    since_seq: u64,
+    replay: Vec<BitcoinEvent>,
+    topics: HashSet<EventTopic>,
+) -> impl Stream<Item = Result<actix_web::web::Bytes, actix_web::Error>> {
+    let mut last_sequence = since_seq;
+    let live = BroadcastStream::new(live).map(|item| match item {
+        Ok(event) => SseItem::Event(event),
+        Err(BroadcastStreamRecvError::Lagged(missed)) => {
+            warn!("SSE client lagged behind by {} events, ending its stream", missed);
+            SseItem::Lagged
+        }
+    });
+    let keep_alive = IntervalStream::new(tokio::time::interval_at(
+        tokio::time::Instant::now() + SSE_KEEP_ALIVE,
+        SSE_KEEP_ALIVE,
+    ))
+    .map(|_| SseItem::KeepAlive);
+
+    let retry = tokio_stream::once(format!("retry: {}\n\n", SSE_RETRY_MS));
+    let events = tokio_stream::iter(replay.into_iter().map(SseItem::Event))
+        .chain(live)
+        .merge(keep_alive)
+        .take_while(|item| !matches!(item, SseItem::Lagged))
+        .filter_map(move |item| match item {
+            SseItem::Event(event) => {
+                if !topics.contains(&event.event_type.topic()) {
+                    return None;
+                }
+                // Unjournaled events carry no sequence and are never duplicates
+                if event.sequence != 0 {
+                    if event.sequence <= last_sequence {
+                        return None;
+                    }
+                    last_sequence = event.sequence;
+                }
+                Some(sse_frame(&event))
+            }
+            SseItem::KeepAlive => Some(": keep-alive\n\n".to_string()),
+            SseItem::Lagged => None,
+        });
+    retry.chain(events).map(|frame| Ok(actix_web::web::Bytes::from(frame)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::FeeRate;
+    use crate::events::BitcoinEventType;
+
+    fn event(sequence: u64, event_type: BitcoinEventType) -> BitcoinEvent {
+        let mut event = BitcoinEvent::new(event_type, "regtest", "node");
+        event.sequence = sequence;
+        event
+    }
+
+    fn block(sequence: u64) -> BitcoinEvent {
+        event(sequence, BitcoinEventType::BlockAdded { hash: "00".to_string(), height: sequence, size: 0, tx_count: 1, timestamp: 0 })
+    }
+
+    #[tokio::test]
+    async fn test_sse_replays_then_follows_live_events() {
+        let (sender, live) = tokio::sync::broadcast::channel(16);
+        let topics = [EventTopic::Blocks].into_iter().collect();
+        let stream = sse_events(live, 1, vec![block(2), block(3)], topics);
+
+        // Already replayed, then another topic, then new
+        sender.send(block(3)).unwrap();
+        sender.send(event(4, BitcoinEventType::MempoolUpdate { tx_count: 0, total_size: 0, min_fee_rate: FeeRate::ZERO, max_fee_rate: FeeRate::ZERO })).unwrap();
+        sender.send(block(5)).unwrap();
+        drop(sender);
+
+        let frames: Vec<String> = stream
+            .map(|frame| String::from_utf8(frame.unwrap().to_vec()).unwrap())
+            .take(4)
+            .collect()
+            .await;
+        assert_eq!(frames[0], "retry: 3000\n\n");
+        let ids: Vec<&str> = frames[1..].iter().map(|frame| frame.lines().next().unwrap()).collect();
+        assert_eq!(ids, vec!["id: 2", "id: 3", "id: 5"]);
+        assert!(frames[1].starts_with("id: 2\nevent: BlockAdded\ndata: {"));
+        assert!(frames[1].ends_with("}\n\n"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Process and disk resource sampling
+//!
+//! Every `metrics.system.interval_secs` the node's resident memory, CPU use
+//! and open file descriptors are sampled with the free space on the disk
+//! holding the data directory, and exported as Prometheus gauges. When that
+//! disk's free space falls below `metrics.system.low_disk_free_mb` a
+//! `LowDiskSpace` event is published, once until it has recovered.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use sysinfo::{Disks, Pid, System};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::events::{BitcoinEventType, EventManager};
+use crate::metrics;
+
+/// One sample of the node's resource use
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemStats {
+    pub memory_bytes: u64,
+    /// Share of one core used since the previous sample, so above 100 when
+    /// several threads are busy
+    pub cpu_percent: f64,
+    /// Where the platform exposes it
+    pub open_fds: Option<u64>,
+    /// The disk holding the data directory, if it could be found
+    pub disk: Option<DiskSpace>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiskSpace {
+    pub mount_point: PathBuf,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// The disk `path` lives on: the deepest mount point it is under
+fn disk_for(path: &Path, disks: Vec<DiskSpace>) -> Option<DiskSpace> {
+    disks.into_iter()
+        .filter(|disk| path.starts_with(&disk.mount_point))
+        .max_by_key(|disk| disk.mount_point.components().count())
+}
+
+/// Entries in `/proc/self/fd`, less the one reading the directory holds
+fn open_fds() -> Option<u64> {
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?;
+    Some((entries.count() as u64).saturating_sub(1))
+}
+
+/// Raised when free space drops below the threshold, cleared once it is back above
+#[derive(Debug)]
+struct LowDiskAlarm {
+    threshold_bytes: u64,
+    raised: bool,
+}
+
+impl LowDiskAlarm {
+    fn new(threshold_bytes: u64) -> Self {
+        Self { threshold_bytes, raised: false }
+    }
+
+    /// Whether `available_bytes` newly raises the alarm
+    fn check(&mut self, available_bytes: u64) -> bool {
+        let low = available_bytes < self.threshold_bytes;
+        let raised = low && !self.raised;
+        self.raised = low;
+        raised
+    }
+}
+
+pub struct SystemMonitor {
+    interval: Duration,
+    datadir: PathBuf,
+    system: System,
+    disks: Disks,
+    pid: Option<Pid>,
+    alarm: LowDiskAlarm,
+}
+
+impl SystemMonitor {
+    pub fn new(config: &Config) -> Self {
+        // Mount points are absolute, so the data directory has to be too
+        let datadir = config.datadir.canonicalize().unwrap_or_else(|_| config.datadir.clone());
+        Self {
+            interval: Duration::from_secs(config.metrics.system.interval_secs.max(1)),
+            datadir,
+            system: System::new(),
+            disks: Disks::new(),
+            pid: sysinfo::get_current_pid().ok(),
+            alarm: LowDiskAlarm::new(config.metrics.system.low_disk_free_mb * 1024 * 1024),
+        }
+    }
+
+    pub fn sample(&mut self) -> SystemStats {
+        let (memory_bytes, cpu_percent) = self.pid
+            .filter(|pid| self.system.refresh_process(*pid))
+            .and_then(|pid| self.system.process(pid))
+            .map(|process| (process.memory(), process.cpu_usage() as f64))
+            .unwrap_or((0, 0.0));
+
+        self.disks.refresh_list();
+        let disks = self.disks.list().iter()
+            .map(|disk| DiskSpace {
+                mount_point: disk.mount_point().to_path_buf(),
+                available_bytes: disk.available_space(),
+                total_bytes: disk.total_space(),
+            })
+            .collect();
+
+        SystemStats {
+            memory_bytes,
+            cpu_percent,
+            open_fds: open_fds(),
+            disk: disk_for(&self.datadir, disks),
+        }
+    }
+
+    /// Sample until the node stops, publishing `LowDiskSpace` as the disk fills
+    pub async fn run(mut self, events: EventManager, config: Config) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            let stats = self.sample();
+            metrics::record_system_stats(&stats);
+
+            let Some(disk) = stats.disk else {
+                continue;
+            };
+            let was_raised = self.alarm.raised;
+            if self.alarm.check(disk.available_bytes) {
+                warn!("Data directory {} has {} MB free, below {} MB", self.datadir.display(),
+                    disk.available_bytes / (1024 * 1024), config.metrics.system.low_disk_free_mb);
+                let event = BitcoinEventType::LowDiskSpace {
+                    path: self.datadir.display().to_string(),
+                    available_bytes: disk.available_bytes,
+                    total_bytes: disk.total_bytes,
+                    threshold_bytes: self.alarm.threshold_bytes,
+                };
+                if let Err(e) = events.publish(event, config.network.as_str(), &config.events.k8s.node_name).await {
+                    warn!("Failed to publish low disk space: {}", e);
+                }
+            } else if was_raised && !self.alarm.raised {
+                info!("Data directory {} is back to {} MB free", self.datadir.display(), disk.available_bytes / (1024 * 1024));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(mount_point: &str, available_bytes: u64) -> DiskSpace {
+        DiskSpace { mount_point: PathBuf::from(mount_point), available_bytes, total_bytes: 1_000 }
+    }
+
+    #[test]
+    fn test_datadir_disk_is_deepest_mount() {
+        let disks = || vec![disk("/", 100), disk("/var", 200), disk("/var/lib/bitcoin", 300), disk("/variable", 400)];
+        assert_eq!(disk_for(Path::new("/var/lib/bitcoin/data"), disks()).unwrap().available_bytes, 300);
+        assert_eq!(disk_for(Path::new("/var/log"), disks()).unwrap().available_bytes, 200);
+        // Components, not string prefixes
+        assert_eq!(disk_for(Path::new("/variable2"), disks()).unwrap().available_bytes, 100);
+        assert_eq!(disk_for(Path::new("relative"), disks()), None);
+    }
+
+    #[test]
+    fn test_low_disk_alarm_fires_once_until_recovered() {
+        let mut alarm = LowDiskAlarm::new(1_000);
+        assert!(!alarm.check(5_000));
+        assert!(alarm.check(900));
+        assert!(!alarm.check(800));
+        assert!(!alarm.check(1_200));
+        assert!(alarm.check(999));
+    }
+}
@@ -0,0 +1,177 @@
+//! Runtime topology: which threads the actors run on
+//!
+//! By default every actor shares the System arbiter started in `main`, a
+//! single event loop thread with tokio's blocking pool for RocksDB work.
+//! Larger machines can give validation (the storage, chain and mempool
+//! actors, which connect blocks and verify scripts) an arbiter of its own,
+//! optionally pinned to a set of cores, and spread the remaining actors over
+//! further worker arbiters. The API and RPC servers keep their own threads.
+
+use std::io;
+
+use actix::prelude::*;
+use tracing::warn;
+
+use crate::config::RuntimeConfig;
+
+/// Where an actor is started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Block and transaction validation
+    Validation,
+    /// Everything else, round robin over the worker arbiters
+    Worker,
+}
+
+pub struct Topology {
+    validation: Option<Arbiter>,
+    workers: Vec<Arbiter>,
+    next_worker: usize,
+}
+
+impl Topology {
+    /// Spawn the arbiters `config` asks for beyond the System one
+    pub fn new(config: &RuntimeConfig) -> Self {
+        let extra = config.arbiters.saturating_sub(1);
+        let validation = (extra > 0).then(|| spawn_arbiter(config, config.validation_cores.clone()));
+        let workers = (1..extra).map(|_| spawn_arbiter(config, Vec::new())).collect();
+        Self { validation, workers, next_worker: 0 }
+    }
+
+    /// Start `actor` on the arbiter for `placement`, or the current one when
+    /// the topology has none for it
+    pub fn start<A>(&mut self, placement: Placement, actor: A) -> Addr<A>
+    where
+        A: Actor<Context = Context<A>> + Send,
+    {
+        match self.arbiter(placement) {
+            Some(arbiter) => A::start_in_arbiter(&arbiter, move |_ctx| actor),
+            None => actor.start(),
+        }
+    }
+
+    fn arbiter(&mut self, placement: Placement) -> Option<ArbiterHandle> {
+        match placement {
+            Placement::Validation => self.validation.as_ref().map(Arbiter::handle),
+            Placement::Worker if self.workers.is_empty() => None,
+            Placement::Worker => {
+                let arbiter = &self.workers[self.next_worker % self.workers.len()];
+                self.next_worker += 1;
+                Some(arbiter.handle())
+            }
+        }
+    }
+
+    /// Stop the spawned arbiters; their actors must already have shut down
+    pub fn stop(self) {
+        for arbiter in self.validation.into_iter().chain(self.workers) {
+            arbiter.stop();
+        }
+    }
+}
+
+fn spawn_arbiter(config: &RuntimeConfig, cores: Vec<usize>) -> Arbiter {
+    let blocking_threads = config.blocking_threads;
+    Arbiter::with_tokio_rt(move || {
+        // The factory runs on the new arbiter's own thread
+        if !cores.is_empty() {
+            pin_or_warn(&cores);
+        }
+        tokio_runtime(blocking_threads, cores).expect("Failed to build arbiter runtime")
+    })
+}
+
+/// Single threaded runtime like the ones actix builds, with a bounded
+/// blocking pool whose threads are pinned to `cores` when given
+pub fn tokio_runtime(blocking_threads: usize, cores: Vec<usize>) -> io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_current_thread();
+    builder.enable_all().max_blocking_threads(blocking_threads.max(1));
+    if !cores.is_empty() {
+        builder.on_thread_start(move || pin_or_warn(&cores));
+    }
+    builder.build()
+}
+
+fn pin_or_warn(cores: &[usize]) {
+    if let Err(e) = pin_current_thread(cores) {
+        warn!("Failed to pin {:?} to cores {:?}: {}", std::thread::current().name(), cores, e);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    if let Some(core) = cores.iter().find(|&&core| core >= libc::CPU_SETSIZE as usize) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("core {} is out of range", core)));
+    }
+    // SAFETY: the set is a plain bitmask and every index was checked against its size
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &core in cores {
+            libc::CPU_SET(core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cores: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "CPU pinning is only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::ThreadId;
+
+    struct Probe;
+
+    impl Actor for Probe {
+        type Context = Context<Self>;
+    }
+
+    #[derive(Message)]
+    #[rtype(result = "ThreadId")]
+    struct WhereAmI;
+
+    impl Handler<WhereAmI> for Probe {
+        type Result = MessageResult<WhereAmI>;
+
+        fn handle(&mut self, _msg: WhereAmI, _ctx: &mut Self::Context) -> Self::Result {
+            MessageResult(std::thread::current().id())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_actors_follow_their_placement() {
+        let here = std::thread::current().id();
+
+        let mut single = Topology::new(&RuntimeConfig::default());
+        assert_eq!(single.start(Placement::Validation, Probe).send(WhereAmI).await.unwrap(), here);
+        assert_eq!(single.start(Placement::Worker, Probe).send(WhereAmI).await.unwrap(), here);
+        single.stop();
+
+        let config = RuntimeConfig { arbiters: 4, ..Default::default() };
+        let mut topology = Topology::new(&config);
+        let validation = topology.start(Placement::Validation, Probe).send(WhereAmI).await.unwrap();
+        let first = topology.start(Placement::Worker, Probe).send(WhereAmI).await.unwrap();
+        let second = topology.start(Placement::Worker, Probe).send(WhereAmI).await.unwrap();
+        let third = topology.start(Placement::Worker, Probe).send(WhereAmI).await.unwrap();
+        assert_ne!(validation, here);
+        assert!(![here, validation].contains(&first));
+        assert_ne!(first, second);
+        // Two workers, so the third actor shares the first one's arbiter
+        assert_eq!(first, third);
+        topology.stop();
+    }
+
+    #[test]
+    fn test_runtime_runs_blocking_work() {
+        let runtime = tokio_runtime(1, Vec::new()).unwrap();
+        let sum = runtime.block_on(async { tokio::task::spawn_blocking(|| 2 + 2).await.unwrap() });
+        assert_eq!(sum, 4);
+        assert!(pin_current_thread(&[usize::MAX]).is_err());
+    }
+}
@@ -0,0 +1,519 @@
+//! Uniform Resources (BCR-2020-005) for animated QR codes
+//!
+//! Air-gapped signers such as SeedSigner and Passport exchange PSBTs as
+//! `ur:crypto-psbt` strings: the CBOR payload is split into fixed size
+//! fragments, wrapped in minimal bytewords and, past the first pass, mixed
+//! with the fountain code so a scanner can recover from missed frames.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::psbt::Psbt;
+
+use crate::error::{UrError, UrResult};
+
+/// UR type air-gapped wallets use for PSBTs
+pub const PSBT_UR_TYPE: &str = "crypto-psbt";
+
+/// Newer registry name for the same payload, accepted when decoding
+const PSBT_UR_TYPE_V2: &str = "psbt";
+
+pub const DEFAULT_MAX_FRAGMENT_LEN: usize = 200;
+
+const BYTEWORDS: [&str; 256] = [
+    "able", "acid", "also", "apex", "aqua", "arch", "atom", "aunt", "away", "axis", "back", "bald", "barn", "belt", "beta", "bias",
+    "blue", "body", "brag", "brew", "bulb", "buzz", "calm", "cash", "cats", "chef", "city", "claw", "code", "cola", "cook", "cost",
+    "crux", "curl", "cusp", "cyan", "dark", "data", "days", "deli", "dice", "diet", "door", "down", "draw", "drop", "drum", "dull",
+    "duty", "each", "easy", "echo", "edge", "epic", "even", "exam", "exit", "eyes", "fact", "fair", "fern", "figs", "film", "fish",
+    "fizz", "flap", "flew", "flux", "foxy", "free", "frog", "fuel", "fund", "gala", "game", "gear", "gems", "gift", "girl", "glow",
+    "good", "gray", "grim", "guru", "gush", "gyro", "half", "hang", "hard", "hawk", "heat", "help", "high", "hill", "holy", "hope",
+    "horn", "huts", "iced", "idea", "idle", "inch", "inky", "into", "iris", "iron", "item", "jade", "jazz", "join", "jolt", "jowl",
+    "judo", "jugs", "jump", "junk", "jury", "keep", "keno", "kept", "keys", "kick", "kiln", "king", "kite", "kiwi", "knob", "lamb",
+    "lava", "lazy", "leaf", "legs", "liar", "limp", "lion", "list", "logo", "loud", "love", "luau", "luck", "lung", "main", "many",
+    "math", "maze", "memo", "menu", "meow", "mild", "mint", "miss", "monk", "nail", "navy", "need", "news", "next", "noon", "note",
+    "numb", "obey", "oboe", "omit", "onyx", "open", "oval", "owls", "paid", "part", "peck", "play", "plus", "poem", "pool", "pose",
+    "puff", "puma", "purr", "quad", "quiz", "race", "ramp", "real", "redo", "rich", "road", "rock", "roof", "ruby", "ruin", "runs",
+    "rust", "safe", "saga", "scar", "sets", "silk", "skew", "slot", "soap", "solo", "song", "stub", "surf", "swan", "taco", "task",
+    "taxi", "tent", "tied", "time", "tiny", "toil", "tomb", "toys", "trip", "tuna", "twin", "ugly", "undo", "unit", "urge", "user",
+    "vast", "very", "veto", "vial", "vibe", "view", "visa", "void", "vows", "wall", "wand", "warm", "wasp", "wave", "waxy", "webs",
+    "what", "when", "whiz", "wolf", "work", "yank", "yawn", "yell", "yoga", "yurt", "zaps", "zero", "zest", "zinc", "zone", "zoom",
+];
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Minimal bytewords: first and last letter of each word, with a CRC32 suffix
+fn bytewords_encode(data: &[u8]) -> String {
+    let checksum = crc32(data).to_be_bytes();
+    data.iter()
+        .chain(checksum.iter())
+        .flat_map(|byte| {
+            let word = BYTEWORDS[*byte as usize].as_bytes();
+            [word[0] as char, word[3] as char]
+        })
+        .collect()
+}
+
+fn bytewords_decode(text: &str) -> UrResult<Vec<u8>> {
+    let text = text.to_ascii_lowercase();
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(UrError::Bytewords);
+    }
+    let mut bytes = text
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            BYTEWORDS
+                .iter()
+                .position(|word| word.as_bytes()[0] == pair[0] && word.as_bytes()[3] == pair[1])
+                .map(|index| index as u8)
+                .ok_or(UrError::Bytewords)
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    if bytes.len() < 4 {
+        return Err(UrError::Bytewords);
+    }
+    let checksum = bytes.split_off(bytes.len() - 4);
+    if crc32(&bytes).to_be_bytes()[..] != checksum[..] {
+        return Err(UrError::Checksum);
+    }
+    Ok(bytes)
+}
+
+fn cbor_header(major: u8, value: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend([major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_bytes(data: &[u8], out: &mut Vec<u8>) {
+    cbor_header(2, data.len() as u64, out);
+    out.extend_from_slice(data);
+}
+
+/// Reads CBOR items of the few kinds UR parts use
+struct CborReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CborReader<'a> {
+    fn header(&mut self, expected_major: u8) -> UrResult<u64> {
+        let (&first, rest) = self.data.split_first().ok_or(UrError::Cbor)?;
+        if first >> 5 != expected_major {
+            return Err(UrError::Cbor);
+        }
+        let width = match first & 0x1f {
+            info @ 0..=23 => {
+                self.data = rest;
+                return Ok(info as u64);
+            }
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(UrError::Cbor),
+        };
+        if rest.len() < width {
+            return Err(UrError::Cbor);
+        }
+        let value = rest[..width].iter().fold(0u64, |value, byte| (value << 8) | *byte as u64);
+        self.data = &rest[width..];
+        Ok(value)
+    }
+
+    fn unsigned(&mut self) -> UrResult<u64> {
+        self.header(0)
+    }
+
+    fn bytes(&mut self) -> UrResult<&'a [u8]> {
+        let len = self.header(2)? as usize;
+        if self.data.len() < len {
+            return Err(UrError::Cbor);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+}
+
+/// Xoshiro256** seeded from a SHA-256 digest, as the fountain code specifies
+struct Xoshiro256 {
+    state: [u64; 4],
+}
+
+impl Xoshiro256 {
+    fn new(seed: &[u8]) -> Self {
+        let digest = sha256::Hash::hash(seed).to_byte_array();
+        let mut state = [0u64; 4];
+        for (word, chunk) in state.iter_mut().zip(digest.chunks(8)) {
+            *word = u64::from_be_bytes(chunk.try_into().expect("8 byte chunk"));
+        }
+        Self { state }
+    }
+
+    fn next(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    fn next_double(&mut self) -> f64 {
+        self.next() as f64 / (u64::MAX as f64 + 1.0)
+    }
+
+    fn next_int(&mut self, low: u64, high: u64) -> u64 {
+        (self.next_double() * (high - low + 1) as f64) as u64 + low
+    }
+
+    /// Vose alias sampling of a degree in 1..=count weighted by 1/degree
+    fn choose_degree(&mut self, count: usize) -> usize {
+        let total: f64 = (1..=count).map(|degree| 1.0 / degree as f64).sum();
+        let mut weights: Vec<f64> = (1..=count).map(|degree| count as f64 / (degree as f64 * total)).collect();
+
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (Vec::new(), Vec::new());
+        for index in (0..count).rev() {
+            if weights[index] < 1.0 { small.push(index) } else { large.push(index) }
+        }
+        let mut probs = vec![0.0; count];
+        let mut aliases = vec![0; count];
+        while let (Some(&less), Some(&more)) = (small.last(), large.last()) {
+            small.pop();
+            large.pop();
+            probs[less] = weights[less];
+            aliases[less] = more;
+            weights[more] += weights[less] - 1.0;
+            if weights[more] < 1.0 { small.push(more) } else { large.push(more) }
+        }
+        for index in large.into_iter().chain(small) {
+            probs[index] = 1.0;
+        }
+
+        let r1 = self.next_double();
+        let r2 = self.next_double();
+        let index = (count as f64 * r1) as usize;
+        let chosen = if r2 < probs[index] { index } else { aliases[index] };
+        chosen + 1
+    }
+
+    fn shuffled(&mut self, mut items: Vec<usize>) -> Vec<usize> {
+        let mut shuffled = Vec::with_capacity(items.len());
+        while !items.is_empty() {
+            let index = self.next_int(0, items.len() as u64 - 1) as usize;
+            shuffled.push(items.remove(index));
+        }
+        shuffled
+    }
+}
+
+/// Fragment indexes XORed together in part `sequence`
+fn choose_fragments(sequence: u32, fragment_count: usize, checksum: u32) -> BTreeSet<usize> {
+    if sequence as usize <= fragment_count {
+        return BTreeSet::from([sequence as usize - 1]);
+    }
+    let mut seed = sequence.to_be_bytes().to_vec();
+    seed.extend(checksum.to_be_bytes());
+    let mut rng = Xoshiro256::new(&seed);
+    let degree = rng.choose_degree(fragment_count);
+    rng.shuffled((0..fragment_count).collect()).into_iter().take(degree).collect()
+}
+
+fn xor_into(target: &mut [u8], other: &[u8]) {
+    for (a, b) in target.iter_mut().zip(other) {
+        *a ^= b;
+    }
+}
+
+/// Encode `message` (already CBOR) as UR strings. A message that fits in one
+/// fragment becomes a single-part UR; otherwise `part_count` parts are
+/// produced, where parts past the fragment count are fountain-mixed.
+pub fn encode(ur_type: &str, message: &[u8], max_fragment_len: usize, part_count: Option<usize>) -> Vec<String> {
+    let max_fragment_len = max_fragment_len.max(1);
+    if message.len() <= max_fragment_len {
+        return vec![format!("ur:{}/{}", ur_type, bytewords_encode(message))];
+    }
+
+    let fragment_count = message.len().div_ceil(max_fragment_len);
+    let fragment_len = message.len().div_ceil(fragment_count);
+    let mut padded = message.to_vec();
+    padded.resize(fragment_len * fragment_count, 0);
+    let fragments: Vec<&[u8]> = padded.chunks(fragment_len).collect();
+    let checksum = crc32(message);
+
+    (1..=part_count.unwrap_or(fragment_count).max(1) as u32)
+        .map(|sequence| {
+            let mut data = vec![0u8; fragment_len];
+            for index in choose_fragments(sequence, fragment_count, checksum) {
+                xor_into(&mut data, fragments[index]);
+            }
+
+            let mut part = Vec::with_capacity(fragment_len + 24);
+            cbor_header(4, 5, &mut part);
+            cbor_header(0, sequence as u64, &mut part);
+            cbor_header(0, fragment_count as u64, &mut part);
+            cbor_header(0, message.len() as u64, &mut part);
+            cbor_header(0, checksum as u64, &mut part);
+            cbor_bytes(&data, &mut part);
+            format!("ur:{}/{}-{}/{}", ur_type, sequence, fragment_count, bytewords_encode(&part))
+        })
+        .collect()
+}
+
+/// Parameters every part of one multi-part message shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MessageShape {
+    fragment_count: usize,
+    message_len: usize,
+    checksum: u32,
+    fragment_len: usize,
+}
+
+/// Collects scanned UR parts, in any order and with repeats, until the message is complete
+#[derive(Debug, Default)]
+pub struct UrDecoder {
+    ur_type: Option<String>,
+    shape: Option<MessageShape>,
+    fragments: BTreeMap<usize, Vec<u8>>,
+    mixed: Vec<(BTreeSet<usize>, Vec<u8>)>,
+    message: Option<Vec<u8>>,
+}
+
+impl UrDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ur_type(&self) -> Option<&str> {
+        self.ur_type.as_deref()
+    }
+
+    /// Simple fragments recovered so far and the total needed
+    pub fn progress(&self) -> (usize, usize) {
+        match self.shape {
+            Some(shape) => (self.fragments.len(), shape.fragment_count),
+            None => (usize::from(self.message.is_some()), 1),
+        }
+    }
+
+    /// The CBOR message once every fragment is known
+    pub fn message(&self) -> Option<&[u8]> {
+        self.message.as_deref()
+    }
+
+    pub fn receive(&mut self, part: &str) -> UrResult<()> {
+        let part = part.trim().to_ascii_lowercase();
+        let body = part.strip_prefix("ur:").ok_or_else(|| UrError::Malformed("missing ur: prefix".to_string()))?;
+        let segments: Vec<&str> = body.split('/').collect();
+        let (ur_type, sequence, payload) = match segments.as_slice() {
+            [ur_type, payload] => (*ur_type, None, *payload),
+            [ur_type, sequence, payload] => (*ur_type, Some(*sequence), *payload),
+            _ => return Err(UrError::Malformed("expected ur:<type>/[<seq>-<count>/]<data>".to_string())),
+        };
+        match &self.ur_type {
+            Some(known) if known != ur_type => return Err(UrError::Inconsistent),
+            Some(_) => {}
+            None => self.ur_type = Some(ur_type.to_string()),
+        }
+        if self.message.is_some() {
+            return Ok(());
+        }
+
+        let data = bytewords_decode(payload)?;
+        if sequence.is_none() {
+            self.message = Some(data);
+            return Ok(());
+        }
+
+        let mut reader = CborReader { data: &data };
+        if reader.header(4)? != 5 {
+            return Err(UrError::Cbor);
+        }
+        let sequence = reader.unsigned()?;
+        let shape = MessageShape {
+            fragment_count: reader.unsigned()? as usize,
+            message_len: reader.unsigned()? as usize,
+            checksum: u32::try_from(reader.unsigned()?).map_err(|_| UrError::Cbor)?,
+            fragment_len: 0,
+        };
+        let fragment = reader.bytes()?.to_vec();
+        let shape = MessageShape { fragment_len: fragment.len(), ..shape };
+        if sequence == 0 || shape.fragment_count == 0 || shape.fragment_len * shape.fragment_count < shape.message_len {
+            return Err(UrError::Cbor);
+        }
+        match self.shape {
+            Some(known) if known != shape => return Err(UrError::Inconsistent),
+            Some(_) => {}
+            None => self.shape = Some(shape),
+        }
+
+        let sequence = u32::try_from(sequence).map_err(|_| UrError::Cbor)?;
+        let indexes = choose_fragments(sequence, shape.fragment_count, shape.checksum);
+        self.mixed.push((indexes, fragment));
+        self.reduce();
+
+        if self.fragments.len() == shape.fragment_count {
+            let mut message: Vec<u8> = self.fragments.values().flatten().copied().collect();
+            message.truncate(shape.message_len);
+            if crc32(&message) != shape.checksum {
+                return Err(UrError::Checksum);
+            }
+            self.message = Some(message);
+        }
+        Ok(())
+    }
+
+    /// Peel known fragments out of mixed parts until no more single fragments fall out
+    fn reduce(&mut self) {
+        loop {
+            let mut progress = false;
+            for (indexes, data) in &mut self.mixed {
+                let known: Vec<usize> = indexes.iter().copied().filter(|index| self.fragments.contains_key(index)).collect();
+                for index in known {
+                    xor_into(data, &self.fragments[&index]);
+                    indexes.remove(&index);
+                }
+            }
+            let (simple, mixed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.mixed)
+                .into_iter()
+                .filter(|(indexes, _)| !indexes.is_empty())
+                .partition(|(indexes, _)| indexes.len() == 1);
+            self.mixed = mixed;
+            for (indexes, data) in simple {
+                let index = *indexes.first().expect("one index");
+                progress |= self.fragments.insert(index, data).is_none();
+            }
+
+            // A mixed part containing another mixed part can drop it
+            for i in 0..self.mixed.len() {
+                for j in 0..self.mixed.len() {
+                    if i == j || !self.mixed[j].0.is_subset(&self.mixed[i].0) || self.mixed[j].0 == self.mixed[i].0 {
+                        continue;
+                    }
+                    let (subset, data) = self.mixed[j].clone();
+                    let (indexes, target) = &mut self.mixed[i];
+                    xor_into(target, &data);
+                    indexes.retain(|index| !subset.contains(index));
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+    }
+}
+
+/// PSBT as `ur:crypto-psbt` parts
+pub fn encode_psbt(psbt: &Psbt, max_fragment_len: usize, part_count: Option<usize>) -> Vec<String> {
+    let mut message = Vec::new();
+    cbor_bytes(&psbt.serialize(), &mut message);
+    encode(PSBT_UR_TYPE, &message, max_fragment_len, part_count)
+}
+
+/// PSBT carried by a completed decoder
+pub fn decode_psbt(decoder: &UrDecoder) -> UrResult<Option<Psbt>> {
+    let Some(message) = decoder.message() else {
+        return Ok(None);
+    };
+    match decoder.ur_type() {
+        Some(PSBT_UR_TYPE) | Some(PSBT_UR_TYPE_V2) => {}
+        other => return Err(UrError::UnexpectedType(other.unwrap_or_default().to_string())),
+    }
+    let mut reader = CborReader { data: message };
+    let bytes = reader.bytes()?;
+    Psbt::deserialize(bytes).map(Some).map_err(|e| UrError::Psbt(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test message from the reference implementation
+    fn wolf_message(len: usize) -> Vec<u8> {
+        let mut rng = Xoshiro256::new(b"Wolf");
+        (0..len).map(|_| rng.next_int(0, 255) as u8).collect()
+    }
+
+    fn sample_psbt() -> Psbt {
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default(); 3],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(50_000),
+                script_pubkey: bitcoin::ScriptBuf::from_bytes(vec![0x00, 0x14].into_iter().chain([7u8; 20]).collect()),
+            }],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    #[test]
+    fn test_bytewords_vector() {
+        assert_eq!(bytewords_encode(&[0, 1, 2, 128, 255]), "aeadaolazmjendeoti");
+        assert_eq!(bytewords_decode("AEADAOLAZMJENDEOTI").unwrap(), vec![0, 1, 2, 128, 255]);
+        assert!(matches!(bytewords_decode("aeadaolazmjendeotj"), Err(UrError::Bytewords)));
+        assert!(matches!(bytewords_decode("aeadaolazmjendeoto"), Err(UrError::Checksum)));
+    }
+
+    #[test]
+    fn test_multipart_matches_reference() {
+        let mut message = Vec::new();
+        cbor_bytes(&wolf_message(256), &mut message);
+        let parts = encode("bytes", &message, 30, None);
+
+        assert_eq!(parts.len(), 9);
+        assert_eq!(parts[0], "ur:bytes/1-9/lpadascfadaxcywenbpljkhdcahkadaemejtswhhylkepmykhhtsytsnoyoyaxaedsuttydmmhhpktpmsrjtdkgslpgh");
+    }
+
+    #[test]
+    fn test_fountain_parts_recover_missing_fragments() {
+        let psbt = sample_psbt();
+        let parts = encode_psbt(&psbt, 20, Some(60));
+        let fragment_count: usize = parts[0].split('/').nth(1).unwrap().split('-').nth(1).unwrap().parse().unwrap();
+        assert!(fragment_count > 2);
+
+        // Skip the first two simple fragments so only mixed parts can fill them in
+        let mut decoder = UrDecoder::new();
+        for part in parts.iter().skip(2) {
+            decoder.receive(part).unwrap();
+            if decoder.message().is_some() {
+                break;
+            }
+        }
+        assert_eq!(decode_psbt(&decoder).unwrap(), Some(psbt.clone()));
+
+        let single = encode_psbt(&psbt, DEFAULT_MAX_FRAGMENT_LEN, None);
+        assert_eq!(single.len(), 1);
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&single[0].to_uppercase()).unwrap();
+        assert_eq!(decode_psbt(&decoder).unwrap(), Some(psbt));
+    }
+}
@@ -0,0 +1,318 @@
+//! Per-endpoint API usage accounting
+//!
+//! Every REST request is recorded against its route pattern, so
+//! `/api/v1/block/{hash}` is one endpoint however many hashes are asked
+//! for. Counts, error rates and latency percentiles cover a rolling window
+//! of `api.usage.window_secs` and are served at `/api/v1/admin/usage`.
+//! Endpoints can be given p95 latency and error rate objectives; with
+//! `slo_events` on, an endpoint that starts missing one publishes an
+//! `SloBreached` event, once until it recovers.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{Config, UsageConfig};
+use crate::events::{BitcoinEventType, EventManager};
+
+/// Requests kept per endpoint, so a flood cannot grow the window without bound
+const MAX_SAMPLES_PER_ENDPOINT: usize = 10_000;
+
+/// How often objectives are checked when `slo_events` is on
+const SLO_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Key for requests that matched no route
+pub const UNMATCHED: &str = "unmatched";
+
+struct Sample {
+    at: Instant,
+    latency: Duration,
+    status: u16,
+}
+
+#[derive(Default)]
+struct EndpointWindow {
+    samples: VecDeque<Sample>,
+    /// Requests since startup, including those outside the window
+    total: u64,
+    /// Objectives an `SloBreached` event has been published for and not yet recovered
+    breached: HashSet<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EndpointUsage {
+    /// Method and route, e.g. `GET /api/v1/block/{hash}`
+    pub endpoint: String,
+    pub requests: u64,
+    pub total_requests: u64,
+    pub requests_per_minute: f64,
+    /// 5xx responses
+    pub server_errors: u64,
+    /// 4xx responses, including rate limited ones
+    pub client_errors: u64,
+    pub error_rate: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub latency_slo_ms: Option<u64>,
+    /// Objectives the endpoint misses over the current window
+    pub breaching: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub window_secs: u64,
+    /// Busiest first
+    pub endpoints: Vec<EndpointUsage>,
+}
+
+/// An objective an endpoint has just started missing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breach {
+    pub endpoint: String,
+    pub objective: &'static str,
+    pub value: f64,
+    pub target: f64,
+}
+
+fn millis(latency: Duration) -> f64 {
+    latency.as_micros() as f64 / 1000.0
+}
+
+/// Nearest-rank percentile of sorted latencies, in milliseconds
+fn percentile(sorted: &[Duration], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    millis(sorted[rank - 1])
+}
+
+/// Shared by every API worker
+#[derive(Clone)]
+pub struct UsageTracker {
+    config: Arc<UsageConfig>,
+    endpoints: Arc<Mutex<HashMap<String, EndpointWindow>>>,
+}
+
+impl UsageTracker {
+    pub fn new(config: &UsageConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            endpoints: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs.max(1))
+    }
+
+    pub fn record(&self, endpoint: String, status: u16, latency: Duration) {
+        self.record_at(endpoint, status, latency, Instant::now());
+    }
+
+    fn record_at(&self, endpoint: String, status: u16, latency: Duration, now: Instant) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let window = endpoints.entry(endpoint).or_default();
+        window.total += 1;
+        if window.samples.len() == MAX_SAMPLES_PER_ENDPOINT {
+            window.samples.pop_front();
+        }
+        window.samples.push_back(Sample { at: now, latency, status });
+    }
+
+    /// The p95 objective for an endpoint, its own before the global one
+    fn latency_slo_ms(&self, endpoint: &str) -> Option<u64> {
+        let route = endpoint.split_once(' ').map(|(_, route)| route).unwrap_or(endpoint);
+        self.config.endpoint_latency_slo_ms.get(route).copied().or(self.config.latency_slo_ms)
+    }
+
+    /// Objectives `usage` misses, with the measured value and the target
+    fn missed(&self, usage: &EndpointUsage) -> Vec<(&'static str, f64, f64)> {
+        if usage.requests < self.config.min_requests {
+            return Vec::new();
+        }
+        let mut missed = Vec::new();
+        if let Some(target) = usage.latency_slo_ms {
+            if usage.p95_ms > target as f64 {
+                missed.push(("p95_latency_ms", usage.p95_ms, target as f64));
+            }
+        }
+        if let Some(target) = self.config.error_rate_slo {
+            if usage.error_rate > target {
+                missed.push(("error_rate", usage.error_rate, target));
+            }
+        }
+        missed
+    }
+
+    fn usage(&self, endpoint: &str, window: &EndpointWindow) -> EndpointUsage {
+        let mut latencies: Vec<Duration> = window.samples.iter().map(|sample| sample.latency).collect();
+        latencies.sort_unstable();
+        let requests = window.samples.len() as u64;
+        let server_errors = window.samples.iter().filter(|sample| sample.status >= 500).count() as u64;
+        let client_errors = window.samples.iter().filter(|sample| (400..500).contains(&sample.status)).count() as u64;
+        let mut usage = EndpointUsage {
+            endpoint: endpoint.to_string(),
+            requests,
+            total_requests: window.total,
+            requests_per_minute: requests as f64 * 60.0 / self.window().as_secs_f64(),
+            server_errors,
+            client_errors,
+            error_rate: if requests == 0 { 0.0 } else { server_errors as f64 / requests as f64 },
+            p50_ms: percentile(&latencies, 0.50),
+            p95_ms: percentile(&latencies, 0.95),
+            p99_ms: percentile(&latencies, 0.99),
+            max_ms: latencies.last().copied().map(millis).unwrap_or(0.0),
+            latency_slo_ms: self.latency_slo_ms(endpoint),
+            breaching: Vec::new(),
+        };
+        usage.breaching = self.missed(&usage).into_iter().map(|(objective, _, _)| objective).collect();
+        usage
+    }
+
+    /// Drop requests that have left the window
+    fn expire(&self, endpoints: &mut HashMap<String, EndpointWindow>, now: Instant) {
+        let window = self.window();
+        for endpoint in endpoints.values_mut() {
+            while endpoint.samples.front().is_some_and(|sample| now.duration_since(sample.at) > window) {
+                endpoint.samples.pop_front();
+            }
+        }
+    }
+
+    pub fn report(&self) -> UsageReport {
+        self.report_at(Instant::now())
+    }
+
+    fn report_at(&self, now: Instant) -> UsageReport {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        self.expire(&mut endpoints, now);
+        let mut usage: Vec<EndpointUsage> = endpoints.iter()
+            .map(|(endpoint, window)| self.usage(endpoint, window))
+            .collect();
+        usage.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.endpoint.cmp(&b.endpoint)));
+        UsageReport { window_secs: self.config.window_secs, endpoints: usage }
+    }
+
+    /// Objectives endpoints have started missing since the last check
+    fn check_slos(&self, now: Instant) -> Vec<Breach> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        self.expire(&mut endpoints, now);
+        let mut breaches = Vec::new();
+        for (endpoint, window) in endpoints.iter_mut() {
+            let missed = self.missed(&self.usage(endpoint, window));
+            let recovered: Vec<&'static str> = window.breached.iter()
+                .filter(|objective| !missed.iter().any(|(missed, _, _)| missed == *objective))
+                .copied()
+                .collect();
+            for objective in recovered {
+                info!("{} meets its {} objective again", endpoint, objective);
+                window.breached.remove(objective);
+            }
+            for (objective, value, target) in missed {
+                if window.breached.insert(objective) {
+                    breaches.push(Breach { endpoint: endpoint.clone(), objective, value, target });
+                }
+            }
+        }
+        breaches
+    }
+
+    /// Publish an `SloBreached` event for each objective an endpoint starts missing
+    pub async fn run_slo_checks(self, events: EventManager, config: Config) {
+        let mut interval = tokio::time::interval(SLO_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            for breach in self.check_slos(Instant::now()) {
+                warn!("{} is missing its {} objective: {:.3} against {:.3}", breach.endpoint, breach.objective, breach.value, breach.target);
+                let event = BitcoinEventType::SloBreached {
+                    endpoint: breach.endpoint,
+                    objective: breach.objective.to_string(),
+                    value: breach.value,
+                    target: breach.target,
+                    window_secs: self.config.window_secs,
+                };
+                if let Err(e) = events.publish(event, config.network.as_str(), &config.events.k8s.node_name).await {
+                    warn!("Failed to publish SLO breach: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(config: UsageConfig) -> UsageTracker {
+        UsageTracker::new(&UsageConfig { window_secs: 60, min_requests: 10, ..config })
+    }
+
+    #[test]
+    fn test_usage_window_and_percentiles() {
+        let tracker = tracker(UsageConfig::default());
+        let start = Instant::now();
+        for ms in 1..=100 {
+            let status = if ms % 10 == 0 { 500 } else if ms % 25 == 0 { 429 } else { 200 };
+            tracker.record_at("GET /api/v1/block/{hash}".to_string(), status, Duration::from_millis(ms), start);
+        }
+        tracker.record_at("GET /api/v1/info".to_string(), 200, Duration::from_millis(1), start);
+
+        let report = tracker.report_at(start);
+        let block = &report.endpoints[0];
+        assert_eq!(block.endpoint, "GET /api/v1/block/{hash}");
+        assert_eq!((block.requests, block.server_errors, block.client_errors), (100, 10, 2));
+        assert_eq!(block.error_rate, 0.1);
+        assert_eq!((block.p50_ms, block.p95_ms, block.p99_ms, block.max_ms), (50.0, 95.0, 99.0, 100.0));
+        assert_eq!(block.requests_per_minute, 100.0);
+
+        // Requests age out of the window but stay in the total
+        tracker.record_at("GET /api/v1/info".to_string(), 200, Duration::from_millis(1), start + Duration::from_secs(61));
+        let report = tracker.report_at(start + Duration::from_secs(61));
+        let info = report.endpoints.iter().find(|usage| usage.endpoint == "GET /api/v1/info").unwrap();
+        assert_eq!((info.requests, info.total_requests), (1, 2));
+        assert_eq!(report.endpoints.iter().find(|usage| usage.endpoint.contains("block")).unwrap().requests, 0);
+    }
+
+    #[test]
+    fn test_slo_breaches_fire_once_until_recovered() {
+        let mut endpoint_latency_slo_ms = std::collections::BTreeMap::new();
+        endpoint_latency_slo_ms.insert("/api/v1/block/{hash}".to_string(), 50);
+        let tracker = tracker(UsageConfig {
+            latency_slo_ms: Some(500),
+            endpoint_latency_slo_ms,
+            error_rate_slo: Some(0.05),
+            ..UsageConfig::default()
+        });
+        let start = Instant::now();
+        for _ in 0..20 {
+            tracker.record_at("GET /api/v1/block/{hash}".to_string(), 200, Duration::from_millis(80), start);
+            tracker.record_at("GET /api/v1/info".to_string(), 200, Duration::from_millis(80), start);
+        }
+        // Too few requests to judge yet
+        tracker.record_at("GET /api/v1/stats".to_string(), 500, Duration::from_millis(1), start);
+
+        let breaches = tracker.check_slos(start);
+        assert_eq!(breaches, vec![Breach {
+            endpoint: "GET /api/v1/block/{hash}".to_string(),
+            objective: "p95_latency_ms",
+            value: 80.0,
+            target: 50.0,
+        }]);
+        assert!(tracker.check_slos(start).is_empty());
+        assert_eq!(tracker.report_at(start).endpoints[0].breaching, vec!["p95_latency_ms"]);
+
+        // Once the slow requests leave the window it recovers, and can breach again
+        let later = start + Duration::from_secs(61);
+        assert!(tracker.check_slos(later).is_empty());
+        for _ in 0..20 {
+            tracker.record_at("GET /api/v1/block/{hash}".to_string(), 200, Duration::from_millis(80), later);
+        }
+        assert_eq!(tracker.check_slos(later).len(), 1);
+    }
+}
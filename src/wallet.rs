@@ -0,0 +1,999 @@
+//! Watch-only HD wallets that fund PSBTs
+//!
+//! A wallet is an account xpub (with optional key origin) plus the addresses
+//! handed out from it. The node never holds private keys: funding builds an
+//! unsigned PSBT carrying BIP32 derivations, which an external signer
+//! completes, so coin selection stays on the node and signing stays air-gapped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource, Xpub};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::{absolute, transaction, Address, Amount, Block, Denomination, Network, OutPoint, ScriptBuf, Sequence, SignedAmount, Transaction, TxIn, TxOut, Txid, Witness};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::actors::mempool::MIN_RELAY_FEE_RATE;
+use crate::amount::FeeRate;
+use crate::error::{WalletError, WalletResult};
+
+/// Confirmations before a coinbase output can be spent
+pub const COINBASE_MATURITY: u32 = 100;
+
+pub const RECEIVE_CHAIN: u32 = 0;
+pub const CHANGE_CHAIN: u32 = 1;
+
+/// Non-witness bytes every transaction pays for, rounded up from 10.5 vbytes
+const TX_OVERHEAD_VSIZE: u64 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    /// P2WPKH
+    Bech32,
+    /// P2TR key path, using the derived key as the internal key
+    Bech32m,
+}
+
+impl AddressKind {
+    /// Spending vsize of one input of this kind, signature included
+    fn input_vsize(self) -> u64 {
+        match self {
+            AddressKind::Bech32 => 68,
+            AddressKind::Bech32m => 58,
+        }
+    }
+}
+
+impl FromStr for AddressKind {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> WalletResult<Self> {
+        match s {
+            "bech32" => Ok(AddressKind::Bech32),
+            "bech32m" => Ok(AddressKind::Bech32m),
+            other => Err(WalletError::InvalidAddressType(other.to_string())),
+        }
+    }
+}
+
+/// Account xpub in descriptor key form, `[fingerprint/path]xpub`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountKey {
+    pub xpub: Xpub,
+    pub origin: KeySource,
+}
+
+impl FromStr for AccountKey {
+    type Err = WalletError;
+
+    fn from_str(s: &str) -> WalletResult<Self> {
+        let invalid = |reason: String| WalletError::InvalidKey(reason);
+        let (origin, xpub) = match s.strip_prefix('[') {
+            Some(rest) => {
+                let (origin, xpub) = rest.split_once(']').ok_or_else(|| invalid("unterminated key origin".to_string()))?;
+                let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+                let fingerprint = Fingerprint::from_str(fingerprint).map_err(|e| invalid(e.to_string()))?;
+                let path = DerivationPath::from_str(format!("m/{}", path).trim_end_matches('/'))
+                    .map_err(|e| invalid(e.to_string()))?;
+                (Some((fingerprint, path)), xpub)
+            }
+            None => (None, s),
+        };
+        let xpub = Xpub::from_str(xpub).map_err(|e| invalid(e.to_string()))?;
+        // Without an origin the xpub itself is treated as the root
+        let origin = origin.unwrap_or_else(|| (xpub.fingerprint(), DerivationPath::master()));
+        Ok(Self { xpub, origin })
+    }
+}
+
+impl AccountKey {
+    /// Public key at `chain/index` below the account, with its full origin
+    pub fn key_at(&self, chain: u32, index: u32) -> WalletResult<(PublicKey, KeySource)> {
+        let path = [
+            ChildNumber::from_normal_idx(chain).map_err(|e| WalletError::InvalidKey(e.to_string()))?,
+            ChildNumber::from_normal_idx(index).map_err(|e| WalletError::InvalidKey(e.to_string()))?,
+        ];
+        let derived = self.xpub.derive_pub(&Secp256k1::verification_only(), &path)
+            .map_err(|e| WalletError::InvalidKey(e.to_string()))?;
+        let (fingerprint, origin_path) = &self.origin;
+        Ok((derived.public_key, (*fingerprint, origin_path.extend(path))))
+    }
+
+    pub fn address(&self, chain: u32, index: u32, kind: AddressKind, network: Network) -> WalletResult<Address> {
+        let (public_key, _) = self.key_at(chain, index)?;
+        match kind {
+            AddressKind::Bech32 => Address::p2wpkh(&bitcoin::PublicKey::new(public_key), network)
+                .map_err(|e| WalletError::InvalidKey(e.to_string())),
+            AddressKind::Bech32m => Ok(Address::p2tr(&Secp256k1::verification_only(), public_key.x_only_public_key().0, None, network)),
+        }
+    }
+}
+
+impl fmt::Display for AccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (fingerprint, path) = &self.origin;
+        let path = path.to_string();
+        let path = path.trim_start_matches('m');
+        write!(f, "[{}{}]{}", fingerprint, path, self.xpub)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletAddress {
+    pub address: String,
+    pub chain: u32,
+    pub index: u32,
+    pub kind: AddressKind,
+    pub label: Option<String>,
+    /// First confirmed payment to this address (`txid:vout`); any other coin on it is dirty
+    #[serde(default)]
+    pub first_funding: Option<String>,
+}
+
+impl WalletAddress {
+    pub fn is_change(&self) -> bool {
+        self.chain == CHANGE_CHAIN
+    }
+
+    /// Seen paid on-chain at least once
+    pub fn is_used(&self) -> bool {
+        self.first_funding.is_some()
+    }
+}
+
+/// Persisted wallet state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wallet {
+    pub name: String,
+    pub account_key: String,
+    pub default_kind: AddressKind,
+    /// Keep coins on reused addresses out of coin selection unless asked for
+    #[serde(default)]
+    pub avoid_reuse: bool,
+    /// Next unused index on the receive and change chains
+    pub next_index: [u32; 2],
+    pub addresses: Vec<WalletAddress>,
+    pub created_at: DateTime<Utc>,
+    /// Confirmed transactions touching the wallet, in chain order
+    #[serde(default)]
+    pub history: Vec<WalletTx>,
+    /// Free-form bookkeeping notes keyed by txid
+    #[serde(default)]
+    pub notes: BTreeMap<String, String>,
+}
+
+/// Wallet output created by a transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletCredit {
+    pub vout: u32,
+    pub value: u64,
+    /// Index into the wallet's `addresses`
+    pub address: usize,
+}
+
+/// Confirmed transaction paying or spending the wallet, amounts in sat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletTx {
+    pub txid: String,
+    pub height: u64,
+    /// Block timestamp
+    pub time: u32,
+    pub credits: Vec<WalletCredit>,
+    /// Value of wallet coins spent
+    pub debit: u64,
+    /// Only known when every input was the wallet's
+    pub fee: Option<u64>,
+}
+
+/// One line of the bookkeeping export
+#[derive(Debug, Clone)]
+pub struct HistoryRow {
+    pub txid: String,
+    pub height: u64,
+    pub time: DateTime<Utc>,
+    pub amount: SignedAmount,
+    pub fee: Option<Amount>,
+    pub balance: SignedAmount,
+    pub label: String,
+    pub note: Option<String>,
+}
+
+/// Address derived but not yet handed out
+struct DerivedAddress {
+    record: WalletAddress,
+    script_pubkey: ScriptBuf,
+}
+
+impl Wallet {
+    pub fn new(name: &str, account_key: &AccountKey) -> WalletResult<Self> {
+        validate_name(name)?;
+        Ok(Self {
+            name: name.to_string(),
+            account_key: account_key.to_string(),
+            default_kind: AddressKind::Bech32,
+            avoid_reuse: false,
+            next_index: [0, 0],
+            addresses: Vec::new(),
+            created_at: Utc::now(),
+            history: Vec::new(),
+            notes: BTreeMap::new(),
+        })
+    }
+
+    fn key(&self) -> WalletResult<AccountKey> {
+        self.account_key.parse()
+    }
+
+    fn key_at(&self, chain: u32, index: u32) -> WalletResult<(PublicKey, KeySource)> {
+        self.key()?.key_at(chain, index)
+    }
+
+    fn derive(&self, chain: u32, index: u32, kind: AddressKind, network: Network) -> WalletResult<DerivedAddress> {
+        let address = self.key()?.address(chain, index, kind, network)?;
+        Ok(DerivedAddress {
+            script_pubkey: address.script_pubkey(),
+            record: WalletAddress { address: address.to_string(), chain, index, kind, label: None, first_funding: None },
+        })
+    }
+
+    fn peek(&self, change: bool, kind: Option<AddressKind>, network: Network) -> WalletResult<DerivedAddress> {
+        let chain = if change { CHANGE_CHAIN } else { RECEIVE_CHAIN };
+        self.derive(chain, self.next_index[chain as usize], kind.unwrap_or(self.default_kind), network)
+    }
+
+    fn commit(&mut self, derived: DerivedAddress) -> WalletAddress {
+        self.next_index[derived.record.chain as usize] = derived.record.index + 1;
+        self.addresses.push(derived.record.clone());
+        derived.record
+    }
+
+    /// Hand out the next receive (or change) address
+    pub fn new_address(&mut self, change: bool, kind: Option<AddressKind>, label: Option<String>, network: Network) -> WalletResult<WalletAddress> {
+        let mut derived = self.peek(change, kind, network)?;
+        derived.record.label = label;
+        Ok(self.commit(derived))
+    }
+
+    /// scriptPubKey of every address handed out, pointing at its entry in `addresses`
+    pub fn scripts(&self) -> WalletResult<HashMap<ScriptBuf, usize>> {
+        self.addresses
+            .iter()
+            .enumerate()
+            .map(|(position, address)| {
+                let script = Address::from_str(&address.address)
+                    .map_err(|e| WalletError::InvalidAddress(e.to_string()))?
+                    .assume_checked()
+                    .script_pubkey();
+                Ok((script, position))
+            })
+            .collect()
+    }
+
+    /// Remember the first confirmed payment to an address; true if this one was it
+    pub fn record_funding(&mut self, address: usize, outpoint: OutPoint) -> bool {
+        let address = &mut self.addresses[address];
+        if address.is_used() {
+            return false;
+        }
+        address.first_funding = Some(outpoint.to_string());
+        true
+    }
+
+    /// Flag coins paid to an address that had already received, recording
+    /// first payments oldest first; true if any address became used
+    pub fn mark_reused(&mut self, coins: &mut [WalletCoin]) -> bool {
+        let mut order: Vec<usize> = (0..coins.len()).collect();
+        order.sort_by(|&a, &b| coins[b].confirmations.cmp(&coins[a].confirmations).then(coins[a].outpoint.cmp(&coins[b].outpoint)));
+
+        let mut changed = false;
+        for position in order {
+            let coin = &mut coins[position];
+            if coin.confirmations > 0 {
+                changed |= self.record_funding(coin.address, coin.outpoint);
+            }
+            coin.reused = self.addresses[coin.address].first_funding
+                .as_ref()
+                .is_some_and(|first| *first != coin.outpoint.to_string());
+        }
+        changed
+    }
+
+    /// Record the block's wallet transactions, replacing anything previously
+    /// recorded at or above its height so a reorg rewrites history; true if
+    /// the wallet changed
+    pub fn apply_block(&mut self, block: &Block, height: u64, scripts: &HashMap<ScriptBuf, usize>) -> bool {
+        let before = self.history.len();
+        self.history.retain(|tx| tx.height < height);
+        let mut changed = self.history.len() != before;
+
+        let mut owned: HashMap<OutPoint, u64> = HashMap::new();
+        for tx in &self.history {
+            if let Ok(txid) = tx.txid.parse::<Txid>() {
+                owned.extend(tx.credits.iter().map(|credit| (OutPoint { txid, vout: credit.vout }, credit.value)));
+            }
+        }
+
+        for tx in &block.txdata {
+            let txid = tx.txid();
+            let credits: Vec<WalletCredit> = tx.output.iter()
+                .enumerate()
+                .filter_map(|(vout, output)| scripts.get(&output.script_pubkey).map(|&address| WalletCredit {
+                    vout: vout as u32,
+                    value: output.value.to_sat(),
+                    address,
+                }))
+                .collect();
+            let spent: Vec<u64> = tx.input.iter()
+                .filter_map(|input| owned.get(&input.previous_output).copied())
+                .collect();
+            if credits.is_empty() && spent.is_empty() {
+                continue;
+            }
+
+            let debit: u64 = spent.iter().sum();
+            let output_total: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+            let fee = (!tx.is_coinbase() && spent.len() == tx.input.len()).then(|| debit.saturating_sub(output_total));
+            for credit in &credits {
+                self.record_funding(credit.address, OutPoint { txid, vout: credit.vout });
+                owned.insert(OutPoint { txid, vout: credit.vout }, credit.value);
+            }
+            self.history.push(WalletTx {
+                txid: txid.to_string(),
+                height,
+                time: block.header.time,
+                credits,
+                debit,
+                fee,
+            });
+            changed = true;
+        }
+        changed
+    }
+
+    /// Attach a note to a transaction, or clear it with `None`
+    pub fn set_note(&mut self, txid: Txid, note: Option<String>) {
+        match note.filter(|note| !note.is_empty()) {
+            Some(note) => self.notes.insert(txid.to_string(), note),
+            None => self.notes.remove(&txid.to_string()),
+        };
+    }
+
+    /// History with net amounts and a running balance, oldest first
+    pub fn history_rows(&self) -> Vec<HistoryRow> {
+        let mut balance = SignedAmount::ZERO;
+        self.history.iter()
+            .map(|tx| {
+                let credited: u64 = tx.credits.iter().map(|credit| credit.value).sum();
+                let amount = SignedAmount::from_sat(credited as i64 - tx.debit as i64);
+                balance += amount;
+                let mut labels: Vec<&str> = tx.credits.iter()
+                    .filter_map(|credit| self.addresses[credit.address].label.as_deref())
+                    .collect();
+                labels.dedup();
+                HistoryRow {
+                    txid: tx.txid.clone(),
+                    height: tx.height,
+                    time: DateTime::from_timestamp(tx.time as i64, 0).unwrap_or_default(),
+                    amount,
+                    fee: tx.fee.map(Amount::from_sat),
+                    balance,
+                    label: labels.join("; "),
+                    note: self.notes.get(&tx.txid).cloned(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn path(datadir: &Path, name: &str) -> WalletResult<PathBuf> {
+        validate_name(name)?;
+        Ok(datadir.join("wallets").join(format!("{}.json", name)))
+    }
+
+    pub fn save(&self, datadir: &Path) -> WalletResult<()> {
+        let path = Self::path(datadir, &self.name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let data = serde_json::to_vec_pretty(self).map_err(|e| WalletError::Serialization(e.to_string()))?;
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, data)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// Every wallet saved under `datadir`
+    pub fn load_all(datadir: &Path) -> WalletResult<Vec<Self>> {
+        let dir = datadir.join("wallets");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut wallets = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let data = std::fs::read(&path)?;
+            let wallet: Self = serde_json::from_slice(&data).map_err(|e| WalletError::Serialization(e.to_string()))?;
+            wallet.key()?;
+            wallets.push(wallet);
+        }
+        Ok(wallets)
+    }
+}
+
+fn validate_name(name: &str) -> WalletResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid { Ok(()) } else { Err(WalletError::InvalidName(name.to_string())) }
+}
+
+/// Seconds a lease lasts when the caller does not say, as in lnd
+pub const DEFAULT_LEASE_SECS: u64 = 600;
+
+/// Reservation of an output by an external application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    /// 32-byte hex lock ID chosen by the application
+    pub id: String,
+    pub outpoint: String,
+    /// Unix time the lease lapses
+    pub expiration: i64,
+}
+
+/// Outputs coin selection must not touch, persisted as `<datadir>/leases.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LeaseBook {
+    /// Keyed by outpoint
+    leases: BTreeMap<String, Lease>,
+}
+
+impl LeaseBook {
+    fn path(datadir: &Path) -> PathBuf {
+        datadir.join("leases.json")
+    }
+
+    pub fn load(datadir: &Path) -> WalletResult<Self> {
+        let path = Self::path(datadir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data).map_err(|e| WalletError::Serialization(e.to_string()))
+    }
+
+    pub fn save(&self, datadir: &Path) -> WalletResult<()> {
+        std::fs::create_dir_all(datadir)?;
+        let path = Self::path(datadir);
+        let data = serde_json::to_vec_pretty(self).map_err(|e| WalletError::Serialization(e.to_string()))?;
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, data)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    fn validate_id(id: &str) -> WalletResult<()> {
+        let valid = id.len() == 64 && id.bytes().all(|byte| byte.is_ascii_hexdigit());
+        if valid { Ok(()) } else { Err(WalletError::InvalidRequest("lease ID must be 32 bytes of hex".to_string())) }
+    }
+
+    /// Drop lapsed leases; true if any were removed
+    pub fn prune(&mut self, now: i64) -> bool {
+        let before = self.leases.len();
+        self.leases.retain(|_, lease| lease.expiration > now);
+        self.leases.len() != before
+    }
+
+    /// Lease or extend a lease on `outpoint`; an output leased under another ID is refused
+    pub fn lease(&mut self, id: &str, outpoint: OutPoint, duration_secs: u64, now: i64) -> WalletResult<i64> {
+        Self::validate_id(id)?;
+        if duration_secs == 0 {
+            return Err(WalletError::InvalidRequest("lease duration must be positive".to_string()));
+        }
+        self.prune(now);
+        let id = id.to_lowercase();
+        if self.leases.get(&outpoint.to_string()).is_some_and(|lease| lease.id != id) {
+            return Err(WalletError::OutputLeased(outpoint));
+        }
+        let expiration = now.saturating_add(duration_secs.min(i64::MAX as u64) as i64);
+        self.leases.insert(outpoint.to_string(), Lease { id, outpoint: outpoint.to_string(), expiration });
+        Ok(expiration)
+    }
+
+    pub fn release(&mut self, id: &str, outpoint: OutPoint, now: i64) -> WalletResult<()> {
+        Self::validate_id(id)?;
+        self.prune(now);
+        match self.leases.get(&outpoint.to_string()) {
+            None => Err(WalletError::LeaseNotFound(outpoint)),
+            Some(lease) if !lease.id.eq_ignore_ascii_case(id) => Err(WalletError::OutputLeased(outpoint)),
+            Some(_) => {
+                self.leases.remove(&outpoint.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn is_leased(&self, outpoint: &OutPoint, now: i64) -> bool {
+        self.leases.get(&outpoint.to_string()).is_some_and(|lease| lease.expiration > now)
+    }
+
+    /// Leases still in force
+    pub fn active(&self, now: i64) -> Vec<Lease> {
+        self.leases.values().filter(|lease| lease.expiration > now).cloned().collect()
+    }
+}
+
+/// Unspent wallet output as seen by coin selection
+#[derive(Debug, Clone)]
+pub struct WalletCoin {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub confirmations: u32,
+    pub is_coinbase: bool,
+    /// Index into the wallet's `addresses`
+    pub address: usize,
+    /// Paid to an address that had already received (a "dirty" coin)
+    pub reused: bool,
+}
+
+impl WalletCoin {
+    pub fn is_mature(&self) -> bool {
+        !self.is_coinbase || self.confirmations >= COINBASE_MATURITY
+    }
+}
+
+/// Caller overrides for input selection and change
+#[derive(Debug, Clone)]
+pub struct CoinControl {
+    /// Coins that must be spent
+    pub inputs: Vec<OutPoint>,
+    /// Whether more coins may be added; defaults to true only when no inputs were given
+    pub add_inputs: Option<bool>,
+    pub min_conf: u32,
+    /// Leave dirty coins alone; defaults to the wallet's `avoid_reuse` flag
+    pub avoid_reuse: Option<bool>,
+    pub change_address: Option<Address>,
+    pub change_type: Option<AddressKind>,
+    pub fee_rate: FeeRate,
+}
+
+impl Default for CoinControl {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            add_inputs: None,
+            min_conf: 1,
+            avoid_reuse: None,
+            change_address: None,
+            change_type: None,
+            fee_rate: MIN_RELAY_FEE_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FundedPsbt {
+    pub psbt: Psbt,
+    pub fee: Amount,
+    pub change_position: Option<usize>,
+}
+
+const HISTORY_CSV_HEADER: &str = "date,height,txid,amount,fee,balance,label,note,fiat_currency,fiat_price,fiat_value";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render history as CSV; fiat columns stay empty for days without a price
+pub fn history_csv(rows: &[HistoryRow], currency: &str, prices: &HashMap<NaiveDate, f64>) -> String {
+    let mut csv = String::from(HISTORY_CSV_HEADER);
+    csv.push('\n');
+    for row in rows {
+        let price = prices.get(&row.time.date_naive());
+        let fields = [
+            row.time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            row.height.to_string(),
+            row.txid.clone(),
+            row.amount.to_string_in(Denomination::Bitcoin),
+            row.fee.map(|fee| fee.to_string_in(Denomination::Bitcoin)).unwrap_or_default(),
+            row.balance.to_string_in(Denomination::Bitcoin),
+            row.label.clone(),
+            row.note.clone().unwrap_or_default(),
+            currency.to_string(),
+            price.map(|price| format!("{:.2}", price)).unwrap_or_default(),
+            price.map(|price| format!("{:.2}", row.amount.to_btc() * price)).unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Daily fiat prices from `wallet.price_url`, which returns JSON per date
+#[cfg(feature = "http-client")]
+pub struct PriceSource {
+    client: reqwest::Client,
+    url: String,
+    pointer: String,
+}
+
+#[cfg(feature = "http-client")]
+impl PriceSource {
+    pub fn from_config(config: &crate::config::WalletConfig) -> Option<Self> {
+        let url = config.price_url.as_ref()?;
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.price_timeout_secs))
+            .build()
+            .unwrap_or_default();
+        Some(Self {
+            client,
+            url: url.replace("{currency}", &config.fiat_currency),
+            pointer: config.price_pointer.clone(),
+        })
+    }
+
+    async fn price(&self, date: NaiveDate) -> Result<f64, String> {
+        let url = self.url.replace("{date}", &date.format("%Y-%m-%d").to_string());
+        let body: serde_json::Value = self.client.get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        let price = body.pointer(&self.pointer).ok_or_else(|| format!("no value at {}", self.pointer))?;
+        // Some feeds quote prices as strings
+        price.as_f64()
+            .or_else(|| price.as_str().and_then(|price| price.parse().ok()))
+            .ok_or_else(|| format!("value at {} is not a number", self.pointer))
+    }
+
+    /// Price for each day, leaving out days the source could not answer
+    pub async fn prices(&self, dates: impl IntoIterator<Item = NaiveDate>) -> HashMap<NaiveDate, f64> {
+        let mut prices = HashMap::new();
+        for date in dates {
+            if prices.contains_key(&date) {
+                continue;
+            }
+            match self.price(date).await {
+                Ok(price) => {
+                    prices.insert(date, price);
+                }
+                Err(e) => tracing::warn!("No fiat price for {}: {}", date, e),
+            }
+        }
+        prices
+    }
+}
+
+fn output_vsize(script_pubkey: &ScriptBuf) -> u64 {
+    8 + 1 + script_pubkey.len() as u64
+}
+
+fn fee_for(vsize: u64, fee_rate: FeeRate) -> Amount {
+    fee_rate.fee_for(vsize).into()
+}
+
+/// Pick coins for `outputs`, spending the required inputs first and then the
+/// largest eligible coins, and build the unsigned PSBT with change last
+pub fn fund_psbt(
+    wallet: &mut Wallet,
+    coins: &[WalletCoin],
+    outputs: Vec<TxOut>,
+    lock_time: absolute::LockTime,
+    control: &CoinControl,
+    network: Network,
+) -> WalletResult<FundedPsbt> {
+    if outputs.is_empty() {
+        return Err(WalletError::InvalidRequest("at least one output is required".to_string()));
+    }
+
+    let mut selected: Vec<&WalletCoin> = Vec::new();
+    for outpoint in &control.inputs {
+        let coin = coins.iter()
+            .find(|coin| coin.outpoint == *outpoint)
+            .ok_or(WalletError::UnknownInput(*outpoint))?;
+        if !coin.is_mature() {
+            return Err(WalletError::UnknownInput(*outpoint));
+        }
+        if !selected.iter().any(|picked| picked.outpoint == *outpoint) {
+            selected.push(coin);
+        }
+    }
+
+    let add_inputs = control.add_inputs.unwrap_or(control.inputs.is_empty());
+    let avoid_reuse = control.avoid_reuse.unwrap_or(wallet.avoid_reuse);
+    let mut candidates: Vec<&WalletCoin> = coins.iter()
+        .filter(|coin| coin.is_mature() && coin.confirmations >= control.min_conf)
+        .filter(|coin| !(avoid_reuse && coin.reused))
+        .filter(|coin| !selected.iter().any(|picked| picked.outpoint == coin.outpoint))
+        .collect();
+    candidates.sort_by(|a, b| b.txout.value.cmp(&a.txout.value).then(a.outpoint.cmp(&b.outpoint)));
+    let mut candidates = candidates.into_iter();
+
+    let change = match &control.change_address {
+        Some(address) => (address.script_pubkey(), None),
+        None => {
+            let derived = wallet.peek(true, control.change_type, network)?;
+            (derived.script_pubkey.clone(), Some(derived))
+        }
+    };
+    let (change_script, change_derived) = change;
+
+    let target: Amount = outputs.iter().map(|output| output.value).sum();
+    let outputs_vsize: u64 = outputs.iter().map(|output| output_vsize(&output.script_pubkey)).sum();
+    let input_vsize = |coin: &WalletCoin| wallet.addresses[coin.address].kind.input_vsize();
+
+    let (fee, change_value) = loop {
+        let inputs_vsize: u64 = selected.iter().map(|coin| input_vsize(coin)).sum();
+        let available: Amount = selected.iter().map(|coin| coin.txout.value).sum();
+        let base_vsize = TX_OVERHEAD_VSIZE + inputs_vsize + outputs_vsize;
+
+        let fee_without_change = fee_for(base_vsize, control.fee_rate);
+        if !selected.is_empty() && available >= target + fee_without_change {
+            let fee_with_change = fee_for(base_vsize + output_vsize(&change_script), control.fee_rate);
+            let leftover = available.checked_sub(target + fee_with_change);
+            match leftover {
+                Some(change) if change >= change_script.dust_value() => break (fee_with_change, Some(change)),
+                // Too small to be worth an output, so it goes to the miner
+                _ => break (available - target, None),
+            }
+        }
+
+        match candidates.next().filter(|_| add_inputs) {
+            Some(coin) => selected.push(coin),
+            None => {
+                return Err(WalletError::InsufficientFunds {
+                    available: available.to_sat(),
+                    needed: (target + fee_without_change).to_sat(),
+                })
+            }
+        }
+    };
+
+    let mut tx_outputs = outputs;
+    let change_position = change_value.map(|value| {
+        tx_outputs.push(TxOut { value, script_pubkey: change_script.clone() });
+        tx_outputs.len() - 1
+    });
+    let tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: selected.iter()
+            .map(|coin| TxIn {
+                previous_output: coin.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: tx_outputs,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).map_err(|e| WalletError::InvalidRequest(e.to_string()))?;
+
+    for (input, coin) in psbt.inputs.iter_mut().zip(&selected) {
+        let address = &wallet.addresses[coin.address];
+        let (public_key, source) = wallet.key_at(address.chain, address.index)?;
+        input.witness_utxo = Some(coin.txout.clone());
+        add_key_origin(&mut input.bip32_derivation, &mut input.tap_key_origins, &mut input.tap_internal_key, address.kind, public_key, source);
+    }
+
+    if let (Some(position), Some(derived)) = (change_position, change_derived) {
+        let (public_key, source) = wallet.key_at(derived.record.chain, derived.record.index)?;
+        let kind = derived.record.kind;
+        let output = &mut psbt.outputs[position];
+        add_key_origin(&mut output.bip32_derivation, &mut output.tap_key_origins, &mut output.tap_internal_key, kind, public_key, source);
+        wallet.commit(derived);
+    }
+
+    Ok(FundedPsbt { psbt, fee, change_position })
+}
+
+/// Derivation info a signer uses to recognise its key
+fn add_key_origin(
+    bip32_derivation: &mut std::collections::BTreeMap<PublicKey, KeySource>,
+    tap_key_origins: &mut std::collections::BTreeMap<bitcoin::secp256k1::XOnlyPublicKey, (Vec<bitcoin::taproot::TapLeafHash>, KeySource)>,
+    tap_internal_key: &mut Option<bitcoin::secp256k1::XOnlyPublicKey>,
+    kind: AddressKind,
+    public_key: PublicKey,
+    source: KeySource,
+) {
+    match kind {
+        AddressKind::Bech32 => {
+            bip32_derivation.insert(public_key, source);
+        }
+        AddressKind::Bech32m => {
+            let x_only = public_key.x_only_public_key().0;
+            *tap_internal_key = Some(x_only);
+            tap_key_origins.insert(x_only, (Vec::new(), source));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    // BIP84 test vector account key (abandon ... about)
+    const ACCOUNT: &str = "[73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    fn wallet_with_coins(values: &[(u64, u32)]) -> (Wallet, Vec<WalletCoin>) {
+        let mut wallet = Wallet::new("test", &ACCOUNT.parse().unwrap()).unwrap();
+        let coins = values.iter()
+            .enumerate()
+            .map(|(i, (value, confirmations))| {
+                let address = wallet.new_address(false, None, None, Network::Bitcoin).unwrap();
+                WalletCoin {
+                    outpoint: OutPoint { txid: bitcoin::Txid::from_byte_array([i as u8 + 1; 32]), vout: 0 },
+                    txout: TxOut {
+                        value: Amount::from_sat(*value),
+                        script_pubkey: Address::from_str(&address.address).unwrap().assume_checked().script_pubkey(),
+                    },
+                    confirmations: *confirmations,
+                    is_coinbase: false,
+                    address: i,
+                    reused: false,
+                }
+            })
+            .collect();
+        (wallet, coins)
+    }
+
+    fn payment(value: u64) -> Vec<TxOut> {
+        vec![TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()) }]
+    }
+
+    #[test]
+    fn test_account_addresses_match_bip84() {
+        let key: AccountKey = ACCOUNT.parse().unwrap();
+        assert_eq!(key.to_string(), ACCOUNT);
+
+        let mut wallet = Wallet::new("bip84", &key).unwrap();
+        assert_eq!(wallet.new_address(false, None, None, Network::Bitcoin).unwrap().address, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(wallet.new_address(true, None, None, Network::Bitcoin).unwrap().address, "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+        assert_eq!(wallet.next_index, [1, 1]);
+        assert!(matches!(Wallet::new("../x", &key), Err(WalletError::InvalidName(_))));
+    }
+
+    #[test]
+    fn test_coin_control_selection() {
+        let (mut wallet, coins) = wallet_with_coins(&[(50_000, 6), (20_000, 1), (80_000, 0)]);
+
+        // Largest confirmed coin first; the unconfirmed one stays out under min_conf 1
+        let funded = fund_psbt(&mut wallet, &coins, payment(30_000), absolute::LockTime::ZERO, &CoinControl::default(), Network::Bitcoin).unwrap();
+        assert_eq!(funded.psbt.unsigned_tx.input[0].previous_output, coins[0].outpoint);
+        assert_eq!(funded.change_position, Some(1));
+        assert_eq!(funded.psbt.inputs[0].bip32_derivation.len(), 1);
+        assert_eq!(wallet.next_index[1], 1);
+        let total_out: Amount = funded.psbt.unsigned_tx.output.iter().map(|output| output.value).sum();
+        assert_eq!(total_out + funded.fee, Amount::from_sat(50_000));
+
+        // An explicit input is used alone unless more may be added
+        let control = CoinControl { inputs: vec![coins[1].outpoint], ..CoinControl::default() };
+        assert!(matches!(
+            fund_psbt(&mut wallet, &coins, payment(30_000), absolute::LockTime::ZERO, &control, Network::Bitcoin),
+            Err(WalletError::InsufficientFunds { .. })
+        ));
+        let control = CoinControl { add_inputs: Some(true), min_conf: 0, change_type: Some(AddressKind::Bech32m), ..control };
+        let funded = fund_psbt(&mut wallet, &coins, payment(30_000), absolute::LockTime::ZERO, &control, Network::Bitcoin).unwrap();
+        let inputs: Vec<OutPoint> = funded.psbt.unsigned_tx.input.iter().map(|input| input.previous_output).collect();
+        assert_eq!(inputs, vec![coins[1].outpoint, coins[2].outpoint]);
+        assert!(funded.psbt.unsigned_tx.output[1].script_pubkey.is_p2tr());
+
+        // Reused addresses are skipped on request, and an override change address is used as given
+        let mut reused = coins.clone();
+        reused[0].reused = true;
+        let change_address = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap().assume_checked();
+        let control = CoinControl { avoid_reuse: Some(true), change_address: Some(change_address.clone()), ..CoinControl::default() };
+        let funded = fund_psbt(&mut wallet, &reused, payment(10_000), absolute::LockTime::ZERO, &control, Network::Bitcoin).unwrap();
+        assert_eq!(funded.psbt.unsigned_tx.input[0].previous_output, coins[1].outpoint);
+        assert_eq!(funded.psbt.unsigned_tx.output[1].script_pubkey, change_address.script_pubkey());
+    }
+
+    #[test]
+    fn test_avoid_reuse_marks_dirty_coins() {
+        let (mut wallet, mut coins) = wallet_with_coins(&[(50_000, 6), (20_000, 3), (80_000, 0)]);
+        // A second and third payment land on the first address; the older of the two confirmed ones wins
+        coins[1].address = 0;
+        coins[2].address = 0;
+        coins[1].txout.script_pubkey = coins[0].txout.script_pubkey.clone();
+        coins[2].txout.script_pubkey = coins[0].txout.script_pubkey.clone();
+
+        assert!(wallet.mark_reused(&mut coins));
+        assert!(wallet.addresses[0].is_used() && !wallet.addresses[1].is_used());
+        assert_eq!(coins.iter().map(|coin| coin.reused).collect::<Vec<_>>(), vec![false, true, true]);
+        // The first payment stays the clean one after it is spent
+        let mut remaining = coins[1..].to_vec();
+        assert!(!wallet.mark_reused(&mut remaining));
+        assert!(remaining.iter().all(|coin| coin.reused));
+
+        // The wallet flag excludes dirty coins by default, and an explicit false overrides it
+        wallet.avoid_reuse = true;
+        assert!(matches!(
+            fund_psbt(&mut wallet, &remaining, payment(10_000), absolute::LockTime::ZERO, &CoinControl::default(), Network::Bitcoin),
+            Err(WalletError::InsufficientFunds { .. })
+        ));
+        let control = CoinControl { avoid_reuse: Some(false), ..CoinControl::default() };
+        let funded = fund_psbt(&mut wallet, &remaining, payment(10_000), absolute::LockTime::ZERO, &control, Network::Bitcoin).unwrap();
+        assert_eq!(funded.psbt.unsigned_tx.input[0].previous_output, coins[1].outpoint);
+    }
+
+    #[test]
+    fn test_history_rows_and_csv() {
+        let (mut wallet, _) = wallet_with_coins(&[(0, 0), (0, 0)]);
+        wallet.addresses[0].label = Some("Invoice #7, ACME".to_string());
+        let scripts = wallet.scripts().unwrap();
+        let script = |i: usize| Address::from_str(&wallet.addresses[i].address).unwrap().assume_checked().script_pubkey();
+
+        let coinbase = Transaction {
+            version: transaction::Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::null(), ..TxIn::default() }],
+            output: vec![TxOut { value: Amount::from_sat(100_000), script_pubkey: script(0) }],
+        };
+        let spend = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint { txid: coinbase.txid(), vout: 0 }, ..TxIn::default() }],
+            output: [payment(60_000), vec![TxOut { value: Amount::from_sat(39_000), script_pubkey: script(1) }]].concat(),
+        };
+        let mut block = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        block.header.time = 1_700_000_000;
+        block.txdata = vec![coinbase.clone(), spend.clone()];
+
+        assert!(wallet.apply_block(&block, 5, &scripts));
+        wallet.set_note(spend.txid(), Some("Rent \"March\"".to_string()));
+        let rows = wallet.history_rows();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].amount, SignedAmount::from_sat(-61_000));
+        assert_eq!(rows[1].fee, Some(Amount::from_sat(1_000)));
+        assert_eq!(rows[1].balance, SignedAmount::from_sat(39_000));
+        assert!(wallet.addresses[0].is_used());
+
+        let date = rows[0].time.date_naive();
+        let csv = history_csv(&rows, "USD", &HashMap::from([(date, 40_000.0)]));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], HISTORY_CSV_HEADER);
+        assert_eq!(lines[1], format!("2023-11-14T22:13:20Z,5,{},0.001,,0.001,\"Invoice #7, ACME\",,USD,40000.00,40.00", coinbase.txid()));
+        assert!(lines[2].contains(",-0.00061,0.00001,0.00039,,\"Rent \"\"March\"\"\",USD,40000.00,-24.40"));
+
+        // Reconnecting at the same height replaces what was recorded there
+        block.txdata = vec![coinbase];
+        assert!(wallet.apply_block(&block, 5, &scripts));
+        assert_eq!(wallet.history.len(), 1);
+    }
+
+    #[test]
+    fn test_lease_book() {
+        let id = "ab".repeat(32);
+        let other = "cd".repeat(32);
+        let outpoint = OutPoint { txid: bitcoin::Txid::from_byte_array([7; 32]), vout: 1 };
+        let mut book = LeaseBook::default();
+
+        assert_eq!(book.lease(&id, outpoint, DEFAULT_LEASE_SECS, 1_000).unwrap(), 1_600);
+        assert!(book.is_leased(&outpoint, 1_599) && !book.is_leased(&outpoint, 1_600));
+        // The holder may extend; anyone else is refused until the lease lapses
+        assert_eq!(book.lease(&id, outpoint, 60, 1_100).unwrap(), 1_160);
+        assert!(matches!(book.lease(&other, outpoint, 60, 1_100), Err(WalletError::OutputLeased(_))));
+        assert!(matches!(book.release(&other, outpoint, 1_100), Err(WalletError::OutputLeased(_))));
+        assert_eq!(book.lease(&other, outpoint, 60, 1_200).unwrap(), 1_260);
+        assert_eq!(book.active(1_200).len(), 1);
+
+        book.release(&other, outpoint, 1_200).unwrap();
+        assert!(matches!(book.release(&other, outpoint, 1_200), Err(WalletError::LeaseNotFound(_))));
+        assert!(matches!(book.lease("zz", outpoint, 60, 0), Err(WalletError::InvalidRequest(_))));
+    }
+}
@@ -0,0 +1,169 @@
+//! Runs LND in regtest with bitknotsrs as its bitcoind backend.
+//!
+//! Needs an `lnd` and `lncli` build: point `LND_BIN` at lnd (and `LNCLI_BIN`
+//! at lncli if it is not next to it), then run
+//! `cargo test --test lnd_compat -- --ignored --nocapture`.
+
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use bitknotsrs::Config;
+use serde_json::{json, Value};
+
+const BLOCKS: u64 = 101;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Kills the child process when the test ends, pass or fail
+struct ChildGuard(Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn node_config(datadir: &Path, rpc_port: u16, zmq_port: u16) -> Config {
+    let mut config = Config::default_regtest();
+    config.datadir = datadir.to_path_buf();
+    config.storage.rocks_db_path = datadir.join("rocksdb");
+    config.api.port = free_port();
+    config.rpc.port = rpc_port;
+    // LND calls far more than the default allowlist
+    config.rpc.allowed_methods.clear();
+    config.metrics.enabled = false;
+    config.logging.file_enabled = false;
+    config.network_config.listen = false;
+    config.network_config.listen_port = free_port();
+    config.network_config.enable_dns_seeds = false;
+    config.network_config.zmq.pub_port = Some(zmq_port);
+    config.network_config.zmq.topics = vec!["rawblock".to_string(), "rawtx".to_string()];
+    config.events.enabled_publishers = vec![];
+    config.events.zmq.port = free_port();
+    config
+}
+
+async fn rpc(port: u16, method: &str, params: Value) -> Value {
+    let response: Value = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/", port))
+        .basic_auth("user", Some("pass"))
+        // The 1.0 envelope btcd's rpcclient sends
+        .json(&json!({ "jsonrpc": "1.0", "id": 1, "method": method, "params": params }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(response["error"].is_null(), "{} failed: {}", method, response);
+    response["result"].clone()
+}
+
+fn lncli(lncli: &Path, lnddir: &Path, rpc_port: u16, args: &[&str]) -> Option<Value> {
+    let output = Command::new(lncli)
+        .arg(format!("--lnddir={}", lnddir.display()))
+        .arg(format!("--rpcserver=127.0.0.1:{}", rpc_port))
+        .arg("--network=regtest")
+        .args(args)
+        .output()
+        .ok()?;
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+#[tokio::test]
+#[ignore = "needs LND_BIN pointing at an lnd build"]
+async fn test_lnd_syncs_with_bitcoind_backend() {
+    let Ok(lnd) = std::env::var("LND_BIN").map(PathBuf::from) else {
+        eprintln!("LND_BIN is not set, skipping");
+        return;
+    };
+    let lncli_bin = std::env::var("LNCLI_BIN")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| lnd.with_file_name("lncli"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let (rpc_port, zmq_port) = (free_port(), free_port());
+    let config_path = dir.path().join("config.toml");
+    let config = node_config(&dir.path().join("node"), rpc_port, zmq_port);
+    std::fs::write(&config_path, toml::to_string_pretty(&config).unwrap()).unwrap();
+
+    // Mine a chain for LND to sync before the node starts
+    let script = dir.path().join("chain.toml");
+    std::fs::write(&script, format!("[[steps]]\naction = \"mine\"\nblocks = {}\n", BLOCKS)).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_bitknotsrs"))
+        .arg("--config").arg(&config_path)
+        .arg("scenario").arg(&script)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "scenario failed");
+
+    let _node = ChildGuard(Command::new(env!("CARGO_BIN_EXE_bitknotsrs"))
+        .arg("--config").arg(&config_path)
+        .spawn()
+        .unwrap());
+
+    let deadline = Instant::now() + SYNC_TIMEOUT;
+    loop {
+        let ready = reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}/", rpc_port))
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "getblockcount", "params": [] }))
+            .send()
+            .await
+            .is_ok();
+        if ready {
+            break;
+        }
+        assert!(Instant::now() < deadline, "node RPC never came up");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    // The calls LND's bitcoind chain backend makes while syncing
+    assert_eq!(rpc(rpc_port, "getblockcount", json!([])).await, json!(BLOCKS));
+    let tip = rpc(rpc_port, "getbestblockhash", json!([])).await;
+    assert_eq!(rpc(rpc_port, "getblockhash", json!([BLOCKS])).await, tip);
+    let header = rpc(rpc_port, "getblockheader", json!([tip, true])).await;
+    assert_eq!(header["height"], json!(BLOCKS));
+    let raw_header = rpc(rpc_port, "getblockheader", json!([tip, false])).await;
+    let raw_block = rpc(rpc_port, "getblock", json!([tip, 0])).await;
+    assert!(raw_block.as_str().unwrap().starts_with(raw_header.as_str().unwrap()));
+    assert!(rpc(rpc_port, "estimatesmartfee", json!([6])).await["feerate"].as_f64().unwrap() > 0.0);
+
+    let lnddir = dir.path().join("lnd");
+    let lnd_rpc_port = free_port();
+    let _lnd = ChildGuard(Command::new(&lnd)
+        .arg(format!("--lnddir={}", lnddir.display()))
+        .arg("--bitcoin.active")
+        .arg("--bitcoin.regtest")
+        .arg("--bitcoin.node=bitcoind")
+        .arg(format!("--bitcoind.rpchost=127.0.0.1:{}", rpc_port))
+        .arg("--bitcoind.rpcuser=user")
+        .arg("--bitcoind.rpcpass=pass")
+        .arg(format!("--bitcoind.zmqpubrawblock=tcp://127.0.0.1:{}", zmq_port))
+        .arg(format!("--bitcoind.zmqpubrawtx=tcp://127.0.0.1:{}", zmq_port))
+        .arg("--noseedbackup")
+        .arg("--norest")
+        .arg(format!("--rpclisten=127.0.0.1:{}", lnd_rpc_port))
+        .arg(format!("--listen=127.0.0.1:{}", free_port()))
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap());
+
+    let deadline = Instant::now() + SYNC_TIMEOUT;
+    loop {
+        if let Some(info) = lncli(&lncli_bin, &lnddir, lnd_rpc_port, &["getinfo"]) {
+            if info["synced_to_chain"] == json!(true) && info["block_height"] == json!(BLOCKS) {
+                assert_eq!(info["block_hash"], tip);
+                break;
+            }
+        }
+        assert!(Instant::now() < deadline, "LND did not sync to height {}", BLOCKS);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
@@ -0,0 +1,358 @@
+//! Golden files for the JSON shape of every RPC method.
+//!
+//! Each case runs against the same regtest fixture: a deterministic scenario
+//! chain with one transaction left in the mempool, the mock clock, and a
+//! watch-only wallet on an account key from BIP32 test vector 1. The
+//! canonical JSON of the response (keys sorted, pretty printed) must match
+//! `tests/golden/rpc/<case>.json`. After an intended change run
+//! `UPDATE_GOLDEN=1 cargo test --test rpc_golden` and review the diff.
+//!
+//! The cases cover the wallet and mining methods, so the default features
+//! must be on.
+#![cfg(all(feature = "wallet", feature = "miner"))]
+
+use std::path::PathBuf;
+
+use actix::Actor;
+use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoin::hex::FromHex;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Network;
+use bitknotsrs::actors::chain::ChainActor;
+use bitknotsrs::actors::mempool::{MempoolActor, MempoolDeltaFeed, MEMPOOL_DELTA_BUFFER};
+use bitknotsrs::actors::network::NetworkActor;
+use bitknotsrs::actors::storage::StorageActor;
+use bitknotsrs::actors::wallet::WalletActor;
+use bitknotsrs::actors::{GetBlock, GetFromMempool};
+use bitknotsrs::events::EventManager;
+use bitknotsrs::rpc::{registry, RpcActors};
+use bitknotsrs::scenario::{Scenario, ScenarioRunner};
+use bitknotsrs::settings::Settings;
+use bitknotsrs::Config;
+use jsonrpc_core::IoHandler;
+use serde_json::{json, Map, Value};
+
+const MOCK_TIME: i64 = 1_700_000_000;
+
+const SCENARIO: &str = r#"
+    [[steps]]
+    action = "mine"
+    blocks = 101
+    to = "alice"
+
+    [[steps]]
+    action = "send"
+    from = "alice"
+    to = "bob"
+    amount = 100000000
+    fee_rate = 2
+"#;
+
+/// BIP32 test vector 1
+const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+const MASTER_XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+/// Secret keys 1 and 2, whose public keys are G and 2G
+const SCAN_SECRET: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+const SCAN_PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+const SPEND_PUBKEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+const SP_ADDRESS: &str = "tsp1qqfumuen7l8wthtz45p3ftn58pvrs9xlumvkuu2xet8egzkcklqtesqkxq3legs0d04knq32qd62uqlxct3mcujuvau7202avpxu4cuy7u5mdxur5";
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/rpc")
+}
+
+/// `[fingerprint/path]tpub` for an account of the test vector seed
+fn account_key(path: &str) -> String {
+    let secp = Secp256k1::new();
+    let seed = Vec::<u8>::from_hex(SEED).unwrap();
+    let master = Xpriv::new_master(Network::Regtest, &seed).unwrap();
+    let path: DerivationPath = path.parse().unwrap();
+    let account = Xpub::from_priv(&secp, &master.derive_priv(&secp, &path).unwrap());
+    format!("[{}/{}]{}", master.fingerprint(&secp), path.to_string().trim_start_matches("m/"), account)
+}
+
+fn unsigned_psbt(txid: &str) -> bitcoin::psbt::Psbt {
+    let tx = bitcoin::Transaction {
+        version: bitcoin::transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint::new(txid.parse().unwrap(), 0),
+            ..Default::default()
+        }],
+        output: vec![bitcoin::TxOut {
+            value: bitcoin::Amount::from_sat(50_000_000),
+            script_pubkey: bitcoin::ScriptBuf::new_op_return([]),
+        }],
+    };
+    bitcoin::psbt::Psbt::from_unsigned_tx(tx).unwrap()
+}
+
+/// Objects rebuilt with sorted keys, whatever map ordering serde_json was built with
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries.into_iter().map(|(key, value)| (key, canonical(value))).collect::<Map<_, _>>())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        value => value,
+    }
+}
+
+/// Values that follow RocksDB's memory use rather than the fixture
+const VOLATILE_KEYS: &[&str] = &["coins_tip_cache_bytes"];
+
+/// Volatile numbers replaced by a placeholder, so only their presence is compared
+fn mask_volatile(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if VOLATILE_KEYS.contains(&key.as_str()) && value.is_u64() {
+                    *value = json!("<u64>");
+                } else {
+                    mask_volatile(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(mask_volatile),
+        _ => {}
+    }
+}
+
+struct Fixture {
+    io: IoHandler,
+    tip: String,
+    txid: String,
+    raw_tx: String,
+    tip_block: bitcoin::Block,
+    _datadir: tempfile::TempDir,
+}
+
+async fn fixture() -> Fixture {
+    bitknotsrs::clock::set_mock_time(MOCK_TIME);
+
+    let datadir = tempfile::tempdir().unwrap();
+    let mut config = Config::default_regtest();
+    config.datadir = datadir.path().to_path_buf();
+    config.storage.rocks_db_path = datadir.path().join("rocksdb");
+    config.metrics.enabled = false;
+    config.logging.file_enabled = false;
+    config.network_config.listen = false;
+    config.network_config.enable_dns_seeds = false;
+    config.events.enabled_publishers = vec![];
+    config.storage.silent_payment_index = true;
+    let event_manager = EventManager::with_publishers(Vec::new());
+
+    let storage_actor = StorageActor::new(&config).start();
+    let network_actor = NetworkActor::new(&config, storage_actor.clone(), event_manager.clone()).start();
+    let mempool_actor = MempoolActor::new(
+        &config,
+        storage_actor.clone(),
+        MempoolDeltaFeed::new(MEMPOOL_DELTA_BUFFER),
+        Vec::new(),
+        event_manager.clone(),
+    ).start();
+    let wallet_actor = WalletActor::new(&config, storage_actor.clone(), mempool_actor.clone()).start();
+    let chain_actor = ChainActor::new(
+        &config,
+        storage_actor.clone(),
+        mempool_actor.clone(),
+        vec![wallet_actor.clone().recipient()],
+        event_manager.clone(),
+    ).start();
+
+    let mut runner = ScenarioRunner::new(Network::Regtest, storage_actor.clone(), mempool_actor.clone(), chain_actor.clone()).unwrap();
+    let report = runner.run(&Scenario::parse(SCENARIO).unwrap()).await.unwrap();
+    let txid = report.steps[1].txid.clone().unwrap();
+    let tx = mempool_actor.send(GetFromMempool { txid: txid.parse().unwrap() }).await.unwrap().unwrap().unwrap();
+    let tip_block = storage_actor.send(GetBlock { hash: report.tip_hash.parse().unwrap() }).await.unwrap().unwrap().unwrap();
+
+    let settings = Settings::load(config.clone()).unwrap();
+    let actors = RpcActors {
+        storage: storage_actor,
+        mempool: mempool_actor,
+        network: network_actor,
+        chain: chain_actor,
+        wallet: wallet_actor,
+    };
+    let io = bitknotsrs::rpc::build_handler(&config, settings, event_manager, actors);
+    Fixture {
+        io,
+        tip: report.tip_hash,
+        txid,
+        raw_tx: bitcoin::consensus::encode::serialize_hex(&tx),
+        tip_block,
+        _datadir: datadir,
+    }
+}
+
+/// `(case, method, params)` in call order; later cases see the state earlier ones leave
+fn cases(fixture: &Fixture) -> Vec<(&'static str, &'static str, Value)> {
+    let (tip, txid) = (fixture.tip.as_str(), fixture.txid.as_str());
+    let wallet = account_key("m/84'/1'/0'");
+    let cosigners = vec![account_key("m/48'/1'/0'/2'"), account_key("m/48'/1'/1'/2'")];
+    // A single input spending the fixture transaction's first output, for the PSBT methods
+    let psbt = unsigned_psbt(txid);
+    let ur_parts = bitknotsrs::ur::encode_psbt(&psbt, 60, None);
+    let psbt = psbt.to_string();
+    let lease_id = "11".repeat(32);
+    // The fixture transaction with an extra output burning 1000 sat
+    let mut burn_tx: bitcoin::Transaction = bitcoin::consensus::deserialize(&Vec::from_hex(&fixture.raw_tx).unwrap()).unwrap();
+    burn_tx.output.push(bitcoin::TxOut { value: bitcoin::Amount::from_sat(1000), script_pubkey: bitcoin::ScriptBuf::new_op_return([1]) });
+    let burn_tx = bitcoin::consensus::encode::serialize_hex(&burn_tx);
+    // The tip block, then copies that fail proof of work and build on an unknown parent
+    let tip_block = bitcoin::consensus::encode::serialize_hex(&fixture.tip_block);
+    let mut high_hash = fixture.tip_block.clone();
+    while high_hash.header.validate_pow(high_hash.header.target()).is_ok() {
+        high_hash.header.nonce += 1;
+    }
+    let mut orphan = fixture.tip_block.clone();
+    orphan.header.prev_blockhash = bitcoin::hashes::Hash::from_byte_array([1; 32]);
+    while orphan.header.validate_pow(orphan.header.target()).is_err() {
+        orphan.header.nonce += 1;
+    }
+    let (high_hash, orphan) = (bitcoin::consensus::encode::serialize_hex(&high_hash), bitcoin::consensus::encode::serialize_hex(&orphan));
+
+    vec![
+        ("help", "help", json!([])),
+        ("help_command", "help", json!(["getblock"])),
+        ("rpc_discover", "rpc.discover", json!([])),
+        ("getversion", "getversion", json!([])),
+        ("uptime", "uptime", json!([])),
+        ("setmocktime", "setmocktime", json!([MOCK_TIME])),
+        ("getblockchaininfo", "getblockchaininfo", json!([])),
+        ("getchainstates", "getchainstates", json!([])),
+        ("getbestblockhash", "getbestblockhash", json!([])),
+        ("getblockcount", "getblockcount", json!([])),
+        ("getblockhash", "getblockhash", json!([1])),
+        ("waitforblockheight_timeout", "waitforblockheight", json!([102, 10])),
+        ("getblock", "getblock", json!([tip])),
+        ("getblock_raw", "getblock", json!([tip, 0])),
+        ("getblock_verbose", "getblock", json!([tip, 2])),
+        ("getblockheader", "getblockheader", json!([tip])),
+        ("getblockheader_raw", "getblockheader", json!([tip, false])),
+        ("gettxoutsetinfo", "gettxoutsetinfo", json!([])),
+        ("getstaleblocks", "getstaleblocks", json!([])),
+        ("acceptreorg_not_held", "acceptreorg", json!([tip])),
+        ("estimatesmartfee", "estimatesmartfee", json!([6])),
+        ("getmempoolinfo", "getmempoolinfo", json!([])),
+        ("getrawmempool", "getrawmempool", json!([])),
+        ("getmempoolentry", "getmempoolentry", json!([txid])),
+        ("getmempoolancestors", "getmempoolancestors", json!([txid])),
+        ("getmempooldescendants", "getmempooldescendants", json!([txid])),
+        ("getblocktemplate", "getblocktemplate", json!([])),
+        ("submitblock_duplicate", "submitblock", json!([tip_block])),
+        ("submitblock_high_hash", "submitblock", json!([high_hash])),
+        ("submitblock_orphan", "submitblock", json!([orphan])),
+        ("submitblock_decode_failed", "submitblock", json!(["00"])),
+        ("getnetworkinfo", "getnetworkinfo", json!([])),
+        ("getpeerinfo", "getpeerinfo", json!([])),
+        ("getconnectioncount", "getconnectioncount", json!([])),
+        ("getzmqnotifications", "getzmqnotifications", json!([])),
+        ("getrawtransaction", "getrawtransaction", json!([txid])),
+        ("getrawtransaction_verbose", "getrawtransaction", json!([txid, true])),
+        ("gettxout", "gettxout", json!([txid, 0, true])),
+        ("sendrawtransaction", "sendrawtransaction", json!([fixture.raw_tx])),
+        ("sendrawtransaction_maxfeerate", "sendrawtransaction", json!([fixture.raw_tx, 1])),
+        ("sendrawtransaction_maxburnamount", "sendrawtransaction", json!([burn_tx])),
+        ("sendrawtransaction_burn_allowed", "sendrawtransaction", json!([burn_tx, 0.1, 0.00001])),
+        ("encodepsbtur", "encodepsbtur", json!([psbt, 60])),
+        ("decodepsbtur", "decodepsbtur", json!([ur_parts])),
+        ("getpaymenturi", "getpaymenturi", json!(["bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080", 0.5, "golden"])),
+        ("parsepaymenturi", "parsepaymenturi", json!(["bitcoin:bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080?amount=0.5"])),
+        ("getsilentpaymentaddress", "getsilentpaymentaddress", json!([SCAN_PUBKEY, SPEND_PUBKEY])),
+        ("getsilentpaymenttweaks", "getsilentpaymenttweaks", json!([1])),
+        ("scansilentpayments", "scansilentpayments", json!([SCAN_SECRET, SP_ADDRESS, 1])),
+        ("createwallet", "createwallet", json!(["golden", wallet])),
+        ("listwallets", "listwallets", json!([])),
+        ("setwalletflag", "setwalletflag", json!(["golden", "avoid_reuse", true])),
+        ("settxnote", "settxnote", json!(["golden", txid, "golden note"])),
+        ("getnewaddress", "getnewaddress", json!(["golden", "first"])),
+        ("getnewaddress_bech32m", "getnewaddress", json!(["golden", null, "bech32m"])),
+        ("getrawchangeaddress", "getrawchangeaddress", json!(["golden"])),
+        ("listunspent", "listunspent", json!(["golden"])),
+        ("leaseoutput", "leaseoutput", json!([lease_id, txid, 0])),
+        ("listleases", "listleases", json!([])),
+        ("releaseoutput", "releaseoutput", json!([lease_id, txid, 0])),
+        ("walletcreatefundedpsbt", "walletcreatefundedpsbt", json!(["golden", [], [{ "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080": 0.1 }]])),
+        ("createmultisigdescriptor", "createmultisigdescriptor", json!([2, cosigners])),
+        ("createmultisigproposal", "createmultisigproposal", json!([psbt, "golden"])),
+        ("submitmultisigsignatures", "submitmultisigsignatures", json!(["00".repeat(32), psbt])),
+        ("getmultisigproposal", "getmultisigproposal", json!(["00".repeat(32)])),
+        ("listmultisigproposals", "listmultisigproposals", json!([])),
+        ("setconfig", "setconfig", json!(["mempool.rbf", "opt_in"])),
+        ("setconfig_restart", "setconfig", json!(["network_config.max_peers", 12])),
+        ("setconfig_invalid", "setconfig", json!(["mempool.rbf", "sometimes"])),
+        ("setconfig_unknown", "setconfig", json!(["rpc.password", "x"])),
+        ("getconfig_key", "getconfig", json!(["mempool.rbf"])),
+        ("setconfig_remove", "setconfig", json!(["network_config.max_peers"])),
+        ("getconfig", "getconfig", json!([])),
+    ]
+}
+
+async fn call(io: &IoHandler, method: &str, params: &Value) -> Value {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let response: Value = serde_json::from_str(&io.handle_request(&request.to_string()).await.unwrap()).unwrap();
+    let mut golden = Map::new();
+    golden.insert("method".to_string(), json!(method));
+    golden.insert("params".to_string(), params.clone());
+    for key in ["result", "error"] {
+        if let Some(value) = response.get(key) {
+            golden.insert(key.to_string(), value.clone());
+        }
+    }
+    let mut golden = Value::Object(golden);
+    mask_volatile(&mut golden);
+    canonical(golden)
+}
+
+#[test]
+fn test_account_keys_follow_bip32_vector() {
+    let secp = Secp256k1::new();
+    let seed = Vec::<u8>::from_hex(SEED).unwrap();
+    let master = Xpriv::new_master(Network::Bitcoin, &seed).unwrap();
+    assert_eq!(Xpub::from_priv(&secp, &master).to_string(), MASTER_XPUB);
+    assert!(account_key("m/84'/1'/0'").starts_with("[3442193e/84'/1'/0']tpub"));
+}
+
+#[actix_rt::test]
+async fn test_rpc_responses_match_golden_files() {
+    let fixture = fixture().await;
+    let cases = cases(&fixture);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let dir = golden_dir();
+    if update {
+        std::fs::create_dir_all(&dir).unwrap();
+    }
+
+    let mut mismatched = Vec::new();
+    for (case, method, params) in &cases {
+        let actual = serde_json::to_string_pretty(&call(&fixture.io, method, params).await).unwrap() + "\n";
+        let path = dir.join(format!("{}.json", case));
+        if update {
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => mismatched.push(format!("{}:\n--- expected\n{}+++ actual\n{}", case, expected, actual)),
+            Err(_) => mismatched.push(format!("{}: no golden file at {}", case, path.display())),
+        }
+    }
+    assert!(mismatched.is_empty(), "responses changed, rerun with UPDATE_GOLDEN=1 if intended:\n{}", mismatched.join("\n"));
+
+    // Every registered method needs at least one case, so new ones cannot slip through
+    let covered: Vec<&str> = cases.iter().map(|(_, method, _)| *method).collect();
+    let mut missing: Vec<&String> = fixture.io.iter().map(|(method, _)| method).filter(|method| !covered.contains(&method.as_str())).collect();
+    missing.sort();
+    assert!(missing.is_empty(), "RPC methods without a golden case: {:?}", missing);
+
+    // and the registry, which `help` is built from, lists exactly the registered methods
+    let mut registered: Vec<&str> = fixture.io.iter().map(|(method, _)| method.as_str()).collect();
+    registered.sort();
+    let mut declared: Vec<&str> = registry::available(true).map(|spec| spec.name).collect();
+    declared.sort();
+    assert_eq!(registered, declared, "the RPC registry is out of date");
+}